@@ -0,0 +1,53 @@
+//! Records the commit, target, and resolved versions of the main
+//! dependencies juke is built from, for `juke --version` and bug reports.
+//! Builds without git, as from a source tarball, say "unknown" instead.
+
+use std::path::Path;
+use std::process::Command;
+
+fn main() {
+    let hash = Command::new("git")
+        .args(["rev-parse", "--short=10", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|hash| hash.trim().to_string())
+        .filter(|hash| !hash.is_empty())
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=JUKE_GIT_HASH={hash}");
+    println!("cargo:rustc-env=JUKE_TARGET={}", std::env::var("TARGET").unwrap_or_else(|_| "unknown".to_string()));
+    println!("cargo:rustc-env=JUKE_DEPENDENCIES={}", locked_versions(&["rodio", "lofty", "ratatui", "crossterm"]));
+    println!("cargo:rerun-if-changed=Cargo.lock");
+
+    // Only a new commit or checkout changes the hash
+    println!("cargo:rerun-if-changed=build.rs");
+    for path in [".git/HEAD", ".git/refs/heads"] {
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={path}");
+        }
+    }
+}
+
+/// Reads the versions Cargo.lock resolved the named packages to, as
+/// `name version` pairs separated by commas. A package locked at several
+/// versions is listed once for each.
+fn locked_versions(names: &[&str]) -> String {
+    let lock = std::fs::read_to_string("Cargo.lock").unwrap_or_default();
+    let mut found = Vec::new();
+    for package in lock.split("[[package]]").skip(1) {
+        let field = |key: &str| {
+            package.lines().find_map(|line| {
+                let value = line.strip_prefix(key)?.trim_start().strip_prefix('=')?;
+                Some(value.trim().trim_matches('"').to_string())
+            })
+        };
+        if let (Some(name), Some(version)) = (field("name"), field("version"))
+            && names.contains(&name.as_str())
+        {
+            found.push((names.iter().position(|&n| n == name), format!("{name} {version}")));
+        }
+    }
+    found.sort();
+    found.into_iter().map(|(_, package)| package).collect::<Vec<_>>().join(",")
+}
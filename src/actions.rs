@@ -0,0 +1,477 @@
+//! Everything the keys do, listed once.
+//!
+//! The key handler looks keys up here, the help screen lists the entries
+//! with their keys, and the command palette searches them by name, so an
+//! action added here shows up in all three. The `[keys]` section of the
+//! config changes the keys of the actions it names, through a [`Keymap`].
+
+use crate::config::KeyConfig;
+use crate::listfilter::Chip;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// Something a key or the command palette can do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    PlayPause,
+    Next,
+    Previous,
+    SeekForward,
+    SeekBackward,
+    Replay,
+    PreampUp,
+    PreampDown,
+    VolumeUp,
+    VolumeDown,
+    Mute,
+    Shuffle,
+    Repeat,
+    Album,
+    StopAfter,
+    TrackList,
+    QueuePanel,
+    FocusNext,
+    FocusPrevious,
+    Refresh,
+    Log,
+    Stats,
+    Browser,
+    Preferences,
+    Palette,
+    Help,
+    Quit,
+    SortMenu,
+    MarksMenu,
+    SetMark,
+    JumpToMark,
+    JumpMode,
+    Chip(Chip),
+    ClearChips,
+    Queue,
+    Preview,
+    Delete,
+    Rename,
+    SavePlaylist,
+    ReadTags,
+    WriteTags,
+    PlayAndSave,
+}
+
+/// Where an action's keys work.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// The player screen.
+    Player,
+    /// The track list, where the action works on the selected track.
+    TrackList,
+    /// Both of them.
+    Both,
+}
+
+impl Scope {
+    /// Whether keys of this scope work in `screen`, which is `Player` or
+    /// `TrackList`.
+    fn includes(self, screen: Scope) -> bool {
+        self == Scope::Both || self == screen
+    }
+}
+
+/// A key and the modifiers held with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl Key {
+    const fn plain(code: KeyCode) -> Self {
+        Self { code, modifiers: KeyModifiers::NONE }
+    }
+
+    const fn char(c: char) -> Self {
+        Self::plain(KeyCode::Char(c))
+    }
+
+    const fn ctrl(c: char) -> Self {
+        Self { code: KeyCode::Char(c), modifiers: KeyModifiers::CONTROL }
+    }
+
+    const fn shift(code: KeyCode) -> Self {
+        Self { code, modifiers: KeyModifiers::SHIFT }
+    }
+
+    /// Whether a key press is this key. Shift is part of a character
+    /// already, so it only counts for other keys.
+    pub fn matches(&self, event: &KeyEvent) -> bool {
+        let mut significant = KeyModifiers::CONTROL | KeyModifiers::ALT;
+        if !matches!(event.code, KeyCode::Char(_) | KeyCode::BackTab) {
+            significant |= KeyModifiers::SHIFT;
+        }
+        self.code == event.code && event.modifiers & significant == self.modifiers
+    }
+
+    /// Reads a key as the config names it, like "n", "Space", "Shift+Right"
+    /// or "Ctrl+r".
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut name = text;
+        // A lone "+" is a key, not a separator
+        while let Some((modifier, rest)) = name.split_once('+').filter(|(_, rest)| !rest.is_empty()) {
+            modifiers |= match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return None,
+            };
+            name = rest;
+        }
+        let mut chars = name.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match name.to_ascii_lowercase().as_str() {
+                "space" => KeyCode::Char(' '),
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "enter" => KeyCode::Enter,
+                "esc" | "escape" => KeyCode::Esc,
+                "tab" => KeyCode::Tab,
+                "backspace" => KeyCode::Backspace,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                f => KeyCode::F(f.strip_prefix('f')?.parse().ok().filter(|n| (1..=12).contains(n))?),
+            },
+        };
+        // Shift is part of a character already
+        if let KeyCode::Char(c) = code
+            && modifiers.contains(KeyModifiers::SHIFT)
+        {
+            modifiers -= KeyModifiers::SHIFT;
+            return Some(Self { code: KeyCode::Char(c.to_ascii_uppercase()), modifiers });
+        }
+        if code == KeyCode::Tab && modifiers == KeyModifiers::SHIFT {
+            return Some(Self::plain(KeyCode::BackTab));
+        }
+        Some(Self { code, modifiers })
+    }
+
+    /// Names the key the way the help screen shows it, like "Shift+→".
+    pub fn label(&self) -> String {
+        let name = match self.code {
+            KeyCode::Char(' ') => "Space".to_string(),
+            KeyCode::Char(c) => c.to_string(),
+            KeyCode::Left => "←".to_string(),
+            KeyCode::Right => "→".to_string(),
+            KeyCode::F(n) => format!("F{}", n),
+            KeyCode::BackTab => "Shift+Tab".to_string(),
+            KeyCode::Esc => "Esc".to_string(),
+            code => format!("{:?}", code),
+        };
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            format!("C-{}", name)
+        } else if self.modifiers.contains(KeyModifiers::SHIFT) {
+            format!("Shift+{}", name)
+        } else {
+            name
+        }
+    }
+}
+
+/// An action with its keys and description.
+#[derive(Debug)]
+pub struct Entry {
+    pub action: Action,
+    pub scope: Scope,
+    /// Keys that run it, in the order the help screen lists them.
+    pub keys: &'static [Key],
+    /// Text key of its description, which may use `{seconds}` for the seek
+    /// step.
+    pub text: &'static str,
+}
+
+const fn entry(action: Action, scope: Scope, keys: &'static [Key], text: &'static str) -> Entry {
+    Entry { action, scope, keys, text }
+}
+
+/// Every action, in the order the help screen and the palette list them.
+pub const ACTIONS: &[Entry] = &[
+    entry(Action::PlayPause, Scope::Player, &[Key::char(' ')], "help.play_pause"),
+    entry(Action::Next, Scope::Player, &[Key::char('n'), Key::plain(KeyCode::Right)], "help.next"),
+    entry(Action::Previous, Scope::Player, &[Key::char('p'), Key::plain(KeyCode::Left)], "help.previous"),
+    entry(Action::SeekForward, Scope::Player, &[Key::shift(KeyCode::Right)], "help.seek_forward"),
+    entry(Action::SeekBackward, Scope::Player, &[Key::shift(KeyCode::Left)], "help.seek_backward"),
+    entry(Action::Replay, Scope::Player, &[Key::char(',')], "help.replay"),
+    entry(Action::VolumeUp, Scope::Player, &[Key::char('=')], "help.volume_up"),
+    entry(Action::VolumeDown, Scope::Player, &[Key::char('-')], "help.volume_down"),
+    entry(Action::Mute, Scope::Player, &[Key::char('m')], "help.mute"),
+    entry(Action::PreampUp, Scope::Player, &[Key::char('+')], "help.preamp_up"),
+    entry(Action::PreampDown, Scope::Player, &[Key::char('_')], "help.preamp_down"),
+    entry(Action::Shuffle, Scope::Player, &[Key::char('s')], "help.shuffle"),
+    entry(Action::Repeat, Scope::Player, &[Key::char('r')], "help.repeat"),
+    entry(Action::Album, Scope::Player, &[Key::char('A')], "help.album"),
+    entry(Action::StopAfter, Scope::Player, &[Key::char('.')], "help.stop_after"),
+    entry(Action::TrackList, Scope::Player, &[Key::char('t')], "help.track_list"),
+    entry(Action::QueuePanel, Scope::Player, &[Key::char('u')], "help.queue_panel"),
+    entry(Action::FocusNext, Scope::Both, &[Key::plain(KeyCode::Tab)], "help.focus_next"),
+    entry(Action::FocusPrevious, Scope::Both, &[Key::plain(KeyCode::BackTab)], "help.focus_previous"),
+    entry(Action::Refresh, Scope::Both, &[Key::plain(KeyCode::F(5)), Key::ctrl('r')], "help.refresh"),
+    entry(Action::Log, Scope::Player, &[Key::char('e')], "help.log"),
+    entry(Action::Stats, Scope::Player, &[Key::char('S')], "help.stats"),
+    entry(Action::Browser, Scope::Player, &[Key::char('b')], "help.browser"),
+    entry(Action::Preferences, Scope::Player, &[Key::char('o')], "help.preferences"),
+    entry(Action::Palette, Scope::Both, &[Key::ctrl('p')], "help.palette"),
+    entry(Action::SavePlaylist, Scope::Both, &[Key::ctrl('s')], "help.save_playlist"),
+    entry(Action::Help, Scope::Player, &[Key::char('?')], "help.help"),
+    entry(Action::Quit, Scope::Player, &[Key::plain(KeyCode::Esc), Key::char('q')], "help.quit"),
+    entry(Action::SortMenu, Scope::TrackList, &[Key::char('o')], "help.sort_menu"),
+    entry(Action::MarksMenu, Scope::TrackList, &[Key::char('M')], "help.marks_menu"),
+    entry(Action::SetMark, Scope::TrackList, &[Key::char('m')], "help.set_mark"),
+    entry(Action::JumpToMark, Scope::TrackList, &[Key::char('\'')], "help.jump_to_mark"),
+    entry(Action::JumpMode, Scope::TrackList, &[Key::char('J')], "help.jump_mode"),
+    entry(Action::Chip(Chip::Marked), Scope::TrackList, &[Key::char('1')], "help.chip_marked"),
+    entry(Action::Chip(Chip::Queued), Scope::TrackList, &[Key::char('2')], "help.chip_queued"),
+    entry(Action::Chip(Chip::Unplayable), Scope::TrackList, &[Key::char('3'), Key::char('!')], "help.unplayable"),
+    entry(Action::Chip(Chip::Album), Scope::TrackList, &[Key::char('4')], "help.chip_album"),
+    entry(Action::ClearChips, Scope::TrackList, &[Key::ctrl('l')], "help.clear_chips"),
+    entry(Action::Queue, Scope::TrackList, &[Key::char('Q')], "help.queue"),
+    entry(Action::Preview, Scope::TrackList, &[Key::char('P')], "help.preview"),
+    entry(Action::Delete, Scope::TrackList, &[Key::char('D')], "help.delete"),
+    entry(Action::Rename, Scope::TrackList, &[Key::char('e')], "help.rename"),
+    entry(Action::ReadTags, Scope::TrackList, &[Key::char('I')], "help.read_tags"),
+    entry(Action::WriteTags, Scope::TrackList, &[Key::char('W')], "help.write_tags"),
+    // Ctrl+Enter reaches the key handler as Ctrl+J in most terminals
+    entry(
+        Action::PlayAndSave,
+        Scope::TrackList,
+        &[Key { code: KeyCode::Enter, modifiers: KeyModifiers::CONTROL }, Key::ctrl('j')],
+        "help.play_and_save",
+    ),
+];
+
+/// Returns the entry for an action.
+pub fn entry_for(action: Action) -> &'static Entry {
+    ACTIONS
+        .iter()
+        .find(|entry| entry.action == action)
+        .expect("every action has an entry")
+}
+
+/// The keys each action runs from: those in [`ACTIONS`], with the ones the
+/// config sets in their place.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    /// Keys of each entry of [`ACTIONS`], in the same order.
+    keys: Vec<Vec<Key>>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self { keys: ACTIONS.iter().map(|entry| entry.keys.to_vec()).collect() }
+    }
+}
+
+impl Keymap {
+    /// Builds the keymap from the `[keys]` section of the config. Returns
+    /// the key names it could not read too; an action whose keys are all
+    /// unreadable keeps its own. A key the config gives one action is taken
+    /// from any other that had it.
+    pub fn new(config: &KeyConfig) -> (Self, Vec<String>) {
+        let mut keymap = Self::default();
+        let mut unknown = Vec::new();
+        let bindings = [
+            (Action::PlayPause, &config.play_pause),
+            (Action::Next, &config.next),
+            (Action::Previous, &config.prev),
+            (Action::SeekForward, &config.seek_forward),
+            (Action::SeekBackward, &config.seek_back),
+            (Action::Replay, &config.replay),
+            (Action::Shuffle, &config.shuffle),
+            (Action::Repeat, &config.repeat),
+            (Action::TrackList, &config.track_list),
+            (Action::Help, &config.help),
+            (Action::Quit, &config.quit),
+        ];
+        for (action, binding) in bindings {
+            let mut keys = Vec::new();
+            for name in binding.keys() {
+                match Key::parse(name) {
+                    Some(key) => keys.push(key),
+                    None => unknown.push(name.to_string()),
+                }
+            }
+            if !keys.is_empty() {
+                keymap.bind(action, keys);
+            }
+        }
+        (keymap, unknown)
+    }
+
+    fn bind(&mut self, action: Action, keys: Vec<Key>) {
+        let at = ACTIONS.iter().position(|entry| entry.action == action).expect("every action has an entry");
+        for (other, entry) in self.keys.iter_mut().zip(ACTIONS) {
+            let shared = entry.scope.includes(Scope::Player) && ACTIONS[at].scope.includes(Scope::Player)
+                || entry.scope.includes(Scope::TrackList) && ACTIONS[at].scope.includes(Scope::TrackList);
+            if shared {
+                other.retain(|key| !keys.contains(key));
+            }
+        }
+        self.keys[at] = keys;
+    }
+
+    /// Returns the keys that run an action.
+    pub fn keys(&self, action: Action) -> &[Key] {
+        let at = ACTIONS.iter().position(|entry| entry.action == action).expect("every action has an entry");
+        &self.keys[at]
+    }
+
+    /// Lists an action's keys the way the help screen shows them, like
+    /// "n / →".
+    pub fn key_labels(&self, action: Action) -> String {
+        self.keys(action).iter().map(Key::label).collect::<Vec<_>>().join(" / ")
+    }
+
+    /// Finds the action a key press runs on `screen`, `Player` or
+    /// `TrackList`.
+    pub fn find(&self, screen: Scope, event: &KeyEvent) -> Option<Action> {
+        ACTIONS
+            .iter()
+            .zip(&self.keys)
+            .filter(|(entry, _)| entry.scope.includes(screen))
+            .find(|(_, keys)| keys.iter().any(|key| key.matches(event)))
+            .map(|(entry, _)| entry.action)
+    }
+}
+
+/// Scores how well `query` matches `text` when its characters appear in
+/// order, ignoring case; lower is better. Characters at the start of a word
+/// and right after the last match cost nothing, others cost one, and
+/// characters skipped over cost one for each run skipped.
+pub fn fuzzy_score(query: &str, text: &str) -> Option<u32> {
+    let text: Vec<char> = text.chars().flat_map(char::to_lowercase).collect();
+    let mut score = 0;
+    let mut next = 0;
+    for wanted in query.chars().flat_map(char::to_lowercase).filter(|c| !c.is_whitespace()) {
+        let found = next + text[next..].iter().position(|&c| c == wanted)?;
+        let word_start = found == 0 || !text[found - 1].is_alphanumeric();
+        if found > next && next > 0 {
+            score += 1;
+        }
+        if !word_start && found != next {
+            score += 1;
+        }
+        next = found + 1;
+    }
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::KeyEventKind;
+
+    fn press(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new_with_kind(code, modifiers, KeyEventKind::Press)
+    }
+
+    #[test]
+    fn test_keys_find_actions() {
+        let cases = [
+            (Scope::Player, KeyCode::Right, KeyModifiers::NONE, Some(Action::Next)),
+            (Scope::Player, KeyCode::Right, KeyModifiers::SHIFT, Some(Action::SeekForward)),
+            (Scope::Player, KeyCode::Char('r'), KeyModifiers::NONE, Some(Action::Repeat)),
+            (Scope::Player, KeyCode::Char('r'), KeyModifiers::CONTROL, Some(Action::Refresh)),
+            (Scope::Player, KeyCode::Char('S'), KeyModifiers::SHIFT, Some(Action::Stats)),
+            (Scope::Player, KeyCode::Char('o'), KeyModifiers::NONE, Some(Action::Preferences)),
+            (Scope::TrackList, KeyCode::Char('o'), KeyModifiers::NONE, Some(Action::SortMenu)),
+            (Scope::TrackList, KeyCode::Char('p'), KeyModifiers::CONTROL, Some(Action::Palette)),
+            (Scope::TrackList, KeyCode::Char('2'), KeyModifiers::NONE, Some(Action::Chip(Chip::Queued))),
+            (Scope::TrackList, KeyCode::Char('!'), KeyModifiers::SHIFT, Some(Action::Chip(Chip::Unplayable))),
+            (Scope::TrackList, KeyCode::Char('l'), KeyModifiers::CONTROL, Some(Action::ClearChips)),
+            (Scope::TrackList, KeyCode::Char('n'), KeyModifiers::NONE, None),
+            (Scope::Player, KeyCode::Char('n'), KeyModifiers::ALT, None),
+        ];
+        let keymap = Keymap::default();
+        for (screen, code, modifiers, expected) in cases {
+            assert_eq!(keymap.find(screen, &press(code, modifiers)), expected, "{:?} {:?}", modifiers, code);
+        }
+    }
+
+    #[test]
+    fn test_no_key_runs_two_actions() {
+        for screen in [Scope::Player, Scope::TrackList] {
+            let keys: Vec<&Key> = ACTIONS
+                .iter()
+                .filter(|entry| entry.scope.includes(screen))
+                .flat_map(|entry| entry.keys)
+                .collect();
+            for (i, key) in keys.iter().enumerate() {
+                assert!(!keys[..i].contains(key), "{} is bound twice", key.label());
+            }
+        }
+        for entry in ACTIONS {
+            assert_eq!(entry_for(entry.action).text, entry.text, "{:?} is listed twice", entry.action);
+        }
+    }
+
+    #[test]
+    fn test_key_labels() {
+        let keymap = Keymap::default();
+        assert_eq!(keymap.key_labels(Action::Next), "n / →");
+        assert_eq!(keymap.key_labels(Action::SeekBackward), "Shift+←");
+        assert_eq!(keymap.key_labels(Action::Refresh), "F5 / C-r");
+        assert_eq!(keymap.key_labels(Action::PlayPause), "Space");
+        assert_eq!(keymap.key_labels(Action::FocusPrevious), "Shift+Tab");
+    }
+
+    #[test]
+    fn test_parse_keys() {
+        assert_eq!(Key::parse("n"), Some(Key::char('n')));
+        assert_eq!(Key::parse("Space"), Some(Key::char(' ')));
+        assert_eq!(Key::parse("+"), Some(Key::char('+')));
+        assert_eq!(Key::parse("Shift+Right"), Some(Key::shift(KeyCode::Right)));
+        assert_eq!(Key::parse("Shift+s"), Some(Key::char('S')));
+        assert_eq!(Key::parse("ctrl+r"), Some(Key::ctrl('r')));
+        assert_eq!(Key::parse("F5"), Some(Key::plain(KeyCode::F(5))));
+        assert_eq!(Key::parse("Shift+Tab"), Some(Key::plain(KeyCode::BackTab)));
+        assert_eq!(Key::parse("Hyper+x"), None);
+        assert_eq!(Key::parse("F13"), None);
+        assert_eq!(Key::parse("Nope"), None);
+    }
+
+    #[test]
+    fn test_config_keys_replace_the_defaults() {
+        // The config's defaults are the registry's keys
+        let (keymap, unknown) = Keymap::new(&KeyConfig::default());
+        assert!(unknown.is_empty());
+        for entry in ACTIONS {
+            assert_eq!(keymap.keys(entry.action), entry.keys, "{:?}", entry.action);
+        }
+
+        let config: KeyConfig = toml::from_str("next = \"j\"\nshuffle = [\"x\", \"Nope\"]\nquit = \"e\"").unwrap();
+        let (keymap, unknown) = Keymap::new(&config);
+        assert_eq!(unknown, ["Nope"]);
+        let find = |screen, c| keymap.find(screen, &press(KeyCode::Char(c), KeyModifiers::NONE));
+        assert_eq!(find(Scope::Player, 'j'), Some(Action::Next));
+        assert_eq!(find(Scope::Player, 'n'), None);
+        assert_eq!(find(Scope::Player, 'x'), Some(Action::Shuffle));
+        assert_eq!(find(Scope::Player, 'p'), Some(Action::Previous));
+        // The log gives up e on the player screen; renaming keeps it in the track list
+        assert_eq!(find(Scope::Player, 'e'), Some(Action::Quit));
+        assert_eq!(keymap.key_labels(Action::Log), "");
+        assert_eq!(find(Scope::TrackList, 'e'), Some(Action::Rename));
+    }
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert_eq!(fuzzy_score("", "Toggle shuffle"), Some(0));
+        assert_eq!(fuzzy_score("shuf", "Toggle shuffle"), Some(0));
+        assert_eq!(fuzzy_score("ts", "Toggle shuffle"), Some(1));
+        assert_eq!(fuzzy_score("SHUFFLE", "Toggle shuffle"), Some(0));
+        assert_eq!(fuzzy_score("xyz", "Toggle shuffle"), None);
+        // Word starts beat letters from the middle of words
+        assert_eq!(fuzzy_score("sm", "Sort menu"), Some(1));
+        assert_eq!(fuzzy_score("sm", "Is mine"), Some(2));
+    }
+}
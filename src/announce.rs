@@ -0,0 +1,122 @@
+//! Track change announcements for speech synthesizers (`ui.announce_tracks`).
+//!
+//! Each track change writes one plain line, such as "Now playing: Band,
+//! Song, 3 of 34", to `ui.announce_path` or else to standard error when it
+//! is redirected, never to the terminal the interface is drawn on. A user
+//! hook such as `while read -r line < fifo; do spd-say "$line"; done` can
+//! then speak it.
+
+use crate::config::UiConfig;
+use crate::template::{Template, Values};
+use std::fs::OpenOptions;
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
+use std::sync::mpsc;
+
+/// Announcement text when `ui.announce_template` is unset.
+pub const DEFAULT_TEMPLATE: &str = "Now playing: %{artist:, }%title, %index of %count";
+
+/// Where announcements go.
+#[derive(Debug)]
+enum Channel {
+    /// Lines for the thread writing to `ui.announce_path`.
+    Path(mpsc::Sender<String>),
+    Stderr,
+}
+
+/// Writes track change announcements.
+#[derive(Debug)]
+pub struct Announcer {
+    template: Template,
+    channel: Channel,
+}
+
+impl Announcer {
+    /// Starts announcing to the configured path, or to standard error if it
+    /// is not a terminal. Returns `None` when there is nowhere to write.
+    pub fn new(config: &UiConfig) -> Option<Self> {
+        let channel = match &config.announce_path {
+            Some(path) => Channel::Path(spawn_writer(path.clone())),
+            None if !std::io::stderr().is_terminal() => Channel::Stderr,
+            None => return None,
+        };
+        let template = config.announce_template.as_deref().unwrap_or(DEFAULT_TEMPLATE);
+        Some(Self { template: Template::parse(template), channel })
+    }
+
+    /// Returns the announcement for a track.
+    pub fn line(&self, values: &Values) -> String {
+        // A line break would split the announcement in two
+        self.template.expand(values).replace(['\n', '\r'], " ")
+    }
+
+    /// Announces a track.
+    pub fn announce(&self, values: &Values) {
+        let line = self.line(values);
+        match &self.channel {
+            Channel::Path(sender) => {
+                let _ = sender.send(line);
+            }
+            Channel::Stderr => eprintln!("{}", line),
+        }
+    }
+}
+
+/// Starts the thread writing announcements to a file or FIFO.
+///
+/// The file is opened for every announcement, so a reader sees each one end.
+/// Opening a FIFO waits for a reader, so playback never does; by the time
+/// one turns up only the latest announcement is still worth reading.
+fn spawn_writer(path: PathBuf) -> mpsc::Sender<String> {
+    let (sender, receiver) = mpsc::channel::<String>();
+    std::thread::spawn(move || {
+        while let Ok(mut line) = receiver.recv() {
+            let Ok(mut file) = OpenOptions::new().create(true).append(true).open(&path) else {
+                continue;
+            };
+            while let Ok(newer) = receiver.try_recv() {
+                line = newer;
+            }
+            let _ = writeln!(file, "{}", line);
+        }
+    });
+    sender
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn values(artist: Option<&str>) -> Values {
+        Values {
+            artist: artist.map(String::from),
+            title: Some("Song".to_string()),
+            ..Default::default()
+        }
+        .with_place(2, 34)
+    }
+
+    #[test]
+    fn test_announce_to_file() {
+        let path = std::env::temp_dir().join(format!("juke_announce_{}.txt", std::process::id()));
+        let config = UiConfig {
+            announce_tracks: true,
+            announce_path: Some(path.clone()),
+            ..Default::default()
+        };
+        let announcer = Announcer::new(&config).unwrap();
+        assert_eq!(announcer.line(&values(Some("Band"))), "Now playing: Band, Song, 3 of 34");
+        assert_eq!(announcer.line(&values(None)), "Now playing: Song, 3 of 34");
+
+        announcer.announce(&values(Some("Band")));
+        let started = Instant::now();
+        let mut written = String::new();
+        while written.is_empty() && started.elapsed() < Duration::from_secs(5) {
+            std::thread::sleep(Duration::from_millis(10));
+            written = std::fs::read_to_string(&path).unwrap_or_default();
+        }
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written, "Now playing: Band, Song, 3 of 34\n");
+    }
+}
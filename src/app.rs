@@ -1,25 +1,208 @@
 //! Application state and main loop.
 
-use crate::config::Config;
-use crate::player::{Player, PlayerError};
-use crate::playlist::Playlist;
+use crate::actions::{self, Action, Keymap, Scope, ACTIONS};
+use crate::announce::Announcer;
+use crate::browser::Browser;
+use crate::collation::{Collation, SortKey};
+use crate::columns::{self, Column};
+use crate::dirty::Redraw;
+use crate::config::{Bell, Config, InterfaceMode, PREAMP_CLIP_WARNING_DB, PREAMP_LIMIT_DB};
+use crate::discs;
+use crate::frames;
+use crate::gapless::{self, Transition};
+use crate::history;
+use crate::library::Library;
+use crate::listfilter::{Chip, Chips, TrackFilter};
+use crate::listview::{RowModel, UniformRows, Viewport};
+use crate::loudness::LoudnessCache;
+use crate::marks::{self, Marks};
+use crate::mpd::MpdServer;
+use crate::listenlog;
+use crate::lookup::{self, Lookup, Proposal};
+use crate::profile::Profile;
+use crate::prompthistory::{self, PromptHistory};
+use crate::tagcache::{self, TagCache};
+use crate::tagwrite::{TagEdit, TagWriteQueue};
+use crate::notify::{self, Notifier};
+use crate::plain::{self, PlainPrinter};
+use crate::jump::JumpIndex;
+use crate::player::{Player, PlayerError, PlayerEvent};
+use crate::remote::{self, RemoteServer, Response};
+use crate::preferences::{self, PREFERENCES};
+use crate::playlist::{NameOptions, Playlist, PlaylistError, PlaylistSettings, PlaylistSource, Refreshed, ShuffleState, Track};
+use crate::session::{self, Session, SESSION_VERSION};
+use crate::state::StateStore;
+use crate::stats;
+use crate::strings::Strings;
+use crate::suspend;
+use crate::task::{Pool, Task};
+use crate::template::{self, Values};
+use crate::focus::{self, Focus};
+use crate::themes::Theme;
+use crate::trim::{self, Trim};
+use crate::ui::{self, ProgressStyle};
+use crate::visualizer;
 use ratatui::{
-    backend::CrosstermBackend,
     layout::{Alignment, Constraint, Direction, Layout},
     style::{Color, Modifier, Style},
     text::{Line, Span},
     widgets::{Block, Borders, Paragraph},
     Terminal,
 };
+use serde_json::{json, Value};
 use std::io;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
 use std::time::Duration;
 
+/// How long a flashed status message stays visible.
+const STATUS_MESSAGE_DURATION: Duration = Duration::from_secs(2);
+
+/// Pre-amp adjustment per key press in dB.
+const PREAMP_STEP_DB: f32 = 1.0;
+
+/// Most long operations run at once; the rest wait their turn.
+const MAX_TASKS: usize = 2;
+
+/// Volume adjustment per key press in percent.
+const VOLUME_STEP: i16 = 5;
+
+/// How often the status line is redrawn when nothing is animating.
+const STATUS_TICK: Duration = Duration::from_secs(1);
+
+/// Tracks kept visible above and below the track list selection.
+const TRACK_LIST_MARGIN: u16 = 2;
+
+/// Upcoming tracks listed in the queue panel.
+const QUEUE_PANEL_LEN: usize = 100;
+
+/// Shortest time between redraws in low bandwidth mode.
+const LOW_BANDWIDTH_FRAME: Duration = Duration::from_millis(500);
+
+/// How much of a track the track list preview plays.
+const PREVIEW_LENGTH: Duration = Duration::from_secs(10);
+
+/// Where in a track the preview starts, as a fraction of its length.
+const PREVIEW_AT: f32 = 0.4;
+
+/// Replay presses this close together add up to one seek.
+const REPLAY_COALESCE: Duration = Duration::from_millis(300);
+
+/// Seek presses this close together move the target without seeking.
+const SEEK_COALESCE: Duration = Duration::from_millis(400);
+
+/// Longest wait for input, so background work is noticed promptly.
+const MAX_POLL_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// How long the visual bell inverts the status line.
+const BELL_FLASH: Duration = Duration::from_millis(150);
+
+/// Shortest time between two bells, so a burst of errors rings once.
+const BELL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long before the end of a track the next one is decoded, when it
+/// should follow without a gap.
+const GAPLESS_LEAD: Duration = Duration::from_secs(15);
+
+/// How long before the end of a track the next one is appended to it, when
+/// it should follow without a gap.
+const QUEUE_LEAD: Duration = Duration::from_secs(5);
+
+/// Commands the palette lists at once, best matches first.
+const PALETTE_ROWS: usize = 10;
+
+/// Where the interface is shown.
+enum Frontend {
+    /// Full-screen interface drawn with ratatui.
+    Terminal(Terminal<Backend>),
+    /// One line per change, for screen readers.
+    Plain(PlainPrinter),
+}
+
+/// What the full-screen interface draws to.
+#[cfg(not(test))]
+type Backend = ratatui::backend::CrosstermBackend<io::Stdout>;
+#[cfg(test)]
+type Backend = ratatui::backend::TestBackend;
+
+#[cfg(not(test))]
+fn backend() -> Backend {
+    ratatui::backend::CrosstermBackend::new(io::stdout())
+}
+
+#[cfg(test)]
+fn backend() -> Backend {
+    ratatui::backend::TestBackend::new(80, 24)
+}
+
 /// UI display mode.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum UIMode {
     Normal,
     TrackList,
     Help,
+    Log,
+    ConfirmDelete,
+    SortMenu,
+    Stats,
+    MarksMenu,
+    Browser,
+    Preferences,
+    Palette,
+    Prompt,
+    ConfirmQuit,
+}
+
+/// What to do with unsaved playlist edits on quitting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuitChoice {
+    Save,
+    Discard,
+    Cancel,
+}
+
+impl QuitChoice {
+    /// Every choice, in the order the menu lists them.
+    const ALL: [QuitChoice; 3] = [QuitChoice::Save, QuitChoice::Discard, QuitChoice::Cancel];
+
+    fn text_key(self) -> &'static str {
+        match self {
+            QuitChoice::Save => "quit.save",
+            QuitChoice::Discard => "quit.discard",
+            QuitChoice::Cancel => "quit.cancel",
+        }
+    }
+}
+
+/// What the text typed into the prompt is for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PromptAction {
+    /// A new title for the track at this index, if it is still this file.
+    Rename { index: usize, path: PathBuf },
+    /// Where to save the playlist, quitting once it is saved if set.
+    SavePlaylist { then_quit: bool },
+    /// Where to save search results being played, titled after the search.
+    SaveResults { indices: Vec<usize>, title: String },
+}
+
+impl PromptAction {
+    /// Whose history the prompt's text belongs to.
+    fn history_kind(&self) -> prompthistory::Kind {
+        match self {
+            PromptAction::Rename { .. } => prompthistory::Kind::Rename,
+            PromptAction::SavePlaylist { .. } | PromptAction::SaveResults { .. } => prompthistory::Kind::SavePath,
+        }
+    }
+}
+
+/// A key that waits for a second one, like `m` followed by a mark letter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyPrefix {
+    /// Marks the selected track with the next letter.
+    SetMark,
+    /// Jumps to the track marked with the next letter.
+    JumpToMark,
 }
 
 /// Main application state.
@@ -27,242 +210,2405 @@ pub struct App {
     player: Player,
     playlist: Playlist,
     config: Config,
+    loudness: LoudnessCache,
+    library: Library, // Play and skip counts
+    theme: Theme,
+    strings: Strings,
+    keymap: Keymap, // The keys of every action, with the config's in place of the defaults
+    notifier: Notifier,
+    announcer: Option<Announcer>, // Spoken track change announcements
+    remote: Option<RemoteServer>,
+    mpd: Option<MpdServer>, // MPD protocol listener
+    mpd_seen: Option<(u64, usize, ShuffleState, crate::playlist::RepeatMode, u32)>, // Playlist and volume as MPD clients were last woken for
+    listen_log: Option<PathBuf>, // Where listens are logged for scrobbling later
     running: bool,
-    last_display_update: std::time::Instant,
-    terminal: Terminal<CrosstermBackend<io::Stdout>>,
+    redraw: Redraw, // What changed since the last draw
+    shown_tasks: Option<String>, // Task progress as last marked
+    last_tick: suspend::Clocks, // When update last ran, on a clock that stops in sleep and one that does not
+    last_position: Duration, // Playback position at the last update, kept across a sleep
+    last_visualizer_update: std::time::Instant,
+    frontend: Frontend,
     ui_mode: UIMode,
     search_query: String,
     waveform_history: Vec<f32>, // Rolling buffer of amplitude values for visualization
+    visualizer_source: Box<dyn visualizer::VisualizerSource>, // Where the bar levels come from
     track_list_selected: usize, // Selected index in filtered track list view
-    track_list_scroll: usize,   // Scroll offset for track list view
+    track_list_view: Viewport,  // Scroll position of the track list view
+    track_list_layout: columns::Layout, // Columns of the track list, from ui.track_list_columns
     filtered_indices: Vec<usize>, // Indices of tracks matching search filter
+    filtered_for: Option<(String, u64, Chips)>, // Lowercased query, playlist revision and chips filtered_indices was built for
+    chips: Chips, // Filter chips turned on in the track list
+    chip_counts: [usize; 4], // Tracks each chip that is on lets through
+    status_message: Option<(String, std::time::Instant)>, // Transient message shown in the status line
+    notice: Option<String>,     // Persistent notice shown until the log is opened
+    log: Vec<String>,           // Messages shown in the log view
+    log_scroll: usize,          // Scroll offset for log view
+    tasks: Pool,                    // Runs long operations on worker threads
+    refresh_task: Option<Task<Result<Refreshed, PlaylistError>>>, // Pending background refresh
+    tag_cache: TagCache,            // Tags read since fast scans
+    tags_task: Option<Task<Track>>, // Tags being read in the background
+    tags_read: usize,               // Files whose tags the background reading sent so far
+    measure_task: Option<(PathBuf, Task<Option<Duration>>)>, // MP3 being measured frame by frame
+    lookup_rx: Option<mpsc::Receiver<(PathBuf, Proposal)>>, // Background MusicBrainz lookups
+    tag_writes: TagWriteQueue, // Tag edits, held back for the file playing
+    audio_init: Duration, // Time taken to open the audio output
+    startup: Option<Profile>, // Startup timing, until the first frame is drawn
+    startup_summary: Option<String>,
+    player_events: mpsc::Receiver<PlayerEvent>, // Playback transitions, in order
+    pending_delete: Option<usize>, // Playlist index awaiting delete confirmation
+    pending_seek: Option<Duration>, // Restored position, applied once the track loads
+    seek_failed_at: Option<std::time::Instant>, // When a seek last failed, shown on the progress bar
+    seek_target: Option<(Duration, std::time::Instant)>, // Where a burst of seek presses will land, and when last pressed
+    sort: Option<(SortKey, bool)>, // Last applied sort key and whether it was descending
+    jump_index: Option<JumpIndex>,  // Initials of the track list rows, while letters jump instead of searching
+    sort_menu: ui::Menu,            // Sort menu, while UIMode::SortMenu is shown
+    sort_menu_descending: bool,     // Reverse order chosen in the open sort menu
+    queue_panel: bool,              // Whether the upcoming tracks panel is shown
+    focus: Focus,                   // Pane last given the focus; see App::focus
+    queue_panel_selected: usize,    // Selected entry in the queue panel
+    queue_panel_view: Viewport,     // Scroll position of the queue panel
+    low_bandwidth: bool,            // Redraw less often and hide the visualizer
+    last_frame: Option<u64>,        // Fingerprint of what the screen last showed
+    last_draw: std::time::Instant,  // When the screen was last drawn
+    redraw_pending: bool,           // A change is waiting for the low bandwidth frame limit
+    stats: stats::Summary,          // Listening statistics, while UIMode::Stats is shown
+    marks: Marks,                   // Track list marks of the playlist source
+    marks_menu: ui::Menu,           // List of marks, while UIMode::MarksMenu is shown
+    key_prefix: Option<KeyPrefix>,  // First key of a two-key command, waiting for the second
+    stop_after: bool,               // Pause when the current track ends instead of playing on
+    focused: bool,                  // Whether the terminal has focus, as far as it reports
+    session_path: Option<PathBuf>,  // Where the session is saved when the queue changes
+    saved_queue: Vec<usize>,        // Queue as of the last session save
+    pending_replay: Option<(Duration, Duration, std::time::Instant)>, // Position at the first replay press, how far back, and when last pressed
+    preview: Option<(String, bool)>, // Track being previewed, and whether the current one was playing before
+    browser: Option<Browser>,       // Folder browser, kept so it reopens where it was left
+    preferences_menu: ui::Menu,     // Preferences, while UIMode::Preferences is shown
+    palette_menu: ui::Menu,         // Matching commands, while UIMode::Palette is shown
+    palette_query: String,          // What was typed into the command palette
+    palette_actions: Vec<Action>,   // Actions of the palette's rows
+    palette_from: UIMode,           // Screen the palette was opened over
+    prompt: ui::Prompt,             // Text being typed, while UIMode::Prompt is shown
+    prompt_action: Option<PromptAction>, // What the prompt's text is for
+    prompt_from: UIMode,            // Screen the prompt was opened over
+    prompt_history: PromptHistory,  // Earlier entries of each kind of prompt
+    saved_to: Option<PathBuf>,      // M3U file the playlist was last saved to
+    quit_menu: ui::Menu,            // Save, discard or cancel, while UIMode::ConfirmQuit is shown
+    quit_from: UIMode,              // Screen quitting was asked from
+    edits_discarded: bool,          // Unsaved playlist edits were let go on purpose
+    preferences_changed: Vec<usize>, // Preferences changed since the screen was opened
+    state: StateStore,              // Where state and preferences are saved
+    playlist_empty: bool,           // Whether every track was gone at the last update
+    last_bell: Option<std::time::Instant>, // When the bell last rang
+    bell_flash_until: Option<std::time::Instant>, // End of the visual bell showing now
+    history_paused: bool, // The state directory is low on space, so listens are not appended
+    prepared_next: Option<(usize, usize)>, // Playing and next track indices the next was last decided for
+    preloaded: Option<PathBuf>, // Track the player last reported decoded ahead of time
+    queue_failed: Option<PathBuf>, // Track that failed to open when queued, not tried again until a track loads
 }
 
 impl App {
     /// Creates a new application with the given playlist and config.
-    pub fn new(playlist: Playlist, config: Config) -> Result<Self, PlayerError> {
-        let player = Player::new()?;
+    pub fn new(
+        playlist: Playlist,
+        config: Config,
+        state: StateStore,
+        theme: Theme,
+        strings: Strings,
+    ) -> Result<Self, PlayerError> {
+        let opening = std::time::Instant::now();
+        let (player, device_warning) = Player::with_device(config.playback.device.as_deref())?;
+        let audio_init = opening.elapsed();
+        Self::with_player(player, device_warning, audio_init, playlist, config, state, theme, strings)
+    }
+
+    /// Creates the application on a player without a device, drawing to a
+    /// test backend. Nothing listens for remote or MPD clients.
+    #[cfg(test)]
+    pub fn for_tests(playlist: Playlist, mut config: Config) -> Self {
+        config.remote.enabled = false;
+        config.integrations.mpd_port = None;
+        let theme = Theme::from_config(&config).unwrap();
+        let state = StateStore::open(None, None);
+        let player = Player::without_device();
+        Self::with_player(player, None, Duration::ZERO, playlist, config, state, theme, Strings::default()).unwrap()
+    }
+
+    /// Draws the interface and returns what the test backend shows, a line
+    /// a row.
+    #[cfg(test)]
+    pub fn screen(&mut self) -> String {
+        self.display_status();
+        let Frontend::Terminal(terminal) = &self.frontend else {
+            return String::new();
+        };
+        let buffer = terminal.backend().buffer();
+        let rows = buffer.content.chunks(usize::from(buffer.area.width));
+        rows.map(|row| row.iter().map(|cell| cell.symbol()).collect::<String>()).collect::<Vec<_>>().join("\n")
+    }
+
+    /// Creates the application around a player already opened, taking
+    /// `audio_init` to open it.
+    #[allow(clippy::too_many_arguments)]
+    fn with_player(
+        mut player: Player,
+        device_warning: Option<String>,
+        audio_init: Duration,
+        mut playlist: Playlist,
+        config: Config,
+        state: StateStore,
+        theme: Theme,
+        strings: Strings,
+    ) -> Result<Self, PlayerError> {
+        player.set_preamp_db(config.playback.preamp_db);
+        player.set_limiter(config.playback.limiter, Duration::from_millis(config.playback.limiter_release_ms.into()));
+        player.set_volume(f32::from(config.playback.volume) / 100.0);
+        player.set_decoded_cache((config.playback.decoded_cache_mb as usize) << 20);
+        player.set_track_change_fade(Duration::from_millis(config.playback.track_change_fade_ms.into()));
+        player.set_startup_fade(Duration::from_secs(config.playback.startup_fade_secs.into()));
+        let player_events = player.subscribe();
+        let loudness = LoudnessCache::load(state.dir());
+        let library = Library::load(state.dir());
+        let tag_cache = TagCache::load(state.dir());
+        let cached_tags = tag_cache.apply(&mut playlist);
+        if config.playlist.smart_shuffle {
+            let weights = playlist
+                .tracks()
+                .iter()
+                .map(|track| (track.path.clone(), library.stats(&track.path).shuffle_weight()))
+                .filter(|(_, weight)| *weight != 1.0)
+                .collect();
+            playlist.set_shuffle_weights(Some(weights));
+        }
+        let notifier = Notifier::new(&config.notifications, &strings);
+
+        // Surface unreadable entries from the directory scan
+        let mut log = Vec::new();
+        let mut notice = None;
+        if let Some(warning) = device_warning {
+            notice = Some(strings.text_with("notice.see_log", &[("message", &warning)]));
+            log.push(warning);
+        }
+        if let Some(report) = playlist.scan_report() {
+            if !report.errors.is_empty() {
+                notice = Some(strings.text_with(
+                    "notice.scan_errors",
+                    &[
+                        ("scanned", &format_count(report.scanned)),
+                        ("errors", &format_count(report.errors.len())),
+                    ],
+                ));
+            }
+            for (path, error) in &report.errors {
+                log.push(format!("{}: {}", path.display(), error));
+            }
+        }
+
+        let remote = if config.remote.enabled {
+            match RemoteServer::start() {
+                Ok(server) => Some(server),
+                Err(e) => {
+                    log.push(strings.text_with("log.remote_disabled", &[("error", &e)]));
+                    None
+                }
+            }
+        } else {
+            None
+        };
 
-        let backend = CrosstermBackend::new(io::stdout());
-        let terminal = Terminal::new(backend)
-            .map_err(|e| PlayerError::InitializationError(e.to_string()))?;
+        // Fill in missing tags from MusicBrainz without holding up playback
+        let lookup_rx = match (config.lookup.enabled, lookup::AVAILABLE) {
+            (true, true) => {
+                let lookup = Lookup::new(&config.lookup, state.dir());
+                let tracks = playlist.tracks().iter().filter(|t| lookup::needs_lookup(t)).cloned().collect();
+                Some(lookup::spawn(tracks, lookup))
+            }
+            (true, false) => {
+                log.push(strings.text("log.lookup_unavailable").to_string());
+                None
+            }
+            (false, _) => None,
+        };
+
+        let announcer = if config.ui.announce_tracks {
+            let announcer = Announcer::new(&config.ui);
+            if announcer.is_none() {
+                log.push(strings.text("log.announce_unavailable").to_string());
+            }
+            announcer
+        } else {
+            None
+        };
+
+        let mpd = config.integrations.mpd_port.and_then(|port| match MpdServer::start(port) {
+            Ok(server) => Some(server),
+            Err(e) => {
+                log.push(strings.text_with("log.mpd_disabled", &[("port", &port), ("error", &e)]));
+                None
+            }
+        });
+
+        let (track_list_layout, warnings) =
+            columns::Layout::parse(config.ui.track_list_columns.as_deref().unwrap_or(columns::DEFAULT));
+        log.extend(warnings);
+
+        let marks = Marks::load(state.dir(), source_path(&playlist).as_deref());
+
+        if playlist.load_options().fast_scan {
+            let unread = playlist.tracks().iter().filter(|track| !track.probed).count();
+            log.push(strings.text_with("log.fast_scan", &[("cached", &cached_tags), ("unread", &unread)]));
+        }
+
+        let visualizer_source = visualizer::choose(config.visualizer.source, player.level_meter());
+        let bars = config.visualizer.bars;
+        let listen_log =
+            config.integrations.listen_log.as_deref().map(|path| crate::setup::expand_home(&path.to_string_lossy()));
+        let source = strings.text(visualizer_source.kind().text_key()).to_string();
+        log.push(strings.text_with("visualizer.source", &[("source", &source)]));
+        let (keymap, unknown_keys) = Keymap::new(&config.keys);
+        for key in unknown_keys {
+            log.push(strings.text_with("log.unknown_key", &[("key", &key)]));
+        }
+
+        let low_bandwidth = config.ui.low_bandwidth(std::env::var_os("SSH_CONNECTION").is_some());
+        let frontend = match config.ui.mode {
+            InterfaceMode::Full => Frontend::Terminal(
+                Terminal::new(backend()).map_err(|e| PlayerError::InitializationError(e.to_string()))?,
+            ),
+            InterfaceMode::Plain => Frontend::Plain(PlainPrinter::default()),
+        };
 
         Ok(Self {
             player,
             playlist,
             config,
+            loudness,
+            library,
+            theme,
+            strings,
+            keymap,
+            notifier,
+            announcer,
+            remote,
+            mpd,
+            mpd_seen: None,
+            listen_log,
             running: true,
-            last_display_update: std::time::Instant::now(),
-            terminal,
+            redraw: Redraw::new(std::time::Instant::now()),
+            shown_tasks: None,
+            last_tick: suspend::Clocks::now(),
+            last_position: Duration::ZERO,
+            last_visualizer_update: std::time::Instant::now(),
+            frontend,
             ui_mode: UIMode::Normal,
             search_query: String::new(),
-            waveform_history: vec![0.0; 12], // 12 fixed bars for visualization
+            waveform_history: vec![0.0; bars],
+            visualizer_source,
             track_list_selected: 0,
-            track_list_scroll: 0,
+            track_list_view: Viewport::new(TRACK_LIST_MARGIN),
+            track_list_layout,
             filtered_indices: Vec::new(),
+            filtered_for: None,
+            chips: Chips::default(),
+            chip_counts: [0; 4],
+            status_message: None,
+            notice,
+            log,
+            log_scroll: 0,
+            tasks: Pool::new(MAX_TASKS),
+            refresh_task: None,
+            tag_cache,
+            tags_task: None,
+            tags_read: 0,
+            measure_task: None,
+            lookup_rx,
+            tag_writes: TagWriteQueue::spawn(),
+            audio_init,
+            startup: None,
+            startup_summary: None,
+            player_events,
+            pending_delete: None,
+            pending_seek: None,
+            seek_failed_at: None,
+            seek_target: None,
+            sort: None,
+            jump_index: None,
+            sort_menu: ui::Menu::default(),
+            sort_menu_descending: false,
+            queue_panel: false,
+            focus: Focus::NowPlaying,
+            queue_panel_selected: 0,
+            queue_panel_view: Viewport::new(TRACK_LIST_MARGIN),
+            low_bandwidth,
+            last_frame: None,
+            last_draw: std::time::Instant::now(),
+            redraw_pending: false,
+            stats: stats::Summary::default(),
+            marks,
+            marks_menu: ui::Menu::default(),
+            key_prefix: None,
+            stop_after: false,
+            focused: true,
+            session_path: None,
+            saved_queue: Vec::new(),
+            pending_replay: None,
+            preview: None,
+            browser: None,
+            preferences_menu: ui::Menu::default(),
+            palette_menu: ui::Menu::default(),
+            palette_query: String::new(),
+            palette_actions: Vec::new(),
+            palette_from: UIMode::Normal,
+            prompt: ui::Prompt::default(),
+            prompt_action: None,
+            prompt_from: UIMode::Normal,
+            prompt_history: PromptHistory::load(state.dir()),
+            saved_to: None,
+            quit_menu: ui::Menu::default(),
+            quit_from: UIMode::Normal,
+            edits_discarded: false,
+            preferences_changed: Vec::new(),
+            state,
+            playlist_empty: false,
+            last_bell: None,
+            bell_flash_until: None,
+            history_paused: false,
+            prepared_next: None,
+            preloaded: None,
+            queue_failed: None,
         })
     }
 
     /// Starts the application and loads the first track.
     pub fn start(&mut self) -> Result<(), PlayerError> {
-        if let Some(track) = self.playlist.current_track() {
-            self.player.load_track(&track.path)?;
+        if !self.playlist.is_empty() {
+            self.open_current_track(false)?;
+            if !self.player.has_track() {
+                return Ok(());
+            }
+
+            if let Some(position) = self.pending_seek.take() {
+                if position < self.player.duration() {
+                    self.player.seek_to(position)?;
+                } else {
+                    self.log.push(self.strings.text("session.past_end").to_string());
+                }
+            }
+
             self.player.play();
             self.display_status();
         }
         Ok(())
     }
 
-    /// Updates the application state (called from main loop).
-    pub fn update(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        // Check if current track finished
-        if self.player.has_track() && self.player.is_finished() {
-            if self.playlist.next() {
-                self.load_current_track()?;
+    /// Returns how long opening the audio output took.
+    pub fn audio_init_time(&self) -> Duration {
+        self.audio_init
+    }
+
+    /// Hands over the startup profile, to be finished and logged once the
+    /// first frame is drawn.
+    pub fn set_startup_profile(&mut self, profile: Profile) {
+        self.startup = Some(profile);
+    }
+
+    /// Returns the startup profile's summary line.
+    pub fn startup_summary(&self) -> Option<String> {
+        self.startup_summary.clone().or_else(|| self.startup.as_ref().map(Profile::summary))
+    }
+
+    /// Finishes the startup profile with the first frame drawn.
+    fn first_frame_drawn(&mut self, drawing: std::time::Instant) {
+        let Some(mut profile) = self.startup.take() else {
+            return;
+        };
+        profile.add("first_frame", drawing.elapsed());
+        profile.finish();
+        let summary = profile.summary();
+        self.log.push(summary.clone());
+        self.startup_summary = Some(summary);
+    }
+
+    /// Applies the preferences the playlist's M3U file carries, before
+    /// [`App::start`]. The volume is left alone when `keep_volume` is set,
+    /// as when given on the command line.
+    pub fn apply_playlist_settings(&mut self, keep_volume: bool) {
+        let settings = self.playlist.settings().clone();
+        if settings.is_empty() {
+            return;
+        }
+        if let Some(shuffle) = settings.shuffle {
+            self.playlist.set_shuffle(shuffle);
+        }
+        if let Some(repeat) = settings.repeat {
+            self.playlist.set_repeat(repeat);
+        }
+        if let Some(volume) = settings.volume
+            && !keep_volume
+        {
+            self.set_volume(volume);
+        }
+        // The tracks were saved in this order, so it is only remembered
+        if settings.sort.is_some() {
+            self.sort = settings.sort;
+        }
+        let message = self.strings.text("playlist.settings_applied").to_string();
+        self.log.push(message.clone());
+        self.flash(message);
+    }
+
+    /// Restores a saved session before [`App::start`].
+    ///
+    /// The playlist source is chosen by the caller. The remaining pieces are
+    /// applied in order (track, position, volume, shuffle/repeat, queue), and
+    /// each one is skipped with a log entry if it no longer applies.
+    pub fn restore_session(&mut self, saved: &Session, restore_volume: bool) {
+        // Track
+        let mut track_restored = false;
+        if let Some(path) = &saved.track {
+            let key = crate::playlist::canonical_key(path);
+            match self
+                .playlist
+                .tracks()
+                .iter()
+                .position(|t| crate::playlist::canonical_key(&t.path) == key)
+            {
+                Some(index) => track_restored = self.playlist.goto(index),
+                None => self.log.push(self.strings.text("session.missing_track").to_string()),
+            }
+        }
+
+        // Position, only meaningful within the restored track
+        if let Some(secs) = saved.position_secs
+            && self.config.resume.restore_position
+        {
+            if track_restored {
+                self.pending_seek = Some(Duration::from_secs(secs));
             } else {
-                // End of playlist
-                self.running = false;
+                self.log.push(self.strings.text("session.other_track").to_string());
+            }
+        }
+
+        // Volume, unless given on the command line or in the environment
+        if let Some(volume) = saved.volume
+            && restore_volume
+        {
+            if volume <= 100 {
+                self.set_volume(volume);
+            } else {
+                self.log.push(self.strings.text_with("session.invalid_volume", &[("volume", &volume)]));
             }
         }
 
-        // Update waveform visualization
-        if self.player.has_track() {
-            self.update_waveform();
+        // Shuffle and repeat
+        if let Some(shuffle) = saved.shuffle {
+            self.playlist.set_shuffle(if shuffle { ShuffleState::On } else { ShuffleState::Off });
+        }
+        if saved.repeat.is_some() {
+            match saved.repeat_mode() {
+                Some(mode) => self.playlist.set_repeat(mode),
+                None => self.log.push(self.strings.text("session.invalid_repeat").to_string()),
+            }
         }
 
-        // Update display periodically for smooth waveform animation
-        // Update every 30ms when playing, every second when paused
-        let update_interval = if self.player.state() == crate::player::PlaybackState::Playing {
-            Duration::from_millis(30)
-        } else {
-            Duration::from_secs(1)
-        };
+        // Queue, dropping tracks that are gone
+        if self.config.resume.restore_queue {
+            let restored = self.playlist.restore_queue(&saved.queue);
+            if restored > 0 {
+                let message = self.strings.text_with("session.queue_restored", &[("count", &restored)]);
+                self.log.push(message.clone());
+                self.flash(message);
+            }
+        }
+        self.saved_queue = self.playlist.queue().to_vec();
+    }
+
+    /// Captures the current session for saving at exit.
+    pub fn session(&self) -> Session {
+        let source = source_path(&self.playlist);
+        let position_secs = self
+            .config
+            .resume
+            .restore_position
+            .then(|| self.player.current_position().as_secs())
+            .filter(|_| self.player.has_track());
+
+        Session {
+            version: SESSION_VERSION,
+            source,
+            track: self.playlist.current_track().map(|t| t.path.clone()),
+            position_secs,
+            volume: Some((self.player.volume() * 100.0).round() as u8),
+            shuffle: Some(self.playlist.shuffle_state() == ShuffleState::On),
+            repeat: Some(session::repeat_name(self.playlist.repeat_mode()).to_string()),
+            queue: self.playlist.queued_paths(),
+        }
+    }
+
+    /// Sets the file the session is saved to whenever the queue changes.
+    pub fn set_session_path(&mut self, path: PathBuf) {
+        self.session_path = Some(path);
+    }
+
+    /// Saves the session if the queue changed since it was last saved, so a
+    /// crash does not lose it.
+    fn save_queue_if_changed(&mut self) {
+        if self.playlist.queue() == self.saved_queue.as_slice() {
+            return;
+        }
+        self.saved_queue = self.playlist.queue().to_vec();
+        self.mark();
+        if let Some(path) = &self.session_path
+            && let Err(e) = self.session().save(path)
+        {
+            self.log.push(self.strings.text_with("error.save_session", &[("error", &e)]));
+        }
+    }
+
+    /// Stops playback once the last track is gone, deleted or dropped by a
+    /// refresh, and loads the first track when a refresh brings some back.
+    fn check_empty_playlist(&mut self) {
+        let empty = self.playlist.is_empty();
+        if empty == self.playlist_empty {
+            return;
+        }
+        self.playlist_empty = empty;
+        self.mark();
+        if empty {
+            self.player.stop();
+            self.player.stop_preview();
+            self.preview = None;
+            self.pending_seek = None;
+            self.pending_replay = None;
+            self.seek_target = None;
+            self.stop_after = false;
+            self.queue_panel_selected = 0;
+            // The empty playlist screen is the player view
+            self.set_ui_mode(UIMode::Normal);
+        } else if let Err(e) = self.open_current_track(false) {
+            self.flash_error(self.strings.text_with("error.playback", &[("error", &e)]));
+        }
+    }
+
+    /// Flashes that the playlist is empty and returns true if it is, for
+    /// commands that need a track.
+    fn nothing_to_play(&mut self) -> bool {
+        if !self.playlist.is_empty() {
+            return false;
+        }
+        self.flash(self.strings.text("empty.nothing_to_play").to_string());
+        true
+    }
+
+    /// Updates the application state (called from main loop).
+    pub fn update(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.check_for_sleep();
+
+        // Apply a finished background refresh
+        self.poll_refresh();
+        self.poll_lookup();
+        self.poll_tags();
+        self.poll_measure();
+        self.poll_remote();
+        self.poll_tag_writes();
+
+        self.check_empty_playlist();
+        self.save_queue_if_changed();
+        self.apply_replay();
+        self.apply_seek_target();
+        if self.preview.is_some() && !self.player.preview_playing() {
+            self.end_preview();
+        }
+        let player_changed = self.poll_player()?;
+        self.wake_mpd_clients(player_changed);
+        self.prepare_next_track();
+        self.queue_next_track();
 
-        if self.last_display_update.elapsed() >= update_interval {
+        // Advance the visualizer at its own rate
+        let elapsed = self.last_visualizer_update.elapsed();
+        if !self.visualizer_active() {
+            self.waveform_history.fill(0.0);
+        } else if elapsed >= visualizer::frame_interval(self.config.visualizer.fps) {
+            self.update_waveform(elapsed);
+            self.last_visualizer_update = std::time::Instant::now();
+        }
+
+        // The visual bell is over; take it off the screen now
+        if self.bell_flash_until.is_some_and(|until| std::time::Instant::now() >= until) {
+            self.bell_flash_until = None;
+            self.mark();
+        }
+
+        // Progress moves on between results
+        let tasks = self.task_status();
+        if tasks != self.shown_tasks {
+            self.shown_tasks = tasks;
+            self.mark();
+        }
+
+        // Draw whatever changed during this iteration, the clock's tick, or
+        // a change held back by the low bandwidth frame limit
+        if self.redraw.due(std::time::Instant::now(), self.redraw_interval())
+            || (self.redraw_pending && self.last_draw.elapsed() >= LOW_BANDWIDTH_FRAME)
+        {
             self.display_status();
-            self.last_display_update = std::time::Instant::now();
         }
 
         Ok(())
     }
 
-    /// Updates the waveform visualization data.
-    fn update_waveform(&mut self) {
-        // Update all bars independently (simulated based on playback state)
-        if self.player.state() == crate::player::PlaybackState::Playing {
-            // Generate bar heights based on time
-            // In a real implementation, this would use FFT on actual audio data
-            // Use modulo to keep time in a reasonable range for sine calculations
-            let time = (std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap_or(Duration::ZERO)
-                .as_millis() % 60000) as f32 / 1000.0; // 0-60 seconds range
-
-            // Update each bar with its own frequency to simulate different frequency bands
-            for (i, bar) in self.waveform_history.iter_mut().enumerate() {
-                // Each bar has a different base frequency (simulating bass to treble)
-                let freq = 1.0 + (i as f32 * 0.5); // Frequencies from 1 Hz to 6.5 Hz
-                let amplitude = (time * freq * std::f32::consts::PI).sin().abs();
-                // Add some variation to make it more interesting
-                let variation = (time * freq * 2.0).sin() * 0.3;
-                *bar = (amplitude * 0.7 + variation.abs() * 0.3).min(1.0);
+    /// Handles the player's events since the last update, including those
+    /// caused by handling earlier ones. Returns whether there were any.
+    fn poll_player(&mut self) -> Result<bool, PlayerError> {
+        self.player.poll();
+        let mut changed = false;
+        while let Ok(event) = self.player_events.try_recv() {
+            changed = true;
+            self.mark();
+            match event {
+                PlayerEvent::TrackLoaded { path, .. } => {
+                    self.preloaded = None;
+                    self.queue_failed = None;
+                    self.tag_writes.set_playing(Some(&path));
+                    self.notify(notify::Event::TrackChange);
+                    if self.config.playlist.accurate_duration {
+                        self.measure_current_track();
+                    }
+                }
+                PlayerEvent::Stopped => self.tag_writes.set_playing(None),
+                // Pausing for a preview is not worth a notification
+                PlayerEvent::Paused if self.preview.is_none() => self.notify(notify::Event::Pause),
+                PlayerEvent::Finished => self.track_finished()?,
+                PlayerEvent::Preloaded { path, ready } => self.preloaded = ready.then_some(path),
+                // Errors are reported by whatever asked the player to do something
+                _ => {}
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Wakes idle MPD clients when the player did something, or the
+    /// playlist, the play options or the volume changed since last time.
+    fn wake_mpd_clients(&mut self, player_changed: bool) {
+        let Some(mpd) = &self.mpd else {
+            return;
+        };
+        let seen = (
+            self.playlist.revision(),
+            self.playlist.current_index(),
+            self.playlist.shuffle_state(),
+            self.playlist.repeat_mode(),
+            self.player.volume().to_bits(),
+        );
+        if player_changed || self.mpd_seen != Some(seen) {
+            mpd.notify();
+            self.mpd_seen = Some(seen);
+        }
+    }
+
+    /// Decodes the track that actually plays next, after the queue and
+    /// shuffle, ahead of time when it should follow the current one without
+    /// a gap. Decided once per pair, within [`GAPLESS_LEAD`] of the end.
+    fn prepare_next_track(&mut self) {
+        let remaining = self.player.duration().saturating_sub(self.player.current_position());
+        if !self.config.playback.preload
+            || self.player.state() != crate::player::PlaybackState::Playing
+            || self.player.duration().is_zero()
+            || remaining > GAPLESS_LEAD
+        {
+            return;
+        }
+        let (Some(current), Some(next)) = (self.playlist.current_track_index(), self.playlist.peek_next()) else {
+            return;
+        };
+        if self.prepared_next == Some((current, next)) {
+            return;
+        }
+        self.prepared_next = Some((current, next));
+        let tracks = self.playlist.tracks();
+        if gapless::transition(&tracks[current], &tracks[next], &self.config.playback) == Transition::Gapless {
+            self.player.prepare_next(&tracks[next].path);
+        }
+    }
+
+    /// Appends the track that plays next to the one playing within
+    /// [`QUEUE_LEAD`] of the end, when it should follow without a gap, so
+    /// that it starts the moment this one ends. One no longer next, as after
+    /// a change to the queue or shuffle, is dropped.
+    fn queue_next_track(&mut self) {
+        let remaining = self.player.duration().saturating_sub(self.player.current_position());
+        let due = self.config.playback.preload
+            && !self.stop_after
+            && self.player.state() == crate::player::PlaybackState::Playing
+            && !self.player.duration().is_zero()
+            && remaining <= QUEUE_LEAD;
+        let next = self.playlist.peek_next().filter(|&next| due && !self.playlist.is_unplayable(next));
+        let wanted = match (self.playlist.current_track(), next.and_then(|next| self.playlist.tracks().get(next))) {
+            // Segments open at their own start, not where the file begins
+            (Some(current), Some(next))
+                if !next.is_segment()
+                    && gapless::transition(current, next, &self.config.playback) == Transition::Gapless =>
+            {
+                Some(next.path.clone())
             }
+            _ => None,
+        };
+        if self.player.queued_track() == wanted.as_deref() {
+            return;
+        }
+        self.player.unqueue_next_track();
+        if let Some(path) = wanted.filter(|path| self.queue_failed.as_ref() != Some(path))
+            && self.player.queue_next_track(&path).is_err()
+        {
+            // It is skipped, and the error logged, when it comes to play
+            self.queue_failed = Some(path);
+        }
+    }
+
+    /// Whether the player reported the track that plays next decoded ahead
+    /// of time.
+    fn next_preloaded(&self) -> bool {
+        let next = self.playlist.peek_next().and_then(|index| self.playlist.tracks().get(index));
+        next.is_some_and(|track| self.preloaded.as_ref() == Some(&track.path))
+    }
+
+    /// Describes how full the decoded cache is, for the top of the log view.
+    fn decoded_cache_line(&self) -> Option<String> {
+        let usage = self.player.decoded_cache_usage()?;
+        let megabytes = |bytes: usize| format!("{:.1}", bytes as f64 / f64::from(1 << 20));
+        Some(self.strings.text_with(
+            "log.decoded_cache",
+            &[("entries", &usage.entries), ("used", &megabytes(usage.bytes)), ("budget", &megabytes(usage.budget))],
+        ))
+    }
+
+    /// Moves on from a track that played to its end.
+    fn track_finished(&mut self) -> Result<(), PlayerError> {
+        self.check_duration();
+        self.record_listen(false);
+        // Stopping after this track wins over repeat: whatever comes next
+        // is loaded paused, so play starts it from the top
+        let play_on = !self.stop_after;
+        if self.stop_after && !self.config.playback.stop_after_latched {
+            self.stop_after = false;
+        }
+        let in_selection = self.playlist.selection_len().is_some();
+        if self.playlist.next() {
+            self.open_current_track(play_on)?;
+        } else if in_selection {
+            // A selection set to stop: stay on its last track, ready to play again
+            self.player.stop();
+            self.flash(self.strings.text("selection.over").to_string());
         } else {
-            // When paused, reset all bars to zero
-            for bar in self.waveform_history.iter_mut() {
-                *bar = 0.0;
+            // End of playlist
+            self.notify(notify::Event::PlaylistEnd);
+            self.ring_bell();
+            self.running = false;
+        }
+        Ok(())
+    }
+
+    /// Recovers playback when the system was suspended since the last
+    /// update. The output is reopened and the track reloaded at the last
+    /// position seen before the sleep.
+    fn check_for_sleep(&mut self) {
+        let now = suspend::Clocks::now();
+        let last = std::mem::replace(&mut self.last_tick, now);
+        if !suspend::was_suspended(last, now) {
+            self.last_position = self.player.current_position();
+            return;
+        }
+
+        let was_playing = self.player.state() == crate::player::PlaybackState::Playing;
+        match self.player.reopen_output(self.last_position) {
+            Ok(()) => {
+                if was_playing && !self.config.playback.pause_on_suspend {
+                    self.player.play();
+                }
+                self.flash(self.strings.text("player.resumed").to_string());
             }
+            Err(e) => self.flash_error(self.strings.text_with("error.playback", &[("error", &e)])),
+        }
+        self.mark();
+    }
+
+    /// Updates the waveform visualization data.
+    fn update_waveform(&mut self, elapsed: Duration) {
+        let decay = self.config.visualizer.decay;
+        if self.player.state() != crate::player::PlaybackState::Playing {
+            visualizer::settle_bars(&mut self.waveform_history, decay, elapsed);
+            return;
+        }
+        visualizer::update_bars(self.visualizer_source.as_ref(), &mut self.waveform_history, decay, elapsed);
+    }
+
+    /// Draws the visualizer from fixed levels, for screenshots.
+    pub fn use_test_visualizer(&mut self) {
+        self.visualizer_source = Box::new(visualizer::TestBars);
+        self.log_visualizer_source();
+    }
+
+    /// Notes in the log where the visualizer's levels come from.
+    fn log_visualizer_source(&mut self) {
+        let source = self.strings.text(self.visualizer_source.kind().text_key()).to_string();
+        self.log(self.strings.text_with("visualizer.source", &[("source", &source)]));
+    }
+
+    /// Returns true while the visualizer is enabled and a track is playing,
+    /// or has just paused and the bars are still falling.
+    fn visualizer_active(&self) -> bool {
+        self.config.visualizer.enabled
+            && !self.low_bandwidth
+            && self.focused
+            && matches!(self.frontend, Frontend::Terminal(_))
+            && self.player.has_track()
+            && (self.player.state() == crate::player::PlaybackState::Playing
+                || self.waveform_history.iter().any(|&bar| bar > 0.0))
+    }
+
+    /// Returns how often the screen is redrawn: every status tick, or every
+    /// visualizer frame while it is animating. Nobody watches the visualizer
+    /// in an unfocused terminal, so it stops and redraws drop to the tick.
+    fn redraw_interval(&self) -> Duration {
+        if self.visualizer_active() {
+            visualizer::frame_interval(self.config.visualizer.fps).min(STATUS_TICK)
+        } else {
+            STATUS_TICK
+        }
+    }
+
+    /// Returns how long to wait for input before the next redraw is due.
+    pub fn poll_timeout(&self) -> Duration {
+        let mut timeout = self.redraw.wait(std::time::Instant::now(), self.redraw_interval());
+        if self.redraw_pending {
+            timeout = timeout.min(LOW_BANDWIDTH_FRAME.saturating_sub(self.last_draw.elapsed()));
+        }
+        if let Some(until) = self.bell_flash_until {
+            timeout = timeout.min(until.saturating_duration_since(std::time::Instant::now()));
+        }
+        timeout
+            .clamp(Duration::from_millis(1), MAX_POLL_TIMEOUT)
+    }
+
+    /// Records whether the terminal has focus, redrawing when it comes back.
+    pub fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+        if focused {
+            self.mark();
         }
     }
 
     /// Handles play/pause toggle.
     pub fn toggle_play_pause(&mut self) {
+        if self.nothing_to_play() {
+            return;
+        }
+        // Stopped after nothing would open; try again
+        if !self.player.has_track() {
+            if let Err(e) = self.open_current_track(true) {
+                self.flash_error(self.strings.text_with("error.playback", &[("error", &e)]));
+            }
+            return;
+        }
         self.player.toggle_play_pause();
-        self.display_status();
+        self.mark();
+    }
+
+    /// Sends a desktop notification about the current track, if enabled,
+    /// and announces a new one.
+    fn notify(&self, event: notify::Event) {
+        let values = match self.playlist.current_track() {
+            Some(track) => Values::for_track(track, self.player.current_position(), self.player.duration())
+                .with_place(self.playlist.current_index(), self.playlist.len()),
+            None => Values::default(),
+        };
+        self.notifier.notify(event, &values, self.focused);
+        if event == notify::Event::TrackChange
+            && let Some(announcer) = &self.announcer
+        {
+            announcer.announce(&values);
+        }
     }
 
     /// Plays the next track.
     pub fn next_track(&mut self) -> Result<(), PlayerError> {
+        if self.nothing_to_play() {
+            return Ok(());
+        }
+        self.record_listen(true);
         if self.playlist.next() {
-            self.load_current_track()?;
+            self.switch_to_current_track()?;
         }
         Ok(())
     }
 
     /// Plays the previous track.
     pub fn previous_track(&mut self) -> Result<(), PlayerError> {
+        if self.nothing_to_play() {
+            return Ok(());
+        }
+        self.record_listen(false);
         if self.playlist.previous() {
-            self.load_current_track()?;
+            self.switch_to_current_track()?;
         }
         Ok(())
     }
 
     /// Seeks forward.
     pub fn seek_forward(&mut self) -> Result<(), PlayerError> {
-        let step = Duration::from_secs(self.config.playback.seek_step as u64);
-        self.player.seek_forward(step)?;
-        self.display_status();
+        self.move_seek_target(true);
         Ok(())
     }
 
     /// Seeks backward.
     pub fn seek_backward(&mut self) -> Result<(), PlayerError> {
-        let step = Duration::from_secs(self.config.playback.seek_step as u64);
-        self.player.seek_backward(step)?;
-        self.display_status();
+        self.move_seek_target(false);
         Ok(())
     }
 
-    /// Toggles shuffle mode.
-    pub fn toggle_shuffle(&mut self) {
-        self.playlist.toggle_shuffle();
-        self.display_status();
+    /// Moves the seek target a step from where the last press left it, or
+    /// from the position. The seek happens once presses stop coming.
+    fn move_seek_target(&mut self, forward: bool) {
+        if self.nothing_to_play() || !self.player.has_track() {
+            return;
+        }
+        let step = Duration::from_secs(self.config.playback.seek_step as u64);
+        let from = self.seek_target.map_or_else(|| self.player.current_position(), |(target, _)| target);
+        let target = if forward {
+            // Can't seek beyond the end
+            Some(from + step).filter(|&target| target < self.player.duration()).unwrap_or(from)
+        } else {
+            from.saturating_sub(step)
+        };
+        self.seek_target = Some((target, std::time::Instant::now()));
+        self.mark();
     }
 
-    /// Cycles repeat mode.
-    pub fn cycle_repeat(&mut self) {
-        self.playlist.cycle_repeat();
-        self.display_status();
+    /// Seeks to the target once no more seek presses have come for a moment.
+    fn apply_seek_target(&mut self) {
+        let Some((target, pressed_at)) = self.seek_target else {
+            return;
+        };
+        if pressed_at.elapsed() < SEEK_COALESCE {
+            return;
+        }
+        self.seek_target = None;
+        let result = self.player.seek_to(target);
+        self.finish_seek(result);
     }
 
-    /// Quits the application.
-    pub fn quit(&mut self) {
-        self.player.stop();
-        self.running = false;
+    /// Drops the seek target of a burst of seek presses without seeking.
+    /// Returns false if there was none.
+    pub fn cancel_seek(&mut self) -> bool {
+        if self.seek_target.take().is_none() {
+            return false;
+        }
+        self.mark();
+        true
     }
 
-    /// Stops playback before shutdown.
-    pub fn stop_playback(&mut self) {
-        self.player.stop();
+    /// Jumps back `replay_secs` and keeps playing. Presses in quick
+    /// succession add up and are applied as one seek once they stop.
+    pub fn replay(&mut self) {
+        if self.nothing_to_play() || !self.player.has_track() {
+            return;
+        }
+        let step = Duration::from_secs(u64::from(self.config.playback.replay_secs));
+        let (from, back) = match self.pending_replay {
+            Some((from, back, _)) => (from, back + step),
+            None => (self.player.current_position(), step),
+        };
+        self.pending_replay = Some((from, back, std::time::Instant::now()));
+        self.flash(self.strings.text_with("replay.back", &[("seconds", &back.as_secs())]));
     }
 
-    /// Returns whether the app is running.
-    pub fn is_running(&self) -> bool {
-        self.running
+    /// Applies the replay presses once no more have come for a moment.
+    fn apply_replay(&mut self) {
+        let Some((from, back, pressed_at)) = self.pending_replay else {
+            return;
+        };
+        if pressed_at.elapsed() < REPLAY_COALESCE {
+            return;
+        }
+        self.pending_replay = None;
+        // Never further back than the start, so the track never changes
+        let result = self.player.seek_to(from.saturating_sub(back));
+        self.finish_seek(result);
     }
 
-    /// Returns the current UI mode.
-    pub fn ui_mode(&self) -> UIMode {
-        self.ui_mode
+    /// Redraws after a seek. A failed seek is reported and briefly turns the
+    /// progress bar red rather than ending playback.
+    fn finish_seek(&mut self, result: Result<(), PlayerError>) {
+        if let Err(e) = result {
+            self.seek_failed_at = Some(std::time::Instant::now());
+            self.flash_error(self.strings.text_with("error.playback", &[("error", &e)]));
+        }
+        self.mark();
+    }
+
+    /// Turns shuffle on, overriding the restored session.
+    pub fn shuffle_on(&mut self) {
+        self.playlist.set_shuffle(ShuffleState::On);
+    }
+
+    /// Toggles shuffle mode.
+    pub fn toggle_shuffle(&mut self) {
+        self.playlist.toggle_shuffle();
+        self.mark();
+    }
+
+    /// Plays the current track's album in order, or goes back to the
+    /// playlist order if an album is playing.
+    pub fn toggle_album(&mut self) {
+        if self.nothing_to_play() {
+            return;
+        }
+        let message = if self.playlist.in_album() {
+            self.playlist.leave_album();
+            self.strings.text("album.left").to_string()
+        } else {
+            match self.playlist.play_album() {
+                Some(album) => self.strings.text_with("album.mode", &[("album", &album)]),
+                None => self.strings.text("album.none").to_string(),
+            }
+        };
+        self.flash(message);
+    }
+
+    /// Turns pausing at the end of the current track on or off.
+    pub fn toggle_stop_after(&mut self) {
+        if self.nothing_to_play() {
+            return;
+        }
+        self.stop_after = !self.stop_after;
+        let key = if self.stop_after { "stop_after.on" } else { "stop_after.off" };
+        let message = self.strings.text(key).to_string();
+        self.flash(message);
+    }
+
+    /// Cycles repeat mode.
+    pub fn cycle_repeat(&mut self) {
+        self.playlist.cycle_repeat();
+        self.mark();
+    }
+
+    /// Reloads the playlist from its source on a background thread.
+    pub fn refresh_playlist(&mut self) {
+        if self.refresh_task.is_some() {
+            return;
+        }
+        let sources = self.playlist.sources().to_vec();
+        if sources.is_empty() {
+            self.flash(self.strings.text("refresh.nothing").to_string());
+            return;
+        }
+        if !sources.iter().all(PlaylistSource::can_reload) {
+            self.flash(self.strings.text("refresh.stdin").to_string());
+            return;
+        }
+        let options = self.playlist.load_options().clone();
+
+        // Canonicalizing paths is slow on a network drive, so the task does it
+        let current: Vec<PathBuf> = self.playlist.tracks().iter().map(|t| t.path.clone()).collect();
+        let task = self.tasks.spawn(self.strings.text("task.refresh"), move |reporter| {
+            let result = PlaylistSource::load_all_watched(&sources, &options, &mut |scanned| {
+                reporter.progress(scanned, None)
+            });
+            reporter.send(result.map(|tracks| Refreshed::new(tracks, &current)));
+        });
+        self.refresh_task = Some(task);
+        self.mark();
+    }
+
+    /// Captures the player state for the `status` command.
+    fn status(&self) -> remote::Status {
+        let track = self.playlist.current_track().map(|track| remote::StatusTrack {
+            path: track.path.to_string_lossy().to_string(),
+            title: track.display_name(),
+            artist: track.artist.clone(),
+            album: track.album.clone(),
+        });
+        let state = match self.player.state() {
+            crate::player::PlaybackState::Playing => "playing",
+            crate::player::PlaybackState::Paused => "paused",
+            crate::player::PlaybackState::Stopped => "stopped",
+        };
+
+        remote::Status {
+            index: track.as_ref().map(|_| self.playlist.current_index()),
+            track,
+            position_secs: self.player.current_position().as_secs_f64(),
+            duration_secs: self.player.duration().as_secs_f64(),
+            state,
+            volume: (self.player.volume() * 100.0).round() as u8,
+            shuffle: self.playlist.shuffle_state() == ShuffleState::On,
+            repeat: session::repeat_name(self.playlist.repeat_mode()),
+            length: self.playlist.len(),
+            playlist_version: self.playlist.revision(),
+        }
+    }
+
+    /// Builds the reply to a remote `playlist` command.
+    fn playlist_json(&self) -> Value {
+        let tracks = self
+            .playlist
+            .tracks()
+            .iter()
+            .map(|track| {
+                remote::PlaylistEntry {
+                    path: track.path.to_string_lossy().to_string(),
+                    title: track.display_name(),
+                    artist: track.artist.clone(),
+                    album: track.album.clone(),
+                    duration_secs: track.duration.map(|d| d.as_secs_f64()),
+                }
+                .to_json()
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "version": self.playlist.revision(),
+            "tracks": tracks,
+        })
+    }
+
+    /// Runs commands received from `juke --send`, `juke --status`, and MPD clients.
+    fn poll_remote(&mut self) {
+        while let Some((command, reply)) = self
+            .remote
+            .as_ref()
+            .and_then(RemoteServer::try_next)
+            .or_else(|| self.mpd.as_ref().and_then(MpdServer::try_next))
+        {
+            let response = self.run_remote_command(command);
+            let _ = reply.send(response);
+        }
+    }
+
+    /// Runs one remote command.
+    fn run_remote_command(&mut self, command: remote::Command) -> Response {
+        let result = match command {
+            remote::Command::PlayPause
+            | remote::Command::Play
+            | remote::Command::Pause
+            | remote::Command::Next
+            | remote::Command::Previous
+            | remote::Command::PlayAt(_)
+                if self.playlist.is_empty() =>
+            {
+                return Response::Error(self.strings.text("empty.nothing_to_play").to_string());
+            }
+            remote::Command::PlayPause => {
+                self.toggle_play_pause();
+                Ok(())
+            }
+            remote::Command::Play => {
+                self.player.play();
+                Ok(())
+            }
+            remote::Command::Pause => {
+                self.player.pause();
+                Ok(())
+            }
+            remote::Command::Next => self.next_track(),
+            remote::Command::Previous => self.previous_track(),
+            remote::Command::PlayAt(index) => {
+                if index >= self.playlist.len() {
+                    return Response::Error(self.strings.text("remote.no_such_track").to_string());
+                }
+                self.record_listen(false);
+                self.playlist.goto(index);
+                self.switch_to_current_track()
+            }
+            remote::Command::Seek(position) => {
+                let position = Duration::from_secs_f64(position);
+                if !self.player.has_track() {
+                    return Response::Error(self.strings.text("remote.nothing_playing").to_string());
+                }
+                if position >= self.player.duration() {
+                    return Response::Error(self.strings.text("remote.past_end").to_string());
+                }
+                let result = self.player.seek_to(position);
+                if result.is_err() {
+                    self.seek_failed_at = Some(std::time::Instant::now());
+                }
+                result
+            }
+            remote::Command::Volume(level) => {
+                self.set_volume(level);
+                Ok(())
+            }
+            remote::Command::Quit => {
+                self.quit();
+                Ok(())
+            }
+            remote::Command::Status => {
+                return Response::Ok(vec![("status".to_string(), self.status().to_json())]);
+            }
+            remote::Command::Playlist => {
+                return Response::Ok(vec![("playlist".to_string(), self.playlist_json())]);
+            }
+        };
+
+        self.mark();
+        match result {
+            Ok(()) => Response::ok(),
+            Err(e) => Response::Error(e.to_string()),
+        }
+    }
+
+    /// Merges the result of a background refresh once it is ready.
+    fn poll_refresh(&mut self) {
+        let Some(task) = self.refresh_task.as_mut() else {
+            return;
+        };
+        let result = task.poll().pop();
+        if task.finished() {
+            self.refresh_task = None;
+        }
+        let Some(result) = result else {
+            return;
+        };
+        self.refresh_task = None;
+
+        match result {
+            Ok(refreshed) => {
+                let diff = self.playlist.merge_refreshed(refreshed);
+                self.tag_cache.apply(&mut self.playlist);
+                self.mark();
+                if self.ui_mode == UIMode::TrackList {
+                    self.update_filtered_indices();
+                }
+                self.flash(self.strings.text_with("refresh.done", &[("added", &diff.added), ("removed", &diff.removed)]));
+            }
+            Err(e) => self.flash_error(self.strings.text_with("refresh.failed", &[("error", &e)])),
+        }
+    }
+
+    /// Applies tags found by the background lookup.
+    fn poll_lookup(&mut self) {
+        let Some(rx) = self.lookup_rx.as_ref() else {
+            return;
+        };
+        let mut changed = false;
+        loop {
+            let (path, proposal) = match rx.try_recv() {
+                Ok(result) => result,
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.lookup_rx = None;
+                    break;
+                }
+            };
+            let Some(index) = self.playlist.tracks().iter().position(|t| t.path == path) else {
+                continue;
+            };
+            let mut applied = false;
+            self.playlist.update_track(index, |track| applied = lookup::apply(track, &proposal));
+            if applied {
+                let name = self.playlist.tracks()[index].display_name();
+                self.log.push(self.strings.text_with(
+                    "log.looked_up",
+                    &[("path", &path.display()), ("track", &name), ("score", &proposal.score)],
+                ));
+                changed = true;
+            }
+        }
+        if changed {
+            self.mark();
+            if self.ui_mode == UIMode::TrackList {
+                self.update_filtered_indices();
+            }
+        }
+    }
+
+    /// Reads the tags a fast scan left out in the background, filling in
+    /// the track list as they arrive.
+    pub fn read_tags(&mut self) {
+        if self.tags_task.is_some() {
+            return;
+        }
+        let mut paths: Vec<PathBuf> =
+            self.playlist.tracks().iter().filter(|track| !track.probed).map(|track| track.path.clone()).collect();
+        // Segments of one file share its tags
+        paths.dedup();
+        if paths.is_empty() {
+            self.flash(self.strings.text("tags.all_read").to_string());
+            return;
+        }
+        self.tags_read = 0;
+        self.tags_task = Some(self.tasks.spawn(self.strings.text("task.read_tags"), move |reporter| {
+            tagcache::read(paths, reporter)
+        }));
+        self.mark();
+    }
+
+    /// Applies tags read in the background.
+    fn poll_tags(&mut self) {
+        let Some(task) = self.tags_task.as_mut() else {
+            return;
+        };
+        let read = task.poll();
+        let finished = task.finished();
+        for probed in &read {
+            self.apply_tags(probed);
+        }
+        self.tags_read += read.len();
+        if finished {
+            self.tags_task = None;
+            let _ = self.tag_cache.save();
+            self.flash(self.strings.text_with("tags.read", &[("count", &self.tags_read)]));
+        }
+        if !read.is_empty() {
+            self.mark();
+            if self.ui_mode == UIMode::TrackList {
+                self.update_filtered_indices();
+            }
+        }
+    }
+
+    /// Compares how long the track that just ended played with the length
+    /// it claimed, and measures its file when they are too far apart.
+    fn check_duration(&mut self) {
+        let (Some(played), Some(track)) = (self.player.played_to_end(), self.playlist.current_track()) else {
+            return;
+        };
+        let claimed = self.player.duration();
+        if !frames::disagrees(claimed, played) || !self.measurable(track) {
+            return;
+        }
+        self.log.push(self.strings.text_with(
+            "log.duration_mismatch",
+            &[
+                ("track", &track.display_name()),
+                ("played", &template::format_time(played)),
+                ("claimed", &template::format_time(claimed)),
+            ],
+        ));
+        self.measure_current_track();
+    }
+
+    /// Whether a track is an MP3 played whole that was not measured yet.
+    fn measurable(&self, track: &Track) -> bool {
+        !track.is_segment() && frames::measurable(&track.path) && self.tag_cache.measured(&track.path).is_none()
+    }
+
+    /// Measures the current track's file frame by frame in the background,
+    /// if it is measurable. One file is measured at a time.
+    fn measure_current_track(&mut self) {
+        let Some(track) = self.playlist.current_track().filter(|track| self.measurable(track)) else {
+            return;
+        };
+        if self.measure_task.is_some() {
+            return;
+        }
+        let path = track.path.clone();
+        let file = path.clone();
+        let task = self.tasks.spawn(self.strings.text("task.measure"), move |reporter| frames::measure(&file, reporter));
+        self.measure_task = Some((path, task));
+    }
+
+    /// Applies a length measured in the background to the tracks of its
+    /// file, to the player if it is playing it, and to the tag cache.
+    fn poll_measure(&mut self) {
+        let Some((_, task)) = self.measure_task.as_mut() else {
+            return;
+        };
+        let measured = task.poll().pop();
+        if measured.is_none() && !task.finished() {
+            return;
+        }
+        let Some((path, _)) = self.measure_task.take() else {
+            return;
+        };
+        let Some(length) = measured.flatten() else {
+            return;
+        };
+        self.tag_cache.set_measured(&path, length);
+        let _ = self.tag_cache.save();
+        self.mark();
+        let mut claimed = None;
+        for index in 0..self.playlist.len() {
+            let track = &self.playlist.tracks()[index];
+            if track.path == path && !track.is_segment() {
+                claimed = claimed.or(track.duration);
+                self.playlist.update_track(index, |track| track.duration = Some(length));
+            }
+        }
+        if self.playlist.current_track().is_some_and(|track| track.path == path && !track.is_segment()) {
+            self.player.correct_duration(length);
+        }
+        if claimed.is_none_or(|claimed| claimed.abs_diff(length) >= Duration::from_secs(1)) {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let claimed = claimed.map_or_else(|| "--:--".to_string(), template::format_time);
+            self.log.push(self.strings.text_with(
+                "log.duration_measured",
+                &[("track", &name), ("length", &template::format_time(length)), ("claimed", &claimed)],
+            ));
+        }
+    }
+
+    /// Cancels the long operations running, keeping what they have done so
+    /// far. Returns false when none were.
+    pub fn cancel_tasks(&mut self) -> bool {
+        let mut names = Vec::new();
+        if let Some(task) = self.refresh_task.take() {
+            names.push(task.name().to_string());
+        }
+        if let Some(task) = self.tags_task.take() {
+            names.push(task.name().to_string());
+            let _ = self.tag_cache.save();
+        }
+        if let Some((_, task)) = self.measure_task.take() {
+            names.push(task.name().to_string());
+        }
+        if names.is_empty() {
+            return false;
+        }
+        self.flash(self.strings.text_with("task.cancelled", &[("tasks", &names.join(", "))]));
+        true
+    }
+
+    /// Describes the long operations running, for the status line.
+    fn task_status(&self) -> Option<String> {
+        let tasks: Vec<String> = [
+            self.refresh_task.as_ref().map(|task| describe_task(task, &self.strings)),
+            self.tags_task.as_ref().map(|task| describe_task(task, &self.strings)),
+            self.measure_task.as_ref().map(|(_, task)| describe_task(task, &self.strings)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        (!tasks.is_empty()).then(|| self.strings.text_with("task.status", &[("tasks", &tasks.join(" · "))]))
+    }
+
+    /// Fills in the tracks of a file a fast scan left unread, and caches its
+    /// tags for the next launch.
+    fn apply_tags(&mut self, probed: &Track) {
+        self.tag_cache.insert(probed);
+        for index in 0..self.playlist.len() {
+            let track = &self.playlist.tracks()[index];
+            if !track.probed && track.path == probed.path {
+                self.playlist.update_track(index, |track| track.enrich(probed));
+            }
+        }
+    }
+
+    /// Logs tag writes made since the last update.
+    fn poll_tag_writes(&mut self) {
+        for outcome in self.tag_writes.finished() {
+            let path = outcome.path.display();
+            let message = match outcome.result {
+                Ok(()) => self.strings.text_with("log.tags_written", &[("path", &path)]),
+                Err(e) => self.strings.text_with("error.tag_write", &[("path", &path), ("error", &e)]),
+            };
+            self.log.push(message);
+            self.mark();
+        }
+    }
+
+    /// Raises the pre-amp by one step.
+    pub fn preamp_up(&mut self) {
+        self.adjust_preamp(PREAMP_STEP_DB);
+    }
+
+    /// Lowers the pre-amp by one step.
+    pub fn preamp_down(&mut self) {
+        self.adjust_preamp(-PREAMP_STEP_DB);
+    }
+
+    /// Raises the volume by one step.
+    pub fn volume_up(&mut self) {
+        self.adjust_volume(VOLUME_STEP);
+    }
+
+    /// Lowers the volume by one step.
+    pub fn volume_down(&mut self) {
+        self.adjust_volume(-VOLUME_STEP);
+    }
+
+    /// Mutes output, or unmutes it at the volume it had.
+    pub fn toggle_mute(&mut self) {
+        self.player.set_muted(!self.player.is_muted());
+        self.mark();
+    }
+
+    /// Adjusts the volume in whole percent, which the status line shows.
+    fn adjust_volume(&mut self, delta: i16) {
+        let volume = (i16::from(self.config.playback.volume) + delta).clamp(0, 100) as u8;
+        self.set_volume(volume);
+        self.mark();
+    }
+
+    /// Sets the volume, kept in the config so the preferences screen starts
+    /// from it.
+    fn set_volume(&mut self, volume: u8) {
+        self.config.playback.volume = volume;
+        self.player.set_volume(f32::from(volume) / 100.0);
+    }
+
+    /// Adjusts the pre-amp and flashes the new value.
+    fn adjust_preamp(&mut self, delta: f32) {
+        let preamp_db = (self.config.playback.preamp_db + delta).clamp(-PREAMP_LIMIT_DB, PREAMP_LIMIT_DB);
+        self.config.playback.preamp_db = preamp_db;
+        self.player.set_preamp_db(preamp_db);
+
+        let clips = preamp_db > PREAMP_CLIP_WARNING_DB && !self.config.playback.limiter;
+        let key = if clips { "preamp.may_clip" } else { "preamp.changed" };
+        self.flash(self.strings.text_with(key, &[("db", &format!("{:+.1}", preamp_db))]));
+    }
+
+    /// Marks the screen to be drawn again at the end of this update.
+    fn mark(&mut self) {
+        self.redraw.mark();
+    }
+
+    /// Shows a transient message in the status line.
+    fn flash(&mut self, message: String) {
+        self.status_message = Some((message, std::time::Instant::now()));
+        self.mark();
+    }
+
+    /// Flashes an error message, ringing the bell.
+    fn flash_error(&mut self, message: String) {
+        self.flash(message);
+        self.ring_bell();
+    }
+
+    /// Adds a message to the log without drawing attention to it.
+    pub fn log(&mut self, message: String) {
+        self.log.push(message);
+    }
+
+    /// Stops appending listens to the play history and the listen log for
+    /// the rest of the session.
+    pub fn pause_history(&mut self) {
+        self.history_paused = true;
+    }
+
+    /// Shows a warning until the log is opened, and keeps it in the log.
+    pub fn warn(&mut self, message: String) {
+        self.notice = Some(self.strings.text_with("notice.see_log", &[("message", &message)]));
+        self.log.push(message);
+        self.ring_bell();
+    }
+
+    /// Rings the bell set by `ui.bell`, unless it rang within the last
+    /// [`BELL_INTERVAL`]. The audible bell goes out between frames, so it
+    /// does not disturb them; the visual one shows for [`BELL_FLASH`].
+    fn ring_bell(&mut self) {
+        let now = std::time::Instant::now();
+        if self.config.ui.bell == Bell::Off || self.last_bell.is_some_and(|last| now - last < BELL_INTERVAL) {
+            return;
+        }
+        self.last_bell = Some(now);
+        match self.config.ui.bell {
+            Bell::Audible => {
+                use std::io::Write;
+                // Both frontends write to stdout
+                let _ = io::stdout().write_all(b"\x07").and_then(|_| io::stdout().flush());
+            }
+            Bell::Visual => {
+                self.bell_flash_until = Some(now + BELL_FLASH);
+                self.mark();
+            }
+            Bell::Off => {}
+        }
+    }
+
+    /// Quits the application.
+    pub fn quit(&mut self) {
+        self.player.stop();
+        self.running = false;
+    }
+
+    /// Quits, first asking what to do with the playlist's edits when it has
+    /// edits worth saving.
+    pub fn request_quit(&mut self) {
+        if !self.playlist.is_modified() {
+            self.quit();
+            return;
+        }
+        let items = QuitChoice::ALL.iter().map(|choice| self.strings.text(choice.text_key()).to_string()).collect();
+        self.quit_menu = ui::Menu::new(
+            self.strings.text("quit.title").to_string(),
+            items,
+            None,
+            self.strings.text("quit.footer").to_string(),
+        );
+        self.quit_from = self.ui_mode;
+        self.ui_mode = UIMode::ConfirmQuit;
+        self.mark();
+    }
+
+    /// Moves the quit menu selection up.
+    pub fn quit_menu_up(&mut self) {
+        self.quit_menu.up();
+        self.mark();
+    }
+
+    /// Moves the quit menu selection down.
+    pub fn quit_menu_down(&mut self) {
+        self.quit_menu.down();
+        self.mark();
+    }
+
+    /// Closes the quit menu with a choice, or the selected one. Saving
+    /// quits only once the playlist is saved.
+    pub fn close_quit_menu(&mut self, choice: Option<QuitChoice>) {
+        let choice = choice.or_else(|| QuitChoice::ALL.get(self.quit_menu.selected).copied());
+        self.ui_mode = self.quit_from;
+        match choice {
+            Some(QuitChoice::Save) => self.start_saving_playlist(true),
+            Some(QuitChoice::Discard) => {
+                self.edits_discarded = true;
+                self.quit();
+            }
+            Some(QuitChoice::Cancel) | None => self.mark(),
+        }
+    }
+
+    /// Whether the playlist has edits worth saving that were neither saved
+    /// nor discarded, as when quitting on a signal.
+    pub fn edits_lost(&self) -> bool {
+        self.playlist.is_modified() && !self.edits_discarded
+    }
+
+    /// Stops playback before shutdown.
+    pub fn stop_playback(&mut self) {
+        self.record_listen(false);
+        self.player.stop();
+        let _ = self.library.save();
+        let _ = self.tag_cache.save();
+    }
+
+    /// Counts the current track as played or skipped before leaving it.
+    ///
+    /// A track counts as played once half of it has been heard; leaving it
+    /// earlier with "next" counts as a skip. Both are added to the play history.
+    fn record_listen(&mut self, next_pressed: bool) {
+        if !self.player.has_track() {
+            return;
+        }
+        let Some(track) = self.playlist.current_track() else {
+            return;
+        };
+        let (path, title, artist, album, track_number) =
+            (track.path.clone(), track.display_name(), track.artist.clone(), track.album.clone(), track.track_number);
+        let duration = self.player.duration();
+        let finished = self.player.is_finished();
+        let listened = if finished { duration } else { self.player.current_position().min(duration) };
+        let now = crate::library::unix_now();
+        self.library.record_listened(listened, now);
+
+        // The listen log counts a listen by the rules of scrobbling, which
+        // also take four minutes of a long track
+        let rating = if listenlog::counts(duration, listened) {
+            Some(listenlog::Rating::Listened)
+        } else {
+            next_pressed.then_some(listenlog::Rating::Skipped)
+        };
+        if let (Some(log), Some(rating)) = (&self.listen_log, rating)
+            && !self.history_paused
+        {
+            let listen = listenlog::Listen {
+                artist: artist.as_deref(),
+                album: album.as_deref(),
+                title: &title,
+                track_number,
+                duration,
+                rating,
+                started: now.saturating_sub(listened.as_secs()),
+            };
+            if let Err(e) = listenlog::append(log, &listen) {
+                self.log.push(self.strings.text_with("error.listen_log", &[("path", &log.display()), ("error", &e)]));
+            }
+        }
+
+        let played = finished || (!duration.is_zero() && self.player.current_position() * 2 >= duration);
+        let stats = if played {
+            self.library.record_play(&path)
+        } else if next_pressed {
+            self.library.record_skip(&path)
+        } else {
+            return;
+        };
+        self.playlist.set_shuffle_weight(&path, stats.shuffle_weight());
+
+        if let Some(state_dir) = self.state.dir()
+            && !self.history_paused
+        {
+            let entry = history::Entry {
+                time: now,
+                path,
+                title,
+                artist,
+                album,
+                listened_secs: listened.as_secs(),
+                completed: played,
+            };
+            let _ = history::append(state_dir, &entry);
+        }
+    }
+
+    /// Returns whether the app is running.
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Returns the current UI mode.
+    pub fn ui_mode(&self) -> UIMode {
+        self.ui_mode
     }
 
     /// Sets the UI mode.
     pub fn set_ui_mode(&mut self, mode: UIMode) {
         self.ui_mode = mode;
+        self.key_prefix = None;
+        if mode != UIMode::TrackList {
+            self.jump_index = None;
+        }
         if mode == UIMode::TrackList {
             // Initialize selection and update filtered indices
             self.update_filtered_indices();
             // Find the current track in filtered list
-            let current_idx = self.playlist.current_index();
+            let current_idx = self.playlist.current_track_index().unwrap_or(usize::MAX);
             self.track_list_selected = self.filtered_indices
                 .iter()
                 .position(|&idx| idx == current_idx)
                 .unwrap_or(0);
 
-            // Reset scroll to show selected track
-            self.track_list_scroll = 0;
+            // Center the selected track if possible
+            self.scroll_track_list(true);
+        } else {
+            self.search_query.clear();
+        }
+        if mode == UIMode::Stats {
+            // Counts change with every track, so summarize afresh each time
+            self.stats = stats::summarize(&self.library, self.playlist.tracks());
+        }
+        if mode == UIMode::Log {
+            self.notice = None;
+            self.log_scroll = 0;
+        }
+        self.mark();
+    }
+
+    /// Runs an action from a key or the command palette. Track list actions
+    /// work on the selected track, and do nothing elsewhere.
+    pub fn run_action(&mut self, action: Action) -> Result<(), PlayerError> {
+        match action {
+            Action::PlayPause => self.toggle_play_pause(),
+            Action::Next => self.next_track()?,
+            Action::Previous => self.previous_track()?,
+            Action::SeekForward => self.seek_forward()?,
+            Action::SeekBackward => self.seek_backward()?,
+            Action::Replay => self.replay(),
+            Action::PreampUp => self.preamp_up(),
+            Action::PreampDown => self.preamp_down(),
+            Action::VolumeUp => self.volume_up(),
+            Action::VolumeDown => self.volume_down(),
+            Action::Mute => self.toggle_mute(),
+            Action::Shuffle => self.toggle_shuffle(),
+            Action::Repeat => self.cycle_repeat(),
+            Action::Album => self.toggle_album(),
+            Action::StopAfter => self.toggle_stop_after(),
+            Action::TrackList => self.set_ui_mode(UIMode::TrackList),
+            Action::QueuePanel => self.toggle_queue_panel(),
+            Action::FocusNext => self.cycle_focus(true),
+            Action::FocusPrevious => self.cycle_focus(false),
+            Action::Refresh => self.refresh_playlist(),
+            Action::Log => self.set_ui_mode(UIMode::Log),
+            Action::Stats => self.set_ui_mode(UIMode::Stats),
+            Action::Browser => self.open_browser(),
+            Action::Preferences => self.open_preferences(),
+            Action::Palette => self.open_palette(),
+            Action::Help => self.set_ui_mode(UIMode::Help),
+            Action::Quit => self.request_quit(),
+            Action::SortMenu => self.open_sort_menu(),
+            Action::MarksMenu => self.open_marks_menu(),
+            Action::SetMark if self.ui_mode == UIMode::TrackList => self.start_key_prefix(KeyPrefix::SetMark),
+            Action::JumpToMark if self.ui_mode == UIMode::TrackList => self.start_key_prefix(KeyPrefix::JumpToMark),
+            Action::SetMark | Action::JumpToMark => {}
+            Action::JumpMode => self.toggle_jump_mode(),
+            Action::Chip(chip) => self.track_list_toggle_chip(chip),
+            Action::ClearChips => self.track_list_clear_chips(),
+            Action::Queue => self.track_list_toggle_queued(),
+            Action::Preview => self.track_list_preview(),
+            Action::Delete => self.track_list_request_delete(),
+            Action::Rename => self.track_list_rename(),
+            Action::ReadTags => self.read_tags(),
+            Action::WriteTags => self.track_list_write_tags(),
+            Action::PlayAndSave => self.track_list_play_and_save()?,
+            Action::SavePlaylist => self.save_playlist(),
+        }
+        Ok(())
+    }
+
+    /// Asks for a line of text over the current screen, starting from
+    /// `text`.
+    fn open_prompt(&mut self, title: String, text: &str, action: PromptAction) {
+        let history = self.prompt_history.entries(action.history_kind()).to_vec();
+        self.prompt = ui::Prompt::new(title, text, self.strings.text("prompt.footer").to_string())
+            .with_history(history, self.strings.text("prompt.search").to_string());
+        self.prompt_action = Some(action);
+        self.prompt_from = self.ui_mode;
+        self.ui_mode = UIMode::Prompt;
+        self.mark();
+    }
+
+    /// Edits the prompt's text.
+    pub fn prompt_edit(&mut self, edit: impl FnOnce(&mut ui::Prompt)) {
+        edit(&mut self.prompt);
+        self.mark();
+    }
+
+    /// Closes the prompt without doing anything, or first stops searching
+    /// its history.
+    pub fn close_prompt(&mut self) {
+        if self.prompt.cancel_search() {
+            self.mark();
+            return;
+        }
+        self.prompt_action = None;
+        self.ui_mode = self.prompt_from;
+        self.mark();
+    }
+
+    /// Closes the prompt and does what it asked for with the text typed.
+    /// Empty text does nothing.
+    pub fn prompt_submit(&mut self) {
+        let action = self.prompt_action.take();
+        let text = self.prompt.text().trim().to_string();
+        self.ui_mode = self.prompt_from;
+        if let Some(action) = &action {
+            self.prompt_history.add(action.history_kind(), &text);
+            let _ = self.prompt_history.save();
+        }
+        match action {
+            _ if text.is_empty() => self.mark(),
+            Some(PromptAction::Rename { index, path }) => {
+                // A refresh may have moved the track meanwhile
+                let same_track = self.playlist.tracks().get(index).is_some_and(|track| track.path == path);
+                if same_track && self.playlist.rename(index, &text) {
+                    self.update_filtered_indices();
+                    self.flash(self.strings.text_with("tracks.renamed", &[("title", &text)]));
+                } else {
+                    self.mark();
+                }
+            }
+            Some(PromptAction::SavePlaylist { then_quit }) => {
+                self.save_playlist_to(crate::setup::expand_home(&text), then_quit);
+            }
+            Some(PromptAction::SaveResults { indices, title }) => {
+                let tracks: Vec<Track> =
+                    indices.iter().filter_map(|&i| self.playlist.tracks().get(i).cloned()).collect();
+                let path = crate::setup::expand_home(&text);
+                let title = Some(title.as_str()).filter(|title| !title.is_empty());
+                let saved = match path.parent() {
+                    Some(dir) if !dir.as_os_str().is_empty() => std::fs::create_dir_all(dir),
+                    _ => Ok(()),
+                }
+                .and_then(|()| {
+                    let settings = self.settings_to_save(&PlaylistSettings::default());
+                    crate::playlist::save_m3u(&path, &tracks, title, &settings)
+                });
+                match saved {
+                    Ok(()) => self.flash(self.strings.text_with(
+                        "selection.saved",
+                        &[("count", &tracks.len()), ("path", &path.display())],
+                    )),
+                    Err(e) => self.flash_error(self.strings.text_with("playlist.save_failed", &[("error", &e)])),
+                }
+            }
+            None => self.mark(),
+        }
+    }
+
+    /// Writes the selected track's new title, and the tags looked up for
+    /// it, into its file. Writes to the file playing wait until it stops.
+    pub fn track_list_write_tags(&mut self) {
+        let Some(track) = self.filtered_indices.get(self.track_list_selected).and_then(|&i| self.playlist.tracks().get(i))
+        else {
+            return;
+        };
+        let mut edits = Vec::new();
+        if track.renamed
+            && let Some(title) = &track.title
+        {
+            edits.push(TagEdit::Title(title.clone()));
+        }
+        // Only fields the lookup filled in; the file keeps any it has
+        if track.inferred.looked_up {
+            edits.push(TagEdit::FillMissing {
+                title: track.display_name(),
+                artist: track.artist.clone().filter(|_| track.inferred.artist),
+                album: track.album.clone().filter(|_| track.inferred.album),
+            });
+        }
+        if edits.is_empty() {
+            self.flash(self.strings.text("tags.nothing_to_write").to_string());
+            return;
+        }
+        let (path, name) = (track.path.clone(), track.display_name());
+        for edit in edits {
+            self.tag_writes.enqueue(&path, edit);
+        }
+        let playing = self.player.has_track() && self.playlist.current_track().is_some_and(|track| track.path == path);
+        let key = if playing { "tags.write_held" } else { "tags.writing" };
+        self.flash(self.strings.text_with(key, &[("track", &name)]));
+    }
+
+    /// Asks for a new title for the selected track, starting from the name
+    /// shown. Only the playlist changes; W writes it into the file.
+    pub fn track_list_rename(&mut self) {
+        if self.ui_mode != UIMode::TrackList {
+            return;
+        }
+        let Some(track) = self.filtered_indices.get(self.track_list_selected).and_then(|&i| self.playlist.tracks().get(i))
+        else {
+            return;
+        };
+        let (index, path, name) = (self.filtered_indices[self.track_list_selected], track.path.clone(), track.display_name());
+        let title = self.strings.text("prompt.rename").to_string();
+        self.open_prompt(title, &name, PromptAction::Rename { index, path });
+    }
+
+    /// Saves the playlist to the M3U file it was loaded from, or asks where
+    /// to save it, suggesting where it was saved last.
+    pub fn save_playlist(&mut self) {
+        if !matches!(self.ui_mode, UIMode::Normal | UIMode::TrackList) {
+            return;
+        }
+        self.start_saving_playlist(false);
+    }
+
+    fn start_saving_playlist(&mut self, then_quit: bool) {
+        // Only a playlist loaded from just the one file is saved back to it
+        if let [PlaylistSource::M3u(path)] = self.playlist.sources() {
+            self.save_playlist_to(path.clone(), then_quit);
+            return;
+        }
+        let suggestion = self.saved_to.clone().unwrap_or_else(|| {
+            let dir = self.playlist.sources().iter().find_map(|source| match source {
+                PlaylistSource::Directory(dir) => Some(dir.clone()),
+                _ => None,
+            });
+            dir.unwrap_or_else(|| std::env::current_dir().unwrap_or_default()).join("playlist.m3u")
+        });
+        let title = self.strings.text("prompt.save_playlist").to_string();
+        self.open_prompt(title, &suggestion.display().to_string(), PromptAction::SavePlaylist { then_quit });
+    }
+
+    /// Writes the playlist to an M3U file, then quits if `then_quit` is set.
+    fn save_playlist_to(&mut self, path: PathBuf, then_quit: bool) {
+        self.playlist.set_settings(self.settings_to_save(self.playlist.settings()));
+        match self.playlist.save(&path) {
+            Ok(()) => {
+                let message = self.strings.text_with(
+                    "playlist.saved",
+                    &[("count", &self.playlist.len()), ("path", &path.display())],
+                );
+                self.saved_to = Some(path);
+                if then_quit {
+                    self.quit();
+                    return;
+                }
+                self.flash(message);
+            }
+            Err(e) => self.flash_error(self.strings.text_with("playlist.save_failed", &[("error", &e)])),
+        }
+    }
+
+    /// Returns the preferences to write with a saved playlist: the ones in
+    /// use with `playlist.save_settings`, else `kept`.
+    fn settings_to_save(&self, kept: &PlaylistSettings) -> PlaylistSettings {
+        if !self.config.playlist.save_settings {
+            return kept.clone();
+        }
+        PlaylistSettings {
+            shuffle: Some(self.playlist.shuffle_state()),
+            repeat: Some(self.playlist.repeat_mode()),
+            volume: Some(self.config.playback.volume),
+            sort: self.sort,
+        }
+    }
+
+    /// Opens the command palette over the player or the track list, listing
+    /// every action.
+    pub fn open_palette(&mut self) {
+        if !matches!(self.ui_mode, UIMode::Normal | UIMode::TrackList) {
+            return;
+        }
+        self.palette_from = self.ui_mode;
+        self.palette_query.clear();
+        self.update_palette();
+        self.ui_mode = UIMode::Palette;
+        self.mark();
+    }
+
+    /// Lists the actions matching the palette's search, best first, with
+    /// their keys.
+    fn update_palette(&mut self) {
+        let seek_step = self.config.playback.seek_step;
+        let mut matches: Vec<(u32, usize, String)> = ACTIONS
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.action != Action::Palette)
+            .filter_map(|(order, entry)| {
+                let name = self.strings.text_with(entry.text, &[("seconds", &seek_step)]);
+                Some((actions::fuzzy_score(&self.palette_query, &name)?, order, name))
+            })
+            .collect();
+        matches.sort();
+        matches.truncate(PALETTE_ROWS);
+
+        let width = matches.iter().map(|(_, _, name)| name.chars().count()).max().unwrap_or(0);
+        self.palette_actions = matches.iter().map(|&(_, order, _)| ACTIONS[order].action).collect();
+        let mut items: Vec<String> = matches
+            .iter()
+            .map(|(_, order, name)| format!("{:<width$}  {}", name, self.keymap.key_labels(ACTIONS[*order].action), width = width))
+            .collect();
+        if items.is_empty() {
+            items.push(self.strings.text("palette.no_matches").to_string());
+        }
+        let title = self.strings.text_with("palette.title", &[("query", &self.palette_query)]);
+        self.palette_menu = ui::Menu::new(title, items, None, self.strings.text("palette.footer").to_string());
+    }
+
+    /// Adds a character to the palette's search.
+    pub fn palette_input(&mut self, c: char) {
+        self.palette_query.push(c);
+        self.update_palette();
+        self.mark();
+    }
+
+    /// Removes the last character from the palette's search.
+    pub fn palette_backspace(&mut self) {
+        self.palette_query.pop();
+        self.update_palette();
+        self.mark();
+    }
+
+    /// Moves the palette selection up.
+    pub fn palette_up(&mut self) {
+        self.palette_menu.up();
+        self.mark();
+    }
+
+    /// Moves the palette selection down.
+    pub fn palette_down(&mut self) {
+        self.palette_menu.down();
+        self.mark();
+    }
+
+    /// Closes the palette, back to the screen it was opened over.
+    pub fn close_palette(&mut self) {
+        self.ui_mode = self.palette_from;
+        self.mark();
+    }
+
+    /// Closes the palette and runs the selected action. Track list actions
+    /// open the track list first; ones that ask for more, like a mark
+    /// letter or a sort order, go on to ask.
+    pub fn palette_run(&mut self) -> Result<(), PlayerError> {
+        let Some(&action) = self.palette_actions.get(self.palette_menu.selected) else {
+            return Ok(());
+        };
+        self.close_palette();
+        if actions::entry_for(action).scope == Scope::TrackList && self.ui_mode != UIMode::TrackList {
+            self.set_ui_mode(UIMode::TrackList);
+        }
+        self.run_action(action)
+    }
+
+    /// Opens the folder browser where it was last left, or at the
+    /// playlist's source directory the first time.
+    pub fn open_browser(&mut self) {
+        if self.browser.is_none() {
+            let root = self.playlist.sources().iter().find_map(|source| match source {
+                PlaylistSource::Directory(root) => Some(root),
+                _ => None,
+            });
+            let options = self.playlist.load_options();
+            let opened = match root {
+                Some(root) => Browser::open(root, options.scan, options.collation.clone()),
+                None => {
+                    self.flash(self.strings.text("browser.no_directory").to_string());
+                    return;
+                }
+            };
+            match opened {
+                Ok(browser) => self.browser = Some(browser),
+                Err(e) => {
+                    self.flash_error(self.strings.text_with("browser.failed", &[("error", &e)]));
+                    return;
+                }
+            }
+        }
+        self.set_ui_mode(UIMode::Browser);
+        self.scroll_browser();
+    }
+
+    /// Moves the folder browser's selection up.
+    pub fn browser_up(&mut self) {
+        if let Some(browser) = &mut self.browser {
+            browser.up();
+            self.scroll_browser();
+            self.mark();
+        }
+    }
+
+    /// Moves the folder browser's selection down.
+    pub fn browser_down(&mut self) {
+        if let Some(browser) = &mut self.browser {
+            browser.down();
+            self.scroll_browser();
+            self.mark();
+        }
+    }
+
+    /// Opens the selected folder in the browser.
+    pub fn browser_enter(&mut self) {
+        let result = self.browser.as_mut().map(Browser::enter);
+        self.finish_browser_move(result);
+    }
+
+    /// Goes up a folder in the browser.
+    pub fn browser_leave(&mut self) {
+        let result = self.browser.as_mut().map(Browser::leave);
+        self.finish_browser_move(result);
+    }
+
+    /// Redraws the browser after moving between folders, or flashes why it
+    /// could not.
+    fn finish_browser_move(&mut self, result: Option<io::Result<bool>>) {
+        match result {
+            Some(Ok(true)) => {
+                self.scroll_browser();
+                self.mark();
+            }
+            Some(Err(e)) => self.flash_error(self.strings.text_with("browser.failed", &[("error", &e)])),
+            _ => {}
+        }
+    }
+
+    /// Plays the selected folder, or the one shown if it has no
+    /// subfolders, in order like an album. The browser stays where it is.
+    pub fn browser_play(&mut self) -> Result<(), PlayerError> {
+        let Some(browser) = &self.browser else {
+            return Ok(());
+        };
+        let dir = browser.selected_path().unwrap_or_else(|| browser.dir().to_path_buf());
+        if !self.playlist.tracks().iter().any(|track| track.path.starts_with(&dir)) {
+            let name = dir.file_name().unwrap_or(dir.as_os_str()).to_string_lossy().into_owned();
+            self.flash(self.strings.text_with("browser.no_tracks", &[("folder", &name)]));
+            return Ok(());
+        }
+
+        self.record_listen(false);
+        self.playlist.play_directory(&dir);
+        self.load_current_track()?;
+        self.set_ui_mode(UIMode::Normal);
+        let album = self.playlist.album_name().unwrap_or_default().to_string();
+        self.flash(self.strings.text_with("album.mode", &[("album", &album)]));
+        Ok(())
+    }
+
+    /// Opens the preferences screen.
+    pub fn open_preferences(&mut self) {
+        self.preferences_menu = ui::Menu::new(
+            self.strings.text("prefs.title").to_string(),
+            self.preferences_items(),
+            None,
+            self.strings.text("prefs.footer").to_string(),
+        );
+        self.preferences_changed.clear();
+        self.set_ui_mode(UIMode::Preferences);
+    }
+
+    /// Returns the preferences screen's rows: each label and its value.
+    fn preferences_items(&self) -> Vec<String> {
+        let labels: Vec<&str> = PREFERENCES.iter().map(|p| self.strings.text(p.label)).collect();
+        let width = labels.iter().map(|label| label.chars().count()).max().unwrap_or(0);
+        let (on, off) = (self.strings.text("prefs.on"), self.strings.text("prefs.off"));
+        PREFERENCES
+            .iter()
+            .zip(labels)
+            .map(|(preference, label)| {
+                format!("{:<width$}  {}", label, preference.display(&self.config, on, off), width = width)
+            })
+            .collect()
+    }
+
+    /// Moves the preferences selection up.
+    pub fn preferences_up(&mut self) {
+        self.preferences_menu.up();
+        self.mark();
+    }
+
+    /// Moves the preferences selection down.
+    pub fn preferences_down(&mut self) {
+        self.preferences_menu.down();
+        self.mark();
+    }
+
+    /// Steps the selected preference up or down and applies it at once.
+    pub fn preferences_step(&mut self, up: bool) {
+        let index = self.preferences_menu.selected;
+        let Some(preference) = PREFERENCES.get(index) else {
+            return;
+        };
+        preference.step(&mut self.config, up);
+        if !self.preferences_changed.contains(&index) {
+            self.preferences_changed.push(index);
+        }
+        self.apply_preferences();
+        self.preferences_menu.items = self.preferences_items();
+        self.mark();
+    }
+
+    /// Brings the player, theme, and notifications in line with the config.
+    /// Everything else on the preferences screen is read as it is used.
+    fn apply_preferences(&mut self) {
+        self.player.set_volume(f32::from(self.config.playback.volume) / 100.0);
+        self.player.set_limiter(
+            self.config.playback.limiter,
+            Duration::from_millis(self.config.playback.limiter_release_ms.into()),
+        );
+        self.player.set_track_change_fade(Duration::from_millis(self.config.playback.track_change_fade_ms.into()));
+        // Give the memory back at once, and decide again if it is turned back on
+        if !self.config.playback.preload {
+            self.player.release_prepared();
+            self.preloaded = None;
+            self.prepared_next = None;
+        }
+        if let Ok(theme) = crate::themes::Theme::from_config(&self.config) {
+            self.theme = theme;
+        }
+        self.notifier = Notifier::new(&self.config.notifications, &self.strings);
+    }
 
-            // Center the selected track if possible
-            if let Ok(size) = self.terminal.size() {
-                let visible_height = size.height.saturating_sub(7) as usize;
-                if visible_height > 0 && self.track_list_selected > visible_height / 2 {
-                    self.track_list_scroll = self.track_list_selected.saturating_sub(visible_height / 2);
-                }
-            }
-        } else {
-            self.search_query.clear();
+    /// Closes the preferences screen, saving what was changed to the config
+    /// file.
+    pub fn close_preferences(&mut self) {
+        self.set_ui_mode(UIMode::Normal);
+        if self.preferences_changed.is_empty() {
+            return;
+        }
+        let changed: Vec<&preferences::Preference> =
+            self.preferences_changed.drain(..).map(|index| &PREFERENCES[index]).collect();
+        let message = match self.state.config_path() {
+            Some(path) => match preferences::save(path, &self.config, &changed) {
+                Ok(()) => self.strings.text_with("prefs.saved", &[("path", &path.display())]),
+                Err(e) => self.strings.text_with("prefs.save_failed", &[("error", &e)]),
+            },
+            None => self.strings.text("prefs.not_saved").to_string(),
+        };
+        self.flash(message);
+    }
+
+    /// Scrolls the log view up.
+    pub fn log_up(&mut self) {
+        if self.log_scroll > 0 {
+            self.log_scroll -= 1;
+            self.mark();
+        }
+    }
+
+    /// Scrolls the log view down.
+    pub fn log_down(&mut self) {
+        if self.log_scroll + 1 < self.log.len() {
+            self.log_scroll += 1;
+            self.mark();
         }
-        self.display_status();
     }
 
-    /// Updates the filtered track indices based on search query.
+    /// Updates the filtered track indices based on search query and the
+    /// filter chips.
+    ///
+    /// When the query only grew since the last update and the playlist is
+    /// unchanged, the previous matches are narrowed instead of rescanning.
     fn update_filtered_indices(&mut self) {
         let search_lower = self.search_query.to_lowercase();
-        self.filtered_indices.clear();
+        let revision = self.playlist.revision();
+        // Queues, marks and failures change without the playlist changing,
+        // so chips always rescan, as does the first search after turning
+        // the last one off
+        let narrowing = self.chips.is_empty()
+            && matches!(
+                &self.filtered_for,
+                Some((previous, rev, chips))
+                    if *rev == revision && chips.is_empty() && search_lower.starts_with(previous.as_str())
+            );
 
-        for (idx, track) in self.playlist.tracks().iter().enumerate() {
-            if search_lower.is_empty() {
-                // No filter - include all tracks
-                self.filtered_indices.push(idx);
-            } else {
-                // Check if track matches search
-                let display_name = track.display_name().to_lowercase();
-                let artist_name = track.artist.as_deref().unwrap_or("").to_lowercase();
-                let album_name = track.album.as_deref().unwrap_or("").to_lowercase();
-
-                if display_name.contains(&search_lower)
-                    || artist_name.contains(&search_lower)
-                    || album_name.contains(&search_lower)
-                {
-                    self.filtered_indices.push(idx);
-                }
-            }
+        let marks = self.marks.by_path();
+        let filter = TrackFilter::new(&self.playlist, &search_lower, self.chips, &marks);
+        if narrowing {
+            self.filtered_indices.retain(|&idx| filter.passes(idx));
+        } else {
+            self.filtered_indices.clear();
+            self.filtered_indices.extend(filter.indices());
+        }
+        for (count, chip) in self.chip_counts.iter_mut().zip(Chip::ALL) {
+            *count = if self.chips.contains(chip) { filter.count(chip) } else { 0 };
         }
+        self.filtered_for = Some((search_lower, revision, self.chips));
 
         // Reset selection to first filtered track if current selection is out of bounds
         if self.track_list_selected >= self.filtered_indices.len() {
             self.track_list_selected = 0;
-            self.track_list_scroll = 0;
+            self.track_list_view.offset = 0;
+        }
+        if self.jump_index.is_some() {
+            self.build_jump_index();
+        }
+    }
+
+    /// Files the track list rows under their initials for the current sort.
+    fn build_jump_index(&mut self) {
+        let collation = Collation::new(&self.config.sort.articles);
+        let sort = self.sort.map(|(key, _)| key);
+        self.jump_index = Some(JumpIndex::build(self.playlist.tracks(), &self.filtered_indices, sort, &collation));
+    }
+
+    /// Turns alphabet jumping in the track list on or off. While it is on,
+    /// letters jump instead of searching.
+    pub fn toggle_jump_mode(&mut self) {
+        if self.ui_mode != UIMode::TrackList {
+            return;
+        }
+        if self.jump_index.take().is_none() {
+            self.build_jump_index();
+        }
+        self.mark();
+    }
+
+    /// Returns the keys of every action.
+    pub fn keymap(&self) -> &Keymap {
+        &self.keymap
+    }
+
+    /// Returns whether letters jump in the track list.
+    pub fn jump_mode(&self) -> bool {
+        self.jump_index.is_some()
+    }
+
+    /// Selects the next track list row filed under a letter, or `#` for
+    /// anything but A to Z.
+    pub fn jump_to_letter(&mut self, letter: char) {
+        let Some(index) = &self.jump_index else {
+            return;
+        };
+        let letter = if letter.is_ascii_alphabetic() { letter } else { '#' };
+        match index.jump(letter, self.track_list_selected) {
+            Some(row) => {
+                self.track_list_selected = row;
+                self.scroll_track_list(true);
+                self.mark();
+            }
+            None => {
+                let message = self.strings.text_with("jump.none", &[("letter", &letter.to_ascii_uppercase())]);
+                self.flash(message);
+            }
         }
     }
 
@@ -271,7 +2617,7 @@ impl App {
         if self.ui_mode == UIMode::TrackList {
             self.search_query.push(c);
             self.update_filtered_indices();
-            self.display_status();
+            self.mark();
         }
     }
 
@@ -280,7 +2626,7 @@ impl App {
         if self.ui_mode == UIMode::TrackList {
             self.search_query.pop();
             self.update_filtered_indices();
-            self.display_status();
+            self.mark();
         }
     }
 
@@ -288,13 +2634,8 @@ impl App {
     pub fn track_list_up(&mut self) {
         if self.ui_mode == UIMode::TrackList && self.track_list_selected > 0 {
             self.track_list_selected -= 1;
-
-            // Adjust scroll if selection moved above visible area
-            if self.track_list_selected < self.track_list_scroll {
-                self.track_list_scroll = self.track_list_selected;
-            }
-
-            self.display_status();
+            self.scroll_track_list(false);
+            self.mark();
         }
     }
 
@@ -304,20 +2645,8 @@ impl App {
             let max_index = self.filtered_indices.len().saturating_sub(1);
             if self.track_list_selected < max_index {
                 self.track_list_selected += 1;
-
-                // Get terminal size to calculate visible area
-                if let Ok(size) = self.terminal.size() {
-                    // Track list layout: 3 lines header + content with borders (2 lines) + 2 lines footer
-                    // Visible content area = total - 3 (header) - 2 (footer) - 2 (borders) = total - 7
-                    let visible_height = size.height.saturating_sub(7) as usize;
-
-                    // Adjust scroll if selection moved below visible area
-                    if visible_height > 0 && self.track_list_selected >= self.track_list_scroll + visible_height {
-                        self.track_list_scroll = self.track_list_selected.saturating_sub(visible_height - 1);
-                    }
-                }
-
-                self.display_status();
+                self.scroll_track_list(false);
+                self.mark();
             }
         }
     }
@@ -327,66 +2656,979 @@ impl App {
         if self.ui_mode == UIMode::TrackList && self.track_list_selected < self.filtered_indices.len() {
             // Map filtered index to actual playlist index
             let actual_index = self.filtered_indices[self.track_list_selected];
+            self.record_listen(false);
             if self.playlist.goto(actual_index) {
-                self.load_current_track()?;
+                self.switch_to_current_track()?;
                 self.set_ui_mode(UIMode::Normal);
             }
         }
         Ok(())
     }
 
-    /// Loads the current track from the playlist.
-    fn load_current_track(&mut self) -> Result<(), PlayerError> {
-        if let Some(track) = self.playlist.current_track() {
-            match self.player.load_track(&track.path) {
-                Ok(_) => {
-                    self.player.play();
-                    self.display_status();
-                }
-                Err(PlayerError::DecodeError(msg)) => {
-                    // Log error to stderr (silent skip in UI)
-                    eprintln!(
-                        "Warning: Skipping unplayable track: {} ({})",
-                        track.display_name(),
-                        msg
-                    );
-                    // Skip to next track
-                    if self.playlist.next() {
-                        return self.load_current_track(); // Recursive retry
-                    } else {
-                        return Err(PlayerError::DecodeError(
-                            "No playable tracks remaining".to_string(),
-                        ));
-                    }
-                }
-                Err(e) => return Err(e), // Propagate other errors
-            }
+    /// Plays only the tracks in the track list, in list order, starting at
+    /// the selected one.
+    pub fn track_list_play_filtered(&mut self) -> Result<(), PlayerError> {
+        if self.ui_mode != UIMode::TrackList || self.track_list_selected >= self.filtered_indices.len() {
+            return Ok(());
+        }
+        self.record_listen(false);
+        let stop_at_end = !self.config.playlist.continue_after_selection;
+        if self.playlist.play_selection(self.filtered_indices.clone(), self.track_list_selected, stop_at_end) {
+            self.load_current_track()?;
+            self.set_ui_mode(UIMode::Normal);
+            let count = self.filtered_indices.len();
+            self.flash(self.strings.text_with("selection.mode", &[("count", &count)]));
+        }
+        Ok(())
+    }
+
+    /// Plays the tracks in the track list from the top, like
+    /// [`App::track_list_play_filtered`], then offers to save them as an M3U
+    /// named after the search in `paths.playlists_dir`.
+    pub fn track_list_play_and_save(&mut self) -> Result<(), PlayerError> {
+        if self.ui_mode != UIMode::TrackList || self.filtered_indices.is_empty() {
+            return Ok(());
+        }
+        let indices = self.filtered_indices.clone();
+        let query = self.search_query.trim().to_string();
+        self.track_list_selected = 0;
+        self.track_list_play_filtered()?;
+        if self.ui_mode == UIMode::TrackList || !self.config.playlist.save_search_results {
+            return Ok(());
+        }
+
+        let dir = match &self.config.paths.playlists_dir {
+            Some(dir) => crate::setup::expand_home(&dir.to_string_lossy()),
+            None => dirs::home_dir().unwrap_or_default().join("playlists"),
+        };
+        let name = match crate::pattern::slug(&query) {
+            slug if slug.is_empty() => "search-results".to_string(),
+            slug => slug,
+        };
+        let title = self.strings.text("prompt.save_results").to_string();
+        let path = dir.join(format!("{}.m3u", name));
+        self.open_prompt(title, &path.display().to_string(), PromptAction::SaveResults { indices, title: query });
+        Ok(())
+    }
+
+    /// Asks for confirmation before deleting the selected track's file.
+    pub fn track_list_request_delete(&mut self) {
+        if self.ui_mode == UIMode::TrackList && self.track_list_selected < self.filtered_indices.len() {
+            self.pending_delete = Some(self.filtered_indices[self.track_list_selected]);
+            self.ui_mode = UIMode::ConfirmDelete;
+            self.mark();
+        }
+    }
+
+    /// Returns the track list search query.
+    pub fn search_query(&self) -> &str {
+        &self.search_query
+    }
+
+    /// Opens the sort menu over the track list, with the current sort marked.
+    pub fn open_sort_menu(&mut self) {
+        if self.ui_mode != UIMode::TrackList {
+            return;
+        }
+        let items = SortKey::ALL.iter().map(|key| self.strings.text(key.text_key()).to_string()).collect();
+        let marked = self.sort.and_then(|(key, _)| SortKey::ALL.iter().position(|k| *k == key));
+        self.sort_menu_descending = self.sort.is_some_and(|(_, descending)| descending);
+        self.sort_menu = ui::Menu::new(String::new(), items, marked, self.strings.text("sort.footer").to_string());
+        self.update_sort_menu_title();
+        self.ui_mode = UIMode::SortMenu;
+        self.mark();
+    }
+
+    /// Shows the chosen direction in the sort menu's title.
+    fn update_sort_menu_title(&mut self) {
+        let direction = if self.sort_menu_descending { "sort.descending" } else { "sort.ascending" };
+        self.sort_menu.title = self
+            .strings
+            .text_with("sort.menu_title", &[("direction", &self.strings.text(direction))]);
+    }
+
+    /// Moves the sort menu selection up.
+    pub fn sort_menu_up(&mut self) {
+        self.sort_menu.up();
+        self.mark();
+    }
+
+    /// Moves the sort menu selection down.
+    pub fn sort_menu_down(&mut self) {
+        self.sort_menu.down();
+        self.mark();
+    }
+
+    /// Toggles reverse order in the sort menu.
+    pub fn sort_menu_reverse(&mut self) {
+        self.sort_menu_descending = !self.sort_menu_descending;
+        self.update_sort_menu_title();
+        self.mark();
+    }
+
+    /// Closes the sort menu without sorting.
+    pub fn cancel_sort_menu(&mut self) {
+        self.ui_mode = UIMode::TrackList;
+        self.mark();
+    }
+
+    /// Sorts the playlist by the key selected in the sort menu.
+    ///
+    /// The playing track keeps playing, and the search is run again on the
+    /// new order.
+    pub fn apply_sort_menu(&mut self) {
+        let Some(&key) = SortKey::ALL.get(self.sort_menu.selected) else {
+            return;
+        };
+        let descending = self.sort_menu_descending && key != SortKey::Random;
+        self.playlist.sort(key, descending, &Collation::new(&self.config.sort.articles));
+        self.sort = Some((key, descending));
+
+        let message = if key == SortKey::Random {
+            self.strings.text("sort.shuffled").to_string()
+        } else {
+            self.strings.text_with(
+                "sort.sorted",
+                &[
+                    ("key", &self.strings.text(key.text_key())),
+                    ("direction", &self.strings.text(sort_direction(key, descending))),
+                ],
+            )
+        };
+        self.set_ui_mode(UIMode::TrackList);
+        self.flash(message);
+    }
+
+    /// Turns a filter chip of the track list on or off.
+    pub fn track_list_toggle_chip(&mut self, chip: Chip) {
+        if self.ui_mode != UIMode::TrackList {
+            return;
+        }
+        let on = self.chips.toggle(chip);
+        self.update_filtered_indices();
+        if on && self.filtered_indices.is_empty() {
+            let name = self.strings.text(chip.text_key()).to_string();
+            self.flash(self.strings.text_with("chips.none", &[("chip", &name)]));
+        } else {
+            self.mark();
+        }
+    }
+
+    /// Turns every filter chip of the track list off.
+    pub fn track_list_clear_chips(&mut self) {
+        if self.ui_mode != UIMode::TrackList || self.chips.is_empty() {
+            return;
+        }
+        self.chips.clear();
+        self.update_filtered_indices();
+        self.mark();
+    }
+
+    /// Refilters the track list after something a chip looks at changed.
+    fn refilter_chips(&mut self) {
+        if !self.chips.is_empty() {
+            self.update_filtered_indices();
+        }
+    }
+
+    /// Queues the selected track to play next, or takes it off the queue.
+    pub fn track_list_toggle_queued(&mut self) {
+        if self.ui_mode != UIMode::TrackList {
+            return;
+        }
+        let Some(&index) = self.filtered_indices.get(self.track_list_selected) else {
+            return;
+        };
+        let Some(name) = self.playlist.tracks().get(index).map(|t| t.display_name()) else {
+            return;
+        };
+        let key = if self.playlist.toggle_queued(index) { "queue.added" } else { "queue.removed" };
+        let message = self.strings.text_with(key, &[("track", &name)]);
+        self.refilter_chips();
+        self.flash(message);
+    }
+
+    /// Waits for the second key of a two-key command.
+    pub fn start_key_prefix(&mut self, prefix: KeyPrefix) {
+        self.key_prefix = Some(prefix);
+    }
+
+    /// Returns the first key of a two-key command, if one is waiting, and
+    /// stops waiting.
+    pub fn take_key_prefix(&mut self) -> Option<KeyPrefix> {
+        self.key_prefix.take()
+    }
+
+    /// Finishes a two-key command with its second key.
+    pub fn finish_key_prefix(&mut self, prefix: KeyPrefix, key: char) {
+        if !marks::is_mark_letter(key) {
+            let message = self.strings.text_with("marks.invalid", &[("key", &key)]);
+            self.flash(message);
+            return;
+        }
+        match prefix {
+            KeyPrefix::SetMark => self.set_mark(key),
+            KeyPrefix::JumpToMark => self.jump_to_mark(key),
+        }
+    }
+
+    /// Marks the selected track with a letter.
+    fn set_mark(&mut self, letter: char) {
+        let Some(track) = self.filtered_indices.get(self.track_list_selected).and_then(|&i| self.playlist.tracks().get(i))
+        else {
+            return;
+        };
+        let name = track.display_name();
+        self.marks.set(letter, &track.path);
+        let _ = self.marks.save();
+        let message = self.strings.text_with("marks.set", &[("track", &name), ("letter", &letter)]);
+        self.refilter_chips();
+        self.flash(message);
+    }
+
+    /// Selects the track marked with a letter in the track list, clearing
+    /// the search and chips if they hide the track.
+    fn jump_to_mark(&mut self, letter: char) {
+        let Some(path) = self.marks.get(letter) else {
+            let message = self.strings.text_with("marks.unset", &[("letter", &letter)]);
+            self.flash(message);
+            return;
+        };
+        let Some(index) = self.playlist.tracks().iter().position(|track| track.path == path) else {
+            let message = self.strings.text_with("marks.missing", &[("letter", &letter)]);
+            self.flash(message);
+            return;
+        };
+        if !self.filtered_indices.contains(&index) {
+            self.search_query.clear();
+            self.chips.clear();
+            self.update_filtered_indices();
+        }
+        if let Some(position) = self.filtered_indices.iter().position(|&i| i == index) {
+            self.track_list_selected = position;
+            self.scroll_track_list(true);
+        }
+        self.mark();
+    }
+
+    /// Lists the playlist's marks over the track list.
+    pub fn open_marks_menu(&mut self) {
+        if self.ui_mode != UIMode::TrackList {
+            return;
+        }
+        let tracks = self.playlist.tracks();
+        let items: Vec<String> = self
+            .marks
+            .list()
+            .into_iter()
+            .map(|(letter, path)| {
+                let name = match tracks.iter().find(|track| track.path == path) {
+                    Some(track) => track.display_name(),
+                    None => self.strings.text_with("marks.gone", &[("path", &path.display())]),
+                };
+                format!("{}  {}", letter, name)
+            })
+            .collect();
+        if items.is_empty() {
+            let message = self.strings.text("marks.none").to_string();
+            self.flash(message);
+            return;
+        }
+        let title = self.strings.text("marks.title").to_string();
+        self.marks_menu = ui::Menu::new(title, items, None, self.strings.text("marks.footer").to_string());
+        self.ui_mode = UIMode::MarksMenu;
+        self.mark();
+    }
+
+    /// Moves the marks list selection up.
+    pub fn marks_menu_up(&mut self) {
+        self.marks_menu.up();
+        self.mark();
+    }
+
+    /// Moves the marks list selection down.
+    pub fn marks_menu_down(&mut self) {
+        self.marks_menu.down();
+        self.mark();
+    }
+
+    /// Closes the marks list and jumps to a mark, or to the selected one.
+    pub fn close_marks_menu(&mut self, letter: Option<char>) {
+        let letter = letter.or_else(|| self.marks.list().get(self.marks_menu.selected).map(|(letter, _)| *letter));
+        self.ui_mode = UIMode::TrackList;
+        match letter {
+            Some(letter) => self.jump_to_mark(letter),
+            None => self.mark(),
+        }
+    }
+
+    /// Closes the marks list without jumping.
+    pub fn cancel_marks_menu(&mut self) {
+        self.ui_mode = UIMode::TrackList;
+        self.mark();
+    }
+
+    /// Plays a few seconds from the middle of the selected track, pausing the
+    /// current one until the preview ends or a key is pressed.
+    pub fn track_list_preview(&mut self) {
+        if self.ui_mode != UIMode::TrackList {
+            return;
+        }
+        let Some(track) = self.filtered_indices.get(self.track_list_selected).and_then(|&i| self.playlist.tracks().get(i))
+        else {
+            return;
+        };
+        let (path, bounds, name) = (track.path.clone(), (track.start_offset.unwrap_or_default(), track.end_offset), track.display_name());
+
+        let was_playing = self.player.state() == crate::player::PlaybackState::Playing;
+        self.player.pause();
+        match self.player.start_preview(&path, bounds, PREVIEW_AT, PREVIEW_LENGTH) {
+            Ok(()) => self.preview = Some((name, was_playing)),
+            Err(e) => {
+                if was_playing {
+                    self.player.play();
+                }
+                self.flash_error(self.strings.text_with("error.playback", &[("error", &e)]));
+            }
+        }
+        self.mark();
+    }
+
+    /// Returns whether a track is being previewed.
+    pub fn previewing(&self) -> bool {
+        self.preview.is_some()
+    }
+
+    /// Stops the preview and resumes the current track where it was.
+    pub fn end_preview(&mut self) {
+        let Some((_, was_playing)) = self.preview.take() else {
+            return;
+        };
+        self.player.stop_preview();
+        if was_playing {
+            self.player.play();
+        }
+        self.mark();
+    }
+
+    /// Shows or hides the panel of upcoming tracks.
+    pub fn toggle_queue_panel(&mut self) {
+        self.queue_panel = !self.queue_panel;
+        self.focus = if self.queue_panel { Focus::Queue } else { Focus::NowPlaying };
+        self.queue_panel_selected = 0;
+        self.queue_panel_view.offset = 0;
+        self.mark();
+    }
+
+    /// Returns whether the queue panel is shown.
+    /// Returns the panes on screen that can have the focus, in the order
+    /// Tab moves through them.
+    fn panes(&self) -> Vec<Focus> {
+        match self.ui_mode {
+            UIMode::Normal if self.queue_panel => vec![Focus::NowPlaying, Focus::Queue],
+            UIMode::Normal => vec![Focus::NowPlaying],
+            UIMode::TrackList => vec![Focus::TrackList],
+            UIMode::Browser => vec![Focus::Browser],
+            UIMode::Prompt => vec![Focus::Prompt],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns the pane with the focus: the one last given it while it is
+    /// on screen, or else the screen's first. Screens such as help and the
+    /// menus have no panes.
+    pub fn focus(&self) -> Option<Focus> {
+        let panes = self.panes();
+        panes.contains(&self.focus).then_some(self.focus).or_else(|| panes.first().copied())
+    }
+
+    /// Moves the focus to the next pane on screen, or the previous one.
+    pub fn cycle_focus(&mut self, forward: bool) {
+        if let Some(current) = self.focus()
+            && let Some(next) = focus::cycle(&self.panes(), current, forward)
+        {
+            self.focus = next;
+            self.mark();
+        }
+    }
+
+    /// Moves the queue panel selection up.
+    pub fn queue_panel_up(&mut self) {
+        if self.queue_panel && self.queue_panel_selected > 0 {
+            self.queue_panel_selected -= 1;
+            self.scroll_queue_panel();
+            self.mark();
+        }
+    }
+
+    /// Moves the queue panel selection down.
+    pub fn queue_panel_down(&mut self) {
+        let len = self.playlist.upcoming(QUEUE_PANEL_LEN).len();
+        if self.queue_panel && self.queue_panel_selected + 1 < len {
+            self.queue_panel_selected += 1;
+            self.scroll_queue_panel();
+            self.mark();
+        }
+    }
+
+    /// Jumps playback to the selected queue panel entry, taking it off the
+    /// queue if it was queued.
+    pub fn queue_panel_play_selected(&mut self) -> Result<(), PlayerError> {
+        if !self.queue_panel {
+            return Ok(());
+        }
+        let upcoming = self.playlist.upcoming(QUEUE_PANEL_LEN);
+        let Some(&index) = upcoming.get(self.queue_panel_selected) else {
+            return Ok(());
+        };
+        self.record_listen(false);
+        if self.playlist.is_queued(index) {
+            self.playlist.unqueue(index);
+        }
+        if self.playlist.goto(index) {
+            self.queue_panel_selected = 0;
+            self.queue_panel_view.offset = 0;
+            self.load_current_track()?;
+        }
+        Ok(())
+    }
+
+    /// Cancels a pending delete.
+    pub fn cancel_delete(&mut self) {
+        self.pending_delete = None;
+        self.ui_mode = UIMode::TrackList;
+        self.mark();
+    }
+
+    /// Deletes (or trashes) the file awaiting confirmation and removes it from the playlist.
+    ///
+    /// Failures are reported in the status line.
+    pub fn confirm_delete(&mut self) {
+        self.ui_mode = UIMode::TrackList;
+        let Some(index) = self.pending_delete.take() else {
+            return;
+        };
+        let Some(track) = self.playlist.tracks().get(index).cloned() else {
+            return;
+        };
+
+        // Release the file handle before touching the file
+        let is_current = self.playlist.current_track_index() == Some(index);
+        let stopped_at = is_current.then(|| {
+            (self.player.current_position(), self.player.state() == crate::player::PlaybackState::Playing)
+        });
+        if is_current {
+            self.player.stop();
+        }
+
+        let permanent = self.config.playlist.delete_permanently;
+        let result = if permanent {
+            std::fs::remove_file(&track.path)
+        } else {
+            crate::trash::move_to_trash(&track.path)
+        };
+
+        let message = match result {
+            Ok(()) => {
+                self.playlist.remove(index);
+                self.update_filtered_indices();
+                self.check_empty_playlist();
+                if is_current
+                    && !self.playlist.is_empty()
+                    && let Err(e) = self.load_current_track()
+                {
+                    self.flash_error(self.strings.text_with("error.playback", &[("error", &e)]));
+                }
+                if permanent {
+                    self.strings.text_with("delete.deleted", &[("track", &track.display_name())])
+                } else {
+                    self.strings.text_with("delete.trashed", &[("track", &track.display_name())])
+                }
+            }
+            Err(e) => {
+                // Pick the track we stopped up where it was
+                if let Some((position, playing)) = stopped_at
+                    && let Err(e) = self.reopen_current_track_at(position, playing)
+                {
+                    self.flash_error(self.strings.text_with("error.playback", &[("error", &e)]));
+                }
+                self.strings.text_with("delete.failed", &[("track", &track.display_name()), ("error", &e)])
+            }
+        };
+        self.flash(message);
+    }
+
+    /// Loads the current track from the playlist.
+    fn load_current_track(&mut self) -> Result<(), PlayerError> {
+        self.open_current_track(true)
+    }
+
+    /// Loads the current track again at a position, playing it if `play`.
+    fn reopen_current_track_at(&mut self, position: Duration, play: bool) -> Result<(), PlayerError> {
+        self.open_current_track(false)?;
+        if self.player.has_track() && !position.is_zero() {
+            self.player.seek_to(position)?;
+        }
+        if play {
+            self.player.play();
+        }
+        Ok(())
+    }
+
+    /// Loads the current track after a change by hand, fading over from the
+    /// one playing if `playback.track_change_fade_ms` is set.
+    fn switch_to_current_track(&mut self) -> Result<(), PlayerError> {
+        self.player.fade_next_load();
+        self.load_current_track()
+    }
+
+    /// Loads the current track and plays it, or leaves it paused at the start.
+    fn open_current_track(&mut self, play: bool) -> Result<(), PlayerError> {
+        // A replay or seek meant for the previous track
+        self.pending_replay = None;
+        self.seek_target = None;
+        if self.playlist.is_empty() {
+            return Ok(());
+        }
+        // A fast scan left the tags for now; the ReplayGain among them matters
+        if let Some(track) = self.playlist.current_track().filter(|track| !track.probed) {
+            let probed = crate::playlist::extract_metadata(&track.path);
+            self.apply_tags(&probed);
+        }
+        let (player, config, loudness, tag_cache) = (&mut self.player, &self.config, &self.loudness, &self.tag_cache);
+        let (strings, log) = (&self.strings, &mut self.log);
+        let opened = self.playlist.open_playable(
+            |track| {
+                player.set_replay_gain(replay_gain(config, loudness, track));
+                player.load_segment(&track.path, track.start_offset.unwrap_or_default(), track.end_offset)?;
+                if let Some(length) = tag_cache.measured(&track.path).filter(|_| !track.is_segment()) {
+                    player.correct_duration(length);
+                }
+                player.set_trim(trim::trim_for(&config.playlist.trim, &track.path))
+            },
+            // A file that is gone, say deleted or on a drive since unmounted,
+            // is skipped like one that does not decode
+            |track, e| match e {
+                PlayerError::DecodeError(msg) | PlayerError::FileError(msg) => {
+                    log.push(strings.text_with("error.unplayable", &[("track", &track.display_name()), ("error", msg)]));
+                    true
+                }
+                PlayerError::InitializationError(_) => false,
+            },
+        )?;
+        self.refilter_chips();
+        if !opened {
+            // Stopped rather than quitting: a refresh or a file coming back
+            // gives something to play again
+            self.player.stop();
+            self.flash_error(self.strings.text("error.no_playable_tracks").to_string());
+        } else if play {
+            self.player.play();
+        } else {
+            // A segment running into the next one is still playing
+            self.player.pause();
+        }
+        self.mark();
+        Ok(())
+    }
+
+    /// Keeps the selected track visible in the track list, or centers it.
+    fn scroll_track_list(&mut self, center: bool) {
+        if let Some(height) = self.terminal_height() {
+            // Track list layout: 3 lines header + content with borders (2 lines) + 2 lines footer
+            self.track_list_view.height = height.saturating_sub(7);
+        }
+        let discs = self.sorted_by_album().then(|| self.playlist.tracks());
+        let rows = TrackRows { filtered_indices: &self.filtered_indices, discs };
+        if center {
+            self.track_list_view.center_on(&rows, self.track_list_selected);
+        } else {
+            self.track_list_view.scroll_to(&rows, self.track_list_selected);
+        }
+    }
+
+    /// Whether the track list is grouped by album, with a "Disc N" heading
+    /// over each disc of a multi-disc album.
+    fn sorted_by_album(&self) -> bool {
+        matches!(self.sort, Some((SortKey::Album, _)))
+    }
+
+    /// Keeps the selected queue panel entry visible.
+    fn scroll_queue_panel(&mut self) {
+        if let Some(height) = self.terminal_height() {
+            // The panel spans the whole height, less its borders
+            self.queue_panel_view.height = height.saturating_sub(2);
+        }
+        let rows = UniformRows(self.playlist.upcoming(QUEUE_PANEL_LEN).len());
+        self.queue_panel_view.scroll_to(&rows, self.queue_panel_selected);
+    }
+
+    /// Keeps the folder browser's selection in view.
+    fn scroll_browser(&mut self) {
+        // The browser fills the screen, less the borders and a 2 line footer
+        let height = self.terminal_height().map(|height| height.saturating_sub(4));
+        if let Some(browser) = &mut self.browser {
+            browser.scroll(height.unwrap_or(0));
+        }
+    }
+
+    /// Returns the terminal height, or `None` with the plain frontend.
+    fn terminal_height(&self) -> Option<u16> {
+        match &self.frontend {
+            Frontend::Terminal(terminal) => terminal.size().ok().map(|size| size.height),
+            Frontend::Plain(_) => None,
+        }
+    }
+
+    /// Returns the flashed status message while it is fresh, else the long
+    /// operations running, else the notice.
+    fn current_message(&self) -> Option<String> {
+        self.status_message
+            .as_ref()
+            .filter(|(_, shown_at)| shown_at.elapsed() < STATUS_MESSAGE_DURATION)
+            .map(|(message, _)| message.clone())
+            .or_else(|| self.task_status())
+            .or_else(|| self.notice.clone())
+    }
+
+    /// Describes what the track list shows: where the tracks came from, how
+    /// they are sorted, and how many match the search.
+    fn track_list_breadcrumb(&self) -> String {
+        let mut parts = vec![match self.playlist.title() {
+            Some(title) => title.to_string(),
+            None => self
+                .playlist
+                .source_label(dirs::home_dir().as_deref())
+                .unwrap_or_else(|| self.strings.text("tracks.no_source").to_string()),
+        }];
+        if let Some((key, descending)) = self.sort {
+            parts.push(if key == SortKey::Random {
+                self.strings.text(key.text_key()).to_string()
+            } else {
+                format!("{} {}", self.strings.text(key.text_key()), self.strings.text(sort_direction(key, descending)))
+            });
+        }
+        if !self.search_query.is_empty() {
+            parts.push(self.strings.text_with(
+                "tracks.filtered",
+                &[
+                    ("query", &self.search_query),
+                    ("matches", &self.filtered_indices.len()),
+                    ("total", &self.playlist.len()),
+                ],
+            ));
+        }
+        parts.join(" | ")
+    }
+
+    /// Describes the current state for the plain frontend.
+    fn plain_snapshot(&self) -> plain::Snapshot {
+        let seek_step = self.config.playback.seek_step;
+        let view = match self.ui_mode {
+            UIMode::Normal => plain::View::Normal,
+            UIMode::Help => plain::View::Help(
+                help_sections(&self.strings)
+                    .into_iter()
+                    .flat_map(|(heading, entries)| {
+                        let entries = entries.into_iter().map(|entry| {
+                            let text = self.strings.text_with(entry.text, &[("seconds", &seek_step)]);
+                            format!("{}: {}", self.keymap.key_labels(entry.action), text)
+                        });
+                        heading.into_iter().chain(entries)
+                    })
+                    .collect(),
+            ),
+            UIMode::Log => plain::View::Log(self.decoded_cache_line().into_iter().chain(self.log.clone()).collect()),
+            UIMode::Stats => plain::View::Stats(stats_text(&self.stats, &self.strings)),
+            UIMode::TrackList => plain::View::TrackList {
+                query: self.search_query.clone(),
+                matches: self.filtered_indices.len(),
+                selected: self
+                    .filtered_indices
+                    .get(self.track_list_selected)
+                    .and_then(|&i| Some((i + 1, self.playlist.tracks().get(i)?.display_name()))),
+            },
+            UIMode::ConfirmDelete => {
+                let (question, action) = if self.config.playlist.delete_permanently {
+                    ("delete.title_permanent", "delete.action_permanent")
+                } else {
+                    ("delete.title_trash", "delete.action_trash")
+                };
+                let path = self
+                    .pending_delete
+                    .and_then(|i| self.playlist.tracks().get(i))
+                    .map(|t| t.path.display().to_string())
+                    .unwrap_or_default();
+                plain::View::ConfirmDelete(self.strings.text_with(
+                    "plain.confirm_delete",
+                    &[
+                        ("question", &self.strings.text(question)),
+                        ("path", &path),
+                        ("action", &self.strings.text(action)),
+                    ],
+                ))
+            }
+            UIMode::SortMenu => plain::View::Menu {
+                title: self.sort_menu.title.clone(),
+                selected: self.sort_menu.items.get(self.sort_menu.selected).cloned().unwrap_or_default(),
+            },
+            UIMode::MarksMenu => plain::View::Menu {
+                title: self.marks_menu.title.clone(),
+                selected: self.marks_menu.items.get(self.marks_menu.selected).cloned().unwrap_or_default(),
+            },
+            UIMode::Preferences => plain::View::Menu {
+                title: self.preferences_menu.title.clone(),
+                selected: self.preferences_menu.items.get(self.preferences_menu.selected).cloned().unwrap_or_default(),
+            },
+            UIMode::Palette => plain::View::Menu {
+                title: self.palette_menu.title.clone(),
+                selected: self.palette_menu.items.get(self.palette_menu.selected).cloned().unwrap_or_default(),
+            },
+            UIMode::Prompt => plain::View::Menu {
+                title: self.prompt.title.clone(),
+                selected: self.prompt.text().to_string(),
+            },
+            UIMode::ConfirmQuit => plain::View::Menu {
+                title: self.quit_menu.title.clone(),
+                selected: self.quit_menu.items.get(self.quit_menu.selected).cloned().unwrap_or_default(),
+            },
+            UIMode::Browser => plain::View::Menu {
+                title: self.browser.as_ref().map(Browser::label).unwrap_or_default(),
+                selected: self
+                    .browser
+                    .as_ref()
+                    .and_then(|browser| browser.entries().get(browser.selected()).cloned())
+                    .unwrap_or_else(|| self.strings.text("browser.empty").to_string()),
+            },
+        };
+
+        plain::Snapshot {
+            track: self.playlist.current_track().map(|track| match &track.artist {
+                Some(artist) => format!("{} – {}", artist, track.display_name()),
+                None => track.display_name(),
+            }),
+            index: self.playlist.current_index(),
+            length: self.playlist.len(),
+            state: self.player.state(),
+            position: self.player.current_position(),
+            volume: (self.player.volume() * 100.0).round() as u8,
+            shuffle: self.playlist.shuffle_state(),
+            repeat: self.playlist.repeat_mode(),
+            message: self.current_message(),
+            view,
+        }
+    }
+
+    /// Hashes everything the next frame would show, so an unchanged frame
+    /// can be skipped without building it.
+    ///
+    /// The position counts in tenths of a second and the visualizer by the
+    /// bars it draws; when only those change, ratatui's diff sends just the
+    /// cells that differ.
+    fn frame_fingerprint(&self) -> u64 {
+        use std::hash::{Hash, Hasher};
+
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        if let Frontend::Terminal(terminal) = &self.frontend
+            && let Ok(size) = terminal.size()
+        {
+            (size.width, size.height).hash(&mut hasher);
         }
-        Ok(())
+        self.ui_mode.hash(&mut hasher);
+        self.playlist.revision().hash(&mut hasher);
+        self.playlist.current_index().hash(&mut hasher);
+        self.playlist.queue().hash(&mut hasher);
+        (self.playlist.unplayable_count(), self.chips, self.chip_counts).hash(&mut hasher);
+        self.playlist.shuffle_state().hash(&mut hasher);
+        self.playlist.repeat_mode().hash(&mut hasher);
+        (self.playlist.album_name(), self.playlist.selection_len()).hash(&mut hasher);
+        self.stop_after.hash(&mut hasher);
+        self.player.limiting().hash(&mut hasher);
+        self.config.playback.volume.hash(&mut hasher);
+        self.next_preloaded().hash(&mut hasher);
+        if self.ui_mode == UIMode::Log {
+            self.decoded_cache_line().hash(&mut hasher);
+        }
+        (self.player.current_position().as_millis() / 100).hash(&mut hasher);
+        self.player.duration().as_secs().hash(&mut hasher);
+        self.player.trim().hash(&mut hasher);
+        self.player.state().hash(&mut hasher);
+        self.seek_failed_at.is_some_and(|at| at.elapsed() < STATUS_MESSAGE_DURATION).hash(&mut hasher);
+        self.seek_target.map(|(target, _)| target.as_secs()).hash(&mut hasher);
+        if self.config.visualizer.enabled {
+            visualizer::render_bars(&self.waveform_history).hash(&mut hasher);
+        }
+        self.current_message().hash(&mut hasher);
+        self.preview.hash(&mut hasher);
+        self.search_query.hash(&mut hasher);
+        self.jump_index.is_some().hash(&mut hasher);
+        self.filtered_for.hash(&mut hasher);
+        self.filtered_indices.len().hash(&mut hasher);
+        (self.track_list_selected, self.track_list_view).hash(&mut hasher);
+        (self.log.len(), self.log_scroll).hash(&mut hasher);
+        self.pending_delete.hash(&mut hasher);
+        self.sort_menu.hash(&mut hasher);
+        self.marks_menu.hash(&mut hasher);
+        self.preferences_menu.hash(&mut hasher);
+        self.palette_menu.hash(&mut hasher);
+        self.prompt.hash(&mut hasher);
+        self.focus().hash(&mut hasher);
+        self.quit_menu.hash(&mut hasher);
+        self.bell_flash_until.is_some_and(|until| std::time::Instant::now() < until).hash(&mut hasher);
+        self.marks.list().hash(&mut hasher);
+        if let Some(browser) = &self.browser {
+            (browser.dir(), browser.selected(), browser.view()).hash(&mut hasher);
+        }
+        (self.queue_panel, self.queue_panel_selected, self.queue_panel_view).hash(&mut hasher);
+        hasher.finish()
     }
 
-    /// Displays the current status using ratatui.
+    /// Displays the current status with the active frontend.
     fn display_status(&mut self) {
+        let drawing = std::time::Instant::now();
+        self.redraw.drawn(drawing);
+        if matches!(self.frontend, Frontend::Plain(_)) {
+            let snapshot = self.plain_snapshot();
+            if let Frontend::Plain(printer) = &mut self.frontend
+                && let Err(e) = printer.print(snapshot, &self.strings)
+            {
+                eprintln!("{}", self.strings.text_with("error.draw", &[("error", &e)]));
+                self.running = false;
+            }
+            self.first_frame_drawn(drawing);
+            return;
+        }
+
+        // Over slow links every frame costs, so only draw what changed, and
+        // in low bandwidth mode no more than twice a second
+        let fingerprint = self.frame_fingerprint();
+        if self.last_frame == Some(fingerprint) {
+            self.redraw_pending = false;
+            return;
+        }
+        if self.low_bandwidth && self.last_frame.is_some() && self.last_draw.elapsed() < LOW_BANDWIDTH_FRAME {
+            self.redraw_pending = true;
+            return;
+        }
+        self.last_frame = Some(fingerprint);
+        self.last_draw = std::time::Instant::now();
+        self.redraw_pending = false;
+
         let ui_mode = self.ui_mode;
-        let search_query = self.search_query.clone();
+        let search_query = self.search_query.as_str();
         let current_index = self.playlist.current_index();
+        let current_track_index = self.playlist.current_track_index().unwrap_or(usize::MAX);
         let playlist_len = self.playlist.len();
         let shuffle_state = self.playlist.shuffle_state();
         let repeat_mode = self.playlist.repeat_mode();
+        // Shuffle and repeat are on hold while an album or selection plays
+        let scope = match (self.playlist.album_name(), self.playlist.selection_len()) {
+            (Some(album), _) => Some(self.strings.text_with("album.mode", &[("album", &truncate_text(album, 30))])),
+            (None, Some(count)) => Some(self.strings.text_with("selection.mode", &[("count", &count)])),
+            (None, None) => None,
+        };
+        let scope = scope.as_deref();
+        let stop_after = self.stop_after;
+        let limiting = self.player.limiting();
+        let preloaded = self.next_preloaded();
+        let volume = (!self.player.is_muted()).then_some(self.config.playback.volume);
         let seek_step = self.config.playback.seek_step;
+        let seek_failed = self.seek_failed_at.is_some_and(|at| at.elapsed() < STATUS_MESSAGE_DURATION);
+        let seek_target = self.seek_target.map(|(target, _)| target);
+        let trim = self.player.trim();
         let track_list_selected = self.track_list_selected;
-        let track_list_scroll = self.track_list_scroll;
+        let track_list_view = self.track_list_view;
+        let track_list_layout = &self.track_list_layout;
+        let disc_headings = self.sorted_by_album();
 
-        let current_track = self.playlist.current_track().cloned();
+        let current_track = self.playlist.current_track();
         let pos = self.player.current_position();
         let dur = self.player.duration();
         let state = self.player.state();
 
-        let tracks: Vec<_> = self.playlist.tracks().to_vec();
-        let waveform_data = self.waveform_history.clone();
-        let filtered_indices = self.filtered_indices.clone();
+        // Borrow rather than clone: with large playlists a per-frame copy dominates the draw
+        let tracks = self.playlist.tracks();
+        let waveform_data: &[f32] = if self.config.visualizer.enabled {
+            &self.waveform_history
+        } else {
+            &[]
+        };
+        let filtered_indices = self.filtered_indices.as_slice();
+        let playlist = &self.playlist;
+        let unplayable = |index| playlist.is_unplayable(index);
+        // The preview stays in the message area for as long as it plays
+        let previewing = self
+            .preview
+            .as_ref()
+            .map(|(name, _)| self.strings.text_with("preview.playing", &[("track", name)]));
+        let status_message = previewing.clone().or_else(|| self.current_message());
+        let log = self.log.as_slice();
+        let log_scroll = self.log_scroll;
+        let cache_line = self.decoded_cache_line();
+        let pending_delete = self
+            .pending_delete
+            .and_then(|i| self.playlist.tracks().get(i))
+            .map(|t| (t.path.display().to_string(), self.config.playlist.delete_permanently));
+        let name_options = NameOptions {
+            show_track_numbers: self.config.ui.show_track_numbers,
+        };
+        let no_color = self.config.ui.no_color;
+        let theme = self.theme;
+        let strings = &self.strings;
+        let keymap = &self.keymap;
+        let sort_menu = &self.sort_menu;
+        let stats = &self.stats;
+        let breadcrumb = if matches!(ui_mode, UIMode::Normal | UIMode::Help | UIMode::Log | UIMode::Stats | UIMode::Browser | UIMode::Preferences) {
+            String::new()
+        } else {
+            self.track_list_breadcrumb()
+        };
+        let marks_menu = &self.marks_menu;
+        let jump_index = self.jump_index.as_ref();
+        let empty_source = self.playlist.source_label(dirs::home_dir().as_deref()).unwrap_or_default();
+        let preferences_menu = &self.preferences_menu;
+        let palette_menu = &self.palette_menu;
+        let prompt = &self.prompt;
+        let quit_menu = &self.quit_menu;
+        let bell = self.bell_flash_until.is_some_and(|until| std::time::Instant::now() < until);
+        // The palette, prompt and quit menu are drawn over the screen they were opened from
+        let view = match ui_mode {
+            UIMode::Palette => self.palette_from,
+            UIMode::Prompt => self.prompt_from,
+            UIMode::ConfirmQuit => self.quit_from,
+            mode => mode,
+        };
+        let browser = self.browser.as_ref();
+        let marks = self.marks.by_path();
+        let chips: Vec<(char, String, Option<usize>)> = Chip::ALL
+            .iter()
+            .zip(self.chip_counts)
+            .map(|(&chip, count)| {
+                (chip.key(), self.strings.text(chip.text_key()).to_string(), self.chips.contains(chip).then_some(count))
+            })
+            .collect();
+        // Only fresh messages, so a standing notice does not hide the key hints
+        let flash = self
+            .status_message
+            .as_ref()
+            .filter(|(_, shown_at)| shown_at.elapsed() < STATUS_MESSAGE_DURATION)
+            .map(|(message, _)| message.as_str());
+        let flash = previewing.as_deref().or(flash);
+        let queue = self.playlist.queue();
+        // Panes beside each other say which has the focus
+        let focused = self.focus();
+        let queue_panel = self.queue_panel.then(|| QueuePanel {
+            entries: upcoming_entries(&self.playlist, dur.saturating_sub(pos), &name_options),
+            selected: self.queue_panel_selected,
+            viewport: self.queue_panel_view,
+            focused: focused == Some(Focus::Queue),
+        });
+        let player_focused = self.queue_panel.then_some(focused == Some(Focus::NowPlaying));
+        let Frontend::Terminal(terminal) = &mut self.frontend else {
+            return;
+        };
 
-        if let Err(e) = self.terminal.draw(move |f| {
+        if let Err(e) = terminal.draw(|f| {
             let size = f.area();
 
             // Check minimum terminal size
@@ -394,35 +3636,179 @@ impl App {
             const MIN_HEIGHT: u16 = 10;
 
             if size.width < MIN_WIDTH || size.height < MIN_HEIGHT {
-                render_size_warning(f, size, MIN_WIDTH, MIN_HEIGHT);
-                return;
+                render_size_warning(f, size, MIN_WIDTH, MIN_HEIGHT, strings, &theme);
+            } else {
+                match view {
+                    UIMode::Normal | UIMode::Preferences | UIMode::Palette | UIMode::Prompt | UIMode::ConfirmQuit => {
+                        let mut player_area = size;
+                        if let Some(panel) = queue_panel {
+                            let chunks = Layout::default()
+                                .direction(Direction::Horizontal)
+                                .constraints([Constraint::Min(0), Constraint::Length((size.width / 2).min(45))])
+                                .split(size);
+                            player_area = chunks[0];
+                            render_queue_panel(f, chunks[1], panel, strings, &theme);
+                        }
+                        if playlist_len == 0 {
+                            render_empty_view(f, player_area, &empty_source, flash, strings, &theme);
+                            if bell {
+                                ui::bell_row(f.buffer_mut(), player_area);
+                            }
+                        } else {
+                            render_normal_view(
+                                f, player_area, current_track, pos, dur, state,
+                                current_index, playlist_len, shuffle_state, repeat_mode, scope, stop_after, limiting, preloaded, volume, seek_failed, seek_target, trim,
+                                waveform_data, status_message.as_deref(), bell, player_focused, &name_options, strings, &theme
+                            );
+                        }
+                        if ui_mode == UIMode::Preferences {
+                            preferences_menu.render(f, size, &theme);
+                        }
+                    }
+                    UIMode::TrackList => render_track_list_view(
+                        f, size, tracks, &unplayable, current_track_index, track_list_selected, search_query, filtered_indices, track_list_view,
+                        queue, &marks, jump_index, &chips, &breadcrumb, flash, disc_headings, track_list_layout, &name_options, strings, &theme
+                    ),
+                    UIMode::ConfirmDelete => {
+                        render_track_list_view(
+                            f, size, tracks, &unplayable, current_track_index, track_list_selected, search_query, filtered_indices, track_list_view,
+                            queue, &marks, jump_index, &chips, &breadcrumb, flash, disc_headings, track_list_layout, &name_options, strings, &theme
+                        );
+                        if let Some((path, permanent)) = &pending_delete {
+                            render_confirm_delete(f, size, path, *permanent, strings, &theme);
+                        }
+                    }
+                    UIMode::SortMenu => {
+                        render_track_list_view(
+                            f, size, tracks, &unplayable, current_track_index, track_list_selected, search_query, filtered_indices, track_list_view,
+                            queue, &marks, jump_index, &chips, &breadcrumb, flash, disc_headings, track_list_layout, &name_options, strings, &theme
+                        );
+                        sort_menu.render(f, size, &theme);
+                    }
+                    UIMode::MarksMenu => {
+                        render_track_list_view(
+                            f, size, tracks, &unplayable, current_track_index, track_list_selected, search_query, filtered_indices, track_list_view,
+                            queue, &marks, jump_index, &chips, &breadcrumb, flash, disc_headings, track_list_layout, &name_options, strings, &theme
+                        );
+                        marks_menu.render(f, size, &theme);
+                    }
+                    UIMode::Browser => {
+                        if let Some(browser) = browser {
+                            render_browser_view(f, size, browser, strings, &theme);
+                        }
+                    }
+                    UIMode::Help => render_help_view(f, size, seek_step, keymap, strings, &theme),
+                    UIMode::Stats => render_stats_view(f, size, stats, strings, &theme),
+                    UIMode::Log => render_log_view(f, size, cache_line.as_deref(), log, log_scroll, strings, &theme),
+                }
+                // The player's status line flashes itself; elsewhere the bottom row does
+                if bell && !matches!(view, UIMode::Normal | UIMode::Preferences | UIMode::Palette | UIMode::Prompt | UIMode::ConfirmQuit) {
+                    ui::bell_row(f.buffer_mut(), size);
+                }
+                if ui_mode == UIMode::Palette {
+                    palette_menu.render(f, size, &theme);
+                }
+                if ui_mode == UIMode::Prompt {
+                    prompt.render(f, size, &theme);
+                }
+                if ui_mode == UIMode::ConfirmQuit {
+                    quit_menu.render(f, size, &theme);
+                }
             }
 
-            match ui_mode {
-                UIMode::Normal => render_normal_view(
-                    f, size, current_track.as_ref(), pos, dur, state,
-                    current_index, playlist_len, shuffle_state, repeat_mode, seek_step,
-                    &waveform_data
-                ),
-                UIMode::TrackList => render_track_list_view(
-                    f, size, &tracks, current_index, track_list_selected, &search_query, &filtered_indices, track_list_scroll
-                ),
-                UIMode::Help => render_help_view(f, size, seek_step),
+            if no_color {
+                strip_colors(f.buffer_mut());
             }
         }) {
-            eprintln!("Fatal: Failed to draw terminal: {}", e);
+            eprintln!("{}", self.strings.text_with("error.draw", &[("error", &e)]));
             self.running = false;
         }
+        self.first_frame_drawn(drawing);
+    }
+}
+
+/// Returns the text key describing a sort's direction.
+fn sort_direction(key: SortKey, descending: bool) -> &'static str {
+    let numeric = matches!(key, SortKey::TrackNumber | SortKey::Duration);
+    match (numeric, descending) {
+        (false, false) => "sort.a_to_z",
+        (false, true) => "sort.z_to_a",
+        (true, false) => "sort.low_to_high",
+        (true, true) => "sort.high_to_low",
+    }
+}
+
+/// Returns where the playlist was loaded from, as a canonical path, when
+/// that is one folder or playlist file that can be loaded again.
+fn source_path(playlist: &Playlist) -> Option<PathBuf> {
+    match playlist.sources() {
+        [PlaylistSource::Directory(path) | PlaylistSource::M3u(path)] => {
+            Some(std::fs::canonicalize(path).unwrap_or_else(|_| path.clone()))
+        }
+        _ => None,
+    }
+}
+
+/// Removes colors from a rendered frame, keeping highlights visible as reverse video.
+fn strip_colors(buffer: &mut ratatui::buffer::Buffer) {
+    for cell in buffer.content.iter_mut() {
+        if cell.bg != Color::Reset {
+            cell.modifier |= Modifier::REVERSED;
+        }
+        cell.set_fg(Color::Reset);
+        cell.set_bg(Color::Reset);
+    }
+}
+
+/// Formats a count with thousands separators (4211 → "4,211").
+fn format_count(n: usize) -> String {
+    let digits = n.to_string();
+    let mut out = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Truncates text to max width in terminal columns, adding ellipsis if needed.
+/// Describes a long operation by its name and how far it has got.
+fn describe_task<T>(task: &Task<T>, strings: &Strings) -> String {
+    let name = task.name();
+    let progress = task.progress();
+    match progress.percent() {
+        _ if !task.started() => strings.text_with("task.waiting", &[("name", &name)]),
+        Some(percent) => strings.text_with("task.percent", &[("name", &name), ("percent", &percent)]),
+        None if progress.done > 0 => strings.text_with("task.count", &[("name", &name), ("done", &progress.done)]),
+        None => name.to_string(),
     }
 }
 
-/// Truncates text to max width, adding ellipsis if needed.
 fn truncate_text(text: &str, max_width: usize) -> String {
-    if text.len() <= max_width {
-        text.to_string()
-    } else {
-        format!("{}...", &text[..max_width.saturating_sub(3)])
+    if Span::raw(text).width() <= max_width {
+        return text.to_string();
     }
+    let budget = max_width.saturating_sub(3);
+    let mut out = String::new();
+    let mut width = 0;
+    for c in text.chars() {
+        width += Span::raw(c.to_string()).width();
+        if width > budget {
+            break;
+        }
+        out.push(c);
+    }
+    out.push_str("...");
+    out
+}
+
+/// Truncates or pads text to fill exactly `width` terminal columns.
+fn fit_text(text: &str, width: usize) -> String {
+    let text = truncate_text(text, width);
+    let padding = width.saturating_sub(Span::raw(text.as_str()).width());
+    format!("{}{}", text, " ".repeat(padding))
 }
 
 /// Truncates text based on available terminal width.
@@ -443,8 +3829,21 @@ fn render_normal_view(
     _playlist_len: usize,
     shuffle_state: crate::playlist::ShuffleState,
     repeat_mode: crate::playlist::RepeatMode,
-    _seek_step: u32,
+    scope: Option<&str>,
+    stop_after: bool,
+    limiting: bool,
+    preloaded: bool,
+    volume: Option<u8>,
+    seek_failed: bool,
+    seek_target: Option<Duration>,
+    trim: Trim,
     waveform_data: &[f32],
+    status_message: Option<&str>,
+    bell: bool,
+    focused: Option<bool>,
+    name_options: &NameOptions,
+    strings: &Strings,
+    theme: &Theme,
 ) {
             // Single full-screen content area
             let mut content_lines = vec![];
@@ -467,14 +3866,14 @@ fn render_normal_view(
                 }
 
                 // Track Title
-                let display_name = truncate_for_display(&track.display_name(), size.width, 4);
+                let display_name = truncate_for_display(&track.formatted_name(name_options), size.width, 4);
                 content_lines.push(Line::from(format!("  {}", display_name)));
 
                 // Empty line
                 content_lines.push(Line::from(""));
 
                 // Waveform and Time
-                let waveform_str = render_waveform(waveform_data);
+                let waveform_str = visualizer::render_bars(waveform_data);
                 let time_str = format!(
                     "{:02}:{:02} / {:02}:{:02}",
                     pos.as_secs() / 60,
@@ -482,55 +3881,120 @@ fn render_normal_view(
                     dur.as_secs() / 60,
                     dur.as_secs() % 60
                 );
-                content_lines.push(Line::from(vec![
-                    Span::styled(format!("  {}   ", waveform_str), Style::default().fg(Color::Cyan)),
+                let mut time_spans = vec![
+                    Span::styled(format!("  {}   ", waveform_str), Style::default().fg(theme.accent)),
                     Span::raw(time_str),
-                ]));
+                ];
+                if let Some(target) = seek_target {
+                    let target = format!("{:02}:{:02}", target.as_secs() / 60, target.as_secs() % 60);
+                    time_spans.push(Span::styled(
+                        format!("  {}", strings.text_with("seek.target", &[("time", &target)])),
+                        Style::default().fg(theme.highlight).add_modifier(Modifier::BOLD),
+                    ));
+                }
+                content_lines.push(Line::from(time_spans));
 
                 // Empty line
                 content_lines.push(Line::from(""));
 
-                // Progress bar using braille characters
-                let progress_bar = render_progress_bar(pos, dur, size.width.saturating_sub(5) as usize);
-                content_lines.push(Line::from(format!("  {} ", progress_bar)));
+                // Progress bar
+                let progress_style = ProgressStyle::new(state, seek_failed);
+                let mut progress_spans = vec![Span::raw("  ")];
+                progress_spans.extend(ui::render_progress_bar(
+                    pos, dur, trim, None, seek_target, size.width.saturating_sub(5) as usize, progress_style, theme,
+                ));
+                content_lines.push(Line::from(progress_spans));
 
                 // Empty line
                 content_lines.push(Line::from(""));
 
                 // Status line: [▶ Playing]  [⤮ Shuffle]  [↻ Repeat]  ? Help
-                let state_text = match state {
-                    crate::player::PlaybackState::Playing => "▶ Playing",
-                    crate::player::PlaybackState::Paused => "⏸ Paused",
-                    crate::player::PlaybackState::Stopped => "⏹ Stopped",
-                };
+                let state_text = strings.text(match state {
+                    crate::player::PlaybackState::Playing => "state.playing",
+                    crate::player::PlaybackState::Paused => "state.paused",
+                    crate::player::PlaybackState::Stopped => "state.stopped",
+                });
 
-                let shuffle_text = match shuffle_state {
-                    crate::playlist::ShuffleState::Off => "Shuffle: Off",
-                    crate::playlist::ShuffleState::On => "⤮ Shuffle",
-                };
+                let shuffle_text = strings.text(match shuffle_state {
+                    crate::playlist::ShuffleState::Off => "shuffle.off",
+                    crate::playlist::ShuffleState::On => "shuffle.on",
+                });
 
-                let repeat_text = match repeat_mode {
-                    crate::playlist::RepeatMode::Off => "Repeat: Off",
-                    crate::playlist::RepeatMode::All => "↻ All",
-                    crate::playlist::RepeatMode::Single => "↻ Single",
-                };
+                let repeat_text = strings.text(match repeat_mode {
+                    crate::playlist::RepeatMode::Off => "repeat.off",
+                    crate::playlist::RepeatMode::All => "repeat.all",
+                    crate::playlist::RepeatMode::Single => "repeat.single",
+                });
 
-                content_lines.push(Line::from(vec![
+                let mut status_spans = vec![
                     Span::raw("  ["),
-                    Span::styled(state_text, Style::default().fg(Color::Green)),
+                    Span::styled(state_text, Style::default().fg(theme.status)),
                     Span::raw("]  ["),
-                    Span::styled(shuffle_text, Style::default().fg(Color::Yellow)),
+                    Span::styled(shuffle_text, Style::default().fg(theme.highlight)),
                     Span::raw("]  ["),
-                    Span::styled(repeat_text, Style::default().fg(Color::Magenta)),
+                    Span::styled(repeat_text, Style::default().fg(theme.secondary)),
+                    Span::raw("]  ["),
+                    Span::styled(
+                        match volume {
+                            Some(volume) => strings.text_with("player.volume", &[("volume", &volume)]),
+                            None => strings.text("player.muted").to_string(),
+                        },
+                        Style::default().fg(theme.dim),
+                    ),
                     Span::raw("]  "),
-                    Span::styled("? Help", Style::default().fg(Color::Cyan)),
-                ]));
+                ];
+                if let Some(scope) = scope {
+                    status_spans.push(Span::raw("["));
+                    status_spans.push(Span::styled(
+                        scope.to_string(),
+                        Style::default().fg(theme.accent).add_modifier(Modifier::BOLD),
+                    ));
+                    status_spans.push(Span::raw("]  "));
+                }
+                if stop_after {
+                    status_spans.push(Span::raw("["));
+                    status_spans.push(Span::styled(
+                        strings.text("stop_after.status"),
+                        Style::default().fg(theme.highlight).add_modifier(Modifier::BOLD),
+                    ));
+                    status_spans.push(Span::raw("]  "));
+                }
+                // Lit only while the limiter is pulling peaks back
+                if limiting {
+                    status_spans.push(Span::raw("["));
+                    status_spans.push(Span::styled(
+                        strings.text("limiter.status"),
+                        Style::default().fg(theme.error).add_modifier(Modifier::BOLD),
+                    ));
+                    status_spans.push(Span::raw("]  "));
+                }
+                // Lit once the player reports the next track decoded, not when it was asked to
+                if preloaded {
+                    status_spans.push(Span::raw("["));
+                    status_spans.push(Span::styled(strings.text("preload.status"), Style::default().fg(theme.secondary)));
+                    status_spans.push(Span::raw("]  "));
+                }
+                status_spans.push(Span::styled(strings.text("player.help_hint"), Style::default().fg(theme.accent)));
+                let status_line = Line::from(status_spans);
+                content_lines.push(if bell { ui::bell_line(status_line) } else { status_line });
+
+                // Transient status message
+                if let Some(message) = status_message {
+                    content_lines.push(Line::from(""));
+                    content_lines.push(Line::from(Span::styled(
+                        format!("  {}", message),
+                        Style::default().fg(theme.dim),
+                    )));
+                }
             } else {
-                content_lines.push(Line::from("  No track loaded"));
+                content_lines.push(Line::from(format!("  {}", strings.text("player.no_track"))));
             }
 
-            let content = Paragraph::new(content_lines)
-                .block(Block::default().borders(Borders::ALL));
+            let mut block = Block::default().borders(Borders::ALL);
+            if let Some(focused) = focused {
+                block = block.title(ui::pane_title(strings.text("focus.now_playing"), focused, theme));
+            }
+            let content = Paragraph::new(content_lines).block(block);
             f.render_widget(content, size);
 }
 
@@ -539,11 +4003,23 @@ fn render_track_list_view(
     f: &mut ratatui::Frame,
     size: ratatui::layout::Rect,
     tracks: &[crate::playlist::Track],
+    unplayable: &dyn Fn(usize) -> bool,
     current_index: usize,
     selected_index: usize,
     search_query: &str,
     filtered_indices: &[usize],
-    scroll_offset: usize,
+    mut viewport: Viewport,
+    queue: &[usize],
+    marks: &BTreeMap<&Path, char>,
+    jump_index: Option<&JumpIndex>,
+    chips: &[(char, String, Option<usize>)],
+    breadcrumb: &str,
+    message: Option<&str>,
+    disc_headings: bool,
+    layout: &columns::Layout,
+    name_options: &NameOptions,
+    strings: &Strings,
+    theme: &Theme,
 ) {
         // Create layout for track list
         let chunks = Layout::default()
@@ -557,89 +4033,453 @@ fn render_track_list_view(
 
         // Header with search bar
         let search_text = if search_query.is_empty() {
-            "Track List - Start typing to search...".to_string()
+            strings.text("tracks.prompt").to_string()
         } else {
-            format!("Search: {}_", search_query)
+            strings.text_with("tracks.search", &[("query", &search_query)])
         };
-        let header = Paragraph::new(search_text)
-            .style(Style::default().fg(Color::Cyan))
-            .block(Block::default().borders(Borders::ALL))
+        let mut header_spans = vec![Span::raw(search_text)];
+        header_spans.extend(ui::chip_spans(chips, theme));
+        let header = Paragraph::new(Line::from(header_spans))
+            .style(Style::default().fg(theme.accent))
+            .block(Block::default().borders(Borders::ALL).title(format!(
+                " {} ",
+                truncate_text(breadcrumb, usize::from(size.width.saturating_sub(6)))
+            )))
             .alignment(Alignment::Left);
         f.render_widget(header, chunks[0]);
 
-        // Track list
+        // Track list, beside the A to Z strip when jumping
         let mut track_lines = vec![];
+        let strip = jump_index.map(|index| render_jump_strip(index, selected_index, chunks[1].height.saturating_sub(2), theme));
+        let strip_width = strip.as_ref().and_then(|strip| strip.first()).map_or(0, |line| line.width() as u16);
+        // Recently added playlists date every track
+        let layout = match tracks.first() {
+            Some(track) if track.added.is_some() => layout.with_added(),
+            _ => layout.clone(),
+        };
+        // Inside the borders, after the marker and mark
+        let cells = layout.cells(chunks[1].width.saturating_sub(strip_width + 4), tracks.len());
 
-        // Calculate visible range based on scroll offset and content area height
-        // Account for borders (top and bottom) reducing the drawable area by 2 lines
-        let visible_height = chunks[1].height.saturating_sub(2) as usize;
-        let visible_start = scroll_offset;
-        let visible_end = (scroll_offset + visible_height).min(filtered_indices.len());
+        // Account for borders (top and bottom) reducing the drawable area by 2 lines;
+        // the terminal may have been resized since the selection last moved
+        let rows = TrackRows { filtered_indices, discs: disc_headings.then_some(tracks) };
+        viewport.height = chunks[1].height.saturating_sub(2);
+        viewport.scroll_to(&rows, selected_index);
 
-        // Only render tracks in the visible range
-        for filtered_idx in visible_start..visible_end {
+        // Only render tracks intersecting the viewport
+        for filtered_idx in viewport.visible(&rows) {
             let actual_idx = filtered_indices[filtered_idx];
 
             if actual_idx >= tracks.len() {
                 continue;
             }
 
+            if let Some(disc) = rows.disc_heading(filtered_idx) {
+                track_lines.push(Line::from(Span::styled(
+                    format!("    {}", strings.text_with("tracks.disc", &[("disc", &disc)])),
+                    Style::default().fg(theme.secondary).add_modifier(Modifier::BOLD),
+                )));
+            }
             let track = &tracks[actual_idx];
-            let prefix = if actual_idx == current_index { "▶ " } else { "  " };
-            let track_num = format!("{:3}. ", actual_idx + 1);
-
-            let mut line_spans = vec![Span::raw(prefix), Span::raw(track_num)];
-
-            // Truncate track name based on available width (reserve 25 chars for prefix, number, duration)
-            let display_name = truncate_for_display(&track.display_name(), size.width, 25);
+            let failed = unplayable(actual_idx);
+            let marker = if actual_idx == current_index {
+                theme.glyphs.playing_marker
+            } else if failed {
+                theme.glyphs.unplayable_marker
+            } else if queue.contains(&actual_idx) {
+                theme.glyphs.queue_badge
+            } else if filtered_idx == selected_index {
+                theme.glyphs.selected_marker
+            } else {
+                ' '
+            };
+            // The mark letter goes in the gutter column after the marker
+            let mark = marks.get(track.path.as_path()).copied().unwrap_or(' ');
+            let mut line_spans = vec![
+                Span::raw(marker.to_string()),
+                Span::styled(mark.to_string(), Style::default().fg(theme.accent)),
+            ];
 
             // Determine styling based on whether this is the selected or currently playing track
             let style = if filtered_idx == selected_index {
                 // Selected track - highlighted with reverse colors
-                Style::default().bg(Color::Cyan).fg(Color::Black).add_modifier(Modifier::BOLD)
+                Style::default().bg(theme.selection_bg).fg(theme.selection_fg).add_modifier(Modifier::BOLD)
             } else if actual_idx == current_index {
                 // Currently playing track - yellow and bold
-                Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+                Style::default().fg(theme.highlight).add_modifier(Modifier::BOLD)
+            } else if failed {
+                // Failed to open when last tried
+                Style::default().fg(theme.dim)
             } else {
                 // Regular track
                 Style::default()
             };
 
-            line_spans.push(Span::styled(display_name, style));
-
-            if let Some(duration) = &track.duration {
-                let duration_str = format!(
-                    "  [{:02}:{:02}]",
-                    duration.as_secs() / 60,
-                    duration.as_secs() % 60
-                );
-                line_spans.push(Span::styled(
-                    duration_str,
-                    Style::default().fg(Color::DarkGray),
-                ));
+            let dim = Style::default().fg(theme.dim);
+            let mut x = 0;
+            for cell in &cells {
+                line_spans.push(Span::raw(" ".repeat(usize::from(cell.x - x))));
+                x = cell.x + cell.width;
+                let width = usize::from(cell.width);
+                let (text, style) = match cell.column {
+                    Column::Number => (format!("{:>1$}.", actual_idx + 1, width - 1), Style::default()),
+                    Column::Title if track.renamed => {
+                        let name = truncate_text(&track.formatted_name(name_options), width.saturating_sub(2));
+                        line_spans.push(Span::styled(name.clone(), style));
+                        let marker = format!(" {}", theme.glyphs.renamed_marker);
+                        line_spans.push(Span::styled(fit_text(&marker, width.saturating_sub(Span::raw(name).width())), dim));
+                        continue;
+                    }
+                    Column::Title => (track.formatted_name(name_options), style),
+                    Column::Artist => (track.artist.clone().unwrap_or_default(), style),
+                    Column::Album => (track.album.clone().unwrap_or_default(), style),
+                    Column::Duration => match &track.duration {
+                        Some(duration) => {
+                            (format!("[{:02}:{:02}]", duration.as_secs() / 60, duration.as_secs() % 60), dim)
+                        }
+                        // Not read yet after a fast scan
+                        None if !track.probed => ("[--:--]".to_string(), dim),
+                        None => (String::new(), dim),
+                    },
+                    Column::Added => match track.added {
+                        Some(added) => {
+                            let date = crate::dates::format_timestamp(std::time::UNIX_EPOCH + Duration::from_secs(added));
+                            (date[..10].to_string(), dim)
+                        }
+                        None => (String::new(), dim),
+                    },
+                };
+                line_spans.push(Span::styled(fit_text(&text, width), style));
             }
 
             track_lines.push(Line::from(line_spans));
         }
 
         if track_lines.is_empty() {
-            track_lines.push(Line::from("  No tracks match your search"));
+            track_lines.push(Line::from(format!("  {}", strings.text("tracks.no_matches"))));
         }
 
+        let mut list_area = chunks[1];
+        if let Some(strip) = strip {
+            let columns = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Min(0), Constraint::Length(strip_width)])
+                .split(list_area);
+            list_area = columns[0];
+            let strip_area = columns[1].inner(ratatui::layout::Margin { horizontal: 0, vertical: 1 });
+            f.render_widget(Paragraph::new(strip), strip_area);
+        }
         let track_list = Paragraph::new(track_lines)
-            .block(Block::default().borders(Borders::ALL).title("Tracks"));
-        f.render_widget(track_list, chunks[1]);
+            .block(Block::default().borders(Borders::ALL).title(strings.text("tracks.title")));
+        f.render_widget(track_list, list_area);
 
-        // Footer
-        let footer = Paragraph::new("Esc: Back | Enter: Play selected | Type to search")
-            .style(Style::default().fg(Color::DarkGray))
+        // Footer, or a message such as a mark being set
+        let footer_text = if jump_index.is_some() { "jump.footer" } else { "tracks.footer" };
+        let footer = Paragraph::new(message.unwrap_or(strings.text(footer_text)))
+            .style(Style::default().fg(theme.dim))
             .block(Block::default().borders(Borders::NONE))
             .alignment(Alignment::Center);
         f.render_widget(footer, chunks[2]);
 }
 
+/// Lays out the A to Z strip beside the track list in at most `height`
+/// lines, several letters to a line if there is not room for one each.
+/// Letters with no tracks are dimmed and the selected track's is highlighted.
+fn render_jump_strip(index: &JumpIndex, selected: usize, height: u16, theme: &Theme) -> Vec<Line<'static>> {
+    let per_line = crate::jump::LETTERS.len().div_ceil(usize::from(height.max(1)));
+    let current = index.initial_of(selected);
+    crate::jump::LETTERS
+        .chunks(per_line)
+        .map(|letters| {
+            let mut spans = vec![Span::raw(" ")];
+            spans.extend(letters.iter().map(|&letter| {
+                let style = if current == Some(letter) {
+                    Style::default().bg(theme.selection_bg).fg(theme.selection_fg).add_modifier(Modifier::BOLD)
+                } else if index.has(letter) {
+                    Style::default().fg(theme.accent)
+                } else {
+                    Style::default().fg(theme.dim)
+                };
+                Span::styled(letter.to_string(), style)
+            }));
+            // Short last lines are padded so every line is as wide
+            spans.push(Span::raw(" ".repeat(per_line - letters.len())));
+            Line::from(spans)
+        })
+        .collect()
+}
+
+/// A track in the queue panel.
+struct UpcomingEntry {
+    name: String,
+    duration: Option<Duration>,
+    /// Time until the track starts, unknown after a track of unknown length.
+    starts_in: Option<Duration>,
+    queued: bool,
+}
+
+/// What the queue panel shows: the upcoming tracks, which is selected and
+/// how far the list is scrolled.
+struct QueuePanel {
+    entries: Vec<UpcomingEntry>,
+    selected: usize,
+    viewport: Viewport,
+    focused: bool,
+}
+
+/// Lists the tracks the queue panel shows, counting the time until each
+/// starts from what is left of the current one.
+fn upcoming_entries(playlist: &Playlist, remaining: Duration, name_options: &NameOptions) -> Vec<UpcomingEntry> {
+    let mut starts_in = Some(remaining);
+    playlist
+        .upcoming(QUEUE_PANEL_LEN)
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, index)| {
+            let track = playlist.tracks().get(index)?;
+            let entry = UpcomingEntry {
+                name: track.formatted_name(name_options),
+                duration: track.duration,
+                starts_in,
+                queued: i < playlist.queue().len(),
+            };
+            starts_in = starts_in.zip(track.duration).map(|(start, duration)| start + duration);
+            Some(entry)
+        })
+        .collect()
+}
+
+/// Renders the panel of upcoming tracks.
+fn render_queue_panel(
+    f: &mut ratatui::Frame,
+    area: ratatui::layout::Rect,
+    panel: QueuePanel,
+    strings: &Strings,
+    theme: &Theme,
+) {
+    let QueuePanel { entries, selected, mut viewport, focused } = panel;
+    let rows = UniformRows(entries.len());
+    viewport.height = area.height.saturating_sub(2);
+    viewport.scroll_to(&rows, selected);
+
+    // Badge, then the name, then the duration and start columns
+    let name_width = (area.width.saturating_sub(2) as usize).saturating_sub(18).max(1);
+    let clock = |d: Option<Duration>| {
+        d.map_or("--:--".to_string(), |d| format!("{:02}:{:02}", d.as_secs() / 60, d.as_secs() % 60))
+    };
+    let mut lines: Vec<Line> = viewport
+        .visible(&rows)
+        .map(|i| {
+            let entry = &entries[i];
+            let badge = if entry.queued { theme.glyphs.queue_badge } else { ' ' };
+            let name = truncate_text(&entry.name, name_width);
+            let padding = " ".repeat(name_width.saturating_sub(Span::raw(name.as_str()).width()));
+            let style = if i == selected {
+                Style::default().bg(theme.selection_bg).fg(theme.selection_fg).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            Line::from(vec![
+                Span::styled(format!("{} ", badge), Style::default().fg(theme.accent)),
+                Span::styled(format!("{}{}", name, padding), style),
+                Span::styled(format!(" {}", clock(entry.duration)), Style::default().fg(theme.dim)),
+                Span::styled(format!("  in {}", clock(entry.starts_in)), Style::default().fg(theme.dim)),
+            ])
+        })
+        .collect();
+    if lines.is_empty() {
+        lines.push(Line::from(format!(" {}", strings.text("queue.empty"))));
+    }
+
+    let panel = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(ui::pane_title(strings.text("queue.title"), focused, theme))
+            .border_style(Style::default().fg(theme.accent)),
+    );
+    f.render_widget(panel, area);
+}
+
+/// Renders the folder browser: the subfolders of the folder shown.
+fn render_browser_view(
+    f: &mut ratatui::Frame,
+    size: ratatui::layout::Rect,
+    browser: &Browser,
+    strings: &Strings,
+    theme: &Theme,
+) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(0),     // Folders
+            Constraint::Length(2),  // Footer
+        ])
+        .split(size);
+
+    let entries = browser.entries();
+    let rows = UniformRows(entries.len());
+    let mut viewport = browser.view();
+    viewport.height = chunks[0].height.saturating_sub(2);
+    viewport.scroll_to(&rows, browser.selected());
+
+    let name_width = (chunks[0].width as usize).saturating_sub(5).max(1);
+    let mut lines: Vec<Line> = viewport
+        .visible(&rows)
+        .map(|i| {
+            let style = if i == browser.selected() {
+                Style::default().bg(theme.selection_bg).fg(theme.selection_fg).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            let name = truncate_text(&entries[i], name_width);
+            Line::from(Span::styled(format!(" {}{} ", name, std::path::MAIN_SEPARATOR), style))
+        })
+        .collect();
+    if lines.is_empty() {
+        lines.push(Line::from(format!(" {}", strings.text("browser.empty"))));
+    }
+
+    let list = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(browser.label())
+            .border_style(Style::default().fg(theme.accent)),
+    );
+    f.render_widget(list, chunks[0]);
+
+    let footer = Paragraph::new(strings.text("browser.footer"))
+        .style(Style::default().fg(theme.dim))
+        .alignment(Alignment::Center);
+    f.render_widget(footer, chunks[1]);
+}
+
+/// Rows of the track list view, one line per track matching the search.
+struct TrackRows<'a> {
+    filtered_indices: &'a [usize],
+    /// Tracks to head each disc of, when grouped by album.
+    discs: Option<&'a [Track]>,
+}
+
+impl TrackRows<'_> {
+    /// Returns the disc whose heading goes above a row, if one does.
+    fn disc_heading(&self, row: usize) -> Option<u32> {
+        discs::disc_starting_at(self.discs?, self.filtered_indices, row)
+    }
+}
+
+impl RowModel for TrackRows<'_> {
+    fn len(&self) -> usize {
+        self.filtered_indices.len()
+    }
+
+    fn height(&self, row: usize) -> u16 {
+        1 + u16::from(self.disc_heading(row).is_some())
+    }
+}
+
+/// Renders the delete confirmation modal.
+fn render_confirm_delete(
+    f: &mut ratatui::Frame,
+    size: ratatui::layout::Rect,
+    path: &str,
+    permanent: bool,
+    strings: &Strings,
+    theme: &Theme,
+) {
+    use ratatui::widgets::{Clear, Wrap};
+
+    let width = size.width.saturating_sub(8).min(70);
+    let area = ratatui::layout::Rect {
+        x: size.x + (size.width - width) / 2,
+        y: size.y + size.height.saturating_sub(7) / 2,
+        width,
+        height: 7.min(size.height),
+    };
+
+    let (title, action) = if permanent {
+        (strings.text("delete.title_permanent"), strings.text("delete.action_permanent"))
+    } else {
+        (strings.text("delete.title_trash"), strings.text("delete.action_trash"))
+    };
+
+    let text = vec![
+        Line::from(Span::styled(path.to_string(), Style::default().add_modifier(Modifier::BOLD))),
+        Line::from(""),
+        Line::from(vec![
+            Span::styled("y", Style::default().fg(theme.highlight)),
+            Span::raw(format!(": {}  ", action)),
+            Span::styled("n", Style::default().fg(theme.highlight)),
+            Span::raw(format!(": {}", strings.text("delete.cancel"))),
+        ]),
+    ];
+
+    let dialog = Paragraph::new(text)
+        .wrap(Wrap { trim: true })
+        .block(Block::default().borders(Borders::ALL).title(title).style(Style::default().fg(theme.error)));
+    f.render_widget(Clear, area);
+    f.render_widget(dialog, area);
+}
+
+/// Renders the log view.
+fn render_log_view(
+    f: &mut ratatui::Frame,
+    size: ratatui::layout::Rect,
+    cache_line: Option<&str>,
+    log: &[String],
+    scroll_offset: usize,
+    strings: &Strings,
+    theme: &Theme,
+) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(0),     // Log entries
+            Constraint::Length(2),  // Footer
+        ])
+        .split(size);
+
+    let mut lines: Vec<Line> = cache_line
+        .map(|line| Line::from(Span::styled(format!("  {}", line), Style::default().fg(theme.dim))))
+        .into_iter()
+        .collect();
+    let visible_height = (chunks[0].height.saturating_sub(2) as usize).saturating_sub(lines.len());
+    lines.extend(
+        log.iter()
+            .skip(scroll_offset)
+            .take(visible_height)
+            .map(|entry| Line::from(format!("  {}", truncate_for_display(entry, size.width, 4)))),
+    );
+
+    if log.is_empty() {
+        lines.push(Line::from(format!("  {}", strings.text("log.empty"))));
+    }
+
+    let entries = Paragraph::new(lines)
+        .block(Block::default().borders(Borders::ALL).title(strings.text("log.title")));
+    f.render_widget(entries, chunks[0]);
+
+    let footer = Paragraph::new(strings.text("log.footer"))
+        .style(Style::default().fg(theme.dim))
+        .alignment(Alignment::Center);
+    f.render_widget(footer, chunks[1]);
+}
+
+/// Groups the actions for the help screen: the player's, then under a
+/// heading the track list's.
+fn help_sections(strings: &Strings) -> [(Option<String>, Vec<&'static actions::Entry>); 2] {
+    let (track_list, player) = ACTIONS.iter().partition(|entry| entry.scope == Scope::TrackList);
+    [(None, player), (Some(strings.text("help.in_track_list").to_string()), track_list)]
+}
+
 /// Renders the help overlay view.
-fn render_help_view(f: &mut ratatui::Frame, size: ratatui::layout::Rect, seek_step: u32) {
+fn render_help_view(
+    f: &mut ratatui::Frame,
+    size: ratatui::layout::Rect,
+    seek_step: u32,
+    keymap: &Keymap,
+    strings: &Strings,
+    theme: &Theme,
+) {
         // Create centered help box
         let help_area = {
             let vertical = Layout::default()
@@ -661,82 +4501,184 @@ fn render_help_view(f: &mut ratatui::Frame, size: ratatui::layout::Rect, seek_st
                 .split(vertical[1])[1]
         };
 
-        let help_text = vec![
+        let mut help_text = vec![
             Line::from(""),
             Line::from(Span::styled(
-                "juke - Keybindings",
-                Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
+                strings.text("help.heading"),
+                Style::default().fg(theme.accent).add_modifier(Modifier::BOLD),
             )),
             Line::from(""),
-            Line::from(vec![
-                Span::styled("  Space      ", Style::default().fg(Color::Yellow)),
-                Span::raw("Play / Pause"),
-            ]),
-            Line::from(vec![
-                Span::styled("  n / →      ", Style::default().fg(Color::Yellow)),
-                Span::raw("Next track"),
-            ]),
-            Line::from(vec![
-                Span::styled("  p / ←      ", Style::default().fg(Color::Yellow)),
-                Span::raw("Previous track"),
-            ]),
-            Line::from(vec![
-                Span::styled("  Shift+→    ", Style::default().fg(Color::Yellow)),
-                Span::raw(format!("Seek forward {}s", seek_step)),
-            ]),
-            Line::from(vec![
-                Span::styled("  Shift+←    ", Style::default().fg(Color::Yellow)),
-                Span::raw(format!("Seek backward {}s", seek_step)),
-            ]),
-            Line::from(vec![
-                Span::styled("  s          ", Style::default().fg(Color::Yellow)),
-                Span::raw("Toggle shuffle"),
-            ]),
-            Line::from(vec![
-                Span::styled("  r          ", Style::default().fg(Color::Yellow)),
-                Span::raw("Cycle repeat mode"),
-            ]),
-            Line::from(vec![
-                Span::styled("  t          ", Style::default().fg(Color::Yellow)),
-                Span::raw("Toggle track list"),
-            ]),
-            Line::from(vec![
-                Span::styled("  ?          ", Style::default().fg(Color::Yellow)),
-                Span::raw("Toggle help (this screen)"),
-            ]),
-            Line::from(vec![
-                Span::styled("  Esc / q    ", Style::default().fg(Color::Yellow)),
-                Span::raw("Quit"),
-            ]),
-            Line::from(""),
-            Line::from(Span::styled(
-                "Press any key to close",
-                Style::default().fg(Color::DarkGray),
-            )),
         ];
+        for (heading, entries) in help_sections(strings) {
+            if let Some(heading) = heading {
+                help_text.push(Line::from(""));
+                help_text.push(Line::from(Span::styled(heading, Style::default().fg(theme.accent))));
+            }
+            for entry in entries {
+                help_text.push(Line::from(vec![
+                    Span::styled(format!("  {:<11}", keymap.key_labels(entry.action)), Style::default().fg(theme.highlight)),
+                    Span::raw(strings.text_with(entry.text, &[("seconds", &seek_step)])),
+                ]));
+            }
+        }
+        help_text.push(Line::from(""));
+        help_text.push(Line::from(Span::styled(
+            strings.text("help.close"),
+            Style::default().fg(theme.dim),
+        )));
+        // Named in bug reports
+        help_text.push(Line::from(Span::styled(crate::buildinfo::summary(), Style::default().fg(theme.dim))));
 
+        // Translations may be longer than the English text, so wrap rather than clip
         let help = Paragraph::new(help_text)
-            .block(Block::default().borders(Borders::ALL).title("Help"))
+            .wrap(ratatui::widgets::Wrap { trim: false })
+            .block(Block::default().borders(Borders::ALL).title(strings.text("help.title")))
             .alignment(Alignment::Left);
         f.render_widget(help, help_area);
 }
 
+/// Renders the listening statistics screen.
+fn render_stats_view(
+    f: &mut ratatui::Frame,
+    size: ratatui::layout::Rect,
+    summary: &stats::Summary,
+    strings: &Strings,
+    theme: &Theme,
+) {
+    const CHART_LINES: u16 = 4;
+
+    let block = Block::default().borders(Borders::ALL).title(strings.text("stats.title"));
+    let inner = block.inner(size);
+    f.render_widget(block, size);
+    let footer = Line::from(Span::styled(strings.text("help.close"), Style::default().fg(theme.dim)));
+
+    if summary.is_empty() {
+        let text = vec![
+            Line::from(""),
+            Line::from(strings.text("stats.empty")),
+            Line::from(""),
+            footer,
+        ];
+        let empty = Paragraph::new(text).wrap(ratatui::widgets::Wrap { trim: false }).alignment(Alignment::Center);
+        f.render_widget(empty, inner);
+        return;
+    }
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(CHART_LINES + 4), // Total, blank line, chart, day names, blank line
+            Constraint::Min(0),                  // Rankings
+            Constraint::Length(1),               // Footer
+        ])
+        .split(inner);
+
+    // Weekday histogram, one bar two columns wide under each day name
+    let mut chart = vec![
+        Line::from(vec![
+            Span::raw(" "),
+            Span::styled(
+                strings.text_with("stats.listened", &[("time", &stats::format_listened(summary.listened))]),
+                Style::default().fg(theme.accent).add_modifier(Modifier::BOLD),
+            ),
+        ]),
+        Line::from(""),
+    ];
+    let heights = stats::bar_heights(&summary.by_weekday, CHART_LINES);
+    for line in 0..CHART_LINES {
+        let floor = u32::from(CHART_LINES - 1 - line) * 8;
+        let bars: String = heights
+            .iter()
+            .map(|&height| match height.saturating_sub(floor).min(8) {
+                0 => "    ".to_string(),
+                level => format!(" {0}{0} ", visualizer::BAR_LEVELS[level as usize - 1]),
+            })
+            .collect();
+        chart.push(Line::from(Span::styled(bars, Style::default().fg(theme.status))));
+    }
+    let days: String = strings.text("stats.weekdays").split_whitespace().map(|day| format!("{:^4}", day)).collect();
+    chart.push(Line::from(Span::styled(days, Style::default().fg(theme.dim))));
+    f.render_widget(Paragraph::new(chart), chunks[0]);
+
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Ratio(1, 3); 3])
+        .split(chunks[1]);
+    let rankings = [
+        ("stats.top_artists", &summary.top_artists),
+        ("stats.top_tracks", &summary.top_tracks),
+        ("stats.most_skipped", &summary.most_skipped),
+    ];
+    for ((title, entries), area) in rankings.into_iter().zip(columns.iter()) {
+        let mut lines = vec![Line::from(Span::styled(
+            format!(" {}", strings.text(title)),
+            Style::default().fg(theme.highlight).add_modifier(Modifier::BOLD),
+        ))];
+        if entries.is_empty() {
+            lines.push(Line::from(Span::styled(format!(" {}", strings.text("stats.none")), Style::default().fg(theme.dim))));
+        }
+        for (rank, (name, count)) in entries.iter().enumerate() {
+            let count = format!(" {}", count);
+            let name_width = (area.width as usize).saturating_sub(count.len() + 5);
+            lines.push(Line::from(vec![
+                Span::styled(format!("{:>3}. ", rank + 1), Style::default().fg(theme.dim)),
+                Span::raw(truncate_text(name, name_width)),
+                Span::styled(count, Style::default().fg(theme.secondary)),
+            ]));
+        }
+        f.render_widget(Paragraph::new(lines), *area);
+    }
+
+    f.render_widget(Paragraph::new(footer).alignment(Alignment::Center), chunks[2]);
+}
+
+/// Describes the listening statistics line by line for the plain frontend.
+fn stats_text(summary: &stats::Summary, strings: &Strings) -> Vec<String> {
+    if summary.is_empty() {
+        return vec![strings.text("stats.empty").to_string()];
+    }
+    let mut lines = vec![strings.text_with("stats.listened", &[("time", &stats::format_listened(summary.listened))])];
+    for (day, listened) in strings.text("stats.weekdays").split_whitespace().zip(summary.by_weekday) {
+        lines.push(format!("{}: {}", day, stats::format_listened(listened)));
+    }
+    let rankings = [
+        ("stats.top_artists", &summary.top_artists),
+        ("stats.top_tracks", &summary.top_tracks),
+        ("stats.most_skipped", &summary.most_skipped),
+    ];
+    for (title, entries) in rankings {
+        lines.push(format!("{}:", strings.text(title)));
+        if entries.is_empty() {
+            lines.push(strings.text("stats.none").to_string());
+        }
+        lines.extend(entries.iter().enumerate().map(|(rank, (name, count))| format!("{}. {} ({})", rank + 1, name, count)));
+    }
+    lines
+}
+
 /// Renders a warning when terminal is too small.
 fn render_size_warning(
     f: &mut ratatui::Frame,
     size: ratatui::layout::Rect,
     min_width: u16,
     min_height: u16,
+    strings: &Strings,
+    theme: &Theme,
 ) {
     use ratatui::widgets::Wrap;
 
-    let message = format!(
-        "Terminal too small!\n\nMinimum: {}x{}\nCurrent: {}x{}\n\nPlease resize terminal to continue.",
-        min_width, min_height, size.width, size.height
+    let message = strings.text_with(
+        "size.warning",
+        &[
+            ("min_width", &min_width),
+            ("min_height", &min_height),
+            ("width", &size.width),
+            ("height", &size.height),
+        ],
     );
 
     let paragraph = Paragraph::new(message)
-        .style(Style::default().fg(Color::Red).add_modifier(Modifier::BOLD))
+        .style(Style::default().fg(theme.error).add_modifier(Modifier::BOLD))
         .wrap(Wrap { trim: true })
         .alignment(Alignment::Center);
 
@@ -753,33 +4695,115 @@ fn render_size_warning(
     f.render_widget(paragraph, vertical[1]);
 }
 
-/// Renders a progress bar using braille characters.
-fn render_progress_bar(pos: Duration, dur: Duration, width: usize) -> String {
-    if width == 0 || dur.as_secs() == 0 {
-        return String::new();
+/// Renders the player view once every track is gone, with what can still
+/// be done.
+fn render_empty_view(
+    f: &mut ratatui::Frame,
+    size: ratatui::layout::Rect,
+    source: &str,
+    message: Option<&str>,
+    strings: &Strings,
+    theme: &Theme,
+) {
+    let hint = if source.is_empty() {
+        strings.text("empty.hint_no_source").to_string()
+    } else {
+        strings.text_with("empty.hint", &[("source", &source)])
+    };
+    let mut lines = vec![
+        Line::from(Span::styled(
+            strings.text("empty.title"),
+            Style::default().fg(theme.highlight).add_modifier(Modifier::BOLD),
+        )),
+        Line::from(""),
+        Line::from(Span::styled(hint, Style::default().fg(theme.dim))),
+    ];
+    if let Some(message) = message {
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled(message.to_string(), Style::default().fg(theme.dim))));
     }
 
-    let progress = (pos.as_secs_f64() / dur.as_secs_f64()).min(1.0);
-    let filled_width = (progress * width as f64).round() as usize;
-
-    let filled = "⣿".repeat(filled_width);
-    let empty = "⣀".repeat(width.saturating_sub(filled_width));
+    let vertical = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage(40),
+            Constraint::Length(5),
+            Constraint::Percentage(40),
+        ])
+        .split(size);
+    let paragraph = Paragraph::new(lines)
+        .wrap(ratatui::widgets::Wrap { trim: true })
+        .alignment(Alignment::Center);
+    f.render_widget(paragraph, vertical[1]);
+}
 
-    format!("{}{}", filled, empty)
+/// Returns the ReplayGain to apply for a track, if enabled: tags first,
+/// then the loudness cache.
+fn replay_gain(config: &Config, loudness: &LoudnessCache, track: &crate::playlist::Track) -> Option<f32> {
+    if !config.playback.replaygain {
+        return None;
+    }
+    track.replay_gain.or_else(|| loudness.replay_gain_db(&track.path))
 }
 
-/// Renders bar visualizer data as a string of block characters.
-fn render_waveform(data: &[f32]) -> String {
-    // Use block characters to represent amplitude levels
-    // Characters from lowest to highest: ▁▂▃▄▅▆▇█
-    const LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
 
-    // Each bar is rendered with a block character based on its amplitude
-    data.iter()
-        .map(|&amplitude| {
-            // Map amplitude (0.0-1.0) to character index (0-7)
-            let index = (amplitude * 7.0).round() as usize;
-            LEVELS[index.min(7)]
-        })
-        .collect()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app_of(names: &[&str]) -> App {
+        let tracks = names.iter().map(|name| Track::new(PathBuf::from(format!("/music/{}.mp3", name)))).collect();
+        let mut app = App::for_tests(Playlist::from_tracks(tracks).unwrap(), Config::default());
+        app.set_ui_mode(UIMode::TrackList);
+        app
+    }
+
+    #[test]
+    fn test_turning_the_last_chip_off_shows_every_track() {
+        let mut app = app_of(&["alpha", "beta", "gamma"]);
+        app.playlist.toggle_queued(1);
+        app.track_list_toggle_chip(Chip::Queued);
+        assert_eq!(app.filtered_indices, [1]);
+        app.track_list_toggle_chip(Chip::Queued);
+        assert_eq!(app.filtered_indices, [0, 1, 2]);
+
+        // With a search, only the chip is lifted
+        app.search_input('a');
+        app.track_list_toggle_chip(Chip::Queued);
+        app.track_list_toggle_chip(Chip::Queued);
+        assert_eq!(app.filtered_indices, [0, 1, 2]);
+        app.track_list_toggle_chip(Chip::Queued);
+        app.track_list_clear_chips();
+        app.search_input('l');
+        assert_eq!(app.filtered_indices, [0]);
+    }
+
+    /// Returns the track list rows after filtering from scratch.
+    fn rescanned(app: &mut App) -> Vec<usize> {
+        let narrowed = app.filtered_indices.clone();
+        app.filtered_for = None;
+        app.update_filtered_indices();
+        assert_eq!(app.filtered_indices, narrowed, "narrowing differs for {:?}", app.search_query);
+        narrowed
+    }
+
+    #[test]
+    fn test_narrowing_matches_a_rescan() {
+        let mut app = app_of(&["alpha", "alpine", "beta", "palp", "gamma"]);
+        for c in "alp".chars() {
+            app.search_input(c);
+            rescanned(&mut app);
+        }
+        assert_eq!(rescanned(&mut app), [0, 1, 3]);
+
+        app.search_backspace();
+        app.search_backspace();
+        assert_eq!(rescanned(&mut app), [0, 1, 2, 3, 4]);
+
+        // A track renamed to match is found by the next character
+        app.search_input('l');
+        app.playlist.update_track(2, |track| track.title = Some("Balp".to_string()));
+        app.search_input('p');
+        assert_eq!(rescanned(&mut app), [0, 1, 2, 3]);
+    }
 }
@@ -0,0 +1,219 @@
+//! The folder browser, for playing one directory of a big music root as
+//! if it were an album.
+//!
+//! Directories are listed one level at a time as they are opened, so the
+//! tree below the root is never walked up front. Each directory remembers
+//! the entry last selected in it, and the browser as a whole is kept
+//! between visits.
+
+use crate::collation::Collation;
+use crate::listview::{UniformRows, Viewport};
+use crate::playlist::ScanOptions;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Lines of context kept above and below the selection.
+const MARGIN: u16 = 2;
+
+/// Where the folder browser is, and what it shows there.
+#[derive(Debug, Clone)]
+pub struct Browser {
+    /// Directory the browser cannot go above: the playlist's source.
+    root: PathBuf,
+    /// Directory being shown.
+    dir: PathBuf,
+    /// Names of the subdirectories of `dir`, in collation order.
+    entries: Vec<String>,
+    selected: usize,
+    view: Viewport,
+    /// Entry last selected in each directory visited.
+    remembered: HashMap<PathBuf, usize>,
+    options: ScanOptions,
+    collation: Collation,
+}
+
+impl Browser {
+    /// Opens the browser on a root directory, listing only its immediate
+    /// subdirectories. Hidden directories and symbolic links are listed
+    /// the way a directory scan with `options` would descend into them.
+    pub fn open(root: &Path, options: ScanOptions, collation: Collation) -> io::Result<Self> {
+        let mut browser = Self {
+            root: root.to_path_buf(),
+            dir: root.to_path_buf(),
+            entries: Vec::new(),
+            selected: 0,
+            view: Viewport::new(MARGIN),
+            remembered: HashMap::new(),
+            options,
+            collation,
+        };
+        browser.entries = browser.list(root)?;
+        Ok(browser)
+    }
+
+    /// Lists the subdirectories of a directory.
+    fn list(&self, dir: &Path) -> io::Result<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let Ok(entry) = entry else {
+                continue;
+            };
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with('.') && !self.options.include_hidden {
+                continue;
+            }
+            let is_dir = match entry.file_type() {
+                Ok(kind) if kind.is_symlink() => {
+                    self.options.follow_symlinks && entry.path().metadata().is_ok_and(|m| m.is_dir())
+                }
+                Ok(kind) => kind.is_dir(),
+                Err(_) => false,
+            };
+            if is_dir {
+                names.push(name);
+            }
+        }
+        names.sort_by_cached_key(|name| self.collation.key(name));
+        Ok(names)
+    }
+
+    /// Shows another directory, selecting what was last selected there or
+    /// else the entry named `select`. Nothing changes if it cannot be read.
+    fn show(&mut self, dir: PathBuf, select: Option<&str>) -> io::Result<()> {
+        let entries = self.list(&dir)?;
+        self.remembered.insert(self.dir.clone(), self.selected);
+        let selected = select
+            .and_then(|name| entries.iter().position(|entry| entry == name))
+            .or_else(|| self.remembered.get(&dir).copied())
+            .unwrap_or(0);
+        self.selected = selected.min(entries.len().saturating_sub(1));
+        self.dir = dir;
+        self.entries = entries;
+        self.view.offset = 0;
+        Ok(())
+    }
+
+    /// Opens the selected directory. Returns false if there is none.
+    pub fn enter(&mut self) -> io::Result<bool> {
+        let Some(path) = self.selected_path() else {
+            return Ok(false);
+        };
+        self.show(path, None)?;
+        Ok(true)
+    }
+
+    /// Goes up to the parent directory, selecting the one just left.
+    /// Returns false at the root.
+    pub fn leave(&mut self) -> io::Result<bool> {
+        if self.dir == self.root {
+            return Ok(false);
+        }
+        let Some(parent) = self.dir.parent().map(Path::to_path_buf) else {
+            return Ok(false);
+        };
+        let left = self.dir.file_name().map(|name| name.to_string_lossy().into_owned());
+        self.show(parent, left.as_deref())?;
+        Ok(true)
+    }
+
+    /// Moves the selection up.
+    pub fn up(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    /// Moves the selection down.
+    pub fn down(&mut self) {
+        if self.selected + 1 < self.entries.len() {
+            self.selected += 1;
+        }
+    }
+
+    /// Keeps the selection in view with `height` lines for the entries.
+    pub fn scroll(&mut self, height: u16) {
+        self.view.height = height;
+        self.view.scroll_to(&UniformRows(self.entries.len()), self.selected);
+    }
+
+    /// Returns the selected subdirectory, if the directory has any.
+    pub fn selected_path(&self) -> Option<PathBuf> {
+        self.entries.get(self.selected).map(|name| self.dir.join(name))
+    }
+
+    /// Returns the directory being shown.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the names of the subdirectories shown.
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    /// Returns the index of the selected entry.
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Returns the scroll position of the entries.
+    pub fn view(&self) -> Viewport {
+        self.view
+    }
+
+    /// Names the directory shown by its path below the root, starting with
+    /// the root's own name.
+    pub fn label(&self) -> String {
+        let root = self.root.file_name().map_or_else(
+            || self.root.display().to_string(),
+            |name| name.to_string_lossy().into_owned(),
+        );
+        match self.dir.strip_prefix(&self.root) {
+            Ok(rest) if !rest.as_os_str().is_empty() => {
+                format!("{}{}{}", root, std::path::MAIN_SEPARATOR, rest.display())
+            }
+            _ => root,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_browse_and_remember() {
+        let root = std::env::temp_dir().join(format!("juke_browser_{}", std::process::id()));
+        for dir in ["b", "A", "c/two", "c/one", ".hidden"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        fs::write(root.join("song.mp3"), b"").unwrap();
+
+        let options = ScanOptions { include_hidden: false, ..Default::default() };
+        let mut browser = Browser::open(&root, options, Collation::default()).unwrap();
+        assert_eq!(browser.entries(), ["A", "b", "c"]);
+        assert!(!browser.leave().unwrap());
+
+        browser.down();
+        browser.down();
+        browser.down();
+        assert_eq!(browser.selected_path(), Some(root.join("c")));
+        assert!(browser.enter().unwrap());
+        assert_eq!(browser.entries(), ["one", "two"]);
+        assert_eq!(browser.label(), format!("{}{}c", root.file_name().unwrap().to_string_lossy(), std::path::MAIN_SEPARATOR));
+        browser.down();
+
+        // Going up selects the directory left, and going back in restores its selection
+        assert!(browser.leave().unwrap());
+        assert_eq!(browser.selected(), 2);
+        assert!(browser.enter().unwrap());
+        assert_eq!(browser.selected_path(), Some(root.join("c").join("two")));
+
+        // A leaf directory has nothing to enter
+        assert!(browser.enter().unwrap());
+        assert_eq!(browser.selected_path(), None);
+        assert!(!browser.enter().unwrap());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
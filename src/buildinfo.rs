@@ -0,0 +1,55 @@
+//! Which build of juke is running, for bug reports.
+
+/// The crate version.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The commit built from, or "unknown" when git was not there to ask.
+pub const GIT_HASH: &str = env!("JUKE_GIT_HASH");
+
+/// The platform built for.
+pub const TARGET: &str = env!("JUKE_TARGET");
+
+/// Returns the versions Cargo.lock resolved the main dependencies to, as
+/// name and version.
+pub fn dependencies() -> Vec<(&'static str, &'static str)> {
+    env!("JUKE_DEPENDENCIES").split(',').filter_map(|package| package.split_once(' ')).collect()
+}
+
+/// Returns the optional Cargo features built in.
+pub fn features() -> Vec<&'static str> {
+    [
+        ("tui", cfg!(feature = "tui")),
+        ("musicbrainz", cfg!(feature = "musicbrainz")),
+        ("acoustid", cfg!(feature = "acoustid")),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| enabled.then_some(name))
+    .collect()
+}
+
+/// Describes the build on one line, such as
+/// `juke 1.0.0 (3f2c1a9b4e, x86_64-unknown-linux-gnu; features: tui)`.
+pub fn summary() -> String {
+    let features = features();
+    let features = if features.is_empty() { "none".to_string() } else { features.join(", ") };
+    format!("juke {} ({}, {}; features: {})", VERSION, GIT_HASH, TARGET, features)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        assert_eq!(VERSION, env!("CARGO_PKG_VERSION"));
+        let summary = summary();
+        assert!(summary.starts_with(&format!("juke {} (", env!("CARGO_PKG_VERSION"))));
+        assert!(summary.contains(TARGET));
+        assert!(!GIT_HASH.is_empty());
+        // The binary needs the terminal interface
+        assert!(features().contains(&"tui"));
+        let dependencies = dependencies();
+        assert!(dependencies.iter().any(|&(name, version)| name == "rodio" && version.starts_with("0.19.")));
+        assert!(dependencies.iter().all(|(_, version)| version.split('.').count() == 3));
+    }
+}
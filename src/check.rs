@@ -0,0 +1,267 @@
+//! Playlist validation without playback (`juke --check`).
+
+use crate::doctor;
+use crate::playlist::{self, Track};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+
+/// Kinds of problems found in a playlist entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProblemKind {
+    /// The file does not exist.
+    Missing,
+    /// The file exists but cannot be opened.
+    Unreadable,
+    /// The file opens but no decoder accepts it (`--deep` only).
+    Undecodable,
+    /// The entry has no duration, or a duration of zero.
+    ZeroDuration,
+    /// The same file appears earlier in the playlist.
+    Duplicate,
+}
+
+impl ProblemKind {
+    /// Returns the name used in reports.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProblemKind::Missing => "missing",
+            ProblemKind::Unreadable => "unreadable",
+            ProblemKind::Undecodable => "undecodable",
+            ProblemKind::ZeroDuration => "zero-duration",
+            ProblemKind::Duplicate => "duplicate",
+        }
+    }
+}
+
+/// A problem with one playlist entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    /// Position of the entry in the playlist, starting at 1.
+    pub entry: usize,
+    pub path: PathBuf,
+    pub kind: ProblemKind,
+    pub detail: Option<String>,
+}
+
+/// The result of checking a playlist.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub entries: usize,
+    pub deep: bool,
+    pub problems: Vec<Problem>,
+}
+
+impl Report {
+    /// Writes a human-readable report.
+    pub fn write_text(&self, out: &mut dyn Write) -> io::Result<()> {
+        if self.problems.is_empty() {
+            return writeln!(out, "No problems found in {} entries", self.entries);
+        }
+        let noun = if self.problems.len() == 1 { "problem" } else { "problems" };
+        writeln!(out, "Checked {} entries: {} {}", self.entries, self.problems.len(), noun)?;
+        let width = self.entries.to_string().len();
+        for problem in &self.problems {
+            write!(
+                out,
+                "  #{:<width$}  {:<13}  {}",
+                problem.entry,
+                problem.kind.name(),
+                problem.path.display(),
+                width = width
+            )?;
+            match &problem.detail {
+                Some(detail) => writeln!(out, ": {}", detail)?,
+                None => writeln!(out)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the report as JSON for scripts.
+    pub fn to_json(&self) -> Value {
+        let problems = self
+            .problems
+            .iter()
+            .map(|problem| {
+                json!({
+                    "entry": problem.entry,
+                    "path": problem.path.to_string_lossy().to_string(),
+                    "problem": problem.kind.name(),
+                    "detail": problem.detail.clone(),
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "entries": self.entries,
+            "deep": self.deep,
+            "problems": problems,
+        })
+    }
+}
+
+/// Checks every track in parallel. With `deep`, each file is also opened
+/// with a decoder, which is much slower.
+pub fn check(tracks: &[Track], deep: bool) -> Report {
+    let workers = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(tracks.len().max(1));
+    let next = AtomicUsize::new(0);
+    let (tx, rx) = mpsc::channel();
+
+    let mut results: Vec<(PathBuf, Option<Problem>)> = std::thread::scope(|s| {
+        for _ in 0..workers {
+            let tx = tx.clone();
+            let next = &next;
+            s.spawn(move || loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                let Some(track) = tracks.get(i) else {
+                    break;
+                };
+                let key = playlist::canonical_key(&track.path);
+                if tx.send((i, key, check_track(i + 1, track, deep))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(tx);
+
+        let mut results = vec![(PathBuf::new(), None); tracks.len()];
+        for (i, key, problem) in rx {
+            results[i] = (key, problem);
+        }
+        results
+    });
+
+    // Duplicates are found in playlist order, pointing at the first occurrence
+    let mut first_seen: HashMap<PathBuf, usize> = HashMap::new();
+    let mut problems = Vec::new();
+    for (i, (key, problem)) in results.iter_mut().enumerate() {
+        if let Some(problem) = problem.take() {
+            problems.push(problem);
+            continue;
+        }
+        match first_seen.get(key) {
+            Some(first) => problems.push(Problem {
+                entry: i + 1,
+                path: tracks[i].path.clone(),
+                kind: ProblemKind::Duplicate,
+                detail: Some(format!("same file as #{}", first)),
+            }),
+            None => {
+                first_seen.insert(std::mem::take(key), i + 1);
+            }
+        }
+    }
+
+    Report {
+        entries: tracks.len(),
+        deep,
+        problems,
+    }
+}
+
+/// Checks a single entry's file, returning its most serious problem.
+fn check_track(entry: usize, track: &Track, deep: bool) -> Option<Problem> {
+    let problem = |kind, detail| Problem {
+        entry,
+        path: track.path.clone(),
+        kind,
+        detail,
+    };
+
+    if let Err(e) = File::open(&track.path) {
+        return Some(if e.kind() == io::ErrorKind::NotFound {
+            problem(ProblemKind::Missing, None)
+        } else {
+            problem(ProblemKind::Unreadable, Some(e.to_string()))
+        });
+    }
+    if track.path.is_dir() {
+        return Some(problem(ProblemKind::Unreadable, Some("is a directory".to_string())));
+    }
+    if deep && let Err(e) = doctor::probe(&track.path) {
+        return Some(problem(ProblemKind::Undecodable, Some(e)));
+    }
+    match track.duration {
+        None => Some(problem(ProblemKind::ZeroDuration, Some("duration unknown".to_string()))),
+        Some(duration) if duration.is_zero() => Some(problem(ProblemKind::ZeroDuration, None)),
+        Some(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn track(path: PathBuf, secs: Option<u64>) -> Track {
+        Track {
+            duration: secs.map(Duration::from_secs),
+            ..Track::new(path)
+        }
+    }
+
+    #[test]
+    fn test_check_problems() {
+        let dir = std::env::temp_dir().join(format!("juke_check_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("good.mp3"), b"not really audio").unwrap();
+        std::fs::write(dir.join("silent.mp3"), b"").unwrap();
+
+        let tracks = vec![
+            track(dir.join("good.mp3"), Some(180)),
+            track(dir.join("missing.mp3"), Some(200)),
+            track(dir.join("silent.mp3"), Some(0)),
+            track(dir.join("../").join(dir.file_name().unwrap()).join("good.mp3"), Some(180)),
+        ];
+        let report = check(&tracks, false);
+        let deep = check(&tracks, true);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let kinds: Vec<_> = report.problems.iter().map(|p| (p.entry, p.kind)).collect();
+        assert_eq!(
+            kinds,
+            [(2, ProblemKind::Missing), (3, ProblemKind::ZeroDuration), (4, ProblemKind::Duplicate)]
+        );
+        assert_eq!(report.problems[2].detail.as_deref(), Some("same file as #1"));
+
+        // Decoding catches files that only look fine
+        assert_eq!(deep.problems[0].kind, ProblemKind::Undecodable);
+        assert_eq!(deep.problems[0].entry, 1);
+    }
+
+    #[test]
+    fn test_report_output() {
+        let report = Report {
+            entries: 12,
+            deep: false,
+            problems: vec![Problem {
+                entry: 3,
+                path: PathBuf::from("/music/a.mp3"),
+                kind: ProblemKind::Missing,
+                detail: None,
+            }],
+        };
+
+        let mut out = Vec::new();
+        report.write_text(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Checked 12 entries: 1 problem\n  #3   missing        /music/a.mp3\n"
+        );
+        assert_eq!(
+            report.to_json().to_string(),
+            r#"{"entries":12,"deep":false,"problems":[{"entry":3,"path":"/music/a.mp3","problem":"missing","detail":null}]}"#
+        );
+
+        let mut out = Vec::new();
+        Report { entries: 5, ..Default::default() }.write_text(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "No problems found in 5 entries\n");
+    }
+}
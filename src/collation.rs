@@ -0,0 +1,410 @@
+//! Locale-aware string comparison shared by every track ordering.
+
+use crate::discs;
+use crate::playlist::Track;
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Field used to order tracks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Path,
+    Title,
+    Artist,
+    Album,
+    /// Disc, then track number.
+    TrackNumber,
+    Duration,
+    /// A one-off shuffle of the list itself, unlike shuffle mode.
+    Random,
+}
+
+impl SortKey {
+    /// Every key, in the order sort menus list them.
+    pub const ALL: [SortKey; 7] = [
+        SortKey::Title,
+        SortKey::Artist,
+        SortKey::Album,
+        SortKey::TrackNumber,
+        SortKey::Duration,
+        SortKey::Path,
+        SortKey::Random,
+    ];
+
+    /// Returns the key of the key's name in the interface strings.
+    pub fn text_key(self) -> &'static str {
+        match self {
+            SortKey::Path => "sort.path",
+            SortKey::Title => "sort.title",
+            SortKey::Artist => "sort.artist",
+            SortKey::Album => "sort.album",
+            SortKey::TrackNumber => "sort.track_number",
+            SortKey::Duration => "sort.duration",
+            SortKey::Random => "sort.random",
+        }
+    }
+
+    /// Returns the name the key is written as in saved playlists.
+    pub fn name(self) -> &'static str {
+        match self {
+            SortKey::Path => "path",
+            SortKey::Title => "title",
+            SortKey::Artist => "artist",
+            SortKey::Album => "album",
+            SortKey::TrackNumber => "track",
+            SortKey::Duration => "duration",
+            SortKey::Random => "random",
+        }
+    }
+
+    /// Returns the key written as `name`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|key| key.name() == name)
+    }
+}
+
+/// Case- and accent-insensitive comparison with optional leading-article stripping.
+#[derive(Debug, Clone)]
+pub struct Collation {
+    /// Folded articles, e.g. "the", "a", "l'".
+    articles: Vec<String>,
+}
+
+impl Collation {
+    /// Creates a collation that ignores the given leading articles.
+    pub fn new<S: AsRef<str>>(articles: &[S]) -> Self {
+        Self {
+            articles: articles
+                .iter()
+                .map(|a| fold(a.as_ref().trim()))
+                .filter(|a| !a.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Returns the sort key for a string: folded, with a leading article removed.
+    pub fn key(&self, text: &str) -> String {
+        let folded = fold(text.trim());
+        for article in &self.articles {
+            // Elided articles like "l'" attach directly to the next word
+            let rest = if article.ends_with('\'') {
+                folded.strip_prefix(article.as_str())
+            } else {
+                folded
+                    .strip_prefix(article.as_str())
+                    .and_then(|r| r.strip_prefix(' '))
+            };
+            if let Some(rest) = rest {
+                let rest = rest.trim_start();
+                if !rest.is_empty() {
+                    return rest.to_string();
+                }
+            }
+        }
+        folded
+    }
+
+    /// Compares two strings by their sort keys.
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        self.key(a).cmp(&self.key(b))
+    }
+
+    /// Returns the sort key for a path: its components folded, then the path
+    /// itself to tell apart paths that fold the same.
+    ///
+    /// Articles are not stripped from file names, only folded.
+    pub fn path_key(&self, path: &Path) -> (Vec<String>, PathBuf) {
+        let parts = path.components().map(|part| fold(&part.as_os_str().to_string_lossy())).collect();
+        (parts, path.to_path_buf())
+    }
+
+    /// Compares two paths component by component.
+    pub fn compare_paths(&self, a: &Path, b: &Path) -> Ordering {
+        self.path_key(a).cmp(&self.path_key(b))
+    }
+
+    /// Returns what a track is sorted by for a sort key, folded once so
+    /// that sorting does not fold the same text again for every comparison.
+    pub fn track_key(&self, track: &Track, key: SortKey) -> TrackKey {
+        let text = match key {
+            SortKey::Title => Some(self.key(&track.display_name())),
+            SortKey::Artist => track.artist.as_deref().map(|artist| self.key(artist)),
+            SortKey::Album => track.album.as_deref().map(|album| self.key(discs::album_key(album))),
+            _ => None,
+        };
+        let number = match key {
+            SortKey::Album => Some((discs::disc_of(track).unwrap_or(1), track.track_number.unwrap_or(u32::MAX))),
+            SortKey::TrackNumber => track.track_number.map(|n| (discs::disc_of(track).unwrap_or(1), n)),
+            _ => None,
+        };
+        TrackKey { key, text, number, duration: track.duration, path: self.path_key(&track.path) }
+    }
+
+    /// Compares two tracks by a sort key, falling back to the path for a stable order.
+    ///
+    /// Descending order reverses the key but still places missing values last.
+    /// Albums are compared without their disc suffixes, and the tracks of one
+    /// album go by disc, then track number, in either direction.
+    /// [`SortKey::Random`] compares everything as equal.
+    pub fn compare_tracks(&self, a: &Track, b: &Track, key: SortKey, descending: bool) -> Ordering {
+        self.track_key(a, key).compare(&self.track_key(b, key), descending)
+    }
+
+    /// Sorts tracks as [`Collation::compare_tracks`] orders them, working
+    /// out each track's key once.
+    pub fn sort_tracks(&self, tracks: &mut Vec<Track>, key: SortKey, descending: bool) {
+        let mut keyed: Vec<(TrackKey, Track)> =
+            tracks.drain(..).map(|track| (self.track_key(&track, key), track)).collect();
+        keyed.sort_by(|(a, _), (b, _)| a.compare(b, descending));
+        tracks.extend(keyed.into_iter().map(|(_, track)| track));
+    }
+}
+
+/// What a track is sorted by for one [`SortKey`].
+#[derive(Debug, Clone)]
+pub struct TrackKey {
+    key: SortKey,
+    /// Title, artist or album, folded.
+    text: Option<String>,
+    /// Disc and track number.
+    number: Option<(u32, u32)>,
+    duration: Option<Duration>,
+    path: (Vec<String>, PathBuf),
+}
+
+impl TrackKey {
+    /// Compares with the key of another track, sorted the same way.
+    pub fn compare(&self, other: &TrackKey, descending: bool) -> Ordering {
+        let direct = |ordering: Ordering| if descending { ordering.reverse() } else { ordering };
+        let primary = match self.key {
+            SortKey::Path => return direct(self.path.cmp(&other.path)),
+            SortKey::Random => Ordering::Equal,
+            SortKey::Title | SortKey::Artist => missing_last(self.text.as_ref(), other.text.as_ref(), descending),
+            SortKey::Album => missing_last(self.text.as_ref(), other.text.as_ref(), descending)
+                .then_with(|| self.number.cmp(&other.number)),
+            SortKey::TrackNumber => missing_last(self.number, other.number, descending),
+            SortKey::Duration => missing_last(self.duration, other.duration, descending),
+        };
+        primary.then_with(|| self.path.cmp(&other.path))
+    }
+}
+
+/// Compares optional values, placing missing ones last in either direction.
+fn missing_last<T: Ord>(a: Option<T>, b: Option<T>, descending: bool) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) if descending => b.cmp(&a),
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+impl Default for Collation {
+    fn default() -> Self {
+        Self::new(&["the", "a", "an"])
+    }
+}
+
+/// Lowercases text and strips diacritics from Latin letters.
+pub fn fold(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars().flat_map(char::to_lowercase) {
+        match fold_char(c) {
+            Some(base) => out.push_str(base),
+            None => out.push(c),
+        }
+    }
+    out
+}
+
+/// Maps an accented or ligature Latin letter to its base form.
+fn fold_char(c: char) -> Option<&'static str> {
+    let base = match c {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => "a",
+        'ç' | 'ć' | 'ĉ' | 'ċ' | 'č' => "c",
+        'ď' | 'đ' | 'ð' => "d",
+        'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => "e",
+        'ĝ' | 'ğ' | 'ġ' | 'ģ' => "g",
+        'ĥ' | 'ħ' => "h",
+        'ì' | 'í' | 'î' | 'ï' | 'ĩ' | 'ī' | 'ĭ' | 'į' | 'ı' => "i",
+        'ĵ' => "j",
+        'ķ' => "k",
+        'ĺ' | 'ļ' | 'ľ' | 'ŀ' | 'ł' => "l",
+        'ñ' | 'ń' | 'ņ' | 'ň' => "n",
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ŏ' | 'ő' => "o",
+        'ŕ' | 'ŗ' | 'ř' => "r",
+        'ś' | 'ŝ' | 'ş' | 'š' => "s",
+        'ţ' | 'ť' | 'ŧ' => "t",
+        'ù' | 'ú' | 'û' | 'ü' | 'ũ' | 'ū' | 'ŭ' | 'ů' | 'ű' | 'ų' => "u",
+        'ŵ' => "w",
+        'ý' | 'ÿ' | 'ŷ' => "y",
+        'ź' | 'ż' | 'ž' => "z",
+        'ß' => "ss",
+        'æ' => "ae",
+        'œ' => "oe",
+        'þ' => "th",
+        // Typographic apostrophes compare like ASCII ones
+        '\u{2019}' | '\u{2018}' => "'",
+        _ => return None,
+    };
+    Some(base)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_fold() {
+        let cases = [
+            ("Élodie", "elodie"),
+            ("MÖTLEY CRÜE", "motley crue"),
+            ("Straße", "strasse"),
+            ("Sigur Rós", "sigur ros"),
+            ("Björk", "bjork"),
+            ("Œuvre", "oeuvre"),
+            ("東京", "東京"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(fold(input), expected, "folding {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_article_stripping() {
+        let collation = Collation::new(&["The", "A", "An", "L'"]);
+        let cases = [
+            ("The Beatles", "beatles"),
+            ("the who", "who"),
+            ("A Tribe Called Quest", "tribe called quest"),
+            ("An Horse", "horse"),
+            ("L'Impératrice", "imperatrice"),
+            ("L\u{2019}Impératrice", "imperatrice"),
+            // Not an article: no separating space
+            ("Theatre", "theatre"),
+            ("Abba", "abba"),
+            // A bare article is kept as the whole key
+            ("The", "the"),
+            ("  The  Band ", "band"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(collation.key(input), expected, "key for {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_no_articles() {
+        let collation = Collation::new::<&str>(&[]);
+        assert_eq!(collation.key("The Beatles"), "the beatles");
+    }
+
+    #[test]
+    fn test_accented_names_interleave() {
+        let collation = Collation::default();
+        let mut names = vec!["Zebra", "Élodie", "eagle", "The Beatles", "Edith", "abba"];
+        names.sort_by(|a, b| collation.compare(a, b));
+        assert_eq!(names, ["abba", "The Beatles", "eagle", "Edith", "Élodie", "Zebra"]);
+    }
+
+    #[test]
+    fn test_compare_paths() {
+        let collation = Collation::default();
+        let mut paths = vec![
+            PathBuf::from("music/Zappa/a.mp3"),
+            PathBuf::from("music/élan/b.mp3"),
+            PathBuf::from("music/Eagles/c.mp3"),
+            PathBuf::from("music/abba.mp3"),
+        ];
+        paths.sort_by(|a, b| collation.compare_paths(a, b));
+        assert_eq!(
+            paths,
+            [
+                PathBuf::from("music/abba.mp3"),
+                PathBuf::from("music/Eagles/c.mp3"),
+                PathBuf::from("music/élan/b.mp3"),
+                PathBuf::from("music/Zappa/a.mp3"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compare_tracks_stable_tiebreak() {
+        let collation = Collation::default();
+        let mut a = Track::new(PathBuf::from("b.mp3"));
+        let mut b = Track::new(PathBuf::from("a.mp3"));
+        a.artist = Some("The Same".to_string());
+        b.artist = Some("same".to_string());
+
+        // Equal artists fall back to path order
+        assert_eq!(collation.compare_tracks(&a, &b, SortKey::Artist, false), Ordering::Greater);
+
+        // Missing artists sort last, also in descending order
+        b.artist = None;
+        assert_eq!(collation.compare_tracks(&a, &b, SortKey::Artist, false), Ordering::Less);
+        assert_eq!(collation.compare_tracks(&a, &b, SortKey::Artist, true), Ordering::Less);
+    }
+
+    #[test]
+    fn test_compare_tracks_by_number_and_duration() {
+        let collation = Collation::default();
+        let track = |path: &str, disc, number, secs: Option<u64>| {
+            let mut track = Track::new(PathBuf::from(path));
+            track.disc_number = disc;
+            track.track_number = number;
+            track.duration = secs.map(std::time::Duration::from_secs);
+            track
+        };
+        let mut tracks = [
+            track("a.mp3", Some(2), Some(1), Some(300)),
+            track("b.mp3", None, None, None),
+            track("c.mp3", Some(1), Some(2), Some(100)),
+            track("d.mp3", None, Some(1), Some(200)),
+        ];
+        let names = |tracks: &[Track]| tracks.iter().map(|t| t.display_name()).collect::<Vec<_>>();
+
+        tracks.sort_by(|a, b| collation.compare_tracks(a, b, SortKey::TrackNumber, false));
+        assert_eq!(names(&tracks), ["d.mp3", "c.mp3", "a.mp3", "b.mp3"]);
+        tracks.sort_by(|a, b| collation.compare_tracks(a, b, SortKey::Duration, true));
+        assert_eq!(names(&tracks), ["a.mp3", "d.mp3", "c.mp3", "b.mp3"]);
+        tracks.sort_by(|a, b| collation.compare_tracks(a, b, SortKey::Path, true));
+        assert_eq!(names(&tracks), ["d.mp3", "c.mp3", "b.mp3", "a.mp3"]);
+    }
+
+    #[test]
+    fn test_compare_tracks_keeps_discs_together() {
+        let collation = Collation::default();
+        let track = |path: &str, album: &str, disc, number| {
+            let mut track = Track::new(PathBuf::from(path));
+            track.album = Some(album.to_string());
+            track.disc_number = disc;
+            track.track_number = Some(number);
+            track
+        };
+        let mut tracks = [
+            track("z.mp3", "Live (Disc 2)", None, 1),
+            track("y.mp3", "Live (Disc 1)", None, 2),
+            track("x.mp3", "Live (Disc 1)", None, 1),
+            track("w.mp3", "Alive", Some(2), 1),
+            track("v.mp3", "Alive", Some(1), 9),
+        ];
+        let names = |tracks: &[Track]| tracks.iter().map(|t| t.display_name()).collect::<Vec<_>>();
+
+        tracks.sort_by(|a, b| collation.compare_tracks(a, b, SortKey::Album, false));
+        assert_eq!(names(&tracks), ["v.mp3", "w.mp3", "x.mp3", "y.mp3", "z.mp3"]);
+        // Keys worked out once sort the same
+        let mut sorted = tracks.to_vec();
+        tracks.reverse();
+        collation.sort_tracks(&mut sorted, SortKey::Album, true);
+        tracks.sort_by(|a, b| collation.compare_tracks(a, b, SortKey::Album, true));
+        assert_eq!(names(&sorted), names(&tracks));
+        // Albums reverse, their tracks stay in order
+        tracks.sort_by(|a, b| collation.compare_tracks(a, b, SortKey::Album, true));
+        assert_eq!(names(&tracks), ["x.mp3", "y.mp3", "z.mp3", "v.mp3", "w.mp3"]);
+        // A disc named in the album counts like the tag
+        tracks.sort_by(|a, b| collation.compare_tracks(a, b, SortKey::TrackNumber, false));
+        assert_eq!(names(&tracks), ["x.mp3", "y.mp3", "v.mp3", "w.mp3", "z.mp3"]);
+    }
+}
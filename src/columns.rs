@@ -0,0 +1,221 @@
+//! The track list's columns, chosen and ordered with
+//! `ui.track_list_columns`, and how a row's width is shared between them.
+//!
+//! The number, duration and date columns are as wide as what they show.
+//! The title, artist and album stretch over the rest, the title twice as
+//! much as the others. When even their minimum widths do not fit, the
+//! album and artist are left out, last named first; the title always stays.
+
+/// The layout used when `ui.track_list_columns` is unset.
+pub const DEFAULT: &str = "num,title,duration";
+
+/// A column of the track list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    Number,
+    Title,
+    Artist,
+    Album,
+    Duration,
+    /// When a recently added track was first seen, added on its own for
+    /// recently added playlists.
+    Added,
+}
+
+impl Column {
+    /// Returns the column a name in `ui.track_list_columns` stands for.
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "num" => Some(Column::Number),
+            "title" => Some(Column::Title),
+            "artist" => Some(Column::Artist),
+            "album" => Some(Column::Album),
+            "duration" => Some(Column::Duration),
+            _ => None,
+        }
+    }
+
+    /// Width of a column that does not stretch, for a list of `rows` tracks.
+    fn fixed_width(self, rows: usize) -> Option<u16> {
+        match self {
+            // "123." and wider for longer lists
+            Column::Number => Some(rows.to_string().len().max(3) as u16 + 1),
+            // "[03:21]"
+            Column::Duration => Some(7),
+            // "2024-05-01"
+            Column::Added => Some(10),
+            Column::Title | Column::Artist | Column::Album => None,
+        }
+    }
+
+    /// Narrowest a stretching column gets before it is left out.
+    fn min_width(self) -> u16 {
+        match self {
+            Column::Title => 10,
+            _ => 8,
+        }
+    }
+
+    /// Share of the spare width a stretching column takes.
+    fn weight(self) -> u16 {
+        match self {
+            Column::Title => 2,
+            _ => 1,
+        }
+    }
+}
+
+/// Where a column starts in a row, and how wide it is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    pub column: Column,
+    pub x: u16,
+    pub width: u16,
+}
+
+/// The columns of the track list, in order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    columns: Vec<Column>,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self::parse(DEFAULT).0
+    }
+}
+
+impl Layout {
+    /// Parses a comma-separated list of column names, with a warning for
+    /// each name skipped. A layout without the title gets it first.
+    pub fn parse(spec: &str) -> (Self, Vec<String>) {
+        let mut columns = Vec::new();
+        let mut warnings = Vec::new();
+        for name in spec.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            match Column::from_name(&name.to_lowercase()) {
+                Some(column) if columns.contains(&column) => {
+                    warnings.push(format!("track_list_columns names {} twice, skipping it", name));
+                }
+                Some(column) => columns.push(column),
+                None => warnings.push(format!("Unknown track list column {:?}, skipping it", name)),
+            }
+        }
+        if !columns.contains(&Column::Title) {
+            warnings.push("track_list_columns must include title, adding it".to_string());
+            let at = usize::from(columns.first() == Some(&Column::Number));
+            columns.insert(at, Column::Title);
+        }
+        (Self { columns }, warnings)
+    }
+
+    /// Returns the layout with the date column added at the end, for
+    /// recently added playlists.
+    pub fn with_added(&self) -> Self {
+        let mut columns = self.columns.clone();
+        columns.push(Column::Added);
+        Self { columns }
+    }
+
+    /// Shares out `width` columns between the cells of a row in a list of
+    /// `rows` tracks. Cells are two apart, or one after the number.
+    pub fn cells(&self, width: u16, rows: usize) -> Vec<Cell> {
+        let mut columns = self.columns.clone();
+        let gap = |columns: &[Column], index: usize| match index {
+            0 => 0,
+            _ if columns[index - 1] == Column::Number => 1,
+            _ => 2,
+        };
+        let needed = |columns: &[Column]| -> u16 {
+            (0..columns.len())
+                .map(|index| gap(columns, index) + columns[index].fixed_width(rows).unwrap_or(columns[index].min_width()))
+                .sum()
+        };
+        while needed(&columns) > width
+            && let Some(index) = columns.iter().rposition(|&c| matches!(c, Column::Artist | Column::Album))
+        {
+            columns.remove(index);
+        }
+
+        let spare = width.saturating_sub(needed(&columns));
+        let stretching = || columns.iter().filter(|column| column.fixed_width(rows).is_none());
+        let weights: u16 = stretching().map(|column| column.weight()).sum();
+        let mut left_over = spare - stretching().map(|column| spare * column.weight() / weights).sum::<u16>();
+        let mut cells = Vec::with_capacity(columns.len());
+        let mut x = 0;
+        for (index, &column) in columns.iter().enumerate() {
+            x += gap(&columns, index);
+            let width = match column.fixed_width(rows) {
+                Some(fixed) => fixed,
+                None if column == Column::Title => {
+                    let extra = std::mem::take(&mut left_over);
+                    // Squeezed below its minimum when nothing else is left to give
+                    let short = needed(&columns).saturating_sub(width);
+                    (column.min_width() + spare * column.weight() / weights + extra).saturating_sub(short)
+                }
+                None => column.min_width() + spare * column.weight() / weights,
+            };
+            cells.push(Cell { column, x, width });
+            x += width;
+        }
+        cells
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bounds(spec: &str, width: u16, rows: usize) -> Vec<(Column, u16, u16)> {
+        let (layout, warnings) = Layout::parse(spec);
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        layout.cells(width, rows).into_iter().map(|cell| (cell.column, cell.x, cell.width)).collect()
+    }
+
+    #[test]
+    fn test_parse_skips_unknown_and_requires_title() {
+        let (layout, warnings) = Layout::parse("num, Duration,genre,num");
+        assert_eq!(layout.columns, [Column::Number, Column::Title, Column::Duration]);
+        assert_eq!(warnings.len(), 3, "{:?}", warnings);
+        assert!(warnings[0].contains("\"genre\""));
+        assert_eq!(Layout::default().columns, [Column::Number, Column::Title, Column::Duration]);
+    }
+
+    #[test]
+    fn test_cells_share_the_width() {
+        // 4 + 1 + title + 2 + 7: the title takes the rest
+        assert_eq!(
+            bounds("num,title,duration", 60, 100),
+            [(Column::Number, 0, 4), (Column::Title, 5, 46), (Column::Duration, 53, 7)]
+        );
+        // 36 over the minimums: half to the title, a quarter each to the
+        // artist and album
+        assert_eq!(
+            bounds("num,title,artist,album,duration", 80, 100),
+            [
+                (Column::Number, 0, 4),
+                (Column::Title, 5, 28),
+                (Column::Artist, 35, 17),
+                (Column::Album, 54, 17),
+                (Column::Duration, 73, 7),
+            ]
+        );
+        // What does not divide evenly goes to the title
+        assert_eq!(bounds("title,artist", 33, 10), [(Column::Title, 0, 19), (Column::Artist, 21, 12)]);
+        // Longer lists get a wider number
+        assert_eq!(bounds("title,num", 30, 12_345)[1], (Column::Number, 24, 6));
+    }
+
+    #[test]
+    fn test_narrow_rows_leave_out_artist_and_album() {
+        // 4 + 1 + 10 + 2 + 8 + 2 + 8 + 2 + 7 = 44 needed
+        let columns: Vec<Column> =
+            bounds("num,title,artist,album,duration", 40, 10).into_iter().map(|(column, _, _)| column).collect();
+        assert_eq!(columns, [Column::Number, Column::Title, Column::Artist, Column::Duration]);
+        assert_eq!(
+            bounds("num,title,artist,album,duration", 28, 10),
+            [(Column::Number, 0, 4), (Column::Title, 5, 14), (Column::Duration, 21, 7)]
+        );
+        // Too narrow even for the title's minimum
+        assert_eq!(bounds("num,title,duration", 20, 10)[1], (Column::Title, 5, 6));
+    }
+}
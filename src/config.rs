@@ -1,35 +1,482 @@
 //! Configuration file loading and management.
 
+use crate::filter::{self, DEFAULT_EXTENSIONS};
+use crate::trim::TrimRule;
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+/// Maximum pre-amp boost or cut in dB.
+pub const PREAMP_LIMIT_DB: f32 = 15.0;
+
+/// Pre-amp level above which output may clip without a limiter.
+pub const PREAMP_CLIP_WARNING_DB: f32 = 6.0;
+
+/// Scan depth above which the setting is probably a mistake.
+const MAX_DEPTH_WARNING: usize = 64;
 
 /// Main configuration structure.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub playback: PlaybackConfig,
+    #[serde(default)]
+    pub ui: UiConfig,
+    #[serde(default)]
+    pub playlist: PlaylistConfig,
+    #[serde(default)]
+    pub sort: SortConfig,
+    #[serde(default)]
+    pub paths: PathsConfig,
+    #[serde(default)]
+    pub theme: ThemeConfig,
+    #[serde(default)]
+    pub visualizer: VisualizerConfig,
+    #[serde(default)]
+    pub resume: ResumeConfig,
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
+    #[serde(default)]
+    pub remote: RemoteConfig,
+    #[serde(default)]
+    pub lookup: LookupConfig,
+    #[serde(default)]
+    pub integrations: IntegrationsConfig,
     pub keys: KeyConfig,
 }
 
 /// Playback configuration.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct PlaybackConfig {
     /// Seek step in seconds.
     pub seek_step: u32,
+    /// How far the replay key jumps back, in seconds.
+    pub replay_secs: u32,
+    /// Apply ReplayGain from tags or the loudness cache.
+    pub replaygain: bool,
+    /// Global pre-amp in dB (-15 to +15).
+    pub preamp_db: f32,
+    /// Output volume in percent (0-100).
+    pub volume: u8,
+    /// Output device, matched case-insensitively against device names.
+    pub device: Option<String>,
+    /// Stay paused after the system wakes from sleep instead of continuing.
+    pub pause_on_suspend: bool,
+    /// Keep pausing after every track once "stop after current" is on,
+    /// instead of only after the next one.
+    pub stop_after_latched: bool,
+    /// Limit peaks when volume, pre-amp, and ReplayGain add up to more
+    /// than unity, instead of letting them clip.
+    pub limiter: bool,
+    /// How quickly the limiter lets go after a peak, in milliseconds.
+    pub limiter_release_ms: u32,
+    /// Memory for keeping recently played files decoded, in megabytes.
+    pub decoded_cache_mb: u32,
+    /// How long switching tracks by hand fades the old track out and the
+    /// new one in, in milliseconds; 0 cuts straight over.
+    pub track_change_fade_ms: u32,
+    /// How long the first playback after launch fades in from silence, in
+    /// seconds; 0 starts at full volume.
+    pub startup_fade_secs: u32,
+    /// Which track changes get the next track decoded ahead of time.
+    pub gapless: Gapless,
+    /// Whether the next track is decoded ahead of time at all; off saves
+    /// the memory it takes.
+    pub preload: bool,
+}
+
+/// Display configuration.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UiConfig {
+    /// Prefix track names with their disc and track number.
+    pub show_track_numbers: bool,
+    /// Render without colors.
+    pub no_color: bool,
+    /// Use plain ASCII for the progress bar and markers.
+    pub ascii_only: bool,
+    /// Characters overriding the progress bar and marker glyphs.
+    pub glyphs: GlyphsConfig,
+    /// Language of the interface, loaded from `languages/<language>.toml` (English if unset).
+    pub language: Option<String>,
+    /// Full-screen interface or plain line-by-line output.
+    pub mode: InterfaceMode,
+    /// Redraw at most twice a second and hide the visualizer, for slow
+    /// connections. On over SSH when unset.
+    pub low_bandwidth: Option<bool>,
+    /// Write a line for a speech synthesizer on every track change.
+    pub announce_tracks: bool,
+    /// FIFO or file the announcements are written to; standard error when
+    /// unset, if it is redirected.
+    pub announce_path: Option<PathBuf>,
+    /// Announcement text, using the placeholders described in [`crate::template`].
+    pub announce_template: Option<String>,
+    /// Cue on errors and at the end of the playlist.
+    pub bell: Bell,
+    /// Track list columns in order, comma-separated, from "num", "title",
+    /// "artist", "album" and "duration"; "num,title,duration" when unset.
+    pub track_list_columns: Option<String>,
+}
+
+impl UiConfig {
+    /// Returns whether to save bandwidth, given whether juke runs over SSH.
+    pub fn low_bandwidth(&self, over_ssh: bool) -> bool {
+        self.low_bandwidth.unwrap_or(over_ssh)
+    }
+}
+
+/// How the interface is drawn.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InterfaceMode {
+    /// Full-screen terminal interface.
+    #[default]
+    Full,
+    /// One line of scrolling text per change, for screen readers.
+    Plain,
+}
+
+/// Which terminal background the default theme is chosen for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Background {
+    /// Ask the terminal, then fall back to `COLORFGBG`, then to dark.
+    #[default]
+    Auto,
+    Dark,
+    Light,
+}
+
+/// How juke gets attention on errors and at the end of the playlist.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Bell {
+    #[default]
+    Off,
+    /// The terminal's bell.
+    Audible,
+    /// The status line flashes in inverse video.
+    Visual,
+}
+
+/// When a track that plays to its end runs straight into the next.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Gapless {
+    /// Before every track.
+    Always,
+    /// Only before the next track of the same album.
+    #[default]
+    Album,
+    /// Never; each track is opened when the one before ends.
+    Never,
+}
+
+/// Glyph overrides; each must be a single one-column character.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GlyphsConfig {
+    pub progress_filled: Option<String>,
+    pub progress_empty: Option<String>,
+    pub playing_marker: Option<String>,
+    pub selected_marker: Option<String>,
+    pub queue_badge: Option<String>,
+    pub seek_marker: Option<String>,
+    pub unplayable_marker: Option<String>,
+    pub renamed_marker: Option<String>,
+}
+
+/// Playlist loading configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlaylistConfig {
+    /// Fill missing artist/album/year tags from the directory layout.
+    pub infer_tags_from_path: bool,
+    /// Delete files outright instead of moving them to the trash.
+    pub delete_permanently: bool,
+    /// File extensions picked up when scanning directories.
+    pub extensions: Vec<String>,
+    /// Globs matched against full paths; matching files and directories are skipped.
+    pub exclude_patterns: Vec<String>,
+    /// Deepest directory level scanned, the top directory being 1 (0 = unlimited).
+    pub max_depth: usize,
+    /// Follow symbolic links while scanning.
+    pub follow_symlinks: bool,
+    /// Scan files and directories whose names start with a dot.
+    pub include_hidden: bool,
+    /// Shuffle often-skipped tracks toward the end.
+    pub smart_shuffle: bool,
+    /// Go back to the playlist after playing a selection of search results,
+    /// instead of stopping.
+    pub continue_after_selection: bool,
+    /// Scan without reading tags, leaving them for later. Unset, only
+    /// directories on network mounts are scanned fast.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fast_scan: Option<bool>,
+    /// Offer to save search results played with Ctrl+Enter as a playlist.
+    pub save_search_results: bool,
+    /// Record shuffle, repeat, volume, and sort order in `#JUKE:` lines of
+    /// saved playlists.
+    pub save_settings: bool,
+    /// Measure the length of every MP3 played by counting its frames,
+    /// rather than only those that end far from the length they claimed.
+    pub accurate_duration: bool,
+    /// Intros and outros skipped in the files of matching directories.
+    pub trim: Vec<TrimRule>,
+}
+
+impl Default for PlaylistConfig {
+    fn default() -> Self {
+        Self {
+            infer_tags_from_path: false,
+            delete_permanently: false,
+            extensions: DEFAULT_EXTENSIONS.iter().map(|ext| ext.to_string()).collect(),
+            exclude_patterns: Vec::new(),
+            max_depth: 0,
+            follow_symlinks: true,
+            include_hidden: true,
+            smart_shuffle: false,
+            continue_after_selection: false,
+            fast_scan: None,
+            save_search_results: true,
+            save_settings: false,
+            accurate_duration: false,
+            trim: Vec::new(),
+        }
+    }
+}
+
+/// Sorting configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SortConfig {
+    /// Leading articles ignored when sorting by artist or title.
+    pub articles: Vec<String>,
+}
+
+impl Default for SortConfig {
+    fn default() -> Self {
+        Self {
+            articles: vec!["The".to_string(), "A".to_string(), "An".to_string()],
+        }
+    }
+}
+
+/// File locations.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PathsConfig {
+    /// Directory for caches and saved state (defaults to the platform data directory).
+    pub state_dir: Option<PathBuf>,
+    /// Music played when juke is started without a path and there is no
+    /// session to resume (defaults to the current directory).
+    pub music_dir: Option<PathBuf>,
+    /// Where search results are saved as playlists (defaults to
+    /// `~/playlists`).
+    pub playlists_dir: Option<PathBuf>,
+}
+
+/// Session resume configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResumeConfig {
+    /// Save the session at exit and restore it at startup.
+    pub enabled: bool,
+    /// Restore the position within the track.
+    pub restore_position: bool,
+    /// Restore the play queue.
+    pub restore_queue: bool,
+    /// Session file location (defaults to `session.toml` in the state directory).
+    pub state_path: Option<PathBuf>,
+}
+
+impl Default for ResumeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            restore_position: true,
+            restore_queue: true,
+            state_path: None,
+        }
+    }
+}
+
+/// Remote control configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RemoteConfig {
+    /// Accept commands from `juke --send` over a local socket or named pipe.
+    pub enabled: bool,
+}
+
+impl Default for RemoteConfig {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+/// Desktop notification configuration.
+///
+/// Templates use the placeholders described in [`crate::template`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationsConfig {
+    /// Show desktop notifications.
+    pub enabled: bool,
+    /// Notification title.
+    pub summary_template: String,
+    /// Notification text.
+    pub body_template: String,
+    /// Notify when a new track starts.
+    pub on_track_change: bool,
+    /// Notify when playback is paused.
+    pub on_pause: bool,
+    /// Notify when the last track finishes.
+    pub on_playlist_end: bool,
+    /// Only notify while the terminal juke runs in is not focused.
+    pub only_when_unfocused: bool,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            summary_template: "%title".to_string(),
+            body_template: "%artist – %album".to_string(),
+            on_track_change: true,
+            on_pause: false,
+            on_playlist_end: true,
+            only_when_unfocused: false,
+        }
+    }
+}
+
+/// Protocols for controlling juke from other programs.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IntegrationsConfig {
+    /// Accept MPD clients on this port of 127.0.0.1 (6600 is MPD's usual port).
+    pub mpd_port: Option<u16>,
+    /// Append every listen to this file in the `.scrobbler.log` format.
+    pub listen_log: Option<PathBuf>,
+}
+
+/// MusicBrainz lookup of missing tags.
+///
+/// Requires a build with the `musicbrainz` feature; otherwise it is ignored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LookupConfig {
+    /// Look up tracks with missing tags in the background while playing.
+    pub enabled: bool,
+    /// Lowest MusicBrainz match score (0-100) accepted.
+    pub min_score: u8,
+    /// AcoustID API key for fingerprinting untagged files (`acoustid` feature).
+    pub acoustid_api_key: Option<String>,
+}
+
+impl Default for LookupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_score: 90,
+            acoustid_api_key: None,
+        }
+    }
 }
 
-/// Keybinding configuration.
+/// Visualizer configuration.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VisualizerConfig {
+    /// Show the bar visualizer.
+    pub enabled: bool,
+    /// Frames per second (5-60).
+    pub fps: u32,
+    /// How fast bars fall when the signal drops (0.0 holds, 1.0 drops instantly).
+    pub decay: f32,
+    /// Where the bar levels come from.
+    pub source: BarSource,
+    /// Bars drawn.
+    pub bars: usize,
+}
+
+/// Where the visualizer's bar levels come from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BarSource {
+    /// The audio's frequency bands, bass on the left.
+    #[default]
+    Auto,
+    /// The audio's loudness over the last moments, newest on the right.
+    Loudness,
+    /// Simulated levels, which do not follow the audio.
+    Simulated,
+}
+
+impl Default for VisualizerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            fps: 30,
+            decay: 0.5,
+            source: BarSource::default(),
+            bars: 12,
+        }
+    }
+}
+
+/// Color theme configuration.
+///
+/// Colors are names ("cyan", "dark gray"), hex ("#88C0D0"), or 256-color indexes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThemeConfig {
+    /// Built-in preset the colors below are applied on top of.
+    pub preset: String,
+    /// Background the "default" preset is picked for.
+    pub background: Background,
+    pub accent: Option<String>,
+    pub status: Option<String>,
+    pub highlight: Option<String>,
+    pub secondary: Option<String>,
+    pub dim: Option<String>,
+    pub error: Option<String>,
+    pub selection_fg: Option<String>,
+    pub selection_bg: Option<String>,
+}
+
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        Self {
+            preset: "default".to_string(),
+            background: Background::Auto,
+            accent: None,
+            status: None,
+            highlight: None,
+            secondary: None,
+            dim: None,
+            error: None,
+            selection_fg: None,
+            selection_bg: None,
+        }
+    }
+}
+
+/// Keybinding configuration. Each replaces the keys of one action; the
+/// defaults are the keys the action has without a config.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct KeyConfig {
     pub play_pause: KeyBinding,
     pub next: KeyBinding,
     pub prev: KeyBinding,
     pub seek_forward: KeyBinding,
     pub seek_back: KeyBinding,
+    pub replay: KeyBinding,
     pub shuffle: KeyBinding,
     pub repeat: KeyBinding,
     pub track_list: KeyBinding,
-    pub search: KeyBinding,
     pub help: KeyBinding,
     pub quit: KeyBinding,
 }
@@ -44,7 +491,6 @@ pub enum KeyBinding {
 
 impl KeyBinding {
     /// Returns all key strings for this binding.
-    #[allow(dead_code)]
     pub fn keys(&self) -> Vec<&str> {
         match self {
             KeyBinding::Single(key) => vec![key.as_str()],
@@ -63,6 +509,17 @@ impl Default for Config {
     fn default() -> Self {
         Self {
             playback: PlaybackConfig::default(),
+            ui: UiConfig::default(),
+            playlist: PlaylistConfig::default(),
+            sort: SortConfig::default(),
+            paths: PathsConfig::default(),
+            theme: ThemeConfig::default(),
+            visualizer: VisualizerConfig::default(),
+            resume: ResumeConfig::default(),
+            notifications: NotificationsConfig::default(),
+            remote: RemoteConfig::default(),
+            lookup: LookupConfig::default(),
+            integrations: IntegrationsConfig::default(),
             keys: KeyConfig::default(),
         }
     }
@@ -70,7 +527,23 @@ impl Default for Config {
 
 impl Default for PlaybackConfig {
     fn default() -> Self {
-        Self { seek_step: 10 }
+        Self {
+            seek_step: 10,
+            replay_secs: 10,
+            replaygain: true,
+            preamp_db: 0.0,
+            volume: 100,
+            device: None,
+            pause_on_suspend: true,
+            stop_after_latched: false,
+            limiter: true,
+            limiter_release_ms: 150,
+            decoded_cache_mb: 64,
+            track_change_fade_ms: 0,
+            startup_fade_secs: 0,
+            gapless: Gapless::default(),
+            preload: true,
+        }
     }
 }
 
@@ -82,12 +555,12 @@ impl Default for KeyConfig {
             prev: KeyBinding::Multiple(vec!["p".to_string(), "Left".to_string()]),
             seek_forward: KeyBinding::Single("Shift+Right".to_string()),
             seek_back: KeyBinding::Single("Shift+Left".to_string()),
-            shuffle: KeyBinding::Single("S".to_string()),
+            replay: KeyBinding::Single(",".to_string()),
+            shuffle: KeyBinding::Single("s".to_string()),
             repeat: KeyBinding::Single("r".to_string()),
             track_list: KeyBinding::Single("t".to_string()),
-            search: KeyBinding::Single("/".to_string()),
-            help: KeyBinding::Multiple(vec!["?".to_string(), "h".to_string()]),
-            quit: KeyBinding::Multiple(vec!["q".to_string(), "Esc".to_string()]),
+            help: KeyBinding::Single("?".to_string()),
+            quit: KeyBinding::Multiple(vec!["Esc".to_string(), "q".to_string()]),
         }
     }
 }
@@ -106,54 +579,60 @@ impl Config {
         })
     }
 
-    /// Loads the configuration from the config file.
+    /// Loads the configuration from a config file, with warnings about
+    /// anything that had to be changed or left out.
     ///
     /// If the file doesn't exist, creates it with default values.
-    /// If the file is invalid, returns the default config and prints a warning.
-    pub fn load() -> Self {
-        let path = match Self::config_path() {
-            Some(p) => p,
-            None => {
-                eprintln!("Warning: Could not determine config directory, using defaults");
-                return Self::default();
-            }
-        };
-
+    /// If the file is invalid, returns the default config and a warning.
+    pub fn load_from(path: &Path) -> (Self, Vec<String>) {
         // If config doesn't exist, create it with defaults
         if !path.exists() {
             let config = Self::default();
-            if let Err(e) = config.save(&path) {
-                eprintln!("Warning: Could not create default config file: {}", e);
-            }
-            return config;
+            let warnings = match config.save(path) {
+                Ok(()) => Vec::new(),
+                Err(e) => vec![format!("Could not create default config file: {}", e)],
+            };
+            return (config, warnings);
         }
 
         // Load and parse config
-        match fs::read_to_string(&path) {
-            Ok(contents) => match toml::from_str::<Config>(&contents) {
-                Ok(mut config) => {
-                    config.validate();
-                    config
-                }
-                Err(e) => {
-                    eprintln!(
-                        "Warning: Could not parse config file at {:?}: {}",
-                        path, e
-                    );
-                    eprintln!("Using default configuration");
-                    Self::default()
-                }
+        match fs::read_to_string(path) {
+            Ok(contents) => match Self::parse(&contents) {
+                Ok(parsed) => parsed,
+                Err(e) => (
+                    Self::default(),
+                    vec![format!("Could not parse config file at {:?}: {}, using default configuration", path, e)],
+                ),
             },
-            Err(e) => {
-                eprintln!("Warning: Could not read config file at {:?}: {}", path, e);
-                eprintln!("Using default configuration");
-                Self::default()
-            }
+            Err(e) => (
+                Self::default(),
+                vec![format!("Could not read config file at {:?}: {}, using default configuration", path, e)],
+            ),
         }
     }
 
+    /// Parses a config file's contents, bringing out-of-range values back
+    /// into range with a warning for each.
+    ///
+    /// ```
+    /// use juke::config::Config;
+    ///
+    /// let mut config = Config::default();
+    /// config.playback.volume = 250;
+    /// let contents = toml::to_string(&config).unwrap();
+    ///
+    /// let (config, warnings) = Config::parse(&contents).unwrap();
+    /// assert_eq!(config.playback.volume, 100);
+    /// assert_eq!(warnings, ["volume must be between 0 and 100, using 100"]);
+    /// ```
+    pub fn parse(contents: &str) -> Result<(Self, Vec<String>), toml::de::Error> {
+        let mut config = toml::from_str::<Config>(contents)?;
+        let warnings = config.validate();
+        Ok((config, warnings))
+    }
+
     /// Saves the configuration to the specified path.
-    fn save(&self, path: &PathBuf) -> std::io::Result<()> {
+    fn save(&self, path: &Path) -> std::io::Result<()> {
         // Create parent directory if it doesn't exist
         if let Some(parent) = path.parent() {
             fs::create_dir_all(parent)?;
@@ -165,18 +644,102 @@ impl Config {
         fs::write(path, toml_string)
     }
 
-    /// Validates configuration values and applies constraints.
-    fn validate(&mut self) {
+    /// Validates configuration values and applies constraints, returning a
+    /// warning for each value changed or worth a second look.
+    fn validate(&mut self) -> Vec<String> {
+        let mut warnings = Vec::new();
+
         // Ensure seek_step is at least 1 second
         if self.playback.seek_step == 0 {
-            eprintln!("Warning: seek_step must be at least 1, using default value of 10");
+            warnings.push("seek_step must be at least 1, using default value of 10".to_string());
             self.playback.seek_step = 10;
         }
 
+        // Keep the pre-amp within range
+        if self.playback.preamp_db.abs() > PREAMP_LIMIT_DB || self.playback.preamp_db.is_nan() {
+            warnings.push(format!(
+                "preamp_db must be between -{0} and +{0}, clamping",
+                PREAMP_LIMIT_DB
+            ));
+            self.playback.preamp_db = if self.playback.preamp_db.is_nan() {
+                0.0
+            } else {
+                self.playback.preamp_db.clamp(-PREAMP_LIMIT_DB, PREAMP_LIMIT_DB)
+            };
+        }
+        if !(10..=2000).contains(&self.playback.limiter_release_ms) {
+            warnings.push("limiter_release_ms must be between 10 and 2000, clamping".to_string());
+            self.playback.limiter_release_ms = self.playback.limiter_release_ms.clamp(10, 2000);
+        }
+        if self.playback.decoded_cache_mb > 4096 {
+            warnings.push("decoded_cache_mb must be at most 4096, clamping".to_string());
+            self.playback.decoded_cache_mb = 4096;
+        }
+        if self.playback.track_change_fade_ms > 2000 {
+            warnings.push("track_change_fade_ms must be at most 2000, clamping".to_string());
+            self.playback.track_change_fade_ms = 2000;
+        }
+        if self.playback.startup_fade_secs > 60 {
+            warnings.push("startup_fade_secs must be at most 60, clamping".to_string());
+            self.playback.startup_fade_secs = 60;
+        }
+        if self.playback.volume > 100 {
+            warnings.push("volume must be between 0 and 100, using 100".to_string());
+            self.playback.volume = 100;
+        }
+
+        if !(5..=60).contains(&self.visualizer.fps) {
+            warnings.push("visualizer.fps must be between 5 and 60, clamping".to_string());
+            self.visualizer.fps = self.visualizer.fps.clamp(5, 60);
+        }
+
+        if !(0.0..=1.0).contains(&self.visualizer.decay) {
+            warnings.push("visualizer.decay must be between 0.0 and 1.0, using 0.5".to_string());
+            self.visualizer.decay = 0.5;
+        }
+        if !(4..=32).contains(&self.visualizer.bars) {
+            warnings.push("visualizer.bars must be between 4 and 32, clamping".to_string());
+            self.visualizer.bars = self.visualizer.bars.clamp(4, 32);
+        }
+
+        self.playlist.extensions = self
+            .playlist
+            .extensions
+            .iter()
+            .map(|ext| filter::normalize_extension(ext))
+            .filter(|ext| !ext.is_empty())
+            .collect();
+        if self.playlist.extensions.is_empty() {
+            warnings.push("playlist.extensions must not be empty, using the built-in list".to_string());
+            self.playlist.extensions = PlaylistConfig::default().extensions;
+        }
+
+        if self.playlist.max_depth > MAX_DEPTH_WARNING {
+            warnings.push(format!(
+                "playlist.max_depth = {} is unusually deep, use 0 for unlimited",
+                self.playlist.max_depth
+            ));
+        }
+
+        let before = self.playlist.trim.len();
+        self.playlist.trim.retain(|rule| !rule.dir.trim().is_empty());
+        if self.playlist.trim.len() < before {
+            warnings.push("playlist.trim rules need a dir glob, skipping those without one".to_string());
+        }
+
+        if self.playback.preamp_db > PREAMP_CLIP_WARNING_DB && !self.playback.limiter {
+            warnings.push(format!(
+                "preamp_db above +{} dB may clip with the limiter off",
+                PREAMP_CLIP_WARNING_DB
+            ));
+        }
+
         // Could add more validation here:
         // - Check for duplicate keybindings
         // - Validate key string formats
         // - etc.
+
+        warnings
     }
 }
 
@@ -188,6 +751,10 @@ mod tests {
     fn test_default_config() {
         let config = Config::default();
         assert_eq!(config.playback.seek_step, 10);
+        assert!(config.playback.replaygain);
+        assert!(!config.ui.show_track_numbers);
+        assert!(config.ui.low_bandwidth(true));
+        assert!(!config.ui.low_bandwidth(false));
         assert!(config.keys.play_pause.contains("Space"));
         assert!(config.keys.next.contains("n"));
         assert!(config.keys.next.contains("Right"));
@@ -213,6 +780,20 @@ mod tests {
         // Should be able to deserialize back
         let parsed: Config = toml::from_str(&toml_str).unwrap();
         assert_eq!(parsed.playback.seek_step, config.playback.seek_step);
+
+        // The generated default file lists the scan options
+        for key in ["max_depth = 0", "follow_symlinks = true", "include_hidden = true"] {
+            assert!(toml_str.contains(key), "missing {}", key);
+        }
+    }
+
+    #[test]
+    fn test_missing_playback_fields_use_defaults() {
+        let mut toml_str = toml::to_string_pretty(&Config::default()).unwrap();
+        toml_str = toml_str.replace("replaygain = true\n", "");
+
+        let parsed: Config = toml::from_str(&toml_str).unwrap();
+        assert!(parsed.playback.replaygain);
     }
 
     #[test]
@@ -221,5 +802,42 @@ mod tests {
         config.playback.seek_step = 0;
         config.validate();
         assert_eq!(config.playback.seek_step, 10);
+
+        config.playback.preamp_db = 40.0;
+        config.validate();
+        assert_eq!(config.playback.preamp_db, PREAMP_LIMIT_DB);
+
+        config.playback.preamp_db = -40.0;
+        config.validate();
+        assert_eq!(config.playback.preamp_db, -PREAMP_LIMIT_DB);
+
+        config.playback.volume = 250;
+        config.validate();
+        assert_eq!(config.playback.volume, 100);
+
+        config.visualizer.fps = 120;
+        config.visualizer.decay = f32::NAN;
+        config.validate();
+        assert_eq!(config.visualizer.fps, 60);
+        assert_eq!(config.visualizer.decay, 0.5);
+        assert_eq!(config.visualizer.bars, 12);
+        config.playlist.extensions = vec![".MKA".to_string(), " ".to_string()];
+        config.validate();
+        assert_eq!(config.playlist.extensions, ["mka"]);
+
+        config.playlist.extensions.clear();
+        config.validate();
+        assert_eq!(config.playlist.extensions, DEFAULT_EXTENSIONS);
+    }
+
+    #[test]
+    fn test_trim_rules() {
+        let mut contents = toml::to_string_pretty(&Config::default()).unwrap().replace("trim = []\n", "");
+        contents.push_str("\n[[playlist.trim]]\ndir = \"/pods/**\"\nskip_intro_secs = 30\n\n[[playlist.trim]]\nskip_outro_secs = 5\n");
+        let (config, warnings) = Config::parse(&contents).unwrap();
+        assert_eq!(config.playlist.trim.len(), 1);
+        assert_eq!(config.playlist.trim[0].skip_intro_secs, Some(30));
+        assert_eq!(config.playlist.trim[0].skip_outro_secs, None);
+        assert!(warnings.iter().any(|warning| warning.contains("playlist.trim")), "{:?}", warnings);
     }
 }
@@ -0,0 +1,599 @@
+//! Recently played files kept decoded in memory, so going back to one, or
+//! to and fro between two, starts at once instead of decoding it again.
+//!
+//! The samples of a file are kept as it plays, and once it has played to the
+//! end without a seek they stay while they fit in `playback.decoded_cache_mb`,
+//! the least recently used going first. An entry is dropped once its file's
+//! modification time changes. Files too long to fit are not kept, and the
+//! cache is where a track decoded ahead of time waits.
+//!
+//! Capturing runs on the audio thread, so it only ever pushes into room set
+//! aside when the file was opened. Finished and abandoned captures go to a
+//! worker thread, which adds them to the cache or frees them.
+
+use crate::player::CacheUsage;
+use rodio::source::SeekError;
+use rodio::{Decoder, Source};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, SystemTime};
+
+/// A whole file's samples.
+#[derive(Debug)]
+pub struct Decoded {
+    samples: Vec<i16>,
+    channels: u16,
+    sample_rate: u32,
+    /// Modification time of the file when it was decoded.
+    modified: Option<SystemTime>,
+}
+
+impl Decoded {
+    /// Memory the samples take up.
+    pub fn bytes(&self) -> usize {
+        self.samples.len() * std::mem::size_of::<i16>()
+    }
+}
+
+/// Told whether a file being decoded made it into the cache.
+struct Waiter(Box<dyn FnOnce(bool) + Send>);
+
+impl std::fmt::Debug for Waiter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Waiter")
+    }
+}
+
+/// The cache's contents.
+#[derive(Debug, Default)]
+struct Entries {
+    budget: usize,
+    used: usize,
+    /// Bytes set aside for captures still playing.
+    reserved: usize,
+    /// Least recently used first.
+    entries: Vec<(PathBuf, Arc<Decoded>)>,
+    /// Files being decoded, with who to tell when they are done.
+    pending: HashMap<PathBuf, Vec<Waiter>>,
+}
+
+impl Entries {
+    fn remove(&mut self, index: usize) {
+        let (_, decoded) = self.entries.remove(index);
+        self.used -= decoded.bytes();
+    }
+
+    /// Drops the least recently used entries until `room` more bytes fit.
+    /// Returns false if they never would.
+    fn make_room(&mut self, room: usize) -> bool {
+        if self.reserved + room > self.budget {
+            return false;
+        }
+        while self.used + self.reserved + room > self.budget {
+            self.remove(0);
+        }
+        true
+    }
+
+    /// Adds a file's samples, replacing any kept before. Returns false if
+    /// they do not fit in the budget.
+    fn insert(&mut self, path: &Path, decoded: Decoded) -> bool {
+        if let Some(index) = self.entries.iter().position(|(cached, _)| cached == path) {
+            self.remove(index);
+        }
+        if !self.make_room(decoded.bytes()) {
+            return false;
+        }
+        self.used += decoded.bytes();
+        self.entries.push((path.to_path_buf(), Arc::new(decoded)));
+        true
+    }
+}
+
+/// A capture that has ended, for the worker to deal with.
+#[derive(Debug)]
+enum Finished {
+    /// Played through: add it to the cache.
+    Kept { path: PathBuf, decoded: Decoded, reserved: usize },
+    /// Stopped, seeked or outgrown: free it.
+    Dropped { samples: Vec<i16>, reserved: usize },
+}
+
+/// A poisoned lock only means a thread panicked holding it; the entries
+/// are still consistent, as every change to them is made in one go.
+fn lock(inner: &Mutex<Entries>) -> MutexGuard<'_, Entries> {
+    inner.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Decoded files shared between the player and the threads decoding them.
+#[derive(Debug, Clone)]
+pub struct DecodedCache {
+    inner: Arc<Mutex<Entries>>,
+    finished: Sender<Finished>,
+}
+
+impl DecodedCache {
+    /// Creates a cache holding up to `budget` bytes of samples, with the
+    /// worker that takes finished captures. The worker stops once the cache
+    /// and every capture from it are gone.
+    pub fn new(budget: usize) -> Self {
+        let inner = Arc::new(Mutex::new(Entries { budget, ..Entries::default() }));
+        let (finished, received) = mpsc::channel();
+        let entries = Arc::clone(&inner);
+        std::thread::spawn(move || {
+            for finished in received {
+                match finished {
+                    Finished::Kept { path, mut decoded, reserved } => {
+                        decoded.samples.shrink_to_fit();
+                        let mut inner = lock(&entries);
+                        inner.reserved -= reserved;
+                        inner.insert(&path, decoded);
+                    }
+                    Finished::Dropped { samples, reserved } => {
+                        lock(&entries).reserved -= reserved;
+                        drop(samples);
+                    }
+                }
+            }
+        });
+        Self { inner, finished }
+    }
+
+    fn entries(&self) -> MutexGuard<'_, Entries> {
+        lock(&self.inner)
+    }
+
+    /// Changes how many bytes the cache may hold, dropping entries that no
+    /// longer fit. A budget of zero turns it off.
+    pub fn set_budget(&self, budget: usize) {
+        let mut inner = self.entries();
+        inner.budget = budget;
+        inner.make_room(0);
+    }
+
+    /// Returns bytes held.
+    #[cfg(test)]
+    pub fn used(&self) -> usize {
+        self.entries().used
+    }
+
+    /// Waits for the worker to deal with every capture that has ended.
+    #[cfg(test)]
+    fn settle(&self) {
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while self.entries().reserved > 0 {
+            assert!(std::time::Instant::now() < deadline, "captures never settled");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    /// Returns how full the cache is.
+    pub fn usage(&self) -> CacheUsage {
+        let inner = self.entries();
+        CacheUsage { entries: inner.entries.len(), bytes: inner.used, budget: inner.budget }
+    }
+
+    /// Returns a file's samples, if cached and the file has not changed.
+    pub fn get(&self, path: &Path) -> Option<Samples> {
+        let modified = modified(path);
+        let mut inner = self.entries();
+        let index = inner.entries.iter().position(|(cached, _)| cached == path)?;
+        if inner.entries[index].1.modified != modified {
+            inner.remove(index);
+            return None;
+        }
+        let entry = inner.entries.remove(index);
+        let decoded = entry.1.clone();
+        inner.entries.push(entry);
+        Some(Samples { decoded, at: 0 })
+    }
+
+    /// Adds a file's samples, replacing any kept before. Returns false if
+    /// they do not fit in the budget.
+    #[cfg(test)]
+    pub fn insert(&self, path: &Path, decoded: Decoded) -> bool {
+        self.entries().insert(path, decoded)
+    }
+
+    /// Wraps a file's source so that its samples are added to the cache
+    /// once it has played through. Room for them is set aside now, from the
+    /// length the source gives, and counts against the budget while it
+    /// plays; a source that does not know its length is not kept.
+    pub fn capture<S: Source<Item = i16>>(&self, path: &Path, source: S) -> Capture<S> {
+        // Lengths are rounded, so allow a second more
+        let slack = u128::from(source.sample_rate()) * u128::from(source.channels());
+        let capacity = estimated_bytes(&source)
+            .map(|bytes| bytes / std::mem::size_of::<i16>() as u128 + slack)
+            .and_then(|samples| usize::try_from(samples).ok());
+        let mut inner = self.entries();
+        let reserved = capacity
+            .map(|capacity| capacity.min(inner.budget / std::mem::size_of::<i16>()))
+            .map(|capacity| (capacity, capacity * std::mem::size_of::<i16>()))
+            .filter(|&(capacity, bytes)| capacity > 0 && inner.make_room(bytes));
+        if let Some((_, bytes)) = reserved {
+            inner.reserved += bytes;
+        }
+        drop(inner);
+        Capture {
+            cache: self.clone(),
+            path: path.to_path_buf(),
+            modified: modified(path),
+            channels: source.channels(),
+            sample_rate: source.sample_rate(),
+            reserved: reserved.map_or(0, |(_, bytes)| bytes),
+            samples: reserved.map(|(capacity, _)| Vec::with_capacity(capacity)),
+            source,
+        }
+    }
+
+    /// Decodes a file into the cache on a background thread, unless it is
+    /// there already, being decoded, or too long to fit, then calls `done`
+    /// with whether the file is in the cache, at once if it already is or
+    /// never will be.
+    pub fn fill_then(&self, path: &Path, done: impl FnOnce(bool) + Send + 'static) {
+        let mut inner = self.entries();
+        let cached = inner.entries.iter().any(|(cached, _)| cached == path);
+        if inner.budget == 0 || cached {
+            drop(inner);
+            done(cached);
+            return;
+        }
+        let waiter = Waiter(Box::new(done));
+        if let Some(waiters) = inner.pending.get_mut(path) {
+            waiters.push(waiter);
+            return;
+        }
+        inner.pending.insert(path.to_path_buf(), vec![waiter]);
+        let budget = inner.budget;
+        drop(inner);
+
+        let cache = self.clone();
+        let path = path.to_path_buf();
+        std::thread::spawn(move || {
+            let decoded = decode_within(&path, budget);
+            let mut inner = cache.entries();
+            // Gone from pending when forgotten while it was decoded
+            let waiters = inner.pending.remove(&path).unwrap_or_default();
+            let kept = !waiters.is_empty() && decoded.is_some_and(|decoded| inner.insert(&path, decoded));
+            drop(inner);
+            for Waiter(done) in waiters {
+                done(kept);
+            }
+        });
+    }
+
+    /// Drops a file from the cache, and stops one being decoded from being
+    /// added.
+    pub fn forget(&self, path: &Path) {
+        let mut inner = self.entries();
+        if let Some(index) = inner.entries.iter().position(|(cached, _)| cached == path) {
+            inner.remove(index);
+        }
+        let waiters = inner.pending.remove(path).unwrap_or_default();
+        drop(inner);
+        for Waiter(done) in waiters {
+            done(false);
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Returns the bytes a source's samples would take up, if it knows its length.
+fn estimated_bytes<S: Source<Item = i16>>(source: &S) -> Option<u128> {
+    let samples_per_sec = u128::from(source.sample_rate()) * u128::from(source.channels());
+    let duration = source.total_duration()?;
+    Some(duration.as_millis() * samples_per_sec / 1000 * std::mem::size_of::<i16>() as u128)
+}
+
+/// Decodes a whole file, giving up if its samples would take more than
+/// `budget` bytes.
+fn decode_within(path: &Path, budget: usize) -> Option<Decoded> {
+    let modified = modified(path);
+    let file = File::open(path).ok()?;
+    let mut source = Decoder::new(BufReader::new(file)).ok()?;
+    if estimated_bytes(&source).is_some_and(|bytes| bytes > budget as u128) {
+        return None;
+    }
+    let (channels, sample_rate) = (source.channels(), source.sample_rate());
+    let limit = budget / std::mem::size_of::<i16>();
+    let mut samples = Vec::new();
+    for sample in source.by_ref() {
+        if samples.len() == limit {
+            return None;
+        }
+        samples.push(sample);
+    }
+    samples.shrink_to_fit();
+    Some(Decoded { samples, channels, sample_rate, modified })
+}
+
+/// A file's source that keeps the samples it plays, adding them to the cache
+/// when it ends. Nothing is added after a seek, or once they outgrow the room
+/// set aside for them.
+pub struct Capture<S> {
+    source: S,
+    cache: DecodedCache,
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    channels: u16,
+    sample_rate: u32,
+    /// Bytes set aside in the cache for the samples.
+    reserved: usize,
+    /// Samples played so far, or None once they will not be kept.
+    samples: Option<Vec<i16>>,
+}
+
+impl<S> Capture<S> {
+    /// Hands the samples to the worker, to be kept or freed there rather
+    /// than on the audio thread.
+    fn finish(&mut self, keep: bool) {
+        let Some(samples) = self.samples.take() else {
+            return;
+        };
+        let reserved = self.reserved;
+        let finished = if keep {
+            let (channels, sample_rate, modified) = (self.channels, self.sample_rate, self.modified);
+            let decoded = Decoded { samples, channels, sample_rate, modified };
+            Finished::Kept { path: std::mem::take(&mut self.path), decoded, reserved }
+        } else {
+            Finished::Dropped { samples, reserved }
+        };
+        // Only fails once the worker is gone, when there is nothing to keep
+        let _ = self.cache.finished.send(finished);
+    }
+}
+
+impl<S> Drop for Capture<S> {
+    fn drop(&mut self) {
+        self.finish(false);
+    }
+}
+
+impl<S: Source<Item = i16>> Iterator for Capture<S> {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let Some(sample) = self.source.next() else {
+            self.finish(true);
+            return None;
+        };
+        if let Some(samples) = &mut self.samples {
+            if samples.len() == samples.capacity() {
+                self.finish(false);
+            } else {
+                samples.push(sample);
+            }
+        }
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.source.size_hint()
+    }
+}
+
+impl<S: Source<Item = i16>> Source for Capture<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.finish(false);
+        self.source.try_seek(pos)
+    }
+}
+
+/// Plays cached samples.
+#[derive(Debug, Clone)]
+pub struct Samples {
+    decoded: Arc<Decoded>,
+    at: usize,
+}
+
+impl Iterator for Samples {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let sample = *self.decoded.samples.get(self.at)?;
+        self.at += 1;
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.decoded.samples.len() - self.at;
+        (left, Some(left))
+    }
+}
+
+impl Source for Samples {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.decoded.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.decoded.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        let frames = self.decoded.samples.len() as u64 / u64::from(self.decoded.channels.max(1));
+        Some(Duration::from_secs_f64(frames as f64 / f64::from(self.decoded.sample_rate.max(1))))
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        let channels = usize::from(self.decoded.channels.max(1));
+        let frame = (pos.as_secs_f64() * f64::from(self.decoded.sample_rate)) as usize;
+        self.at = (frame * channels).min(self.decoded.samples.len());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decoded(seconds: usize) -> Decoded {
+        // 1 kHz mono: 2000 bytes a second
+        Decoded { samples: vec![0; seconds * 1000], channels: 1, sample_rate: 1000, modified: None }
+    }
+
+    #[test]
+    fn test_budget_and_eviction() {
+        let cache = DecodedCache::new(10_000);
+        assert!(cache.insert(Path::new("a"), decoded(2)));
+        assert!(cache.insert(Path::new("b"), decoded(2)));
+        assert_eq!(cache.used(), 8_000);
+
+        // Using a keeps it, so b goes first
+        assert!(cache.get(Path::new("a")).is_some());
+        assert!(cache.insert(Path::new("c"), decoded(2)));
+        assert_eq!(cache.used(), 8_000);
+        assert!(cache.get(Path::new("b")).is_none());
+        assert!(cache.get(Path::new("a")).is_some());
+
+        // Replacing an entry does not count it twice
+        assert!(cache.insert(Path::new("a"), decoded(1)));
+        assert_eq!(cache.used(), 6_000);
+
+        // Too long to fit at all: nothing is dropped for it
+        assert!(!cache.insert(Path::new("long"), decoded(6)));
+        assert_eq!(cache.used(), 6_000);
+
+        // a was added last, so c goes
+        cache.set_budget(4_000);
+        assert_eq!(cache.used(), 2_000);
+        assert!(cache.get(Path::new("a")).is_some());
+        cache.set_budget(0);
+        assert_eq!(cache.used(), 0);
+    }
+
+    #[test]
+    fn test_usage_follows_inserts_and_forgets() {
+        let cache = DecodedCache::new(10_000);
+        assert_eq!(cache.usage(), CacheUsage { entries: 0, bytes: 0, budget: 10_000 });
+        cache.insert(Path::new("a"), decoded(2));
+        cache.insert(Path::new("b"), decoded(1));
+        assert_eq!(cache.usage(), CacheUsage { entries: 2, bytes: 6_000, budget: 10_000 });
+
+        // Evicting a for c leaves b and c
+        cache.insert(Path::new("c"), decoded(3));
+        assert_eq!(cache.usage(), CacheUsage { entries: 2, bytes: 8_000, budget: 10_000 });
+
+        cache.forget(Path::new("c"));
+        cache.forget(Path::new("missing"));
+        assert_eq!(cache.usage(), CacheUsage { entries: 1, bytes: 2_000, budget: 10_000 });
+
+        // A file forgotten while it is decoded is not added
+        let (tx, rx) = std::sync::mpsc::channel();
+        let path = std::env::temp_dir().join(format!("juke_decoded_forget_{}", std::process::id()));
+        cache.entries().pending.insert(path.clone(), Vec::new());
+        cache.fill_then(&path, move |kept| tx.send(kept).unwrap());
+        cache.forget(&path);
+        assert_eq!(rx.recv(), Ok(false));
+        assert_eq!(cache.usage().entries, 1);
+
+        // Already cached answers at once
+        let (tx, rx) = std::sync::mpsc::channel();
+        cache.fill_then(Path::new("b"), move |kept| tx.send(kept).unwrap());
+        assert_eq!(rx.try_recv(), Ok(true));
+    }
+
+    #[test]
+    fn test_changed_file_is_dropped() {
+        let path = std::env::temp_dir().join(format!("juke_decoded_{}", std::process::id()));
+        fs::write(&path, b"").unwrap();
+        let cache = DecodedCache::new(10_000);
+        let entry = Decoded { modified: modified(&path), ..decoded(1) };
+        assert!(cache.insert(&path, entry));
+        assert!(cache.get(&path).is_some());
+
+        let later = SystemTime::now() + Duration::from_secs(60);
+        File::options().write(true).open(&path).unwrap().set_modified(later).unwrap();
+        assert!(cache.get(&path).is_none());
+        assert_eq!(cache.used(), 0);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_capture_keeps_a_track_played_through() {
+        let cache = DecodedCache::new(10_000);
+        let source = || rodio::buffer::SamplesBuffer::new(1, 1000, vec![3i16; 1000]);
+        let played: Vec<i16> = cache.capture(Path::new("a"), source()).collect();
+        assert_eq!(played.len(), 1000);
+        cache.settle();
+        let mut samples = cache.get(Path::new("a")).unwrap();
+        assert_eq!(samples.total_duration(), Some(Duration::from_secs(1)));
+        assert_eq!(samples.next(), Some(3));
+
+        // Stopped partway or seeked: nothing is kept
+        cache.capture(Path::new("b"), source()).take(10).for_each(drop);
+        let mut seeked = cache.capture(Path::new("c"), source());
+        seeked.next();
+        seeked.try_seek(Duration::from_millis(500)).unwrap();
+        seeked.for_each(drop);
+        cache.settle();
+        assert!(cache.get(Path::new("b")).is_none());
+        assert!(cache.get(Path::new("c")).is_none());
+
+        // Longer than the budget: nothing is kept
+        let small = DecodedCache::new(1_000);
+        small.capture(Path::new("a"), source()).for_each(drop);
+        small.settle();
+        assert_eq!(small.used(), 0);
+    }
+
+    #[test]
+    fn test_capture_in_flight_counts_against_the_budget() {
+        let cache = DecodedCache::new(10_000);
+        assert!(cache.insert(Path::new("a"), decoded(2)));
+        assert!(cache.insert(Path::new("b"), decoded(2)));
+
+        // Two seconds and a second of slack: a goes to make room
+        let source = rodio::buffer::SamplesBuffer::new(1, 1000, vec![3i16; 2000]);
+        let capture = cache.capture(Path::new("c"), source);
+        assert_eq!(cache.entries().reserved, 6_000);
+        assert_eq!(cache.usage().entries, 1);
+        assert!(!cache.insert(Path::new("d"), decoded(3)));
+
+        // Stopped partway: the room is given back
+        drop(capture);
+        cache.settle();
+        assert!(cache.insert(Path::new("d"), decoded(3)));
+        assert_eq!(cache.used(), 10_000);
+    }
+
+    #[test]
+    fn test_samples_source() {
+        let cache = DecodedCache::new(10_000);
+        let mut entry = decoded(2);
+        entry.samples[1500] = 7;
+        cache.insert(Path::new("a"), entry);
+        let mut samples = cache.get(Path::new("a")).unwrap();
+        assert_eq!(samples.total_duration(), Some(Duration::from_secs(2)));
+        samples.try_seek(Duration::from_millis(1500)).unwrap();
+        assert_eq!(samples.next(), Some(7));
+        assert_eq!(samples.size_hint(), (499, Some(499)));
+    }
+}
@@ -0,0 +1,86 @@
+//! When the screen needs drawing again.
+//!
+//! Whatever changes what the screen shows, a key or a background task
+//! finishing, marks the screen out of date, and the main loop draws at the
+//! end of the same iteration instead of at the next status tick. The tick
+//! is left to move the clock, the progress bar and the visualizer along.
+
+use std::time::{Duration, Instant};
+
+/// Whether anything changed since the last draw, and when the tick last
+/// came.
+#[derive(Debug, Clone)]
+pub struct Redraw {
+    dirty: bool,
+    last_draw: Instant,
+}
+
+impl Redraw {
+    /// Starts with everything to draw.
+    pub fn new(now: Instant) -> Self {
+        Self { dirty: true, last_draw: now }
+    }
+
+    /// Marks the screen as out of date.
+    pub fn mark(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Whether to draw now: something is marked, or a `tick` has passed.
+    pub fn due(&self, now: Instant, tick: Duration) -> bool {
+        self.dirty || now.duration_since(self.last_draw) >= tick
+    }
+
+    /// How long the main loop may wait for input before it has to draw.
+    pub fn wait(&self, now: Instant, tick: Duration) -> Duration {
+        if self.dirty { Duration::ZERO } else { tick.saturating_sub(now.duration_since(self.last_draw)) }
+    }
+
+    /// Notes that the screen was drawn, clearing the mark and starting the
+    /// tick over.
+    pub fn drawn(&mut self, now: Instant) {
+        self.dirty = false;
+        self.last_draw = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TICK: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn test_background_change_drawn_in_the_same_iteration() {
+        let start = Instant::now();
+        let mut redraw = Redraw::new(start);
+        assert!(redraw.due(start, TICK));
+        redraw.drawn(start);
+
+        // Paused, so only the tick would draw
+        let now = start + Duration::from_millis(100);
+        assert!(!redraw.due(now, TICK));
+        assert_eq!(redraw.wait(now, TICK), Duration::from_millis(900));
+
+        // Tags read in the background arrive during the update
+        redraw.mark();
+        assert!(redraw.due(now, TICK));
+        assert_eq!(redraw.wait(now, TICK), Duration::ZERO);
+        redraw.drawn(now);
+        assert!(!redraw.due(now, TICK));
+        assert!(redraw.due(now + TICK, TICK));
+    }
+
+    #[test]
+    fn test_mark_lasts_until_drawn() {
+        let start = Instant::now();
+        let mut redraw = Redraw::new(start);
+        redraw.drawn(start);
+        redraw.mark();
+        redraw.mark();
+        assert!(redraw.due(start, TICK));
+        assert!(redraw.due(start + TICK / 2, TICK));
+        redraw.drawn(start);
+        assert!(!redraw.due(start, TICK));
+    }
+}
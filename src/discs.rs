@@ -0,0 +1,182 @@
+//! Telling the discs of a multi-disc album apart, and keeping them together.
+//!
+//! Albums are often tagged "Album (Disc 1)", "Album CD2" and so on, one
+//! name per disc. Grouping goes by the name without that suffix, while
+//! display keeps the name as tagged, and the disc number tag, or else the
+//! number in the suffix, orders the discs.
+
+use crate::playlist::Track;
+
+/// Disc numbers spelled out, from one.
+const NUMBER_WORDS: [&str; 10] = ["one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten"];
+
+/// Separators left between an album name and its disc suffix.
+const SEPARATORS: &[char] = &[' ', '-', '–', '—', ',', ':', '/'];
+
+/// Splits a disc suffix such as " (Disc 2)", " [CD2]", " - Disk Two" or
+/// " CD 2 of 3" off an album name, returning the name without it and the
+/// disc's number.
+///
+/// ```
+/// use juke::discs::split_disc;
+///
+/// assert_eq!(split_disc("Live at Leeds (Disc 2)"), ("Live at Leeds", Some(2)));
+/// assert_eq!(split_disc("Blue"), ("Blue", None));
+/// ```
+pub fn split_disc(album: &str) -> (&str, Option<u32>) {
+    let album = album.trim_end();
+    // Bracketed: "Album (Disc 2)", "Album [CD2]"
+    for (open, close) in [('(', ')'), ('[', ']')] {
+        if let Some(inner) = album.strip_suffix(close)
+            && let Some(at) = inner.rfind(open)
+            && let Some(disc) = parse_disc(&inner[at + open.len_utf8()..])
+        {
+            let name = album[..at].trim_end_matches(SEPARATORS);
+            if !name.is_empty() {
+                return (name, Some(disc));
+            }
+        }
+    }
+    // Trailing: "Album - Disc 2", "Album, CD 2", "Album Disk One"
+    for (at, c) in album.char_indices() {
+        let starts_word = album[..at].ends_with(SEPARATORS);
+        if !starts_word || !matches!(c, 'd' | 'D' | 'c' | 'C') {
+            continue;
+        }
+        if let Some(disc) = parse_disc(&album[at..]) {
+            let name = album[..at].trim_end_matches(SEPARATORS);
+            if !name.is_empty() {
+                return (name, Some(disc));
+            }
+        }
+    }
+    (album, None)
+}
+
+/// Parses "Disc 2", "disk two", "CD2" or "CD 2 of 3" as a whole.
+fn parse_disc(text: &str) -> Option<u32> {
+    let text = text.trim().to_lowercase();
+    let rest = ["disc", "disk", "cd"].iter().find_map(|prefix| text.strip_prefix(prefix))?;
+    let mut words = rest.trim_start_matches([' ', '.', '#']).split_whitespace();
+    let number = words.next()?;
+    let number = match number.parse::<u32>() {
+        Ok(number) => number,
+        Err(_) => NUMBER_WORDS.iter().position(|word| *word == number)? as u32 + 1,
+    };
+    // Nothing may follow but "of 3"
+    match (words.next(), words.next(), words.next()) {
+        (None, None, None) => {}
+        (Some("of"), Some(count), None) if count.parse::<u32>().is_ok() => {}
+        _ => return None,
+    }
+    (number > 0).then_some(number)
+}
+
+/// Returns the name tracks of an album are grouped under: the album as
+/// tagged, without any disc suffix.
+pub fn album_key(album: &str) -> &str {
+    split_disc(album).0
+}
+
+/// Returns a track's disc: its disc number tag, or else the number in its
+/// album's disc suffix.
+pub fn disc_of(track: &Track) -> Option<u32> {
+    track.disc_number.or_else(|| track.album.as_deref().and_then(|album| split_disc(album).1))
+}
+
+/// Whether two tracks are on the same album by the same artist, whichever
+/// discs they are on.
+pub fn same_album(a: &Track, b: &Track) -> bool {
+    match (&a.album, &b.album) {
+        (Some(x), Some(y)) => album_key(x) == album_key(y) && a.artist == b.artist,
+        _ => false,
+    }
+}
+
+/// Returns the disc that starts at `row` of a list showing `tracks` in the
+/// order of `rows`, where a "Disc N" heading goes: the first row of a disc
+/// on an album that shows more than one.
+pub fn disc_starting_at(tracks: &[Track], rows: &[usize], row: usize) -> Option<u32> {
+    let track = tracks.get(*rows.get(row)?)?;
+    let disc = disc_of(track)?;
+    let on_album = |other: usize| rows.get(other).and_then(|&index| tracks.get(index)).filter(|o| same_album(track, o));
+    let previous = row.checked_sub(1).and_then(on_album);
+    if previous.is_some_and(|previous| disc_of(previous) == Some(disc)) {
+        return None;
+    }
+    // Only a disc the album goes on from, or on to another, is worth a heading
+    let several = previous.is_some() || (row + 1..).map_while(on_album).any(|next| disc_of(next) != Some(disc));
+    several.then_some(disc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_split_disc_variants() {
+        let cases = [
+            ("Album (Disc 1)", ("Album", Some(1))),
+            ("Album [CD2]", ("Album", Some(2))),
+            ("Album (cd 3 of 4)", ("Album", Some(3))),
+            ("Album - Disc 2", ("Album", Some(2))),
+            ("Album, CD 2", ("Album", Some(2))),
+            ("Album CD1", ("Album", Some(1))),
+            ("Album Disk One", ("Album", Some(1))),
+            ("Album: Disc #10", ("Album", Some(10))),
+            ("Album (Deluxe Edition) (Disc 2)", ("Album (Deluxe Edition)", Some(2))),
+            // Not disc suffixes
+            ("Album (Deluxe Edition)", ("Album (Deluxe Edition)", None)),
+            ("Disc 1", ("Disc 1", None)),
+            ("The CD Collection", ("The CD Collection", None)),
+            ("Discovery", ("Discovery", None)),
+            ("Album (Disc 0)", ("Album (Disc 0)", None)),
+            ("Album Disc 2 Remixes", ("Album Disc 2 Remixes", None)),
+        ];
+        for (album, expected) in cases {
+            assert_eq!(split_disc(album), expected, "splitting {:?}", album);
+        }
+    }
+
+    #[test]
+    fn test_tracks_on_different_discs_share_an_album() {
+        let track = |album: &str, disc: Option<u32>| {
+            let mut track = Track::new(PathBuf::from("a.flac"));
+            track.album = Some(album.to_string());
+            track.artist = Some("Band".to_string());
+            track.disc_number = disc;
+            track
+        };
+        let first = track("Live (Disc 1)", None);
+        let second = track("Live (Disc 2)", None);
+        assert!(same_album(&first, &second));
+        assert!(!same_album(&first, &track("Studio", None)));
+        assert_eq!(disc_of(&second), Some(2));
+        // The tag wins over the name
+        assert_eq!(disc_of(&track("Live (Disc 2)", Some(3))), Some(3));
+        assert_eq!(disc_of(&track("Live", None)), None);
+    }
+
+    #[test]
+    fn test_disc_headings_start_each_disc() {
+        let track = |album: &str, disc: Option<u32>| {
+            let mut track = Track::new(PathBuf::from("a.flac"));
+            track.album = Some(album.to_string());
+            track.disc_number = disc;
+            track
+        };
+        let tracks = [
+            track("Live CD1", None),
+            track("Live CD1", None),
+            track("Live CD2", None),
+            track("Single", Some(1)),
+            track("Untagged", None),
+        ];
+        let headings = |rows: &[usize]| (0..rows.len()).map(|row| disc_starting_at(&tracks, rows, row)).collect::<Vec<_>>();
+        assert_eq!(headings(&[0, 1, 2, 3, 4]), [Some(1), None, Some(2), None, None]);
+        // A search leaving one disc shows no heading
+        assert_eq!(headings(&[2, 3]), [None, None]);
+        assert_eq!(disc_starting_at(&tracks, &[0], 1), None);
+    }
+}
@@ -0,0 +1,342 @@
+//! Diagnostic report for bug reports (`juke --doctor`).
+
+use crate::buildinfo;
+use crate::lock::StateLock;
+use crate::loudness::LoudnessCache;
+use crate::player;
+use crate::playlist::{self, LoadOptions};
+use crate::settings::Settings;
+use crate::statecheck;
+use crate::strings::Strings;
+use rodio::cpal::traits::HostTrait;
+use rodio::DeviceTrait;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Name of the log file in the state directory that crash reports are appended to.
+pub const LOG_FILE: &str = "juke.log";
+
+/// Audio output detected on this machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioInfo {
+    pub host: String,
+    pub default_device: Option<String>,
+    pub devices: Result<Vec<String>, String>,
+}
+
+impl AudioInfo {
+    /// Queries the default audio host.
+    pub fn detect() -> Self {
+        let host = rodio::cpal::default_host();
+        Self {
+            host: host.id().name().to_string(),
+            default_device: host.default_output_device().and_then(|d| d.name().ok()),
+            devices: player::output_device_names().map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// What a crash report tells about the machine, gathered at startup: asking
+/// the audio host or reading the loudness cache from a panic hook could
+/// block or panic again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    audio: AudioInfo,
+    /// The loudness cache's path and status.
+    cache: String,
+}
+
+impl Snapshot {
+    /// Reads the loudness cache now, next to the audio output detected.
+    pub fn new(settings: &Settings, audio: AudioInfo) -> Self {
+        Self { audio, cache: cache_status(settings, PathStyle { anonymize: false }) }
+    }
+}
+
+/// Writes paths as given, or as stable hashes with `--anonymize`.
+#[derive(Debug, Clone, Copy)]
+pub struct PathStyle {
+    pub anonymize: bool,
+}
+
+impl PathStyle {
+    /// Formats a path, hashing it but keeping the extension when anonymizing.
+    /// The hash is the same in every build, so reports can be compared.
+    pub fn show(&self, path: &Path) -> String {
+        if !self.anonymize {
+            return path.display().to_string();
+        }
+        let hash = fnv1a(path.as_os_str().as_encoded_bytes());
+        match path.extension() {
+            Some(ext) => format!("<{:016x}>.{}", hash, ext.to_string_lossy()),
+            None => format!("<{:016x}>", hash),
+        }
+    }
+}
+
+/// 64-bit FNV-1a.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// Writes the report without the scan: configuration, audio, cache, and versions.
+pub fn write_environment(
+    out: &mut dyn Write,
+    settings: &Settings,
+    audio: &AudioInfo,
+    paths: PathStyle,
+) -> io::Result<()> {
+    write_build(out, settings, paths)?;
+    write_audio(out, audio)?;
+    write_cache(out, &cache_status(settings, paths))
+}
+
+/// Writes the audio section.
+fn write_audio(out: &mut dyn Write, audio: &AudioInfo) -> io::Result<()> {
+    writeln!(out, "\n== Audio ==")?;
+    writeln!(out, "host: {}", audio.host)?;
+    writeln!(out, "default device: {}", audio.default_device.as_deref().unwrap_or("none"))?;
+    match &audio.devices {
+        Ok(devices) if devices.is_empty() => writeln!(out, "devices: none")?,
+        Ok(devices) => {
+            writeln!(out, "devices:")?;
+            for device in devices {
+                writeln!(out, "  {}", device)?;
+            }
+        }
+        Err(e) => writeln!(out, "devices: error: {}", e)?,
+    }
+    Ok(())
+}
+
+/// Writes the loudness cache section.
+fn write_cache(out: &mut dyn Write, status: &str) -> io::Result<()> {
+    writeln!(out, "\n== Loudness cache ==")?;
+    writeln!(out, "{}", status)
+}
+
+/// Describes the loudness cache: where it is and how many tracks it knows.
+fn cache_status(settings: &Settings, paths: PathStyle) -> String {
+    let Some(state_dir) = settings.config.paths.state_dir.as_deref() else {
+        return "disabled: no state directory".to_string();
+    };
+    let path = LoudnessCache::cache_path(state_dir);
+    let status = if path.exists() {
+        format!("{} entries", LoudnessCache::load(Some(state_dir)).entry_count())
+    } else {
+        "not created yet".to_string()
+    };
+    format!("{}: {}", paths.show(&path), status)
+}
+
+/// Writes the versions and the configuration, which need nothing but what
+/// is in memory already.
+fn write_build(out: &mut dyn Write, settings: &Settings, paths: PathStyle) -> io::Result<()> {
+    writeln!(out, "== Versions ==")?;
+    writeln!(out, "{}", buildinfo::summary())?;
+    for (name, version) in buildinfo::dependencies() {
+        writeln!(out, "{} {}", name, version)?;
+    }
+    writeln!(out, "os {} ({})", std::env::consts::OS, std::env::consts::ARCH)?;
+
+    writeln!(out, "\n== Configuration ==")?;
+    let mut config = settings.describe();
+    if paths.anonymize {
+        // Hide every path the configuration mentions
+        let known = [
+            settings.config_path.clone(),
+            settings.config.paths.state_dir.clone(),
+            settings.config.resume.state_path.clone(),
+        ];
+        for path in known.into_iter().flatten() {
+            config = config.replace(&path.display().to_string(), &paths.show(&path));
+        }
+    }
+    write!(out, "{}", config)
+}
+
+/// Writes the state directory section, running the same check as startup.
+/// Broken files are only moved aside when no other juke is using them.
+pub fn write_state(out: &mut dyn Write, state_dir: Option<&Path>, paths: PathStyle) -> io::Result<()> {
+    writeln!(out, "\n== State directory ==")?;
+    let Some(state_dir) = state_dir else {
+        return writeln!(out, "disabled: no state directory");
+    };
+    let lock = StateLock::acquire(state_dir);
+    if lock.is_err() {
+        writeln!(out, "in use by another juke: broken files are reported, not moved")?;
+    }
+    let report = statecheck::check(state_dir, lock.is_ok());
+    for line in report.lines(&Strings::default(), |path| paths.show(path)) {
+        writeln!(out, "{}", line)?;
+    }
+    Ok(())
+}
+
+/// Writes the scan section: every file found, how long it took to probe, and
+/// whether it decodes.
+pub fn write_scan(out: &mut dyn Write, path: &Path, options: &LoadOptions, paths: PathStyle) -> io::Result<()> {
+    writeln!(out, "\n== Scan: {} ==", paths.show(path))?;
+
+    let started = Instant::now();
+    let (tracks, report) = match playlist::scan_directory(path, options) {
+        Ok(result) => result,
+        Err(e) => {
+            writeln!(out, "error: {}", e)?;
+            return Ok(());
+        }
+    };
+    writeln!(
+        out,
+        "{} files examined, {} tracks, {} errors in {}",
+        report.scanned,
+        report.added,
+        report.errors.len(),
+        format_elapsed(started.elapsed())
+    )?;
+
+    for track in &tracks {
+        let started = Instant::now();
+        let result = probe(&track.path);
+        let elapsed = format_elapsed(started.elapsed());
+        match result {
+            Ok(()) => writeln!(out, "  ok    {:>9}  {}", elapsed, paths.show(&track.path))?,
+            Err(e) => writeln!(out, "  FAIL  {:>9}  {}: {}", elapsed, paths.show(&track.path), e)?,
+        }
+    }
+    for (path, error) in &report.errors {
+        writeln!(out, "  FAIL  {:>9}  {}: {}", "-", paths.show(path), error)?;
+    }
+
+    Ok(())
+}
+
+/// Checks that a file opens and its decoder starts.
+pub fn probe(path: &Path) -> Result<(), String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    rodio::Decoder::new(BufReader::new(file)).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Appends a crash report to the log file in the state directory, with the
+/// audio and cache as they were at startup.
+pub fn append_crash_report(settings: &Settings, snapshot: &Snapshot, panic: &str) -> io::Result<PathBuf> {
+    let state_dir = settings
+        .config
+        .paths
+        .state_dir
+        .as_deref()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no state directory"))?;
+    std::fs::create_dir_all(state_dir)?;
+    let path = state_dir.join(LOG_FILE);
+
+    let mut report = Vec::new();
+    let since_epoch = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    writeln!(report, "==== Crash at {} s since epoch ====", since_epoch.as_secs())?;
+    writeln!(report, "{}\n", panic)?;
+    // Nothing that could block or panic again, like asking the audio host
+    write_build(&mut report, settings, PathStyle { anonymize: false })?;
+    write_audio(&mut report, &snapshot.audio)?;
+    write_cache(&mut report, &snapshot.cache)?;
+    writeln!(report)?;
+
+    OpenOptions::new().create(true).append(true).open(&path)?.write_all(&report)?;
+    Ok(path)
+}
+
+/// Formats a duration in milliseconds.
+fn format_elapsed(elapsed: Duration) -> String {
+    format!("{:.1} ms", elapsed.as_secs_f64() * 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn settings(state_dir: &Path) -> Settings {
+        let mut config = Config::default();
+        config.paths.state_dir = Some(state_dir.to_path_buf());
+        Settings {
+            config,
+            config_path: Some(state_dir.join("config.toml")),
+            sources: Vec::new(),
+        }
+    }
+
+    fn audio() -> AudioInfo {
+        AudioInfo {
+            host: "Test".to_string(),
+            default_device: None,
+            devices: Ok(vec!["Speakers".to_string()]),
+        }
+    }
+
+    #[test]
+    fn test_report_for_empty_directory() {
+        let dir = std::env::temp_dir().join(format!("juke_doctor_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut out = Vec::new();
+        let paths = PathStyle { anonymize: false };
+        write_environment(&mut out, &settings(&dir), &audio(), paths).unwrap();
+        write_scan(&mut out, &dir, &LoadOptions::default(), paths).unwrap();
+        // After the scan, which would otherwise count the lock file
+        write_state(&mut out, Some(&dir), paths).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        for section in ["== Versions ==", "== Configuration ==", "== Audio ==", "== Loudness cache ==", "== State directory ==", "== Scan: "] {
+            assert!(out.contains(section), "missing {:?} in:\n{}", section, out);
+        }
+        assert!(out.contains(&format!("juke {}", env!("CARGO_PKG_VERSION"))));
+        assert!(out.contains("rodio 0."));
+        assert!(out.contains("  Speakers\n"));
+        assert!(out.contains("not created yet"));
+        assert!(out.contains("0 files examined, 0 tracks, 0 errors"));
+        assert!(out.contains("0 files checked"));
+        // The embedded configuration is valid TOML
+        let config = &out[out.find("== Configuration ==").unwrap() + 20..out.find("== Audio ==").unwrap()];
+        assert!(config.parse::<toml::Table>().is_ok());
+    }
+
+    #[test]
+    fn test_crash_report_stays_in_memory() {
+        let dir = std::env::temp_dir().join(format!("juke_crash_{}", std::process::id()));
+        let settings = settings(&dir);
+        let snapshot = Snapshot::new(&settings, audio());
+        // Changes after startup are not looked up again
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(LoudnessCache::cache_path(&dir), "").unwrap();
+        let path = append_crash_report(&settings, &snapshot, "boom").unwrap();
+        let report = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(report.contains("boom\n\n== Versions =="));
+        assert!(report.contains("== Configuration =="));
+        assert!(report.contains("== Audio ==\nhost: Test\n"));
+        assert!(report.contains("  Speakers\n"));
+        assert!(report.contains("== Loudness cache ==\n"));
+        assert!(report.contains("not created yet"));
+    }
+
+    #[test]
+    fn test_anonymize() {
+        let dir = Path::new("/home/alice/Music");
+        let mut out = Vec::new();
+        write_environment(&mut out, &settings(dir), &audio(), PathStyle { anonymize: true }).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(!out.contains("alice"), "leaked a path:\n{}", out);
+
+        let paths = PathStyle { anonymize: true };
+        let song = paths.show(Path::new("/home/alice/Music/song.mp3"));
+        assert!(song.starts_with('<') && song.ends_with(">.mp3"));
+        assert_eq!(song, paths.show(Path::new("/home/alice/Music/song.mp3")));
+        // The same in every build and on every run
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+}
@@ -0,0 +1,189 @@
+//! Gain ramps applied inside a sink's source.
+//!
+//! A ramp moves the gain in a straight line towards a target, one sample
+//! at a time, so there are no steps to hear, however rarely the player
+//! asks for it. It is what `playback.track_change_fade_ms` fades the
+//! outgoing track down and the incoming one up with, and what
+//! `playback.startup_fade_secs` brings the first track in with.
+
+use rodio::source::SeekError;
+use rodio::Source;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Samples between reads of the target from the player.
+const CONTROL_INTERVAL: usize = 256;
+
+/// Shared between the player and the ramp on the audio thread.
+#[derive(Debug)]
+pub struct FadeControl {
+    /// Gain to ramp to, as `f32` bits.
+    target: AtomicU32,
+    /// Time a ramp over the full range takes, in milliseconds.
+    length_ms: AtomicU32,
+    /// Gain reached so far, as `f32` bits, so a source built again for the
+    /// same track carries on from it.
+    gain: AtomicU32,
+}
+
+impl FadeControl {
+    /// Creates a control starting at `gain`, and staying there.
+    pub fn new(gain: f32) -> Arc<Self> {
+        Arc::new(Self {
+            target: AtomicU32::new(gain.to_bits()),
+            length_ms: AtomicU32::new(0),
+            gain: AtomicU32::new(gain.to_bits()),
+        })
+    }
+
+    /// Ramps the gain to `target` (0.0 to 1.0), going from silence to full
+    /// in `length`; zero jumps straight there.
+    pub fn ramp_to(&self, target: f32, length: Duration) {
+        let length_ms = u32::try_from(length.as_millis()).unwrap_or(u32::MAX);
+        self.length_ms.store(length_ms, Ordering::Relaxed);
+        self.target.store(target.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    /// Returns the gain reached so far.
+    pub fn gain(&self) -> f32 {
+        f32::from_bits(self.gain.load(Ordering::Relaxed))
+    }
+
+    /// Returns the gain being ramped to.
+    pub fn target(&self) -> f32 {
+        f32::from_bits(self.target.load(Ordering::Relaxed))
+    }
+}
+
+/// Applies a ramped gain to a source.
+pub struct Fade<S> {
+    source: S,
+    control: Arc<FadeControl>,
+    gain: f32,
+    target: f32,
+    /// Gain change per sample.
+    step: f32,
+    until_control: usize,
+    samples_per_ms: f32,
+}
+
+impl<S: Source<Item = f32>> Fade<S> {
+    /// Wraps a source, starting from the gain the control has reached.
+    pub fn new(source: S, control: Arc<FadeControl>) -> Self {
+        // Channels are interleaved, so every sample moves time on by 1 / (rate × channels)
+        let samples_per_ms = source.sample_rate() as f32 * f32::from(source.channels().max(1)) / 1000.0;
+        let gain = control.gain();
+        Self {
+            source,
+            control,
+            gain,
+            target: gain,
+            step: 1.0,
+            until_control: 0,
+            samples_per_ms,
+        }
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for Fade<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.source.next()?;
+        if self.until_control == 0 {
+            self.target = self.control.target();
+            let length = self.control.length_ms.load(Ordering::Relaxed) as f32 * self.samples_per_ms;
+            self.step = if length >= 1.0 { 1.0 / length } else { 1.0 };
+            self.control.gain.store(self.gain.to_bits(), Ordering::Relaxed);
+            self.until_control = CONTROL_INTERVAL;
+        }
+        self.until_control -= 1;
+
+        if self.gain < self.target {
+            self.gain = (self.gain + self.step).min(self.target);
+        } else if self.gain > self.target {
+            self.gain = (self.gain - self.step).max(self.target);
+        }
+        Some(sample * self.gain)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.source.size_hint()
+    }
+}
+
+impl<S: Source<Item = f32>> Source for Fade<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.source.try_seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    /// Full-scale samples at 48 kHz mono, so the output is the gain.
+    fn constant(length: Duration) -> SamplesBuffer<f32> {
+        SamplesBuffer::new(1, 48_000, vec![1.0; length.as_millis() as usize * 48])
+    }
+
+    #[test]
+    fn test_ramp_in_over_length() {
+        let control = FadeControl::new(0.0);
+        control.ramp_to(1.0, Duration::from_millis(100));
+        let gains: Vec<f32> = Fade::new(constant(Duration::from_millis(200)), control.clone()).collect();
+        // 48 samples a millisecond: silent at first, halfway at 50ms, full from 100ms
+        assert!(gains[0] < 0.001);
+        assert!((gains[2400] - 0.5).abs() < 0.01, "{}", gains[2400]);
+        assert!(gains[4800..].iter().all(|&gain| gain == 1.0));
+        assert!(gains.windows(2).all(|pair| pair[1] >= pair[0]));
+        assert_eq!(control.gain(), 1.0);
+    }
+
+    #[test]
+    fn test_ramp_curve_over_seconds() {
+        // One sample a millisecond, so the sample count is the clock
+        let control = FadeControl::new(0.0);
+        control.ramp_to(1.0, Duration::from_secs(4));
+        let gains: Vec<f32> = Fade::new(SamplesBuffer::new(1, 1000, vec![1.0; 5000]), control).collect();
+        for (ms, expected) in [(0, 0.0), (1000, 0.25), (2000, 0.5), (3000, 0.75), (4000, 1.0), (4999, 1.0)] {
+            assert!((gains[ms] - expected).abs() < 0.001, "{}ms: {}", ms, gains[ms]);
+        }
+    }
+
+    #[test]
+    fn test_new_source_carries_on_from_reached_gain() {
+        let control = FadeControl::new(1.0);
+        control.ramp_to(0.0, Duration::from_millis(100));
+        Fade::new(constant(Duration::from_millis(50)), control.clone()).for_each(drop);
+        let reached = control.gain();
+        assert!(reached > 0.4 && reached < 0.6, "{}", reached);
+
+        // Built again, as after a seek, the ramp goes on down rather than jumping back
+        let first = Fade::new(constant(Duration::from_millis(10)), control.clone()).next().unwrap();
+        assert!(first < reached && reached - first < 0.01);
+
+        // No length jumps at once
+        control.ramp_to(1.0, Duration::ZERO);
+        let gains: Vec<f32> = Fade::new(constant(Duration::from_millis(1)), control).collect();
+        assert!(gains.iter().all(|&gain| gain == 1.0));
+    }
+}
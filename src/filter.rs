@@ -0,0 +1,174 @@
+//! Which files a directory scan picks up: the extension whitelist, exclude
+//! globs from the config, and `.jukeignore` files.
+//!
+//! Globs support `*` and `?` within one path component and `**` across
+//! components. A pattern without a `/` matches the file or directory name alone.
+
+use std::fs;
+use std::path::Path;
+
+/// Name of the per-directory ignore file.
+pub const IGNORE_FILE: &str = ".jukeignore";
+
+/// Extensions scanned when `playlist.extensions` is not set.
+pub const DEFAULT_EXTENSIONS: &[&str] = &["mp3", "flac", "ogg"];
+
+/// Extension whitelist and exclude globs applied during scanning.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanFilter {
+    extensions: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl Default for ScanFilter {
+    fn default() -> Self {
+        Self {
+            extensions: DEFAULT_EXTENSIONS.iter().map(|ext| ext.to_string()).collect(),
+            exclude: Vec::new(),
+        }
+    }
+}
+
+impl ScanFilter {
+    /// Builds a filter, normalizing extensions to lowercase without a leading dot.
+    pub fn new(extensions: &[String], exclude: &[String]) -> Self {
+        Self {
+            extensions: extensions.iter().map(|ext| normalize_extension(ext)).collect(),
+            exclude: exclude.to_vec(),
+        }
+    }
+
+    /// Returns true if the file has a whitelisted extension.
+    pub fn is_audio_file(&self, path: &Path) -> bool {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| self.extensions.iter().any(|e| e.eq_ignore_ascii_case(ext)))
+    }
+
+    /// Returns true if an exclude glob matches the full path.
+    pub fn is_excluded(&self, path: &Path, is_dir: bool) -> bool {
+        matches_any(&self.exclude, path, is_dir)
+    }
+}
+
+/// Normalizes an extension to lowercase without a leading dot.
+pub fn normalize_extension(extension: &str) -> String {
+    extension.trim().trim_start_matches('.').to_lowercase()
+}
+
+/// Reads the patterns from a directory's `.jukeignore`, skipping blank lines
+/// and `#` comments. Returns nothing if the file is missing.
+pub(crate) fn read_ignore_file(dir: &Path) -> Vec<String> {
+    fs::read_to_string(dir.join(IGNORE_FILE))
+        .map(|contents| parse_ignore(&contents))
+        .unwrap_or_default()
+}
+
+/// Parses `.jukeignore` contents.
+fn parse_ignore(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
+
+/// Returns true if any pattern matches the path.
+///
+/// Directories also match patterns ending in `/**`, so whole subtrees are
+/// skipped without descending into them.
+pub(crate) fn matches_any(patterns: &[String], path: &Path, is_dir: bool) -> bool {
+    patterns.iter().any(|pattern| matches(pattern, path, is_dir))
+}
+
+/// Returns true if the pattern matches the path, as [`matches_any`] does.
+pub(crate) fn matches(pattern: &str, path: &Path, is_dir: bool) -> bool {
+    let text = path.to_string_lossy().replace(std::path::MAIN_SEPARATOR, "/");
+    let name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+    let pattern = pattern.trim_end_matches('/');
+    let target = if pattern.contains('/') { text.as_str() } else { &name };
+    glob_match(pattern, target) || (is_dir && pattern.strip_suffix("/**").is_some_and(|dir| glob_match(dir, &text)))
+}
+
+/// Matches text against a glob.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    glob_match_chars(&pattern, &text)
+}
+
+fn glob_match_chars(pattern: &[char], text: &[char]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        ['*', '*', rest @ ..] => {
+            // `**/` also matches no directories at all
+            if let ['/', after @ ..] = rest
+                && glob_match_chars(after, text)
+            {
+                return true;
+            }
+            (0..=text.len()).any(|i| glob_match_chars(rest, &text[i..]))
+        }
+        ['*', rest @ ..] => {
+            for i in 0..=text.len() {
+                if glob_match_chars(rest, &text[i..]) {
+                    return true;
+                }
+                if text.get(i) == Some(&'/') {
+                    break;
+                }
+            }
+            false
+        }
+        ['?', rest @ ..] => {
+            matches!(text.first(), Some(c) if *c != '/') && glob_match_chars(rest, &text[1..])
+        }
+        [c, rest @ ..] => text.first() == Some(c) && glob_match_chars(rest, &text[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extensions_are_case_insensitive() {
+        let filter = ScanFilter::new(&["MKA".to_string(), ".wv".to_string()], &[]);
+        assert!(filter.is_audio_file(Path::new("a.mka")));
+        assert!(filter.is_audio_file(Path::new("a.WV")));
+        assert!(!filter.is_audio_file(Path::new("a.mp3")));
+        assert!(!filter.is_audio_file(Path::new("mka")));
+
+        assert!(ScanFilter::default().is_audio_file(Path::new("song.FLAC")));
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.ogg", "ring.ogg"));
+        assert!(!glob_match("*.ogg", "dir/ring.ogg"));
+        assert!(glob_match("**/*.ogg", "/music/dir/ring.ogg"));
+        assert!(glob_match("/music/**/live/*", "/music/live/a.mp3"));
+        assert!(glob_match("/music/**/live/*", "/music/x/y/live/a.mp3"));
+        assert!(glob_match("track??.mp3", "track01.mp3"));
+        assert!(!glob_match("track?.mp3", "track01.mp3"));
+    }
+
+    #[test]
+    fn test_exclude_subdirectory() {
+        let filter = ScanFilter::new(&[], &["**/Ringtones/**".to_string()]);
+        assert!(filter.is_excluded(Path::new("/music/Phone/Ringtones"), true));
+        assert!(filter.is_excluded(Path::new("/music/Phone/Ringtones/beep.ogg"), false));
+        assert!(!filter.is_excluded(Path::new("/music/Phone/Songs/a.ogg"), false));
+
+        // Patterns without a slash match names anywhere
+        let filter = ScanFilter::new(&[], &["Ringtones".to_string(), "*.tmp".to_string()]);
+        assert!(filter.is_excluded(Path::new("/music/Phone/Ringtones"), true));
+        assert!(filter.is_excluded(Path::new("/music/a.tmp"), false));
+    }
+
+    #[test]
+    fn test_parse_ignore() {
+        assert_eq!(parse_ignore("# comment\n\n*.ogg\n  demos/ \n"), ["*.ogg", "demos/"]);
+    }
+}
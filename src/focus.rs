@@ -0,0 +1,177 @@
+//! Which pane keys go to when several are on screen.
+//!
+//! Each screen shows one or more panes, and one of them has the focus:
+//! keys go to its handler, except for a few global keys that work whichever
+//! pane has it. A pane claims the keys it needs first, so typing into a
+//! search or prompt never quits or changes the volume. Tab and Shift+Tab
+//! move the focus between the panes on screen.
+
+use crate::actions::{Action, Keymap};
+use crossterm::event::{KeyCode, KeyEvent};
+
+/// A pane that can have the focus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Focus {
+    NowPlaying,
+    TrackList,
+    /// The upcoming tracks panel beside the player.
+    Queue,
+    Browser,
+    Prompt,
+}
+
+/// Actions whose keys work whichever pane has the focus, unless it claims
+/// them.
+const GLOBAL: &[Action] =
+    &[Action::Quit, Action::Help, Action::VolumeUp, Action::VolumeDown, Action::FocusNext, Action::FocusPrevious];
+
+/// Where a key press goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Route {
+    /// A global action, run whichever pane has the focus.
+    Global(Action),
+    /// The focused pane's handler.
+    Pane,
+}
+
+impl Focus {
+    /// Whether the pane handles a key itself, before any global key.
+    fn claims(self, key: &KeyEvent) -> bool {
+        let cycles = matches!(key.code, KeyCode::Tab | KeyCode::BackTab);
+        match self {
+            // Modal: Tab is not for leaving it
+            Focus::Prompt => true,
+            // Characters are search input or track list commands
+            Focus::TrackList => !cycles,
+            Focus::Browser => matches!(
+                key.code,
+                KeyCode::Up
+                    | KeyCode::Down
+                    | KeyCode::Enter
+                    | KeyCode::Right
+                    | KeyCode::Left
+                    | KeyCode::Backspace
+                    | KeyCode::Esc
+                    | KeyCode::Char('p' | 'q' | 'b')
+            ),
+            Focus::Queue => matches!(key.code, KeyCode::Up | KeyCode::Down | KeyCode::Enter | KeyCode::Esc),
+            // Esc cancels a pending seek or task before it quits
+            Focus::NowPlaying => key.code == KeyCode::Esc,
+        }
+    }
+}
+
+/// Decides where a key press goes while `focus` has the focus.
+pub fn route(focus: Focus, keymap: &Keymap, key: &KeyEvent) -> Route {
+    if focus.claims(key) {
+        return Route::Pane;
+    }
+    GLOBAL
+        .iter()
+        .copied()
+        .find(|&action| keymap.keys(action).iter().any(|k| k.matches(key)))
+        .map_or(Route::Pane, Route::Global)
+}
+
+/// Returns the pane after `current` among `panes`, or before it going
+/// backward, wrapping around. A pane not on screen gives way to the first.
+pub fn cycle(panes: &[Focus], current: Focus, forward: bool) -> Option<Focus> {
+    let at = panes.iter().position(|&pane| pane == current);
+    let next = match (at, forward) {
+        (None, _) => 0,
+        (Some(at), true) => (at + 1) % panes.len(),
+        (Some(at), false) => (at + panes.len() - 1) % panes.len(),
+    };
+    panes.get(next).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::{KeyEventKind, KeyModifiers};
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new_with_kind(code, KeyModifiers::NONE, KeyEventKind::Press)
+    }
+
+    fn routes(focus: Focus, codes: &[KeyCode]) -> Vec<Route> {
+        codes.iter().map(|&code| route(focus, &Keymap::default(), &key(code))).collect()
+    }
+
+    const KEYS: [KeyCode; 7] =
+        [KeyCode::Char('q'), KeyCode::Char('?'), KeyCode::Char('='), KeyCode::Esc, KeyCode::Up, KeyCode::Tab, KeyCode::Char('n')];
+
+    #[test]
+    fn test_now_playing_routing() {
+        assert_eq!(
+            routes(Focus::NowPlaying, &KEYS),
+            [
+                Route::Global(Action::Quit),
+                Route::Global(Action::Help),
+                Route::Global(Action::VolumeUp),
+                Route::Pane,
+                Route::Pane,
+                Route::Global(Action::FocusNext),
+                Route::Pane,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_queue_routing() {
+        assert_eq!(
+            routes(Focus::Queue, &KEYS),
+            [
+                Route::Global(Action::Quit),
+                Route::Global(Action::Help),
+                Route::Global(Action::VolumeUp),
+                Route::Pane,
+                Route::Pane,
+                Route::Global(Action::FocusNext),
+                Route::Pane,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_track_list_routing() {
+        // Every character may be part of a search
+        let mut expected = [Route::Pane; 7];
+        expected[5] = Route::Global(Action::FocusNext);
+        assert_eq!(routes(Focus::TrackList, &KEYS), expected);
+        assert_eq!(route(Focus::TrackList, &Keymap::default(), &key(KeyCode::BackTab)), Route::Global(Action::FocusPrevious));
+    }
+
+    #[test]
+    fn test_browser_routing() {
+        assert_eq!(
+            routes(Focus::Browser, &KEYS),
+            [
+                Route::Pane,
+                Route::Global(Action::Help),
+                Route::Global(Action::VolumeUp),
+                Route::Pane,
+                Route::Pane,
+                Route::Global(Action::FocusNext),
+                Route::Pane,
+            ]
+        );
+        assert_eq!(route(Focus::Browser, &Keymap::default(), &key(KeyCode::Char('-'))), Route::Global(Action::VolumeDown));
+    }
+
+    #[test]
+    fn test_prompt_routing() {
+        assert_eq!(routes(Focus::Prompt, &KEYS), [Route::Pane; 7]);
+    }
+
+    #[test]
+    fn test_cycle() {
+        let panes = [Focus::NowPlaying, Focus::Queue];
+        assert_eq!(cycle(&panes, Focus::NowPlaying, true), Some(Focus::Queue));
+        assert_eq!(cycle(&panes, Focus::Queue, true), Some(Focus::NowPlaying));
+        assert_eq!(cycle(&panes, Focus::NowPlaying, false), Some(Focus::Queue));
+        assert_eq!(cycle(&[Focus::TrackList], Focus::TrackList, true), Some(Focus::TrackList));
+        assert_eq!(cycle(&[Focus::TrackList], Focus::Queue, false), Some(Focus::TrackList));
+        assert_eq!(cycle(&[], Focus::Queue, true), None);
+    }
+}
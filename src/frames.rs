@@ -0,0 +1,252 @@
+//! Exact lengths of MP3 files, counted frame by frame.
+//!
+//! A variable bitrate MP3 without a Xing or VBRI header does not say how
+//! long it is, so tag readers and decoders estimate it from the bitrate of
+//! the first frames, which can be off by a fifth. Walking every frame header
+//! counts the samples actually there instead. That reads the whole file, so
+//! it is work for a background task.
+
+use crate::task::Reporter;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+use std::time::Duration;
+
+/// How far apart the length a track claims and how long it played may be,
+/// as a fraction of the claim, before it is measured.
+pub const TOLERANCE: f64 = 0.05;
+
+/// Tracks claiming less are not compared, since the player notices an end
+/// only at its next update.
+const SHORTEST_COMPARED: Duration = Duration::from_secs(20);
+
+/// Bytes read between progress reports.
+const PROGRESS_STEP: u64 = 1 << 20;
+
+/// Kilobits per second by bitrate index, for MPEG-1 layers I, II and III,
+/// then MPEG-2 and 2.5 layer I, then their layers II and III.
+const BITRATES: [[u32; 15]; 5] = [
+    [0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448],
+    [0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384],
+    [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320],
+    [0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256],
+    [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+];
+
+/// Sample rates of MPEG-1 by index; MPEG-2 halves them and 2.5 quarters them.
+const SAMPLE_RATES: [u32; 3] = [44100, 48000, 32000];
+
+/// What one frame header says.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Frame {
+    /// The version and layer bits, the same for every frame of a stream.
+    stream: u8,
+    sample_rate: u32,
+    samples: u32,
+    /// Bytes in the frame, header included.
+    length: u32,
+}
+
+impl Frame {
+    /// Parses a frame header, if `bytes` are one.
+    fn parse(bytes: [u8; 4]) -> Option<Self> {
+        if bytes[0] != 0xFF || bytes[1] & 0xE0 != 0xE0 {
+            return None;
+        }
+        // 0 is MPEG-2.5, 1 is reserved, 2 is MPEG-2 and 3 is MPEG-1
+        let version = (bytes[1] >> 3) & 3;
+        let layer = 4 - ((bytes[1] >> 1) & 3);
+        let bitrate_index = usize::from(bytes[2] >> 4);
+        let rate_index = usize::from((bytes[2] >> 2) & 3);
+        // Free format frames give no length to skip by
+        if version == 1 || layer == 4 || bitrate_index == 0 || bitrate_index == 15 || rate_index == 3 {
+            return None;
+        }
+        let mpeg1 = version == 3;
+        let table = match (mpeg1, layer) {
+            (true, layer) => usize::from(layer) - 1,
+            (false, 1) => 3,
+            (false, _) => 4,
+        };
+        let bitrate = BITRATES[table][bitrate_index] * 1000;
+        let sample_rate = SAMPLE_RATES[rate_index] >> [2, 0, 1, 0][usize::from(version)];
+        let samples = match (layer, mpeg1) {
+            (1, _) => 384,
+            (2, _) | (3, true) => 1152,
+            _ => 576,
+        };
+        let padding = u32::from((bytes[2] >> 1) & 1);
+        let length = match layer {
+            1 => (12 * bitrate / sample_rate + padding) * 4,
+            _ => samples / 8 * bitrate / sample_rate + padding,
+        };
+        Some(Self { stream: bytes[1] & 0x1E, sample_rate, samples, length })
+    }
+}
+
+/// Whether the file at `path` is one [`measure`] can count.
+pub fn measurable(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("mp3"))
+}
+
+/// Whether a track that played for `played` before its file ran out claimed
+/// a length too far off, by more than [`TOLERANCE`].
+pub fn disagrees(claimed: Duration, played: Duration) -> bool {
+    claimed >= SHORTEST_COMPARED
+        && (claimed.as_secs_f64() - played.as_secs_f64()).abs() > claimed.as_secs_f64() * TOLERANCE
+}
+
+/// Measures the MP3 at `path` as the work of a task, sending its length, or
+/// `None` when it could not be read or has no frames. Progress is in bytes.
+pub fn measure(path: &Path, reporter: &Reporter<Option<Duration>>) {
+    let Ok(file) = File::open(path) else {
+        reporter.send(None);
+        return;
+    };
+    let total = file.metadata().ok().map(|metadata| metadata.len() as usize);
+    let length = count(file, |read| reporter.progress(read as usize, total)).ok().flatten();
+    reporter.send(length);
+}
+
+/// Counts the frames of an MP3 stream and returns its length, calling
+/// `progress` with the bytes read now and then; when that returns false,
+/// counting stops with nothing measured.
+fn count<R: Read>(reader: R, mut progress: impl FnMut(u64) -> bool) -> io::Result<Option<Duration>> {
+    let mut reader = BufReader::new(reader);
+    let mut read = skip_id3v2(&mut reader)?;
+    let mut reported = read;
+    let mut first: Option<Frame> = None;
+    let mut samples: u64 = 0;
+    let mut header = [0u8; 4];
+    let mut filled = 0;
+    loop {
+        if !fill(&mut reader, &mut header[filled..])? {
+            break;
+        }
+        let same_stream = |frame: &Frame| {
+            first.is_none_or(|first| first.stream == frame.stream && first.sample_rate == frame.sample_rate)
+        };
+        match Frame::parse(header).filter(same_stream) {
+            Some(frame) => {
+                first.get_or_insert(frame);
+                samples += u64::from(frame.samples);
+                let body = u64::from(frame.length - 4);
+                read += 4 + io::copy(&mut (&mut reader).take(body), &mut io::sink())?;
+                filled = 0;
+            }
+            // Not a frame, or one of another stream: look for the next one a
+            // byte further on
+            None => {
+                header.rotate_left(1);
+                read += 1;
+                filled = 3;
+            }
+        }
+        if read - reported >= PROGRESS_STEP {
+            reported = read;
+            if !progress(read) {
+                return Ok(None);
+            }
+        }
+    }
+    Ok(first.map(|first| Duration::from_secs_f64(samples as f64 / f64::from(first.sample_rate))))
+}
+
+/// Skips an ID3v2 tag at the start of the stream, returning its size.
+fn skip_id3v2<R: Read>(reader: &mut BufReader<R>) -> io::Result<u64> {
+    let head = reader.fill_buf()?;
+    if head.len() < 10 || !head.starts_with(b"ID3") {
+        return Ok(0);
+    }
+    // The size is stored in seven bits of each byte, and leaves out the
+    // header and any footer
+    let size = head[6..10].iter().fold(0u64, |size, &byte| size << 7 | u64::from(byte & 0x7F));
+    let footer = if head[5] & 0x10 != 0 { 10 } else { 0 };
+    reader.consume(10);
+    let skipped = io::copy(&mut reader.take(size + footer), &mut io::sink())?;
+    Ok(10 + skipped)
+}
+
+/// Fills `buf` from `reader`, returning false at the end of the stream.
+fn fill(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<bool> {
+    match reader.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An MPEG-1 layer III frame at 44.1 kHz of the bitrate with `index`.
+    fn frame(index: u8, padding: bool) -> Vec<u8> {
+        let header = [0xFF, 0xFB, index << 4 | u8::from(padding) << 1, 0x44];
+        let length = Frame::parse(header).unwrap().length as usize;
+        let mut frame = header.to_vec();
+        frame.resize(length, 0);
+        frame
+    }
+
+    #[test]
+    fn test_frame_lengths() {
+        let parse = |bytes| Frame::parse(bytes).map(|frame: Frame| (frame.sample_rate, frame.samples, frame.length));
+        // MPEG-1 layer III, 128 and 320 kbps at 44.1 kHz, padded or not
+        assert_eq!(parse([0xFF, 0xFB, 0x90, 0x44]), Some((44100, 1152, 417)));
+        assert_eq!(parse([0xFF, 0xFB, 0x92, 0x44]), Some((44100, 1152, 418)));
+        assert_eq!(parse([0xFF, 0xFB, 0xE0, 0x44]), Some((44100, 1152, 1044)));
+        // MPEG-2 layer III, 64 kbps at 22.05 kHz
+        assert_eq!(parse([0xFF, 0xF3, 0x80, 0x44]), Some((22050, 576, 208)));
+        // MPEG-1 layer I, 384 kbps at 48 kHz
+        assert_eq!(parse([0xFF, 0xFF, 0xC4, 0x44]), Some((48000, 384, 384)));
+        // Free format, a bad bitrate, a reserved rate, and no sync
+        assert_eq!(parse([0xFF, 0xFB, 0x00, 0x44]), None);
+        assert_eq!(parse([0xFF, 0xFB, 0xF0, 0x44]), None);
+        assert_eq!(parse([0xFF, 0xFB, 0x9C, 0x44]), None);
+        assert_eq!(parse([0x49, 0x44, 0x33, 0x04]), None);
+    }
+
+    #[test]
+    fn test_count_variable_bitrate_frames() {
+        // An ID3v2 tag of 20 bytes, whose body holds a false sync
+        let mut stream = b"ID3\x04\x00\x00\x00\x00\x00\x14".to_vec();
+        stream.extend([0xFF, 0xFB, 0x90, 0x44]);
+        stream.resize(30, 0);
+        for (index, padding) in [(9, false), (14, true), (5, false), (14, false)] {
+            stream.extend(frame(index, padding));
+        }
+        // Junk between frames, and an ID3v1 tag at the end
+        stream.extend([0xFF, 0x00, 0x12]);
+        stream.extend(frame(11, false));
+        stream.extend(b"TAG");
+        stream.resize(stream.len() + 125, 0xFF);
+
+        let length = count(stream.as_slice(), |_| true).unwrap().unwrap();
+        assert_eq!(length, Duration::from_secs_f64(5.0 * 1152.0 / 44100.0));
+        assert_eq!(count([0u8; 100].as_slice(), |_| true).unwrap(), None);
+    }
+
+    #[test]
+    fn test_count_stops_when_cancelled() {
+        let stream: Vec<u8> = (0..3000).flat_map(|_| frame(14, false)).collect();
+        let mut reports = Vec::new();
+        let length = count(stream.as_slice(), |read| {
+            reports.push(read);
+            false
+        });
+        assert_eq!(length.unwrap(), None);
+        assert_eq!(reports.len(), 1);
+    }
+
+    #[test]
+    fn test_disagrees() {
+        let secs = Duration::from_secs;
+        assert!(!disagrees(secs(200), secs(209)));
+        assert!(disagrees(secs(200), secs(211)));
+        assert!(disagrees(secs(200), secs(160)));
+        // Too short to tell, or of unknown length
+        assert!(!disagrees(secs(10), secs(5)));
+        assert!(!disagrees(Duration::ZERO, secs(200)));
+    }
+}
@@ -0,0 +1,105 @@
+//! Deciding how one track runs into the next.
+//!
+//! Gaps only matter between tracks meant to run together, like the songs of
+//! a live album, so by default the next track is readied ahead of time only
+//! when it continues the album of the one playing.
+
+use crate::config::{Gapless, PlaybackConfig};
+use crate::discs;
+use crate::playlist::Track;
+
+/// How a track that plays to its end goes over to the next one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transition {
+    /// The next track is decoded ahead of time and starts straight after,
+    /// with no fade.
+    Gapless,
+    /// The next track is opened when the current one ends.
+    Gap,
+}
+
+/// Decides how `current` goes over to `next`, the track that actually plays
+/// after it, under `playback.gapless`.
+pub fn transition(current: &Track, next: &Track, config: &PlaybackConfig) -> Transition {
+    let gapless = match config.gapless {
+        Gapless::Always => true,
+        Gapless::Album => continues_album(current, next),
+        Gapless::Never => false,
+    };
+    if gapless { Transition::Gapless } else { Transition::Gap }
+}
+
+/// Whether `next` is the track after `current` on the same album: the
+/// following track number on the same disc, or the first of the next disc,
+/// even when each disc is tagged as an album of its own.
+/// Compilations name a different artist on each track, so tracks from the
+/// same directory count as one album too.
+pub fn continues_album(current: &Track, next: &Track) -> bool {
+    let same_album = match (&current.album, &next.album) {
+        (Some(album), Some(next_album)) => discs::album_key(album) == discs::album_key(next_album),
+        _ => false,
+    };
+    let same_release = current.artist == next.artist || current.path.parent() == next.path.parent();
+    let (Some(number), Some(next_number)) = (current.track_number, next.track_number) else {
+        return false;
+    };
+    let (disc, next_disc) = (discs::disc_of(current).unwrap_or(1), discs::disc_of(next).unwrap_or(1));
+    let follows = (next_disc == disc && next_number == number + 1) || (next_disc == disc + 1 && next_number == 1);
+    same_album && same_release && follows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn track(path: &str, album: &str, artist: &str, disc: Option<u32>, number: u32) -> Track {
+        let mut track = Track::new(PathBuf::from(path));
+        track.album = Some(album.to_string());
+        track.artist = Some(artist.to_string());
+        track.disc_number = disc;
+        track.track_number = Some(number);
+        track
+    }
+
+    fn config(gapless: Gapless) -> PlaybackConfig {
+        PlaybackConfig { gapless, ..PlaybackConfig::default() }
+    }
+
+    #[test]
+    fn test_album_continuity() {
+        let first = track("/live/01.flac", "Live", "Band", None, 1);
+        let second = track("/live/02.flac", "Live", "Band", None, 2);
+        let fourth = track("/live/04.flac", "Live", "Band", None, 4);
+        let other = track("/studio/02.flac", "Studio", "Band", None, 2);
+        assert!(continues_album(&first, &second));
+        assert!(!continues_album(&second, &first));
+        assert!(!continues_album(&second, &fourth));
+        assert!(!continues_album(&first, &other));
+
+        // Over to the next disc, and a compilation's next artist
+        let disc_end = track("/box/1-12.flac", "Box", "Band", Some(1), 12);
+        let disc_start = track("/box/2-01.flac", "Box", "Band", Some(2), 1);
+        assert!(continues_album(&disc_end, &disc_start));
+        let various = track("/live/02b.flac", "Live", "Guest", None, 2);
+        assert!(continues_album(&first, &various));
+        let elsewhere = track("/other/02.flac", "Live", "Guest", None, 2);
+        assert!(!continues_album(&first, &elsewhere));
+
+        // Without track numbers nothing is known to follow
+        let mut untagged = second.clone();
+        untagged.track_number = None;
+        assert!(!continues_album(&first, &untagged));
+    }
+
+    #[test]
+    fn test_transition_modes() {
+        let first = track("/live/01.flac", "Live", "Band", None, 1);
+        let second = track("/live/02.flac", "Live", "Band", None, 2);
+        let other = track("/studio/07.flac", "Studio", "Band", None, 7);
+        assert_eq!(transition(&first, &second, &config(Gapless::Album)), Transition::Gapless);
+        assert_eq!(transition(&first, &other, &config(Gapless::Album)), Transition::Gap);
+        assert_eq!(transition(&first, &other, &config(Gapless::Always)), Transition::Gapless);
+        assert_eq!(transition(&first, &second, &config(Gapless::Never)), Transition::Gap);
+    }
+}
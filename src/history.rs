@@ -0,0 +1,313 @@
+//! A log of every track played to the end or skipped, kept in
+//! `history.jsonl` in the state directory and exported with
+//! `juke --export-history`.
+//!
+//! Each listen is appended as one line of JSON, so recording one does not
+//! rewrite the whole file, and a line torn by a crash loses only that
+//! listen.
+
+use crate::dates;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// One listen.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    /// Seconds since the Unix epoch when the track was left.
+    pub time: u64,
+    pub path: PathBuf,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album: Option<String>,
+    /// How much of the track was heard, in seconds.
+    pub listened_secs: u64,
+    /// Played to the end, or at least half of it; false for a skip.
+    pub completed: bool,
+}
+
+/// Errors reading the history or exporting it.
+#[derive(Debug)]
+pub enum HistoryError {
+    Io(std::io::Error),
+    /// The export file's extension is neither `.csv` nor `.json`.
+    UnknownFormat(PathBuf),
+    /// A `--since` or `--until` date is not `YYYY-MM-DD`.
+    BadDate(String),
+}
+
+impl std::fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HistoryError::Io(e) => write!(f, "Cannot read history: {}", e),
+            HistoryError::UnknownFormat(path) => {
+                write!(f, "Cannot tell the format of {}: use a .csv or .json file", path.display())
+            }
+            HistoryError::BadDate(date) => write!(f, "Invalid date {:?}, expected YYYY-MM-DD", date),
+        }
+    }
+}
+
+impl std::error::Error for HistoryError {}
+
+/// Returns the history file in a state directory.
+pub fn path(state_dir: &Path) -> PathBuf {
+    state_dir.join("history.jsonl")
+}
+
+/// Appends a listen to the history in a state directory, with a single
+/// write so it is never interleaved with another.
+pub fn append(state_dir: &Path, entry: &Entry) -> std::io::Result<()> {
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+    fs::create_dir_all(state_dir)?;
+    let mut file = OpenOptions::new().create(true).append(true).open(path(state_dir))?;
+    file.write_all(line.as_bytes())
+}
+
+/// Reads the history from a state directory, oldest first, with a warning
+/// for each line skipped because it does not parse, like the last one when
+/// juke died while appending it. A missing file is an empty history.
+pub fn load(state_dir: &Path) -> Result<(Vec<Entry>, Vec<String>), HistoryError> {
+    let bytes = match fs::read(path(state_dir)) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((Vec::new(), Vec::new())),
+        Err(e) => return Err(HistoryError::Io(e)),
+    };
+    // A torn line can end partway through a character
+    let contents = String::from_utf8_lossy(&bytes);
+    let mut entries = Vec::new();
+    let mut warnings = Vec::new();
+    for (number, line) in contents.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        match serde_json::from_str(line) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => warnings.push(format!("Skipping line {} of the play history: {}", number + 1, e)),
+        }
+    }
+    Ok((entries, warnings))
+}
+
+/// Keeps the listens from the start of `since` to the end of `until`, both
+/// `YYYY-MM-DD` dates in UTC.
+pub fn filter(entries: Vec<Entry>, since: Option<&str>, until: Option<&str>) -> Result<Vec<Entry>, HistoryError> {
+    let day = |date: &str| parse_date(date).ok_or_else(|| HistoryError::BadDate(date.to_string()));
+    let from = since.map(day).transpose()?.unwrap_or(0);
+    let to = until.map(day).transpose()?.map_or(u64::MAX, |start| start + SECS_PER_DAY);
+    Ok(entries.into_iter().filter(|entry| entry.time >= from && entry.time < to).collect())
+}
+
+/// Parses a `YYYY-MM-DD` date into the Unix time of its midnight (UTC).
+fn parse_date(text: &str) -> Option<u64> {
+    let mut parts = text.split('-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: i64 = parts.next()?.parse().ok()?;
+    let day: i64 = parts.next()?.parse().ok()?;
+    if parts.next().is_some() || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let secs = u64::try_from(dates::days_from_civil(year, month, day)).ok()? * SECS_PER_DAY;
+    // Reject dates like 2025-02-30 that roll over into the next month
+    (dates::date(secs) == text).then_some(secs)
+}
+
+/// How an export is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    Json,
+}
+
+impl Format {
+    /// Picks the format from a file's extension.
+    pub fn from_path(path: &Path) -> Result<Self, HistoryError> {
+        match path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase).as_deref() {
+            Some("csv") => Ok(Format::Csv),
+            Some("json") => Ok(Format::Json),
+            _ => Err(HistoryError::UnknownFormat(path.to_path_buf())),
+        }
+    }
+
+    /// Writes listens in this format.
+    pub fn write(self, entries: &[Entry]) -> String {
+        match self {
+            Format::Csv => to_csv(entries),
+            Format::Json => format!("{}\n", to_json(entries)),
+        }
+    }
+}
+
+/// Formats a Unix time as `YYYY-MM-DDThh:mm:ssZ`.
+fn timestamp(time: u64) -> String {
+    format!("{}Z", dates::format_timestamp(UNIX_EPOCH + Duration::from_secs(time)))
+}
+
+/// Columns of the CSV export.
+const CSV_HEADER: &str = "time,path,title,artist,album,listened,completed";
+
+/// Writes listens as CSV with a header row.
+fn to_csv(entries: &[Entry]) -> String {
+    let mut out = format!("{}\n", CSV_HEADER);
+    for entry in entries {
+        let fields = [
+            timestamp(entry.time),
+            entry.path.to_string_lossy().into_owned(),
+            entry.title.clone(),
+            entry.artist.clone().unwrap_or_default(),
+            entry.album.clone().unwrap_or_default(),
+            entry.listened_secs.to_string(),
+            entry.completed.to_string(),
+        ];
+        let quoted: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        out.push_str(&quoted.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+/// Quotes a CSV field if it contains a separator, quote, or line break,
+/// doubling any quotes inside.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Returns listens as a JSON array of objects, named like `--status`, with
+/// times in seconds.
+fn to_json(entries: &[Entry]) -> Value {
+    let plays = entries
+        .iter()
+        .map(|entry| {
+            json!({
+                "time": timestamp(entry.time),
+                "path": entry.path.to_string_lossy().to_string(),
+                "title": entry.title.clone(),
+                "artist": entry.artist.clone(),
+                "album": entry.album.clone(),
+                "listened": entry.listened_secs,
+                "completed": entry.completed,
+            })
+        })
+        .collect();
+    Value::Array(plays)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(time: u64, title: &str, completed: bool) -> Entry {
+        Entry {
+            time,
+            path: PathBuf::from(format!("/music/{}.mp3", title.len())),
+            title: title.to_string(),
+            artist: Some("Artist, The".to_string()),
+            album: None,
+            listened_secs: 95,
+            completed,
+        }
+    }
+
+    /// Splits CSV into rows of fields, undoing the quoting.
+    fn parse_csv(text: &str) -> Vec<Vec<String>> {
+        let mut rows = Vec::new();
+        let (mut row, mut field, mut quoted) = (Vec::new(), String::new(), false);
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match (c, quoted) {
+                ('"', true) if chars.peek() == Some(&'"') => {
+                    field.push('"');
+                    chars.next();
+                }
+                ('"', _) => quoted = !quoted,
+                (',', false) => row.push(std::mem::take(&mut field)),
+                ('\n', false) => {
+                    row.push(std::mem::take(&mut field));
+                    rows.push(std::mem::take(&mut row));
+                }
+                (c, _) => field.push(c),
+            }
+        }
+        rows
+    }
+
+    #[test]
+    fn test_history_round_trip() {
+        let dir = std::env::temp_dir().join(format!("juke_history_{}", std::process::id()));
+        let entries = [
+            entry(86_400, "Plain", true),
+            entry(2 * 86_400, "Comma, \"Quoted\"", false),
+            entry(3 * 86_400, "Line\nBreak", true),
+        ];
+        for entry in &entries {
+            append(&dir, entry).unwrap();
+        }
+        let (loaded, warnings) = load(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(loaded, entries);
+        assert!(warnings.is_empty(), "{:?}", warnings);
+
+        let rows = parse_csv(&Format::Csv.write(&loaded));
+        assert_eq!(rows.len(), entries.len() + 1);
+        assert_eq!(rows[0].join(","), CSV_HEADER);
+        assert_eq!(rows[2][2], "Comma, \"Quoted\"");
+        assert_eq!(rows[3][2], "Line\nBreak");
+        assert_eq!(rows[1][3], "Artist, The");
+        assert!(rows.iter().all(|row| row.len() == 7));
+        assert_eq!(rows[1][0], "1970-01-02T00:00:00Z");
+
+        let json = serde_json::from_str::<Value>(Format::Json.write(&loaded).trim()).unwrap();
+        let Value::Array(plays) = json else { panic!("not an array") };
+        assert_eq!(plays.len(), entries.len());
+        assert_eq!(plays[1].get("title").and_then(Value::as_str), Some("Comma, \"Quoted\""));
+        assert_eq!(plays[1].get("completed").and_then(Value::as_bool), Some(false));
+        assert_eq!(plays[0].get("album"), Some(&Value::Null));
+        assert_eq!(plays[0].get("listened").and_then(Value::as_f64), Some(95.0));
+    }
+
+    #[test]
+    fn test_load_skips_a_torn_entry() {
+        let dir = std::env::temp_dir().join(format!("juke_history_torn_{}", std::process::id()));
+        append(&dir, &entry(86_400, "Kept", true)).unwrap();
+        append(&dir, &entry(2 * 86_400, "Also kept", false)).unwrap();
+        // Cut the last line short, partway through a character
+        let mut file = OpenOptions::new().append(true).open(path(&dir)).unwrap();
+        file.write_all(&"{\"time\":259200,\"title\":\"Caf\u{e9}".as_bytes()[..28]).unwrap();
+
+        let (loaded, warnings) = load(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(loaded, [entry(86_400, "Kept", true), entry(2 * 86_400, "Also kept", false)]);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("line 3"), "{:?}", warnings);
+    }
+
+    #[test]
+    fn test_filter_by_date() {
+        let entries: Vec<Entry> = (0..5).map(|day| entry(day * SECS_PER_DAY + 3600, "Song", true)).collect();
+        let days = |since, until| {
+            let kept = filter(entries.clone(), since, until).unwrap();
+            kept.iter().map(|e| e.time / SECS_PER_DAY).collect::<Vec<_>>()
+        };
+        assert_eq!(days(Some("1970-01-02"), Some("1970-01-03")), [1, 2]);
+        assert_eq!(days(None, Some("1970-01-01")), [0]);
+        assert_eq!(days(Some("1970-01-04"), None), [3, 4]);
+        assert!(matches!(filter(entries, Some("2025-02-30"), None), Err(HistoryError::BadDate(_))));
+
+        assert_eq!(parse_date("2024-02-29"), Some(19_782 * SECS_PER_DAY));
+        assert_eq!(parse_date("2023-02-29"), None);
+        assert_eq!(parse_date("1969-12-31"), None);
+        assert_eq!(parse_date("2024-1-5"), None);
+        assert_eq!(Format::from_path(Path::new("out.CSV")).unwrap(), Format::Csv);
+        assert!(Format::from_path(Path::new("out.txt")).is_err());
+    }
+}
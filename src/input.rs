@@ -1,15 +1,20 @@
 //! Keyboard input handling and event processing.
 
-use crate::app::{App, UIMode};
+use crate::actions::Scope;
+use crate::app::{App, QuitChoice, UIMode};
+use crate::focus::{self, Focus, Route};
+use crate::ui::Prompt;
 use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
 use std::time::Duration;
 
-/// Handles a single input event.
-pub fn handle_input(app: &mut App) -> Result<(), Box<dyn std::error::Error>> {
-    // Poll for events with short timeout
-    if event::poll(Duration::from_millis(100))? {
-        if let Event::Key(key) = event::read()? {
-            handle_key_event(app, key)?;
+/// Handles a single input event, waiting at most `timeout` for one.
+pub fn handle_input(app: &mut App, timeout: Duration) -> Result<(), Box<dyn std::error::Error>> {
+    if event::poll(timeout)? {
+        match event::read()? {
+            Event::Key(key) => handle_key_event(app, key)?,
+            Event::FocusGained => app.set_focused(true),
+            Event::FocusLost => app.set_focused(false),
+            _ => {}
         }
     }
     Ok(())
@@ -17,45 +22,113 @@ pub fn handle_input(app: &mut App) -> Result<(), Box<dyn std::error::Error>> {
 
 /// Handles a keyboard event.
 fn handle_key_event(app: &mut App, key: KeyEvent) -> Result<(), Box<dyn std::error::Error>> {
+    // Any key ends a preview, and does nothing else
+    if app.previewing() {
+        app.end_preview();
+        return Ok(());
+    }
+
     match app.ui_mode() {
-        UIMode::Normal => handle_normal_mode(app, key)?,
-        UIMode::TrackList => handle_track_list_mode(app, key)?,
+        UIMode::Normal | UIMode::TrackList | UIMode::Browser | UIMode::Prompt => handle_focused(app, key)?,
         UIMode::Help => handle_help_mode(app, key)?,
+        UIMode::Log => handle_log_mode(app, key)?,
+        UIMode::ConfirmDelete => handle_confirm_delete_mode(app, key)?,
+        UIMode::SortMenu => handle_sort_menu_mode(app, key),
+        // Any key closes the stats screen, like help
+        UIMode::Stats => handle_help_mode(app, key)?,
+        UIMode::MarksMenu => handle_marks_menu_mode(app, key),
+        UIMode::Preferences => handle_preferences_mode(app, key),
+        UIMode::Palette => handle_palette_mode(app, key)?,
+        UIMode::ConfirmQuit => handle_confirm_quit_mode(app, key),
+    }
+    Ok(())
+}
+
+/// Handles a key on a screen of panes: a global key, or one for the pane
+/// with the focus.
+fn handle_focused(app: &mut App, key: KeyEvent) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(focused) = app.focus() else {
+        return Ok(());
+    };
+    match focus::route(focused, app.keymap(), &key) {
+        Route::Global(action) => app.run_action(action)?,
+        Route::Pane => match focused {
+            Focus::NowPlaying => handle_now_playing(app, key)?,
+            Focus::Queue => handle_queue_panel(app, key)?,
+            Focus::TrackList => handle_track_list_mode(app, key)?,
+            Focus::Browser => handle_browser_mode(app, key)?,
+            Focus::Prompt => handle_prompt_mode(app, key),
+        },
     }
     Ok(())
 }
 
-/// Handles keyboard events in normal mode.
-fn handle_normal_mode(app: &mut App, key: KeyEvent) -> Result<(), Box<dyn std::error::Error>> {
+/// Handles keyboard events in the upcoming tracks panel; the player's keys
+/// work there too.
+fn handle_queue_panel(app: &mut App, key: KeyEvent) -> Result<(), Box<dyn std::error::Error>> {
     match key.code {
-        KeyCode::Char('q') => app.quit(),
-        KeyCode::Char(' ') => app.toggle_play_pause(),
-        KeyCode::Char('n') => app.next_track()?,
-        KeyCode::Char('p') => app.previous_track()?,
-        KeyCode::Char('s') => app.toggle_shuffle(),
-        KeyCode::Char('r') => app.cycle_repeat(),
-        KeyCode::Char('t') => app.set_ui_mode(UIMode::TrackList),
-        KeyCode::Char('?') => app.set_ui_mode(UIMode::Help),
-        KeyCode::Right if key.modifiers.contains(KeyModifiers::SHIFT) => app.seek_forward()?,
-        KeyCode::Left if key.modifiers.contains(KeyModifiers::SHIFT) => app.seek_backward()?,
-        KeyCode::Right => app.next_track()?,
-        KeyCode::Left => app.previous_track()?,
-        KeyCode::Esc => app.quit(),
-        _ => {}
+        KeyCode::Up => app.queue_panel_up(),
+        KeyCode::Down => app.queue_panel_down(),
+        KeyCode::Enter => app.queue_panel_play_selected()?,
+        _ => handle_now_playing(app, key)?,
+    }
+    Ok(())
+}
+
+/// Handles keyboard events on the player screen.
+fn handle_now_playing(app: &mut App, key: KeyEvent) -> Result<(), Box<dyn std::error::Error>> {
+    match key.code {
+        KeyCode::Esc if app.cancel_seek() => {}
+        KeyCode::Esc if app.cancel_tasks() => {}
+        _ => {
+            if let Some(action) = app.keymap().find(Scope::Player, &key) {
+                app.run_action(action)?;
+            }
+        }
     }
     Ok(())
 }
 
 /// Handles keyboard events in track list mode.
 fn handle_track_list_mode(app: &mut App, key: KeyEvent) -> Result<(), Box<dyn std::error::Error>> {
+    // The second key of a two-key command; anything but a character cancels it
+    if let Some(prefix) = app.take_key_prefix() {
+        if let KeyCode::Char(c) = key.code {
+            app.finish_key_prefix(prefix, c);
+        }
+        return Ok(());
+    }
+
+    // Once a search is typed, characters other than capitals go into it
+    let searching = !app.search_query().is_empty()
+        && !key.modifiers.contains(KeyModifiers::CONTROL)
+        && matches!(key.code, KeyCode::Char(c) if !c.is_uppercase());
+
     match key.code {
+        KeyCode::Esc if app.jump_mode() => app.toggle_jump_mode(),
+        KeyCode::Esc if app.cancel_tasks() => {}
+        KeyCode::Char(c) if app.jump_mode() && c != 'J' && !key.modifiers.contains(KeyModifiers::CONTROL) => {
+            app.jump_to_letter(c)
+        }
         KeyCode::Esc => app.set_ui_mode(UIMode::Normal),
         KeyCode::Up => app.track_list_up(),
         KeyCode::Down => app.track_list_down(),
-        KeyCode::Enter => app.track_list_play_selected()?,
+        // Not every terminal tells Shift+Enter from Enter; Alt+Enter works in most
+        KeyCode::Enter if key.modifiers.intersects(KeyModifiers::SHIFT | KeyModifiers::ALT) => {
+            app.track_list_play_filtered()?
+        }
+        // Ctrl+Enter goes to the keymap, which plays and saves
+        KeyCode::Enter if !key.modifiers.contains(KeyModifiers::CONTROL) => app.track_list_play_selected()?,
         KeyCode::Backspace => app.search_backspace(),
-        KeyCode::Char(c) => app.search_input(c),
-        _ => {}
+        // Search is case-insensitive, so capitals are free for commands
+        _ => match app.keymap().find(Scope::TrackList, &key) {
+            Some(action) if !searching => app.run_action(action)?,
+            _ => {
+                if let KeyCode::Char(c) = key.code {
+                    app.search_input(c);
+                }
+            }
+        },
     }
     Ok(())
 }
@@ -66,3 +139,182 @@ fn handle_help_mode(app: &mut App, _key: KeyEvent) -> Result<(), Box<dyn std::er
     app.set_ui_mode(UIMode::Normal);
     Ok(())
 }
+
+/// Handles keyboard events in log mode.
+fn handle_log_mode(app: &mut App, key: KeyEvent) -> Result<(), Box<dyn std::error::Error>> {
+    match key.code {
+        KeyCode::Up => app.log_up(),
+        KeyCode::Down => app.log_down(),
+        KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('e') => app.set_ui_mode(UIMode::Normal),
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Handles keyboard events in the delete confirmation modal.
+fn handle_confirm_delete_mode(app: &mut App, key: KeyEvent) -> Result<(), Box<dyn std::error::Error>> {
+    match key.code {
+        KeyCode::Char('y') | KeyCode::Char('Y') | KeyCode::Enter => app.confirm_delete(),
+        _ => app.cancel_delete(),
+    }
+    Ok(())
+}
+
+/// Handles keyboard events in the sort menu.
+fn handle_sort_menu_mode(app: &mut App, key: KeyEvent) {
+    match key.code {
+        KeyCode::Up => app.sort_menu_up(),
+        KeyCode::Down => app.sort_menu_down(),
+        KeyCode::Enter => app.apply_sort_menu(),
+        KeyCode::Char('r') | KeyCode::Char('R') => app.sort_menu_reverse(),
+        KeyCode::Esc => app.cancel_sort_menu(),
+        _ => {}
+    }
+}
+
+/// Handles keyboard events in the menu asking whether to save before
+/// quitting.
+fn handle_confirm_quit_mode(app: &mut App, key: KeyEvent) {
+    match key.code {
+        KeyCode::Up => app.quit_menu_up(),
+        KeyCode::Down => app.quit_menu_down(),
+        KeyCode::Enter => app.close_quit_menu(None),
+        KeyCode::Char('s') | KeyCode::Char('S') => app.close_quit_menu(Some(QuitChoice::Save)),
+        KeyCode::Char('d') | KeyCode::Char('D') => app.close_quit_menu(Some(QuitChoice::Discard)),
+        KeyCode::Esc | KeyCode::Char('c') | KeyCode::Char('C') => app.close_quit_menu(Some(QuitChoice::Cancel)),
+        _ => {}
+    }
+}
+
+/// Handles keyboard events in the list of marks.
+fn handle_marks_menu_mode(app: &mut App, key: KeyEvent) {
+    match key.code {
+        KeyCode::Up => app.marks_menu_up(),
+        KeyCode::Down => app.marks_menu_down(),
+        KeyCode::Enter => app.close_marks_menu(None),
+        KeyCode::Char(c) if crate::marks::is_mark_letter(c) => app.close_marks_menu(Some(c)),
+        KeyCode::Esc => app.cancel_marks_menu(),
+        _ => {}
+    }
+}
+
+/// Handles keyboard events in the folder browser.
+fn handle_browser_mode(app: &mut App, key: KeyEvent) -> Result<(), Box<dyn std::error::Error>> {
+    match key.code {
+        KeyCode::Up => app.browser_up(),
+        KeyCode::Down => app.browser_down(),
+        KeyCode::Enter | KeyCode::Right => app.browser_enter(),
+        KeyCode::Backspace | KeyCode::Left => app.browser_leave(),
+        KeyCode::Char('p') => app.browser_play()?,
+        KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('b') => app.set_ui_mode(UIMode::Normal),
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Handles keyboard events in the command palette.
+fn handle_palette_mode(app: &mut App, key: KeyEvent) -> Result<(), Box<dyn std::error::Error>> {
+    match key.code {
+        KeyCode::Up => app.palette_up(),
+        KeyCode::Down => app.palette_down(),
+        KeyCode::Enter => app.palette_run()?,
+        KeyCode::Backspace => app.palette_backspace(),
+        KeyCode::Esc => app.close_palette(),
+        KeyCode::Char('p') if key.modifiers.contains(KeyModifiers::CONTROL) => app.close_palette(),
+        KeyCode::Char(c) => app.palette_input(c),
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Handles keyboard events in a text prompt.
+fn handle_prompt_mode(app: &mut App, key: KeyEvent) {
+    match key.code {
+        KeyCode::Enter => app.prompt_submit(),
+        KeyCode::Esc => app.close_prompt(),
+        KeyCode::Backspace => app.prompt_edit(Prompt::backspace),
+        KeyCode::Delete => app.prompt_edit(Prompt::delete),
+        KeyCode::Left => app.prompt_edit(Prompt::left),
+        KeyCode::Right => app.prompt_edit(Prompt::right),
+        KeyCode::Home => app.prompt_edit(Prompt::home),
+        KeyCode::End => app.prompt_edit(Prompt::end),
+        KeyCode::Up => app.prompt_edit(Prompt::older),
+        KeyCode::Down => app.prompt_edit(Prompt::newer),
+        KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => app.prompt_edit(Prompt::search),
+        KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => app.prompt_edit(Prompt::clear),
+        KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => app.prompt_edit(|prompt| prompt.insert(c)),
+        _ => {}
+    }
+}
+
+/// Handles keyboard events on the preferences screen.
+fn handle_preferences_mode(app: &mut App, key: KeyEvent) {
+    match key.code {
+        KeyCode::Up => app.preferences_up(),
+        KeyCode::Down => app.preferences_down(),
+        KeyCode::Right | KeyCode::Enter => app.preferences_step(true),
+        KeyCode::Left => app.preferences_step(false),
+        KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('o') => app.close_preferences(),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::playlist::{Playlist, Track};
+    use crossterm::event::KeyEventKind;
+    use std::fs;
+
+    fn press(code: KeyCode) -> KeyEvent {
+        KeyEvent::new_with_kind(code, KeyModifiers::NONE, KeyEventKind::Press)
+    }
+
+    #[test]
+    fn test_deleting_every_track_leaves_the_app_usable() {
+        let dir = std::env::temp_dir().join(format!("juke_input_empty_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let tracks = ["a.mp3", "b.mp3", "c.mp3"].map(|name| {
+            let path = dir.join(name);
+            fs::write(&path, b"").unwrap();
+            Track::new(path)
+        });
+        let mut config = Config::default();
+        config.playlist.delete_permanently = true;
+        let mut app = App::for_tests(Playlist::from_tracks(tracks.to_vec()).unwrap(), config);
+
+        handle_key_event(&mut app, press(KeyCode::Char('t'))).unwrap();
+        for _ in 0..tracks.len() {
+            handle_key_event(&mut app, press(KeyCode::Char('D'))).unwrap();
+            handle_key_event(&mut app, press(KeyCode::Char('y'))).unwrap();
+            app.screen();
+        }
+        assert!(tracks.iter().all(|track| !track.path.exists()));
+        app.update().unwrap();
+        assert!(app.screen().contains("The playlist is empty"));
+
+        // Every way around the player and the track list does nothing
+        let keys = [
+            KeyCode::Char(' '),
+            KeyCode::Char('n'),
+            KeyCode::Char('p'),
+            KeyCode::Right,
+            KeyCode::Left,
+            KeyCode::Char('t'),
+            KeyCode::Down,
+            KeyCode::Up,
+            KeyCode::Enter,
+            KeyCode::Char('D'),
+            KeyCode::Esc,
+        ];
+        for key in keys {
+            handle_key_event(&mut app, press(key)).unwrap();
+            app.update().unwrap();
+            app.screen();
+        }
+        assert!(app.is_running());
+        assert_eq!(app.ui_mode(), UIMode::Normal);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
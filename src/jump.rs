@@ -0,0 +1,124 @@
+//! The track list's alphabet jump index.
+//!
+//! Each row of the list is filed under the initial of what the list is
+//! sorted by, A to Z after folding accents and dropping leading articles,
+//! with everything else under `#`. Pressing a letter selects the first row
+//! filed under it, and pressing it again the next.
+
+use crate::collation::{Collation, SortKey};
+use crate::playlist::Track;
+
+/// Initials in the order the strip shows them.
+pub const LETTERS: [char; 27] = [
+    '#', 'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M', 'N', 'O', 'P', 'Q', 'R', 'S', 'T', 'U',
+    'V', 'W', 'X', 'Y', 'Z',
+];
+
+/// Initials of the rows of a track list.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JumpIndex {
+    initials: Vec<char>,
+}
+
+impl JumpIndex {
+    /// Files the listed tracks under the initials of the field the list is
+    /// sorted by: artist, album, or else title.
+    pub fn build(tracks: &[Track], rows: &[usize], sort: Option<SortKey>, collation: &Collation) -> Self {
+        let initials = rows
+            .iter()
+            .map(|&index| {
+                let Some(track) = tracks.get(index) else {
+                    return '#';
+                };
+                match sort {
+                    Some(SortKey::Artist) => track.artist.as_deref().map_or('#', |a| initial(a, collation)),
+                    Some(SortKey::Album) => track.album.as_deref().map_or('#', |a| initial(a, collation)),
+                    _ => initial(&track.display_name(), collation),
+                }
+            })
+            .collect();
+        Self { initials }
+    }
+
+    /// Returns the initial a row is filed under.
+    pub fn initial_of(&self, row: usize) -> Option<char> {
+        self.initials.get(row).copied()
+    }
+
+    /// Returns whether any row is filed under a letter.
+    pub fn has(&self, letter: char) -> bool {
+        self.initials.contains(&letter)
+    }
+
+    /// Returns the row to select for a letter pressed with `selected`
+    /// selected: the next row under it after the selection, wrapping
+    /// around, or `None` if there is none.
+    pub fn jump(&self, letter: char, selected: usize) -> Option<usize> {
+        let letter = letter.to_ascii_uppercase();
+        let count = self.initials.len();
+        let start = if self.initial_of(selected) == Some(letter) { selected + 1 } else { 0 };
+        (0..count).map(|i| (start + i) % count).find(|&row| self.initials[row] == letter)
+    }
+}
+
+/// Returns the letter text is filed under: its first letter after folding
+/// and dropping a leading article if that is A to Z, and `#` otherwise.
+pub fn initial(text: &str, collation: &Collation) -> char {
+    match collation.key(text).chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => c.to_ascii_uppercase(),
+        _ => '#',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn track(artist: Option<&str>, title: &str) -> Track {
+        let mut track = Track::new(PathBuf::from(format!("{}.mp3", title)));
+        track.artist = artist.map(String::from);
+        track.title = Some(title.to_string());
+        track
+    }
+
+    #[test]
+    fn test_initials() {
+        let collation = Collation::default();
+        assert_eq!(initial("The Beatles", &collation), 'B');
+        assert_eq!(initial("Édith Piaf", &collation), 'E');
+        assert_eq!(initial("  abba", &collation), 'A');
+        assert_eq!(initial("2Pac", &collation), '#');
+        assert_eq!(initial("Кино", &collation), '#');
+        assert_eq!(initial("東京事変", &collation), '#');
+        assert_eq!(initial("", &collation), '#');
+    }
+
+    #[test]
+    fn test_jump_cycles() {
+        let tracks = vec![
+            track(Some("ABBA"), "x"),
+            track(Some("Air"), "y"),
+            track(Some("The Beatles"), "z"),
+            track(None, "w"),
+            track(Some("Aphex Twin"), "v"),
+        ];
+        let rows = [0, 1, 2, 3, 4];
+        let index = JumpIndex::build(&tracks, &rows, Some(SortKey::Artist), &Collation::default());
+        assert!(index.has('A') && index.has('B') && index.has('#'));
+        assert!(!index.has('C'));
+
+        assert_eq!(index.jump('a', 2), Some(0));
+        assert_eq!(index.jump('a', 0), Some(1));
+        assert_eq!(index.jump('A', 1), Some(4));
+        assert_eq!(index.jump('a', 4), Some(0));
+        assert_eq!(index.jump('#', 0), Some(3));
+        assert_eq!(index.jump('c', 0), None);
+
+        // A filtered list only files its own rows; titles when not sorted by artist
+        let index = JumpIndex::build(&tracks, &[2, 4], None, &Collation::default());
+        assert_eq!(index.initial_of(0), Some('Z'));
+        assert_eq!(index.jump('v', 0), Some(1));
+        assert!(!index.has('A'));
+    }
+}
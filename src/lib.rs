@@ -0,0 +1,50 @@
+//! juke's playlist and playback engine, for use without its terminal
+//! interface.
+//!
+//! - [`playlist`] builds a [`Playlist`] of [`Track`]s from a directory or
+//!   an M3U file and moves through it with shuffle, repeat, and a queue.
+//! - [`player`] plays a track on an audio device with the [`Player`],
+//!   reporting every change as a [`PlayerEvent`].
+//! - [`gapless`] decides whether a track runs straight into the next.
+//! - [`frames`] measures the exact length of an MP3 frame by frame.
+//! - [`trim`] skips the intros and outros set for a directory's files.
+//! - [`config`] reads juke's config file into a [`Config`].
+//!
+//! Library code never prints; problems come back as errors, as warnings
+//! from [`Config::load_from`], or in a playlist's
+//! [`ScanReport`](playlist::ScanReport).
+//!
+//! ```
+//! use juke::playlist::{Playlist, Track};
+//! use std::path::PathBuf;
+//!
+//! let tracks = ["one.flac", "two.flac"].map(|name| Track::new(PathBuf::from(name)));
+//! let mut playlist = Playlist::from_tracks(tracks.to_vec()).unwrap();
+//! assert!(playlist.next());
+//! assert_eq!(playlist.current_track().unwrap().display_name(), "two.flac");
+//! ```
+//!
+//! The terminal interface is the `juke` binary, built with the default
+//! `tui` feature; `default-features = false` leaves out its dependencies.
+
+pub mod collation;
+pub mod config;
+pub mod discs;
+pub mod filter;
+pub mod frames;
+pub mod gapless;
+pub mod player;
+pub mod playlist;
+pub mod task;
+pub mod template;
+pub mod trim;
+
+mod decoded;
+mod fade;
+mod limiter;
+mod segments;
+mod spectrum;
+
+pub use config::Config;
+pub use player::{PlaybackState, Player, PlayerError, PlayerEvent};
+pub use playlist::{Playlist, PlaylistError, Track};
@@ -0,0 +1,302 @@
+//! What juke remembers about each file: when it was first seen, for the
+//! "recently added" playlist (`--recent`), how often it was played or
+//! skipped, for smart shuffle, and how long juke was listened to, for the
+//! stats screen.
+//!
+//! Every scan records files juke has not seen before in `library.toml` in the
+//! state directory. File modification times are only a fallback, since they
+//! change on retagging and are often preserved when copying.
+
+use crate::playlist::{self, Track};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Current version of the file format.
+const VERSION: u32 = 1;
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Smallest smart shuffle weight, so no track is ever left out.
+const MIN_SHUFFLE_WEIGHT: f64 = 0.1;
+
+/// How often a track was played to the end or skipped.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PlayStats {
+    pub plays: u32,
+    pub skips: u32,
+}
+
+impl PlayStats {
+    /// Returns the track's weight for smart shuffle.
+    ///
+    /// Tracks skipped more often than played get less than the default
+    /// weight of 1, down to [`MIN_SHUFFLE_WEIGHT`]; tracks played more are
+    /// not favored, so rarely played ones keep coming up.
+    pub fn shuffle_weight(&self) -> f64 {
+        let ratio = f64::from(self.plays + 1) / f64::from(self.skips + 1);
+        ratio.clamp(MIN_SHUFFLE_WEIGHT, 1.0)
+    }
+}
+
+/// What is known about one file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct FileEntry {
+    /// Seconds since the Unix epoch when the file was first seen. May be
+    /// missing in an unversioned index.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    first_seen: Option<u64>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    plays: u32,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    skips: u32,
+}
+
+fn is_zero(count: &u32) -> bool {
+    *count == 0
+}
+
+/// First-seen times of every file juke has scanned, keyed by canonical path.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Library {
+    #[serde(default)]
+    version: u32,
+    /// Seconds listened on each day of the week (UTC), Monday first.
+    #[serde(default)]
+    listened_by_weekday: [u64; 7],
+    #[serde(default)]
+    files: BTreeMap<String, FileEntry>,
+    /// File the index was loaded from and is saved to.
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl Library {
+    /// Loads the index from a state directory, returning an empty one if it is
+    /// missing or unreadable. Without a state directory nothing is persisted.
+    pub fn load(state_dir: Option<&Path>) -> Self {
+        let path = state_dir.map(|dir| dir.join("library.toml"));
+        let mut library: Self = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default();
+        library.migrate();
+        Self { path, ..library }
+    }
+
+    /// Brings an index from an older version up to date.
+    fn migrate(&mut self) {
+        if self.version >= VERSION {
+            return;
+        }
+        // Entries without a time get the file's modification time as the best guess
+        for (key, entry) in &mut self.files {
+            if entry.first_seen.is_none() {
+                entry.first_seen = file_mtime(Path::new(key));
+            }
+        }
+        self.version = VERSION;
+    }
+
+    /// Saves the index to the file it was loaded from.
+    pub fn save(&self) -> std::io::Result<()> {
+        let path = self.path.as_ref().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "no state directory")
+        })?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let toml_string = toml::to_string_pretty(self).map_err(std::io::Error::other)?;
+        fs::write(path, toml_string)
+    }
+
+    /// Records files seen for the first time at `now`. Returns true if any were new.
+    ///
+    /// On the very first scan nothing is new to the user, so files are
+    /// dated by their modification time instead.
+    pub fn record(&mut self, tracks: &[Track], now: u64) -> bool {
+        let first_scan = self.files.is_empty();
+        let mut changed = false;
+        for track in tracks {
+            let key = key(&track.path);
+            if self.files.contains_key(&key) {
+                continue;
+            }
+            let first_seen = if first_scan { file_mtime(&track.path).unwrap_or(now) } else { now };
+            self.files.insert(key, FileEntry { first_seen: Some(first_seen), ..Default::default() });
+            changed = true;
+        }
+        self.version = VERSION;
+        changed
+    }
+
+    /// Returns when a file was first seen, falling back to its modification time.
+    pub fn first_seen(&self, path: &Path) -> Option<u64> {
+        self.files
+            .get(&key(path))
+            .and_then(|entry| entry.first_seen)
+            .or_else(|| file_mtime(path))
+    }
+
+    /// Returns how often a file was played and skipped.
+    pub fn stats(&self, path: &Path) -> PlayStats {
+        self.files.get(&key(path)).map_or_else(PlayStats::default, |entry| PlayStats {
+            plays: entry.plays,
+            skips: entry.skips,
+        })
+    }
+
+    /// Counts a play of a file, returning its updated stats.
+    pub fn record_play(&mut self, path: &Path) -> PlayStats {
+        let entry = self.files.entry(key(path)).or_default();
+        entry.plays = entry.plays.saturating_add(1);
+        self.stats(path)
+    }
+
+    /// Counts a skip of a file, returning its updated stats.
+    pub fn record_skip(&mut self, path: &Path) -> PlayStats {
+        let entry = self.files.entry(key(path)).or_default();
+        entry.skips = entry.skips.saturating_add(1);
+        self.stats(path)
+    }
+
+    /// Adds time spent listening at `now` to the weekday totals.
+    pub fn record_listened(&mut self, listened: Duration, now: u64) {
+        let total = &mut self.listened_by_weekday[weekday(now)];
+        *total = total.saturating_add(listened.as_secs());
+    }
+
+    /// Returns the time spent listening on each day of the week, Monday first.
+    pub fn listened_by_weekday(&self) -> [Duration; 7] {
+        self.listened_by_weekday.map(Duration::from_secs)
+    }
+
+    /// Returns the play and skip counts of every file played or skipped at
+    /// least once, keyed by canonical path.
+    pub fn play_counts(&self) -> impl Iterator<Item = (&str, PlayStats)> {
+        self.files
+            .iter()
+            .filter(|(_, entry)| entry.plays > 0 || entry.skips > 0)
+            .map(|(key, entry)| (key.as_str(), PlayStats { plays: entry.plays, skips: entry.skips }))
+    }
+
+    /// Keeps the tracks first seen within the last `days` days, newest first,
+    /// with their first-seen time in [`Track::added`].
+    pub fn recent(&self, tracks: Vec<Track>, days: u64, now: u64) -> Vec<Track> {
+        let since = now.saturating_sub(days.saturating_mul(SECS_PER_DAY));
+        let mut recent: Vec<Track> = tracks
+            .into_iter()
+            .filter_map(|track| {
+                let added = self.first_seen(&track.path).filter(|t| *t >= since)?;
+                Some(Track { added: Some(added), ..track })
+            })
+            .collect();
+        // Files added together (an album) stay in their scan order
+        recent.sort_by_key(|track| std::cmp::Reverse(track.added));
+        recent
+    }
+}
+
+/// Returns the cache key for a file.
+fn key(path: &Path) -> String {
+    playlist::canonical_key(path).to_string_lossy().into_owned()
+}
+
+fn file_mtime(path: &Path) -> Option<u64> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    modified.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs())
+}
+
+/// Returns the day of the week (UTC) of a Unix time, 0 for Monday.
+pub fn weekday(unix_secs: u64) -> usize {
+    // 1 January 1970 was a Thursday
+    ((unix_secs / SECS_PER_DAY + 3) % 7) as usize
+}
+
+/// Returns the current time in seconds since the Unix epoch.
+pub fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recent_tracks() {
+        let dir = std::env::temp_dir().join(format!("juke_library_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let names = ["old.mp3", "a.mp3", "b.mp3", "new.mp3"];
+        for name in names {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+        let tracks: Vec<Track> = names.iter().map(|name| Track::new(dir.join(name))).collect();
+
+        let now = unix_now();
+        let mut library = Library::load(Some(&dir));
+        // The first scan dates files by modification time, which is "now" here
+        assert!(library.record(&tracks[..1], now));
+        library.files.get_mut(&key(&tracks[0].path)).unwrap().first_seen = Some(now - 40 * SECS_PER_DAY);
+        assert!(library.record(&tracks[1..3], now - 5 * SECS_PER_DAY));
+        assert!(library.record(&tracks, now - SECS_PER_DAY));
+        assert!(!library.record(&tracks, now));
+        library.save().unwrap();
+
+        let reloaded = Library::load(Some(&dir));
+        let recent = reloaded.recent(tracks.clone(), 30, now);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let names: Vec<_> = recent.iter().map(|t| t.path.file_name().unwrap().to_str().unwrap()).collect();
+        assert_eq!(names, ["new.mp3", "a.mp3", "b.mp3"]);
+        assert_eq!(recent[0].added, Some(now - SECS_PER_DAY));
+    }
+
+    #[test]
+    fn test_shuffle_weight() {
+        let weight = |plays, skips| PlayStats { plays, skips }.shuffle_weight();
+        assert_eq!(weight(0, 0), 1.0);
+        assert_eq!(weight(50, 0), 1.0);
+        assert_eq!(weight(1, 3), 0.5);
+        assert_eq!(weight(0, 1000), MIN_SHUFFLE_WEIGHT);
+
+        let mut library = Library::default();
+        let path = Path::new("/music/a.mp3");
+        library.record_play(path);
+        assert_eq!(library.record_skip(path), PlayStats { plays: 1, skips: 1 });
+        let saved = toml::to_string(&library).unwrap();
+        assert!(saved.contains("plays = 1") && !saved.contains("first_seen"));
+    }
+
+    #[test]
+    fn test_listened_by_weekday() {
+        assert_eq!(weekday(0), 3); // Thursday
+        assert_eq!(weekday(4 * SECS_PER_DAY + 100), 0); // Monday 5 January 1970
+
+        let mut library = Library::default();
+        library.record_listened(Duration::from_secs(90), 0);
+        library.record_listened(Duration::from_secs(30), SECS_PER_DAY - 1);
+        library.record_listened(Duration::from_secs(60), 4 * SECS_PER_DAY);
+        let reloaded: Library = toml::from_str(&toml::to_string(&library).unwrap()).unwrap();
+        assert_eq!(reloaded.listened_by_weekday(), [60, 0, 0, 120, 0, 0, 0].map(Duration::from_secs));
+    }
+
+    #[test]
+    fn test_migrates_old_index() {
+        let path = std::env::temp_dir().join(format!("juke_library_old_{}.mp3", std::process::id()));
+        fs::write(&path, b"").unwrap();
+        let old = format!("[files.\"{}\"]\n", key(&path).replace('\\', "\\\\"));
+
+        let mut library: Library = toml::from_str(&old).unwrap();
+        assert_eq!(library.version, 0);
+        library.migrate();
+        let first_seen = library.first_seen(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(library.version, VERSION);
+        assert!(first_seen.is_some_and(|t| t + 60 > unix_now()));
+        assert!(library.files.values().all(|entry| entry.first_seen.is_some()));
+    }
+}
@@ -0,0 +1,209 @@
+//! A soft-knee peak limiter for gains above unity.
+//!
+//! Volume, pre-amp, and ReplayGain multiply into one gain. Up to unity the
+//! sink applies it and nothing can clip, so the limiter passes samples
+//! straight through. Above unity the sink gets unity and the limiter
+//! applies the rest itself, pulling back peaks that would go past full
+//! scale. A peak is caught as soon as it arrives, so none gets through, and
+//! let go of over `playback.limiter_release_ms`.
+
+use rodio::source::SeekError;
+use rodio::Source;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Level at which gain reduction starts, -3 dBFS.
+const KNEE: f32 = 0.708;
+
+/// Level no output sample exceeds, 0 dBFS.
+const CEILING: f32 = 1.0;
+
+/// Gain below which the limiter counts as active, about -0.1 dB.
+const ACTIVE_BELOW: f32 = 0.99;
+
+/// Samples between reads of the gain from the player, so that a change
+/// reaches the audio thread within a few milliseconds.
+const CONTROL_INTERVAL: usize = 256;
+
+/// Shared between the player and the limiter on the audio thread.
+#[derive(Debug)]
+pub struct LimiterControl {
+    /// Gain above unity, as `f32` bits; 1.0 bypasses the limiter.
+    gain: AtomicU32,
+    /// Whether peaks are being pulled back.
+    active: AtomicBool,
+}
+
+impl LimiterControl {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            gain: AtomicU32::new(1.0_f32.to_bits()),
+            active: AtomicBool::new(false),
+        })
+    }
+
+    /// Sets the gain the limiter applies; anything at or below 1.0
+    /// bypasses it.
+    pub fn set_gain(&self, gain: f32) {
+        self.gain.store(gain.max(1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    fn gain(&self) -> f32 {
+        f32::from_bits(self.gain.load(Ordering::Relaxed))
+    }
+
+    /// Returns whether the limiter is pulling peaks back.
+    pub fn active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+}
+
+/// Applies gain above unity to a source and limits the result.
+pub struct Limiter<S> {
+    source: S,
+    control: Arc<LimiterControl>,
+    /// Gain as last read from `control`.
+    gain: f32,
+    until_control: usize,
+    /// Recent peak level: jumps up to each louder sample, then decays.
+    envelope: f32,
+    /// Share of the distance to a quieter level the envelope decays by
+    /// each sample.
+    release: f32,
+}
+
+impl<S: Source<Item = f32>> Limiter<S> {
+    pub fn new(source: S, control: Arc<LimiterControl>, release: Duration) -> Self {
+        // Channels are interleaved, so every sample moves time on by 1 / (rate × channels)
+        let samples_per_sec = source.sample_rate() as f32 * f32::from(source.channels().max(1));
+        let release = 1.0 - (-1.0 / (release.as_secs_f32() * samples_per_sec).max(1.0)).exp();
+        Self {
+            source,
+            control,
+            gain: 1.0,
+            until_control: 0,
+            envelope: 0.0,
+            release,
+        }
+    }
+}
+
+/// Returns the gain that brings a level down onto the knee's curve, which
+/// bends smoothly from the knee towards the ceiling without reaching it.
+fn reduction(level: f32) -> f32 {
+    if level <= KNEE {
+        return 1.0;
+    }
+    let span = CEILING - KNEE;
+    let limited = KNEE + span * (1.0 - (-(level - KNEE) / span).exp());
+    limited / level
+}
+
+impl<S: Source<Item = f32>> Iterator for Limiter<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.source.next()?;
+        if self.until_control == 0 {
+            self.gain = self.control.gain();
+            let active = self.gain > 1.0 && reduction(self.envelope) < ACTIVE_BELOW;
+            self.control.active.store(active, Ordering::Relaxed);
+            self.until_control = CONTROL_INTERVAL;
+        }
+        self.until_control -= 1;
+
+        if self.gain <= 1.0 {
+            self.envelope = 0.0;
+            return Some(sample);
+        }
+
+        let boosted = sample * self.gain;
+        let level = boosted.abs();
+        if level >= self.envelope {
+            self.envelope = level;
+        } else {
+            self.envelope -= (self.envelope - level) * self.release;
+        }
+        // The envelope is never below the sample, so the curve keeps it under
+        // the ceiling; clamping only guards against rounding
+        Some((boosted * reduction(self.envelope)).clamp(-CEILING, CEILING))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.source.size_hint()
+    }
+}
+
+impl<S: Source<Item = f32>> Source for Limiter<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.source.try_seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::source::SineWave;
+
+    const RELEASE: Duration = Duration::from_millis(150);
+
+    fn limit(gain: f32) -> (Vec<f32>, Arc<LimiterControl>) {
+        let control = LimiterControl::new();
+        control.set_gain(gain);
+        let sine = SineWave::new(440.0).take_duration(Duration::from_secs(1));
+        let output = Limiter::new(sine, control.clone(), RELEASE).collect();
+        (output, control)
+    }
+
+    #[test]
+    fn test_full_scale_sine_does_not_clip() {
+        // +6 dB on a full-scale sine
+        let (output, control) = limit(2.0);
+        let peak = output.iter().fold(0.0_f32, |peak, sample| peak.max(sample.abs()));
+        assert!(peak <= CEILING, "peak {} above 0 dBFS", peak);
+        assert!(control.active());
+
+        // Once settled, the peaks sit on the knee's curve rather than being clipped flat
+        let settled = &output[output.len() / 2..];
+        let clipped = settled.iter().filter(|sample| sample.abs() >= CEILING).count();
+        assert_eq!(clipped, 0);
+        let settled_peak = settled.iter().fold(0.0_f32, |peak, sample| peak.max(sample.abs()));
+        assert!(settled_peak > KNEE && settled_peak < CEILING);
+    }
+
+    #[test]
+    fn test_unity_gain_is_bypassed() {
+        let (output, control) = limit(0.5);
+        let sine: Vec<f32> = SineWave::new(440.0).take_duration(Duration::from_secs(1)).collect();
+        assert_eq!(output, sine);
+        assert!(!control.active());
+    }
+
+    #[test]
+    fn test_reduction_curve() {
+        assert_eq!(reduction(0.5), 1.0);
+        assert_eq!(reduction(KNEE), 1.0);
+        for level in [0.8, 1.0, 2.0, 8.0] {
+            let limited = level * reduction(level);
+            assert!(limited > KNEE && limited <= CEILING, "{} limited to {}", level, limited);
+        }
+        assert!(2.0 * reduction(2.0) > 1.0 * reduction(1.0));
+    }
+}
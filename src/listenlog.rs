@@ -0,0 +1,185 @@
+//! A local log of listens in the `.scrobbler.log` format portable players
+//! write, so that importers can submit it to Last.fm, ListenBrainz and the
+//! like later, without juke going online.
+//!
+//! Each listen is one line of tab separated fields, appended with a single
+//! write so an entry is never split, after a header when the file is new.
+//! `juke --rotate-listen-log` moves the file aside once it was imported.
+
+use crate::buildinfo;
+use crate::dates;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Tracks this short are never logged as heard.
+const SHORTEST: Duration = Duration::from_secs(30);
+
+/// Heard for this long, a track counts however long it is.
+const ENOUGH: Duration = Duration::from_secs(4 * 60);
+
+/// How a listen is marked in the log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rating {
+    /// Heard for long enough to count, written `L`.
+    Listened,
+    /// Skipped before that, written `S`; importers leave these out.
+    Skipped,
+}
+
+/// One listen, as the log records it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Listen<'a> {
+    pub artist: Option<&'a str>,
+    pub album: Option<&'a str>,
+    pub title: &'a str,
+    pub track_number: Option<u32>,
+    pub duration: Duration,
+    pub rating: Rating,
+    /// Seconds since the Unix epoch when the track started.
+    pub started: u64,
+}
+
+/// Whether `listened` of a track `duration` long counts as heard, by the
+/// rule scrobbling uses: half of it, or four minutes, of a track longer
+/// than 30 seconds.
+pub fn counts(duration: Duration, listened: Duration) -> bool {
+    duration > SHORTEST && (listened * 2 >= duration || listened >= ENOUGH)
+}
+
+/// The lines that start a log, saying its format, that its times are UTC,
+/// and what wrote it.
+fn header() -> String {
+    format!("#AUDIOSCROBBLER/1.1\n#TZ/UTC\n#CLIENT/juke {}\n", buildinfo::VERSION)
+}
+
+/// Makes text fit in one field: the format has no escapes, so tabs and
+/// line breaks become spaces.
+fn field(text: &str) -> String {
+    text.chars().map(|c| if matches!(c, '\t' | '\n' | '\r') { ' ' } else { c }).collect()
+}
+
+/// Formats a listen as a line of the log, without the line break. Unknown
+/// fields are left empty.
+fn line(listen: &Listen) -> String {
+    let rating = match listen.rating {
+        Rating::Listened => "L",
+        Rating::Skipped => "S",
+    };
+    [
+        field(listen.artist.unwrap_or_default()),
+        field(listen.album.unwrap_or_default()),
+        field(listen.title),
+        listen.track_number.map(|number| number.to_string()).unwrap_or_default(),
+        listen.duration.as_secs().to_string(),
+        rating.to_string(),
+        listen.started.to_string(),
+    ]
+    .join("\t")
+}
+
+/// Appends a listen to the log at `path`, starting it with the header if
+/// it is new or empty.
+pub fn append(path: &Path, listen: &Listen) -> io::Result<()> {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut entry = if file.metadata()?.len() == 0 { header() } else { String::new() };
+    entry.push_str(&line(listen));
+    entry.push('\n');
+    file.write_all(entry.as_bytes())
+}
+
+/// Moves the log at `path` aside, next to it and named after the day,
+/// like `.scrobbler.log.2026-10-17`, so the next listen starts a new one.
+/// Returns where it went, or `None` when there was no log.
+pub fn rotate(path: &Path, now: u64) -> io::Result<Option<PathBuf>> {
+    match fs::metadata(path) {
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    let day = format!("{}.{}", name, dates::date(now));
+    let archive = (1..)
+        .map(|n| if n == 1 { path.with_file_name(&day) } else { path.with_file_name(format!("{}-{}", day, n)) })
+        .find(|archive| !archive.exists())
+        .unwrap_or_default();
+    fs::rename(path, &archive)?;
+    Ok(Some(archive))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listen(title: &str) -> Listen<'_> {
+        Listen {
+            artist: Some("Can"),
+            album: Some("Tago Mago"),
+            title,
+            track_number: Some(3),
+            duration: Duration::from_secs(1107),
+            rating: Rating::Listened,
+            started: 1_760_000_000,
+        }
+    }
+
+    #[test]
+    fn test_line() {
+        assert_eq!(line(&listen("Halleluhwah")), "Can\tTago Mago\tHalleluhwah\t3\t1107\tL\t1760000000");
+
+        // No album or track number, a skip, and breaks in the title
+        let skipped = Listen {
+            album: None,
+            track_number: None,
+            rating: Rating::Skipped,
+            ..listen("Mushroom\tHead\r\nPart 2")
+        };
+        assert_eq!(line(&skipped), "Can\t\tMushroom Head  Part 2\t\t1107\tS\t1760000000");
+        assert_eq!(line(&Listen { artist: None, ..listen("x") }).split('\t').count(), 7);
+    }
+
+    #[test]
+    fn test_counts() {
+        let secs = Duration::from_secs;
+        assert!(counts(secs(200), secs(100)));
+        assert!(!counts(secs(200), secs(99)));
+        // Four minutes are enough of a long track
+        assert!(counts(secs(1200), secs(240)));
+        assert!(!counts(secs(1200), secs(239)));
+        // Short tracks never count
+        assert!(!counts(secs(30), secs(30)));
+        assert!(!counts(Duration::ZERO, Duration::ZERO));
+    }
+
+    #[test]
+    fn test_append_and_rotate() {
+        let dir = std::env::temp_dir().join(format!("juke_listenlog_test_{}", std::process::id()));
+        let path = dir.join("logs").join(".scrobbler.log");
+
+        append(&path, &listen("Halleluhwah")).unwrap();
+        append(&path, &listen("Oh Yeah")).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(&lines[..2], ["#AUDIOSCROBBLER/1.1", "#TZ/UTC"]);
+        assert!(lines[2].starts_with("#CLIENT/juke "));
+        assert_eq!(lines.len(), 5);
+        assert!(lines[4].contains("\tOh Yeah\t"));
+
+        // Rotating twice in a day keeps both archives
+        let now = 1_760_659_200;
+        let first = rotate(&path, now).unwrap().unwrap();
+        assert_eq!(first, dir.join("logs").join(".scrobbler.log.2025-10-17"));
+        assert!(!path.exists());
+        append(&path, &listen("Peking O")).unwrap();
+        let second = rotate(&path, now).unwrap().unwrap();
+        assert_eq!(second, dir.join("logs").join(".scrobbler.log.2025-10-17-2"));
+        assert!(fs::read_to_string(&second).unwrap().starts_with("#AUDIOSCROBBLER/1.1\n"));
+        assert_eq!(rotate(&path, now).unwrap(), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
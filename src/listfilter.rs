@@ -0,0 +1,197 @@
+//! Which tracks the track list shows: those matching the search and every
+//! filter chip turned on.
+
+use crate::discs;
+use crate::playlist::{Playlist, Track};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// A quick filter the track list can narrow to, on top of the search.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Chip {
+    /// Tracks with a mark.
+    Marked,
+    /// Tracks on the queue.
+    Queued,
+    /// Tracks that failed to open.
+    Unplayable,
+    /// Tracks from the playing track's album.
+    Album,
+}
+
+impl Chip {
+    /// Every chip, in the order the header shows them.
+    pub const ALL: [Chip; 4] = [Chip::Marked, Chip::Queued, Chip::Unplayable, Chip::Album];
+
+    /// The key that turns the chip on or off.
+    pub fn key(self) -> char {
+        match self {
+            Chip::Marked => '1',
+            Chip::Queued => '2',
+            Chip::Unplayable => '3',
+            Chip::Album => '4',
+        }
+    }
+
+    /// Text key of the chip's name.
+    pub fn text_key(self) -> &'static str {
+        match self {
+            Chip::Marked => "chips.marked",
+            Chip::Queued => "chips.queued",
+            Chip::Unplayable => "chips.unplayable",
+            Chip::Album => "chips.album",
+        }
+    }
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// The chips turned on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Chips(u8);
+
+impl Chips {
+    /// Turns a chip on or off, returning whether it is now on.
+    pub fn toggle(&mut self, chip: Chip) -> bool {
+        self.0 ^= chip.bit();
+        self.contains(chip)
+    }
+
+    pub fn contains(self, chip: Chip) -> bool {
+        self.0 & chip.bit() != 0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.0 = 0;
+    }
+}
+
+/// Decides which tracks of a playlist pass the search and the chips.
+pub struct TrackFilter<'a> {
+    playlist: &'a Playlist,
+    /// The search, lowercased.
+    query: &'a str,
+    chips: Chips,
+    marks: &'a BTreeMap<&'a Path, char>,
+    /// Album and artist of the playing track, when it has an album.
+    album: Option<(&'a str, Option<&'a str>)>,
+}
+
+impl<'a> TrackFilter<'a> {
+    pub fn new(playlist: &'a Playlist, query: &'a str, chips: Chips, marks: &'a BTreeMap<&'a Path, char>) -> Self {
+        let album = playlist
+            .current_track()
+            .and_then(|track| Some((discs::album_key(track.album.as_deref()?), track.artist.as_deref())));
+        Self { playlist, query, chips, marks, album }
+    }
+
+    /// Whether the track at `index` matches the search and every chip on.
+    pub fn passes(&self, index: usize) -> bool {
+        Chip::ALL.iter().all(|&chip| !self.chips.contains(chip) || self.chip_passes(chip, index))
+            && self.playlist.matches(index, self.query)
+    }
+
+    /// Whether the track at `index` is let through by `chip`, whether or not
+    /// it is on.
+    pub fn chip_passes(&self, chip: Chip, index: usize) -> bool {
+        match chip {
+            Chip::Marked => self.track(index).is_some_and(|track| self.marks.contains_key(track.path.as_path())),
+            Chip::Queued => self.playlist.is_queued(index),
+            Chip::Unplayable => self.playlist.is_unplayable(index),
+            Chip::Album => self.track(index).is_some_and(|track| {
+                self.album == track.album.as_deref().map(|album| (discs::album_key(album), track.artist.as_deref()))
+            }),
+        }
+    }
+
+    /// Counts the tracks `chip` lets through, ignoring the search and the
+    /// other chips.
+    pub fn count(&self, chip: Chip) -> usize {
+        (0..self.playlist.len()).filter(|&index| self.chip_passes(chip, index)).count()
+    }
+
+    /// Returns the indices of the tracks that pass, in playlist order.
+    pub fn indices(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.playlist.len()).filter(|&index| self.passes(index))
+    }
+
+    fn track(&self, index: usize) -> Option<&'a Track> {
+        self.playlist.tracks().get(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn track(path: &str, album: Option<&str>, artist: &str) -> Track {
+        let mut track = Track::new(PathBuf::from(path));
+        track.album = album.map(str::to_string);
+        track.artist = Some(artist.to_string());
+        track
+    }
+
+    fn playlist() -> Playlist {
+        let mut playlist = Playlist::new();
+        playlist.add_track(track("/m/a1.mp3", Some("Blue"), "Ann"));
+        playlist.add_track(track("/m/a2.mp3", Some("Blue"), "Ann"));
+        playlist.add_track(track("/m/b1.mp3", Some("Blue"), "Bob"));
+        playlist.add_track(track("/m/single.mp3", None, "Ann"));
+        playlist
+    }
+
+    #[test]
+    fn test_chips_toggle() {
+        let mut chips = Chips::default();
+        assert!(chips.is_empty());
+        assert!(chips.toggle(Chip::Queued));
+        assert!(chips.toggle(Chip::Album));
+        assert!(chips.contains(Chip::Queued) && !chips.contains(Chip::Marked));
+        assert!(!chips.toggle(Chip::Queued));
+        chips.clear();
+        assert!(chips.is_empty());
+    }
+
+    #[test]
+    fn test_chips_and_search_combine() {
+        let mut playlist = playlist();
+        playlist.toggle_queued(0);
+        playlist.toggle_queued(2);
+        let marks = BTreeMap::from([(Path::new("/m/a2.mp3"), 'a'), (Path::new("/m/b1.mp3"), 'b')]);
+        let shown = |query: &str, chips: &[Chip]| {
+            let mut on = Chips::default();
+            chips.iter().for_each(|&chip| {
+                on.toggle(chip);
+            });
+            TrackFilter::new(&playlist, query, on, &marks).indices().collect::<Vec<_>>()
+        };
+
+        assert_eq!(shown("", &[]), vec![0, 1, 2, 3]);
+        assert_eq!(shown("", &[Chip::Queued]), vec![0, 2]);
+        assert_eq!(shown("", &[Chip::Marked]), vec![1, 2]);
+        assert_eq!(shown("", &[Chip::Queued, Chip::Marked]), vec![2]);
+        assert_eq!(shown("a1", &[Chip::Queued]), vec![0]);
+        assert_eq!(shown("a2", &[Chip::Queued]), Vec::<usize>::new());
+        assert_eq!(shown("", &[Chip::Unplayable]), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_album_chip_follows_playing_track() {
+        let playlist = playlist();
+        let marks = BTreeMap::new();
+        let mut chips = Chips::default();
+        chips.toggle(Chip::Album);
+        let filter = TrackFilter::new(&playlist, "", chips, &marks);
+        // Same album name by another artist is another album
+        assert_eq!(filter.indices().collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!(filter.count(Chip::Album), 2);
+        assert_eq!(filter.count(Chip::Queued), 0);
+    }
+}
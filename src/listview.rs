@@ -0,0 +1,213 @@
+//! Scrolling for lists whose rows may span several lines.
+//!
+//! A [`RowModel`] says how many logical rows there are and how tall each one
+//! is; a [`Viewport`] keeps the selected row on screen with some context
+//! around it and tells the renderer which rows to materialize.
+
+use std::ops::Range;
+
+/// Logical rows of a list and their heights in lines.
+pub trait RowModel {
+    /// Returns the number of rows.
+    fn len(&self) -> usize;
+
+    /// Returns true if there are no rows.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the height of a row, at least 1.
+    fn height(&self, row: usize) -> u16;
+}
+
+/// A list of `n` single-line rows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UniformRows(pub usize);
+
+impl RowModel for UniformRows {
+    fn len(&self) -> usize {
+        self.0
+    }
+
+    fn height(&self, _row: usize) -> u16 {
+        1
+    }
+}
+
+/// The visible window onto a list.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Viewport {
+    /// First visible row.
+    pub offset: usize,
+    /// Lines available for rows.
+    pub height: u16,
+    /// Lines of context kept above and below the selection when possible.
+    pub margin: u16,
+}
+
+impl Viewport {
+    pub fn new(margin: u16) -> Self {
+        Self { offset: 0, height: 0, margin }
+    }
+
+    /// Scrolls as little as possible to show `selected` with its margin.
+    pub fn scroll_to(&mut self, rows: &impl RowModel, selected: usize) {
+        if rows.is_empty() || self.height == 0 {
+            self.offset = 0;
+            return;
+        }
+        let selected = selected.min(rows.len() - 1);
+        let selected_height = rows.height(selected).max(1);
+        // A margin larger than the room around the selection would make it jump
+        let margin = self.margin.min(self.height.saturating_sub(selected_height) / 2);
+
+        // Enough rows below the selection to fill the margin, if there are any
+        let below = lines_after(rows, selected, margin);
+        let lowest = first_fitting(rows, selected, selected_height.saturating_add(below), self.height);
+        let highest = row_above(rows, selected, margin);
+        self.offset = self.offset.clamp(lowest, highest.max(lowest));
+        self.offset = self.offset.min(last_offset(rows, self.height));
+    }
+
+    /// Scrolls so that `selected` is in the middle of the viewport.
+    pub fn center_on(&mut self, rows: &impl RowModel, selected: usize) {
+        if rows.is_empty() || self.height == 0 {
+            self.offset = 0;
+            return;
+        }
+        let selected = selected.min(rows.len() - 1);
+        let above = self.height.saturating_sub(rows.height(selected).max(1)) / 2;
+        self.offset = row_above(rows, selected, above).min(last_offset(rows, self.height));
+    }
+
+    /// Returns the rows that intersect the viewport, including a partly
+    /// visible last row.
+    pub fn visible(&self, rows: &impl RowModel) -> Range<usize> {
+        let start = self.offset.min(rows.len());
+        let mut end = start;
+        let mut used = 0u32;
+        while end < rows.len() && used < u32::from(self.height) {
+            used += u32::from(rows.height(end).max(1));
+            end += 1;
+        }
+        start..end
+    }
+}
+
+/// Returns how many of up to `wanted` lines the rows after `row` fill.
+fn lines_after(rows: &impl RowModel, row: usize, wanted: u16) -> u16 {
+    let mut lines = 0u16;
+    for next in row + 1..rows.len() {
+        if lines >= wanted {
+            break;
+        }
+        lines = lines.saturating_add(rows.height(next).max(1));
+    }
+    lines.min(wanted)
+}
+
+/// Returns the topmost row such that the rows up to `row` together with
+/// `lines` further lines fit in `height`.
+fn first_fitting(rows: &impl RowModel, row: usize, lines: u16, height: u16) -> usize {
+    let mut start = row;
+    let mut used = u32::from(lines);
+    while start > 0 {
+        let above = u32::from(rows.height(start - 1).max(1));
+        if used + above > u32::from(height) {
+            break;
+        }
+        used += above;
+        start -= 1;
+    }
+    start
+}
+
+/// Returns the row that leaves at least `lines` lines above `row`, or the
+/// first row.
+fn row_above(rows: &impl RowModel, row: usize, lines: u16) -> usize {
+    let mut start = row;
+    let mut used = 0u16;
+    while start > 0 && used < lines {
+        start -= 1;
+        used = used.saturating_add(rows.height(start).max(1));
+    }
+    start
+}
+
+/// Returns the largest offset that does not leave empty lines at the bottom.
+fn last_offset(rows: &impl RowModel, height: u16) -> usize {
+    first_fitting(rows, rows.len() - 1, rows.height(rows.len() - 1).max(1), height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Rows(Vec<u16>);
+
+    impl RowModel for Rows {
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+
+        fn height(&self, row: usize) -> u16 {
+            self.0[row]
+        }
+    }
+
+    fn offset_after(rows: &Rows, height: u16, margin: u16, path: &[usize]) -> usize {
+        let mut viewport = Viewport { height, ..Viewport::new(margin) };
+        for &selected in path {
+            viewport.scroll_to(rows, selected);
+        }
+        viewport.offset
+    }
+
+    #[test]
+    fn test_scroll_offset() {
+        let rows = Rows(vec![1; 100]);
+        // Without a margin the selection may touch the edges
+        assert_eq!(offset_after(&rows, 10, 0, &[9]), 0);
+        assert_eq!(offset_after(&rows, 10, 0, &[10]), 1);
+        assert_eq!(offset_after(&rows, 10, 0, &[50, 45]), 41);
+        // With a margin the view moves before the selection reaches the edge
+        assert_eq!(offset_after(&rows, 10, 2, &[7]), 0);
+        assert_eq!(offset_after(&rows, 10, 2, &[8]), 1);
+        assert_eq!(offset_after(&rows, 10, 2, &[50, 42]), 40);
+        // Near the ends the margin cannot be kept
+        assert_eq!(offset_after(&rows, 10, 2, &[1]), 0);
+        assert_eq!(offset_after(&rows, 10, 2, &[99]), 90);
+        // An oversized margin is shrunk rather than making the view jump
+        assert_eq!(offset_after(&rows, 5, 10, &[20]), 18);
+        // Short lists never scroll
+        assert_eq!(offset_after(&Rows(vec![1; 5]), 10, 2, &[4]), 0);
+        assert_eq!(offset_after(&Rows(vec![]), 10, 2, &[3]), 0);
+    }
+
+    #[test]
+    fn test_scroll_offset_with_tall_rows() {
+        // A header of 2 lines every fourth row
+        let rows = Rows((0..40).map(|i| if i % 4 == 0 { 2 } else { 1 }).collect());
+        let mut viewport = Viewport { height: 6, ..Viewport::new(0) };
+        viewport.scroll_to(&rows, 5);
+        // Rows 1..=5 take 1+1+1+2+1 = 6 lines
+        assert_eq!(viewport.offset, 1);
+        assert_eq!(viewport.visible(&rows), 1..6);
+        viewport.offset = 6;
+        viewport.height = 3;
+        // The header at row 8 is only partly visible but still materialized
+        assert_eq!(viewport.visible(&rows), 6..9);
+    }
+
+    #[test]
+    fn test_center_on() {
+        let rows = Rows(vec![1; 100]);
+        let mut viewport = Viewport { height: 11, ..Viewport::new(2) };
+        viewport.center_on(&rows, 50);
+        assert_eq!(viewport.offset, 45);
+        viewport.center_on(&rows, 3);
+        assert_eq!(viewport.offset, 0);
+        viewport.center_on(&rows, 98);
+        assert_eq!(viewport.offset, 89);
+    }
+}
@@ -0,0 +1,91 @@
+//! Keeps two juke instances from writing the same state directory.
+//!
+//! The session, library, history, and caches are rewritten whole, so two
+//! instances sharing them would each undo the other's changes. The first
+//! instance holds an advisory lock on `juke.lock` for as long as it runs;
+//! a second one finds it taken and leaves the state alone.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, Write};
+use std::path::Path;
+
+/// Held for as long as this instance may write the state directory. The
+/// lock is released when the file is closed, even after a crash.
+#[derive(Debug)]
+pub struct StateLock {
+    _file: File,
+}
+
+/// Why the state directory could not be locked.
+#[derive(Debug)]
+pub enum LockError {
+    /// Another instance holds the lock; its process ID, if it wrote one.
+    Held(Option<u32>),
+    Io(io::Error),
+}
+
+impl std::fmt::Display for LockError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LockError::Held(Some(pid)) => write!(f, "State directory in use by juke (pid {})", pid),
+            LockError::Held(None) => write!(f, "State directory in use by another juke"),
+            LockError::Io(e) => write!(f, "Cannot lock the state directory: {}", e),
+        }
+    }
+}
+
+impl From<io::Error> for LockError {
+    fn from(e: io::Error) -> Self {
+        LockError::Io(e)
+    }
+}
+
+impl StateLock {
+    /// Takes the lock on a state directory without waiting.
+    pub fn acquire(state_dir: &Path) -> Result<Self, LockError> {
+        fs::create_dir_all(state_dir)?;
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(state_dir.join("juke.lock"))?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(fs::TryLockError::WouldBlock) => {
+                let mut pid = String::new();
+                let _ = file.read_to_string(&mut pid);
+                return Err(LockError::Held(pid.trim().parse().ok()));
+            }
+            Err(fs::TryLockError::Error(e)) => return Err(LockError::Io(e)),
+        }
+
+        // Tell a second instance who has the lock
+        file.set_len(0)?;
+        file.rewind()?;
+        write!(file, "{}", std::process::id())?;
+        file.flush()?;
+        Ok(Self { _file: file })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_second_instance_is_locked_out() {
+        let dir = std::env::temp_dir().join(format!("juke_lock_{}", std::process::id()));
+
+        let first = StateLock::acquire(&dir).unwrap();
+        let second = StateLock::acquire(&dir);
+        assert!(matches!(second, Err(LockError::Held(Some(pid))) if pid == std::process::id()));
+
+        // Released when the first instance goes away
+        drop(first);
+        let third = StateLock::acquire(&dir);
+        assert!(third.is_ok());
+        drop(third);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
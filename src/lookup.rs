@@ -0,0 +1,653 @@
+//! MusicBrainz lookup of missing tags (`musicbrainz` feature).
+//!
+//! Tracks without tags are matched by whatever tags they do have, or their
+//! file name, plus their duration. Results are cached on disk, including
+//! misses, so each file is only looked up once. With the `acoustid` feature,
+//! completely untagged files are identified by audio fingerprint instead.
+
+use crate::config::LookupConfig;
+use crate::tagwrite::TagEdit;
+use crate::playlist::Track;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Whether this build can reach MusicBrainz.
+pub const AVAILABLE: bool = cfg!(feature = "musicbrainz");
+
+/// MusicBrainz asks every client to identify itself.
+#[cfg_attr(not(feature = "musicbrainz"), allow(dead_code))]
+const USER_AGENT: &str = concat!("juke/", env!("CARGO_PKG_VERSION"), " ( https://github.com/cugniere/juke )");
+
+const SEARCH_URL: &str = "https://musicbrainz.org/ws/2/recording/";
+
+/// MusicBrainz allows one request per second on average.
+const REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How far a recording's length may differ from the file's duration.
+const DURATION_TOLERANCE_MS: u64 = 3000;
+
+/// How long to stop making requests after the network first fails. Each
+/// failure in a row doubles it, up to [`OFFLINE_BACKOFF_MAX`].
+const OFFLINE_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Longest wait between attempts while the network stays down.
+const OFFLINE_BACKOFF_MAX: Duration = Duration::from_secs(30 * 60);
+
+/// How long a lookup that found nothing is remembered before trying again.
+const MISS_EXPIRY_SECS: u64 = 30 * 24 * 60 * 60;
+
+/// Metadata found for a track.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Proposal {
+    pub title: String,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    /// Match score from 0 to 100.
+    pub score: u8,
+}
+
+impl Proposal {
+    /// Returns the edit writing the proposed tags into a file, only where
+    /// it has none.
+    pub fn tag_edit(&self) -> TagEdit {
+        TagEdit::FillMissing { title: self.title.clone(), artist: self.artist.clone(), album: self.album.clone() }
+    }
+}
+
+/// Errors from looking up a track.
+#[derive(Debug)]
+pub enum LookupError {
+    /// juke was built without the `musicbrainz` feature.
+    Disabled,
+    /// The network is unreachable.
+    Offline(String),
+    /// The server refused or failed the request.
+    #[cfg_attr(not(feature = "musicbrainz"), allow(dead_code))]
+    Http(String),
+    /// The response could not be understood.
+    #[cfg_attr(not(feature = "musicbrainz"), allow(dead_code))]
+    Parse(String),
+}
+
+impl fmt::Display for LookupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LookupError::Disabled => write!(f, "juke was built without MusicBrainz support (feature \"musicbrainz\")"),
+            LookupError::Offline(e) => write!(f, "Offline: {}", e),
+            LookupError::Http(e) => write!(f, "MusicBrainz request failed: {}", e),
+            LookupError::Parse(e) => write!(f, "Unexpected MusicBrainz response: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for LookupError {}
+
+/// Returns true if a track is missing tags a lookup could fill in.
+pub fn needs_lookup(track: &Track) -> bool {
+    !track.inferred.looked_up
+        && (track.title.is_none()
+            || track.artist.is_none()
+            || track.album.is_none()
+            || track.inferred.artist
+            || track.inferred.album)
+}
+
+/// Builds the MusicBrainz search query for a track.
+///
+/// Only real tags narrow the search; guesses from the folder layout are
+/// left out since a wrong one would rule out the right recording.
+pub fn build_query(track: &Track) -> Option<String> {
+    let title = track.title.clone().or_else(|| title_from_file_name(&track.path))?;
+    let mut query = format!("recording:{}", phrase(&title));
+    if let Some(artist) = track.artist.as_ref().filter(|_| !track.inferred.artist) {
+        query.push_str(&format!(" AND artist:{}", phrase(artist)));
+    }
+    if let Some(album) = track.album.as_ref().filter(|_| !track.inferred.album) {
+        query.push_str(&format!(" AND release:{}", phrase(album)));
+    }
+    if let Some(duration) = track.duration {
+        let ms = duration.as_millis() as u64;
+        query.push_str(&format!(
+            " AND dur:[{} TO {}]",
+            ms.saturating_sub(DURATION_TOLERANCE_MS),
+            ms + DURATION_TOLERANCE_MS
+        ));
+    }
+    Some(query)
+}
+
+/// Guesses a title from a file name like "03 - Song_Name.mp3".
+fn title_from_file_name(path: &Path) -> Option<String> {
+    let stem = path.file_stem()?.to_string_lossy().replace('_', " ");
+    let title = stem
+        .trim_start_matches(|c: char| c.is_ascii_digit())
+        .trim_start_matches([' ', '-', '.'])
+        .trim();
+    (!title.is_empty()).then(|| title.to_string())
+}
+
+/// Quotes a value as a Lucene phrase.
+fn phrase(value: &str) -> String {
+    let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
+    format!("\"{}\"", escaped)
+}
+
+/// Percent-encodes a query string value.
+fn url_encode(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => out.push(byte as char),
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+/// Picks the best recording from a search response.
+///
+/// Recordings are accepted only with at least `min_score`, and when both
+/// lengths are known, only if they match the track's duration.
+pub fn parse_response(response: &Value, duration: Option<Duration>, min_score: u8) -> Option<Proposal> {
+    let Some(Value::Array(recordings)) = response.get("recordings") else {
+        return None;
+    };
+    recordings.iter().find_map(|recording| {
+        let score = recording.get("score")?.as_f64()?.clamp(0.0, 100.0) as u8;
+        if score < min_score {
+            return None;
+        }
+        if let (Some(length), Some(duration)) = (recording.get("length").and_then(Value::as_f64), duration)
+            && (length - duration.as_millis() as f64).abs() > DURATION_TOLERANCE_MS as f64
+        {
+            return None;
+        }
+
+        let artist = match recording.get("artist-credit") {
+            Some(Value::Array(credits)) => {
+                let names: String = credits
+                    .iter()
+                    .map(|credit| {
+                        let name = credit.get("name").and_then(Value::as_str).unwrap_or_default();
+                        let join = credit.get("joinphrase").and_then(Value::as_str).unwrap_or_default();
+                        format!("{}{}", name, join)
+                    })
+                    .collect();
+                Some(names.trim().to_string()).filter(|n| !n.is_empty())
+            }
+            _ => None,
+        };
+        let album = match recording.get("releases") {
+            Some(Value::Array(releases)) => releases
+                .first()
+                .and_then(|r| r.get("title"))
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            _ => None,
+        };
+
+        Some(Proposal {
+            title: recording.get("title")?.as_str()?.to_string(),
+            artist,
+            album,
+            score,
+        })
+    })
+}
+
+/// Fills in a track's missing or guessed fields from a lookup result.
+///
+/// The fields are marked as looked up, so they are never written back as
+/// tags by accident. Returns true if anything changed.
+pub fn apply(track: &mut Track, proposal: &Proposal) -> bool {
+    let mut changed = false;
+    if track.title.is_none() {
+        track.title = Some(proposal.title.clone());
+        track.inferred.title = true;
+        changed = true;
+    }
+    if let Some(artist) = &proposal.artist
+        && (track.artist.is_none() || track.inferred.artist)
+    {
+        track.artist = Some(artist.clone());
+        track.inferred.artist = true;
+        changed = true;
+    }
+    if let Some(album) = &proposal.album
+        && (track.album.is_none() || track.inferred.album)
+    {
+        track.album = Some(album.clone());
+        track.inferred.album = true;
+        changed = true;
+    }
+    track.inferred.looked_up = true;
+    changed
+}
+
+
+/// Spaces requests out to respect the server's rate limit.
+#[derive(Debug, Clone)]
+struct RateLimiter {
+    interval: Duration,
+    last: Option<Instant>,
+}
+
+impl RateLimiter {
+    fn new(interval: Duration) -> Self {
+        Self { interval, last: None }
+    }
+
+    /// Returns how long to wait before the next request may be made.
+    fn wait_time(&self, now: Instant) -> Duration {
+        self.last
+            .map_or(Duration::ZERO, |last| self.interval.saturating_sub(now.duration_since(last)))
+    }
+
+    /// Sleeps until the next request may be made, then records it.
+    #[cfg_attr(not(feature = "musicbrainz"), allow(dead_code))]
+    fn wait(&mut self) {
+        std::thread::sleep(self.wait_time(Instant::now()));
+        self.last = Some(Instant::now());
+    }
+}
+
+/// A cached lookup; `proposal` is empty when nothing matched.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    /// Seconds since the Unix epoch when the lookup was made.
+    checked: u64,
+    #[serde(default)]
+    proposal: Option<Proposal>,
+}
+
+/// Lookup results kept between runs, keyed by search query.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LookupCache {
+    #[serde(default)]
+    queries: BTreeMap<String, CacheEntry>,
+    /// File the cache was loaded from and is saved to.
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl LookupCache {
+    /// Loads the cache from a state directory, returning an empty one if it is
+    /// missing or unreadable. Without a state directory nothing is persisted.
+    pub fn load(state_dir: Option<&Path>) -> Self {
+        let path = state_dir.map(|dir| dir.join("lookup.toml"));
+        let cache: Self = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default();
+        Self { path, ..cache }
+    }
+
+    /// Saves the cache to the file it was loaded from.
+    pub fn save(&self) -> std::io::Result<()> {
+        let path = self.path.as_ref().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "no state directory")
+        })?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let toml_string = toml::to_string_pretty(self).map_err(std::io::Error::other)?;
+        fs::write(path, toml_string)
+    }
+
+    /// Returns the cached result for a query, if any. Misses expire so
+    /// recordings added to MusicBrainz later are eventually found.
+    fn get(&self, query: &str, now: u64) -> Option<Option<Proposal>> {
+        let entry = self.queries.get(query)?;
+        if entry.proposal.is_none() && now.saturating_sub(entry.checked) > MISS_EXPIRY_SECS {
+            return None;
+        }
+        Some(entry.proposal.clone())
+    }
+
+    fn insert(&mut self, query: String, now: u64, proposal: Option<Proposal>) {
+        self.queries.insert(query, CacheEntry { checked: now, proposal });
+    }
+}
+
+/// Looks up tracks one at a time, with caching and rate limiting.
+pub struct Lookup {
+    cache: LookupCache,
+    min_score: u8,
+    #[cfg_attr(not(feature = "acoustid"), allow(dead_code))]
+    acoustid_api_key: Option<String>,
+    #[cfg_attr(not(feature = "musicbrainz"), allow(dead_code))]
+    limiter: RateLimiter,
+    offline_until: Option<Instant>,
+    /// Network failures in a row.
+    failures: u32,
+}
+
+impl Lookup {
+    pub fn new(config: &LookupConfig, state_dir: Option<&Path>) -> Self {
+        Self {
+            cache: LookupCache::load(state_dir),
+            min_score: config.min_score.min(100),
+            acoustid_api_key: config.acoustid_api_key.clone().filter(|k| !k.is_empty()),
+            limiter: RateLimiter::new(REQUEST_INTERVAL),
+            offline_until: None,
+            failures: 0,
+        }
+    }
+
+    /// Returns how long until requests are made again after a network
+    /// failure; zero when they are not held back.
+    pub fn retry_in(&self) -> Duration {
+        self.offline_until.map_or(Duration::ZERO, |until| until.saturating_duration_since(Instant::now()))
+    }
+
+    /// Looks up a track, returning `None` if nothing matched well enough.
+    ///
+    /// After a network failure, requests stop for a while and only cached
+    /// results are returned.
+    pub fn lookup(&mut self, track: &Track) -> Result<Option<Proposal>, LookupError> {
+        #[cfg(feature = "acoustid")]
+        if track.title.is_none() && track.artist.is_none() && self.acoustid_api_key.is_some() {
+            let key = format!("acoustid:{}", crate::playlist::canonical_key(&track.path).display());
+            return self.cached_or_fetch(key, |lookup| lookup.fetch_acoustid(&track.path));
+        }
+
+        let Some(query) = build_query(track) else {
+            return Ok(None);
+        };
+        let url = format!("{}?query={}&fmt=json&limit=5", SEARCH_URL, url_encode(&query));
+        let duration = track.duration;
+        self.cached_or_fetch(query, |lookup| {
+            let response = lookup.fetch_json(&url)?;
+            Ok(parse_response(&response, duration, lookup.min_score))
+        })
+    }
+
+    /// Returns a cached result, or fetches and caches a new one.
+    fn cached_or_fetch(
+        &mut self,
+        key: String,
+        fetch: impl FnOnce(&mut Self) -> Result<Option<Proposal>, LookupError>,
+    ) -> Result<Option<Proposal>, LookupError> {
+        let now = unix_now();
+        if let Some(cached) = self.cache.get(&key, now) {
+            return Ok(cached);
+        }
+        if let Some(until) = self.offline_until {
+            if Instant::now() < until {
+                return Err(LookupError::Offline("waiting to retry".to_string()));
+            }
+            self.offline_until = None;
+        }
+
+        match fetch(self) {
+            Ok(proposal) => {
+                self.failures = 0;
+                self.cache.insert(key, now, proposal.clone());
+                Ok(proposal)
+            }
+            Err(e) => {
+                if matches!(e, LookupError::Offline(_)) {
+                    self.offline_until = Some(Instant::now() + offline_backoff(self.failures));
+                    self.failures = self.failures.saturating_add(1);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Saves the cache, ignoring a missing state directory.
+    pub fn save(&self) -> std::io::Result<()> {
+        match self.cache.save() {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && self.cache.path.is_none() => Ok(()),
+            result => result,
+        }
+    }
+
+    #[cfg(feature = "musicbrainz")]
+    fn fetch_json(&mut self, url: &str) -> Result<Value, LookupError> {
+        self.limiter.wait();
+        let response = ureq::get(url)
+            .set("User-Agent", USER_AGENT)
+            .set("Accept", "application/json")
+            .timeout(Duration::from_secs(15))
+            .call();
+        let body = match response {
+            Ok(response) => response.into_string().map_err(|e| LookupError::Http(e.to_string()))?,
+            Err(ureq::Error::Status(code, _)) => return Err(LookupError::Http(format!("HTTP {}", code))),
+            Err(ureq::Error::Transport(e)) => return Err(LookupError::Offline(e.to_string())),
+        };
+        serde_json::from_str::<Value>(&body).map_err(|e| LookupError::Parse(e.to_string()))
+    }
+
+    #[cfg(not(feature = "musicbrainz"))]
+    fn fetch_json(&mut self, _url: &str) -> Result<Value, LookupError> {
+        Err(LookupError::Disabled)
+    }
+
+    /// Identifies a file by its Chromaprint fingerprint, computed with `fpcalc`.
+    #[cfg(feature = "acoustid")]
+    fn fetch_acoustid(&mut self, path: &Path) -> Result<Option<Proposal>, LookupError> {
+        let output = std::process::Command::new("fpcalc")
+            .arg("-json")
+            .arg(path)
+            .output()
+            .map_err(|e| LookupError::Http(format!("cannot run fpcalc: {}", e)))?;
+        let print = serde_json::from_str::<Value>(&String::from_utf8_lossy(&output.stdout))
+            .map_err(|e| LookupError::Parse(format!("fpcalc: {}", e)))?;
+        let (Some(duration), Some(fingerprint)) = (
+            print.get("duration").and_then(Value::as_f64),
+            print.get("fingerprint").and_then(Value::as_str),
+        ) else {
+            return Ok(None);
+        };
+
+        let url = format!(
+            "https://api.acoustid.org/v2/lookup?client={}&meta=recordings+releasegroups&duration={}&fingerprint={}",
+            url_encode(self.acoustid_api_key.as_deref().unwrap_or_default()),
+            duration.round() as u64,
+            url_encode(fingerprint)
+        );
+        let response = self.fetch_json(&url)?;
+        Ok(parse_acoustid(&response, self.min_score))
+    }
+}
+
+/// Picks the best recording from an AcoustID lookup response.
+#[cfg(feature = "acoustid")]
+fn parse_acoustid(response: &Value, min_score: u8) -> Option<Proposal> {
+    let Some(Value::Array(results)) = response.get("results") else {
+        return None;
+    };
+    results.iter().find_map(|result| {
+        let score = (result.get("score")?.as_f64()? * 100.0).clamp(0.0, 100.0) as u8;
+        if score < min_score {
+            return None;
+        }
+        let Some(Value::Array(recordings)) = result.get("recordings") else {
+            return None;
+        };
+        let recording = recordings.iter().find(|r| r.get("title").is_some())?;
+        let first_name = |key: &str| match recording.get(key) {
+            Some(Value::Array(items)) => items
+                .first()
+                .and_then(|item| item.get(if key == "artists" { "name" } else { "title" }))
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            _ => None,
+        };
+        Some(Proposal {
+            title: recording.get("title")?.as_str()?.to_string(),
+            artist: first_name("artists"),
+            album: first_name("releasegroups"),
+            score,
+        })
+    })
+}
+
+/// Looks up tracks in the background, sending results as they arrive.
+///
+/// The thread stops early if the receiver is dropped.
+pub fn spawn(tracks: Vec<Track>, mut lookup: Lookup) -> mpsc::Receiver<(PathBuf, Proposal)> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        for track in tracks.iter().filter(|t| needs_lookup(t)) {
+            // While the network is down, the track and those after it wait
+            let result = loop {
+                match lookup.lookup(track) {
+                    Err(LookupError::Offline(_)) => {
+                        let _ = lookup.save();
+                        std::thread::sleep(lookup.retry_in());
+                    }
+                    result => break result,
+                }
+            };
+            if let Ok(Some(proposal)) = result
+                && tx.send((track.path.clone(), proposal)).is_err()
+            {
+                break;
+            }
+        }
+        let _ = lookup.save();
+    });
+    rx
+}
+
+/// Returns how long to wait after `failures` network failures in a row
+/// and one more.
+fn offline_backoff(failures: u32) -> Duration {
+    OFFLINE_BACKOFF.saturating_mul(1 << failures.min(16)).min(OFFLINE_BACKOFF_MAX)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(path: &str) -> Track {
+        Track::new(PathBuf::from(path))
+    }
+
+    #[test]
+    fn test_build_query() {
+        let mut t = track("/music/Artist/Album/03 - Some_Song.mp3");
+        t.duration = Some(Duration::from_secs(200));
+        assert_eq!(
+            build_query(&t).unwrap(),
+            "recording:\"Some Song\" AND dur:[197000 TO 203000]"
+        );
+
+        // Tags are used, but guesses from folders are not
+        t.title = Some("Say \"Hi\"".to_string());
+        t.artist = Some("Artist".to_string());
+        t.album = Some("Album".to_string());
+        t.inferred.album = true;
+        t.duration = None;
+        assert_eq!(build_query(&t).unwrap(), "recording:\"Say \\\"Hi\\\"\" AND artist:\"Artist\"");
+
+        assert_eq!(build_query(&track("/music/01.mp3")), None);
+        assert_eq!(url_encode("a b/\"ü\""), "a%20b%2F%22%C3%BC%22");
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let response = serde_json::from_str::<Value>(
+            r#"{"recordings":[
+                {"score":100,"title":"Wrong Length","length":90000},
+                {"score":95,"title":"Song","length":201000,
+                 "artist-credit":[{"name":"A","joinphrase":" & "},{"name":"B"}],
+                 "releases":[{"title":"Album"},{"title":"Compilation"}]},
+                {"score":99,"title":"Later"}
+            ]}"#,
+        )
+        .unwrap();
+        let proposal = parse_response(&response, Some(Duration::from_secs(200)), 90).unwrap();
+        assert_eq!(
+            proposal,
+            Proposal {
+                title: "Song".to_string(),
+                artist: Some("A & B".to_string()),
+                album: Some("Album".to_string()),
+                score: 95,
+            }
+        );
+        assert_eq!(parse_response(&response, Some(Duration::from_secs(200)), 96).unwrap().title, "Later");
+        assert_eq!(parse_response(&serde_json::from_str::<Value>(r#"{"recordings":[]}"#).unwrap(), None, 0), None);
+        assert_eq!(parse_response(&serde_json::from_str::<Value>(r#"{"error":"x"}"#).unwrap(), None, 0), None);
+    }
+
+    #[test]
+    fn test_apply_only_fills_gaps() {
+        let mut t = track("/music/a.mp3");
+        t.title = Some("Tagged".to_string());
+        t.album = Some("Folder Guess".to_string());
+        t.inferred.album = true;
+        let proposal = Proposal {
+            title: "Other".to_string(),
+            artist: Some("Artist".to_string()),
+            album: Some("Album".to_string()),
+            score: 100,
+        };
+
+        assert!(needs_lookup(&t));
+        assert!(apply(&mut t, &proposal));
+        assert_eq!(t.title.as_deref(), Some("Tagged"));
+        assert_eq!(t.artist.as_deref(), Some("Artist"));
+        assert_eq!(t.album.as_deref(), Some("Album"));
+        assert!(t.inferred.artist && t.inferred.album && !t.inferred.title && t.inferred.looked_up);
+        assert!(!needs_lookup(&t));
+    }
+
+    #[test]
+    fn test_cache_and_rate_limit() {
+        let mut cache = LookupCache::default();
+        cache.insert("hit".to_string(), 0, None);
+        assert_eq!(cache.get("hit", 1), Some(None));
+        assert_eq!(cache.get("hit", MISS_EXPIRY_SECS + 1), None);
+        assert_eq!(cache.get("other", 0), None);
+
+        let mut limiter = RateLimiter::new(Duration::from_secs(1));
+        let now = Instant::now();
+        assert_eq!(limiter.wait_time(now), Duration::ZERO);
+        limiter.last = Some(now);
+        assert!(limiter.wait_time(now) > Duration::from_millis(900));
+        assert_eq!(limiter.wait_time(now + Duration::from_secs(2)), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_offline_backoff() {
+        assert_eq!(offline_backoff(0), OFFLINE_BACKOFF);
+        assert_eq!(offline_backoff(1), OFFLINE_BACKOFF * 2);
+        assert_eq!(offline_backoff(3), OFFLINE_BACKOFF * 8);
+        assert_eq!(offline_backoff(10), OFFLINE_BACKOFF_MAX);
+        assert_eq!(offline_backoff(u32::MAX), OFFLINE_BACKOFF_MAX);
+
+        let mut lookup = Lookup::new(&LookupConfig::default(), None);
+        assert_eq!(lookup.retry_in(), Duration::ZERO);
+        lookup.offline_until = Some(Instant::now() + OFFLINE_BACKOFF);
+        assert!(lookup.retry_in() > OFFLINE_BACKOFF / 2);
+    }
+
+    #[cfg(feature = "acoustid")]
+    #[test]
+    fn test_parse_acoustid() {
+        let response = serde_json::from_str::<Value>(
+            r#"{"status":"ok","results":[{"score":0.97,"recordings":[
+                {"title":"Song","artists":[{"name":"Artist"}],"releasegroups":[{"title":"Album"}]}]}]}"#,
+        )
+        .unwrap();
+        let proposal = parse_acoustid(&response, 90).unwrap();
+        assert_eq!((proposal.title.as_str(), proposal.score), ("Song", 97));
+        assert_eq!(proposal.album.as_deref(), Some("Album"));
+        assert_eq!(parse_acoustid(&response, 98), None);
+    }
+}
@@ -0,0 +1,667 @@
+//! EBU R128 loudness analysis, the loudness cache, and the `--scan-loudness` command.
+
+use crate::tagwrite::{TagEdit, TagWriteQueue};
+use rodio::{Decoder, Source};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::time::UNIX_EPOCH;
+
+/// ReplayGain 2.0 reference level in LUFS.
+pub const REPLAYGAIN_REFERENCE_LUFS: f64 = -18.0;
+
+/// Absolute gating threshold in LUFS.
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+
+/// Relative gating threshold in LU below the ungated loudness.
+const RELATIVE_GATE_LU: f64 = -10.0;
+
+/// Number of cache entries written between intermediate saves during a scan.
+const SAVE_INTERVAL: usize = 25;
+
+/// Oversampling factor used for true peak estimation.
+const OVERSAMPLE: usize = 4;
+
+/// Taps per polyphase branch of the oversampling filter.
+const TAPS_PER_PHASE: usize = 12;
+
+/// Loudness measurement of a single file.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Loudness {
+    /// Integrated loudness in LUFS, or `None` if the file is entirely below the absolute gate.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub integrated_lufs: Option<f64>,
+    /// True peak in dBTP, or `None` for digital silence.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub true_peak_dbtp: Option<f64>,
+}
+
+impl Loudness {
+    /// Returns the ReplayGain track gain in dB relative to the -18 LUFS reference.
+    pub fn replay_gain_db(&self) -> Option<f64> {
+        self.integrated_lufs
+            .map(|lufs| REPLAYGAIN_REFERENCE_LUFS - lufs)
+    }
+
+    /// Returns the true peak as a linear amplitude (1.0 = full scale).
+    pub fn true_peak_linear(&self) -> f64 {
+        self.true_peak_dbtp
+            .map(|db| 10f64.powf(db / 20.0))
+            .unwrap_or(0.0)
+    }
+}
+
+/// Second-order IIR filter section (direct form I).
+#[derive(Debug, Clone, Copy)]
+struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    x1: f64,
+    x2: f64,
+    y1: f64,
+    y2: f64,
+}
+
+impl Biquad {
+    fn new(b0: f64, b1: f64, b2: f64, a1: f64, a2: f64) -> Self {
+        Self { b0, b1, b2, a1, a2, x1: 0.0, x2: 0.0, y1: 0.0, y2: 0.0 }
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b0 * x + self.b1 * self.x1 + self.b2 * self.x2
+            - self.a1 * self.y1
+            - self.a2 * self.y2;
+        self.x2 = self.x1;
+        self.x1 = x;
+        self.y2 = self.y1;
+        self.y1 = y;
+        y
+    }
+}
+
+/// Builds the two K-weighting stages (high shelf + high pass) for a sample rate.
+///
+/// Coefficients are derived from the analog prototype so that any sample rate
+/// matches the 48 kHz reference values in ITU-R BS.1770.
+fn k_weighting(sample_rate: u32) -> (Biquad, Biquad) {
+    let fs = sample_rate as f64;
+
+    // Stage 1: high shelf modelling the acoustic effect of the head
+    let f0 = 1681.974450955533;
+    let gain_db = 3.999843853973347;
+    let q = 0.7071752369554196;
+    let k = (std::f64::consts::PI * f0 / fs).tan();
+    let vh = 10f64.powf(gain_db / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad::new(
+        (vh + vb * k / q + k * k) / a0,
+        2.0 * (k * k - vh) / a0,
+        (vh - vb * k / q + k * k) / a0,
+        2.0 * (k * k - 1.0) / a0,
+        (1.0 - k / q + k * k) / a0,
+    );
+
+    // Stage 2: RLB high pass
+    let f0 = 38.13547087602444;
+    let q = 0.5003270373238773;
+    let k = (std::f64::consts::PI * f0 / fs).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad::new(
+        1.0,
+        -2.0,
+        1.0,
+        2.0 * (k * k - 1.0) / a0,
+        (1.0 - k / q + k * k) / a0,
+    );
+
+    (shelf, high_pass)
+}
+
+/// Returns the BS.1770 weight for a channel in a standard layout.
+fn channel_weight(channel: usize, channels: usize) -> f64 {
+    if channels < 6 {
+        return 1.0;
+    }
+    match channel {
+        3 => 0.0,        // LFE
+        4 | 5 => 1.41,   // Surrounds
+        _ => 1.0,
+    }
+}
+
+/// Builds a windowed-sinc interpolation filter split into polyphase branches.
+fn oversampling_filter() -> Vec<[f64; TAPS_PER_PHASE]> {
+    let len = OVERSAMPLE * TAPS_PER_PHASE;
+    let center = (len - 1) as f64 / 2.0;
+    let mut phases = vec![[0.0; TAPS_PER_PHASE]; OVERSAMPLE];
+
+    for n in 0..len {
+        let t = (n as f64 - center) / OVERSAMPLE as f64;
+        let sinc = if t == 0.0 {
+            1.0
+        } else {
+            let x = std::f64::consts::PI * t;
+            x.sin() / x
+        };
+        // Blackman window
+        let w = 0.42 - 0.5 * (2.0 * std::f64::consts::PI * n as f64 / (len - 1) as f64).cos()
+            + 0.08 * (4.0 * std::f64::consts::PI * n as f64 / (len - 1) as f64).cos();
+        phases[n % OVERSAMPLE][n / OVERSAMPLE] = sinc * w;
+    }
+
+    phases
+}
+
+/// Per-channel analysis state.
+struct ChannelState {
+    shelf: Biquad,
+    high_pass: Biquad,
+    weight: f64,
+    /// Sum of squared K-weighted samples in the current 100 ms sub-block.
+    sub_block_sum: f64,
+    /// Recent input samples for the oversampling filter, newest first.
+    history: [f64; TAPS_PER_PHASE],
+    peak: f64,
+}
+
+/// Streaming EBU R128 analyzer fed with interleaved samples.
+pub struct LoudnessAnalyzer {
+    channels: Vec<ChannelState>,
+    next_channel: usize,
+    sub_block_len: usize,
+    sub_block_pos: usize,
+    /// Weighted mean square of each completed 100 ms sub-block.
+    sub_blocks: Vec<f64>,
+    oversample: bool,
+    phases: Vec<[f64; TAPS_PER_PHASE]>,
+}
+
+impl LoudnessAnalyzer {
+    /// Creates an analyzer for the given channel count and sample rate.
+    pub fn new(channels: u16, sample_rate: u32) -> Self {
+        let channels = channels.max(1) as usize;
+        let sample_rate = sample_rate.max(1);
+        let (shelf, high_pass) = k_weighting(sample_rate);
+
+        Self {
+            channels: (0..channels)
+                .map(|c| ChannelState {
+                    shelf,
+                    high_pass,
+                    weight: channel_weight(c, channels),
+                    sub_block_sum: 0.0,
+                    history: [0.0; TAPS_PER_PHASE],
+                    peak: 0.0,
+                })
+                .collect(),
+            next_channel: 0,
+            sub_block_len: (sample_rate as usize / 10).max(1),
+            sub_block_pos: 0,
+            sub_blocks: Vec::new(),
+            // Above 96 kHz the sample grid is already fine enough
+            oversample: sample_rate < 96_000,
+            phases: oversampling_filter(),
+        }
+    }
+
+    /// Feeds one interleaved sample normalized to [-1.0, 1.0].
+    pub fn push(&mut self, sample: f32) {
+        let x = sample as f64;
+        let oversample = self.oversample;
+        let channel = &mut self.channels[self.next_channel];
+
+        let filtered = channel.high_pass.process(channel.shelf.process(x));
+        channel.sub_block_sum += filtered * filtered;
+
+        channel.peak = channel.peak.max(x.abs());
+        if oversample {
+            channel.history.copy_within(0..TAPS_PER_PHASE - 1, 1);
+            channel.history[0] = x;
+            for phase in &self.phases {
+                let y: f64 = phase.iter().zip(&channel.history).map(|(h, s)| h * s).sum();
+                channel.peak = channel.peak.max(y.abs());
+            }
+        }
+
+        self.next_channel += 1;
+        if self.next_channel == self.channels.len() {
+            self.next_channel = 0;
+            self.sub_block_pos += 1;
+            if self.sub_block_pos == self.sub_block_len {
+                self.finish_sub_block();
+            }
+        }
+    }
+
+    /// Closes the current 100 ms sub-block.
+    fn finish_sub_block(&mut self) {
+        let len = self.sub_block_len as f64;
+        let power = self
+            .channels
+            .iter_mut()
+            .map(|c| {
+                let mean = c.sub_block_sum / len;
+                c.sub_block_sum = 0.0;
+                c.weight * mean
+            })
+            .sum();
+        self.sub_blocks.push(power);
+        self.sub_block_pos = 0;
+    }
+
+    /// Completes the analysis and returns the measurement.
+    pub fn finish(self) -> Loudness {
+        // 400 ms gating blocks with 75% overlap
+        let blocks: Vec<f64> = self
+            .sub_blocks
+            .windows(4)
+            .map(|w| w.iter().sum::<f64>() / 4.0)
+            .collect();
+
+        let peak = self.channels.iter().fold(0.0f64, |acc, c| acc.max(c.peak));
+
+        Loudness {
+            integrated_lufs: gated_loudness(&blocks),
+            true_peak_dbtp: (peak > 0.0).then(|| 20.0 * peak.log10()),
+        }
+    }
+}
+
+/// Converts a mean-square power to LUFS.
+fn power_to_lufs(power: f64) -> f64 {
+    -0.691 + 10.0 * power.log10()
+}
+
+/// Applies the absolute and relative gates to block powers.
+fn gated_loudness(blocks: &[f64]) -> Option<f64> {
+    let mean_above = |threshold: f64| -> Option<f64> {
+        let gated: Vec<f64> = blocks
+            .iter()
+            .copied()
+            .filter(|&p| p > 0.0 && power_to_lufs(p) > threshold)
+            .collect();
+        if gated.is_empty() {
+            None
+        } else {
+            Some(gated.iter().sum::<f64>() / gated.len() as f64)
+        }
+    };
+
+    let ungated = mean_above(ABSOLUTE_GATE_LUFS)?;
+    let relative = power_to_lufs(ungated) + RELATIVE_GATE_LU;
+    mean_above(relative.max(ABSOLUTE_GATE_LUFS)).map(power_to_lufs)
+}
+
+/// Decodes an audio file and measures its loudness.
+pub fn analyze_file(path: &Path) -> Result<Loudness, LoudnessError> {
+    let file = File::open(path).map_err(|e| LoudnessError::Io(e.to_string()))?;
+    let decoder = Decoder::new(BufReader::new(file))
+        .map_err(|e| LoudnessError::Decode(e.to_string()))?;
+
+    let mut analyzer = LoudnessAnalyzer::new(decoder.channels(), decoder.sample_rate());
+    for sample in decoder {
+        analyzer.push(sample as f32 / i16::MAX as f32);
+    }
+
+    Ok(analyzer.finish())
+}
+
+/// A cached measurement keyed to the file's modification time.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct LoudnessEntry {
+    /// File modification time in seconds since the Unix epoch.
+    pub mtime: u64,
+    #[serde(flatten)]
+    pub loudness: Loudness,
+}
+
+/// Persistent store of loudness measurements.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LoudnessCache {
+    #[serde(default)]
+    tracks: BTreeMap<String, LoudnessEntry>,
+    /// File the cache was loaded from and is saved to.
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl LoudnessCache {
+    /// Returns the path to the loudness cache file inside a state directory.
+    pub fn cache_path(state_dir: &Path) -> PathBuf {
+        state_dir.join("loudness.toml")
+    }
+
+    /// Loads the cache from a state directory, returning an empty one if it is
+    /// missing or unreadable. Without a state directory nothing is persisted.
+    pub fn load(state_dir: Option<&Path>) -> Self {
+        let path = state_dir.map(Self::cache_path);
+        let cache: Self = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default();
+        Self { path, ..cache }
+    }
+
+    /// Saves the cache to the file it was loaded from.
+    pub fn save(&self) -> std::io::Result<()> {
+        let path = self.path.as_ref().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "no state directory")
+        })?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let toml_string = toml::to_string_pretty(self)
+            .map_err(std::io::Error::other)?;
+
+        fs::write(path, toml_string)
+    }
+
+    /// Returns the number of stored measurements.
+    pub fn entry_count(&self) -> usize {
+        self.tracks.len()
+    }
+
+    /// Returns the stored measurement if it is still valid for the file on disk.
+    pub fn get(&self, path: &Path) -> Option<Loudness> {
+        let entry = self.tracks.get(&cache_key(path))?;
+        (file_mtime(path) == Some(entry.mtime)).then_some(entry.loudness)
+    }
+
+    /// Stores a measurement for a file.
+    pub fn insert(&mut self, path: &Path, mtime: u64, loudness: Loudness) {
+        self.tracks.insert(cache_key(path), LoudnessEntry { mtime, loudness });
+    }
+
+    /// Moves a stored measurement to a file's new location.
+    ///
+    /// `from` should be the canonical path captured before the move, since
+    /// the old location can no longer be resolved afterwards.
+    pub fn rename(&mut self, from: &Path, to: &Path) {
+        if let Some(entry) = self.tracks.remove(&cache_key(from)) {
+            self.tracks.insert(cache_key(to), entry);
+        }
+    }
+
+    /// Returns the ReplayGain track gain in dB for a file, if measured.
+    pub fn replay_gain_db(&self, path: &Path) -> Option<f32> {
+        self.get(path)
+            .and_then(|l| l.replay_gain_db())
+            .map(|db| db as f32)
+    }
+}
+
+/// Normalizes a path into a cache key.
+fn cache_key(path: &Path) -> String {
+    fs::canonicalize(path)
+        .unwrap_or_else(|_| path.to_path_buf())
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Returns the modification time of a file in seconds since the Unix epoch.
+fn file_mtime(path: &Path) -> Option<u64> {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+}
+
+/// Writes REPLAYGAIN_TRACK_GAIN and REPLAYGAIN_TRACK_PEAK tags into a file.
+fn write_replay_gain_tags(tags: &TagWriteQueue, path: &Path, loudness: &Loudness) -> Result<(), LoudnessError> {
+    let gain = match loudness.replay_gain_db() {
+        Some(gain) => gain,
+        None => return Ok(()), // Nothing meaningful to write for silence
+    };
+    let edit = TagEdit::ReplayGain { gain_db: gain, peak: loudness.true_peak_linear() };
+    tags.enqueue(path, edit).wait().map_err(|e| LoudnessError::Tag(e.to_string()))
+}
+
+/// Options for a loudness scan.
+#[derive(Debug, Clone, Default)]
+pub struct ScanOptions {
+    /// Also write REPLAYGAIN_* tags into the files (modifies them).
+    pub write_tags: bool,
+    /// Directory holding the loudness cache.
+    pub state_dir: Option<PathBuf>,
+}
+
+/// Outcome of a loudness scan.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ScanSummary {
+    pub scanned: usize,
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+/// Measures every file in parallel, printing progress and updating the cache.
+///
+/// Files already measured at their current modification time are skipped, so
+/// an interrupted scan resumes where it left off.
+pub fn scan(paths: &[PathBuf], options: ScanOptions) -> ScanSummary {
+    let mut cache = LoudnessCache::load(options.state_dir.as_deref());
+    let tags = options.write_tags.then(TagWriteQueue::spawn);
+    let mut summary = ScanSummary::default();
+
+    let pending: Vec<(&PathBuf, u64)> = paths
+        .iter()
+        .filter_map(|path| match file_mtime(path) {
+            Some(mtime) => Some((path, mtime)),
+            None => {
+                println!("error: {}: could not read file metadata", path.display());
+                summary.failed += 1;
+                None
+            }
+        })
+        .filter(|(path, _)| {
+            let cached = cache.get(path).is_some();
+            if cached {
+                summary.skipped += 1;
+            }
+            !cached
+        })
+        .collect();
+
+    if summary.skipped > 0 {
+        println!("Skipping {} already scanned files", summary.skipped);
+    }
+
+    let total = pending.len();
+    let workers = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(total.max(1));
+    let next = AtomicUsize::new(0);
+    let (tx, rx) = mpsc::channel();
+
+    std::thread::scope(|s| {
+        for _ in 0..workers {
+            let tx = tx.clone();
+            let next = &next;
+            let pending = &pending;
+            let tags = &tags;
+            s.spawn(move || loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                let Some(&(path, mtime)) = pending.get(i) else {
+                    break;
+                };
+                let result = analyze_file(path).and_then(|loudness| {
+                    if let Some(tags) = tags {
+                        write_replay_gain_tags(tags, path, &loudness)?;
+                    }
+                    Ok(loudness)
+                });
+                // Writing tags changes the mtime, so record the new one
+                let mtime = if tags.is_some() {
+                    file_mtime(path).unwrap_or(mtime)
+                } else {
+                    mtime
+                };
+                if tx.send((path, mtime, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(tx);
+
+        for (done, (path, mtime, result)) in rx.iter().enumerate() {
+            let progress = format!("[{:>width$}/{}]", done + 1, total, width = total.to_string().len());
+            match result {
+                Ok(loudness) => {
+                    println!(
+                        "{} {}  {}  {}",
+                        progress,
+                        format_lufs(loudness.integrated_lufs),
+                        format_dbtp(loudness.true_peak_dbtp),
+                        path.display()
+                    );
+                    cache.insert(path, mtime, loudness);
+                    summary.scanned += 1;
+
+                    if summary.scanned % SAVE_INTERVAL == 0 {
+                        save_cache(&cache);
+                    }
+                }
+                Err(e) => {
+                    println!("{} error: {}: {}", progress, path.display(), e);
+                    summary.failed += 1;
+                }
+            }
+        }
+    });
+
+    save_cache(&cache);
+
+    summary
+}
+
+/// Saves the cache, warning on failure.
+fn save_cache(cache: &LoudnessCache) {
+    if let Err(e) = cache.save() {
+        eprintln!("Warning: Could not save loudness cache: {}", e);
+    }
+}
+
+/// Formats an integrated loudness value for display.
+fn format_lufs(lufs: Option<f64>) -> String {
+    match lufs {
+        Some(lufs) => format!("{:>6.1} LUFS", lufs),
+        None => "  -inf LUFS".to_string(),
+    }
+}
+
+/// Formats a true peak value for display.
+fn format_dbtp(dbtp: Option<f64>) -> String {
+    match dbtp {
+        Some(dbtp) => format!("{:>5.1} dBTP", dbtp),
+        None => " -inf dBTP".to_string(),
+    }
+}
+
+/// Errors that can occur during loudness analysis.
+#[derive(Debug)]
+pub enum LoudnessError {
+    Io(String),
+    Decode(String),
+    Tag(String),
+}
+
+impl std::fmt::Display for LoudnessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoudnessError::Io(msg) => write!(f, "IO error: {}", msg),
+            LoudnessError::Decode(msg) => write!(f, "Decode error: {}", msg),
+            LoudnessError::Tag(msg) => write!(f, "Tag error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for LoudnessError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds a stereo sine wave into a fresh analyzer.
+    fn analyze_sine(freq: f64, amplitude: f64, sample_rate: u32, seconds: f64) -> Loudness {
+        let mut analyzer = LoudnessAnalyzer::new(2, sample_rate);
+        let samples = (sample_rate as f64 * seconds) as usize;
+        for n in 0..samples {
+            let t = n as f64 / sample_rate as f64;
+            let s = (amplitude * (2.0 * std::f64::consts::PI * freq * t).sin()) as f32;
+            analyzer.push(s);
+            analyzer.push(s);
+        }
+        analyzer.finish()
+    }
+
+    #[test]
+    fn test_sine_integrated_loudness() {
+        // A stereo 997 Hz sine at -23 dBFS peak measures -23 LUFS
+        let amplitude = 10f64.powf(-23.0 / 20.0);
+        let loudness = analyze_sine(997.0, amplitude, 48_000, 5.0);
+        let lufs = loudness.integrated_lufs.unwrap();
+        assert!((lufs + 23.0).abs() < 0.2, "got {} LUFS", lufs);
+    }
+
+    #[test]
+    fn test_loudness_independent_of_sample_rate() {
+        let a = analyze_sine(997.0, 0.5, 44_100, 3.0).integrated_lufs.unwrap();
+        let b = analyze_sine(997.0, 0.5, 48_000, 3.0).integrated_lufs.unwrap();
+        assert!((a - b).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_true_peak() {
+        let loudness = analyze_sine(997.0, 0.5, 48_000, 1.0);
+        let peak = loudness.true_peak_dbtp.unwrap();
+        assert!((peak + 6.02).abs() < 0.2, "got {} dBTP", peak);
+    }
+
+    #[test]
+    fn test_silence_is_gated() {
+        let loudness = analyze_sine(997.0, 0.0, 48_000, 1.0);
+        assert!(loudness.integrated_lufs.is_none());
+        assert!(loudness.true_peak_dbtp.is_none());
+        assert!(loudness.replay_gain_db().is_none());
+    }
+
+    #[test]
+    fn test_replay_gain_reference() {
+        let loudness = Loudness {
+            integrated_lufs: Some(-10.0),
+            true_peak_dbtp: Some(0.0),
+        };
+        assert_eq!(loudness.replay_gain_db(), Some(-8.0));
+        assert!((loudness.true_peak_linear() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_cache_serialization() {
+        let mut cache = LoudnessCache::default();
+        cache.tracks.insert(
+            "/music/song.mp3".to_string(),
+            LoudnessEntry {
+                mtime: 42,
+                loudness: Loudness { integrated_lufs: Some(-14.0), true_peak_dbtp: None },
+            },
+        );
+
+        let toml_str = toml::to_string_pretty(&cache).unwrap();
+        let parsed: LoudnessCache = toml::from_str(&toml_str).unwrap();
+        let entry = parsed.tracks["/music/song.mp3"];
+        assert_eq!(entry.mtime, 42);
+        assert_eq!(entry.loudness.integrated_lufs, Some(-14.0));
+        assert_eq!(entry.loudness.true_peak_dbtp, None);
+    }
+}
@@ -1,46 +1,250 @@
+mod actions;
+mod announce;
 mod app;
-mod config;
+mod browser;
+mod buildinfo;
+mod check;
+mod columns;
+mod dates;
+mod dirty;
+mod doctor;
+mod focus;
+mod history;
 mod input;
-mod player;
-mod playlist;
+mod jump;
+mod library;
+mod listenlog;
+mod listfilter;
+mod listview;
+mod lock;
+mod loudness;
+mod lookup;
+mod marks;
+mod mounts;
+mod mpd;
+mod notify;
+mod organize;
+mod pattern;
+mod plain;
+mod preferences;
+mod profile;
+mod prompthistory;
+mod remote;
+mod session;
+mod state;
+mod statecheck;
+mod settings;
+mod setup;
+mod stats;
+mod strings;
+mod suspend;
+mod tagcache;
+mod tagwrite;
+mod terminal;
+mod themes;
+mod trash;
 mod ui;
+mod visualizer;
 
-use crossterm::{
-    execute,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
-};
+use juke::{collation, config, discs, filter, frames, gapless, player, playlist, task, template, trim};
 use std::env;
-use std::io;
+use std::io::{self, IsTerminal, Write};
 use std::path::Path;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Load configuration
-    let config = config::Config::load();
+    let mut profile = profile::Profile::start();
 
-    // Parse command-line arguments
-    let args: Vec<String> = env::args().collect();
+    // Parse command-line arguments, resolving overrides against the config file
+    let mut args: Vec<String> = env::args().collect();
+    if matches!(args.get(1).map(String::as_str), Some("--help" | "-h")) {
+        print_help();
+        return Ok(());
+    }
+    if matches!(args.get(1).map(String::as_str), Some("--version" | "-V")) {
+        println!("{}", buildinfo::summary());
+        return Ok(());
+    }
+
+    // Needs nothing that could fail on a broken setup
+    if args.get(1).map(String::as_str) == Some("--reset-terminal") {
+        terminal::reset()?;
+        println!();
+        return Ok(());
+    }
+
+    let overrides = settings::CliOverrides::extract(&mut args)?;
+    let fresh = overrides.fresh || overrides.recent.is_some();
+    let (recent, shuffle, profile_startup) = (overrides.recent, overrides.shuffle, overrides.profile_startup);
+    let viz_demo = overrides.viz_demo;
+
+    // Only a plain launch at a terminal asks; commands and scripts get the defaults
+    let launching = !args.get(1).is_some_and(|arg| arg.starts_with('-'));
+    if launching
+        && !overrides.no_setup
+        && !profile_startup
+        && io::stdin().is_terminal()
+        && io::stdout().is_terminal()
+        && let Some(path) = overrides.config_path()
+        && !path.exists()
+        && state::writable(&path).is_ok()
+        && let Err(e) = setup::run(&path)
+    {
+        eprintln!("Warning: Setup did not finish ({}), using the defaults", e);
+    }
+    let settings = profile.time("config", || settings::Settings::resolve(overrides));
+
+    if args.get(1).map(String::as_str) == Some("--print-config") {
+        print!("{}", settings.describe());
+        return Ok(());
+    }
+
+    if args.get(1).map(String::as_str) == Some("--send") {
+        return run_send(&args[2..]);
+    }
+
+    if args.get(1).map(String::as_str) == Some("--status") {
+        let response = send_command(&remote::Command::Status)?;
+        println!("{}", response.get("status").unwrap_or(&serde_json::Value::Null));
+        return Ok(());
+    }
+
+    if args.get(1).map(String::as_str) == Some("--list-devices") {
+        for name in player::output_device_names()? {
+            println!("{}", name);
+        }
+        return Ok(());
+    }
+
+    if args.get(1).map(String::as_str) == Some("--list-themes") {
+        print_themes();
+        return Ok(());
+    }
+
+    // A saved volume must not beat one given on the command line or in the environment
+    let restore_volume = matches!(
+        settings.source("playback.volume"),
+        Some(settings::Source::Default | settings::Source::ConfigFile)
+    );
+    if args.get(1).map(String::as_str) == Some("--doctor") {
+        return run_doctor(&args[2..], &settings);
+    }
+
+    let config = settings.config.clone();
+
+    if args.get(1).map(String::as_str) == Some("--check") {
+        return run_check(&args[2..], &config);
+    }
+
+    if args.get(1).map(String::as_str) == Some("--tag-lookup") {
+        return run_tag_lookup(&args[2..], &config);
+    }
+
+    if args.get(1).map(String::as_str) == Some("--scan-loudness") {
+        return run_loudness_scan(&args[2..], &config);
+    }
+
+    if args.get(1).map(String::as_str) == Some("--organize") {
+        return run_organize(&args[2..], &config);
+    }
+
+    if args.get(1).map(String::as_str) == Some("--export-history") {
+        return run_export_history(&args[2..], &config);
+    }
+
+    if args.get(1).map(String::as_str) == Some("--rotate-listen-log") {
+        return run_rotate_listen_log(&config);
+    }
+
+    // Places that cannot be written to are dropped up front, rather than
+    // failing every save
+    let state = state::StateStore::open(config.paths.state_dir.clone(), settings.config_path.clone());
+    // Only the instance holding the lock moves broken files aside
+    let state_report = state.dir().map(|dir| statecheck::check(dir, true));
+
+    let mut theme = themes::Theme::from_config(&config).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+    let config_path = settings.config_path.clone();
+    let config_dir = config_path.as_deref().and_then(std::path::Path::parent);
+    let strings = strings::Strings::load(config.ui.language.as_deref(), config_dir).unwrap_or_else(|e| {
+        eprintln!("Warning: {}, using English", e);
+        strings::Strings::default()
+    });
+
+    let session_path = config
+        .resume
+        .state_path
+        .clone()
+        .or_else(|| state.dir().map(session::Session::default_path));
+    let saved_session = if config.resume.enabled && !fresh {
+        session_path.as_deref().and_then(session::Session::load)
+    } else {
+        None
+    };
+    let saved_source = saved_session
+        .as_ref()
+        .and_then(|s| s.source.as_ref())
+        .filter(|source| source.exists());
 
-    let playlist = if args.len() > 1 {
-        let path = &args[1];
-        load_playlist(path)?
+    let loading = std::time::Instant::now();
+    let mut playlist = if args.len() > 1 {
+        let paths: Vec<&str> = args[1..].iter().map(String::as_str).collect();
+        load_playlist(&paths, &config)?
+    } else if let Some(source) = saved_source {
+        load_playlist(&[&source.to_string_lossy()], &config)?
+    } else if let Some(dir) = &config.paths.music_dir {
+        load_playlist(&[&dir.to_string_lossy()], &config)?
     } else {
         // Default to current directory
-        load_playlist(".")?
+        load_playlist(&["."], &config)?
     };
+    profile_playlist(&mut profile, &playlist, loading.elapsed());
+
+    // Remember when each file was first seen, for --recent
+    let library_loading = std::time::Instant::now();
+    let mut library = library::Library::load(state.dir());
+    let now = library::unix_now();
+    if library.record(playlist.tracks(), now)
+        && let Err(e) = library.save()
+        && state.dir().is_some()
+    {
+        eprintln!("Warning: Could not save the library index: {}", e);
+    }
+    profile.add("library", library_loading.elapsed());
+    if let Some(days) = recent {
+        let tracks = library.recent(playlist.tracks().to_vec(), days, now);
+        playlist = playlist::Playlist::from_tracks(tracks)
+            .map_err(|_| format!("No files were added in the last {} days", days))?;
+    }
 
     if playlist.is_empty() {
-        eprintln!("Error: No audio files found");
-        eprintln!("Usage: {} [directory or playlist.m3u]", args.get(0).unwrap_or(&"juke".to_string()));
+        eprintln!("Error: {}", strings.text("error.no_audio_files"));
+        eprintln!("{}", strings.text_with("error.usage", &[("program", args.get(0).unwrap_or(&"juke".to_string()))]));
         std::process::exit(1);
     }
 
-    // Setup terminal
-    enable_raw_mode()?;
-    execute!(io::stdout(), EnterAlternateScreen)?;
+    // Setup terminal; it is restored when the guard goes, however main ends
+    let terminal = terminal::TerminalGuard::enter(config.ui.mode == config::InterfaceMode::Full)?;
+
+    // Pick the default colors for the terminal's background while nothing
+    // else reads input, so the answer is not taken for key presses
+    if config.theme.background == config::Background::Auto {
+        let background = terminal::query_background().or_else(|| {
+            std::env::var("COLORFGBG")
+                .ok()
+                .and_then(|value| themes::background_from_colorfgbg(&value))
+        });
+        if let Some(background) = background {
+            themes::set_detected_background(background);
+            theme = themes::Theme::from_config(&config).unwrap_or(theme);
+        }
+    }
 
-    // Setup signal handler for graceful shutdown
+    // Setup signal handler for graceful shutdown: the main loop ends and the
+    // terminal guard restores the terminal
     let running = Arc::new(AtomicBool::new(true));
     let r = running.clone();
 
@@ -48,39 +252,111 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         r.store(false, Ordering::SeqCst);
     }).expect("Error setting Ctrl-C handler");
 
-    // Setup panic hook to restore terminal state
+    // Setup panic hook to restore terminal state and log a crash report
+    let snapshot = doctor::Snapshot::new(&settings, doctor::AudioInfo::detect());
     let original_hook = std::panic::take_hook();
     std::panic::set_hook(Box::new(move |panic_info| {
-        let _ = cleanup_terminal();
+        if let Err(e) = terminal::restore() {
+            eprintln!("Could not restore the terminal ({}), run juke --reset-terminal", e);
+        }
         original_hook(panic_info);
+        if let Ok(path) = doctor::append_crash_report(&settings, &snapshot, &panic_info.to_string()) {
+            eprintln!("Crash report written to {}", path.display());
+        }
     }));
 
     // Create and start the app (ensure cleanup on error)
+    // A recently added playlist is not where to pick up next time
+    let save_session = config.resume.enabled && recent.is_none() && state.locked_by().is_none();
+    let mut session_warning = None;
+    let mut edits_lost = false;
+    let mut startup_summary = None;
     let result = (|| -> Result<(), Box<dyn std::error::Error>> {
-        let mut app = app::App::new(playlist, config)?;
+        let state_locked = state.locked_by();
+        let read_only = state.unwritable_message(&strings);
+        let mut app = app::App::new(playlist, config, state, theme, strings.clone())?;
+        profile.add("audio", app.audio_init_time());
+        app.set_startup_profile(profile);
+        if let Some(pid) = state_locked {
+            app.warn(match pid {
+                Some(pid) => strings.text_with("notice.state_locked", &[("pid", &pid)]),
+                None => strings.text("notice.state_locked_unknown").to_string(),
+            });
+        }
+        if let Some(message) = read_only {
+            app.warn(message);
+        }
+        if let Some(report) = &state_report {
+            if report.low_space() {
+                app.pause_history();
+            }
+            if !report.is_healthy() {
+                app.warn(strings.text("notice.state_problems").to_string());
+            }
+            for line in report.lines(&strings, |path| path.display().to_string()) {
+                app.log(line);
+            }
+        }
+        if save_session && let Some(path) = &session_path {
+            app.set_session_path(path.clone());
+        }
+        // A resumed session, then the command line, beat the playlist's own preferences
+        app.apply_playlist_settings(!restore_volume);
+        if let Some(saved) = &saved_session {
+            app.restore_session(saved, restore_volume);
+        }
+        if shuffle {
+            app.shuffle_on();
+        }
+        if viz_demo {
+            app.use_test_visualizer();
+        }
         app.start()?;
 
+        if profile_startup {
+            startup_summary = app.startup_summary();
+            app.stop_playback();
+            return Ok(());
+        }
+
         // Main loop
         run_main_loop(&mut app, running)?;
 
+        // Remember where we were before stopping
+        if save_session
+            && let Some(path) = &session_path
+            && let Err(e) = app.session().save(path)
+        {
+            session_warning = Some(strings.text_with("error.save_session", &[("error", &e)]));
+        }
+
+        // Quitting on a signal or remotely skips the question about edits
+        edits_lost = app.edits_lost();
+
         // Stop audio playback
         app.stop_playback();
 
         Ok(())
     })();
 
-    // Cleanup - restore terminal state (always runs)
-    cleanup_terminal()?;
+    // Restore the terminal before printing anything else
+    if let Err(e) = terminal.leave() {
+        eprintln!("Could not restore the terminal ({}), run juke --reset-terminal", e);
+    }
+    if let Some(summary) = startup_summary {
+        println!("{}", summary);
+        return result;
+    }
+    println!("{}", strings.text("goodbye"));
 
-    result
-}
+    if let Some(warning) = session_warning {
+        eprintln!("{}", warning);
+    }
+    if edits_lost {
+        eprintln!("{}", strings.text("error.edits_lost"));
+    }
 
-/// Cleans up terminal state before exit.
-fn cleanup_terminal() -> Result<(), Box<dyn std::error::Error>> {
-    disable_raw_mode()?;
-    execute!(io::stdout(), LeaveAlternateScreen)?;
-    println!("Thanks for using juke!");
-    Ok(())
+    result
 }
 
 /// Main application loop.
@@ -89,29 +365,438 @@ fn run_main_loop(
     running: Arc<AtomicBool>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     while app.is_running() && running.load(Ordering::SeqCst) {
-        // Handle input
-        input::handle_input(app)?;
+        // Handle input, waiting until the next redraw is due
+        input::handle_input(app, app.poll_timeout())?;
 
         // Update app state (check for track end, update display)
         app.update()?;
+    }
+    Ok(())
+}
 
-        // Small sleep to avoid busy loop
-        std::thread::sleep(std::time::Duration::from_millis(50));
+/// Records the time taken to load the playlist, split into walking
+/// directories, reading tags, and sorting when it was a directory scan.
+/// Every phase is recorded, if only as zero, so the summary keeps its shape.
+fn profile_playlist(profile: &mut profile::Profile, playlist: &playlist::Playlist, took: std::time::Duration) {
+    let (walk, tags, sort) = playlist
+        .scan_report()
+        .map_or(Default::default(), |report| (report.walk_time, report.tags_time, report.sort_time));
+    profile.add("walk", walk);
+    profile.add("tags", tags);
+    profile.add("sort", sort);
+    profile.add("playlist", took.saturating_sub(walk + tags + sort));
+}
+
+/// Runs `--check [--deep] [--json] <playlist>` and exits, with 1 if problems were found.
+fn run_check(args: &[String], config: &config::Config) -> Result<(), Box<dyn std::error::Error>> {
+    let mut path = None;
+    let mut deep = false;
+    let mut json = false;
+    for arg in args {
+        match arg.as_str() {
+            "--deep" => deep = true,
+            "--json" => json = true,
+            other => path = Some(other),
+        }
+    }
+    let path = path.ok_or("Usage: juke --check [--deep] [--json] <directory or playlist.m3u>")?;
+
+    let playlist = load_probed_playlist(path, config)?;
+    let report = check::check(playlist.tracks(), deep);
+    if json {
+        println!("{}", report.to_json());
+    } else {
+        report.write_text(&mut io::stdout().lock())?;
+    }
+
+    if !report.problems.is_empty() {
+        std::process::exit(1);
     }
     Ok(())
 }
 
-/// Loads a playlist from a path (directory or M3U file).
-fn load_playlist(path: &str) -> Result<playlist::Playlist, Box<dyn std::error::Error>> {
-    let path = Path::new(path);
+/// Runs `--tag-lookup [--write] <path>` and exits.
+///
+/// Prints what MusicBrainz knows about tracks with missing tags, and with
+/// `--write`, asks before saving it into the files.
+fn run_tag_lookup(args: &[String], config: &config::Config) -> Result<(), Box<dyn std::error::Error>> {
+    let mut path = None;
+    let mut write = false;
+    for arg in args {
+        match arg.as_str() {
+            "--write" => write = true,
+            other => path = Some(other),
+        }
+    }
+    let path = path.ok_or("Usage: juke --tag-lookup [--write] <directory or playlist.m3u>")?;
+    if !lookup::AVAILABLE {
+        return Err(lookup::LookupError::Disabled.into());
+    }
+
+    let playlist = load_probed_playlist(path, config)?;
+    let tracks: Vec<_> = playlist.tracks().iter().filter(|t| lookup::needs_lookup(t)).collect();
+    println!("Looking up {} of {} tracks (about one per second)...", tracks.len(), playlist.len());
+
+    let mut lookup = lookup::Lookup::new(&config.lookup, config.paths.state_dir.as_deref());
+    let mut found = Vec::new();
+    for track in tracks {
+        match lookup.lookup(track) {
+            Ok(Some(proposal)) => {
+                println!(
+                    "{}\n  -> {} – {} ({}) [{}%]",
+                    track.path.display(),
+                    proposal.artist.as_deref().unwrap_or("?"),
+                    proposal.title,
+                    proposal.album.as_deref().unwrap_or("?"),
+                    proposal.score
+                );
+                found.push((track.path.clone(), proposal));
+            }
+            Ok(None) => println!("{}\n  -> no match", track.path.display()),
+            Err(e @ lookup::LookupError::Offline(_)) => {
+                lookup.save()?;
+                return Err(e.into());
+            }
+            Err(e) => eprintln!("{}: {}", track.path.display(), e),
+        }
+    }
+    lookup.save()?;
+
+    if !write || found.is_empty() {
+        println!("Found tags for {} tracks", found.len());
+        return Ok(());
+    }
+
+    print!("Write missing tags to {} files? [y/N] ", found.len());
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    if !matches!(answer.trim(), "y" | "Y" | "yes") {
+        println!("No files changed");
+        return Ok(());
+    }
 
-    if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("m3u") {
+    let tags = tagwrite::TagWriteQueue::spawn();
+    let mut failed = 0;
+    for (path, proposal) in &found {
+        if let Err(e) = tags.enqueue(path, proposal.tag_edit()).wait() {
+            eprintln!("{}: {}", path.display(), e);
+            failed += 1;
+        }
+    }
+    println!("Done: {} written, {} failed", found.len() - failed, failed);
+    Ok(())
+}
+
+/// Runs `--export-history <file.csv|file.json> [--since <date>] [--until <date>]` and exits.
+fn run_export_history(args: &[String], config: &config::Config) -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = None;
+    let mut since = None;
+    let mut until = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--since" => since = Some(args.next().ok_or("--since needs a date")?.as_str()),
+            "--until" => until = Some(args.next().ok_or("--until needs a date")?.as_str()),
+            other => file = Some(Path::new(other)),
+        }
+    }
+    let file = file.ok_or("Usage: juke --export-history <file.csv|file.json> [--since YYYY-MM-DD] [--until YYYY-MM-DD]")?;
+    let format = history::Format::from_path(file)?;
+    let state_dir = config.paths.state_dir.as_deref().ok_or("No state directory, so there is no history")?;
+
+    let (entries, warnings) = history::load(state_dir)?;
+    for warning in warnings {
+        eprintln!("Warning: {}", warning);
+    }
+    let entries = history::filter(entries, since, until)?;
+    std::fs::write(file, format.write(&entries))?;
+    let noun = if entries.len() == 1 { "play" } else { "plays" };
+    println!("Exported {} {} to {}", entries.len(), noun, file.display());
+    Ok(())
+}
+
+/// Runs `--rotate-listen-log` and exits.
+fn run_rotate_listen_log(config: &config::Config) -> Result<(), Box<dyn std::error::Error>> {
+    let path = config.integrations.listen_log.as_deref().ok_or("No listen log: set integrations.listen_log")?;
+    let path = setup::expand_home(&path.to_string_lossy());
+    match listenlog::rotate(&path, library::unix_now())? {
+        Some(archive) => println!("Moved {} to {}", path.display(), archive.display()),
+        None => println!("{} does not exist yet; nothing to rotate", path.display()),
+    }
+    Ok(())
+}
+
+/// Runs `--scan-loudness [--write-tags] [path]` and exits.
+fn run_loudness_scan(args: &[String], config: &config::Config) -> Result<(), Box<dyn std::error::Error>> {
+    let mut options = loudness::ScanOptions {
+        state_dir: config.paths.state_dir.clone(),
+        ..Default::default()
+    };
+    let mut path = ".";
+
+    for arg in args {
+        match arg.as_str() {
+            "--write-tags" => options.write_tags = true,
+            other => path = other,
+        }
+    }
+
+    let playlist = load_probed_playlist(path, config)?;
+    let paths: Vec<_> = playlist.tracks().iter().map(|t| t.path.clone()).collect();
+
+    println!("Scanning loudness of {} files...", paths.len());
+    let summary = loudness::scan(&paths, options);
+    println!(
+        "Done: {} scanned, {} skipped, {} failed",
+        summary.scanned, summary.skipped, summary.failed
+    );
+
+    Ok(())
+}
+
+/// Runs `--organize <dir> [--pattern <pattern>] [--target <root>] [--dry-run]` and exits.
+fn run_organize(args: &[String], config: &config::Config) -> Result<(), Box<dyn std::error::Error>> {
+    let mut source = None;
+    let mut pattern = organize::DEFAULT_PATTERN.to_string();
+    let mut target = None;
+    let mut dry_run = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--pattern" => pattern = args.next().ok_or("--pattern needs a value")?.clone(),
+            "--target" => target = Some(args.next().ok_or("--target needs a value")?.clone()),
+            "--dry-run" => dry_run = true,
+            other => source = Some(other.to_string()),
+        }
+    }
+
+    let source = source.ok_or("Usage: juke --organize <dir> [--pattern <pattern>] [--target <root>] [--dry-run]")?;
+    let pattern = pattern::Pattern::parse(&pattern)?;
+    let target = std::fs::canonicalize(target.as_deref().unwrap_or(&source))?;
+
+    // Tags only: guesses from the folder layout are never used to rename
+    // files, and every file's tags are read
+    let options = playlist::LoadOptions {
+        infer_tags_from_path: false,
+        fast_scan: false,
+        ..load_options(config)
+    };
+    let playlist = playlist::Playlist::from_directory(&source, &options)?;
+    let scan_errors = playlist.scan_report().map_or(0, |r| r.errors.len());
+    let (moves, skipped) = organize::plan(playlist.tracks(), &pattern, &target);
+
+    let mut summary = if dry_run {
+        for m in &moves {
+            println!("{} -> {}", m.from.display(), m.to.display());
+        }
+        organize::OrganizeSummary { renamed: moves.len(), ..Default::default() }
+    } else {
+        let mut cache = loudness::LoudnessCache::load(config.paths.state_dir.as_deref());
+        let summary = organize::execute(&moves, &mut cache);
+        if summary.renamed > 0 {
+            cache.save()?;
+        }
+        summary
+    };
+    summary.skipped = skipped;
+    summary.errors += scan_errors;
+
+    println!(
+        "{}: {} renamed, {} skipped, {} errors",
+        if dry_run { "Dry run" } else { "Done" },
+        summary.renamed,
+        summary.skipped,
+        summary.errors
+    );
+
+    Ok(())
+}
+
+/// Prints a diagnostic report, scanning the given path if any.
+fn run_doctor(args: &[String], settings: &settings::Settings) -> Result<(), Box<dyn std::error::Error>> {
+    let mut path = None;
+    let mut anonymize = false;
+    for arg in args {
+        match arg.as_str() {
+            "--anonymize" => anonymize = true,
+            other => path = Some(Path::new(other)),
+        }
+    }
+
+    let paths = doctor::PathStyle { anonymize };
+    let mut out = io::stdout().lock();
+    doctor::write_environment(&mut out, settings, &doctor::AudioInfo::detect(), paths)?;
+    doctor::write_state(&mut out, settings.config.paths.state_dir.as_deref(), paths)?;
+    if let Some(path) = path {
+        doctor::write_scan(&mut out, path, &load_options(&settings.config), paths)?;
+    }
+    Ok(())
+}
+
+/// Sends a command to a running instance.
+fn run_send(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(name) = args.first() else {
+        return Err(format!("Usage: juke --send <command> [value]\nCommands: {}", remote::Command::USAGE).into());
+    };
+    let command = remote::Command::parse(name, args.get(1).map(String::as_str))?;
+    let response = send_command(&command)?;
+    if let Some(result) = response.get("status").or_else(|| response.get("playlist")) {
+        println!("{}", result);
+    }
+    Ok(())
+}
+
+/// Sends a command to a running instance and returns the successful response.
+///
+/// Exits with 2 if no instance is running and 1 if the command failed.
+fn send_command(command: &remote::Command) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    let mut connection = match remote::connect() {
+        Ok(connection) => connection,
+        Err(e) if remote::is_not_running(&e) => {
+            eprintln!("Error: No running juke instance");
+            std::process::exit(2);
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let response = remote::request(connection.as_mut(), &command.to_json())?;
+    if response.get("ok").and_then(serde_json::Value::as_bool) != Some(true) {
+        let message = response.get("error").and_then(serde_json::Value::as_str).unwrap_or("unknown error");
+        eprintln!("Error: {}", message);
+        std::process::exit(1);
+    }
+    Ok(response)
+}
+
+/// Prints command-line usage.
+fn print_help() {
+    println!(
+        "\
+Usage: juke [options] [directory, playlist.m3u, or - for paths on stdin]...
+
+Commands:
+  --send <command> [value]  Control a running instance ({commands})
+  --status                  Print what a running instance is playing as JSON
+  --check <path> [--deep] [--json]
+                            Report missing, unreadable, and duplicate entries without playing
+  --scan-loudness <path>    Measure loudness for ReplayGain
+  --tag-lookup <path> [--write]
+                            Look up missing tags on MusicBrainz, asking before writing them
+  --organize <dir>          Rename files from their tags
+  --export-history <file.csv|file.json> [--since <date>] [--until <date>]
+                            Write the play history, optionally between YYYY-MM-DD dates
+  --rotate-listen-log       Move the listen log aside, named after the day, to start a new one
+  --list-devices            List audio output devices
+  --list-themes             Preview the built-in themes
+  --print-config            Show the resolved configuration
+  --doctor [path] [--anonymize]
+                            Print diagnostics for bug reports, scanning path if given
+  --reset-terminal          Restore a terminal left in raw mode or the alternate screen
+  -h, --help                Show this help
+  -V, --version             Print the version, commit, target, and features built in
+
+Options:
+  --config <path>           Config file location
+  --state-dir <path>        Directory for caches and saved state
+  --volume <0-100>          Starting volume
+  --theme <name>            Color theme
+  --no-color                Disable colors
+  --fresh                   Do not restore the saved session
+  --plain                   Print one line per change instead of drawing the screen
+  --recent <days>           Play only files added in the last <days> days, newest first
+  --shuffle                 Start with shuffle on
+  --profile-startup         Print how long each part of startup took, then exit
+  --no-setup                Skip the first-run questions, writing the default config
+  --max-depth <n>           Folder levels to scan (0 = unlimited)
+  --[no-]follow-symlinks    Follow symbolic links while scanning
+  --[no-]hidden             Scan hidden files and folders
+
+--send and --status exit with 2 when no instance is running.
+
+--status output:
+{schema}",
+        commands = remote::Command::USAGE,
+        schema = remote::STATUS_SCHEMA
+    );
+}
+
+/// Prints the built-in themes with a color swatch for each.
+fn print_themes() {
+    use crossterm::style::Stylize;
+
+    for (name, theme) in themes::PRESETS {
+        let mut line = format!("{:<18}", name);
+        for color in theme.swatch() {
+            line.push_str(&format!("{} ", "██".with(color.into())));
+        }
+        line.push_str(&format!(
+            " {}",
+            " Selected ".with(theme.selection_fg.into()).on(theme.selection_bg.into())
+        ));
+        println!("{}", line);
+    }
+}
+
+/// Builds playlist loading options from the config.
+fn load_options(config: &config::Config) -> playlist::LoadOptions {
+    playlist::LoadOptions {
+        collation: collation::Collation::new(&config.sort.articles),
+        infer_tags_from_path: config.playlist.infer_tags_from_path,
+        filter: filter::ScanFilter::new(&config.playlist.extensions, &config.playlist.exclude_patterns),
+        scan: playlist::ScanOptions {
+            max_depth: config.playlist.max_depth,
+            follow_symlinks: config.playlist.follow_symlinks,
+            include_hidden: config.playlist.include_hidden,
+        },
+        fast_scan: config.playlist.fast_scan.unwrap_or(false),
+    }
+}
+
+/// Loads a playlist from paths (directories, M3U files, or `-` for track
+/// paths on standard input) for the player, one after the other. The
+/// player reads the tags a fast scan leaves out later.
+fn load_playlist(paths: &[&str], config: &config::Config) -> Result<playlist::Playlist, Box<dyn std::error::Error>> {
+    let options = playlist::LoadOptions {
+        fast_scan: config
+            .playlist
+            .fast_scan
+            .unwrap_or_else(|| paths.iter().any(|path| mounts::is_network(Path::new(path)))),
+        ..load_options(config)
+    };
+    let mut loaded = paths.iter().map(|&path| match path {
+        // Keys are read from the terminal itself, so stdin is free for paths
+        "-" => Ok(playlist::Playlist::from_reader(io::stdin().lock(), &options)?),
+        path => open_playlist(path, &options),
+    });
+    let mut playlist = loaded.next().ok_or("No directory or playlist given")??;
+    for other in loaded {
+        playlist.append(other?);
+    }
+    Ok(playlist)
+}
+
+/// Loads a playlist for a command that goes by every track's tags and
+/// length, reading them all whatever the player's scan settings.
+fn load_probed_playlist(path: &str, config: &config::Config) -> Result<playlist::Playlist, Box<dyn std::error::Error>> {
+    open_playlist(path, &playlist::LoadOptions { fast_scan: false, ..load_options(config) })
+}
+
+/// Loads a playlist from a directory or M3U file.
+fn open_playlist(
+    path: &str,
+    options: &playlist::LoadOptions,
+) -> Result<playlist::Playlist, Box<dyn std::error::Error>> {
+    let path = Path::new(path);
+    if path.is_file() && playlist::is_playlist_file(path) {
         // Load M3U file
-        Ok(playlist::Playlist::from_m3u(path)?)
+        Ok(playlist::Playlist::from_m3u(path, options)?)
     } else if path.is_dir() {
         // Scan directory
-        Ok(playlist::Playlist::from_directory(path)?)
+        Ok(playlist::Playlist::from_directory(path, options)?)
     } else {
-        Err("Path must be a directory or .m3u file".into())
+        Err("Path must be a directory or .m3u/.m3u8 file".into())
     }
 }
@@ -0,0 +1,137 @@
+//! Track list marks, like vim's: `m` and a letter marks the selected track,
+//! `'` and the letter jumps back to it.
+//!
+//! Marks are kept per playlist source in `marks.toml` in the state
+//! directory. They name the track's file rather than its position, so they
+//! survive sorting, filtering, and rescans.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct MarksFile {
+    /// Marks of each playlist source, keyed by the source's path and then
+    /// by letter.
+    #[serde(default)]
+    sources: BTreeMap<String, BTreeMap<String, PathBuf>>,
+}
+
+/// The marks of the playlist being played.
+#[derive(Debug, Default)]
+pub struct Marks {
+    file: MarksFile,
+    /// Source the marks belong to; empty when the playlist has none.
+    source: String,
+    /// File the marks were loaded from and are saved to.
+    path: Option<PathBuf>,
+}
+
+/// Returns true if `letter` can name a mark.
+pub fn is_mark_letter(letter: char) -> bool {
+    letter.is_ascii_alphabetic()
+}
+
+impl Marks {
+    /// Loads the marks of a playlist source from a state directory. Missing
+    /// or unreadable files give no marks. Without a source, marks last only
+    /// until juke exits.
+    pub fn load(state_dir: Option<&Path>, source: Option<&Path>) -> Self {
+        let path = state_dir.filter(|_| source.is_some()).map(|dir| dir.join("marks.toml"));
+        let file = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default();
+        Self {
+            file,
+            source: source.map(|source| source.to_string_lossy().into_owned()).unwrap_or_default(),
+            path,
+        }
+    }
+
+    /// Saves the marks of every source to the file they were loaded from.
+    pub fn save(&self) -> std::io::Result<()> {
+        let path = self.path.as_ref().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "no state directory")
+        })?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let toml_string = toml::to_string_pretty(&self.file).map_err(std::io::Error::other)?;
+        fs::write(path, toml_string)
+    }
+
+    fn current(&self) -> Option<&BTreeMap<String, PathBuf>> {
+        self.file.sources.get(&self.source)
+    }
+
+    /// Marks a track's file with a letter, replacing the letter's old mark.
+    pub fn set(&mut self, letter: char, track: &Path) {
+        self.file
+            .sources
+            .entry(self.source.clone())
+            .or_default()
+            .insert(letter.to_string(), track.to_path_buf());
+    }
+
+    /// Returns the file marked with a letter.
+    pub fn get(&self, letter: char) -> Option<&Path> {
+        self.current()?.get(&letter.to_string()).map(PathBuf::as_path)
+    }
+
+    /// Returns every mark, in letter order.
+    pub fn list(&self) -> Vec<(char, &Path)> {
+        self.current()
+            .into_iter()
+            .flatten()
+            .filter_map(|(letter, path)| Some((letter.chars().next()?, path.as_path())))
+            .collect()
+    }
+
+    /// Returns the letter each marked file is shown with; a file with several
+    /// marks shows the first.
+    pub fn by_path(&self) -> BTreeMap<&Path, char> {
+        let mut letters = BTreeMap::new();
+        for (letter, path) in self.list() {
+            letters.entry(path).or_insert(letter);
+        }
+        letters
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_marks_per_source() {
+        let dir = std::env::temp_dir().join(format!("juke_marks_{}", std::process::id()));
+        let rock = Path::new("/music/rock");
+
+        let mut marks = Marks::load(Some(&dir), Some(rock));
+        marks.set('a', Path::new("/music/rock/b.mp3"));
+        marks.set('Z', Path::new("/music/rock/a.mp3"));
+        marks.set('c', Path::new("/music/rock/a.mp3"));
+        marks.save().unwrap();
+
+        let reloaded = Marks::load(Some(&dir), Some(rock));
+        assert_eq!(reloaded.get('a'), Some(Path::new("/music/rock/b.mp3")));
+        assert_eq!(reloaded.get('b'), None);
+        let letters: Vec<char> = reloaded.list().iter().map(|(letter, _)| *letter).collect();
+        assert_eq!(letters, ['Z', 'a', 'c']);
+        assert_eq!(reloaded.by_path().get(Path::new("/music/rock/a.mp3")), Some(&'Z'));
+
+        // Another source has marks of its own, and saving keeps both
+        let mut jazz = Marks::load(Some(&dir), Some(Path::new("/music/jazz")));
+        assert!(jazz.list().is_empty());
+        jazz.set('a', Path::new("/music/jazz/x.flac"));
+        jazz.save().unwrap();
+        let reloaded = Marks::load(Some(&dir), Some(rock));
+        assert_eq!(reloaded.get('a'), Some(Path::new("/music/rock/b.mp3")));
+
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(is_mark_letter('q') && !is_mark_letter('1') && !is_mark_letter('é'));
+    }
+}
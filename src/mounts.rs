@@ -0,0 +1,148 @@
+//! Telling whether a directory is on a network file system, where every
+//! file opened costs round trips to the server.
+
+use std::path::{Path, PathBuf};
+
+/// Returns whether `path` is on a network file system such as SMB or NFS.
+/// False when it cannot be told.
+pub fn is_network(path: &Path) -> bool {
+    fs_type(path).is_some_and(|fs_type| is_network_type(&fs_type))
+}
+
+/// Whether a file system type, as [`fs_type`] names it, is a network one.
+fn is_network_type(fs_type: &FsType) -> bool {
+    match fs_type {
+        // Magic numbers from statfs(2)
+        FsType::Magic(magic) => matches!(
+            magic,
+            0x517B // smb
+                | 0xFF53_4D42 // cifs
+                | 0xFE53_4D42 // smb2
+                | 0x6969 // nfs
+                | 0x0102_1997 // 9p
+                | 0x6B41_4653 // afs
+        ),
+        FsType::Name(name) => matches!(
+            name.as_str(),
+            "smbfs"
+                | "nfs"
+                | "afpfs"
+                | "webdav"
+                | "osxfuse"
+                | "macfuse"
+                | "fuse.sshfs"
+                | "fuse.rclone"
+                | "fuse.s3fs"
+                | "fuse.gcsfuse"
+        ),
+    }
+}
+
+/// A file system type as the platform reports it.
+enum FsType {
+    /// The magic number Linux reports.
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    Magic(u64),
+    /// The name macOS reports, or that of a FUSE mount on Linux.
+    #[cfg_attr(not(any(target_os = "linux", target_os = "macos")), allow(dead_code))]
+    Name(String),
+}
+
+/// Magic number shared by every FUSE file system, local or not.
+#[cfg(target_os = "linux")]
+const FUSE_MAGIC: u64 = 0x6573_5546;
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn statfs(path: &Path) -> Option<libc::statfs> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    // SAFETY: statfs is plain data, for which all zeroes is valid
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    // SAFETY: path is NUL-terminated and stat is valid for writes
+    (unsafe { libc::statfs(path.as_ptr(), &mut stat) } == 0).then_some(stat)
+}
+
+#[cfg(target_os = "linux")]
+fn fs_type(path: &Path) -> Option<FsType> {
+    // The type's width and signedness vary between architectures
+    #[allow(clippy::unnecessary_cast)]
+    let magic = statfs(path)?.f_type as u64 & 0xFFFF_FFFF;
+    if magic != FUSE_MAGIC {
+        return Some(FsType::Magic(magic));
+    }
+    // The mount table names the FUSE file system, such as fuse.sshfs
+    let mountinfo = std::fs::read_to_string("/proc/self/mountinfo").ok()?;
+    mount_type(&mountinfo, &path.canonicalize().ok()?).map(FsType::Name)
+}
+
+/// Finds the type of the mount holding `path` in the text of
+/// /proc/self/mountinfo: that of the deepest mount point above it, the
+/// last mounted when several share it.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn mount_type(mountinfo: &str, path: &Path) -> Option<String> {
+    mountinfo
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(' ');
+            let mount_point = PathBuf::from(unescape(fields.nth(4)?));
+            // Optional fields end with a lone dash before the type
+            let fs_type = fields.skip_while(|&field| field != "-").nth(1)?;
+            Some((mount_point, fs_type))
+        })
+        .filter(|(mount_point, _)| path.starts_with(mount_point))
+        .max_by_key(|(mount_point, _)| mount_point.components().count())
+        .map(|(_, fs_type)| fs_type.to_string())
+}
+
+/// Undoes the octal escapes the mount table uses for whitespace and
+/// backslashes in mount points.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn unescape(field: &str) -> String {
+    field.replace("\\040", " ").replace("\\011", "\t").replace("\\012", "\n").replace("\\134", "\\")
+}
+
+#[cfg(target_os = "macos")]
+fn fs_type(path: &Path) -> Option<FsType> {
+    let stat = statfs(path)?;
+    // SAFETY: f_fstypename is a NUL-terminated C string
+    let name = unsafe { std::ffi::CStr::from_ptr(stat.f_fstypename.as_ptr()) };
+    Some(FsType::Name(name.to_string_lossy().into_owned()))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn fs_type(_path: &Path) -> Option<FsType> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_network_types() {
+        assert!(is_network_type(&FsType::Magic(0xFF53_4D42)));
+        assert!(is_network_type(&FsType::Magic(0x6969)));
+        assert!(!is_network_type(&FsType::Magic(0xEF53))); // ext4
+        assert!(is_network_type(&FsType::Name("smbfs".to_string())));
+        assert!(!is_network_type(&FsType::Name("apfs".to_string())));
+        assert!(is_network_type(&FsType::Name("fuse.sshfs".to_string())));
+        assert!(!is_network_type(&FsType::Name("fuse.gocryptfs".to_string())));
+    }
+
+    #[test]
+    fn test_mount_type() {
+        let mountinfo = "\
+22 1 259:2 / / rw,relatime shared:1 - ext4 /dev/nvme0n1p2 rw
+40 22 0:35 / /mnt/music rw,nosuid,nodev shared:20 - fuse.sshfs host:/music rw,user_id=1000
+41 22 0:36 / /mnt/music\\040box rw,nosuid,nodev - fuse.rclone box: rw
+42 40 0:37 / /mnt/music/local rw - fuse.gocryptfs /home/a/.music rw
+";
+        let fs_type = |path: &str| mount_type(mountinfo, Path::new(path));
+        assert_eq!(fs_type("/mnt/music/a/b.flac").as_deref(), Some("fuse.sshfs"));
+        assert_eq!(fs_type("/mnt/music box/b.flac").as_deref(), Some("fuse.rclone"));
+        assert_eq!(fs_type("/mnt/music/local/b.flac").as_deref(), Some("fuse.gocryptfs"));
+        assert_eq!(fs_type("/mnt/musicbox").as_deref(), Some("ext4"));
+        assert_eq!(fs_type("/home").as_deref(), Some("ext4"));
+        assert_eq!(mount_type("", Path::new("/home")), None);
+    }
+}
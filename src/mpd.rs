@@ -0,0 +1,1001 @@
+//! A subset of the MPD protocol, so MPD clients such as `mpc` can control juke.
+//!
+//! Requests are translated into the same [`Command`]s the remote control
+//! socket uses. Supported: `status`, `currentsong`, `play`, `pause`, `next`,
+//! `playid`, `previous`, `seekcur`, `setvol`, `playlistinfo`, `idle`/`noidle`
+//! for the player, mixer, options, and playlist subsystems, plus `ping`,
+//! `close`, `commands`, and command lists. Anything else gets an ACK error.
+//! Idle clients are woken by [`MpdServer::notify`] rather than by polling.
+
+use crate::remote::{self, Command, Pending, Response};
+use serde_json::Value;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Instant;
+
+/// Sent to every client on connect.
+const GREETING: &str = "OK MPD 0.23.0\n";
+
+/// Longest command line accepted.
+const MAX_LINE_LEN: usize = 64 * 1024;
+
+/// How far the position may drift from the clock before it counts as a seek.
+const SEEK_THRESHOLD_SECS: f64 = 2.0;
+
+/// Commands listed by `commands`.
+const COMMANDS: &[&str] = &[
+    "close",
+    "command_list_begin",
+    "command_list_end",
+    "command_list_ok_begin",
+    "commands",
+    "currentsong",
+    "idle",
+    "next",
+    "noidle",
+    "pause",
+    "ping",
+    "play",
+    "playid",
+    "playlistinfo",
+    "previous",
+    "seekcur",
+    "setvol",
+    "status",
+];
+
+/// MPD error codes used in ACK responses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AckCode {
+    NotList = 1,
+    Arg = 2,
+    Unknown = 5,
+    System = 52,
+}
+
+/// An error reply: `ACK [code@index] {command} message`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Ack {
+    code: AckCode,
+    command: String,
+    message: String,
+}
+
+impl Ack {
+    fn new(code: AckCode, command: &str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            command: command.to_string(),
+            message: message.into(),
+        }
+    }
+
+    /// Formats the reply line; `index` is the position in a command list.
+    fn line(&self, index: usize) -> String {
+        format!("ACK [{}@{}] {{{}}} {}\n", self.code as u8, index, self.command, self.message)
+    }
+}
+
+/// Events clients can wait for with `idle`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Subsystem {
+    /// Playback started, stopped, paused, seeked, or changed track.
+    Player,
+    /// The volume changed.
+    Mixer,
+    /// Shuffle or repeat changed.
+    Options,
+    /// The playlist changed.
+    Playlist,
+}
+
+impl Subsystem {
+    const ALL: [Subsystem; 4] = [Subsystem::Player, Subsystem::Mixer, Subsystem::Options, Subsystem::Playlist];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Subsystem::Player => "player",
+            Subsystem::Mixer => "mixer",
+            Subsystem::Options => "options",
+            Subsystem::Playlist => "playlist",
+        }
+    }
+}
+
+/// A `seekcur` target.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Seek {
+    To(f64),
+    By(f64),
+}
+
+/// A parsed request line.
+#[derive(Debug, Clone, PartialEq)]
+enum Request {
+    Status,
+    CurrentSong,
+    /// Start playback, optionally of the track at a playlist position.
+    Play(Option<usize>),
+    /// Start playback, optionally of the song with an id, which is its
+    /// position since juke has no separate ids.
+    PlayId(Option<usize>),
+    /// Pause (true), resume (false), or toggle.
+    Pause(Option<bool>),
+    Next,
+    Previous,
+    SeekCur(Seek),
+    SetVol(u8),
+    /// Entries from `start` up to `end` (exclusive), or all.
+    PlaylistInfo(Option<(usize, Option<usize>)>),
+    /// Wait for changes in the given subsystems, or any.
+    Idle(Vec<Subsystem>),
+    NoIdle,
+    Ping,
+    Close,
+    Commands,
+    /// Start a command list, answering each command with `list_OK` if true.
+    ListBegin(bool),
+    ListEnd,
+}
+
+/// Splits a command line into words. Arguments may be quoted, with `\"`
+/// and `\\` escapes inside quotes.
+fn tokenize(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let Some(&first) = chars.peek() else {
+            return Ok(words);
+        };
+
+        let mut word = String::new();
+        if first == '"' {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => word.push(chars.next().ok_or("Unterminated quote")?),
+                    Some(c) => word.push(c),
+                    None => return Err("Unterminated quote".to_string()),
+                }
+            }
+            if chars.peek().is_some_and(|c| !c.is_whitespace()) {
+                return Err("Space expected after closing quote".to_string());
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                word.push(c);
+            }
+        }
+        words.push(word);
+    }
+}
+
+/// Parses one request line.
+fn parse_request(line: &str) -> Result<Request, Ack> {
+    let words = tokenize(line).map_err(|e| Ack::new(AckCode::Arg, "", e))?;
+    let Some((name, args)) = words.split_first() else {
+        return Err(Ack::new(AckCode::Unknown, "", "No command given"));
+    };
+    let name = name.as_str();
+    let arg_error = |message: &str| Ack::new(AckCode::Arg, name, message);
+    let max_args = |count: usize| {
+        if args.len() > count {
+            Err(arg_error("too many arguments"))
+        } else {
+            Ok(())
+        }
+    };
+    let number = |index: usize, what: &str| -> Result<f64, Ack> {
+        let arg = args.get(index).ok_or_else(|| arg_error("missing argument"))?;
+        arg.parse::<f64>()
+            .ok()
+            .filter(|n| n.is_finite())
+            .ok_or_else(|| arg_error(&format!("Number expected for {}: {}", what, arg)))
+    };
+    let position = |arg: &str| -> Result<usize, Ack> {
+        arg.parse().map_err(|_| arg_error(&format!("Integer expected: {}", arg)))
+    };
+
+    let request = match name {
+        "status" => Request::Status,
+        "currentsong" => Request::CurrentSong,
+        "play" => {
+            max_args(1)?;
+            Request::Play(args.first().map(|a| position(a)).transpose()?)
+        }
+        "playid" => {
+            max_args(1)?;
+            Request::PlayId(args.first().map(|a| position(a)).transpose()?)
+        }
+        "pause" => {
+            max_args(1)?;
+            Request::Pause(match args.first().map(String::as_str) {
+                None => None,
+                Some("1") => Some(true),
+                Some("0") => Some(false),
+                Some(other) => return Err(arg_error(&format!("Boolean (0/1) expected: {}", other))),
+            })
+        }
+        "next" => Request::Next,
+        "previous" => Request::Previous,
+        "seekcur" => {
+            max_args(1)?;
+            let time = number(0, "time")?;
+            let relative = args[0].starts_with(['+', '-']);
+            Request::SeekCur(if relative { Seek::By(time) } else { Seek::To(time) })
+        }
+        "setvol" => {
+            max_args(1)?;
+            let volume = number(0, "volume")?;
+            if !(0.0..=100.0).contains(&volume) {
+                return Err(arg_error("Invalid volume value"));
+            }
+            Request::SetVol(volume.round() as u8)
+        }
+        "playlistinfo" => {
+            max_args(1)?;
+            let range = match args.first() {
+                None => None,
+                Some(arg) => match arg.split_once(':') {
+                    Some((start, "")) => Some((position(start)?, None)),
+                    Some((start, end)) => Some((position(start)?, Some(position(end)?))),
+                    None => {
+                        let pos = position(arg)?;
+                        Some((pos, Some(pos + 1)))
+                    }
+                },
+            };
+            Request::PlaylistInfo(range)
+        }
+        "idle" => {
+            let subsystems = args
+                .iter()
+                .map(|arg| {
+                    Subsystem::ALL
+                        .into_iter()
+                        .find(|s| s.name() == arg)
+                        .ok_or_else(|| arg_error(&format!("Unrecognized idle event: {}", arg)))
+                })
+                .collect::<Result<_, _>>()?;
+            Request::Idle(subsystems)
+        }
+        "noidle" => Request::NoIdle,
+        "ping" => Request::Ping,
+        "close" => Request::Close,
+        "commands" => Request::Commands,
+        "command_list_begin" => Request::ListBegin(false),
+        "command_list_ok_begin" => Request::ListBegin(true),
+        "command_list_end" => Request::ListEnd,
+        _ => return Err(Ack::new(AckCode::Unknown, "", format!("unknown command \"{}\"", name))),
+    };
+    Ok(request)
+}
+
+/// What idle clients compare to notice changes.
+#[derive(Debug, Clone, PartialEq)]
+struct Observed {
+    track: Option<String>,
+    index: Option<f64>,
+    state: String,
+    position: f64,
+    volume: f64,
+    shuffle: bool,
+    repeat: String,
+    playlist_version: f64,
+    at: Instant,
+}
+
+impl Observed {
+    fn from_status(status: &Value, at: Instant) -> Self {
+        let number = |key| status.get(key).and_then(Value::as_f64).unwrap_or_default();
+        let text = |key| status.get(key).and_then(Value::as_str).unwrap_or_default().to_string();
+        Self {
+            track: status.get("track").and_then(|t| t.get("path")).and_then(Value::as_str).map(str::to_string),
+            index: status.get("index").and_then(Value::as_f64),
+            state: text("state"),
+            position: number("position"),
+            volume: number("volume"),
+            shuffle: status.get("shuffle").and_then(Value::as_bool).unwrap_or_default(),
+            repeat: text("repeat"),
+            playlist_version: number("playlist_version"),
+            at,
+        }
+    }
+
+    /// Lists the subsystems that changed since `self`.
+    fn changes(&self, next: &Observed) -> Vec<Subsystem> {
+        // A position that does not follow the clock means a seek
+        let expected = if self.state == "playing" {
+            self.position + next.at.duration_since(self.at).as_secs_f64()
+        } else {
+            self.position
+        };
+        let seeked = self.track == next.track && (next.position - expected).abs() > SEEK_THRESHOLD_SECS;
+
+        let mut changed = Vec::new();
+        if self.track != next.track || self.index != next.index || self.state != next.state || seeked {
+            changed.push(Subsystem::Player);
+        }
+        if self.volume != next.volume {
+            changed.push(Subsystem::Mixer);
+        }
+        if self.shuffle != next.shuffle || self.repeat != next.repeat {
+            changed.push(Subsystem::Options);
+        }
+        if self.playlist_version != next.playlist_version {
+            changed.push(Subsystem::Playlist);
+        }
+        changed
+    }
+}
+
+/// State of one client connection.
+#[derive(Default)]
+struct Session {
+    /// Lines of an open command list, and whether to send `list_OK`.
+    list: Option<(Vec<String>, bool)>,
+    /// Subsystems an idle client is waiting for.
+    idle: Option<Vec<Subsystem>>,
+    /// The player as of the end of the last idle.
+    seen: Option<Observed>,
+}
+
+/// What to do after handling a line.
+enum Reply {
+    Send(String),
+    /// Nothing to send yet, e.g. inside a command list or while idle.
+    Wait,
+    Close,
+}
+
+impl Session {
+    /// Handles one request line.
+    fn handle_line(&mut self, line: &str, player: &mut dyn FnMut(Command) -> Response) -> Reply {
+        // Any line ends an idle; only `noidle` is expected
+        if self.idle.take().is_some() {
+            self.seen = observe(player).ok();
+            if line.trim() == "noidle" {
+                return Reply::Send("OK\n".to_string());
+            }
+        }
+
+        if let Some((lines, ok)) = &mut self.list {
+            if line.trim() != "command_list_end" {
+                lines.push(line.to_string());
+                return Reply::Wait;
+            }
+            let (lines, ok) = (std::mem::take(lines), *ok);
+            self.list = None;
+            return Reply::Send(self.run_list(&lines, ok, player));
+        }
+
+        match parse_request(line) {
+            Ok(Request::Close) => Reply::Close,
+            Ok(Request::ListBegin(ok)) => {
+                self.list = Some((Vec::new(), ok));
+                Reply::Wait
+            }
+            Ok(Request::ListEnd) => Reply::Send(Ack::new(AckCode::NotList, "command_list_end", "not in command list").line(0)),
+            Ok(Request::Idle(subsystems)) => {
+                if self.seen.is_none() {
+                    match observe(player) {
+                        Ok(seen) => self.seen = Some(seen),
+                        Err(message) => return Reply::Send(Ack::new(AckCode::System, "idle", message).line(0)),
+                    }
+                }
+                self.idle = Some(if subsystems.is_empty() { Subsystem::ALL.to_vec() } else { subsystems });
+                // Changes since the last idle are reported right away
+                self.poll_idle(player).map_or(Reply::Wait, Reply::Send)
+            }
+            Ok(request) => match execute(&request, player) {
+                Ok(body) => Reply::Send(body + "OK\n"),
+                Err(ack) => Reply::Send(ack.line(0)),
+            },
+            Err(ack) => Reply::Send(ack.line(0)),
+        }
+    }
+
+    /// Runs a command list, stopping at the first error.
+    fn run_list(&mut self, lines: &[String], ok: bool, player: &mut dyn FnMut(Command) -> Response) -> String {
+        let mut out = String::new();
+        for (index, line) in lines.iter().enumerate() {
+            let result = parse_request(line).and_then(|request| match request {
+                Request::Idle(_) | Request::NoIdle | Request::Close | Request::ListBegin(_) | Request::ListEnd => {
+                    Err(Ack::new(AckCode::Arg, line.split_whitespace().next().unwrap_or_default(), "not allowed in command list"))
+                }
+                request => execute(&request, player),
+            });
+            match result {
+                Ok(body) => {
+                    out.push_str(&body);
+                    if ok {
+                        out.push_str("list_OK\n");
+                    }
+                }
+                Err(ack) => {
+                    out.push_str(&ack.line(index));
+                    return out;
+                }
+            }
+        }
+        out + "OK\n"
+    }
+
+    /// Returns the reply for an idle client if something it waits for changed.
+    fn poll_idle(&mut self, player: &mut dyn FnMut(Command) -> Response) -> Option<String> {
+        let wanted = self.idle.as_ref()?;
+        let next = observe(player).ok()?;
+        let changed: Vec<_> = match &self.seen {
+            Some(seen) => seen.changes(&next).into_iter().filter(|s| wanted.contains(s)).collect(),
+            None => Vec::new(),
+        };
+        self.seen = Some(next);
+        if changed.is_empty() {
+            return None;
+        }
+
+        self.idle = None;
+        let mut out: String = changed.iter().map(|s| format!("changed: {}\n", s.name())).collect();
+        out.push_str("OK\n");
+        Some(out)
+    }
+}
+
+/// Fetches the player status.
+fn status(player: &mut dyn FnMut(Command) -> Response, command: &str) -> Result<Value, Ack> {
+    match player(Command::Status) {
+        Response::Ok(fields) => fields
+            .into_iter()
+            .find(|(key, _)| key == "status")
+            .map(|(_, status)| status)
+            .ok_or_else(|| Ack::new(AckCode::System, command, "no status")),
+        Response::Error(message) => Err(Ack::new(AckCode::System, command, message)),
+    }
+}
+
+fn observe(player: &mut dyn FnMut(Command) -> Response) -> Result<Observed, String> {
+    status(player, "idle").map(|s| Observed::from_status(&s, Instant::now())).map_err(|ack| ack.message)
+}
+
+/// Runs a request, returning the reply body without the final `OK`.
+fn execute(request: &Request, player: &mut dyn FnMut(Command) -> Response) -> Result<String, Ack> {
+    let name = request_name(request);
+    let run = |player: &mut dyn FnMut(Command) -> Response, command| match player(command) {
+        Response::Ok(_) => Ok(String::new()),
+        Response::Error(message) => Err(Ack::new(AckCode::System, name, message)),
+    };
+
+    match request {
+        Request::Status => Ok(format_status(&status(player, name)?)),
+        Request::CurrentSong => {
+            let status = status(player, name)?;
+            Ok(match (status.get("track"), status.get("index").and_then(Value::as_f64)) {
+                (Some(track @ Value::Object(_)), Some(index)) => {
+                    format_song(track, index as usize, status.get("duration").and_then(Value::as_f64))
+                }
+                _ => String::new(),
+            })
+        }
+        Request::Play(Some(position)) | Request::PlayId(Some(position)) => run(player, Command::PlayAt(*position)),
+        Request::Play(None) | Request::PlayId(None) => run(player, Command::Play),
+        Request::Pause(None) => run(player, Command::PlayPause),
+        Request::Pause(Some(true)) => run(player, Command::Pause),
+        Request::Pause(Some(false)) => run(player, Command::Play),
+        Request::Next => run(player, Command::Next),
+        Request::Previous => run(player, Command::Previous),
+        Request::SeekCur(Seek::To(position)) => run(player, Command::Seek(*position)),
+        Request::SeekCur(Seek::By(offset)) => {
+            let position = status(player, name)?.get("position").and_then(Value::as_f64).unwrap_or_default();
+            run(player, Command::Seek((position + offset).max(0.0)))
+        }
+        Request::SetVol(volume) => run(player, Command::Volume(*volume)),
+        Request::PlaylistInfo(range) => {
+            let playlist = match player(Command::Playlist) {
+                Response::Ok(fields) => fields.into_iter().find(|(key, _)| key == "playlist").map(|(_, p)| p),
+                Response::Error(message) => return Err(Ack::new(AckCode::System, name, message)),
+            };
+            let tracks = match playlist.as_ref().and_then(|p| p.get("tracks")) {
+                Some(Value::Array(tracks)) => tracks.as_slice(),
+                _ => &[],
+            };
+            let (start, end) = match *range {
+                None => (0, tracks.len()),
+                Some((start, end)) => (start, end.unwrap_or(tracks.len()).min(tracks.len())),
+            };
+            if range.is_some() && (start >= tracks.len() || start > end) {
+                return Err(Ack::new(AckCode::Arg, name, "Bad song index"));
+            }
+            Ok(tracks[start..end]
+                .iter()
+                .enumerate()
+                .map(|(i, track)| format_song(track, start + i, track.get("duration").and_then(Value::as_f64)))
+                .collect())
+        }
+        Request::Ping => Ok(String::new()),
+        Request::Commands => Ok(COMMANDS.iter().map(|c| format!("command: {}\n", c)).collect()),
+        Request::Idle(_) | Request::NoIdle | Request::Close | Request::ListBegin(_) | Request::ListEnd => {
+            Ok(String::new())
+        }
+    }
+}
+
+/// Returns the command name used in ACK replies.
+fn request_name(request: &Request) -> &'static str {
+    match request {
+        Request::Status => "status",
+        Request::CurrentSong => "currentsong",
+        Request::Play(_) => "play",
+        Request::PlayId(_) => "playid",
+        Request::Pause(_) => "pause",
+        Request::Next => "next",
+        Request::Previous => "previous",
+        Request::SeekCur(_) => "seekcur",
+        Request::SetVol(_) => "setvol",
+        Request::PlaylistInfo(_) => "playlistinfo",
+        Request::Idle(_) => "idle",
+        Request::NoIdle => "noidle",
+        Request::Ping => "ping",
+        Request::Close => "close",
+        Request::Commands => "commands",
+        Request::ListBegin(true) => "command_list_ok_begin",
+        Request::ListBegin(false) => "command_list_begin",
+        Request::ListEnd => "command_list_end",
+    }
+}
+
+/// Formats a remote status object as `status` reply lines.
+fn format_status(status: &Value) -> String {
+    let number = |key| status.get(key).and_then(Value::as_f64).unwrap_or_default();
+    let repeat = status.get("repeat").and_then(Value::as_str).unwrap_or("off");
+    let state = match status.get("state").and_then(Value::as_str) {
+        Some("playing") => "play",
+        Some("paused") => "pause",
+        _ => "stop",
+    };
+
+    let mut out = format!(
+        "volume: {}\nrepeat: {}\nrandom: {}\nsingle: {}\nconsume: 0\nplaylist: {}\nplaylistlength: {}\nstate: {}\n",
+        number("volume"),
+        u8::from(repeat != "off"),
+        u8::from(status.get("shuffle").and_then(Value::as_bool).unwrap_or_default()),
+        u8::from(repeat == "single"),
+        number("playlist_version"),
+        number("length"),
+        state
+    );
+    if let Some(index) = status.get("index").and_then(Value::as_f64) {
+        let (elapsed, duration) = (number("position"), number("duration"));
+        out.push_str(&format!(
+            "song: {index}\nsongid: {index}\ntime: {}:{}\nelapsed: {:.3}\nduration: {:.3}\n",
+            elapsed.round(),
+            duration.round(),
+            elapsed,
+            duration
+        ));
+    }
+    out
+}
+
+/// Formats a track object as song reply lines.
+fn format_song(track: &Value, position: usize, duration: Option<f64>) -> String {
+    let text = |key| track.get(key).and_then(Value::as_str);
+    let mut out = String::new();
+    // Values are single lines in the protocol
+    let mut field = |name: &str, value: &str| {
+        out.push_str(&format!("{}: {}\n", name, value.replace(['\n', '\r'], " ")));
+    };
+    field("file", text("path").unwrap_or_default());
+    for (name, key) in [("Title", "title"), ("Artist", "artist"), ("Album", "album")] {
+        if let Some(value) = text(key) {
+            field(name, value);
+        }
+    }
+    if let Some(duration) = duration.filter(|d| *d > 0.0) {
+        field("Time", &duration.round().to_string());
+        field("duration", &format!("{:.3}", duration));
+    }
+    field("Pos", &position.to_string());
+    field("Id", &position.to_string());
+    out
+}
+
+/// What a connection waits for.
+#[derive(Debug)]
+enum Event {
+    /// Bytes from the client; none once it hung up.
+    Read(Vec<u8>),
+    /// The player or the playlist changed.
+    Changed,
+}
+
+/// Passes on what a client sends until it hangs up or the connection fails.
+fn read_client(mut reader: impl Read, events: mpsc::Sender<Event>) {
+    let mut buffer = [0u8; 4096];
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => {
+                if events.send(Event::Read(buffer[..n].to_vec())).is_err() {
+                    return;
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        }
+    }
+    let _ = events.send(Event::Read(Vec::new()));
+}
+
+/// Serves one client until it disconnects or sends `close`, writing to
+/// `stream` what it answers to `events`. A change only costs a status
+/// request when the client is idle.
+fn serve_connection<S: Write>(
+    mut stream: S,
+    events: impl IntoIterator<Item = Event>,
+    mut player: impl FnMut(Command) -> Response,
+) -> io::Result<()> {
+    stream.write_all(GREETING.as_bytes())?;
+    stream.flush()?;
+
+    let mut session = Session::default();
+    let mut pending = Vec::new();
+    for event in events {
+        let read = match event {
+            Event::Read(bytes) if bytes.is_empty() => return Ok(()),
+            Event::Read(bytes) => bytes,
+            Event::Changed => {
+                if let Some(reply) = session.poll_idle(&mut player) {
+                    stream.write_all(reply.as_bytes())?;
+                    stream.flush()?;
+                }
+                continue;
+            }
+        };
+        pending.extend_from_slice(&read);
+
+        while let Some(end) = pending.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = pending.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            match session.handle_line(line.trim_end_matches(['\n', '\r']), &mut player) {
+                Reply::Send(reply) => {
+                    stream.write_all(reply.as_bytes())?;
+                    stream.flush()?;
+                }
+                Reply::Wait => {}
+                Reply::Close => return Ok(()),
+            }
+        }
+
+        if pending.len() > MAX_LINE_LEN {
+            stream.write_all(Ack::new(AckCode::Arg, "", "Line too long").line(0).as_bytes())?;
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+/// Accepts MPD clients on a background thread and forwards their commands.
+pub struct MpdServer {
+    rx: mpsc::Receiver<Pending>,
+    /// Open connections, told about changes; each is dropped once it closes.
+    clients: Arc<Mutex<Vec<mpsc::Sender<Event>>>>,
+}
+
+impl MpdServer {
+    /// Starts listening on `127.0.0.1:port`.
+    pub fn start(port: u16) -> io::Result<Self> {
+        Ok(Self::spawn(TcpListener::bind(("127.0.0.1", port))?))
+    }
+
+    fn spawn(listener: TcpListener) -> Self {
+        let (tx, rx) = mpsc::channel::<Pending>();
+        let clients = Arc::new(Mutex::new(Vec::new()));
+        let accepted = clients.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else {
+                    continue;
+                };
+                let Ok(reader) = stream.try_clone() else {
+                    continue;
+                };
+                let (events_tx, events) = mpsc::channel();
+                if let Ok(mut clients) = accepted.lock() {
+                    clients.push(events_tx.clone());
+                }
+                std::thread::spawn(move || read_client(reader, events_tx));
+                let tx = tx.clone();
+                std::thread::spawn(move || {
+                    let _ = serve_connection(&stream, events, |command| remote::forward(&tx, command));
+                    // Ends the reader, which may be waiting on the client
+                    let _ = stream.shutdown(Shutdown::Both);
+                });
+            }
+        });
+        Self { rx, clients }
+    }
+
+    /// Wakes clients waiting in `idle` to check what changed. Call it when
+    /// the player or the playlist changed.
+    pub fn notify(&self) {
+        if let Ok(mut clients) = self.clients.lock() {
+            clients.retain(|client| client.send(Event::Changed).is_ok());
+        }
+    }
+
+    /// Returns the next command waiting to be handled, if any.
+    pub fn try_next(&self) -> Option<Pending> {
+        self.rx.try_recv().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::io::BufRead;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    /// A pretend player holding just what the protocol reports.
+    #[derive(Debug)]
+    struct FakePlayer {
+        state: &'static str,
+        index: usize,
+        position: f64,
+        volume: u8,
+        tracks: Vec<&'static str>,
+        received: Vec<Command>,
+    }
+
+    impl FakePlayer {
+        fn new() -> Self {
+            Self {
+                state: "paused",
+                index: 1,
+                position: 42.5,
+                volume: 80,
+                tracks: vec!["One", "Two", "Three"],
+                received: Vec::new(),
+            }
+        }
+
+        fn run(&mut self, command: Command) -> Response {
+            self.received.push(command.clone());
+            let track = |i: usize, title: &str| {
+                json!({
+                    "path": format!("/music/{}.mp3", i),
+                    "title": title,
+                    "artist": "Band",
+                    "album": null,
+                    "duration": 180.0,
+                })
+            };
+            match command {
+                Command::Status => {
+                    let status = json!({
+                        "track": track(self.index, self.tracks[self.index]),
+                        "position": self.position,
+                        "duration": 180.0,
+                        "state": self.state,
+                        "volume": self.volume,
+                        "shuffle": false,
+                        "repeat": "all",
+                        "index": self.index,
+                        "length": self.tracks.len(),
+                        "playlist_version": 3,
+                    });
+                    Response::Ok(vec![("status".to_string(), status)])
+                }
+                Command::Playlist => {
+                    let tracks: Vec<_> = self.tracks.iter().enumerate().map(|(i, t)| track(i, t)).collect();
+                    let playlist = json!({ "tracks": tracks });
+                    Response::Ok(vec![("playlist".to_string(), playlist)])
+                }
+                Command::Seek(position) if position > 180.0 => Response::Error("Position is past the end".to_string()),
+                Command::Seek(position) => {
+                    self.position = position;
+                    Response::ok()
+                }
+                Command::Volume(volume) => {
+                    self.volume = volume;
+                    Response::ok()
+                }
+                Command::Next => {
+                    self.index += 1;
+                    Response::ok()
+                }
+                Command::PlayAt(index) if index >= self.tracks.len() => {
+                    Response::Error("No track at that position".to_string())
+                }
+                Command::PlayAt(index) => {
+                    self.index = index;
+                    self.state = "playing";
+                    Response::ok()
+                }
+                _ => Response::ok(),
+            }
+        }
+    }
+
+    /// One step of a scripted client.
+    enum Step {
+        /// Send a chunk of bytes.
+        Send(&'static str),
+        /// Say the player changed, as juke does after anything happens.
+        Notify,
+        /// Change the player between events.
+        Change(fn(&mut FakePlayer)),
+    }
+
+    /// The events of a script, played back as the server asks for them.
+    struct Scripted {
+        steps: VecDeque<Step>,
+        player: Rc<RefCell<FakePlayer>>,
+    }
+
+    impl Iterator for Scripted {
+        type Item = Event;
+
+        fn next(&mut self) -> Option<Event> {
+            loop {
+                match self.steps.pop_front()? {
+                    Step::Send(chunk) => return Some(Event::Read(chunk.as_bytes().to_vec())),
+                    Step::Notify => return Some(Event::Changed),
+                    Step::Change(change) => change(&mut self.player.borrow_mut()),
+                }
+            }
+        }
+    }
+
+    /// Runs a script and returns everything the server sent after the greeting.
+    fn converse(steps: Vec<Step>) -> (String, Vec<Command>) {
+        let player = Rc::new(RefCell::new(FakePlayer::new()));
+        let client = Scripted { steps: steps.into(), player: player.clone() };
+        let mut output = Vec::new();
+        serve_connection(&mut output, client, |command| player.borrow_mut().run(command)).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let received = player.borrow().received.clone();
+        (output.strip_prefix(GREETING).unwrap().to_string(), received)
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(tokenize("  setvol   50 ").unwrap(), ["setvol", "50"]);
+        assert_eq!(tokenize(r#"find "a \"b\" \\c" x"#).unwrap(), ["find", r#"a "b" \c"#, "x"]);
+        assert_eq!(tokenize(r#"idle """#).unwrap(), ["idle", ""]);
+        assert!(tokenize(r#"find "open"#).is_err());
+        assert!(tokenize(r#"find "a"b"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_request() {
+        assert_eq!(parse_request("seekcur +5"), Ok(Request::SeekCur(Seek::By(5.0))));
+        assert_eq!(parse_request("seekcur 12.5"), Ok(Request::SeekCur(Seek::To(12.5))));
+        assert_eq!(parse_request("pause 1"), Ok(Request::Pause(Some(true))));
+        assert_eq!(parse_request("playlistinfo 1:"), Ok(Request::PlaylistInfo(Some((1, None)))));
+        assert_eq!(parse_request("playlistinfo 2"), Ok(Request::PlaylistInfo(Some((2, Some(3))))));
+        assert_eq!(parse_request("idle mixer player"), Ok(Request::Idle(vec![Subsystem::Mixer, Subsystem::Player])));
+
+        let ack = |line| parse_request(line).unwrap_err().line(0);
+        assert_eq!(ack("update"), "ACK [5@0] {} unknown command \"update\"\n");
+        assert_eq!(ack("setvol 101"), "ACK [2@0] {setvol} Invalid volume value\n");
+        assert_eq!(ack("pause maybe"), "ACK [2@0] {pause} Boolean (0/1) expected: maybe\n");
+        assert_eq!(ack("idle database"), "ACK [2@0] {idle} Unrecognized idle event: database\n");
+    }
+
+    #[test]
+    fn test_commands_over_split_reads() {
+        let (output, received) = converse(vec![
+            Step::Send("setv"),
+            Step::Send("ol 55\nnext\r\nseekcur -2.5\n"),
+            Step::Send("ping\nlsinfo\nsetvol\n"),
+        ]);
+        assert_eq!(
+            output,
+            "OK\nOK\nOK\nOK\nACK [5@0] {} unknown command \"lsinfo\"\nACK [2@0] {setvol} missing argument\n"
+        );
+        assert_eq!(
+            received,
+            [Command::Volume(55), Command::Next, Command::Status, Command::Seek(40.0)]
+        );
+    }
+
+    #[test]
+    fn test_status_and_songs() {
+        let (output, _) = converse(vec![Step::Send("status\ncurrentsong\nplaylistinfo 2:\nplaylistinfo 9\n")]);
+        assert_eq!(
+            output,
+            concat!(
+                "volume: 80\nrepeat: 1\nrandom: 0\nsingle: 0\nconsume: 0\nplaylist: 3\nplaylistlength: 3\n",
+                "state: pause\nsong: 1\nsongid: 1\ntime: 43:180\nelapsed: 42.500\nduration: 180.000\nOK\n",
+                "file: /music/1.mp3\nTitle: Two\nArtist: Band\nTime: 180\nduration: 180.000\nPos: 1\nId: 1\nOK\n",
+                "file: /music/2.mp3\nTitle: Three\nArtist: Band\nTime: 180\nduration: 180.000\nPos: 2\nId: 2\nOK\n",
+                "ACK [2@0] {playlistinfo} Bad song index\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_command_lists() {
+        let (output, received) = converse(vec![Step::Send(
+            "command_list_ok_begin\nsetvol 10\nping\ncommand_list_end\ncommand_list_begin\nnext\nseekcur 999\nnext\ncommand_list_end\n",
+        )]);
+        assert_eq!(
+            output,
+            "list_OK\nlist_OK\nOK\nACK [52@1] {seekcur} Position is past the end\n"
+        );
+        // The list stops at the failing command
+        assert_eq!(received, [Command::Volume(10), Command::Next, Command::Seek(999.0)]);
+    }
+
+    #[test]
+    fn test_play_at_position() {
+        let (output, received) = converse(vec![Step::Send("play 2\nplayid 0\nplay 7\nplay\n")]);
+        assert_eq!(output, "OK\nOK\nACK [52@0] {play} No track at that position\nOK\n");
+        assert_eq!(received, [Command::PlayAt(2), Command::PlayAt(0), Command::PlayAt(7), Command::Play]);
+    }
+
+    #[test]
+    fn test_idle() {
+        let (output, _) = converse(vec![
+            // Waits quietly until something it asked about changes
+            Step::Send("idle player playlist\n"),
+            Step::Notify,
+            Step::Change(|p| p.volume = 20),
+            Step::Notify,
+            Step::Change(|p| p.state = "playing"),
+            Step::Notify,
+            Step::Notify,
+            // Changes made while not idle are reported by the next idle
+            Step::Change(|p| p.volume = 30),
+            Step::Send("idle\n"),
+            Step::Send("idle\n"),
+            Step::Send("noidle\n"),
+            Step::Send("close\nping\n"),
+        ]);
+        assert_eq!(output, "changed: player\nOK\nchanged: mixer\nOK\nOK\n");
+    }
+
+    #[test]
+    fn test_tcp_listener() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = MpdServer::spawn(listener);
+        let player = std::thread::spawn(move || {
+            let (mut received, mut volume) = (Vec::new(), 50);
+            // Starting to idle takes two looks at the status, then the
+            // volume changes and the client is told
+            while received.iter().filter(|c| **c == Command::Status).count() < 3 {
+                let Some((command, reply)) = server.try_next() else {
+                    std::thread::sleep(Duration::from_millis(5));
+                    continue;
+                };
+                let status = json!({ "volume": volume });
+                let _ = reply.send(Response::Ok(vec![("status".to_string(), status)]));
+                received.push(command);
+                if received.iter().filter(|c| **c == Command::Status).count() == 2 {
+                    volume = 60;
+                    server.notify();
+                }
+            }
+            received
+        });
+
+        let mut client = std::net::TcpStream::connect(address).unwrap();
+        client.write_all(b"pause\nidle mixer\n").unwrap();
+        let mut lines = io::BufReader::new(client.try_clone().unwrap()).lines();
+        let mut next_line = || lines.next().unwrap().unwrap();
+        assert_eq!(format!("{}\n", next_line()), GREETING);
+        assert_eq!(next_line(), "OK");
+        assert_eq!(next_line(), "changed: mixer");
+        assert_eq!(next_line(), "OK");
+        client.write_all(b"close\n").unwrap();
+        assert_eq!(player.join().unwrap(), [Command::PlayPause, Command::Status, Command::Status, Command::Status]);
+    }
+}
@@ -0,0 +1,152 @@
+//! Desktop notifications for playback events.
+
+use crate::config::NotificationsConfig;
+use crate::strings::Strings;
+use crate::template::{Template, Values};
+use std::process::{Command, Stdio};
+
+/// Playback events that can trigger a notification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    TrackChange,
+    Pause,
+    PlaylistEnd,
+}
+
+/// Sends notifications according to the `[notifications]` section.
+#[derive(Debug, Clone)]
+pub struct Notifier {
+    config: NotificationsConfig,
+    summary: Template,
+    body: Template,
+    paused: String,
+    playlist_end: String,
+}
+
+impl Notifier {
+    pub fn new(config: &NotificationsConfig, strings: &Strings) -> Self {
+        Self {
+            config: config.clone(),
+            summary: Template::parse(&config.summary_template),
+            body: Template::parse(&config.body_template),
+            paused: strings.text("notify.paused").to_string(),
+            playlist_end: strings.text("notify.playlist_end").to_string(),
+        }
+    }
+
+    /// Returns the summary and body for an event, or `None` if it is disabled
+    /// or juke's terminal is `focused` and only unfocused notifications are
+    /// wanted.
+    ///
+    /// `values` describes the current track; the playlist end message is fixed.
+    pub fn message(&self, event: Event, values: &Values, focused: bool) -> Option<(String, String)> {
+        let enabled = match event {
+            Event::TrackChange => self.config.on_track_change,
+            Event::Pause => self.config.on_pause,
+            Event::PlaylistEnd => self.config.on_playlist_end,
+        };
+        if !self.config.enabled || !enabled || (focused && self.config.only_when_unfocused) {
+            return None;
+        }
+
+        match event {
+            Event::PlaylistEnd => Some(("juke".to_string(), self.playlist_end.clone())),
+            Event::TrackChange | Event::Pause => {
+                let mut summary = self.summary.expand(values);
+                if event == Event::Pause {
+                    summary = self.paused.replace("{summary}", &summary);
+                }
+                Some((summary, self.body.expand(values)))
+            }
+        }
+    }
+
+    /// Sends the notification for an event, if enabled.
+    pub fn notify(&self, event: Event, values: &Values, focused: bool) {
+        if let Some((summary, body)) = self.message(event, values, focused) {
+            send(&summary, &body);
+        }
+    }
+}
+
+/// Shows a notification using the platform's command-line tool, ignoring failures.
+fn send(summary: &str, body: &str) {
+    let mut command = if cfg!(target_os = "macos") {
+        let script = format!(
+            "display notification {} with title {}",
+            applescript_string(body),
+            applescript_string(summary)
+        );
+        let mut command = Command::new("osascript");
+        command.arg("-e").arg(script);
+        command
+    } else if cfg!(unix) {
+        let mut command = Command::new("notify-send");
+        command.arg("--app-name=juke").arg(summary).arg(body);
+        command
+    } else {
+        return;
+    };
+
+    let spawned = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    if let Ok(mut child) = spawned {
+        // Reap the process without blocking playback
+        std::thread::spawn(move || child.wait());
+    }
+}
+
+/// Quotes a string for AppleScript.
+fn applescript_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values() -> Values {
+        Values {
+            title: Some("Song".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_event_toggles() {
+        let config = NotificationsConfig {
+            enabled: true,
+            ..Default::default()
+        };
+        let notifier = Notifier::new(&config, &Strings::default());
+        assert_eq!(
+            notifier.message(Event::TrackChange, &values(), true),
+            Some(("Song".to_string(), String::new()))
+        );
+        assert_eq!(notifier.message(Event::Pause, &values(), true), None);
+        assert!(notifier.message(Event::PlaylistEnd, &values(), true).is_some());
+
+        let notifier = Notifier::new(&NotificationsConfig::default(), &Strings::default());
+        assert_eq!(notifier.message(Event::TrackChange, &values(), true), None);
+    }
+
+    #[test]
+    fn test_only_when_unfocused() {
+        let config = NotificationsConfig {
+            enabled: true,
+            only_when_unfocused: true,
+            ..Default::default()
+        };
+        let notifier = Notifier::new(&config, &Strings::default());
+        assert_eq!(notifier.message(Event::TrackChange, &values(), true), None);
+        assert!(notifier.message(Event::TrackChange, &values(), false).is_some());
+    }
+
+    #[test]
+    fn test_applescript_string() {
+        assert_eq!(applescript_string(r#"a "b" \c"#), r#""a \"b\" \\c""#);
+    }
+}
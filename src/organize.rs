@@ -0,0 +1,181 @@
+//! The `--organize` maintenance command: moving files into a tag-based layout.
+
+use crate::loudness::LoudnessCache;
+use crate::pattern::Pattern;
+use crate::playlist::Track;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Pattern used when `--pattern` is not given.
+pub const DEFAULT_PATTERN: &str = "{artist}/{album}/{track:02} - {title}.{ext}";
+
+/// A planned move of one file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Move {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+/// Counts reported after organizing.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct OrganizeSummary {
+    pub renamed: usize,
+    pub skipped: usize,
+    pub errors: usize,
+}
+
+/// Plans where each track should go under `target`.
+///
+/// Files already at their destination are left out and counted in the
+/// returned number of skipped files. Destinations that already exist, or that
+/// another file in the plan claimed first, get " (2)", " (3)", ... appended.
+pub fn plan(tracks: &[Track], pattern: &Pattern, target: &Path) -> (Vec<Move>, usize) {
+    let mut moves = Vec::new();
+    let mut claimed = HashSet::new();
+    let mut skipped = 0;
+
+    for track in tracks {
+        let from = fs::canonicalize(&track.path).unwrap_or_else(|_| track.path.clone());
+        let mut to = target.join(pattern.render(track));
+
+        if same_file(&from, &to) {
+            claimed.insert(to);
+            skipped += 1;
+            continue;
+        }
+
+        let base = to.clone();
+        let mut n = 1;
+        while to.exists() || claimed.contains(&to) {
+            n += 1;
+            to = with_suffix(&base, n);
+        }
+
+        claimed.insert(to.clone());
+        moves.push(Move { from, to });
+    }
+
+    (moves, skipped)
+}
+
+/// Carries out planned moves, keeping the loudness cache in sync.
+pub fn execute(moves: &[Move], cache: &mut LoudnessCache) -> OrganizeSummary {
+    let mut summary = OrganizeSummary::default();
+
+    for m in moves {
+        match move_file(&m.from, &m.to) {
+            Ok(()) => {
+                cache.rename(&m.from, &m.to);
+                summary.renamed += 1;
+            }
+            Err(e) => {
+                eprintln!("Error: {}: {}", m.from.display(), e);
+                summary.errors += 1;
+            }
+        }
+    }
+
+    summary
+}
+
+/// Moves a file, creating parent directories and copying across filesystems.
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if to.exists() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, "destination exists"));
+    }
+
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+
+    // Renames fail across filesystems; fall back to copy and delete
+    fs::copy(from, to)?;
+    if let Err(e) = fs::remove_file(from) {
+        let _ = fs::remove_file(to);
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Returns true if both paths refer to the same existing file.
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Appends " (n)" to a file stem, keeping the extension.
+fn with_suffix(path: &Path, n: usize) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let name = match path.extension() {
+        Some(ext) => format!("{} ({}).{}", stem, n, ext.to_string_lossy()),
+        None => format!("{} ({})", stem, n),
+    };
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_suffix() {
+        assert_eq!(with_suffix(Path::new("a/b.mp3"), 2), PathBuf::from("a/b (2).mp3"));
+        assert_eq!(with_suffix(Path::new("a/b"), 3), PathBuf::from("a/b (3)"));
+    }
+
+    #[test]
+    fn test_plan_and_execute() {
+        let dir = std::env::temp_dir().join(format!("juke_organize_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("in")).unwrap();
+        let dir = fs::canonicalize(&dir).unwrap();
+
+        let mut tracks = Vec::new();
+        for name in ["x.mp3", "y.mp3", "z.mp3"] {
+            let path = dir.join("in").join(name);
+            fs::write(&path, name).unwrap();
+            let mut track = Track::new(path);
+            track.artist = Some("Artist".to_string());
+            track.title = Some("Song".to_string());
+            tracks.push(track);
+        }
+        // A file already at the destination stays put and claims the name
+        let existing = dir.join("Artist - Song.mp3");
+        fs::write(&existing, "existing").unwrap();
+        let mut in_place = Track::new(existing.clone());
+        in_place.artist = Some("Artist".to_string());
+        in_place.title = Some("Song".to_string());
+        tracks.insert(0, in_place);
+
+        let pattern = Pattern::parse("{artist} - {title}.{ext}").unwrap();
+        let (moves, skipped) = plan(&tracks, &pattern, &dir);
+        assert_eq!(skipped, 1);
+        let targets: Vec<_> = moves.iter().map(|m| m.to.clone()).collect();
+        assert_eq!(
+            targets,
+            [
+                dir.join("Artist - Song (2).mp3"),
+                dir.join("Artist - Song (3).mp3"),
+                dir.join("Artist - Song (4).mp3"),
+            ]
+        );
+
+        let summary = execute(&moves, &mut LoudnessCache::default());
+        assert_eq!(summary, OrganizeSummary { renamed: 3, skipped: 0, errors: 0 });
+        assert_eq!(fs::read_to_string(dir.join("Artist - Song (3).mp3")).unwrap(), "y.mp3");
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "existing");
+        assert!(!dir.join("in/x.mp3").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
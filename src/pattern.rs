@@ -0,0 +1,285 @@
+//! File name patterns such as `{artist}/{album}/{track:02} - {title}.{ext}`.
+
+use crate::playlist::Track;
+use std::path::PathBuf;
+
+/// A tag field usable as a placeholder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Artist,
+    Album,
+    Title,
+    Track,
+    Disc,
+    Year,
+    Ext,
+}
+
+impl Field {
+    /// Parses a placeholder name.
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "artist" => Field::Artist,
+            "album" => Field::Album,
+            "title" => Field::Title,
+            "track" => Field::Track,
+            "disc" => Field::Disc,
+            "year" => Field::Year,
+            "ext" => Field::Ext,
+            _ => return None,
+        })
+    }
+}
+
+/// A piece of a parsed pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Field { field: Field, width: usize },
+}
+
+/// A parsed file name pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    segments: Vec<Segment>,
+}
+
+impl Pattern {
+    /// Parses a pattern. `{{` and `}}` produce literal braces.
+    pub fn parse(pattern: &str) -> Result<Self, PatternError> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = pattern.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut placeholder = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => placeholder.push(c),
+                            None => return Err(PatternError::Unclosed),
+                        }
+                    }
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(parse_placeholder(&placeholder)?);
+                }
+                '}' => return Err(PatternError::UnexpectedBrace),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        if segments.is_empty() {
+            return Err(PatternError::Empty);
+        }
+        Ok(Self { segments })
+    }
+
+    /// Renders the pattern for a track into a relative path.
+    ///
+    /// Tag values are sanitized so they can never introduce path separators;
+    /// only `/` written in the pattern itself creates directories.
+    pub fn render(&self, track: &Track) -> PathBuf {
+        let mut rendered = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => rendered.push_str(text),
+                Segment::Field { field, width } => {
+                    rendered.push_str(&sanitize(&field_value(track, *field, *width)))
+                }
+            }
+        }
+
+        rendered
+            .split('/')
+            .map(sanitize)
+            .filter(|part| !part.is_empty())
+            .collect()
+    }
+}
+
+/// Parses the inside of a `{...}` placeholder.
+fn parse_placeholder(placeholder: &str) -> Result<Segment, PatternError> {
+    let (name, width) = match placeholder.split_once(':') {
+        Some((name, spec)) => {
+            let width = spec
+                .parse::<usize>()
+                .map_err(|_| PatternError::BadWidth(placeholder.to_string()))?;
+            (name, width)
+        }
+        None => (placeholder, 0),
+    };
+
+    let field = Field::from_name(name.trim())
+        .ok_or_else(|| PatternError::UnknownField(name.to_string()))?;
+    if width > 0 && !matches!(field, Field::Track | Field::Disc | Field::Year) {
+        return Err(PatternError::BadWidth(placeholder.to_string()));
+    }
+    Ok(Segment::Field { field, width })
+}
+
+/// Returns the value of a field for a track, with fallbacks for missing tags.
+fn field_value(track: &Track, field: Field, width: usize) -> String {
+    let number = |n: Option<u32>| format!("{:0width$}", n.unwrap_or(0), width = width);
+    match field {
+        Field::Artist => track.artist.clone().unwrap_or_else(|| "Unknown Artist".to_string()),
+        Field::Album => track.album.clone().unwrap_or_else(|| "Unknown Album".to_string()),
+        Field::Title => track.title.clone().unwrap_or_else(|| {
+            track
+                .path
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default()
+        }),
+        Field::Track => number(track.track_number),
+        Field::Disc => number(track.disc_number),
+        Field::Year => number(track.year),
+        Field::Ext => track
+            .path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default(),
+    }
+}
+
+/// Replaces characters that are unsafe in file names on common platforms.
+pub fn sanitize(text: &str) -> String {
+    let replaced: String = text
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+
+    // Windows rejects trailing dots and spaces; "." and ".." are never valid names
+    let trimmed = replaced.trim().trim_end_matches('.').to_string();
+    if trimmed.chars().all(|c| c == '.') {
+        String::new()
+    } else {
+        trimmed
+    }
+}
+
+/// Turns text into a file name of lowercase words joined by dashes, like
+/// "live-1975" for "Live 1975!". Empty when nothing is left.
+pub fn slug(text: &str) -> String {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Errors that can occur while parsing a pattern.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PatternError {
+    Empty,
+    Unclosed,
+    UnexpectedBrace,
+    UnknownField(String),
+    BadWidth(String),
+}
+
+impl std::fmt::Display for PatternError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatternError::Empty => write!(f, "Pattern is empty"),
+            PatternError::Unclosed => write!(f, "Unclosed '{{' in pattern"),
+            PatternError::UnexpectedBrace => write!(f, "Unexpected '}}' in pattern (use '}}}}')"),
+            PatternError::UnknownField(name) => write!(f, "Unknown placeholder {{{}}}", name),
+            PatternError::BadWidth(spec) => write!(f, "Invalid padding in {{{}}}", spec),
+        }
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_track() -> Track {
+        let mut track = Track::new(PathBuf::from("/in/01 song.FLAC"));
+        track.artist = Some("AC/DC".to_string());
+        track.album = Some("Back in Black".to_string());
+        track.title = Some("Hells Bells?".to_string());
+        track.track_number = Some(1);
+        track.disc_number = Some(2);
+        track.year = Some(1980);
+        track
+    }
+
+    #[test]
+    fn test_render_default_pattern() {
+        let pattern = Pattern::parse("{artist}/{album}/{track:02} - {title}.{ext}").unwrap();
+        assert_eq!(
+            pattern.render(&sample_track()),
+            PathBuf::from("AC_DC/Back in Black/01 - Hells Bells_.flac")
+        );
+    }
+
+    #[test]
+    fn test_render_padding_and_escapes() {
+        let pattern = Pattern::parse("{{{year}}} {disc}-{track:03}").unwrap();
+        assert_eq!(pattern.render(&sample_track()), PathBuf::from("{1980} 2-001"));
+    }
+
+    #[test]
+    fn test_render_missing_tags() {
+        let track = Track::new(PathBuf::from("/in/untitled.mp3"));
+        let pattern = Pattern::parse("{artist}/{album}/{track:02} {title}.{ext}").unwrap();
+        assert_eq!(
+            pattern.render(&track),
+            PathBuf::from("Unknown Artist/Unknown Album/00 untitled.mp3")
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(Pattern::parse(""), Err(PatternError::Empty));
+        assert_eq!(Pattern::parse("{artist"), Err(PatternError::Unclosed));
+        assert_eq!(Pattern::parse("artist}"), Err(PatternError::UnexpectedBrace));
+        assert_eq!(Pattern::parse("{genre}"), Err(PatternError::UnknownField("genre".to_string())));
+        assert_eq!(Pattern::parse("{track:x}"), Err(PatternError::BadWidth("track:x".to_string())));
+        assert_eq!(Pattern::parse("{title:02}"), Err(PatternError::BadWidth("title:02".to_string())));
+    }
+
+    #[test]
+    fn test_sanitize() {
+        let cases = [
+            ("a/b\\c", "a_b_c"),
+            ("what?*", "what__"),
+            ("trailing. ", "trailing"),
+            ("..", ""),
+            ("tab\there", "tab_here"),
+            ("Sigur Rós", "Sigur Rós"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(sanitize(input), expected, "sanitizing {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_slug() {
+        assert_eq!(slug("Live 1975"), "live-1975");
+        assert_eq!(slug("  AC/DC: Live!  "), "ac-dc-live");
+        assert_eq!(slug("Sigur Rós"), "sigur-rós");
+        assert_eq!(slug("?!"), "");
+    }
+}
@@ -0,0 +1,247 @@
+//! Plain line-by-line output for screen readers (`--plain`).
+//!
+//! Instead of redrawing a screen, each change in state is announced once as
+//! a complete line of ordinary scrolling text.
+
+use crate::player::PlaybackState;
+use crate::playlist::{RepeatMode, ShuffleState};
+use crate::strings::Strings;
+use crate::template::format_time;
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// The state the plain printer reports on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    /// "Artist – Title" of the current track.
+    pub track: Option<String>,
+    pub index: usize,
+    pub length: usize,
+    pub state: PlaybackState,
+    pub position: Duration,
+    pub volume: u8,
+    pub shuffle: ShuffleState,
+    pub repeat: RepeatMode,
+    /// Transient status message or persistent notice.
+    pub message: Option<String>,
+    pub view: View,
+}
+
+/// What the screen would be showing.
+#[derive(Debug, Clone, PartialEq)]
+pub enum View {
+    Normal,
+    /// Lines of the help screen.
+    Help(Vec<String>),
+    /// Lines of the stats screen.
+    Stats(Vec<String>),
+    /// Entries of the log.
+    Log(Vec<String>),
+    TrackList {
+        query: String,
+        matches: usize,
+        /// Number and name of the selected track.
+        selected: Option<(usize, String)>,
+    },
+    /// The delete confirmation prompt.
+    ConfirmDelete(String),
+    /// A menu, with the selected item.
+    Menu { title: String, selected: String },
+}
+
+/// Announces changes between successive snapshots.
+#[derive(Debug, Default)]
+pub struct PlainPrinter {
+    last: Option<Snapshot>,
+}
+
+impl PlainPrinter {
+    /// Returns the lines announcing what changed since the previous snapshot.
+    pub fn changes(&mut self, next: Snapshot, strings: &Strings) -> Vec<String> {
+        let lines = describe_changes(self.last.as_ref(), &next, strings);
+        self.last = Some(next);
+        lines
+    }
+
+    /// Prints the changes since the previous snapshot.
+    pub fn print(&mut self, next: Snapshot, strings: &Strings) -> io::Result<()> {
+        let lines = self.changes(next, strings);
+        if lines.is_empty() {
+            return Ok(());
+        }
+
+        // Raw mode turns off newline translation, so end lines with CR LF.
+        // Everything is written at once so lines are never split.
+        let mut out = String::new();
+        for line in &lines {
+            out.push_str(&sanitize(line));
+            out.push_str("\r\n");
+        }
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(out.as_bytes())?;
+        stdout.flush()
+    }
+}
+
+/// Lists the announcements for going from `prev` to `next`.
+fn describe_changes(prev: Option<&Snapshot>, next: &Snapshot, strings: &Strings) -> Vec<String> {
+    let mut lines = Vec::new();
+    let time = format_time(next.position);
+
+    // Views other than the player are read out when they open or change
+    if prev.map(|p| &p.view) != Some(&next.view) {
+        match (&next.view, prev.map(|p| &p.view)) {
+            (View::Normal, _) => {}
+            (View::Help(help), _) | (View::Stats(help), _) => lines.extend(help.iter().cloned()),
+            (View::Log(log), _) if log.is_empty() => lines.push(strings.text("log.empty").to_string()),
+            (View::Log(log), _) => lines.extend(log.iter().cloned()),
+            (View::ConfirmDelete(prompt), _) => lines.push(prompt.clone()),
+            (View::Menu { title, selected }, previous) => {
+                if !matches!(previous, Some(View::Menu { title: previous_title, .. }) if previous_title == title) {
+                    lines.push(title.clone());
+                }
+                lines.push(selected.clone());
+            }
+            (View::TrackList { query, matches, selected }, previous) => {
+                let (previous_query, previous_selected) = match previous {
+                    Some(View::TrackList { query, selected, .. }) => (Some(query), Some(selected)),
+                    _ => {
+                        lines.push(strings.text_with("plain.track_list", &[("count", &next.length)]));
+                        (None, None)
+                    }
+                };
+                if !query.is_empty() && previous_query != Some(query) {
+                    lines.push(strings.text_with("plain.search", &[("query", query), ("matches", matches)]));
+                }
+                if previous_selected != Some(selected)
+                    && let Some((number, name)) = selected
+                {
+                    lines.push(strings.text_with("plain.selected", &[("position", number), ("track", name)]));
+                }
+            }
+        }
+    }
+
+    let same_track = prev.is_some_and(|p| p.track == next.track && p.index == next.index);
+    if !same_track {
+        match &next.track {
+            Some(track) => lines.push(strings.text_with(
+                "plain.playing",
+                &[("track", track), ("position", &(next.index + 1)), ("length", &next.length)],
+            )),
+            None => lines.push(strings.text("player.no_track").to_string()),
+        }
+    }
+
+    if let Some(prev) = prev {
+        if prev.state != next.state && (same_track || next.state != PlaybackState::Playing) {
+            lines.push(match next.state {
+                PlaybackState::Playing => strings.text_with("plain.resumed", &[("time", &time)]),
+                PlaybackState::Paused => strings.text_with("plain.paused", &[("time", &time)]),
+                PlaybackState::Stopped => strings.text("plain.stopped").to_string(),
+            });
+        }
+        if prev.volume != next.volume {
+            lines.push(strings.text_with("plain.volume", &[("volume", &next.volume)]));
+        }
+        if prev.shuffle != next.shuffle {
+            lines.push(strings.text(match next.shuffle {
+                ShuffleState::On => "plain.shuffle_on",
+                ShuffleState::Off => "plain.shuffle_off",
+            }).to_string());
+        }
+        if prev.repeat != next.repeat {
+            lines.push(strings.text(match next.repeat {
+                RepeatMode::Off => "plain.repeat_off",
+                RepeatMode::All => "plain.repeat_all",
+                RepeatMode::Single => "plain.repeat_single",
+            }).to_string());
+        }
+    }
+
+    if next.message.is_some() && prev.map(|p| &p.message) != Some(&next.message) {
+        lines.extend(next.message.clone());
+    }
+
+    lines
+}
+
+/// Replaces control characters, including escape sequences hidden in tags,
+/// so only printable text reaches the terminal.
+fn sanitize(line: &str) -> String {
+    line.chars().map(|c| if c.is_control() { ' ' } else { c }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            track: Some("Artist – Title".to_string()),
+            index: 2,
+            length: 34,
+            state: PlaybackState::Playing,
+            position: Duration::from_secs(83),
+            volume: 100,
+            shuffle: ShuffleState::Off,
+            repeat: RepeatMode::Off,
+            message: None,
+            view: View::Normal,
+        }
+    }
+
+    #[test]
+    fn test_announces_changes_once() {
+        let strings = Strings::default();
+        let mut printer = PlainPrinter::default();
+        assert_eq!(printer.changes(snapshot(), &strings), ["Playing: Artist – Title [3/34]"]);
+        // Time passing alone is not announced
+        let later = Snapshot { position: Duration::from_secs(90), ..snapshot() };
+        assert!(printer.changes(later, &strings).is_empty());
+
+        let paused = Snapshot { state: PlaybackState::Paused, volume: 60, ..snapshot() };
+        assert_eq!(printer.changes(paused.clone(), &strings), ["Paused at 1:23", "Volume 60%"]);
+        assert!(printer.changes(paused, &strings).is_empty());
+
+        // A new track is announced without a separate "playing" line
+        let next = Snapshot { index: 3, track: Some("Other".to_string()), volume: 60, ..snapshot() };
+        assert_eq!(printer.changes(next, &strings), ["Playing: Other [4/34]"]);
+    }
+
+    #[test]
+    fn test_views() {
+        let strings = Strings::default();
+        let mut printer = PlainPrinter::default();
+        printer.changes(snapshot(), &strings);
+
+        let list = |query: &str, selected: Option<(usize, String)>| Snapshot {
+            view: View::TrackList { query: query.to_string(), matches: 2, selected },
+            ..snapshot()
+        };
+        let lines = printer.changes(list("", Some((1, "First".to_string()))), &strings);
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("34 tracks"));
+        assert_eq!(lines[1], "1. First");
+        assert_eq!(
+            printer.changes(list("fi", Some((1, "First".to_string()))), &strings),
+            ["Search fi: 2 matches"]
+        );
+
+        let menu = |selected: &str| Snapshot {
+            view: View::Menu { title: "Sort by".to_string(), selected: selected.to_string() },
+            ..snapshot()
+        };
+        assert_eq!(printer.changes(menu("Title"), &strings), ["Sort by", "Title"]);
+        assert_eq!(printer.changes(menu("Artist"), &strings), ["Artist"]);
+
+        let help = Snapshot { view: View::Help(vec!["a".to_string(), "b".to_string()]), ..snapshot() };
+        assert_eq!(printer.changes(help, &strings), ["a", "b"]);
+        assert!(printer.changes(snapshot(), &strings).is_empty());
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(sanitize("\u{1b}[2Jtitle\r\n"), " [2Jtitle  ");
+    }
+}
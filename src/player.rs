@@ -1,73 +1,812 @@
 //! Audio playback engine.
+//!
+//! Every change in what the player is doing is sent as a [`PlayerEvent`] to
+//! the receiver from [`Player::subscribe`], in the order it happened, so
+//! integrations react to transitions instead of comparing state each tick.
+//! The end of a track is noticed by [`Player::poll`].
+//!
+//! For gapless playback, [`Player::queue_next_track`] appends the next track
+//! to the one playing, so that the audio runs straight on while the end is
+//! noticed and the next track loaded.
 
-use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink, Source};
+use crate::decoded::{Capture, DecodedCache, Samples};
+use crate::fade::{Fade, FadeControl};
+use crate::limiter::{Limiter, LimiterControl};
+use crate::spectrum;
+use crate::trim::Trim;
+use rodio::cpal::traits::HostTrait;
+use rodio::source::SeekError;
+use rodio::{Decoder, DeviceTrait, OutputStream, OutputStreamHandle, Sink, Source};
 use std::fs::File;
+use std::collections::VecDeque;
 use std::io::BufReader;
-use std::path::Path;
-use std::time::Duration;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How far from a segment's start playback may be and still run into it
+/// without seeking, allowing for the delay before the end is noticed.
+const SEGMENT_SLACK: Duration = Duration::from_secs(1);
+
+/// How quickly the limiter lets go until told otherwise.
+const DEFAULT_LIMITER_RELEASE: Duration = Duration::from_millis(150);
+
+/// Memory for recently played files until told otherwise.
+const DEFAULT_DECODED_CACHE: usize = 64 << 20;
+
+/// Blocks of samples the level is measured over each second.
+const LEVEL_BLOCKS_PER_SEC: u32 = 30;
+
+/// Blocks whose levels are kept for [`LevelMeter::recent`].
+const LEVEL_HISTORY: usize = 64;
+
+/// Level shown as nothing, in dBFS.
+const LEVEL_FLOOR_DB: f32 = -48.0;
 
 /// Current playback state.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum PlaybackState {
     Playing,
     Paused,
     Stopped,
 }
 
+/// A playback transition.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlayerEvent {
+    /// A track or segment was loaded; it starts paused unless a segment
+    /// runs on from the one before.
+    TrackLoaded { path: PathBuf, duration: Duration },
+    /// The loaded track started playing.
+    Started,
+    Paused,
+    Resumed,
+    /// The track played to its end.
+    Finished,
+    Seeked { from: Duration, to: Duration },
+    /// The track was unloaded.
+    Stopped,
+    /// Loading, seeking, or resuming failed.
+    Error(String),
+    /// A track asked for with [`Player::prepare_next`] was decoded ahead of
+    /// time, or will not be.
+    Preloaded { path: PathBuf, ready: bool },
+}
+
+/// How full the decoded cache is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheUsage {
+    /// Files kept decoded.
+    pub entries: usize,
+    /// Bytes their samples take up.
+    pub bytes: usize,
+    /// Bytes the cache may hold.
+    pub budget: usize,
+}
+
+/// Where the player's audio comes from and goes to.
+pub trait Output {
+    type Sink: OutputSink;
+    type Source: Source<Item = i16> + Send + 'static;
+
+    /// Opens a file for decoding.
+    fn decode(&self, path: &Path) -> Result<Self::Source, PlayerError>;
+
+    /// Creates a sink to play sources on.
+    fn sink(&self) -> Result<Self::Sink, PlayerError>;
+
+    /// Reopens the output, which may be wedged after the system slept. Sinks
+    /// created before are no longer heard.
+    fn reopen(&mut self) -> Result<(), PlayerError>;
+
+    /// Gets a file ready to be opened soon, so that opening it is quick,
+    /// then calls `done` with whether it is.
+    fn prepare(&self, _path: &Path, done: Box<dyn FnOnce(bool) + Send>) {
+        done(false);
+    }
+
+    /// Lets go of what [`Output::prepare`] holds for a file.
+    fn release(&self, _path: &Path) {}
+
+    /// Returns how full the output's decoded cache is, if it has one.
+    fn cache_usage(&self) -> Option<CacheUsage> {
+        None
+    }
+}
+
+/// Loudness and frequency bands of the samples just played, measured as
+/// they go to the sink and read by the visualizer.
+#[derive(Debug, Default)]
+pub struct LevelMeter {
+    /// Peak of the last block, as `f32` bits.
+    peak: AtomicU32,
+    /// RMS of the last block, as `f32` bits.
+    rms: AtomicU32,
+    /// Levels of recent blocks, oldest first.
+    history: Mutex<VecDeque<f32>>,
+    /// The last window of samples, mixed down to mono, for the spectrum.
+    window: Mutex<Vec<f32>>,
+    /// Sample rate of the window.
+    sample_rate: AtomicU32,
+}
+
+impl LevelMeter {
+    /// Records a block's peak and RMS. The audio thread never waits for the
+    /// history: while it is being read, the block is left out of it.
+    fn record(&self, peak: f32, rms: f32) {
+        self.peak.store(peak.to_bits(), Ordering::Relaxed);
+        self.rms.store(rms.to_bits(), Ordering::Relaxed);
+        if let Ok(mut history) = self.history.try_lock() {
+            if history.len() == LEVEL_HISTORY {
+                history.pop_front();
+            }
+            history.push_back(db_level(rms));
+        }
+    }
+
+    /// Replaces the window the spectrum is taken from, unless it is being
+    /// read.
+    fn publish(&self, window: &[f32], sample_rate: u32) {
+        if let Ok(mut current) = self.window.try_lock() {
+            current.clear();
+            current.extend_from_slice(window);
+            self.sample_rate.store(sample_rate, Ordering::Relaxed);
+        }
+    }
+
+    /// Drops the levels measured, as playback pauses or stops.
+    fn silence(&self) {
+        self.peak.store(0, Ordering::Relaxed);
+        self.rms.store(0, Ordering::Relaxed);
+        self.history.lock().unwrap_or_else(|e| e.into_inner()).clear();
+        self.window.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+
+    /// Returns the peak of the last block, from 0.0 to 1.0 or a little over.
+    pub fn peak(&self) -> f32 {
+        f32::from_bits(self.peak.load(Ordering::Relaxed))
+    }
+
+    /// Returns the RMS of the last block.
+    pub fn rms(&self) -> f32 {
+        f32::from_bits(self.rms.load(Ordering::Relaxed))
+    }
+
+    /// Returns the level of the last block, from 0.0 to 1.0 on a decibel
+    /// scale.
+    pub fn level(&self) -> f32 {
+        db_level(self.rms())
+    }
+
+    /// Returns the levels of up to `n` recent blocks, oldest first.
+    pub fn recent(&self, n: usize) -> Vec<f32> {
+        let history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+        history.iter().skip(history.len().saturating_sub(n)).copied().collect()
+    }
+
+    /// Returns `n` frequency band levels from 0.0 to 1.0, bass first, of
+    /// the last samples played; silence before any were.
+    pub fn spectrum(&self, n: usize) -> Vec<f32> {
+        // Copied so that the transform does not hold up the audio thread
+        let window = self.window.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        spectrum::bands(&window, self.sample_rate.load(Ordering::Relaxed), n)
+    }
+}
+
+/// Maps an RMS onto 0.0 to 1.0, from [`LEVEL_FLOOR_DB`] up to full scale.
+fn db_level(rms: f32) -> f32 {
+    if rms <= 0.0 {
+        return 0.0;
+    }
+    (1.0 - 20.0 * rms.log10() / LEVEL_FLOOR_DB).clamp(0.0, 1.0)
+}
+
+/// Passes a source's samples through, measuring them block by block into a
+/// [`LevelMeter`] and collecting windows of them for its spectrum.
+struct Metered<S> {
+    source: S,
+    meter: Arc<LevelMeter>,
+    /// Samples in a block, of all channels.
+    block: usize,
+    counted: usize,
+    peak: f32,
+    sum_squares: f32,
+    channels: u16,
+    /// Channels of the current frame added up so far.
+    channel: u16,
+    frame_sum: f32,
+    /// Frames mixed down to mono since the last window was published.
+    window: Vec<f32>,
+}
+
+impl<S: Source<Item = f32>> Metered<S> {
+    fn new(source: S, meter: Arc<LevelMeter>) -> Self {
+        let channels = source.channels().max(1);
+        let per_sec = source.sample_rate() * u32::from(channels);
+        let block = (per_sec / LEVEL_BLOCKS_PER_SEC).max(1) as usize;
+        Self {
+            source,
+            meter,
+            block,
+            counted: 0,
+            peak: 0.0,
+            sum_squares: 0.0,
+            channels,
+            channel: 0,
+            frame_sum: 0.0,
+            window: Vec::with_capacity(spectrum::WINDOW),
+        }
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for Metered<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.source.next()?;
+        self.peak = self.peak.max(sample.abs());
+        self.sum_squares += sample * sample;
+        self.counted += 1;
+        if self.counted == self.block {
+            self.meter.record(self.peak, (self.sum_squares / self.block as f32).sqrt());
+            self.counted = 0;
+            self.peak = 0.0;
+            self.sum_squares = 0.0;
+        }
+        self.frame_sum += sample;
+        self.channel += 1;
+        if self.channel == self.channels {
+            self.window.push(self.frame_sum / f32::from(self.channels));
+            self.channel = 0;
+            self.frame_sum = 0.0;
+            if self.window.len() == spectrum::WINDOW {
+                self.meter.publish(&self.window, self.source.sample_rate());
+                self.window.clear();
+            }
+        }
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.source.size_hint()
+    }
+}
+
+impl<S: Source<Item = f32>> Source for Metered<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.source.try_seek(pos)
+    }
+}
+
+/// The parts of [`Sink`] the player uses.
+pub trait OutputSink {
+    fn append<S: Source<Item = f32> + Send + 'static>(&self, source: S);
+    fn play(&self);
+    fn pause(&self);
+    fn is_paused(&self) -> bool;
+    /// Returns whether everything appended has been played.
+    fn empty(&self) -> bool;
+    fn set_volume(&self, volume: f32);
+    /// Moves playback to a position in what was appended, waiting until it
+    /// has.
+    fn try_seek(&self, position: Duration) -> Result<(), SeekError>;
+    /// Returns the position in the source playing.
+    fn position(&self) -> Duration;
+}
+
+/// Plays decoded files on an audio device.
+pub struct Device {
+    /// The open output, or None for a player without one.
+    stream: Option<(OutputStream, OutputStreamHandle)>,
+    /// Output device asked for, used again when the output is reopened.
+    device: Option<String>,
+    /// Recently played files, decoded.
+    cache: DecodedCache,
+}
+
+/// A file being decoded as it plays, or one decoded before.
+pub enum DeviceSource {
+    File(Box<Capture<Decoder<BufReader<File>>>>),
+    Cached(Samples),
+}
+
+impl Output for Device {
+    type Sink = Sink;
+    type Source = DeviceSource;
+
+    fn decode(&self, path: &Path) -> Result<Self::Source, PlayerError> {
+        if let Some(samples) = self.cache.get(path) {
+            return Ok(DeviceSource::Cached(samples));
+        }
+        let file = File::open(path)
+            .map_err(|e| PlayerError::FileError(format!("Failed to open file: {}", e)))?;
+        let decoder = Decoder::new(BufReader::new(file))
+            .map_err(|e| PlayerError::DecodeError(format!("Failed to decode audio: {}", e)))?;
+        // Going back to it later is then instant
+        Ok(DeviceSource::File(Box::new(self.cache.capture(path, decoder))))
+    }
+
+    fn prepare(&self, path: &Path, done: Box<dyn FnOnce(bool) + Send>) {
+        self.cache.fill_then(path, done);
+    }
+
+    fn release(&self, path: &Path) {
+        self.cache.forget(path);
+    }
+
+    fn cache_usage(&self) -> Option<CacheUsage> {
+        Some(self.cache.usage())
+    }
+
+    fn sink(&self) -> Result<Sink, PlayerError> {
+        let Some((_, stream_handle)) = &self.stream else {
+            return Err(PlayerError::InitializationError("No audio device".to_string()));
+        };
+        Sink::try_new(stream_handle).map_err(|e| PlayerError::InitializationError(e.to_string()))
+    }
+
+    fn reopen(&mut self) -> Result<(), PlayerError> {
+        if self.stream.is_none() {
+            return Ok(());
+        }
+        let (stream, stream_handle, _) = open_stream(self.device.as_deref())?;
+        self.stream = Some((stream, stream_handle));
+        Ok(())
+    }
+}
+
+impl Iterator for DeviceSource {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        match self {
+            DeviceSource::File(source) => source.next(),
+            DeviceSource::Cached(source) => source.next(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            DeviceSource::File(source) => source.size_hint(),
+            DeviceSource::Cached(source) => source.size_hint(),
+        }
+    }
+}
+
+impl Source for DeviceSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        match self {
+            DeviceSource::File(source) => source.current_frame_len(),
+            DeviceSource::Cached(source) => source.current_frame_len(),
+        }
+    }
+
+    fn channels(&self) -> u16 {
+        match self {
+            DeviceSource::File(source) => source.channels(),
+            DeviceSource::Cached(source) => source.channels(),
+        }
+    }
+
+    fn sample_rate(&self) -> u32 {
+        match self {
+            DeviceSource::File(source) => source.sample_rate(),
+            DeviceSource::Cached(source) => source.sample_rate(),
+        }
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        match self {
+            DeviceSource::File(source) => source.total_duration(),
+            DeviceSource::Cached(source) => source.total_duration(),
+        }
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        match self {
+            DeviceSource::File(source) => source.try_seek(pos),
+            DeviceSource::Cached(source) => source.try_seek(pos),
+        }
+    }
+}
+
+/// A track appended after the one playing, with no gap between them.
+struct Queued {
+    path: PathBuf,
+    /// Length of the whole file.
+    duration: Duration,
+    handover: Arc<Handover>,
+}
+
+/// Shared between the player and a queued track's source.
+#[derive(Debug, Default)]
+struct Handover {
+    /// When the queued track started playing.
+    started: Mutex<Option<Instant>>,
+    /// The queued track is no longer wanted and plays nothing.
+    dropped: AtomicBool,
+}
+
+impl Handover {
+    fn started(&self) -> Option<Instant> {
+        *self.started.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A queued track's source, noting when it starts.
+struct QueuedSource<S> {
+    source: S,
+    handover: Arc<Handover>,
+    started: bool,
+}
+
+impl<S: Source<Item = f32>> Iterator for QueuedSource<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.handover.dropped.load(Ordering::Relaxed) {
+            return None;
+        }
+        if !std::mem::replace(&mut self.started, true) {
+            *self.handover.started.lock().unwrap_or_else(|e| e.into_inner()) = Some(Instant::now());
+        }
+        self.source.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.source.size_hint()
+    }
+}
+
+impl<S: Source<Item = f32>> Source for QueuedSource<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.source.try_seek(pos)
+    }
+}
+
+impl OutputSink for Sink {
+    fn append<S: Source<Item = f32> + Send + 'static>(&self, source: S) {
+        Sink::append(self, source);
+    }
+
+    fn play(&self) {
+        Sink::play(self);
+    }
+
+    fn pause(&self) {
+        Sink::pause(self);
+    }
+
+    fn is_paused(&self) -> bool {
+        Sink::is_paused(self)
+    }
+
+    fn empty(&self) -> bool {
+        Sink::empty(self)
+    }
+
+    fn set_volume(&self, volume: f32) {
+        Sink::set_volume(self, volume);
+    }
+
+    fn try_seek(&self, position: Duration) -> Result<(), SeekError> {
+        Sink::try_seek(self, position)
+    }
+
+    fn position(&self) -> Duration {
+        Sink::get_pos(self)
+    }
+}
+
 /// Audio player with playback control.
-pub struct Player {
-    _stream: OutputStream,
-    stream_handle: OutputStreamHandle,
-    sink: Option<Sink>,
+///
+/// ```no_run
+/// use juke::player::{PlaybackState, Player, PlayerEvent};
+/// use std::time::Duration;
+///
+/// let mut player = Player::new()?;
+/// let events = player.subscribe();
+/// player.load_segment("song.flac", Duration::ZERO, None)?;
+/// player.play();
+/// assert_eq!(player.state(), PlaybackState::Playing);
+///
+/// // The end of the track is noticed when polling
+/// while !events.try_iter().any(|event| event == PlayerEvent::Finished) {
+///     std::thread::sleep(Duration::from_millis(100));
+///     player.poll();
+/// }
+/// # Ok::<(), juke::player::PlayerError>(())
+/// ```
+pub struct Player<O: Output = Device> {
+    output: O,
+    sink: Option<O::Sink>,
     state: PlaybackState,
     current_path: Option<String>,
+    /// Length of the current track, or of the segment being played.
     current_duration: Duration,
+    /// Length of the whole file.
+    file_duration: Duration,
+    /// Where the segment being played starts in the file; positions are relative to it.
+    segment_start: Duration,
+    /// Whether the segment stops before the end of the file.
+    segment_bounded: bool,
+    /// Intro and outro skipped in the current track.
+    trim: Trim,
+    /// Whether the track finishes where its outro starts, until a seek
+    /// into the outro.
+    skip_outro: bool,
     // Track elapsed time manually since rodio doesn't provide easy seeking
     playback_start: Option<std::time::Instant>,
     elapsed_before_pause: Duration,
+    /// User volume (0.0 to 1.0).
+    volume: f32,
+    /// Linear pre-amp factor.
+    preamp: f32,
+    /// Linear ReplayGain factor for the current track.
+    replay_gain: f32,
+    /// Whether output is silenced, the volume kept for unmuting.
+    muted: bool,
+    /// Short excerpt of another track playing while the current one waits.
+    preview: Option<O::Sink>,
+    /// Ramp in the current sink's source.
+    fade: Arc<FadeControl>,
+    /// How long a manual track change fades over; zero cuts.
+    track_change_fade: Duration,
+    /// Whether the next track loaded fades in over the last.
+    fade_next_load: bool,
+    /// How long the first playback fades in over, until it has started.
+    startup_fade: Option<Duration>,
+    /// Whether the current ramp is the startup fade, which volume changes
+    /// and pausing end.
+    startup_fading: bool,
+    /// The track switched away from, fading out until it is dropped.
+    fading_out: Option<(O::Sink, Instant)>,
+    /// Gain above unity for the limiter in the current sink's source.
+    limiter: Arc<LimiterControl>,
+    /// Whether gain above unity goes through the limiter; if not, it clips.
+    limiter_enabled: bool,
+    /// Loudness of what the sinks play.
+    meter: Arc<LevelMeter>,
+    limiter_release: Duration,
+    /// Whether the loaded track has played since it was loaded, telling a
+    /// start from a resume.
+    started: bool,
+    /// Whether the end of the loaded track has been reported.
+    finished: bool,
+    events: Option<mpsc::Sender<PlayerEvent>>,
+    /// File decoded ahead of time that has not been loaded since.
+    prepared: Option<PathBuf>,
+    /// Track appended to the sink to follow the current one.
+    queued: Option<Queued>,
 }
 
 impl Player {
     /// Creates a new player with initialized audio output.
     pub fn new() -> Result<Self, PlayerError> {
-        let (stream, stream_handle) = OutputStream::try_default()
-            .map_err(|e| PlayerError::InitializationError(e.to_string()))?;
+        Self::with_device(None).map(|(player, _)| player)
+    }
+
+    /// Creates a new player on the output device matching `preferred`.
+    ///
+    /// Falls back to the default device if none matches, returning a warning.
+    pub fn with_device(preferred: Option<&str>) -> Result<(Self, Option<String>), PlayerError> {
+        let (stream, stream_handle, warning) = open_stream(preferred)?;
+        let device = Device {
+            stream: Some((stream, stream_handle)),
+            device: preferred.map(str::to_string),
+            cache: DecodedCache::new(DEFAULT_DECODED_CACHE),
+        };
+        Ok((Player::with_output(device), warning))
+    }
+
+    /// Creates a player with no output device, which fails to load any
+    /// track. Lets the interface run where there is no device, as in tests.
+    pub fn without_device() -> Self {
+        Player::with_output(Device { stream: None, device: None, cache: DecodedCache::new(DEFAULT_DECODED_CACHE) })
+    }
 
-        Ok(Self {
-            _stream: stream,
-            stream_handle,
+    /// Sets how many bytes of recently played files are kept decoded; zero
+    /// keeps none.
+    pub fn set_decoded_cache(&mut self, budget: usize) {
+        self.output.cache.set_budget(budget);
+    }
+}
+
+impl<O: Output> Player<O> {
+    /// Creates a player on an output.
+    pub fn with_output(output: O) -> Self {
+        Self {
+            output,
             sink: None,
             state: PlaybackState::Stopped,
             current_path: None,
             current_duration: Duration::ZERO,
+            file_duration: Duration::ZERO,
+            segment_start: Duration::ZERO,
+            segment_bounded: false,
+            trim: Trim::default(),
+            skip_outro: false,
             playback_start: None,
             elapsed_before_pause: Duration::ZERO,
-        })
+            volume: 1.0,
+            preamp: 1.0,
+            replay_gain: 1.0,
+            muted: false,
+            preview: None,
+            fade: FadeControl::new(1.0),
+            track_change_fade: Duration::ZERO,
+            fade_next_load: false,
+            startup_fade: None,
+            startup_fading: false,
+            fading_out: None,
+            limiter: LimiterControl::new(),
+            limiter_enabled: true,
+            meter: Arc::default(),
+            limiter_release: DEFAULT_LIMITER_RELEASE,
+            started: false,
+            finished: false,
+            events: None,
+            prepared: None,
+            queued: None,
+        }
+    }
+
+    /// Returns a receiver for the player's events from now on, replacing any
+    /// earlier subscriber.
+    pub fn subscribe(&mut self) -> mpsc::Receiver<PlayerEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.events = Some(sender);
+        receiver
+    }
+
+    fn emit(&self, event: PlayerEvent) {
+        if let Some(events) = &self.events {
+            let _ = events.send(event);
+        }
+    }
+
+    /// Sends an error event for a failed result and passes it on.
+    fn report<T>(&self, result: Result<T, PlayerError>) -> Result<T, PlayerError> {
+        if let Err(e) = &result {
+            self.emit(PlayerEvent::Error(e.to_string()));
+        }
+        result
+    }
+
+    /// Sends [`PlayerEvent::Finished`] once the loaded track has played to
+    /// its end. Call it regularly.
+    pub fn poll(&mut self) {
+        if self.fading_out.as_ref().is_some_and(|(_, until)| Instant::now() >= *until) {
+            self.fading_out = None;
+        }
+        if !self.finished && self.has_track() && self.is_finished() {
+            self.finished = true;
+            self.emit(PlayerEvent::Finished);
+        }
     }
 
-    /// Loads and plays a track from the given path.
+    /// Loads a whole file as the current track.
+    #[cfg(test)]
     pub fn load_track<P: AsRef<Path>>(&mut self, path: P) -> Result<(), PlayerError> {
-        let path = path.as_ref();
+        self.load_segment(path, Duration::ZERO, None)
+    }
 
-        // Open the file
-        let file = File::open(path)
-            .map_err(|e| PlayerError::FileError(format!("Failed to open file: {}", e)))?;
+    /// Loads the part of a file from `start` to `end` (or its end) as the
+    /// current track. Positions and the duration are relative to `start`.
+    ///
+    /// When playback of the same file has just reached `start`, as when one
+    /// segment runs into the next, the file is not reopened.
+    pub fn load_segment<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        start: Duration,
+        end: Option<Duration>,
+    ) -> Result<(), PlayerError> {
+        let path = path.as_ref();
+        let result = match self.queued_started() {
+            Some(started) if start.is_zero() && end.is_none() && self.queued_track() == Some(path) => {
+                self.hand_over(started);
+                self.set_segment(start, end);
+                Ok(())
+            }
+            _ => {
+                self.drop_queued();
+                self.open_segment(path, start, end)
+            }
+        };
+        self.report(result)?;
+        self.trim = Trim::default();
+        if self.prepared.as_deref() == Some(path) {
+            self.prepared = None;
+        }
+        self.fade_next_load = false;
+        self.finished = false;
+        self.emit(PlayerEvent::TrackLoaded { path: path.to_path_buf(), duration: self.current_duration });
+        self.started = self.state == PlaybackState::Playing;
+        if self.started {
+            self.emit(PlayerEvent::Started);
+        }
+        Ok(())
+    }
 
-        let buf_reader = BufReader::new(file);
+    /// Loads a segment, leaving the sink playing if it runs on from the last.
+    fn open_segment(&mut self, path: &Path, start: Duration, end: Option<Duration>) -> Result<(), PlayerError> {
+        let same_file = self.sink.as_ref().is_some_and(|sink| !sink.empty())
+            && self.current_path.as_deref() == Some(path.to_string_lossy().as_ref());
+        if same_file {
+            let file_position = self.segment_start + self.current_position();
+            self.set_segment(start, end);
+            if file_position.abs_diff(start) <= SEGMENT_SLACK {
+                // Keep playing: only the position is now counted from the new start
+                self.elapsed_before_pause = file_position.saturating_sub(start);
+                if self.playback_start.is_some() {
+                    self.playback_start = Some(std::time::Instant::now());
+                }
+                return Ok(());
+            }
+            return self.reload(Duration::ZERO);
+        }
 
         // Decode the audio file
-        let source = Decoder::new(buf_reader)
-            .map_err(|e| PlayerError::DecodeError(format!("Failed to decode audio: {}", e)))?;
+        let source = self.output.decode(path)?;
 
         // Get duration if available
         let duration = source.total_duration().unwrap_or(Duration::ZERO);
+        let source = source.skip_duration(start);
 
         // Create a new sink
-        let sink = Sink::try_new(&self.stream_handle)
-            .map_err(|e| PlayerError::InitializationError(e.to_string()))?;
+        let sink = self.output.sink()?;
+
+        // Fade the playing track out, and this one in, instead of cutting
+        self.startup_fading = false;
+        let fading = self.fade_next_load && !self.track_change_fade.is_zero() && self.state == PlaybackState::Playing;
+        if fading && let Some(old) = self.sink.take() {
+            self.fade.ramp_to(0.0, self.track_change_fade);
+            // One still fading from a change just before is cut short
+            self.fading_out = Some((old, Instant::now() + self.track_change_fade));
+            self.fade = FadeControl::new(0.0);
+            self.fade.ramp_to(1.0, self.track_change_fade);
+        } else {
+            self.fade = FadeControl::new(1.0);
+        }
 
         // Append the source to the sink
-        sink.append(source);
+        sink.set_volume(self.sink_volume());
+        sink.append(self.limited(source));
 
         // Start paused - user must explicitly play
         sink.pause();
@@ -75,7 +814,8 @@ impl Player {
         // Update state
         self.sink = Some(sink);
         self.current_path = Some(path.to_string_lossy().to_string());
-        self.current_duration = duration;
+        self.file_duration = duration;
+        self.set_segment(start, end);
         self.state = PlaybackState::Paused;
         self.playback_start = None;
         self.elapsed_before_pause = Duration::ZERO;
@@ -83,6 +823,105 @@ impl Player {
         Ok(())
     }
 
+    /// Carries on with the queued track, which started playing at `started`,
+    /// as the current one.
+    fn hand_over(&mut self, started: Instant) {
+        let Some(queued) = self.queued.take() else {
+            return;
+        };
+        self.current_path = Some(queued.path.to_string_lossy().to_string());
+        self.file_duration = queued.duration;
+        match self.playback_start {
+            Some(_) => {
+                self.elapsed_before_pause = Duration::ZERO;
+                self.playback_start = Some(started);
+            }
+            // Paused since; how much of it played before is not known
+            None => self.elapsed_before_pause = Duration::ZERO,
+        }
+    }
+
+    /// Appends the track expected to play next to the one playing, so that
+    /// it starts the moment this one ends. Once it has,
+    /// [`PlayerEvent::Finished`] is sent for the current track, and loading
+    /// the queued one carries on with it instead of opening it again.
+    /// Loading any other track drops it.
+    ///
+    /// Does nothing while a track is queued, or when the current one stops
+    /// before the end of its file, as a segment or a track with a trimmed
+    /// outro does.
+    pub fn queue_next_track<P: AsRef<Path>>(&mut self, path: P) -> Result<(), PlayerError> {
+        let stops_early = self.segment_bounded || (self.skip_outro && !self.trim.outro.is_zero());
+        if self.queued.is_some() || stops_early {
+            return Ok(());
+        }
+        let Some(sink) = self.sink.as_ref().filter(|sink| !sink.empty()) else {
+            return Ok(());
+        };
+        let path = path.as_ref();
+        let source = match self.output.decode(path) {
+            Ok(source) => source,
+            Err(e) => return self.report(Err(e)),
+        };
+        let duration = source.total_duration().unwrap_or(Duration::ZERO);
+        let handover = Arc::new(Handover::default());
+        sink.append(QueuedSource { source: self.limited(source), handover: Arc::clone(&handover), started: false });
+        self.queued = Some(Queued { path: path.to_path_buf(), duration, handover });
+        Ok(())
+    }
+
+    /// Returns the track queued to follow the current one, if any.
+    pub fn queued_track(&self) -> Option<&Path> {
+        self.queued.as_ref().map(|queued| queued.path.as_path())
+    }
+
+    /// Drops the track queued to follow the current one, unless it has
+    /// started playing.
+    pub fn unqueue_next_track(&mut self) {
+        if self.queued_started().is_none() {
+            self.drop_queued();
+        }
+    }
+
+    /// Drops the queued track, silencing it if it is playing.
+    fn drop_queued(&mut self) {
+        if let Some(queued) = self.queued.take() {
+            queued.handover.dropped.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns when the queued track started playing, if it has.
+    fn queued_started(&self) -> Option<Instant> {
+        self.queued.as_ref().and_then(|queued| queued.handover.started())
+    }
+
+    /// Sets the part of the file being played.
+    fn set_segment(&mut self, start: Duration, end: Option<Duration>) {
+        let end = end.map_or(self.file_duration, |end| end.min(self.file_duration));
+        self.segment_start = start;
+        self.segment_bounded = end < self.file_duration;
+        self.current_duration = end.saturating_sub(start);
+    }
+
+    /// Reopens the audio output, which may be wedged after the system slept,
+    /// and reloads the current track paused at `position`.
+    pub fn reopen_output(&mut self, position: Duration) -> Result<(), PlayerError> {
+        // The old sinks must go before the stream they play on
+        self.drop_queued();
+        self.sink = None;
+        self.preview = None;
+        self.fading_out = None;
+        let result = self.output.reopen();
+        self.report(result)?;
+        if self.current_path.is_some() {
+            self.state = PlaybackState::Paused;
+        }
+        self.playback_start = None;
+        self.elapsed_before_pause = position;
+        let result = self.reload(position);
+        self.report(result)
+    }
+
     /// Toggles between play and pause.
     pub fn toggle_play_pause(&mut self) {
         match self.state {
@@ -96,10 +935,17 @@ impl Player {
         let is_paused = self.sink.as_ref().map_or(false, |s| s.is_paused());
 
         if is_paused {
+            // The sink built below starts from the control's gain, silence
+            if let Some(length) = self.startup_fade.take() {
+                self.fade = FadeControl::new(0.0);
+                self.fade.ramp_to(1.0, length);
+                self.startup_fading = true;
+            }
+
             // Recreate the sink to avoid ALSA underrun errors after long pauses
             let current_pos = self.current_position();
-            if self.seek_to(current_pos).is_ok() {
-                // seek_to leaves us paused, so explicitly start playing
+            if self.reload(current_pos).is_ok() {
+                // reload leaves us paused, so explicitly start playing
                 if let Some(sink) = &self.sink {
                     sink.play();
                 }
@@ -113,14 +959,20 @@ impl Player {
                     self.playback_start = Some(std::time::Instant::now());
                 }
             }
+            if self.state == PlaybackState::Playing {
+                let started = std::mem::replace(&mut self.started, true);
+                self.emit(if started { PlayerEvent::Resumed } else { PlayerEvent::Started });
+            }
         }
     }
 
     /// Pauses playback.
     pub fn pause(&mut self) {
+        self.end_startup_fade();
         if let Some(sink) = &self.sink {
             if !sink.is_paused() {
                 sink.pause();
+                self.meter.silence();
                 self.state = PlaybackState::Paused;
 
                 // Update elapsed time
@@ -128,49 +980,153 @@ impl Player {
                     self.elapsed_before_pause += start.elapsed();
                     self.playback_start = None;
                 }
+                self.emit(PlayerEvent::Paused);
             }
         }
     }
 
     /// Stops playback and resets position.
     pub fn stop(&mut self) {
+        let had_track = self.sink.is_some();
+        self.drop_queued();
         self.sink = None;
+        self.fading_out = None;
+        self.fade_next_load = false;
+        self.meter.silence();
         self.state = PlaybackState::Stopped;
         self.current_path = None;
         self.current_duration = Duration::ZERO;
+        self.file_duration = Duration::ZERO;
+        self.segment_start = Duration::ZERO;
+        self.segment_bounded = false;
+        self.trim = Trim::default();
         self.playback_start = None;
         self.elapsed_before_pause = Duration::ZERO;
+        if had_track {
+            self.emit(PlayerEvent::Stopped);
+        }
+    }
+
+    /// Plays `length` of a file on its own sink, leaving the current track as
+    /// it is. The excerpt starts at `at` (0.0 to 1.0) of the part from
+    /// `start` to `end` (or the end of the file). Pause the current track
+    /// first, or both play at once.
+    pub fn start_preview<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        (start, end): (Duration, Option<Duration>),
+        at: f32,
+        length: Duration,
+    ) -> Result<(), PlayerError> {
+        self.preview = None;
+        let mut source = self.output.decode(path.as_ref())?;
+
+        let end = match (end, source.total_duration()) {
+            (Some(end), _) => end,
+            (None, Some(total)) => total,
+            (None, None) => start,
+        };
+        let from = start + end.saturating_sub(start).mul_f32(at.clamp(0.0, 1.0));
+        let sink = self.output.sink()?;
+        sink.set_volume(if self.muted { 0.0 } else { self.volume * self.preamp });
+        // Skipping decodes everything before the excerpt, so only sources
+        // that cannot seek do
+        match source.try_seek(from) {
+            Ok(()) => sink.append(source.take_duration(length).convert_samples()),
+            Err(SeekError::NotSupported { .. }) => {
+                sink.append(source.skip_duration(from).take_duration(length).convert_samples())
+            }
+            Err(e) => return Err(PlayerError::DecodeError(format!("Failed to seek: {}", e))),
+        }
+        self.preview = Some(sink);
+        Ok(())
+    }
+
+    /// Returns whether a preview is still playing.
+    pub fn preview_playing(&self) -> bool {
+        self.preview.as_ref().is_some_and(|sink| !sink.empty())
+    }
+
+    /// Stops the preview, if any.
+    pub fn stop_preview(&mut self) {
+        self.preview = None;
     }
 
-    /// Seeks forward by the specified duration.
+    /// Seeks to a specific position in the track, no further than its end.
     ///
-    /// Note: rodio's Sink doesn't support true seeking, so this is implemented
-    /// by reloading the track and skipping ahead. This is a limitation of the
-    /// current implementation.
-    pub fn seek_forward(&mut self, step: Duration) -> Result<(), PlayerError> {
-        let current_pos = self.current_position();
-        let new_pos = current_pos + step;
-
-        if new_pos >= self.current_duration {
-            // Can't seek beyond the end
+    /// Sources that can seek, such as files decoded before, move there at
+    /// once. The rest are decoded again from the start and skipped ahead,
+    /// which takes a while on long files. Seeking into a skipped outro
+    /// plays it.
+    pub fn seek_to(&mut self, position: Duration) -> Result<(), PlayerError> {
+        if self.current_path.is_none() {
             return Ok(());
         }
+        let from = self.current_position();
+        let result = self.move_to(position);
+        let to = self.report(result)?;
+        self.skip_outro = to < self.trim.outro_start(self.current_duration);
+        self.emit(PlayerEvent::Seeked { from, to });
+        Ok(())
+    }
 
-        self.seek_to(new_pos)
+    /// Skips the intro and outro of the current track: playback moves past
+    /// the intro, unless it is already further on, and the track finishes
+    /// where the outro starts. Loading another track clears the trim.
+    pub fn set_trim(&mut self, trim: Trim) -> Result<(), PlayerError> {
+        self.trim = trim.within(self.current_duration);
+        self.skip_outro = true;
+        if self.current_path.is_some() && self.current_position() < self.trim.intro {
+            let result = self.move_to(self.trim.intro);
+            self.report(result)?;
+        }
+        Ok(())
     }
 
-    /// Seeks backward by the specified duration.
-    pub fn seek_backward(&mut self, step: Duration) -> Result<(), PlayerError> {
-        let current_pos = self.current_position();
-        let new_pos = current_pos.saturating_sub(step);
-        self.seek_to(new_pos)
+    /// Returns what is skipped of the current track.
+    pub fn trim(&self) -> Trim {
+        self.trim
     }
 
-    /// Seeks to a specific position in the track.
-    ///
-    /// Note: This reloads the track and uses skip_duration, which is not perfect
-    /// but works for basic seeking functionality.
-    fn seek_to(&mut self, position: Duration) -> Result<(), PlayerError> {
+    /// Moves playback to a position, no further than the end, and returns
+    /// where it landed.
+    fn move_to(&mut self, position: Duration) -> Result<Duration, PlayerError> {
+        let position = match self.current_duration {
+            Duration::ZERO => position,
+            duration => position.min(duration),
+        };
+        // A sink that has played everything has nothing left to seek in
+        let seeked = match self.sink.as_ref().filter(|sink| !sink.empty()) {
+            Some(sink) => sink.try_seek(self.segment_start + position),
+            None => Err(SeekError::NotSupported { underlying_source: "an empty sink" }),
+        };
+        match seeked {
+            Ok(()) => Ok(self.seeked()),
+            Err(SeekError::NotSupported { .. }) => self.reload(position).map(|()| position),
+            Err(e) => Err(PlayerError::DecodeError(format!("Failed to seek: {}", e))),
+        }
+    }
+
+    /// Counts the position from where a seek in the playing sink landed,
+    /// and returns it. Sources seek no finer than their frames or packets,
+    /// so the sink says where that is, no further than the end of the file.
+    fn seeked(&mut self) -> Duration {
+        let landed = self.sink.as_ref().map_or(self.segment_start, |sink| sink.position());
+        let landed = match self.file_duration {
+            Duration::ZERO => landed,
+            length => landed.min(length),
+        };
+        self.elapsed_before_pause = landed.saturating_sub(self.segment_start);
+        if self.playback_start.is_some() {
+            self.playback_start = Some(Instant::now());
+        }
+        self.finished = false;
+        self.elapsed_before_pause
+    }
+
+    /// Recreates the sink at a position in the track, keeping it playing or
+    /// paused.
+    fn reload(&mut self, position: Duration) -> Result<(), PlayerError> {
         let path = match &self.current_path {
             Some(p) => p.clone(),
             None => return Ok(()),
@@ -179,27 +1135,24 @@ impl Player {
         let was_playing = self.state == PlaybackState::Playing;
 
         // Reload the track
-        let file = File::open(&path)
-            .map_err(|e| PlayerError::FileError(format!("Failed to open file: {}", e)))?;
-
-        let buf_reader = BufReader::new(file);
-        let source = Decoder::new(buf_reader)
-            .map_err(|e| PlayerError::DecodeError(format!("Failed to decode audio: {}", e)))?;
+        let source = self.output.decode(Path::new(&path))?;
 
         // Skip to the desired position
-        let skipped_source = source.skip_duration(position);
+        let skipped_source = source.skip_duration(self.segment_start + position);
 
-        // Create new sink
-        let sink = Sink::try_new(&self.stream_handle)
-            .map_err(|e| PlayerError::InitializationError(e.to_string()))?;
+        // Create new sink, without any queued track
+        let sink = self.output.sink()?;
+        self.drop_queued();
 
-        sink.append(skipped_source);
+        sink.set_volume(self.sink_volume());
+        sink.append(self.limited(skipped_source));
 
         if !was_playing {
             sink.pause();
         }
 
         self.sink = Some(sink);
+        self.finished = false;
         self.elapsed_before_pause = position;
         self.playback_start = if was_playing {
             Some(std::time::Instant::now())
@@ -228,6 +1181,46 @@ impl Player {
         self.current_duration
     }
 
+    /// Corrects the length of the file playing, once measured, when its
+    /// header claimed another. A segment keeps its own end.
+    pub fn correct_duration(&mut self, length: Duration) {
+        if !self.has_track() || self.segment_bounded {
+            return;
+        }
+        self.file_duration = length;
+        self.set_segment(self.segment_start, None);
+    }
+
+    /// Returns how long the current track played before its file ran out,
+    /// once it has. A segment or a skipped outro ends before the file does,
+    /// so gives `None` like a track still playing.
+    pub fn played_to_end(&self) -> Option<Duration> {
+        if self.segment_bounded || (self.skip_outro && !self.trim.outro.is_zero()) {
+            return None;
+        }
+        match self.queued_started() {
+            Some(started) => Some(self.current_position().saturating_sub(started.elapsed())),
+            None => self.sink.as_ref().is_some_and(|sink| sink.empty()).then(|| self.current_position()),
+        }
+    }
+
+    /// Returns the loudness of what just played, from 0.0 to 1.0; nothing
+    /// while paused or stopped.
+    pub fn amplitude(&self) -> f32 {
+        self.meter.level()
+    }
+
+    /// Returns `bands` frequency band levels from 0.0 to 1.0, bass first,
+    /// of what just played; nothing while paused or stopped.
+    pub fn spectrum(&self, bands: usize) -> Vec<f32> {
+        self.meter.spectrum(bands)
+    }
+
+    /// Returns where the loudness and spectrum of what plays are measured.
+    pub fn level_meter(&self) -> Arc<LevelMeter> {
+        Arc::clone(&self.meter)
+    }
+
     /// Returns the current playback state.
     pub fn state(&self) -> PlaybackState {
         self.state
@@ -238,24 +1231,226 @@ impl Player {
         self.sink.is_some()
     }
 
-    /// Returns whether the current track has finished playing.
+    /// Returns whether the current track has finished playing, including a
+    /// segment that reached its end, a track that reached its skipped
+    /// outro, and one the queued track followed.
     pub fn is_finished(&self) -> bool {
+        if self.queued_started().is_some() {
+            return true;
+        }
+        let end = match self.skip_outro {
+            true => self.trim.outro_start(self.current_duration),
+            false => self.current_duration,
+        };
         self.sink.as_ref().map_or(true, |s| s.empty())
+            || ((self.segment_bounded || end < self.current_duration) && self.current_position() >= end)
+    }
+
+    /// Sets the ReplayGain adjustment in dB for the current and subsequent tracks.
+    ///
+    /// `None` plays at unity gain.
+    pub fn set_replay_gain(&mut self, gain_db: Option<f32>) {
+        self.replay_gain = gain_db.map_or(1.0, db_to_linear);
+        self.apply_gain();
+    }
+
+    /// Sets the global pre-amp in dB.
+    pub fn set_preamp_db(&mut self, preamp_db: f32) {
+        self.preamp = db_to_linear(preamp_db);
+        self.apply_gain();
     }
 
     /// Sets the playback volume (0.0 to 1.0).
-    #[allow(dead_code)]
+    ///
+    /// Ends the startup fade, jumping to full.
     pub fn set_volume(&mut self, volume: f32) {
-        if let Some(sink) = &self.sink {
-            sink.set_volume(volume.clamp(0.0, 1.0));
-        }
+        self.end_startup_fade();
+        self.volume = volume.clamp(0.0, 1.0);
+        self.apply_gain();
     }
 
     /// Returns the current volume (0.0 to 1.0).
-    #[allow(dead_code)]
     pub fn volume(&self) -> f32 {
-        self.sink.as_ref().map_or(1.0, |s| s.volume())
+        self.volume
+    }
+
+    /// Mutes or unmutes output without losing the volume setting.
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+        self.apply_gain();
+    }
+
+    /// Returns whether output is muted.
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    /// Turns the limiter on or off and sets how quickly it lets go. The
+    /// release applies from the next track or seek.
+    pub fn set_limiter(&mut self, enabled: bool, release: Duration) {
+        self.limiter_enabled = enabled;
+        self.limiter_release = release;
+        self.apply_gain();
+    }
+
+    /// Sets how long a manual track change fades the old track out and the
+    /// new one in over; zero switches at once.
+    pub fn set_track_change_fade(&mut self, fade: Duration) {
+        self.track_change_fade = fade;
+    }
+
+    /// Fades the next track loaded in over the one playing, if a fade is
+    /// set. Loading returns at once; the old track fades out on its own.
+    pub fn fade_next_load(&mut self) {
+        self.fade_next_load = true;
+    }
+
+    /// Decodes a track expected to play next ahead of time, so that it
+    /// starts without a gap when loaded. Needs the decoded cache. Sends
+    /// [`PlayerEvent::Preloaded`] once it is ready or known not to be.
+    pub fn prepare_next<P: AsRef<Path>>(&mut self, path: P) {
+        let path = path.as_ref();
+        self.prepared = Some(path.to_path_buf());
+        let (events, prepared) = (self.events.clone(), path.to_path_buf());
+        self.output.prepare(
+            path,
+            Box::new(move |ready| {
+                if let Some(events) = events {
+                    let _ = events.send(PlayerEvent::Preloaded { path: prepared, ready });
+                }
+            }),
+        );
+    }
+
+    /// Drops the track decoded ahead of time, if it has not been loaded
+    /// since, to give back its memory.
+    pub fn release_prepared(&mut self) {
+        if let Some(path) = self.prepared.take() {
+            self.output.release(&path);
+        }
+    }
+
+    /// Returns how full the decoded cache is, if the output has one.
+    pub fn decoded_cache_usage(&self) -> Option<CacheUsage> {
+        self.output.cache_usage()
+    }
+
+    /// Fades the first playback in from silence over `length`, on top of the
+    /// volume and gains; zero starts at full. Has no effect once something
+    /// has played.
+    pub fn set_startup_fade(&mut self, length: Duration) {
+        let played = self.startup_fading || self.started;
+        self.startup_fade = (!length.is_zero() && !played).then_some(length);
+    }
+
+    /// Jumps a startup fade still in progress to full.
+    fn end_startup_fade(&mut self) {
+        if std::mem::take(&mut self.startup_fading) {
+            self.fade.ramp_to(1.0, Duration::ZERO);
+        }
+    }
+
+    /// Returns whether the limiter is pulling back peaks right now.
+    pub fn limiting(&self) -> bool {
+        self.state == PlaybackState::Playing && self.limiter.active()
+    }
+
+    /// Returns the total gain: user volume × pre-amp × ReplayGain × mute.
+    fn effective_gain(&self) -> f32 {
+        if self.muted {
+            return 0.0;
+        }
+        self.volume * self.preamp * self.replay_gain
+    }
+
+    /// Returns the gain applied to the sink: all of it, or with the limiter
+    /// on, up to unity, the limiter taking the rest.
+    ///
+    /// Every code path that creates a sink must use this so loudness stays
+    /// identical across loads, seeks, and resumes.
+    fn sink_volume(&self) -> f32 {
+        let gain = self.effective_gain();
+        if self.limiter_enabled { gain.min(1.0) } else { gain }
+    }
+
+    /// Passes a decoded source through the limiter and the track's ramp,
+    /// then the level meter.
+    fn limited<S: Source<Item = i16>>(
+        &self,
+        source: S,
+    ) -> Metered<Fade<Limiter<rodio::source::SamplesConverter<S, f32>>>> {
+        let limited = Limiter::new(source.convert_samples(), self.limiter.clone(), self.limiter_release);
+        Metered::new(Fade::new(limited, self.fade.clone()), Arc::clone(&self.meter))
+    }
+
+    /// Applies the effective gain to the current sink and the limiter.
+    fn apply_gain(&self) {
+        let gain = self.effective_gain();
+        self.limiter.set_gain(if self.limiter_enabled { gain } else { 1.0 });
+        if let Some(sink) = &self.sink {
+            sink.set_volume(self.sink_volume());
+        }
+    }
+}
+
+/// Opens an output stream on the device matching `preferred`, or the default device.
+fn open_stream(
+    preferred: Option<&str>,
+) -> Result<(OutputStream, OutputStreamHandle, Option<String>), PlayerError> {
+    let mut warning = None;
+
+    if let Some(wanted) = preferred.filter(|w| !w.trim().is_empty()) {
+        let devices: Vec<_> = rodio::cpal::default_host()
+            .output_devices()
+            .map(|devices| devices.collect())
+            .unwrap_or_default();
+        let names: Vec<String> = devices.iter().map(|d| d.name().unwrap_or_default()).collect();
+
+        match match_device(&names, wanted) {
+            Some(index) => match OutputStream::try_from_device(&devices[index]) {
+                Ok((stream, handle)) => return Ok((stream, handle, None)),
+                Err(e) => {
+                    warning = Some(format!(
+                        "Could not open audio device '{}' ({}), using the default",
+                        names[index], e
+                    ));
+                }
+            },
+            None => {
+                warning = Some(format!(
+                    "No audio device matches '{}', using the default (see juke --list-devices)",
+                    wanted
+                ));
+            }
+        }
     }
+
+    let (stream, handle) =
+        OutputStream::try_default().map_err(|e| PlayerError::InitializationError(e.to_string()))?;
+    Ok((stream, handle, warning))
+}
+
+/// Returns the names of the available output devices.
+pub fn output_device_names() -> Result<Vec<String>, PlayerError> {
+    let devices = rodio::cpal::default_host()
+        .output_devices()
+        .map_err(|e| PlayerError::InitializationError(e.to_string()))?;
+    Ok(devices.filter_map(|d| d.name().ok()).collect())
+}
+
+/// Finds the device matching `wanted`: an exact name first, then the first name
+/// containing it, both ignoring case.
+fn match_device(names: &[String], wanted: &str) -> Option<usize> {
+    let wanted = wanted.trim().to_lowercase();
+    names
+        .iter()
+        .position(|name| name.to_lowercase() == wanted)
+        .or_else(|| names.iter().position(|name| name.to_lowercase().contains(&wanted)))
+}
+
+/// Converts a gain in decibels to a linear amplitude factor.
+pub fn db_to_linear(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
 }
 
 impl Default for Player {
@@ -287,6 +1482,394 @@ impl std::error::Error for PlayerError {}
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    /// Decodes every file as silence and plays it nowhere; the test says
+    /// when a sink has played everything.
+    struct StubOutput {
+        length: Duration,
+        ended: Rc<Cell<bool>>,
+        /// Whether sinks can seek, rather than the player reloading.
+        seekable: bool,
+        /// Sinks opened so far.
+        sinks: Cell<usize>,
+    }
+
+    impl StubOutput {
+        fn new(length: Duration, ended: Rc<Cell<bool>>) -> Self {
+            Self { length, ended, seekable: false, sinks: Cell::new(0) }
+        }
+    }
+
+    struct StubSink {
+        paused: Cell<bool>,
+        ended: Rc<Cell<bool>>,
+        seekable: bool,
+        volume: Cell<f32>,
+        length: Duration,
+        /// Where seeks land on or before, like packets; zero lands exactly.
+        step: Duration,
+        position: Cell<Duration>,
+    }
+
+    /// Silence at one sample a second.
+    struct Silence {
+        length: Duration,
+        left: u64,
+    }
+
+    impl Iterator for Silence {
+        type Item = i16;
+
+        fn next(&mut self) -> Option<i16> {
+            self.left = self.left.checked_sub(1)?;
+            Some(0)
+        }
+    }
+
+    impl Source for Silence {
+        fn current_frame_len(&self) -> Option<usize> {
+            None
+        }
+
+        fn channels(&self) -> u16 {
+            1
+        }
+
+        fn sample_rate(&self) -> u32 {
+            1
+        }
+
+        fn total_duration(&self) -> Option<Duration> {
+            Some(self.length)
+        }
+    }
+
+    impl Output for StubOutput {
+        type Sink = StubSink;
+        type Source = Silence;
+
+        fn decode(&self, path: &Path) -> Result<Self::Source, PlayerError> {
+            if path.extension().is_some_and(|ext| ext == "mp3") {
+                Ok(Silence { length: self.length, left: self.length.as_secs() })
+            } else {
+                Err(PlayerError::DecodeError(format!("cannot decode {}", path.display())))
+            }
+        }
+
+        fn sink(&self) -> Result<StubSink, PlayerError> {
+            self.ended.set(false);
+            self.sinks.set(self.sinks.get() + 1);
+            Ok(StubSink {
+                paused: Cell::new(false),
+                ended: self.ended.clone(),
+                seekable: self.seekable,
+                volume: Cell::new(1.0),
+                length: self.length,
+                step: Duration::ZERO,
+                position: Cell::new(Duration::ZERO),
+            })
+        }
+
+        fn reopen(&mut self) -> Result<(), PlayerError> {
+            Ok(())
+        }
+    }
+
+    impl OutputSink for StubSink {
+        fn append<S: Source<Item = f32> + Send + 'static>(&self, _source: S) {}
+
+        fn play(&self) {
+            self.paused.set(false);
+        }
+
+        fn pause(&self) {
+            self.paused.set(true);
+        }
+
+        fn is_paused(&self) -> bool {
+            self.paused.get()
+        }
+
+        fn empty(&self) -> bool {
+            self.ended.get()
+        }
+
+        fn set_volume(&self, volume: f32) {
+            self.volume.set(volume);
+        }
+
+        fn try_seek(&self, position: Duration) -> Result<(), SeekError> {
+            if !self.seekable {
+                return Err(SeekError::NotSupported { underlying_source: "Silence" });
+            }
+            let position = position.min(self.length);
+            let landed = match self.step.as_nanos() {
+                0 => position,
+                step => Duration::from_nanos((position.as_nanos() / step * step) as u64),
+            };
+            self.position.set(landed);
+            Ok(())
+        }
+
+        fn position(&self) -> Duration {
+            self.position.get()
+        }
+    }
+
+    #[test]
+    fn test_event_sequence() {
+        let ended = Rc::new(Cell::new(false));
+        let length = Duration::from_secs(180);
+        let mut player = Player::with_output(StubOutput::new(length, ended.clone()));
+        let events = player.subscribe();
+
+        assert!(player.load_track("song.flac").is_err());
+        player.load_track("song.mp3").unwrap();
+        player.play();
+        player.seek_to(Duration::from_secs(60)).unwrap();
+        player.pause();
+        player.play();
+        player.poll();
+        ended.set(true);
+        player.poll();
+        player.poll();
+        player.stop();
+
+        let mut events: Vec<PlayerEvent> = events.try_iter().collect();
+        // Where the seek started depends on how long the test took
+        match &mut events[3] {
+            PlayerEvent::Seeked { from, .. } if *from < Duration::from_secs(1) => *from = Duration::ZERO,
+            event => panic!("unexpected {:?}", event),
+        }
+        assert_eq!(
+            events,
+            [
+                PlayerEvent::Error("Decode error: cannot decode song.flac".to_string()),
+                PlayerEvent::TrackLoaded { path: PathBuf::from("song.mp3"), duration: length },
+                PlayerEvent::Started,
+                PlayerEvent::Seeked { from: Duration::ZERO, to: Duration::from_secs(60) },
+                PlayerEvent::Paused,
+                PlayerEvent::Resumed,
+                PlayerEvent::Finished,
+                PlayerEvent::Stopped,
+            ]
+        );
+        assert_eq!(player.state(), PlaybackState::Stopped);
+    }
+
+    #[test]
+    fn test_seek_keeps_a_seekable_sink() {
+        let length = Duration::from_secs(180);
+        let output = StubOutput { seekable: true, ..StubOutput::new(length, Rc::new(Cell::new(false))) };
+        let mut player = Player::with_output(output);
+        player.load_track("song.mp3").unwrap();
+        player.play();
+        let sinks = player.output.sinks.get();
+
+        player.seek_to(Duration::from_secs(60)).unwrap();
+        assert_eq!(player.output.sinks.get(), sinks);
+        assert_eq!(player.state(), PlaybackState::Playing);
+        assert!(player.current_position() >= Duration::from_secs(60));
+
+        // Past the end lands on the end
+        player.pause();
+        player.seek_to(Duration::from_secs(500)).unwrap();
+        assert_eq!(player.current_position(), length);
+        assert_eq!(player.state(), PlaybackState::Paused);
+
+        // A seek that lands before the position asked for counts from there
+        let events = player.subscribe();
+        player.sink.as_mut().unwrap().step = Duration::from_secs(4);
+        player.seek_to(Duration::from_secs(42)).unwrap();
+        assert_eq!(player.current_position(), Duration::from_secs(40));
+        assert_eq!(
+            events.try_recv().unwrap(),
+            PlayerEvent::Seeked { from: length, to: Duration::from_secs(40) }
+        );
+
+        // A source that cannot seek is loaded again
+        player.sink.as_mut().unwrap().seekable = false;
+        player.seek_to(Duration::from_secs(30)).unwrap();
+        assert_eq!(player.output.sinks.get(), sinks + 1);
+        assert_eq!(player.current_position(), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_mute_keeps_the_volume() {
+        let mut player = Player::with_output(StubOutput::new(Duration::from_secs(180), Rc::new(Cell::new(false))));
+        player.load_track("song.mp3").unwrap();
+        player.set_volume(0.6);
+        let sink_volume = |player: &Player<StubOutput>| player.sink.as_ref().unwrap().volume.get();
+        assert_eq!(sink_volume(&player), 0.6);
+
+        player.set_muted(true);
+        assert!(player.is_muted());
+        assert_eq!(player.effective_gain(), 0.0);
+        assert_eq!(sink_volume(&player), 0.0);
+        assert_eq!(player.volume(), 0.6);
+
+        player.set_muted(false);
+        assert_eq!(sink_volume(&player), 0.6);
+    }
+
+    #[test]
+    fn test_queued_track_follows_without_reopening() {
+        let length = Duration::from_secs(180);
+        let mut player = Player::with_output(StubOutput::new(length, Rc::new(Cell::new(false))));
+        let events = player.subscribe();
+        player.load_track("one.mp3").unwrap();
+        player.play();
+        player.queue_next_track("two.mp3").unwrap();
+        assert_eq!(player.queued_track(), Some(Path::new("two.mp3")));
+        assert!(player.queue_next_track("three.mp3").is_ok());
+        assert_eq!(player.queued_track(), Some(Path::new("two.mp3")));
+        let sinks = player.output.sinks.get();
+        events.try_iter().for_each(drop);
+
+        // The sink reaches the queued track
+        let handover = Arc::clone(&player.queued.as_ref().unwrap().handover);
+        *handover.started.lock().unwrap() = Some(Instant::now());
+        player.unqueue_next_track();
+        player.poll();
+        assert!(player.is_finished());
+        player.load_track("two.mp3").unwrap();
+        assert_eq!(player.output.sinks.get(), sinks);
+        assert_eq!(player.queued_track(), None);
+        assert!(!player.is_finished());
+        assert!(player.current_position() < Duration::from_secs(1));
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            [
+                PlayerEvent::Finished,
+                PlayerEvent::TrackLoaded { path: PathBuf::from("two.mp3"), duration: length },
+                PlayerEvent::Started,
+            ]
+        );
+        assert!(!handover.dropped.load(Ordering::Relaxed));
+
+        // Loading another track drops the queued one
+        player.queue_next_track("three.mp3").unwrap();
+        let handover = Arc::clone(&player.queued.as_ref().unwrap().handover);
+        player.load_track("four.mp3").unwrap();
+        assert!(handover.dropped.load(Ordering::Relaxed));
+        assert_eq!(player.output.sinks.get(), sinks + 1);
+
+        // A segment stops before the file ends, so nothing can follow it
+        player.load_segment("four.mp3", Duration::ZERO, Some(Duration::from_secs(60))).unwrap();
+        player.queue_next_track("five.mp3").unwrap();
+        assert_eq!(player.queued_track(), None);
+    }
+
+    #[test]
+    fn test_trim_skips_intro_and_outro() {
+        let length = Duration::from_secs(180);
+        let mut player = Player::with_output(StubOutput::new(length, Rc::new(Cell::new(false))));
+        player.load_track("song.mp3").unwrap();
+        let trim = Trim { intro: Duration::from_secs(30), outro: Duration::from_secs(20) };
+        player.set_trim(trim).unwrap();
+        assert_eq!(player.current_position(), Duration::from_secs(30));
+        assert_eq!(player.trim(), trim);
+
+        player.seek_to(Duration::from_secs(159)).unwrap();
+        assert!(!player.is_finished());
+        player.seek_to(Duration::from_secs(160)).unwrap();
+        assert!(!player.is_finished(), "seeking into the outro plays it");
+        player.seek_to(Duration::from_secs(100)).unwrap();
+        player.seek_to(Duration::from_secs(165)).unwrap();
+        assert!(!player.is_finished());
+
+        // Seeking into the intro is allowed too
+        player.seek_to(Duration::from_secs(5)).unwrap();
+        assert_eq!(player.current_position(), Duration::from_secs(5));
+
+        // Reaching the outro finishes the track
+        player.set_trim(trim).unwrap();
+        player.seek_to(Duration::from_secs(100)).unwrap();
+        player.elapsed_before_pause = Duration::from_secs(160);
+        assert!(player.is_finished());
+
+        // Another track starts untrimmed
+        player.load_track("other.mp3").unwrap();
+        assert_eq!(player.trim(), Trim::default());
+        assert_eq!(player.current_position(), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_correct_duration_and_played_to_end() {
+        let ended = Rc::new(Cell::new(false));
+        let mut player = Player::with_output(StubOutput::new(Duration::from_secs(180), Rc::clone(&ended)));
+        player.load_track("vbr.mp3").unwrap();
+        player.correct_duration(Duration::from_secs(215));
+        assert_eq!(player.duration(), Duration::from_secs(215));
+        player.seek_to(Duration::from_secs(200)).unwrap();
+        assert_eq!(player.played_to_end(), None);
+        ended.set(true);
+        assert!(player.played_to_end().is_some_and(|played| played >= Duration::from_secs(200)));
+
+        // A segment ends at its own end, not the file's
+        ended.set(false);
+        player.load_segment("vbr.mp3", Duration::from_secs(10), Some(Duration::from_secs(60))).unwrap();
+        player.correct_duration(Duration::from_secs(215));
+        assert_eq!(player.duration(), Duration::from_secs(50));
+        ended.set(true);
+        assert_eq!(player.played_to_end(), None);
+    }
+
+    #[test]
+    fn test_meter_measures_what_plays() {
+        let meter = Arc::new(LevelMeter::default());
+        let sine = rodio::source::SineWave::new(440.0).take_duration(Duration::from_millis(100));
+        assert_eq!(Metered::new(sine, Arc::clone(&meter)).count(), 4800);
+        // Three blocks of a full scale sine, at -3 dBFS
+        assert!((meter.rms() - std::f32::consts::FRAC_1_SQRT_2).abs() < 0.01);
+        assert!(meter.peak() > 0.99);
+        assert_eq!(meter.recent(12).len(), 3);
+        assert!((meter.level() - 0.9375).abs() < 0.01);
+        // 4800 samples fill four windows, the last of which is published
+        let bands = meter.spectrum(12);
+        assert!(bands[4] > 0.9 && bands[11] < 0.2, "440 Hz lights the fifth band: {bands:?}");
+        assert_eq!(db_level(0.001), 0.0);
+
+        // Pausing drops the level at once, whatever is still in the sink
+        let mut player = Player::with_output(StubOutput::new(Duration::from_secs(180), Rc::new(Cell::new(false))));
+        player.load_track("song.mp3").unwrap();
+        player.play();
+        player.meter.record(0.9, 0.5);
+        assert!(player.amplitude() > 0.0);
+        player.pause();
+        assert_eq!(player.amplitude(), 0.0);
+        assert!(player.level_meter().recent(12).is_empty());
+        assert_eq!(player.spectrum(4), vec![0.0; 4]);
+    }
+
+    #[test]
+    fn test_startup_fade() {
+        let length = Duration::from_secs(180);
+        let mut player = Player::with_output(StubOutput::new(length, Rc::new(Cell::new(false))));
+        player.set_startup_fade(Duration::from_secs(3));
+        player.load_track("song.mp3").unwrap();
+        player.set_volume(0.8);
+        player.play();
+        assert_eq!((player.fade.gain(), player.fade.target()), (0.0, 1.0));
+
+        // Pausing jumps to full, and the fade does not come back
+        player.pause();
+        assert_eq!(player.fade.target(), 1.0);
+        assert!(!player.startup_fading);
+        player.play();
+        assert!(player.startup_fade.is_none() && !player.startup_fading);
+
+        // Only the first playback fades in
+        let mut player = Player::with_output(StubOutput::new(length, Rc::new(Cell::new(false))));
+        player.set_startup_fade(Duration::from_secs(3));
+        player.load_track("song.mp3").unwrap();
+        player.play();
+        player.set_volume(0.5);
+        assert!(!player.startup_fading);
+        assert_eq!(player.volume(), 0.5);
+    }
 
     #[test]
     fn test_player_initialization() {
@@ -311,6 +1894,28 @@ mod tests {
         assert_eq!(player.state(), PlaybackState::Stopped);
     }
 
+    #[test]
+    fn test_db_to_linear() {
+        assert_eq!(db_to_linear(0.0), 1.0);
+        assert!((db_to_linear(6.0) - 1.9953).abs() < 1e-3);
+        assert!((db_to_linear(-6.0) - 0.5012).abs() < 1e-3);
+        assert!((db_to_linear(-20.0) - 0.1).abs() < 1e-6);
+        assert!((db_to_linear(15.0) * db_to_linear(-15.0) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_match_device() {
+        let names: Vec<String> = ["default", "USB Audio DAC", "HDMI 1", "usb audio dac (analog)"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(match_device(&names, "usb"), Some(1));
+        assert_eq!(match_device(&names, "USB AUDIO DAC (Analog)"), Some(3));
+        assert_eq!(match_device(&names, " hdmi "), Some(2));
+        assert_eq!(match_device(&names, "bluetooth"), None);
+        assert_eq!(match_device(&[], "usb"), None);
+    }
+
     #[test]
     fn test_volume_control() {
         let mut player = Player::new().unwrap();
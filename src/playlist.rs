@@ -1,11 +1,19 @@
 //! Playlist management, track metadata, M3U parsing, and directory scanning.
 
+use crate::collation::{Collation, SortKey};
+use crate::discs;
+use crate::filter::{self, ScanFilter};
+use crate::segments;
 use lofty::file::{AudioFile, TaggedFileExt};
-use lofty::tag::Accessor;
+use lofty::tag::{Accessor, ItemKey};
+use std::collections::{HashMap, HashSet};
 use std::fs::{self, File};
-use std::io::{BufRead, BufReader};
+use std::io::Read;
 use std::path::{Path, PathBuf};
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+/// Maximum nesting depth when expanding playlists that reference other playlists.
+const MAX_PLAYLIST_DEPTH: usize = 8;
 
 /// A single track in the playlist.
 #[derive(Debug, Clone)]
@@ -14,7 +22,38 @@ pub struct Track {
     pub title: Option<String>,
     pub artist: Option<String>,
     pub album: Option<String>,
+    pub year: Option<u32>,
+    pub track_number: Option<u32>,
+    pub disc_number: Option<u32>,
     pub duration: Option<Duration>,
+    /// ReplayGain track gain in dB, from tags.
+    pub replay_gain: Option<f32>,
+    /// Fields guessed from the directory layout rather than read from tags.
+    pub inferred: InferredFields,
+    /// When the file was first seen, in seconds since the Unix epoch; only
+    /// set for recently added playlists.
+    pub added: Option<u64>,
+    /// Where playback starts in the file, for a segment of a longer file.
+    pub start_offset: Option<Duration>,
+    /// Where playback stops in the file; `None` plays to the end.
+    pub end_offset: Option<Duration>,
+    /// The title was edited in juke. The file is left alone unless asked to
+    /// write it; saving the playlist keeps the new title.
+    pub renamed: bool,
+    /// The file's tags and duration were read. A fast scan leaves them
+    /// unread until the track is enriched.
+    pub probed: bool,
+}
+
+/// Marks track fields that were inferred and must never be written back as tags.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InferredFields {
+    pub artist: bool,
+    pub album: bool,
+    pub year: bool,
+    pub title: bool,
+    /// Inferred fields came from an online lookup rather than the path.
+    pub looked_up: bool,
 }
 
 impl Track {
@@ -25,8 +64,47 @@ impl Track {
             title: None,
             artist: None,
             album: None,
+            year: None,
+            track_number: None,
+            disc_number: None,
             duration: None,
+            replay_gain: None,
+            inferred: InferredFields::default(),
+            added: None,
+            start_offset: None,
+            end_offset: None,
+            renamed: false,
+            probed: false,
+        }
+    }
+
+    /// Whether the track is part of a longer file rather than all of it.
+    pub fn is_segment(&self) -> bool {
+        self.start_offset.is_some() || self.end_offset.is_some()
+    }
+
+    /// Fills in what a fast scan left out from `probed`, the same file read
+    /// in full. Values guessed from the path give way to tags; titles from
+    /// EXTINF lines, segment sidecars, or renaming are kept.
+    pub fn enrich(&mut self, probed: &Track) {
+        fn fill<T: Clone>(field: &mut Option<T>, inferred: &mut bool, value: &Option<T>) {
+            if value.is_some() && (field.is_none() || *inferred) {
+                field.clone_from(value);
+                *inferred = false;
+            }
+        }
+        fill(&mut self.title, &mut self.inferred.title, &probed.title);
+        fill(&mut self.artist, &mut self.inferred.artist, &probed.artist);
+        fill(&mut self.album, &mut self.inferred.album, &probed.album);
+        fill(&mut self.year, &mut self.inferred.year, &probed.year);
+        self.track_number = self.track_number.or(probed.track_number);
+        self.disc_number = self.disc_number.or(probed.disc_number);
+        self.replay_gain = self.replay_gain.or(probed.replay_gain);
+        if self.duration.is_none() {
+            let start = self.start_offset.unwrap_or_default();
+            self.duration = probed.duration.map(|duration| duration.saturating_sub(start));
         }
+        self.probed = true;
     }
 
     /// Returns a display name for the track (title or filename).
@@ -35,10 +113,34 @@ impl Track {
             .clone()
             .unwrap_or_else(|| self.path.file_name().unwrap_or_default().to_string_lossy().to_string())
     }
+
+    /// Returns the display name decorated for rendering.
+    ///
+    /// Unlike [`Track::display_name`], the result is meant only for display and
+    /// must not be used for searching or saving.
+    pub fn formatted_name(&self, options: &NameOptions) -> String {
+        let name = self.display_name();
+        if !options.show_track_numbers {
+            return name;
+        }
+
+        match (self.disc_number, self.track_number) {
+            (Some(disc), Some(track)) => format!("{}-{:02} · {}", disc, track, name),
+            (None, Some(track)) => format!("{:02} · {}", track, name),
+            _ => name,
+        }
+    }
+}
+
+/// Options controlling how track names are rendered.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NameOptions {
+    /// Prefix names with the disc and track number when known.
+    pub show_track_numbers: bool,
 }
 
 /// Shuffle state for the playlist.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ShuffleState {
     Off,
     On,
@@ -55,7 +157,7 @@ impl ShuffleState {
 }
 
 /// Repeat mode for the playlist.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum RepeatMode {
     Off,
     All,
@@ -73,6 +175,204 @@ impl RepeatMode {
     }
 }
 
+/// Options applied when building a playlist from a directory or M3U file.
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptions {
+    /// Ordering used for directory scans.
+    pub collation: Collation,
+    /// Fill missing artist/album/year from the directory layout.
+    pub infer_tags_from_path: bool,
+    /// Extensions and globs deciding which files a directory scan picks up.
+    pub filter: ScanFilter,
+    /// Depth, symlink, and hidden file handling for directory scans.
+    pub scan: ScanOptions,
+    /// Skip reading tags and durations, each a few slow reads on a network
+    /// mount; names come from the file names.
+    pub fast_scan: bool,
+}
+
+/// How far and into what a directory scan descends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScanOptions {
+    /// Deepest directory level scanned, the root being 1 (0 = unlimited).
+    pub max_depth: usize,
+    /// Follow symbolic links to files and directories.
+    pub follow_symlinks: bool,
+    /// Include files and directories whose names start with a dot.
+    pub include_hidden: bool,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: 0,
+            follow_symlinks: true,
+            include_hidden: true,
+        }
+    }
+}
+
+/// Summary of a directory scan, including entries that could not be read.
+#[derive(Debug, Clone, Default)]
+pub struct ScanReport {
+    /// Number of files examined.
+    pub scanned: usize,
+    /// Number of audio tracks added to the playlist.
+    pub added: usize,
+    /// Paths that could not be read, with the reason.
+    pub errors: Vec<(PathBuf, String)>,
+    /// Time spent walking directories, reading tags, and sorting.
+    pub walk_time: Duration,
+    pub tags_time: Duration,
+    pub sort_time: Duration,
+}
+
+/// Where some of a playlist's tracks were loaded from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlaylistSource {
+    Directory(PathBuf),
+    M3u(PathBuf),
+    /// Track paths piped to standard input, which can only be read once.
+    Stdin,
+}
+
+impl PlaylistSource {
+    /// Loads the tracks from this source.
+    pub fn load(&self, options: &LoadOptions) -> Result<Vec<Track>, PlaylistError> {
+        self.load_watched(options, &mut |_| true)
+    }
+
+    /// Whether the tracks can be loaded from this source again.
+    pub fn can_reload(&self) -> bool {
+        !matches!(self, PlaylistSource::Stdin)
+    }
+
+    /// Loads the tracks from every source in turn, as
+    /// [`PlaylistSource::load_watched`] does, counting the files looked at
+    /// across all of them.
+    pub fn load_all_watched(
+        sources: &[PlaylistSource],
+        options: &LoadOptions,
+        watch: &mut dyn FnMut(usize) -> bool,
+    ) -> Result<Vec<Track>, PlaylistError> {
+        let mut tracks = Vec::new();
+        let mut done = 0;
+        for source in sources {
+            let mut scanned = 0;
+            tracks.extend(source.load_watched(options, &mut |count| {
+                scanned = count;
+                watch(done + count)
+            })?);
+            done += scanned;
+        }
+        Ok(tracks)
+    }
+
+    /// Loads the tracks from this source, calling `watch` with the number
+    /// of files looked at so far as a directory scan goes. The scan stops
+    /// early, with what it found, once `watch` returns false.
+    pub fn load_watched(
+        &self,
+        options: &LoadOptions,
+        watch: &mut dyn FnMut(usize) -> bool,
+    ) -> Result<Vec<Track>, PlaylistError> {
+        match self {
+            PlaylistSource::Directory(path) => scan_directory_watched(path, options, watch).map(|(tracks, _)| tracks),
+            PlaylistSource::M3u(path) => parse_m3u(path, options),
+            PlaylistSource::Stdin => Err(PlaylistError::IoError("Standard input was read already".to_string())),
+        }
+    }
+
+    /// Names the source for display: a directory by its path, with the home
+    /// directory written as `~`, and a playlist file by its file name.
+    pub fn label(&self, home: Option<&Path>) -> String {
+        match self {
+            PlaylistSource::Directory(path) => match home.and_then(|home| path.strip_prefix(home).ok()) {
+                Some(rest) if rest.as_os_str().is_empty() => "~".to_string(),
+                Some(rest) => format!("~{}{}", std::path::MAIN_SEPARATOR, rest.display()),
+                None => path.display().to_string(),
+            },
+            PlaylistSource::M3u(path) => {
+                path.file_name().unwrap_or(path.as_os_str()).to_string_lossy().into_owned()
+            }
+            PlaylistSource::Stdin => "stdin".to_string(),
+        }
+    }
+}
+
+/// What tells a track apart when the track list is rebuilt: its file and
+/// where its segment starts.
+type Identity = (PathBuf, Option<Duration>);
+
+/// A change made to a playlist while playing, kept only by saving it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edit {
+    /// A track was given a new title.
+    Rename,
+    /// The tracks were sorted or shuffled into a new order.
+    Reorder,
+    /// A track was taken out.
+    Remove,
+}
+
+impl Edit {
+    /// Whether the edit is lost without saving a playlist loaded from
+    /// `sources`. Folders are scanned again in their own order next time,
+    /// and their removed tracks were deleted from them, so when folders are
+    /// all there is only renames count.
+    pub fn worth_saving(self, sources: &[PlaylistSource]) -> bool {
+        let folders = !sources.is_empty() && sources.iter().all(|s| matches!(s, PlaylistSource::Directory(_)));
+        !folders || self == Edit::Rename
+    }
+}
+
+/// Changes applied by a refresh.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RefreshDiff {
+    pub added: usize,
+    pub removed: usize,
+}
+
+/// A track list loaded again from a playlist's source, with the canonical
+/// paths it is merged by already worked out.
+pub struct Refreshed {
+    tracks: Vec<Track>,
+    keys: HashMap<PathBuf, PathBuf>,
+}
+
+impl Refreshed {
+    /// Canonicalizes the paths of `tracks` and of the `current` ones they
+    /// are merged with. This touches the filesystem for every track, which
+    /// is slow on a network drive, so the background task loading them does
+    /// it rather than [`Playlist::merge_refreshed`].
+    pub fn new(tracks: Vec<Track>, current: &[PathBuf]) -> Self {
+        let keys = tracks
+            .iter()
+            .map(|t| t.path.as_path())
+            .chain(current.iter().map(PathBuf::as_path))
+            .map(|path| (path.to_path_buf(), canonical_key(path)))
+            .collect();
+        Self { tracks, keys }
+    }
+}
+
+/// An album, a directory picked in the folder browser, or a selection of
+/// search results, played in order, interrupting the playlist until it is
+/// over.
+#[derive(Debug, Clone)]
+struct AlbumScope {
+    /// Name of the album or directory; `None` for a selection.
+    name: Option<String>,
+    /// Track indices of the album in disc and track order.
+    tracks: Vec<usize>,
+    /// Position in `tracks` of the track playing.
+    at: usize,
+    /// Track the playlist continues after once the album is over.
+    resume: usize,
+    /// Stop once the album is over instead of going back to the playlist.
+    stop_at_end: bool,
+}
+
 /// A playlist containing tracks with shuffle and repeat support.
 pub struct Playlist {
     tracks: Vec<Track>,
@@ -80,6 +380,34 @@ pub struct Playlist {
     shuffle: ShuffleState,
     shuffle_indices: Vec<usize>,
     repeat: RepeatMode,
+    scan_report: Option<ScanReport>,
+    /// Where the tracks were loaded from, in order; none for tracks chosen
+    /// elsewhere.
+    sources: Vec<PlaylistSource>,
+    /// How they were loaded, and are loaded again on a refresh.
+    load_options: LoadOptions,
+    /// Lowercased search text for each track, parallel to `tracks`.
+    search_keys: Vec<String>,
+    /// Bumped whenever the track list changes, so cached searches can tell they are stale.
+    revision: u64,
+    /// Smart shuffle weights by path; unlisted tracks weigh 1. `None` shuffles uniformly.
+    shuffle_weights: Option<HashMap<PathBuf, f64>>,
+    /// Track indices to play next, before the playlist continues.
+    queue: Vec<usize>,
+    /// The queued track playing, if one is. `current_index` stays where the
+    /// playlist was, so it carries on from there once the queue is done.
+    playing_queued: Option<usize>,
+    /// Album playing in order, ignoring shuffle and repeat, if any.
+    album: Option<AlbumScope>,
+    /// Tracks that failed to open when last tried.
+    unplayable: HashSet<PathBuf>,
+    /// Title from the `#PLAYLIST` line of the M3U file loaded, if any.
+    title: Option<String>,
+    /// Preferences from the `#JUKE:` lines of the M3U file loaded, written
+    /// back when it is saved.
+    settings: PlaylistSettings,
+    /// Edits made since it was loaded or last saved.
+    edits: Vec<Edit>,
 }
 
 impl Playlist {
@@ -91,95 +419,471 @@ impl Playlist {
             shuffle: ShuffleState::Off,
             shuffle_indices: Vec::new(),
             repeat: RepeatMode::Off,
+            scan_report: None,
+            sources: Vec::new(),
+            load_options: LoadOptions::default(),
+            search_keys: Vec::new(),
+            revision: 0,
+            shuffle_weights: None,
+            queue: Vec::new(),
+            playing_queued: None,
+            album: None,
+            unplayable: HashSet::new(),
+            title: None,
+            settings: PlaylistSettings::default(),
+            edits: Vec::new(),
         }
     }
 
     /// Creates a playlist from a directory by scanning for audio files.
-    pub fn from_directory<P: AsRef<Path>>(path: P) -> Result<Self, PlaylistError> {
-        let tracks = scan_directory(path)?;
+    ///
+    /// Unreadable entries below the root are skipped and recorded in the
+    /// playlist's [`ScanReport`]; only an unreadable root is an error.
+    pub fn from_directory<P: AsRef<Path>>(path: P, options: &LoadOptions) -> Result<Self, PlaylistError> {
+        let path = path.as_ref();
+        let (tracks, report) = scan_directory(path, options)?;
         if tracks.is_empty() {
             return Err(PlaylistError::EmptyPlaylist);
         }
 
-        Ok(Self {
+        let mut playlist = Self {
             tracks,
-            current_index: 0,
-            shuffle: ShuffleState::Off,
-            shuffle_indices: Vec::new(),
-            repeat: RepeatMode::Off,
-        })
+            scan_report: Some(report),
+            sources: vec![PlaylistSource::Directory(path.to_path_buf())],
+            load_options: options.clone(),
+            ..Self::new()
+        };
+        playlist.reindex();
+        Ok(playlist)
     }
 
     /// Loads a playlist from an M3U file.
-    pub fn from_m3u<P: AsRef<Path>>(path: P) -> Result<Self, PlaylistError> {
-        let tracks = parse_m3u(path)?;
+    pub fn from_m3u<P: AsRef<Path>>(path: P, options: &LoadOptions) -> Result<Self, PlaylistError> {
+        let path = path.as_ref();
+        let (tracks, m3u, report) = parse_m3u_report(path, options)?;
         if tracks.is_empty() {
             return Err(PlaylistError::EmptyPlaylist);
         }
 
-        Ok(Self {
+        let mut playlist = Self {
             tracks,
-            current_index: 0,
-            shuffle: ShuffleState::Off,
-            shuffle_indices: Vec::new(),
-            repeat: RepeatMode::Off,
-        })
+            title: m3u.title,
+            settings: m3u.settings,
+            scan_report: Some(report),
+            sources: vec![PlaylistSource::M3u(path.to_path_buf())],
+            load_options: options.clone(),
+            ..Self::new()
+        };
+        playlist.reindex();
+        Ok(playlist)
+    }
+
+    /// Loads a playlist from track paths piped to standard input, or read
+    /// from any other reader, one per line as in an M3U file. Relative
+    /// paths are taken from the current directory.
+    pub fn from_reader(mut reader: impl Read, options: &LoadOptions) -> Result<Self, PlaylistError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).map_err(|e| PlaylistError::IoError(e.to_string()))?;
+        let mut errors = Vec::new();
+        let (tracks, mut m3u) = read_m3u_tracks(&data, Path::new(""), options, 0, &mut HashSet::new(), &mut errors);
+        if tracks.is_empty() {
+            return Err(PlaylistError::EmptyPlaylist);
+        }
+        errors.extend(m3u.warnings.drain(..).map(|warning| (PathBuf::from("-"), warning)));
+
+        let mut playlist = Self {
+            scan_report: Some(ScanReport { scanned: tracks.len(), added: tracks.len(), errors, ..Default::default() }),
+            tracks,
+            sources: vec![PlaylistSource::Stdin],
+            load_options: options.clone(),
+            ..Self::new()
+        };
+        playlist.reindex();
+        Ok(playlist)
+    }
+
+    /// Adds the tracks of another playlist after these, and its sources
+    /// after this one's. The title of either no longer fits, so it goes.
+    pub fn append(&mut self, other: Playlist) {
+        self.tracks.extend(other.tracks);
+        self.sources.extend(other.sources);
+        self.title = None;
+        match (&mut self.scan_report, other.scan_report) {
+            (Some(report), Some(other)) => {
+                report.scanned += other.scanned;
+                report.added += other.added;
+                report.errors.extend(other.errors);
+                report.walk_time += other.walk_time;
+                report.tags_time += other.tags_time;
+                report.sort_time += other.sort_time;
+            }
+            (report @ None, other) => *report = other,
+            (Some(_), None) => {}
+        }
+        self.reindex();
+    }
+
+    /// Creates a playlist from tracks chosen elsewhere, keeping their order.
+    ///
+    /// Such a playlist has no source, so it cannot be refreshed.
+    pub fn from_tracks(tracks: Vec<Track>) -> Result<Self, PlaylistError> {
+        if tracks.is_empty() {
+            return Err(PlaylistError::EmptyPlaylist);
+        }
+        let mut playlist = Self { tracks, ..Self::new() };
+        playlist.reindex();
+        Ok(playlist)
     }
 
     /// Adds a track to the playlist.
     #[allow(dead_code)]
     pub fn add_track(&mut self, track: Track) {
+        self.search_keys.push(search_key(&track));
         self.tracks.push(track);
+        self.revision += 1;
         if self.shuffle == ShuffleState::On {
             self.regenerate_shuffle();
         }
     }
 
-    /// Returns the current track, if any.
-    pub fn current_track(&self) -> Option<&Track> {
-        if self.tracks.is_empty() {
+    /// Removes the track at the given playlist index.
+    ///
+    /// The current position keeps pointing at the same track, or at the one
+    /// that followed it when the current track itself is removed.
+    pub fn remove(&mut self, index: usize) -> Option<Track> {
+        if index >= self.tracks.len() {
             return None;
         }
+        let track = self.tracks.remove(index);
+        self.search_keys.remove(index);
+        self.revision += 1;
+        self.record(Edit::Remove);
+        self.album = None;
+        self.queue.retain(|&i| i != index);
+        for i in self.queue.iter_mut() {
+            if *i > index {
+                *i -= 1;
+            }
+        }
+        self.playing_queued = match self.playing_queued {
+            Some(i) if i == index => None,
+            Some(i) if i > index => Some(i - 1),
+            playing => playing,
+        };
+
+        if self.shuffle == ShuffleState::On {
+            if let Some(position) = self.shuffle_indices.iter().position(|&i| i == index) {
+                self.shuffle_indices.remove(position);
+                if position < self.current_index {
+                    self.current_index -= 1;
+                }
+            }
+            for i in self.shuffle_indices.iter_mut() {
+                if *i > index {
+                    *i -= 1;
+                }
+            }
+        } else if index < self.current_index {
+            self.current_index -= 1;
+        }
+
+        self.current_index = self.current_index.min(self.tracks.len().saturating_sub(1));
+        Some(track)
+    }
+
+    /// Returns the playlist index of the current track, accounting for shuffle.
+    pub fn current_track_index(&self) -> Option<usize> {
+        if self.tracks.is_empty() {
+            None
+        } else {
+            Some(self.playing_queued.unwrap_or_else(|| self.get_actual_index(self.current_index)))
+        }
+    }
+
+    /// Returns the current track, if any.
+    pub fn current_track(&self) -> Option<&Track> {
+        self.tracks.get(self.current_track_index()?)
+    }
 
-        let index = self.get_actual_index(self.current_index);
-        self.tracks.get(index)
+    /// Returns the index of the track the playlist is at, which is not the
+    /// current one while a queued track plays.
+    fn resume_point(&self) -> Option<usize> {
+        (!self.tracks.is_empty()).then(|| self.get_actual_index(self.current_index))
     }
 
-    /// Returns the current track index.
+    /// Returns the current track's position in play order.
     pub fn current_index(&self) -> usize {
-        self.current_index
+        self.playing_queued.map_or(self.current_index, |index| self.position_of(index))
     }
 
-    /// Moves to the next track, respecting repeat mode.
+    /// Moves to the next track: the first queued one, else the next on the
+    /// album being played, else the next in order, respecting repeat mode.
+    /// Tracks that failed to open are passed over.
     /// Returns true if successful, false if at end with no repeat.
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> bool {
-        if self.tracks.is_empty() {
-            return false;
+        self.step_past_unplayable(Self::step_next)
+    }
+
+    /// Takes steps until one lands on a track not marked unplayable. If
+    /// every track is, the last step stands; if the steps run out first,
+    /// the playlist stays where it was.
+    fn step_past_unplayable(&mut self, step: fn(&mut Self) -> bool) -> bool {
+        let start = (self.current_index, self.playing_queued, self.queue.clone(), self.album.clone());
+        for taken in 0..self.len() {
+            if !step(self) {
+                if taken > 0 {
+                    (self.current_index, self.playing_queued, self.queue, self.album) = start;
+                }
+                return false;
+            }
+            if !self.current_track_index().is_some_and(|index| self.is_unplayable(index)) {
+                break;
+            }
         }
+        !self.tracks.is_empty()
+    }
 
-        match self.repeat {
-            RepeatMode::Single => true, // Stay on current track
-            RepeatMode::All => {
-                self.current_index = (self.current_index + 1) % self.len();
+    /// Moves to the next track, unplayable or not.
+    fn step_next(&mut self) -> bool {
+        if !self.queue.is_empty() {
+            self.playing_queued = Some(self.queue.remove(0));
+            return true;
+        }
+        let queued = self.playing_queued.take();
+        if let Some(album) = &self.album {
+            match album.tracks.get(album.at + 1) {
+                Some(&index) => return self.move_to(index),
+                None if album.stop_at_end => {
+                    self.album = None;
+                    return false;
+                }
+                // The album is over; carry on from the track it was started from
+                None => {
+                    self.current_index = self.position_of(album.resume);
+                    self.album = None;
+                }
+            }
+        }
+        // Repeating one track repeats a queued one too
+        if self.repeat == RepeatMode::Single && queued.is_some() {
+            self.playing_queued = queued;
+            return true;
+        }
+        match self.next_position(self.current_index) {
+            Some(position) => {
+                self.current_index = position;
                 true
             }
-            RepeatMode::Off => {
-                if self.current_index + 1 < self.len() {
-                    self.current_index += 1;
-                    true
-                } else {
-                    false
+            None => false,
+        }
+    }
+
+    /// Returns the position in play order that follows `position`, or `None`
+    /// at the end with repeat off.
+    fn next_position(&self, position: usize) -> Option<usize> {
+        if self.tracks.is_empty() {
+            return None;
+        }
+        match self.repeat {
+            RepeatMode::Single => Some(position), // Stay on current track
+            RepeatMode::All => Some((position + 1) % self.len()),
+            RepeatMode::Off => Some(position + 1).filter(|&next| next < self.len()),
+        }
+    }
+
+    /// Opens the current track with `open`, moving on in play order past
+    /// tracks it fails on with an error `skip` accepts, such as a file that
+    /// is gone. Those are marked unplayable until they open again.
+    ///
+    /// Returns whether a track opened: false once every track has been
+    /// tried, or at the end with repeat off. An error `skip` does not
+    /// accept is returned.
+    pub fn open_playable<E>(
+        &mut self,
+        mut open: impl FnMut(&Track) -> Result<(), E>,
+        mut skip: impl FnMut(&Track, &E) -> bool,
+    ) -> Result<bool, E> {
+        let mut tried = HashSet::new();
+        // Shuffle with repeat may come back to a track before all are tried
+        for _ in 0..self.len().saturating_mul(2) {
+            let Some(index) = self.current_track_index() else {
+                break;
+            };
+            if tried.insert(index) {
+                let track = &self.tracks[index];
+                match open(track) {
+                    Ok(()) => {
+                        self.unplayable.remove(&track.path);
+                        return Ok(true);
+                    }
+                    Err(e) if skip(track, &e) => {
+                        self.unplayable.insert(track.path.clone());
+                    }
+                    Err(e) => return Err(e),
                 }
             }
+            // Tracks marked before are tried again too, as any may be back
+            if tried.len() == self.len() || !self.step_next() {
+                break;
+            }
+        }
+        Ok(false)
+    }
+
+    /// Returns whether a track failed to open when last tried.
+    pub fn is_unplayable(&self, index: usize) -> bool {
+        self.tracks.get(index).is_some_and(|track| self.unplayable.contains(&track.path))
+    }
+
+    /// Returns how many tracks failed to open when last tried.
+    pub fn unplayable_count(&self) -> usize {
+        self.unplayable.len()
+    }
+
+    /// Returns the index of the track [`Playlist::next`] would move to.
+    pub fn peek_next(&self) -> Option<usize> {
+        self.upcoming(1).first().copied()
+    }
+
+    /// Returns the indices of up to `count` tracks in the order they will
+    /// play: queued tracks first, then the rest of the album being played,
+    /// then the playlist continuing from where it was before the queue,
+    /// respecting shuffle and repeat.
+    pub fn upcoming(&self, count: usize) -> Vec<usize> {
+        let mut upcoming: Vec<usize> = self.queue.iter().copied().take(count).collect();
+        let mut position = self.current_index;
+        if self.repeat == RepeatMode::Single
+            && self.album.is_none()
+            && let Some(last) = upcoming.last().copied().or(self.playing_queued)
+        {
+            upcoming.resize(count, last);
+            return upcoming;
+        }
+        if let Some(album) = &self.album {
+            let rest = album.tracks.iter().skip(album.at + 1);
+            upcoming.extend(rest.take(count - upcoming.len()));
+            if album.stop_at_end {
+                return upcoming;
+            }
+            position = self.position_of(album.resume);
+        }
+        while upcoming.len() < count {
+            let Some(next) = self.next_position(position) else {
+                break;
+            };
+            position = next;
+            upcoming.push(self.get_actual_index(position));
+        }
+        upcoming
+    }
+
+    /// Queues a track to play next, after any already queued, or takes it
+    /// off the queue if it was queued. Returns true if it is now queued.
+    pub fn toggle_queued(&mut self, index: usize) -> bool {
+        if let Some(at) = self.queue.iter().position(|&i| i == index) {
+            self.queue.remove(at);
+            false
+        } else if index < self.tracks.len() {
+            self.queue.push(index);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns the indices of queued tracks, in the order they will play.
+    pub fn queue(&self) -> &[usize] {
+        &self.queue
+    }
+
+    /// Takes a track off the queue.
+    pub fn unqueue(&mut self, index: usize) {
+        self.queue.retain(|&i| i != index);
+    }
+
+    /// Returns whether a track is queued to play next.
+    pub fn is_queued(&self, index: usize) -> bool {
+        self.queue.contains(&index)
+    }
+
+    /// Returns the files of queued tracks, in the order they will play, for
+    /// saving with the session.
+    pub fn queued_paths(&self) -> Vec<PathBuf> {
+        self.queue.iter().filter_map(|&i| self.tracks.get(i)).map(|t| t.path.clone()).collect()
+    }
+
+    /// Queues the tracks of saved files in order, after any already queued,
+    /// silently skipping files no longer in the playlist. Returns how many
+    /// were queued.
+    pub fn restore_queue(&mut self, paths: &[PathBuf]) -> usize {
+        let keys: Vec<PathBuf> = self.tracks.iter().map(|t| canonical_key(&t.path)).collect();
+        let mut restored = 0;
+        for path in paths {
+            let key = canonical_key(path);
+            let found = (0..keys.len()).find(|&i| keys[i] == key && !self.queue.contains(&i));
+            if let Some(index) = found {
+                self.queue.push(index);
+                restored += 1;
+            }
+        }
+        restored
+    }
+
+    /// Returns the identity of each queued track, its file as `key_of`
+    /// names it, to find them again after the track list is rebuilt.
+    fn queue_keys(&self, key_of: impl Fn(&Path) -> PathBuf) -> Vec<Identity> {
+        self.queue
+            .iter()
+            .filter_map(|&i| self.tracks.get(i))
+            .map(|t| (key_of(&t.path), t.start_offset))
+            .collect()
+    }
+
+    /// Returns the identity of the queued track playing, if one is.
+    fn playing_queued_key(&self, key_of: impl Fn(&Path) -> PathBuf) -> Option<Identity> {
+        let track = self.tracks.get(self.playing_queued?)?;
+        Some((key_of(&track.path), track.start_offset))
+    }
+
+    /// Points the queue and the queued track playing at the tracks with the
+    /// given identities, dropping any that are gone. Each track's identity
+    /// is worked out once, with the same `key_of` as the ones given.
+    fn requeue(&mut self, queued: &[Identity], playing: Option<Identity>, key_of: impl Fn(&Path) -> PathBuf) {
+        if queued.is_empty() && playing.is_none() {
+            self.queue.clear();
+            self.playing_queued = None;
+            return;
         }
+        let mut found: HashMap<Identity, usize> = HashMap::with_capacity(self.tracks.len());
+        for (index, track) in self.tracks.iter().enumerate() {
+            found.entry((key_of(&track.path), track.start_offset)).or_insert(index);
+        }
+        self.queue = queued.iter().filter_map(|key| found.get(key).copied()).collect();
+        self.playing_queued = playing.and_then(|key| found.get(&key).copied());
     }
 
-    /// Moves to the previous track.
+    /// Moves to the previous track, passing over tracks that failed to open.
     /// Returns true if successful, false if at beginning.
     pub fn previous(&mut self) -> bool {
+        self.step_past_unplayable(Self::step_previous)
+    }
+
+    /// Moves to the previous track, unplayable or not.
+    fn step_previous(&mut self) -> bool {
         if self.tracks.is_empty() {
             return false;
         }
+        if let Some(album) = &self.album
+            && album.at > 0
+        {
+            let index = album.tracks[album.at - 1];
+            return self.move_to(index);
+        }
+        // Back from a queued track to the one playing before the queue
+        if self.playing_queued.take().is_some() {
+            return true;
+        }
 
         if self.current_index > 0 {
             self.current_index -= 1;
@@ -192,13 +896,125 @@ impl Playlist {
         }
     }
 
-    /// Jumps to a specific track index.
+    /// Jumps to the track at a playlist index. Jumping away from the album
+    /// being played ends it.
     pub fn goto(&mut self, index: usize) -> bool {
-        if index < self.len() {
-            self.current_index = index;
-            true
+        if self.album.as_ref().is_some_and(|album| !album.tracks.contains(&index)) {
+            self.album = None;
+        }
+        self.move_to(index)
+    }
+
+    /// Makes the track at a playlist index current, following it on the
+    /// album being played.
+    fn move_to(&mut self, index: usize) -> bool {
+        if index >= self.len() {
+            return false;
+        }
+        self.playing_queued = None;
+        self.current_index = self.position_of(index);
+        if let Some(album) = &mut self.album
+            && let Some(at) = album.tracks.iter().position(|&i| i == index)
+        {
+            album.at = at;
+        }
+        true
+    }
+
+    /// Plays the current track's album in order from the current track,
+    /// then continues the playlist after it. Tracks belong to the album when
+    /// both album and artist match, whichever disc they are on. Returns the
+    /// album's name without a disc suffix, or `None` if the track has no
+    /// album or is alone on it.
+    pub fn play_album(&mut self) -> Option<&str> {
+        let current = self.current_track_index()?;
+        let track = &self.tracks[current];
+        let album = discs::album_key(track.album.as_deref()?).to_string();
+        let mut tracks: Vec<usize> =
+            (0..self.tracks.len()).filter(|&i| discs::same_album(track, &self.tracks[i])).collect();
+        if tracks.len() < 2 {
+            return None;
+        }
+        tracks.sort_by(|&a, &b| {
+            let (a, b) = (&self.tracks[a], &self.tracks[b]);
+            let order =
+                |t: &Track| (discs::disc_of(t).unwrap_or(1), t.track_number.unwrap_or(u32::MAX), t.start_offset);
+            order(a).cmp(&order(b)).then_with(|| a.path.cmp(&b.path))
+        });
+
+        // Coming back to an album already playing keeps the original place to resume
+        let resume = self.album.as_ref().map_or(self.resume_point().unwrap_or(current), |album| album.resume);
+        let at = tracks.iter().position(|&i| i == current).unwrap_or(0);
+        self.album = Some(AlbumScope { name: Some(album), tracks, at, resume, stop_at_end: false });
+        self.album_name()
+    }
+
+    /// Plays the tracks below a directory in path order, from the first,
+    /// then continues the playlist after the current track, like an album.
+    /// Returns false if no track is below it.
+    pub fn play_directory(&mut self, dir: &Path) -> bool {
+        let mut tracks: Vec<usize> = (0..self.tracks.len()).filter(|&i| self.tracks[i].path.starts_with(dir)).collect();
+        tracks.sort_by(|&a, &b| {
+            let (a, b) = (&self.tracks[a], &self.tracks[b]);
+            a.path.cmp(&b.path).then(a.start_offset.cmp(&b.start_offset))
+        });
+        let Some(&first) = tracks.first() else {
+            return false;
+        };
+
+        let resume = self.album.as_ref().map_or(self.resume_point().unwrap_or(first), |album| album.resume);
+        let name = dir.file_name().unwrap_or(dir.as_os_str()).to_string_lossy().into_owned();
+        self.album = Some(AlbumScope { name: Some(name), tracks, at: 0, resume, stop_at_end: false });
+        self.playing_queued = None;
+        self.current_index = self.position_of(first);
+        true
+    }
+
+    /// Plays the given tracks in the order given, such as search results,
+    /// from the one at `from`. Once they are over, playback stops if
+    /// `stop_at_end` is set, or else continues the playlist after the track
+    /// playing before, like an album. Returns false if there is no track at
+    /// `from`.
+    pub fn play_selection(&mut self, tracks: Vec<usize>, from: usize, stop_at_end: bool) -> bool {
+        let Some(&first) = tracks.get(from) else {
+            return false;
+        };
+        if first >= self.len() {
+            return false;
+        }
+        let resume = self.album.as_ref().map_or(self.resume_point().unwrap_or(first), |album| album.resume);
+        self.album = Some(AlbumScope { name: None, tracks, at: from, resume, stop_at_end });
+        self.playing_queued = None;
+        self.current_index = self.position_of(first);
+        true
+    }
+
+    /// Returns the name of the album being played, if any.
+    pub fn album_name(&self) -> Option<&str> {
+        self.album.as_ref().and_then(|album| album.name.as_deref())
+    }
+
+    /// Returns how many tracks the selection being played has, if one is.
+    pub fn selection_len(&self) -> Option<usize> {
+        self.album.as_ref().filter(|album| album.name.is_none()).map(|album| album.tracks.len())
+    }
+
+    /// Returns whether an album or selection is interrupting the playlist.
+    pub fn in_album(&self) -> bool {
+        self.album.is_some()
+    }
+
+    /// Stops playing the album and goes back to the playlist order.
+    pub fn leave_album(&mut self) {
+        self.album = None;
+    }
+
+    /// Returns the position of a track in play order, accounting for shuffle.
+    fn position_of(&self, index: usize) -> usize {
+        if self.shuffle == ShuffleState::On {
+            self.shuffle_indices.iter().position(|&i| i == index).unwrap_or(index)
         } else {
-            false
+            index
         }
     }
 
@@ -215,6 +1031,31 @@ impl Playlist {
         self.repeat.cycle();
     }
 
+    /// Turns shuffle on or off, keeping the current track.
+    pub fn set_shuffle(&mut self, state: ShuffleState) {
+        if self.shuffle != state {
+            self.toggle_shuffle();
+        }
+    }
+
+    /// Turns smart shuffle on with the given weights, or off with `None`.
+    /// Takes effect the next time the order is shuffled.
+    pub fn set_shuffle_weights(&mut self, weights: Option<HashMap<PathBuf, f64>>) {
+        self.shuffle_weights = weights;
+    }
+
+    /// Updates one track's smart shuffle weight, if smart shuffle is on.
+    pub fn set_shuffle_weight(&mut self, path: &Path, weight: f64) {
+        if let Some(weights) = &mut self.shuffle_weights {
+            weights.insert(path.to_path_buf(), weight);
+        }
+    }
+
+    /// Sets the repeat mode.
+    pub fn set_repeat(&mut self, mode: RepeatMode) {
+        self.repeat = mode;
+    }
+
     /// Returns the current shuffle state.
     pub fn shuffle_state(&self) -> ShuffleState {
         self.shuffle
@@ -240,17 +1081,256 @@ impl Playlist {
         &self.tracks
     }
 
-    /// Regenerates shuffle indices using Fisher-Yates algorithm.
-    fn regenerate_shuffle(&mut self) {
-        use rand::seq::SliceRandom;
-        use rand::thread_rng;
+    /// Sorts the tracks, keeping the current track selected. [`SortKey::Random`]
+    /// shuffles them instead.
+    pub fn sort(&mut self, key: SortKey, descending: bool, collation: &Collation) {
+        let current = self.resume_point().map(|i| (self.tracks[i].path.clone(), self.tracks[i].start_offset));
+        // Sorting only moves tracks, so their paths as written tell them apart
+        let as_written = |path: &Path| path.to_path_buf();
+        let queued = self.queue_keys(as_written);
+        let playing = self.playing_queued_key(as_written);
+
+        if key == SortKey::Random {
+            use rand::seq::SliceRandom;
+            self.tracks.shuffle(&mut rand::thread_rng());
+        } else {
+            collation.sort_tracks(&mut self.tracks, key, descending);
+        }
+        self.reindex();
+        self.requeue(&queued, playing, as_written);
+        self.record(Edit::Reorder);
+
+        // Segments share a path, so their start tells them apart
+        if let Some((path, start)) = current {
+            self.current_index = self
+                .tracks
+                .iter()
+                .position(|t| t.path == path && t.start_offset == start)
+                .unwrap_or(0);
+        }
+        if self.shuffle == ShuffleState::On {
+            self.regenerate_shuffle();
+        }
+    }
 
-        let mut indices: Vec<usize> = (0..self.tracks.len()).collect();
-        indices.shuffle(&mut thread_rng());
-        self.shuffle_indices = indices;
+    /// Returns where this playlist was loaded from, in order.
+    pub fn sources(&self) -> &[PlaylistSource] {
+        &self.sources
+    }
 
-        // Ensure current track stays current after shuffle
-        if let Some(pos) = self.shuffle_indices.iter().position(|&i| i == self.current_index) {
+    /// Returns the options the playlist was loaded with.
+    pub fn load_options(&self) -> &LoadOptions {
+        &self.load_options
+    }
+
+    /// Names where the playlist was loaded from for display, joined with
+    /// `+`, or `None` without a source. See [`PlaylistSource::label`].
+    pub fn source_label(&self, home: Option<&Path>) -> Option<String> {
+        let labels: Vec<String> = self.sources.iter().map(|source| source.label(home)).collect();
+        (!labels.is_empty()).then(|| labels.join(" + "))
+    }
+
+    /// Merges a freshly loaded track list from the playlist's source.
+    ///
+    /// Tracks are matched by the canonical paths `fresh` carries; a track
+    /// added since it was loaded is canonicalized here. Surviving tracks keep their order,
+    /// new tracks are inserted after their predecessor in the fresh list, and
+    /// vanished tracks are dropped, except the current track, which is kept so
+    /// playback is not disturbed. Shuffle order is preserved for survivors and
+    /// new tracks are shuffled into the upcoming part.
+    pub fn merge_refreshed(&mut self, fresh: Refreshed) -> RefreshDiff {
+        let Refreshed { tracks: fresh, keys } = fresh;
+        let key_of = |path: &Path| keys.get(path).cloned().unwrap_or_else(|| canonical_key(path));
+        let current_key = self.resume_point().map(|i| key_of(&self.tracks[i].path));
+        let queued = self.queue_keys(key_of);
+        let playing = self.playing_queued_key(key_of);
+        let old_keys: Vec<PathBuf> = self.tracks.iter().map(|t| key_of(&t.path)).collect();
+        let old_set: HashSet<PathBuf> = old_keys.iter().cloned().collect();
+        let fresh_set: HashSet<PathBuf> = fresh.iter().map(|t| key_of(&t.path)).collect();
+
+        // Group new tracks under the nearest preceding fresh track that already existed
+        let mut anchored: HashMap<Option<PathBuf>, Vec<Track>> = HashMap::new();
+        let mut anchor: Option<PathBuf> = None;
+        let mut added = 0;
+        for track in fresh {
+            let key = key_of(&track.path);
+            if old_set.contains(&key) {
+                anchor = Some(key);
+            } else {
+                anchored.entry(anchor.clone()).or_default().push(track);
+                added += 1;
+            }
+        }
+
+        let old_tracks = std::mem::take(&mut self.tracks);
+        let mut old_to_new = vec![None; old_tracks.len()];
+        let mut is_new = Vec::new();
+        let mut removed = 0;
+
+        let mut push_new = |tracks: &mut Vec<Track>, is_new: &mut Vec<bool>, key: Option<PathBuf>| {
+            for track in anchored.remove(&key).unwrap_or_default() {
+                tracks.push(track);
+                is_new.push(true);
+            }
+        };
+
+        push_new(&mut self.tracks, &mut is_new, None);
+        for (i, (track, key)) in old_tracks.into_iter().zip(old_keys).enumerate() {
+            let kept = current_key.as_ref() == Some(&key) || playing.as_ref().is_some_and(|(path, _)| *path == key);
+            if fresh_set.contains(&key) || kept {
+                old_to_new[i] = Some(self.tracks.len());
+                self.tracks.push(track);
+                is_new.push(false);
+                push_new(&mut self.tracks, &mut is_new, Some(key));
+            } else {
+                removed += 1;
+            }
+        }
+
+        // Fix up the current position
+        let current_new = old_to_new
+            .get(self.get_actual_index(self.current_index))
+            .copied()
+            .flatten();
+        if self.shuffle == ShuffleState::On {
+            use rand::Rng;
+
+            let mut order: Vec<usize> = self.shuffle_indices.iter().filter_map(|&i| old_to_new[i]).collect();
+            let position = order.iter().position(|&i| Some(i) == current_new).unwrap_or(0);
+            let mut rng = rand::thread_rng();
+            for (index, _) in is_new.iter().enumerate().filter(|(_, new)| **new) {
+                let at = rng.gen_range(position + 1..=order.len().max(position + 1));
+                order.insert(at.min(order.len()), index);
+            }
+            self.shuffle_indices = order;
+            self.current_index = position;
+        } else {
+            self.current_index = current_new.unwrap_or(0);
+        }
+
+        self.reindex();
+        self.requeue(&queued, playing, key_of);
+        // A file may be back, say on a drive mounted again; the next try tells
+        self.unplayable.clear();
+        RefreshDiff { added, removed }
+    }
+
+    /// Changes the metadata of the track at `index`, keeping its search key current.
+    pub fn update_track(&mut self, index: usize, update: impl FnOnce(&mut Track)) {
+        if let Some(track) = self.tracks.get_mut(index) {
+            update(track);
+            self.search_keys[index] = search_key(track);
+            self.revision += 1;
+        }
+    }
+
+    /// Returns a number that changes whenever tracks are added, removed, reordered, or retagged.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Returns whether the track at `index` matches a lowercased search query
+    /// in its name, artist, or album.
+    pub fn matches(&self, index: usize, query_lower: &str) -> bool {
+        self.search_keys.get(index).is_some_and(|key| key.contains(query_lower))
+    }
+
+    /// Rebuilds the search keys after the track list changed.
+    fn reindex(&mut self) {
+        // Track indices have changed, so an album being played is lost
+        self.album = None;
+        self.search_keys.clear();
+        self.search_keys.extend(self.tracks.iter().map(search_key));
+        self.revision += 1;
+    }
+
+    /// Returns the title the M3U file this playlist was loaded from gives
+    /// itself.
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    /// Returns the preferences the playlist carries in its M3U file.
+    pub fn settings(&self) -> &PlaylistSettings {
+        &self.settings
+    }
+
+    /// Sets the preferences written with the playlist when it is saved.
+    pub fn set_settings(&mut self, settings: PlaylistSettings) {
+        self.settings = settings;
+    }
+
+    /// Returns whether the playlist has edits worth saving since it was
+    /// loaded or last saved.
+    pub fn is_modified(&self) -> bool {
+        self.edits.iter().any(|edit| edit.worth_saving(&self.sources))
+    }
+
+    fn record(&mut self, edit: Edit) {
+        if !self.edits.contains(&edit) {
+            self.edits.push(edit);
+        }
+    }
+
+    /// Gives a track a new title, in memory only. Returns false if there is
+    /// no such track or the title is unchanged.
+    pub fn rename(&mut self, index: usize, title: &str) -> bool {
+        let Some(track) = self.tracks.get_mut(index) else {
+            return false;
+        };
+        if track.title.as_deref() == Some(title) {
+            return false;
+        }
+        track.title = Some(title.to_string());
+        track.renamed = true;
+        self.search_keys[index] = search_key(track);
+        self.revision += 1;
+        self.record(Edit::Rename);
+        true
+    }
+
+    /// Writes the playlist to an M3U file, in its current order, and counts
+    /// it as saved.
+    pub fn save(&mut self, path: &Path) -> Result<(), PlaylistError> {
+        save_m3u(path, &self.tracks, self.title.as_deref(), &self.settings).map_err(|e| PlaylistError::IoError(e.to_string()))?;
+        self.edits.clear();
+        Ok(())
+    }
+
+    /// Returns the report of the directory scan or M3U file this playlist
+    /// was built from.
+    pub fn scan_report(&self) -> Option<&ScanReport> {
+        self.scan_report.as_ref()
+    }
+
+    /// Regenerates shuffle indices.
+    fn regenerate_shuffle(&mut self) {
+        self.regenerate_shuffle_with(&mut rand::thread_rng());
+    }
+
+    /// Regenerates shuffle indices with the given random source: weighted
+    /// for smart shuffle, otherwise uniformly with Fisher-Yates.
+    fn regenerate_shuffle_with(&mut self, rng: &mut impl rand::Rng) {
+        use rand::seq::SliceRandom;
+
+        // Refill in place so large playlists do not reallocate on every reshuffle
+        self.shuffle_indices.clear();
+        let weights: Option<Vec<f64>> = self.shuffle_weights.as_ref().map(|weights| {
+            self.tracks.iter().map(|t| weights.get(&t.path).copied().unwrap_or(1.0)).collect()
+        });
+        match weights {
+            // Without any stats every weight is 1, which is a plain shuffle
+            Some(weights) if weights.iter().any(|w| *w != 1.0) => {
+                self.shuffle_indices.extend(weighted_order(&weights, rng));
+            }
+            _ => {
+                self.shuffle_indices.extend(0..self.tracks.len());
+                self.shuffle_indices.shuffle(rng);
+            }
+        }
+
+        // Ensure current track stays current after shuffle
+        if let Some(pos) = self.shuffle_indices.iter().position(|&i| i == self.current_index) {
             self.shuffle_indices.swap(0, pos);
         }
         self.current_index = 0;
@@ -272,47 +1352,192 @@ impl Default for Playlist {
     }
 }
 
+/// Builds the text a track is searched by. Fields are joined by a newline,
+/// which a typed query never contains, so matches cannot span two fields.
+fn search_key(track: &Track) -> String {
+    format!(
+        "{}\n{}\n{}",
+        track.display_name(),
+        track.artist.as_deref().unwrap_or(""),
+        track.album.as_deref().unwrap_or("")
+    )
+    .to_lowercase()
+}
+
 /// Scans a directory recursively for audio files.
-fn scan_directory<P: AsRef<Path>>(path: P) -> Result<Vec<Track>, PlaylistError> {
-    let path = path.as_ref();
-    let mut tracks = Vec::new();
+///
+/// Errors below the root are collected into the report instead of aborting.
+pub fn scan_directory<P: AsRef<Path>>(
+    path: P,
+    options: &LoadOptions,
+) -> Result<(Vec<Track>, ScanReport), PlaylistError> {
+    scan_directory_watched(path.as_ref(), options, &mut |_| true)
+}
 
-    fn scan_recursive(dir: &Path, tracks: &mut Vec<Track>) -> std::io::Result<()> {
-        if dir.is_dir() {
-            for entry in fs::read_dir(dir)? {
-                let entry = entry?;
-                let path = entry.path();
+/// Scans a directory like [`scan_directory`], calling `watch` with the
+/// number of files looked at after each, and stopping once it returns false.
+fn scan_directory_watched(
+    path: &Path,
+    options: &LoadOptions,
+    watch: &mut dyn FnMut(usize) -> bool,
+) -> Result<(Vec<Track>, ScanReport), PlaylistError> {
+
+    // Only an unreadable root is fatal
+    fs::read_dir(path).map_err(|e| PlaylistError::IoError(e.to_string()))?;
+
+    let started = Instant::now();
+    let mut scan = DirectoryScan {
+        root: path,
+        options,
+        ignores: Vec::new(),
+        visited: HashSet::from([canonical_key(path)]),
+        tracks: Vec::new(),
+        report: ScanReport::default(),
+        watch,
+        stopped: false,
+    };
+    scan.scan(path, 1);
+
+    let DirectoryScan { mut tracks, mut report, .. } = scan;
+    report.walk_time = started.elapsed().saturating_sub(report.tags_time);
 
-                if path.is_dir() {
-                    scan_recursive(&path, tracks)?;
-                } else if is_audio_file(&path) {
-                    tracks.push(extract_metadata(&path));
-                }
+    // Sort alphabetically by path
+    let sorting = Instant::now();
+    tracks.sort_by_cached_key(|track| options.collation.path_key(&track.path));
+    report.sort_time = sorting.elapsed();
+
+    Ok((tracks, report))
+}
+
+/// State carried through a recursive directory scan.
+struct DirectoryScan<'a> {
+    root: &'a Path,
+    options: &'a LoadOptions,
+    /// Patterns from `.jukeignore` files, with the directory each applies below.
+    ignores: Vec<(PathBuf, Vec<String>)>,
+    /// Directories already scanned, so symlink loops are entered only once.
+    visited: HashSet<PathBuf>,
+    tracks: Vec<Track>,
+    report: ScanReport,
+    /// Told of each file looked at; the scan stops once it returns false.
+    watch: &'a mut dyn FnMut(usize) -> bool,
+    stopped: bool,
+}
+
+impl DirectoryScan<'_> {
+    /// Scans a directory at the given depth (the root is depth 1).
+    fn scan(&mut self, dir: &Path, depth: usize) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.report.errors.push((dir.to_path_buf(), e.to_string()));
+                return;
             }
+        };
+
+        // A .jukeignore applies to its directory and everything below it
+        let patterns = filter::read_ignore_file(dir);
+        let pushed = !patterns.is_empty();
+        if pushed {
+            self.ignores.push((dir.to_path_buf(), patterns));
         }
-        Ok(())
-    }
 
-    scan_recursive(path, &mut tracks)
-        .map_err(|e| PlaylistError::IoError(e.to_string()))?;
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    self.report.errors.push((dir.to_path_buf(), e.to_string()));
+                    continue;
+                }
+            };
+            let path = entry.path();
+            let scan_options = &self.options.scan;
 
-    // Sort alphabetically by path
-    tracks.sort_by(|a, b| a.path.cmp(&b.path));
+            if !scan_options.include_hidden && entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let is_symlink = entry.file_type().is_ok_and(|t| t.is_symlink());
+            if is_symlink && !scan_options.follow_symlinks {
+                continue;
+            }
+
+            let is_dir = path.is_dir();
+            let ignored = self.options.filter.is_excluded(&path, is_dir)
+                || self.ignores.iter().any(|(base, patterns)| {
+                    filter::matches_any(patterns, path.strip_prefix(base).unwrap_or(&path), is_dir)
+                });
+            if ignored {
+                continue;
+            }
+
+            if is_dir {
+                let within_depth = scan_options.max_depth == 0 || depth < scan_options.max_depth;
+                if within_depth && self.visited.insert(canonical_key(&path)) {
+                    self.scan(&path, depth + 1);
+                }
+                if self.stopped {
+                    break;
+                }
+            } else {
+                self.report.scanned += 1;
+                if !(self.watch)(self.report.scanned) {
+                    self.stopped = true;
+                    break;
+                }
+                if self.options.filter.is_audio_file(&path) {
+                    // Catch permission errors that metadata extraction would swallow
+                    if !self.options.fast_scan
+                        && let Err(e) = File::open(&path)
+                    {
+                        self.report.errors.push((path, e.to_string()));
+                        continue;
+                    }
+                    let reading = Instant::now();
+                    let mut track = probe(&path, self.options.fast_scan);
+                    if self.options.infer_tags_from_path {
+                        infer_tags_from_path(&mut track, self.root);
+                    }
+                    // A sidecar splits the file into virtual tracks
+                    let segments = segments::load(&path).unwrap_or_else(|e| {
+                        self.report.errors.push((segments::sidecar_path(&path), e.to_string()));
+                        None
+                    });
+                    self.report.tags_time += reading.elapsed();
+                    match segments {
+                        Some(segments) if !segments.is_empty() => {
+                            let virtual_tracks = segments::expand(track, &segments);
+                            self.report.added += virtual_tracks.len();
+                            self.tracks.extend(virtual_tracks);
+                        }
+                        _ => {
+                            self.tracks.push(track);
+                            self.report.added += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        if pushed {
+            self.ignores.pop();
+        }
+    }
+}
 
-    Ok(tracks)
+/// Returns the canonical form of a path for identity comparisons.
+pub fn canonical_key(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
 }
 
-/// Checks if a file is an audio file based on extension.
-fn is_audio_file(path: &Path) -> bool {
-    path.extension()
-        .and_then(|ext| ext.to_str())
-        .map(|ext| matches!(ext.to_lowercase().as_str(), "mp3" | "flac" | "ogg"))
-        .unwrap_or(false)
+/// Extracts metadata from an audio file, unless `fast` is set.
+fn probe(path: &Path, fast: bool) -> Track {
+    if fast { Track::new(path.to_path_buf()) } else { extract_metadata(path) }
 }
 
 /// Extracts metadata from an audio file using lofty.
-fn extract_metadata(path: &Path) -> Track {
+pub fn extract_metadata(path: &Path) -> Track {
     let mut track = Track::new(path.to_path_buf());
+    track.probed = true;
 
     // Try to read metadata, but don't fail if we can't
     if let Ok(tagged_file) = lofty::read_from_path(path) {
@@ -327,6 +1552,16 @@ fn extract_metadata(path: &Path) -> Track {
 
             // Extract album
             track.album = tag.album().map(|s| s.to_string());
+            track.year = tag.year();
+
+            // Extract track and disc numbers
+            track.track_number = tag.track();
+            track.disc_number = tag.disk();
+
+            // Extract ReplayGain track gain ("-6.50 dB")
+            track.replay_gain = tag
+                .get_string(&ItemKey::ReplayGainTrackGain)
+                .and_then(parse_gain);
         }
 
         // Extract duration from properties
@@ -339,58 +1574,709 @@ fn extract_metadata(path: &Path) -> Track {
     track
 }
 
-/// Parses an M3U playlist file.
-fn parse_m3u<P: AsRef<Path>>(path: P) -> Result<Vec<Track>, PlaylistError> {
+/// Fills missing artist, album, and year from the track's parent directories.
+///
+/// Understands `Artist/Album/track`, `Artist - Album (Year)/track`,
+/// `Artist/Year - Album/track`, and disc subfolders such as `Album/CD1/track`.
+/// Directories at or above `root` are never used, so nothing is inferred
+/// for a track outside it.
+fn infer_tags_from_path(track: &mut Track, root: &Path) {
+    if track.artist.is_some() && track.album.is_some() && track.year.is_some() {
+        return;
+    }
+
+    let Ok(relative) = track.path.strip_prefix(root) else {
+        return;
+    };
+    let mut dirs: Vec<String> = relative
+        .parent()
+        .map(|p| {
+            p.components()
+                .filter_map(|c| match c {
+                    std::path::Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+                    _ => None,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    // Disc folders belong to the album above them
+    if dirs.last().is_some_and(|d| is_disc_folder(d)) {
+        dirs.pop();
+    }
+
+    let Some(album_dir) = dirs.pop() else {
+        return;
+    };
+    let (dir_artist, album, year) = parse_album_folder(&album_dir);
+    let artist = dir_artist.or_else(|| dirs.pop());
+
+    if track.album.is_none() && !album.is_empty() {
+        track.album = Some(album);
+        track.inferred.album = true;
+    }
+    if track.artist.is_none()
+        && let Some(artist) = artist.filter(|a| !a.is_empty())
+    {
+        track.artist = Some(artist);
+        track.inferred.artist = true;
+    }
+    if track.year.is_none() && year.is_some() {
+        track.year = year;
+        track.inferred.year = true;
+    }
+}
+
+/// Orders indices by weighted sampling without replacement: heavier items
+/// tend to come first, but every item with a positive weight can come anywhere.
+///
+/// Each item gets the key `ln(u) / weight` for a uniform `u` and items are
+/// sorted by key, largest first (Efraimidis and Spirakis).
+fn weighted_order(weights: &[f64], rng: &mut impl rand::Rng) -> Vec<usize> {
+    let mut keyed: Vec<(f64, usize)> = weights
+        .iter()
+        .enumerate()
+        .map(|(i, weight)| {
+            let u: f64 = rng.r#gen::<f64>().max(f64::MIN_POSITIVE);
+            (u.ln() / weight.max(f64::MIN_POSITIVE), i)
+        })
+        .collect();
+    keyed.sort_by(|a, b| b.0.total_cmp(&a.0));
+    keyed.into_iter().map(|(_, i)| i).collect()
+}
+
+/// Returns true for folder names like "CD1", "Disc 2", or "disk_03".
+fn is_disc_folder(name: &str) -> bool {
+    let lower = name.to_lowercase();
+    let rest = ["cd", "disc", "disk"]
+        .iter()
+        .find_map(|prefix| lower.strip_prefix(prefix));
+    match rest {
+        Some(rest) => {
+            let digits = rest.trim_start_matches([' ', '_', '-', '.']);
+            !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+        }
+        None => false,
+    }
+}
+
+/// Splits an album folder name into (artist, album, year).
+///
+/// Handles "Artist - Album (Year)", "Album [Year]", "Year - Album", and "(Year) Album".
+fn parse_album_folder(name: &str) -> (Option<String>, String, Option<u32>) {
+    let mut rest = name.trim();
+    let mut year = None;
+
+    // Trailing "(1999)" or "[1999]"
+    for (open, close) in [('(', ')'), ('[', ']')] {
+        if let Some((head, inner)) = rest.strip_suffix(close).and_then(|r| r.rsplit_once(open))
+            && let Some(y) = parse_year(inner)
+        {
+            year = Some(y);
+            rest = head.trim_end();
+            break;
+        }
+    }
+
+    // Leading "1999 - " or "(1999) "
+    if year.is_none()
+        && let Some((head, tail)) = rest.split_once(" - ")
+        && let Some(y) = parse_year(head)
+    {
+        year = Some(y);
+        rest = tail.trim();
+    }
+    if year.is_none()
+        && let Some((head, tail)) = rest.strip_prefix('(').and_then(|r| r.split_once(')'))
+        && let Some(y) = parse_year(head)
+    {
+        year = Some(y);
+        rest = tail.trim();
+    }
+
+    match rest.split_once(" - ") {
+        Some((artist, album)) => (Some(artist.trim().to_string()), album.trim().to_string(), year),
+        None => (None, rest.to_string(), year),
+    }
+}
+
+/// Parses a plausible four-digit release year.
+fn parse_year(text: &str) -> Option<u32> {
+    let text = text.trim();
+    if text.len() != 4 || !text.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    text.parse().ok().filter(|y| (1900..=2100).contains(y))
+}
+
+/// Parses a ReplayGain value such as "-6.50 dB" into decibels.
+fn parse_gain(value: &str) -> Option<f32> {
+    value
+        .trim()
+        .trim_end_matches("dB")
+        .trim_end_matches("db")
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Checks if a file is a playlist based on extension.
+pub fn is_playlist_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| matches!(ext.to_lowercase().as_str(), "m3u" | "m3u8"))
+        .unwrap_or(false)
+}
+
+/// Parses an M3U playlist file, expanding any nested playlists in place.
+fn parse_m3u<P: AsRef<Path>>(path: P, options: &LoadOptions) -> Result<Vec<Track>, PlaylistError> {
+    parse_m3u_report(path, options).map(|(tracks, _, _)| tracks)
+}
+
+/// Parses an M3U playlist file with its title, reporting nested playlists
+/// that could not be expanded as errors.
+fn parse_m3u_report<P: AsRef<Path>>(
+    path: P,
+    options: &LoadOptions,
+) -> Result<(Vec<Track>, M3u, ScanReport), PlaylistError> {
     let path = path.as_ref();
-    let file = File::open(path).map_err(|e| PlaylistError::IoError(e.to_string()))?;
-    let reader = BufReader::new(file);
+    let mut visited = HashSet::new();
+    if let Ok(canonical) = fs::canonicalize(path) {
+        visited.insert(canonical);
+    }
+    let mut errors = Vec::new();
+    let (tracks, mut m3u) = parse_m3u_nested(path, options, 0, &mut visited, &mut errors)?;
+    errors.extend(m3u.warnings.drain(..).map(|warning| (path.to_path_buf(), warning)));
+    let report = ScanReport { scanned: tracks.len(), added: tracks.len(), errors, ..Default::default() };
+    Ok((tracks, m3u, report))
+}
 
-    let mut tracks = Vec::new();
-    let mut current_extinf: Option<(Duration, String)> = None;
+/// Parses one M3U file, returning its tracks and the rest of what it says;
+/// `visited` holds the playlists currently being expanded.
+fn parse_m3u_nested(
+    path: &Path,
+    options: &LoadOptions,
+    depth: usize,
+    visited: &mut HashSet<PathBuf>,
+    errors: &mut Vec<(PathBuf, String)>,
+) -> Result<(Vec<Track>, M3u), PlaylistError> {
+    let data = fs::read(path).map_err(|e| PlaylistError::IoError(e.to_string()))?;
     let playlist_dir = path.parent().unwrap_or_else(|| Path::new("."));
+    Ok(read_m3u_tracks(&data, playlist_dir, options, depth, visited, errors))
+}
 
-    for line in reader.lines() {
-        let line = line.map_err(|e| PlaylistError::IoError(e.to_string()))?;
-        let line = line.trim();
+/// Reads the tracks of M3U data whose relative entries are in
+/// `playlist_dir`, expanding nested playlists.
+fn read_m3u_tracks(
+    data: &[u8],
+    playlist_dir: &Path,
+    options: &LoadOptions,
+    depth: usize,
+    visited: &mut HashSet<PathBuf>,
+    errors: &mut Vec<(PathBuf, String)>,
+) -> (Vec<Track>, M3u) {
+    let mut m3u = read_m3u(data);
+    let mut tracks = Vec::new();
 
-        if line.is_empty() {
+    for (line, extinf, bytes) in std::mem::take(&mut m3u.entries) {
+        // This is a file path, possibly written on another platform
+        let (kind, entry) = normalize_m3u_entry(&line, cfg!(windows));
+        let track_path = match bytes.and_then(|bytes| native_entry(&bytes, playlist_dir)) {
+            Some(path) => path,
+            None if kind == M3uPathKind::Relative => playlist_dir.join(entry),
+            None => PathBuf::from(entry),
+        };
+
+        // Nested playlists are expanded relative to their own directory
+        if is_playlist_file(&track_path) {
+            tracks.extend(expand_nested_playlist(&track_path, options, depth + 1, visited, errors));
             continue;
         }
 
-        if line.starts_with("#EXTINF:") {
-            // Parse #EXTINF:duration,title
-            if let Some(content) = line.strip_prefix("#EXTINF:") {
-                if let Some((duration_str, title)) = content.split_once(',') {
-                    let duration = duration_str
-                        .parse::<i64>()
-                        .ok()
-                        .map(|secs| Duration::from_secs(secs.max(0) as u64));
+        // Extract metadata from the file
+        let mut track = probe(&track_path, options.fast_scan);
 
-                    current_extinf = duration.map(|d| (d, title.to_string()));
-                }
+        // Apply or override with EXTINF metadata if present
+        if let Some(extinf) = extinf {
+            apply_extinf(&mut track, extinf);
+        }
+
+        // Entries are read from the playlist's folder down, never above it
+        if options.infer_tags_from_path {
+            infer_tags_from_path(&mut track, playlist_dir);
+        }
+
+        tracks.push(track);
+    }
+
+    (tracks, m3u)
+}
+
+/// Applies an entry's `#EXTINF`, `#EXTART`, and `#EXTALB` lines to its
+/// track. Artist and album only fill in what the tags leave out, and are
+/// marked as not coming from tags.
+fn apply_extinf(track: &mut Track, extinf: ExtInf) {
+    if extinf.duration.is_some() {
+        track.duration = extinf.duration;
+    }
+    if track.artist.is_none() && extinf.artist.is_some() {
+        track.artist = extinf.artist;
+        track.inferred.artist = true;
+    }
+    if track.album.is_none() && extinf.album.is_some() {
+        track.album = extinf.album;
+        track.inferred.album = true;
+    }
+    let Some(text) = extinf.title else {
+        return;
+    };
+    // "Artist - Title", split on the first " - " so the title keeps any
+    // of its own, unless the artist is known to be someone else
+    track.title = Some(match text.split_once(" - ") {
+        Some((artist, title)) if track.artist.as_deref().is_none_or(|known| known == artist) => {
+            if track.artist.is_none() {
+                track.artist = Some(artist.to_string());
+                track.inferred.artist = true;
             }
-        } else if !line.starts_with('#') {
-            // This is a file path
-            let track_path = if Path::new(line).is_absolute() {
-                PathBuf::from(line)
-            } else {
-                playlist_dir.join(line)
+            title.to_string()
+        }
+        _ => text,
+    });
+}
+
+/// Metadata from an entry's `#EXTINF` line and the directives after it.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct ExtInf {
+    duration: Option<Duration>,
+    /// Everything after the comma, often "Artist - Title".
+    title: Option<String>,
+    /// From `#EXTART`.
+    artist: Option<String>,
+    /// From `#EXTALB`.
+    album: Option<String>,
+}
+
+/// Preferences a playlist carries in `#JUKE:` lines of its M3U file, which
+/// other players, and juke versions before them, skip as comments.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PlaylistSettings {
+    pub shuffle: Option<ShuffleState>,
+    pub repeat: Option<RepeatMode>,
+    /// Volume in percent.
+    pub volume: Option<u8>,
+    /// The order the tracks were sorted in, and whether it was descending.
+    pub sort: Option<(SortKey, bool)>,
+}
+
+impl PlaylistSettings {
+    /// Whether the playlist carries no preferences.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Returns the `#JUKE:` lines recording the preferences, without line
+    /// breaks.
+    fn lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        if let Some(shuffle) = self.shuffle {
+            lines.push(format!("#JUKE:shuffle={}", if shuffle == ShuffleState::On { "on" } else { "off" }));
+        }
+        if let Some(repeat) = self.repeat {
+            let name = match repeat {
+                RepeatMode::Off => "off",
+                RepeatMode::All => "all",
+                RepeatMode::Single => "single",
             };
+            lines.push(format!("#JUKE:repeat={}", name));
+        }
+        if let Some(volume) = self.volume {
+            lines.push(format!("#JUKE:volume={}", volume.min(100)));
+        }
+        // A one-off shuffle is not an order to keep
+        if let Some((key, descending)) = self.sort.filter(|(key, _)| *key != SortKey::Random) {
+            lines.push(format!("#JUKE:sort={} {}", key.name(), if descending { "descending" } else { "ascending" }));
+        }
+        lines
+    }
 
-            // Extract metadata from the file
-            let mut track = extract_metadata(&track_path);
+    /// Reads the part of a `#JUKE:` line after the colon, like
+    /// `repeat=all`, refusing anything but the values juke writes.
+    fn read(&mut self, content: &str) -> Result<(), String> {
+        let (name, value) = content.split_once('=').ok_or_else(|| format!("#JUKE:{} has no value", content))?;
+        let invalid = || format!("Invalid playlist setting {}={:?}", name, value);
+        match name {
+            "shuffle" => {
+                self.shuffle = Some(match value {
+                    "on" => ShuffleState::On,
+                    "off" => ShuffleState::Off,
+                    _ => return Err(invalid()),
+                })
+            }
+            "repeat" => {
+                self.repeat = Some(match value {
+                    "off" => RepeatMode::Off,
+                    "all" => RepeatMode::All,
+                    "single" => RepeatMode::Single,
+                    _ => return Err(invalid()),
+                })
+            }
+            "volume" => {
+                let volume = value.parse::<u8>().ok().filter(|volume| *volume <= 100).ok_or_else(invalid)?;
+                self.volume = Some(volume);
+            }
+            "sort" => {
+                let (key, direction) = value.split_once(' ').unwrap_or((value, "ascending"));
+                let key = SortKey::from_name(key).filter(|key| *key != SortKey::Random).ok_or_else(invalid)?;
+                let descending = match direction {
+                    "ascending" => false,
+                    "descending" => true,
+                    _ => return Err(invalid()),
+                };
+                self.sort = Some((key, descending));
+            }
+            _ => return Err(format!("Unknown playlist setting {:?}", name)),
+        }
+        Ok(())
+    }
+}
+
+/// The lines of an M3U file that juke reads.
+#[derive(Debug, Default)]
+struct M3u {
+    /// From `#PLAYLIST`.
+    title: Option<String>,
+    /// From `#JUKE:` lines.
+    settings: PlaylistSettings,
+    /// Why `#JUKE:` lines were skipped.
+    warnings: Vec<String>,
+    /// Each entry with the `#EXTINF` line and directives preceding it, and
+    /// the entry's bytes when they are not UTF-8.
+    entries: Vec<(String, Option<ExtInf>, Option<Vec<u8>>)>,
+}
+
+/// Splits M3U data into its title and entries.
+///
+/// Accepts any bytes: lines that are not UTF-8 are read as Latin-1, and null
+/// bytes and a leading byte order mark are dropped.
+fn read_m3u(data: &[u8]) -> M3u {
+    let mut m3u = M3u::default();
+    let mut current_extinf: Option<ExtInf> = None;
+    let text = |value: &str| Some(value.trim().to_string()).filter(|value| !value.is_empty());
 
-            // Apply or override with EXTINF metadata if present
-            if let Some((duration, title)) = current_extinf.take() {
-                track.duration = Some(duration);
-                track.title = Some(title);
+    for raw in data.split(|&b| b == b'\n') {
+        let line = decode_m3u_line(raw);
+        let line = line.trim().trim_start_matches('\u{feff}').trim();
+
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(content) = line.strip_prefix("#EXTINF:") {
+            // Directives read before it belong to the same entry
+            let mut extinf = parse_extinf(content);
+            if let Some(earlier) = current_extinf.take() {
+                extinf.artist = earlier.artist;
+                extinf.album = earlier.album;
             }
+            current_extinf = Some(extinf);
+        } else if let Some(content) = line.strip_prefix("#EXTART:") {
+            current_extinf.get_or_insert_default().artist = text(content);
+        } else if let Some(content) = line.strip_prefix("#EXTALB:") {
+            current_extinf.get_or_insert_default().album = text(content);
+        } else if let Some(content) = line.strip_prefix("#PLAYLIST:") {
+            m3u.title = text(content);
+        } else if let Some(content) = line.strip_prefix("#JUKE:") {
+            if let Err(warning) = m3u.settings.read(content.trim()) {
+                m3u.warnings.push(warning);
+            }
+        } else if !line.starts_with('#') {
+            // Unix file names may be any bytes, which the writer keeps as they are
+            let bytes = std::str::from_utf8(raw).is_err().then(|| {
+                let bytes: Vec<u8> = raw.iter().copied().filter(|&b| b != 0).collect();
+                bytes.trim_ascii().to_vec()
+            });
+            m3u.entries.push((line.to_string(), current_extinf.take(), bytes));
+        }
+    }
 
-            tracks.push(track);
+    m3u
+}
+
+/// Decodes one line as UTF-8, falling back to Latin-1, without null bytes.
+fn decode_m3u_line(raw: &[u8]) -> String {
+    let text = match std::str::from_utf8(raw) {
+        Ok(text) => text.to_string(),
+        // Every byte is a valid Latin-1 character
+        Err(_) => raw.iter().map(|&b| b as char).collect(),
+    };
+    text.replace('\0', "")
+}
+
+/// Parses the part of an `#EXTINF` line after the colon.
+///
+/// Handles `213,Title`, fractional `213.4,Title`, duration-only `213`, and
+/// attributes such as `-1 tvg-id="x" group-title="A, B",Title`. A negative
+/// duration means unknown.
+fn parse_extinf(content: &str) -> ExtInf {
+    // The title starts at the first comma outside quoted attribute values
+    let mut in_quotes = false;
+    let comma = content.char_indices().find_map(|(i, c)| match c {
+        '"' => {
+            in_quotes = !in_quotes;
+            None
         }
+        ',' if !in_quotes => Some(i),
+        _ => None,
+    });
+    let (info, title) = match comma {
+        Some(i) => (&content[..i], Some(content[i + 1..].trim())),
+        None => (content, None),
+    };
+
+    let duration = info
+        .split_whitespace()
+        .next()
+        .and_then(|secs| secs.parse::<f64>().ok())
+        .filter(|secs| *secs >= 0.0)
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok());
+
+    ExtInf {
+        duration,
+        title: title.filter(|t| !t.is_empty()).map(str::to_string),
+        ..Default::default()
+    }
+}
+
+/// Kinds of path found in M3U entries, regardless of the platform that wrote them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum M3uPathKind {
+    /// Relative to the playlist's directory: `music/a.mp3`, `..\Music\a.mp3`.
+    Relative,
+    /// Starting at a root: `/music/a.mp3`, `\Music\a.mp3`.
+    Rooted,
+    /// Starting with a drive letter: `D:\Music\a.mp3`.
+    Drive,
+    /// A network share: `\\NAS\share\a.flac`.
+    Unc,
+}
+
+/// Classifies an M3U entry by its Windows or POSIX path syntax.
+fn classify_m3u_entry(entry: &str) -> M3uPathKind {
+    let bytes = entry.as_bytes();
+    let is_sep = |b: Option<&u8>| matches!(b, Some(b'\\' | b'/'));
+
+    // A drive letter needs a separator after it, or `a:song.mp3` would be one
+    if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' && is_sep(bytes.get(2)) {
+        M3uPathKind::Drive
+    } else if is_sep(bytes.first()) && is_sep(bytes.get(1)) {
+        M3uPathKind::Unc
+    } else if is_sep(bytes.first()) {
+        M3uPathKind::Rooted
+    } else {
+        M3uPathKind::Relative
     }
+}
+
+/// Returns the file an entry that is not UTF-8 names byte for byte, when
+/// there is one. Otherwise the entry is read as Latin-1.
+#[cfg(unix)]
+fn native_entry(bytes: &[u8], playlist_dir: &Path) -> Option<PathBuf> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = playlist_dir.join(std::ffi::OsStr::from_bytes(bytes));
+    path.exists().then_some(path)
+}
+
+#[cfg(not(unix))]
+fn native_entry(_bytes: &[u8], _playlist_dir: &Path) -> Option<PathBuf> {
+    None
+}
 
-    Ok(tracks)
+/// Converts an M3U entry to the separators of the running platform.
+///
+/// Windows accepts every form. Elsewhere, backslashes in relative and rooted
+/// entries become slashes; drive and UNC paths cannot be resolved and are left
+/// as written.
+fn normalize_m3u_entry(entry: &str, windows: bool) -> (M3uPathKind, String) {
+    let kind = classify_m3u_entry(entry);
+    let entry = if windows {
+        entry.replace('/', "\\")
+    } else if matches!(kind, M3uPathKind::Relative | M3uPathKind::Rooted) {
+        entry.replace('\\', "/")
+    } else {
+        entry.to_string()
+    };
+    (kind, entry)
+}
+
+/// Writes tracks to an M3U playlist with the platform's native separators.
+///
+/// Tracks below the playlist's directory are written relative to it. Each
+/// track known by more than its file gets an `#EXTINF` line with
+/// "Artist - Title", and `#EXTART` and `#EXTALB` lines, so reading the file
+/// back gives the same fields. Preferences go in `#JUKE:` lines.
+///
+/// Tag values are kept to one line. Paths are written as the platform names
+/// them, bytes and all on Unix. When a path cannot be written so that it
+/// reads back the same, nothing is written and the error lists every such
+/// track.
+pub fn save_m3u(
+    path: &Path,
+    tracks: &[Track],
+    title: Option<&str>,
+    settings: &PlaylistSettings,
+) -> std::io::Result<()> {
+    let problems = m3u_problems(path, tracks);
+    if !problems.is_empty() {
+        let listed: Vec<String> =
+            problems.iter().map(|(track, problem)| format!("{} ({})", track.display(), problem)).collect();
+        let message = format!("Cannot write these tracks to an M3U file: {}", listed.join(", "));
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, message));
+    }
+
+    let playlist_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut contents = String::from("#EXTM3U\n");
+    if let Some(title) = title {
+        contents.push_str(&format!("#PLAYLIST:{}\n", one_line(title)));
+    }
+    for line in settings.lines() {
+        contents.push_str(&line);
+        contents.push('\n');
+    }
+    let mut contents = contents.into_bytes();
+
+    for track in tracks {
+        if track.duration.is_some() || track.title.is_some() {
+            // A negative duration means unknown
+            let seconds = track.duration.map_or(-1, |duration| duration.as_secs() as i64);
+            let name = match &track.artist {
+                Some(artist) => format!("{} - {}", artist, track.display_name()),
+                None => track.display_name(),
+            };
+            contents.extend_from_slice(format!("#EXTINF:{},{}\n", seconds, one_line(&name)).as_bytes());
+        }
+        if let Some(artist) = &track.artist {
+            contents.extend_from_slice(format!("#EXTART:{}\n", one_line(artist)).as_bytes());
+        }
+        if let Some(album) = &track.album {
+            contents.extend_from_slice(format!("#EXTALB:{}\n", one_line(album)).as_bytes());
+        }
+        contents.extend_from_slice(&entry_bytes(&m3u_entry(&track.path, playlist_dir)));
+        contents.push(b'\n');
+    }
+
+    fs::write(path, contents)
+}
+
+/// Returns each track whose path cannot be written to the M3U file at
+/// `path` so that it reads back the same, and why.
+pub fn m3u_problems(path: &Path, tracks: &[Track]) -> Vec<(PathBuf, &'static str)> {
+    let playlist_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let m3u8 = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("m3u8"));
+    let problem = |entry: &Path| {
+        let Some(text) = entry.to_str() else {
+            // Bytes that are not UTF-8 only mean something to Unix, and not in .m3u8 files
+            return (!cfg!(unix) || m3u8).then_some("its path is not UTF-8");
+        };
+        if text.contains(['\n', '\r']) {
+            Some("its path has a line break")
+        } else if text.trim() != text {
+            Some("its path starts or ends with a space")
+        } else {
+            None
+        }
+    };
+    tracks
+        .iter()
+        .filter_map(|track| problem(&m3u_entry(&track.path, playlist_dir)).map(|why| (track.path.clone(), why)))
+        .collect()
+}
+
+/// Returns the entry a track's path is written as: relative to the
+/// playlist's directory when below it, and never read as a comment.
+fn m3u_entry(track: &Path, playlist_dir: &Path) -> PathBuf {
+    match track.strip_prefix(playlist_dir) {
+        Ok(entry) if entry.as_os_str().as_encoded_bytes().starts_with(b"#") => Path::new(".").join(entry),
+        Ok(entry) => entry.to_path_buf(),
+        Err(_) => track.to_path_buf(),
+    }
+}
+
+/// Returns the bytes of an entry as the platform names it.
+#[cfg(unix)]
+fn entry_bytes(entry: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    entry.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn entry_bytes(entry: &Path) -> Vec<u8> {
+    native_separators(&entry.to_string_lossy(), cfg!(windows)).into_bytes()
+}
+
+/// Keeps a tag value on its line of an M3U file, without control
+/// characters.
+fn one_line(value: &str) -> String {
+    value
+        .chars()
+        .filter_map(|c| match c {
+            '\r' | '\n' | '\t' => Some(' '),
+            c if c.is_control() => None,
+            c => Some(c),
+        })
+        .collect()
+}
+
+/// Replaces foreign separators with the platform's own.
+#[cfg_attr(unix, allow(dead_code))]
+fn native_separators(entry: &str, windows: bool) -> String {
+    if windows {
+        entry.replace('/', "\\")
+    } else {
+        entry.to_string()
+    }
+}
+
+/// Expands a playlist referenced from another playlist.
+///
+/// Problems are added to `errors` so the rest of the parent still loads.
+fn expand_nested_playlist(
+    path: &Path,
+    options: &LoadOptions,
+    depth: usize,
+    visited: &mut HashSet<PathBuf>,
+    errors: &mut Vec<(PathBuf, String)>,
+) -> Vec<Track> {
+    let mut skip = |error: String| {
+        errors.push((path.to_path_buf(), error));
+        Vec::new()
+    };
+    let Ok(canonical) = fs::canonicalize(path) else {
+        return skip("Nested playlist not found".to_string());
+    };
+
+    if visited.contains(&canonical) {
+        return skip("Skipping playlist cycle".to_string());
+    }
+    if depth > MAX_PLAYLIST_DEPTH {
+        return skip(format!("Skipping playlist nested more than {} levels deep", MAX_PLAYLIST_DEPTH));
+    }
+
+    visited.insert(canonical.clone());
+    // Only the outermost playlist's title and preferences count
+    let result = parse_m3u_nested(path, options, depth, visited, errors).map(|(tracks, _)| tracks);
+    visited.remove(&canonical);
+
+    match result {
+        Ok(tracks) => {
+            if tracks.is_empty() {
+                errors.push((path.to_path_buf(), "Nested playlist is empty".to_string()));
+            }
+            tracks
+        }
+        Err(e) => {
+            errors.push((path.to_path_buf(), format!("Could not read nested playlist: {}", e)));
+            Vec::new()
+        }
+    }
 }
 
 /// Errors that can occur during playlist operations.
@@ -424,6 +2310,32 @@ mod tests {
         assert_eq!(track.display_name(), "My Song");
     }
 
+    #[test]
+    fn test_track_formatted_name() {
+        let mut track = Track::new(PathBuf::from("/music/song.mp3"));
+        track.title = Some("My Song".to_string());
+        let on = NameOptions { show_track_numbers: true };
+        let off = NameOptions::default();
+
+        // No number known
+        assert_eq!(track.formatted_name(&on), "My Song");
+
+        track.track_number = Some(4);
+        assert_eq!(track.formatted_name(&on), "04 · My Song");
+        assert_eq!(track.formatted_name(&off), "My Song");
+
+        track.disc_number = Some(1);
+        assert_eq!(track.formatted_name(&on), "1-04 · My Song");
+
+        // Disc alone is not enough
+        track.track_number = None;
+        assert_eq!(track.formatted_name(&on), "My Song");
+
+        // display_name is never decorated
+        track.track_number = Some(4);
+        assert_eq!(track.display_name(), "My Song");
+    }
+
     #[test]
     fn test_shuffle_state_toggle() {
         let mut state = ShuffleState::Off;
@@ -495,14 +2407,1007 @@ mod tests {
         assert_eq!(playlist.current_index(), 0);
     }
 
+    #[test]
+    fn test_sort_keeps_current_track() {
+        let mut playlist = Playlist::new();
+        for (file, artist) in [("1.mp3", "Zappa"), ("2.mp3", "The Beatles"), ("3.mp3", "Élodie")] {
+            let mut track = Track::new(PathBuf::from(file));
+            track.artist = Some(artist.to_string());
+            playlist.add_track(track);
+        }
+        playlist.goto(2);
+
+        playlist.sort(SortKey::Artist, false, &Collation::default());
+        let artists: Vec<_> = playlist.tracks().iter().map(|t| t.artist.as_deref().unwrap()).collect();
+        assert_eq!(artists, ["The Beatles", "Élodie", "Zappa"]);
+        assert_eq!(playlist.current_track().unwrap().path, PathBuf::from("3.mp3"));
+
+        playlist.sort(SortKey::Artist, true, &Collation::default());
+        assert_eq!(playlist.tracks()[0].artist.as_deref(), Some("Zappa"));
+        playlist.sort(SortKey::Random, false, &Collation::default());
+        assert_eq!(playlist.current_track().unwrap().path, PathBuf::from("3.mp3"));
+    }
+
+    #[test]
+    fn test_rename_survives_sorting_and_saving() {
+        let dir = std::env::temp_dir().join(format!("juke_rename_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut playlist = playlist_of(&["b.mp3", "a.mp3"]);
+        assert!(!playlist.is_modified());
+        assert!(playlist.rename(0, "Better Name"));
+        assert!(!playlist.rename(0, "Better Name"));
+        assert!(playlist.is_modified());
+        assert!(playlist.matches(0, "better"));
+
+        playlist.sort(SortKey::Path, false, &Collation::default());
+        let renamed = &playlist.tracks()[1];
+        assert_eq!((renamed.title.as_deref(), renamed.renamed), (Some("Better Name"), true));
+        assert!(playlist.matches(1, "better") && !playlist.matches(0, "better"));
+
+        let path = dir.join("renamed.m3u");
+        playlist.save(&path).unwrap();
+        assert!(!playlist.is_modified());
+        let saved = fs::read_to_string(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(saved.contains("#EXTINF:-1,Better Name\nb.mp3\n"), "{}", saved);
+    }
+
+    #[test]
+    fn test_edits_worth_saving_depend_on_source() {
+        let mut playlist = playlist_of(&["b.mp3", "a.mp3", "c.mp3"]);
+        playlist.sources = vec![PlaylistSource::Directory(PathBuf::from("/music"))];
+        playlist.sort(SortKey::Path, false, &Collation::default());
+        playlist.remove(2);
+        assert!(!playlist.is_modified());
+        playlist.rename(0, "Intro");
+        assert!(playlist.is_modified());
+
+        let mut playlist = playlist_of(&["b.mp3", "a.mp3"]);
+        playlist.sources = vec![PlaylistSource::M3u(PathBuf::from("/lists/mix.m3u"))];
+        playlist.remove(1);
+        assert!(playlist.is_modified());
+
+        // Without a source, as when added from the command line, any edit counts
+        let mut playlist = playlist_of(&["b.mp3", "a.mp3"]);
+        playlist.sort(SortKey::Path, false, &Collation::default());
+        assert!(playlist.is_modified());
+
+        // A folder with a playlist file has more to lose than folders alone
+        let folders = [PlaylistSource::Directory(PathBuf::from("/a")), PlaylistSource::Directory(PathBuf::from("/b"))];
+        assert!(!Edit::Remove.worth_saving(&folders));
+        assert!(Edit::Remove.worth_saving(&[folders[0].clone(), PlaylistSource::Stdin]));
+    }
+
+    #[test]
+    fn test_sources_from_stdin_and_appended() {
+        let options = LoadOptions { fast_scan: true, ..LoadOptions::default() };
+        let piped = "music/a.mp3\n\n/srv/b.mp3\n";
+        let mut playlist = Playlist::from_reader(piped.as_bytes(), &options).unwrap();
+        let paths: Vec<_> = playlist.tracks().iter().map(|t| t.path.clone()).collect();
+        assert_eq!(paths, [PathBuf::from("music/a.mp3"), PathBuf::from("/srv/b.mp3")]);
+        assert_eq!(playlist.sources(), [PlaylistSource::Stdin]);
+        assert!(!playlist.sources()[0].can_reload());
+
+        let mut party = playlist_of(&["c.mp3"]);
+        party.sources = vec![PlaylistSource::M3u(PathBuf::from("/home/ana/party.m3u"))];
+        party.title = Some("Party".to_string());
+        playlist.append(party);
+        assert_eq!(names(&playlist), ["a.mp3", "b.mp3", "c.mp3"]);
+        assert_eq!(playlist.title(), None);
+        assert_eq!(playlist.source_label(Some(Path::new("/home/ana"))).as_deref(), Some("stdin + party.m3u"));
+        assert_eq!(playlist_of(&["d.mp3"]).source_label(None), None);
+
+        assert!(Playlist::from_reader("\n".as_bytes(), &options).is_err());
+    }
+
+    #[test]
+    fn test_infer_tags_from_path() {
+        // (path, root, expected artist, expected album, expected year)
+        type Case<'a> = (&'a str, &'a str, Option<&'a str>, Option<&'a str>, Option<u32>);
+        let cases: [Case; 10] = [
+            ("/music/Radiohead/OK Computer/01.mp3", "/music", Some("Radiohead"), Some("OK Computer"), None),
+            ("/music/Radiohead - OK Computer (1997)/01.mp3", "/music", Some("Radiohead"), Some("OK Computer"), Some(1997)),
+            ("/music/Radiohead/1997 - OK Computer/01.mp3", "/music", Some("Radiohead"), Some("OK Computer"), Some(1997)),
+            ("/music/Radiohead/(1997) OK Computer/01.mp3", "/music", Some("Radiohead"), Some("OK Computer"), Some(1997)),
+            ("/music/Radiohead/OK Computer [1997]/01.mp3", "/music", Some("Radiohead"), Some("OK Computer"), Some(1997)),
+            ("/music/Pink Floyd/The Wall/CD2/01.mp3", "/music", Some("Pink Floyd"), Some("The Wall"), None),
+            // Nothing above the scan root is used
+            ("/music/OK Computer/01.mp3", "/music", None, Some("OK Computer"), None),
+            ("/music/01.mp3", "/music", None, None, None),
+            // Entries of a playlist in /home/a/lists, outside its folder and below it
+            ("/home/a/Music/Radiohead/OK Computer/01.mp3", "/home/a/lists", None, None, None),
+            ("/home/a/lists/Radiohead/OK Computer/01.mp3", "/home/a/lists", Some("Radiohead"), Some("OK Computer"), None),
+        ];
+
+        for (path, root, artist, album, year) in cases {
+            let mut track = Track::new(PathBuf::from(path));
+            infer_tags_from_path(&mut track, Path::new(root));
+            assert_eq!(track.artist.as_deref(), artist, "artist for {}", path);
+            assert_eq!(track.album.as_deref(), album, "album for {}", path);
+            assert_eq!(track.year, year, "year for {}", path);
+            assert_eq!(track.inferred.artist, artist.is_some());
+            assert_eq!(track.inferred.album, album.is_some());
+        }
+    }
+
+    #[test]
+    fn test_infer_keeps_existing_tags() {
+        let mut track = Track::new(PathBuf::from("/music/Folder Artist/Folder Album/01.mp3"));
+        track.artist = Some("Tag Artist".to_string());
+
+        infer_tags_from_path(&mut track, Path::new("/music"));
+        assert_eq!(track.artist.as_deref(), Some("Tag Artist"));
+        assert_eq!(track.album.as_deref(), Some("Folder Album"));
+        assert!(!track.inferred.artist);
+        assert!(track.inferred.album);
+    }
+
+    #[test]
+    fn test_is_disc_folder() {
+        assert!(is_disc_folder("CD1"));
+        assert!(is_disc_folder("Disc 2"));
+        assert!(is_disc_folder("disk_03"));
+        assert!(!is_disc_folder("Discovery"));
+        assert!(!is_disc_folder("CD"));
+    }
+
+    /// Builds a playlist of tracks with the given file names.
+    fn playlist_of(names: &[&str]) -> Playlist {
+        let mut playlist = Playlist::new();
+        for name in names {
+            playlist.add_track(Track::new(PathBuf::from(name)));
+        }
+        playlist
+    }
+
+    /// Returns the file names of a playlist's tracks in order.
+    fn names(playlist: &Playlist) -> Vec<String> {
+        playlist.tracks().iter().map(|t| t.display_name()).collect()
+    }
+
+    /// Loads `tracks` as a refresh of `playlist` would.
+    fn refreshed(playlist: &Playlist, tracks: Vec<Track>) -> Refreshed {
+        let current: Vec<PathBuf> = playlist.tracks().iter().map(|t| t.path.clone()).collect();
+        Refreshed::new(tracks, &current)
+    }
+
+    #[test]
+    fn test_restore_queue() {
+        let tracks: Vec<Track> =
+            ["a", "b", "c", "d"].iter().map(|n| Track::new(PathBuf::from(format!("/music/{}.mp3", n)))).collect();
+        let mut playlist = Playlist::from_tracks(tracks).unwrap();
+        playlist.toggle_queued(3);
+        playlist.toggle_queued(1);
+        let saved = playlist.queued_paths();
+        assert_eq!(saved, [PathBuf::from("/music/d.mp3"), PathBuf::from("/music/b.mp3")]);
+
+        // Some of the queued files are gone by the next launch
+        let saved = [
+            PathBuf::from("/music/gone.mp3"),
+            PathBuf::from("/music/c.mp3"),
+            PathBuf::from("/music/also-gone.mp3"),
+            PathBuf::from("/music/a.mp3"),
+            PathBuf::from("/music/c.mp3"),
+        ];
+        let mut restored = Playlist::from_tracks(playlist.tracks().to_vec()).unwrap();
+        assert_eq!(restored.restore_queue(&saved), 2);
+        assert_eq!(restored.queue(), [2, 0]);
+
+        // The first auto-advance already plays from the queue
+        assert!(restored.next());
+        assert_eq!(restored.current_track_index(), Some(2));
+        assert_eq!(restored.queue(), [0]);
+        assert_eq!(restored.restore_queue(&[]), 0);
+    }
+
+    #[test]
+    fn test_source_label() {
+        let home = Path::new("/home/ana");
+        let label = |source: PlaylistSource| source.label(Some(home));
+        assert_eq!(label(PlaylistSource::Directory(PathBuf::from("/home/ana/Music/rock"))), "~/Music/rock");
+        assert_eq!(label(PlaylistSource::Directory(PathBuf::from("/home/ana"))), "~");
+        assert_eq!(label(PlaylistSource::Directory(PathBuf::from("/home/anabel"))), "/home/anabel");
+        assert_eq!(label(PlaylistSource::M3u(PathBuf::from("/home/ana/party.m3u"))), "party.m3u");
+        assert_eq!(PlaylistSource::Directory(PathBuf::from("/srv/music")).label(None), "/srv/music");
+    }
+
+    #[test]
+    fn test_play_album() {
+        let mut playlist = Playlist::new();
+        // Two albums called "Hits", plus a single without an album
+        for (name, artist, album, number) in [
+            ("hits3.mp3", "A", Some("Hits"), 3),
+            ("other.mp3", "B", Some("Hits"), 1),
+            ("hits1.mp3", "A", Some("Hits"), 1),
+            ("single.mp3", "A", None, 1),
+            ("hits2.mp3", "A", Some("Hits"), 2),
+        ] {
+            let mut track = Track::new(PathBuf::from(name));
+            track.artist = Some(artist.to_string());
+            track.album = album.map(String::from);
+            track.track_number = Some(number);
+            playlist.add_track(track);
+        }
+        let current = |playlist: &Playlist| playlist.current_track().unwrap().display_name();
+
+        playlist.toggle_shuffle();
+        assert!(playlist.goto(3));
+        assert_eq!(playlist.play_album(), None);
+        assert!(playlist.goto(4));
+        let resume_order = playlist.upcoming(5);
+
+        // Plays the rest of A's "Hits" in track order, ignoring shuffle
+        assert_eq!(playlist.play_album(), Some("Hits"));
+        let expected: Vec<usize> = [0].into_iter().chain(resume_order.first().copied()).collect();
+        assert_eq!(playlist.upcoming(2), expected);
+        assert!(playlist.previous());
+        assert_eq!(current(&playlist), "hits1.mp3");
+        assert!(playlist.next());
+        assert!(playlist.next());
+        assert_eq!(current(&playlist), "hits3.mp3");
+        assert_eq!(playlist.album_name(), Some("Hits"));
+
+        // Then picks up the shuffled order after the track it started from
+        assert_eq!(playlist.next(), !resume_order.is_empty());
+        assert_eq!(playlist.album_name(), None);
+        if let Some(&next) = resume_order.first() {
+            assert_eq!(playlist.current_track_index(), Some(next));
+        }
+
+        // Jumping elsewhere ends it
+        assert!(playlist.goto(2));
+        assert!(playlist.play_album().is_some());
+        assert!(playlist.goto(1));
+        assert_eq!(playlist.album_name(), None);
+    }
+
+    #[test]
+    fn test_play_directory() {
+        let mut playlist = playlist_of(&["/m/b/2.mp3", "/m/a.mp3", "/m/b/c/1.mp3", "/m/bb/1.mp3", "/m/b/1.mp3"]);
+        assert!(playlist.goto(1));
+        assert!(!playlist.play_directory(Path::new("/m/none")));
+        assert_eq!(playlist.album_name(), None);
+
+        // Everything below b, but not bb, in path order
+        assert!(playlist.play_directory(Path::new("/m/b")));
+        assert_eq!(playlist.album_name(), Some("b"));
+        assert_eq!(playlist.current_track_index(), Some(4));
+        assert_eq!(playlist.upcoming(2), [0, 2]);
+        assert!(playlist.next());
+        assert!(playlist.next());
+        assert_eq!(playlist.current_track_index(), Some(2));
+
+        // Then carries on after the track playing before
+        assert!(playlist.next());
+        assert_eq!(playlist.album_name(), None);
+        assert_eq!(playlist.current_track_index(), Some(2));
+    }
+
+    #[test]
+    fn test_play_selection() {
+        let mut playlist = playlist_of(&["0.mp3", "1.mp3", "2.mp3", "3.mp3", "4.mp3"]);
+        assert!(!playlist.play_selection(vec![3, 1], 2, true));
+        assert_eq!(playlist.selection_len(), None);
+
+        // Plays from the one picked to the last, then stops there
+        assert!(playlist.play_selection(vec![3, 1, 4], 1, true));
+        assert_eq!(playlist.selection_len(), Some(3));
+        assert_eq!(playlist.album_name(), None);
+        assert_eq!(playlist.current_track_index(), Some(1));
+        assert_eq!(playlist.upcoming(5), [4]);
+        assert!(playlist.next());
+        assert_eq!(playlist.current_track_index(), Some(4));
+        assert!(!playlist.next());
+        assert_eq!(playlist.selection_len(), None);
+        assert_eq!(playlist.current_track_index(), Some(4));
+
+        // Or goes back to the playlist after the track playing before
+        assert!(playlist.goto(0));
+        assert!(playlist.play_selection(vec![3, 1], 0, false));
+        assert_eq!(playlist.upcoming(3), [1, 1, 2]);
+        assert!(playlist.next());
+        assert!(playlist.next());
+        assert_eq!(playlist.current_track_index(), Some(1));
+        assert_eq!(playlist.selection_len(), None);
+
+        // Leaving it midway carries on from the track playing
+        assert!(playlist.play_selection(vec![3, 1], 0, true));
+        playlist.leave_album();
+        assert!(playlist.next());
+        assert_eq!(playlist.current_track_index(), Some(4));
+    }
+
+    #[test]
+    fn test_search_keys_follow_changes() {
+        let mut playlist = playlist_of(&["a.mp3", "b.mp3"]);
+        let mut track = Track::new(PathBuf::from("c.mp3"));
+        track.artist = Some("Björk".to_string());
+        playlist.add_track(track);
+        assert!(playlist.matches(2, "björk"));
+        assert!(!playlist.matches(2, "a.mp3"));
+
+        let revision = playlist.revision();
+        playlist.remove(0);
+        assert!(playlist.revision() > revision);
+        assert!(playlist.matches(0, "b.mp3"));
+        assert!(playlist.matches(1, "björk"));
+        assert!(!playlist.matches(2, ""));
+        // Fields are searched separately
+        assert!(!playlist.matches(1, "mp3björk"));
+    }
+
+    #[test]
+    fn test_search_large_playlist() {
+        let mut playlist = Playlist::new();
+        for i in 0..20_000 {
+            let mut track = Track::new(PathBuf::from(format!("/music/artist {}/track {}.flac", i % 500, i)));
+            track.title = Some(format!("Song Number {}", i));
+            track.artist = Some(format!("Artist {}", i % 500));
+            track.album = Some(format!("Album {}", i % 2000));
+            playlist.add_track(track);
+        }
+
+        // Type a query one character at a time, narrowing the previous matches
+        let started = std::time::Instant::now();
+        let mut matches: Vec<usize> = (0..playlist.len()).collect();
+        let query = "song number 1999";
+        for end in 1..=query.len() {
+            matches.retain(|&i| playlist.matches(i, &query[..end]));
+        }
+        let per_key = started.elapsed() / query.len() as u32;
+
+        assert_eq!(matches, vec![1999, 19990, 19991, 19992, 19993, 19994, 19995, 19996, 19997, 19998, 19999]);
+        // Generous enough for unoptimized builds on slow machines
+        assert!(per_key < std::time::Duration::from_millis(20), "{:?} per keystroke", per_key);
+    }
+
+    #[test]
+    fn test_upcoming() {
+        let mut playlist = playlist_of(&["a.mp3", "b.mp3", "c.mp3", "d.mp3"]);
+        playlist.goto(1);
+        assert_eq!(playlist.upcoming(5), [2, 3]);
+        playlist.set_repeat(RepeatMode::All);
+        assert_eq!(playlist.upcoming(5), [2, 3, 0, 1, 2]);
+
+        // Queued tracks come first, then the playlist continues from where it was
+        assert!(playlist.toggle_queued(0));
+        assert!(playlist.toggle_queued(3));
+        assert_eq!(playlist.upcoming(4), [0, 3, 2, 3]);
+        assert_eq!(playlist.peek_next(), Some(0));
+        assert!(!playlist.toggle_queued(0));
+        assert_eq!(playlist.upcoming(3), [3, 2, 3]);
+
+        // Next follows the same order and consumes the queue
+        assert!(playlist.next());
+        assert_eq!(playlist.current_track_index(), Some(3));
+        assert!(!playlist.is_queued(3));
+        assert_eq!(playlist.upcoming(2), [2, 3]);
+
+        playlist.set_repeat(RepeatMode::Single);
+        assert_eq!(playlist.upcoming(2), [3, 3]);
+
+        // With shuffle, upcoming is the shuffled order
+        playlist.set_repeat(RepeatMode::Off);
+        playlist.toggle_shuffle();
+        let order: Vec<usize> = playlist.upcoming(10);
+        assert_eq!(order.len(), 3);
+        for expected in order {
+            assert!(playlist.next());
+            assert_eq!(playlist.current_track_index(), Some(expected));
+        }
+        assert!(!playlist.next());
+
+        // Jumping to a track index works in shuffled order too
+        assert!(playlist.goto(2));
+        assert_eq!(playlist.current_track_index(), Some(2));
+    }
+
+    #[test]
+    fn test_queue_resumes_the_playlist() {
+        let mut playlist = playlist_of(&["a.mp3", "b.mp3", "c.mp3", "d.mp3", "e.mp3"]);
+        playlist.goto(1);
+        playlist.toggle_queued(4);
+        playlist.toggle_queued(3);
+
+        let mut played = Vec::new();
+        while playlist.next() {
+            played.push(playlist.current_track_index().unwrap());
+        }
+        // No track between b and the queued ones is skipped
+        assert_eq!(played, [4, 3, 2, 3, 4]);
+
+        // Previous from a queued track goes back to where the playlist was
+        playlist.goto(0);
+        playlist.toggle_queued(3);
+        assert!(playlist.next());
+        assert_eq!(playlist.current_index(), 3);
+        assert!(playlist.previous());
+        assert_eq!(playlist.current_track_index(), Some(0));
+
+        // Removing a track before the queued one playing keeps it current
+        playlist.toggle_queued(3);
+        assert!(playlist.next());
+        playlist.remove(1);
+        assert_eq!(playlist.current_track().unwrap().path, PathBuf::from("d.mp3"));
+        assert!(playlist.next());
+        assert_eq!(playlist.current_track().unwrap().path, PathBuf::from("c.mp3"));
+    }
+
+    #[test]
+    fn test_queue_follows_reordering() {
+        let mut playlist = playlist_of(&["c.mp3", "a.mp3", "b.mp3"]);
+        playlist.toggle_queued(0);
+        playlist.toggle_queued(2);
+
+        playlist.sort(SortKey::Path, false, &Collation::default());
+        let queued: Vec<_> = playlist.upcoming(2).iter().map(|&i| playlist.tracks()[i].display_name()).collect();
+        assert_eq!(queued, ["c.mp3", "b.mp3"]);
+
+        playlist.remove(0);
+        assert_eq!(playlist.upcoming(1), [1]);
+        assert_eq!(playlist.tracks()[1].display_name(), "c.mp3");
+    }
+
+    #[test]
+    fn test_remove_track() {
+        let mut playlist = playlist_of(&["a.mp3", "b.mp3", "c.mp3", "d.mp3"]);
+        playlist.goto(2);
+
+        // Removing before the current track keeps it current
+        playlist.remove(0);
+        assert_eq!(playlist.current_track().unwrap().display_name(), "c.mp3");
+
+        // Removing the current track advances to the next one
+        playlist.remove(1);
+        assert_eq!(playlist.current_track().unwrap().display_name(), "d.mp3");
+
+        // Removing the last track clamps
+        playlist.remove(1);
+        assert_eq!(playlist.current_track().unwrap().display_name(), "b.mp3");
+        assert!(playlist.remove(5).is_none());
+    }
+
+    #[test]
+    fn test_remove_track_shuffled() {
+        let mut playlist = playlist_of(&["a.mp3", "b.mp3", "c.mp3", "d.mp3"]);
+        playlist.toggle_shuffle();
+        playlist.next();
+        let current = playlist.current_track_index().unwrap();
+        let current_name = playlist.current_track().unwrap().display_name();
+
+        // Remove some other track
+        let other = (0..4).find(|&i| i != current).unwrap();
+        playlist.remove(other);
+        assert_eq!(playlist.current_track().unwrap().display_name(), current_name);
+
+        let mut sorted = playlist.shuffle_indices.clone();
+        sorted.sort();
+        assert_eq!(sorted, [0, 1, 2]);
+    }
+
+    #[test]
+    fn test_files_gone_after_scan() {
+        let dir = std::env::temp_dir().join(format!("juke_gone_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["1.mp3", "2.mp3", "3.mp3", "4.mp3"] {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+        let mut playlist = Playlist::from_directory(&dir, &LoadOptions::default()).unwrap();
+        let open = |track: &Track| File::open(&track.path).map(drop);
+        let name = |playlist: &Playlist| playlist.current_track().unwrap().display_name();
+
+        // Deleted after the scan: skipped and marked, the next one plays
+        fs::remove_file(dir.join("2.mp3")).unwrap();
+        fs::remove_file(dir.join("3.mp3")).unwrap();
+        assert!(playlist.next());
+        let mut skipped = Vec::new();
+        let opened = playlist.open_playable(open, |track, _| {
+            skipped.push(track.display_name());
+            true
+        });
+        assert!(opened.unwrap());
+        assert_eq!(skipped, ["2.mp3", "3.mp3"]);
+        assert_eq!(name(&playlist), "4.mp3");
+        assert!(playlist.is_unplayable(1) && playlist.is_unplayable(2));
+        assert!(!playlist.is_unplayable(3));
+
+        // Nothing playable left, even going round with repeat
+        fs::remove_file(dir.join("1.mp3")).unwrap();
+        fs::remove_file(dir.join("4.mp3")).unwrap();
+        playlist.set_repeat(RepeatMode::All);
+        assert!(!playlist.open_playable(open, |_, _| true).unwrap());
+        playlist.set_repeat(RepeatMode::Single);
+        assert!(!playlist.open_playable(open, |_, _| true).unwrap());
+
+        // An error not to skip stops at once
+        let error = playlist.open_playable(open, |_, _| false).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+
+        // A file that comes back plays again
+        fs::write(dir.join("2.mp3"), b"").unwrap();
+        playlist.set_repeat(RepeatMode::All);
+        assert!(playlist.open_playable(open, |_, _| true).unwrap());
+        assert_eq!(name(&playlist), "2.mp3");
+        assert!(!playlist.is_unplayable(1));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_navigation_passes_over_unplayable() {
+        let mut playlist = playlist_of(&["/m/1.mp3", "/m/2.mp3", "/m/3.mp3", "/m/4.mp3"]);
+        for index in [1, 2] {
+            playlist.unplayable.insert(playlist.tracks[index].path.clone());
+        }
+        let name = |playlist: &Playlist| playlist.current_track().unwrap().display_name();
+
+        assert!(playlist.next());
+        assert_eq!(name(&playlist), "4.mp3");
+        assert!(playlist.previous());
+        assert_eq!(name(&playlist), "1.mp3");
+        // Picked by hand, a marked track is still tried
+        assert!(playlist.goto(2));
+        assert_eq!(name(&playlist), "3.mp3");
+
+        // At the end with repeat off, there is nothing playable to move to
+        assert!(playlist.goto(3));
+        playlist.unplayable.insert(PathBuf::from("/m/4.mp3"));
+        assert!(playlist.goto(0));
+        assert!(!playlist.next());
+        assert_eq!(name(&playlist), "1.mp3");
+        assert_eq!(playlist.current_index(), 0);
+
+        // A refresh forgets the marks
+        playlist.merge_refreshed(refreshed(&playlist, playlist.tracks.clone()));
+        assert_eq!(playlist.unplayable_count(), 0);
+    }
+
+    #[test]
+    fn test_remove_until_empty() {
+        let mut playlist = playlist_of(&["/m/a/1.mp3", "/m/a/2.mp3", "/m/b/1.mp3"]);
+        playlist.toggle_shuffle();
+        playlist.toggle_queued(2);
+        assert!(playlist.play_directory(Path::new("/m/a")));
+        while !playlist.is_empty() {
+            playlist.remove(0);
+        }
+
+        assert!(playlist.current_track().is_none());
+        assert!(!playlist.next());
+        assert!(!playlist.previous());
+        assert!(playlist.upcoming(3).is_empty());
+        assert!(playlist.queue().is_empty());
+        assert_eq!(playlist.album_name(), None);
+        assert_eq!(playlist.play_album(), None);
+        playlist.toggle_shuffle();
+        playlist.toggle_shuffle();
+
+        // A refresh that finds tracks again gives a current track
+        let fresh = vec![Track::new(PathBuf::from("/m/c.mp3"))];
+        let diff = playlist.merge_refreshed(refreshed(&playlist, fresh));
+        assert_eq!(diff, RefreshDiff { added: 1, removed: 0 });
+        assert_eq!(playlist.current_track().unwrap().display_name(), "c.mp3");
+    }
+
+    #[test]
+    fn test_merge_refreshed() {
+        let mut playlist = playlist_of(&["a.mp3", "c.mp3", "d.mp3", "f.mp3"]);
+        playlist.goto(2); // d.mp3
+        playlist.toggle_queued(3);
+        playlist.toggle_queued(1);
+
+        let fresh = ["a.mp3", "b.mp3", "c.mp3", "e.mp3", "f.mp3", "g.mp3"]
+            .iter()
+            .map(|n| Track::new(PathBuf::from(n)))
+            .collect();
+        let diff = playlist.merge_refreshed(refreshed(&playlist, fresh));
+
+        // d.mp3 vanished but is playing, so it stays
+        assert_eq!(diff, RefreshDiff { added: 3, removed: 0 });
+        assert_eq!(names(&playlist), ["a.mp3", "b.mp3", "c.mp3", "e.mp3", "d.mp3", "f.mp3", "g.mp3"]);
+        assert_eq!(playlist.current_track().unwrap().display_name(), "d.mp3");
+        // The queue follows its tracks to where they now are
+        assert_eq!(playlist.queue(), [5, 2]);
+    }
+
+    #[test]
+    fn test_merge_refreshed_removes_vanished() {
+        let mut playlist = playlist_of(&["a.mp3", "b.mp3", "c.mp3"]);
+        playlist.goto(2);
+
+        let fresh = vec![Track::new(PathBuf::from("c.mp3")), Track::new(PathBuf::from("z.mp3"))];
+        let diff = playlist.merge_refreshed(refreshed(&playlist, fresh));
+
+        assert_eq!(diff, RefreshDiff { added: 1, removed: 2 });
+        assert_eq!(names(&playlist), ["c.mp3", "z.mp3"]);
+        assert_eq!(playlist.current_index(), 0);
+    }
+
+    #[test]
+    fn test_merge_refreshed_keeps_shuffle() {
+        let mut playlist = playlist_of(&["a.mp3", "b.mp3", "c.mp3", "d.mp3"]);
+        playlist.toggle_shuffle();
+        playlist.next();
+        let current = playlist.current_track().unwrap().display_name();
+
+        let fresh = ["a.mp3", "b.mp3", "c.mp3", "d.mp3", "e.mp3"]
+            .iter()
+            .map(|n| Track::new(PathBuf::from(n)))
+            .collect();
+        playlist.merge_refreshed(refreshed(&playlist, fresh));
+
+        assert_eq!(playlist.current_track().unwrap().display_name(), current);
+        assert_eq!(playlist.shuffle_indices.len(), 5);
+        let mut sorted = playlist.shuffle_indices.clone();
+        sorted.sort();
+        assert_eq!(sorted, [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_smart_shuffle() {
+        use rand::SeedableRng;
+        use rand::rngs::StdRng;
+
+        let names: Vec<String> = (0..10).map(|i| format!("{}.mp3", i)).collect();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        let mut playlist = playlist_of(&names);
+        playlist.shuffle = ShuffleState::On;
+        let order = |playlist: &mut Playlist, seed| {
+            playlist.current_index = 0;
+            playlist.regenerate_shuffle_with(&mut StdRng::seed_from_u64(seed));
+            playlist.shuffle_indices.clone()
+        };
+
+        // No stats: the same order as a plain shuffle
+        let uniform = order(&mut playlist, 1445);
+        playlist.set_shuffle_weights(Some(HashMap::new()));
+        assert_eq!(order(&mut playlist, 1445), uniform);
+
+        // A often-skipped track sinks, but is never left out
+        playlist.set_shuffle_weight(Path::new("9.mp3"), 0.1);
+        assert_eq!(order(&mut playlist, 7), order(&mut playlist, 7));
+        let mut total = 0;
+        for seed in 0..200 {
+            let order = order(&mut playlist, seed);
+            let mut sorted = order.clone();
+            sorted.sort();
+            assert_eq!(sorted, (0..10).collect::<Vec<_>>());
+            total += order.iter().position(|&i| i == 9).unwrap();
+        }
+        assert!(total / 200 >= 7, "average position {}", total / 200);
+    }
+
     #[test]
     fn test_is_audio_file() {
-        assert!(is_audio_file(Path::new("song.mp3")));
-        assert!(is_audio_file(Path::new("song.MP3")));
-        assert!(is_audio_file(Path::new("song.flac")));
-        assert!(is_audio_file(Path::new("song.ogg")));
-        assert!(!is_audio_file(Path::new("song.txt")));
-        assert!(!is_audio_file(Path::new("song.wav")));
+        let filter = ScanFilter::default();
+        assert!(filter.is_audio_file(Path::new("song.mp3")));
+        assert!(filter.is_audio_file(Path::new("song.MP3")));
+        assert!(filter.is_audio_file(Path::new("song.flac")));
+        assert!(filter.is_audio_file(Path::new("song.ogg")));
+        assert!(!filter.is_audio_file(Path::new("song.txt")));
+        assert!(!filter.is_audio_file(Path::new("song.wav")));
+    }
+
+    #[test]
+    fn test_is_playlist_file() {
+        assert!(is_playlist_file(Path::new("list.m3u")));
+        assert!(is_playlist_file(Path::new("list.M3U8")));
+        assert!(!is_playlist_file(Path::new("song.mp3")));
+    }
+
+    #[test]
+    fn test_nested_playlists() {
+        // master.m3u -> albums/a/a.m3u, albums/b/b.m3u (which loops back to master)
+        let dir = std::env::temp_dir().join(format!("juke_nested_m3u_{}", std::process::id()));
+        fs::create_dir_all(dir.join("albums/a")).unwrap();
+        fs::create_dir_all(dir.join("albums/b")).unwrap();
+        fs::write(
+            dir.join("master.m3u"),
+            "intro.mp3\nalbums/a/a.m3u\nalbums/b/b.m3u\nmissing.m3u\noutro.mp3\n",
+        )
+        .unwrap();
+        fs::write(dir.join("albums/a/a.m3u"), "#EXTM3U\na1.mp3\na2.mp3\n").unwrap();
+        fs::write(dir.join("albums/b/b.m3u"), "b1.mp3\n../../master.m3u\nb/self.m3u\n").unwrap();
+        fs::create_dir_all(dir.join("albums/b/b")).unwrap();
+        fs::write(dir.join("albums/b/b/self.m3u"), "self.m3u\n").unwrap();
+
+        let (tracks, _, report) = parse_m3u_report(dir.join("master.m3u"), &LoadOptions::default()).unwrap();
+        let names: Vec<String> = tracks.iter().map(|t| t.display_name()).collect();
+        fs::remove_dir_all(&dir).unwrap();
+
+        // The cycles, the playlist left empty by one, and the missing playlist
+        // are reported, not printed
+        let skipped: Vec<&str> = report.errors.iter().map(|(_, error)| error.as_str()).collect();
+        assert_eq!(
+            skipped,
+            ["Skipping playlist cycle", "Skipping playlist cycle", "Nested playlist is empty", "Nested playlist not found"]
+        );
+
+        // Order of the master file is kept and relative paths resolve per playlist
+        assert_eq!(names, ["intro.mp3", "a1.mp3", "a2.mp3", "b1.mp3", "outro.mp3"]);
+        assert!(tracks[1].path.ends_with("albums/a/a1.mp3"));
+        assert!(tracks[3].path.ends_with("albums/b/b1.mp3"));
+    }
+
+    #[test]
+    fn test_classify_m3u_entry() {
+        let cases = [
+            ("song.mp3", M3uPathKind::Relative),
+            ("..\\Music\\song.mp3", M3uPathKind::Relative),
+            ("./a/b.mp3", M3uPathKind::Relative),
+            ("/music/a.mp3", M3uPathKind::Rooted),
+            ("\\Music\\a.mp3", M3uPathKind::Rooted),
+            ("D:\\Music\\song.mp3", M3uPathKind::Drive),
+            ("c:/music/song.mp3", M3uPathKind::Drive),
+            ("a:song.mp3", M3uPathKind::Relative),
+            ("C:", M3uPathKind::Relative),
+            ("\\\\NAS\\share\\song.flac", M3uPathKind::Unc),
+            ("//nas/share/song.flac", M3uPathKind::Unc),
+        ];
+        for (entry, expected) in cases {
+            assert_eq!(classify_m3u_entry(entry), expected, "classifying {:?}", entry);
+        }
+    }
+
+    #[test]
+    fn test_normalize_m3u_entry() {
+        // (entry, on Windows, elsewhere)
+        let cases = [
+            ("..\\Music\\song.mp3", "..\\Music\\song.mp3", "../Music/song.mp3"),
+            ("a/b\\c.mp3", "a\\b\\c.mp3", "a/b/c.mp3"),
+            ("/music/a.mp3", "\\music\\a.mp3", "/music/a.mp3"),
+            ("D:\\Music\\song.mp3", "D:\\Music\\song.mp3", "D:\\Music\\song.mp3"),
+            ("\\\\NAS\\share\\a.flac", "\\\\NAS\\share\\a.flac", "\\\\NAS\\share\\a.flac"),
+        ];
+        for (entry, windows, posix) in cases {
+            assert_eq!(normalize_m3u_entry(entry, true).1, windows, "on Windows: {:?}", entry);
+            assert_eq!(normalize_m3u_entry(entry, false).1, posix, "elsewhere: {:?}", entry);
+        }
+        assert_eq!(native_separators("a/b.mp3", true), "a\\b.mp3");
+        assert_eq!(native_separators("a/b.mp3", false), "a/b.mp3");
+    }
+
+    #[test]
+    fn test_m3u_backslashes_round_trip() {
+        let dir = std::env::temp_dir().join(format!("juke_m3u_paths_{}", std::process::id()));
+        fs::create_dir_all(dir.join("Music")).unwrap();
+        fs::create_dir_all(dir.join("lists")).unwrap();
+        fs::write(dir.join("lists/win.m3u"), "..\\Music\\song.mp3\r\n").unwrap();
+
+        let tracks = parse_m3u(dir.join("lists/win.m3u"), &LoadOptions::default()).unwrap();
+        assert!(tracks[0].path.ends_with("Music/song.mp3"));
+
+        save_m3u(&dir.join("lists/saved.m3u"), &tracks, None, &PlaylistSettings::default()).unwrap();
+        let saved = fs::read_to_string(dir.join("lists/saved.m3u")).unwrap();
+        let reparsed = parse_m3u(dir.join("lists/saved.m3u"), &LoadOptions::default()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(saved.contains(&format!("..{}Music{}song.mp3", std::path::MAIN_SEPARATOR, std::path::MAIN_SEPARATOR)));
+        assert!(reparsed[0].path.ends_with("Music/song.mp3"));
+    }
+
+    #[test]
+    fn test_parse_extinf() {
+        let secs = |s: f64| Some(Duration::from_secs_f64(s));
+        let title = |t: &str| Some(t.to_string());
+        let cases = [
+            ("213,Title", secs(213.0), title("Title")),
+            ("213.4,Title", secs(213.4), title("Title")),
+            ("213", secs(213.0), None),
+            ("213,", secs(213.0), None),
+            ("-1,Live Stream", None, title("Live Stream")),
+            ("213 tvg-id=\"x\" group-title=\"A, B\",Title, Part 2", secs(213.0), title("Title, Part 2")),
+            ("abc,Title", None, title("Title")),
+            ("1e400,Title", None, title("Title")),
+            ("NaN", None, None),
+            ("", None, None),
+        ];
+        for (content, duration, expected_title) in cases {
+            let extinf = parse_extinf(content);
+            assert_eq!(extinf.duration, duration, "duration of {:?}", content);
+            assert_eq!(extinf.title, expected_title, "title of {:?}", content);
+        }
+    }
+
+    #[test]
+    fn test_m3u_quirks() {
+        let mut data = b"\xef\xbb\xbf#EXTM3U\r\n#EXTINF:213\r\na.mp3\r\n".to_vec();
+        data.extend_from_slice(b"#EXTINF:90.5 tvg-logo=\"x,y\",Caf\xe9\nb\x00.mp3\n");
+        data.extend_from_slice(b"#EXTINF:garbage\n\xe9t\xe9.mp3\n#EXTINF:1,Orphan\n");
+
+        let entries = read_m3u(&data).entries;
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].0, "a.mp3");
+        assert_eq!(entries[0].1, Some(ExtInf { duration: Some(Duration::from_secs(213)), ..Default::default() }));
+        assert_eq!(entries[1].0, "b.mp3");
+        let second = entries[1].1.clone().unwrap();
+        assert_eq!(second.duration, Some(Duration::from_secs_f64(90.5)));
+        assert_eq!(second.title.as_deref(), Some("Café"));
+        assert_eq!(entries[2].0, "été.mp3");
+        assert_eq!(entries[2].1, Some(ExtInf::default()));
+    }
+
+    #[test]
+    fn test_extinf_artist_and_directives() {
+        let data = "#EXTM3U\n#PLAYLIST:Road Trip\n\
+            #EXTINF:200,Nina Simone - Sinnerman\na.mp3\n\
+            #EXTINF:300,Sonic Youth - Teen Age Riot - Live\nb.mp3\n\
+            #EXTINF:100,Take Five - Remastered\n#EXTART:Dave Brubeck\n#EXTALB:Time Out\nc.mp3\n\
+            #EXTART:Can\n#EXTINF:90,Can - Vitamin C\nd.mp3\n";
+        let m3u = read_m3u(data.as_bytes());
+        assert_eq!(m3u.title.as_deref(), Some("Road Trip"));
+
+        let tracks: Vec<Track> = m3u
+            .entries
+            .into_iter()
+            .map(|(line, extinf, _)| {
+                let mut track = Track::new(PathBuf::from(line));
+                apply_extinf(&mut track, extinf.unwrap());
+                track
+            })
+            .collect();
+        let fields = |t: &Track| (t.artist.clone().unwrap_or_default(), t.display_name(), t.album.clone());
+        assert_eq!(fields(&tracks[0]), ("Nina Simone".into(), "Sinnerman".into(), None));
+        // Only the first " - " splits
+        assert_eq!(fields(&tracks[1]), ("Sonic Youth".into(), "Teen Age Riot - Live".into(), None));
+        // An artist named outright is not split off the title
+        assert_eq!(
+            fields(&tracks[2]),
+            ("Dave Brubeck".into(), "Take Five - Remastered".into(), Some("Time Out".into()))
+        );
+        assert_eq!(fields(&tracks[3]), ("Can".into(), "Vitamin C".into(), None));
+        assert!(tracks[0].inferred.artist && tracks[2].inferred.album);
+
+        // The file's own artist wins, and a different one is not split off
+        let mut tagged = Track::new(PathBuf::from("e.mp3"));
+        tagged.artist = Some("Stereolab".to_string());
+        let extinf = parse_extinf("10,Cybele's Reverie - Single Edit");
+        apply_extinf(&mut tagged, extinf);
+        assert_eq!(tagged.artist.as_deref(), Some("Stereolab"));
+        assert_eq!(tagged.title.as_deref(), Some("Cybele's Reverie - Single Edit"));
+        assert!(!tagged.inferred.artist);
+    }
+
+    #[test]
+    fn test_m3u_fields_round_trip() {
+        let dir = std::env::temp_dir().join(format!("juke_m3u_fields_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut live = Track::new(dir.join("live.mp3"));
+        live.title = Some("Teen Age Riot - Live".to_string());
+        live.artist = Some("Sonic Youth".to_string());
+        live.album = Some("Daydream Nation".to_string());
+        live.duration = Some(Duration::from_secs(300));
+        let mut untitled = Track::new(dir.join("untitled.mp3"));
+        untitled.artist = Some("Unknown".to_string());
+        let bare = Track::new(dir.join("bare.mp3"));
+
+        let path = dir.join("saved.m3u");
+        save_m3u(&path, &[live, untitled, bare], Some("Mix\nTape"), &PlaylistSettings::default()).unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        let (tracks, m3u, _) = parse_m3u_report(&path, &LoadOptions::default()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(saved.starts_with("#EXTM3U\n#PLAYLIST:Mix Tape\n#EXTINF:300,Sonic Youth - Teen Age Riot - Live\n"));
+        assert_eq!(m3u.title.as_deref(), Some("Mix Tape"));
+        assert_eq!(tracks[0].title.as_deref(), Some("Teen Age Riot - Live"));
+        assert_eq!(tracks[0].artist.as_deref(), Some("Sonic Youth"));
+        assert_eq!(tracks[0].album.as_deref(), Some("Daydream Nation"));
+        assert_eq!(tracks[0].duration, Some(Duration::from_secs(300)));
+        // Nothing but the artist: no title is made up from the file name
+        assert_eq!(tracks[1].artist.as_deref(), Some("Unknown"));
+        assert_eq!(tracks[1].title, None);
+        assert_eq!((tracks[2].artist.as_deref(), tracks[2].title.as_deref()), (None, None));
+    }
+
+    #[test]
+    fn test_playlist_settings_round_trip() {
+        let dir = std::env::temp_dir().join(format!("juke_m3u_settings_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let tracks = [dir.join("a.mp3"), dir.join("b.mp3")].map(Track::new);
+        let settings = PlaylistSettings {
+            shuffle: Some(ShuffleState::On),
+            repeat: Some(RepeatMode::Single),
+            volume: Some(35),
+            sort: Some((SortKey::TrackNumber, true)),
+        };
+
+        let path = dir.join("saved.m3u");
+        save_m3u(&path, &tracks, Some("Mix"), &settings).unwrap();
+        let first = fs::read_to_string(&path).unwrap();
+        let mut playlist = Playlist::from_m3u(&path, &LoadOptions::default()).unwrap();
+        assert_eq!(playlist.settings(), &settings);
+        assert_eq!(playlist.tracks().len(), 2);
+        playlist.save(&path).unwrap();
+        let second = fs::read_to_string(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(first.contains("#JUKE:repeat=single\n#JUKE:volume=35\n#JUKE:sort=track descending\n"), "{}", first);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_playlist_settings_reject_garbage() {
+        let data = "#EXTM3U\n#JUKE:shuffle=on\n#JUKE:volume=250\n#JUKE:repeat=sometimes\n#JUKE:sort=random\n\
+            #JUKE:sort=album sideways\n#JUKE:colour=blue\n#JUKE:nonsense\n#JUKE:repeat=all\na.mp3\n";
+        let m3u = read_m3u(data.as_bytes());
+        assert_eq!(
+            m3u.settings,
+            PlaylistSettings { shuffle: Some(ShuffleState::On), repeat: Some(RepeatMode::All), ..Default::default() }
+        );
+        assert_eq!(m3u.warnings.len(), 6, "{:?}", m3u.warnings);
+        assert!(m3u.warnings[0].contains("volume=\"250\""));
+        assert_eq!(m3u.entries.len(), 1);
+        assert!(PlaylistSettings::default().lines().is_empty());
+    }
+
+    #[test]
+    fn test_m3u_awkward_names_round_trip() {
+        let dir = std::env::temp_dir().join(format!("juke_m3u_awkward_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut broken = Track::new(dir.join("#1.mp3"));
+        broken.title = Some("First\nLine\u{7}".to_string());
+        broken.album = Some("Tab\tbed".to_string());
+        let mut tracks = vec![broken];
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            let latin1 = dir.join(std::ffi::OsStr::from_bytes(b"caf\xe9.mp3"));
+            File::create(&latin1).unwrap();
+            tracks.push(Track::new(latin1));
+        }
+
+        let path = dir.join("saved.m3u");
+        save_m3u(&path, &tracks, None, &PlaylistSettings::default()).unwrap();
+        let (reparsed, _, _) = parse_m3u_report(&path, &LoadOptions::default()).unwrap();
+        // Neither a .m3u8 file nor a line break can hold them
+        let refused = save_m3u(&dir.join("saved.m3u8"), &tracks, None, &PlaylistSettings::default());
+        let split = save_m3u(&path, &[Track::new(dir.join("two\nlines.mp3"))], None, &PlaylistSettings::default());
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(reparsed.len(), tracks.len());
+        assert_eq!(reparsed[0].path, dir.join("./#1.mp3"));
+        assert_eq!(reparsed[0].title.as_deref(), Some("First Line"));
+        assert_eq!(reparsed[0].album.as_deref(), Some("Tab bed"));
+        if cfg!(unix) {
+            assert_eq!(reparsed[1].path, tracks[1].path);
+            assert!(refused.unwrap_err().to_string().contains("caf"));
+        }
+        let error = split.unwrap_err().to_string();
+        assert!(error.starts_with("Cannot write these tracks") && error.contains("line break"), "{}", error);
+    }
+
+    #[test]
+    fn test_m3u_random_bytes() {
+        use rand::{Rng, SeedableRng};
+
+        // Fragments that reach the interesting branches more often than plain noise
+        const FRAGMENTS: &[&[u8]] = &[
+            b"#EXTINF:", b",", b"\"", b"\n", b"\r\n", b"\0", b"\xff", b"\xc3", b"\xef\xbb\xbf",
+            b"-1", b"213.4", b" tvg-id=", b"C:\\", b"\\\\", b"/", b"..", b".mp3", b".m3u", b"#",
+        ];
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1438);
+        let dir = std::env::temp_dir().join(format!("juke_m3u_fuzz_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("fuzz.m3u");
+
+        for round in 0..500 {
+            let mut data = Vec::new();
+            for _ in 0..rng.gen_range(0..40) {
+                if rng.gen_bool(0.5) {
+                    data.extend_from_slice(FRAGMENTS[rng.gen_range(0..FRAGMENTS.len())]);
+                } else {
+                    data.push(rng.r#gen());
+                }
+            }
+
+            let entries = read_m3u(&data).entries;
+            assert!(entries.iter().all(|(line, _, _)| !line.is_empty() && !line.contains('\0')));
+            // Fewer rounds through the file system, which is slower
+            if round % 10 == 0 {
+                fs::write(&path, &data).unwrap();
+                assert!(parse_m3u(&path, &LoadOptions::default()).is_ok(), "failed on {:?}", data);
+            }
+        }
+        fs::remove_dir_all(&dir).unwrap();
     }
 
     #[test]
@@ -514,12 +3419,185 @@ mod tests {
         assert!(track.album.is_none());
     }
 
+    #[test]
+    fn test_parse_gain() {
+        assert_eq!(parse_gain("-6.50 dB"), Some(-6.5));
+        assert_eq!(parse_gain("+2.1 dB"), Some(2.1));
+        assert_eq!(parse_gain("3"), Some(3.0));
+        assert_eq!(parse_gain("loud"), None);
+    }
+
+    #[test]
+    fn test_scan_unreadable_root_fails() {
+        let result = scan_directory("/nonexistent/juke/music", &LoadOptions::default());
+        assert!(matches!(result, Err(PlaylistError::IoError(_))));
+    }
+
+    #[test]
+    fn test_scan_report_counts() {
+        let dir = std::env::temp_dir().join(format!("juke_scan_report_{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        File::create(dir.join("a.mp3")).unwrap();
+        File::create(dir.join("sub").join("b.flac")).unwrap();
+        File::create(dir.join("notes.txt")).unwrap();
+
+        let (tracks, report) = scan_directory(&dir, &LoadOptions::default()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(report.scanned, 3);
+        assert_eq!(report.added, 2);
+        assert!(report.errors.is_empty());
+    }
+
+    #[test]
+    fn test_watched_scan_stops_when_told() {
+        let dir = std::env::temp_dir().join(format!("juke_scan_watched_{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        for name in ["a.mp3", "b.mp3", "sub/c.mp3", "sub/d.mp3"] {
+            File::create(dir.join(name)).unwrap();
+        }
+
+        let mut seen = Vec::new();
+        let (tracks, report) = scan_directory_watched(&dir, &LoadOptions::default(), &mut |scanned| {
+            seen.push(scanned);
+            scanned < 3
+        })
+        .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(seen, [1, 2, 3]);
+        assert_eq!(report.scanned, 3);
+        assert_eq!(tracks.len(), 2);
+    }
+
+    #[test]
+    fn test_scan_segments() {
+        let dir = std::env::temp_dir().join(format!("juke_scan_segments_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        File::create(dir.join("live.mp3")).unwrap();
+        File::create(dir.join("other.mp3")).unwrap();
+        fs::write(
+            dir.join("live.segments.toml"),
+            "[[segment]]\ntitle = \"Intro\"\nstart = 0\n\n[[segment]]\ntitle = \"Hidden Track\"\nstart = \"3:00\"\n",
+        )
+        .unwrap();
+        fs::write(dir.join("other.segments.toml"), "[[segment]]\ntitle = \"Broken\"\n").unwrap();
+
+        let (tracks, report) = scan_directory(&dir, &LoadOptions::default()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let names: Vec<_> = tracks.iter().map(|t| t.display_name()).collect();
+        assert_eq!(names, ["Intro", "Hidden Track", "other.mp3"]);
+        assert_eq!(tracks[1].start_offset, Some(Duration::from_secs(180)));
+        assert_eq!(report.added, 3);
+        assert_eq!(report.errors.len(), 1);
+        assert!(report.errors[0].0.ends_with("other.segments.toml"));
+
+        // Segment titles are searchable like any other track
+        let playlist = Playlist::from_tracks(tracks).unwrap();
+        assert!(playlist.matches(1, "hidden"));
+        assert!(!playlist.matches(0, "hidden"));
+    }
+
+    #[test]
+    fn test_fast_scan_skips_probing() {
+        let dir = std::env::temp_dir().join(format!("juke_fast_scan_{}", std::process::id()));
+        fs::create_dir_all(dir.join("Band").join("Live")).unwrap();
+        File::create(dir.join("Band").join("Live").join("01 Intro.mp3")).unwrap();
+
+        let (tracks, _) = scan_directory(&dir, &LoadOptions::default()).unwrap();
+        assert!(tracks[0].probed);
+        let options = LoadOptions { fast_scan: true, infer_tags_from_path: true, ..Default::default() };
+        let (tracks, _) = scan_directory(&dir, &options).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let mut track = tracks[0].clone();
+        assert!(!track.probed);
+        assert_eq!((track.display_name().as_str(), track.artist.as_deref()), ("01 Intro.mp3", Some("Band")));
+
+        // Tags replace guesses from the path, and fill in the rest
+        let mut probed = Track::new(track.path.clone());
+        probed.title = Some("Intro".to_string());
+        probed.artist = Some("The Band".to_string());
+        probed.duration = Some(Duration::from_secs(95));
+        track.enrich(&probed);
+        assert!(track.probed && !track.inferred.artist);
+        assert_eq!((track.title.as_deref(), track.artist.as_deref()), (Some("Intro"), Some("The Band")));
+        assert_eq!(track.album.as_deref(), Some("Live"));
+        assert_eq!(track.duration, Some(Duration::from_secs(95)));
+
+        // A segment keeps its own title and gets the rest of the file
+        let mut segment = Track::new(probed.path.clone());
+        segment.title = Some("Encore".to_string());
+        segment.start_offset = Some(Duration::from_secs(60));
+        segment.enrich(&probed);
+        assert_eq!(segment.title.as_deref(), Some("Encore"));
+        assert_eq!(segment.duration, Some(Duration::from_secs(35)));
+    }
+
+    #[test]
+    fn test_scan_filters() {
+        let dir = std::env::temp_dir().join(format!("juke_scan_filters_{}", std::process::id()));
+        fs::create_dir_all(dir.join("Ringtones")).unwrap();
+        fs::create_dir_all(dir.join("live").join("demos")).unwrap();
+        File::create(dir.join("a.MKA")).unwrap();
+        File::create(dir.join("b.mp3")).unwrap();
+        File::create(dir.join("Ringtones").join("beep.mka")).unwrap();
+        File::create(dir.join("live").join("c.mka")).unwrap();
+        File::create(dir.join("live").join("demos").join("d.mka")).unwrap();
+        fs::write(dir.join("live").join(filter::IGNORE_FILE), "# rough takes\ndemos/**\n").unwrap();
+
+        let options = LoadOptions {
+            filter: ScanFilter::new(&["mka".to_string()], &["**/Ringtones/**".to_string()]),
+            ..Default::default()
+        };
+        let (tracks, _) = scan_directory(&dir, &options).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let names: Vec<_> = tracks.iter().map(|t| t.path.file_name().unwrap().to_owned()).collect();
+        assert_eq!(names, ["a.MKA", "c.mka"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_scan_options() {
+        let dir = std::env::temp_dir().join(format!("juke_scan_options_{}", std::process::id()));
+        fs::create_dir_all(dir.join("one").join("two")).unwrap();
+        fs::create_dir_all(dir.join(".hidden")).unwrap();
+        File::create(dir.join("a.mp3")).unwrap();
+        File::create(dir.join("one").join("b.mp3")).unwrap();
+        File::create(dir.join("one").join("two").join("c.mp3")).unwrap();
+        File::create(dir.join(".hidden").join("d.mp3")).unwrap();
+        // A loop back to the root is only scanned once
+        std::os::unix::fs::symlink(&dir, dir.join("one").join("loop")).unwrap();
+
+        let scan = |scan: ScanOptions| {
+            let options = LoadOptions { scan, ..Default::default() };
+            let (tracks, _) = scan_directory(&dir, &options).unwrap();
+            let mut names: Vec<_> = tracks
+                .iter()
+                .map(|t| t.path.file_name().unwrap().to_string_lossy().to_string())
+                .collect();
+            names.sort();
+            names
+        };
+
+        assert_eq!(scan(ScanOptions::default()), ["a.mp3", "b.mp3", "c.mp3", "d.mp3"]);
+        let shallow = ScanOptions { max_depth: 2, include_hidden: false, ..Default::default() };
+        assert_eq!(scan(shallow), ["a.mp3", "b.mp3"]);
+        let no_links = ScanOptions { follow_symlinks: false, ..Default::default() };
+        assert_eq!(scan(no_links), ["a.mp3", "b.mp3", "c.mp3", "d.mp3"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[test]
     fn test_load_music_directory() {
         // Test loading the actual music directory if it exists
         let music_dir = Path::new("music");
         if music_dir.exists() && music_dir.is_dir() {
-            let result = Playlist::from_directory(music_dir);
+            let result = Playlist::from_directory(music_dir, &LoadOptions::default());
             match result {
                 Ok(playlist) => {
                     println!("\nLoaded {} tracks from music directory", playlist.len());
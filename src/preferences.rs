@@ -0,0 +1,323 @@
+//! The preferences screen: a handful of common settings, edited in place
+//! and written back to the config file.
+//!
+//! Each row is a [`Preference`] naming its key in the config file, how it
+//! is edited, and how it is read from and written to [`Config`]. Only the
+//! rows that were changed are saved, into the file as it is on disk, so its
+//! other keys are kept and flag or environment overrides are not written.
+
+use crate::config::Config;
+use crate::themes;
+use toml_edit::{DocumentMut, Item};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// How a preference is edited.
+#[derive(Debug, Clone, Copy)]
+pub enum Kind {
+    /// On or off.
+    Toggle,
+    /// A whole number from `min` to `max`, moved by `step` and shown with `unit`.
+    Number { min: i64, max: i64, step: i64, unit: &'static str },
+    /// One of a list of names.
+    Choice(fn() -> Vec<&'static str>),
+}
+
+/// The value of a preference.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Bool(bool),
+    Number(i64),
+    Name(String),
+}
+
+impl Value {
+    fn to_toml(&self) -> toml_edit::Value {
+        match self {
+            Value::Bool(on) => (*on).into(),
+            Value::Number(n) => (*n).into(),
+            Value::Name(name) => name.as_str().into(),
+        }
+    }
+}
+
+/// A setting shown on the preferences screen.
+#[derive(Debug)]
+pub struct Preference {
+    /// Section and key in the config file.
+    pub section: &'static str,
+    pub key: &'static str,
+    /// Text key of the row's label.
+    pub label: &'static str,
+    pub kind: Kind,
+    get: fn(&Config) -> Value,
+    set: fn(&mut Config, Value),
+}
+
+impl Preference {
+    /// Returns the preference's value in a config.
+    pub fn get(&self, config: &Config) -> Value {
+        (self.get)(config)
+    }
+
+    /// Moves the value one step up or down: flips a toggle, steps a number
+    /// within its range, or moves along a list of choices, wrapping around.
+    pub fn step(&self, config: &mut Config, up: bool) {
+        let value = match (self.kind, self.get(config)) {
+            (Kind::Toggle, Value::Bool(on)) => Value::Bool(!on),
+            (Kind::Number { min, max, step, .. }, Value::Number(n)) => {
+                Value::Number(if up { n + step } else { n - step }.clamp(min, max))
+            }
+            (Kind::Choice(names), Value::Name(name)) => {
+                let names = names();
+                let at = names.iter().position(|choice| choice.eq_ignore_ascii_case(&name));
+                let next = match at {
+                    Some(at) if up => (at + 1) % names.len(),
+                    Some(at) => (at + names.len() - 1) % names.len(),
+                    None => 0,
+                };
+                Value::Name(names[next].to_string())
+            }
+            (_, value) => value,
+        };
+        (self.set)(config, value);
+    }
+
+    /// Formats the value for display, toggles as `on` or `off`.
+    pub fn display(&self, config: &Config, on: &str, off: &str) -> String {
+        match (self.kind, self.get(config)) {
+            (_, Value::Bool(true)) => on.to_string(),
+            (_, Value::Bool(false)) => off.to_string(),
+            (Kind::Number { unit, .. }, Value::Number(n)) => format!("{}{}", n, unit),
+            (_, Value::Number(n)) => n.to_string(),
+            (_, Value::Name(name)) => name,
+        }
+    }
+}
+
+/// The settings on the preferences screen, in the order shown. Ranges
+/// match what loading the config accepts.
+pub const PREFERENCES: &[Preference] = &[
+    Preference {
+        section: "playback",
+        key: "volume",
+        label: "prefs.volume",
+        kind: Kind::Number { min: 0, max: 100, step: 5, unit: "%" },
+        get: |config| Value::Number(config.playback.volume.into()),
+        set: |config, value| {
+            if let Value::Number(n) = value {
+                config.playback.volume = n.clamp(0, 100) as u8;
+            }
+        },
+    },
+    Preference {
+        section: "playback",
+        key: "seek_step",
+        label: "prefs.seek_step",
+        kind: Kind::Number { min: 1, max: 60, step: 1, unit: "s" },
+        get: |config| Value::Number(config.playback.seek_step.into()),
+        set: |config, value| {
+            if let Value::Number(n) = value {
+                config.playback.seek_step = n.clamp(1, 60) as u32;
+            }
+        },
+    },
+    Preference {
+        section: "playback",
+        key: "replay_secs",
+        label: "prefs.replay_secs",
+        kind: Kind::Number { min: 1, max: 60, step: 1, unit: "s" },
+        get: |config| Value::Number(config.playback.replay_secs.into()),
+        set: |config, value| {
+            if let Value::Number(n) = value {
+                config.playback.replay_secs = n.clamp(1, 60) as u32;
+            }
+        },
+    },
+    Preference {
+        section: "playback",
+        key: "limiter",
+        label: "prefs.limiter",
+        kind: Kind::Toggle,
+        get: |config| Value::Bool(config.playback.limiter),
+        set: |config, value| {
+            if let Value::Bool(on) = value {
+                config.playback.limiter = on;
+            }
+        },
+    },
+    Preference {
+        section: "playback",
+        key: "stop_after_latched",
+        label: "prefs.stop_after_latched",
+        kind: Kind::Toggle,
+        get: |config| Value::Bool(config.playback.stop_after_latched),
+        set: |config, value| {
+            if let Value::Bool(on) = value {
+                config.playback.stop_after_latched = on;
+            }
+        },
+    },
+    Preference {
+        section: "playback",
+        key: "preload",
+        label: "prefs.preload",
+        kind: Kind::Toggle,
+        get: |config| Value::Bool(config.playback.preload),
+        set: |config, value| {
+            if let Value::Bool(on) = value {
+                config.playback.preload = on;
+            }
+        },
+    },
+    Preference {
+        section: "theme",
+        key: "preset",
+        label: "prefs.theme",
+        kind: Kind::Choice(|| themes::PRESETS.iter().map(|(name, _)| *name).collect()),
+        get: |config| Value::Name(config.theme.preset.clone()),
+        set: |config, value| {
+            if let Value::Name(name) = value {
+                config.theme.preset = name;
+            }
+        },
+    },
+    Preference {
+        section: "visualizer",
+        key: "enabled",
+        label: "prefs.visualizer",
+        kind: Kind::Toggle,
+        get: |config| Value::Bool(config.visualizer.enabled),
+        set: |config, value| {
+            if let Value::Bool(on) = value {
+                config.visualizer.enabled = on;
+            }
+        },
+    },
+    Preference {
+        section: "visualizer",
+        key: "fps",
+        label: "prefs.visualizer_fps",
+        kind: Kind::Number { min: 5, max: 60, step: 5, unit: " fps" },
+        get: |config| Value::Number(config.visualizer.fps.into()),
+        set: |config, value| {
+            if let Value::Number(n) = value {
+                config.visualizer.fps = n.clamp(5, 60) as u32;
+            }
+        },
+    },
+    Preference {
+        section: "notifications",
+        key: "enabled",
+        label: "prefs.notifications",
+        kind: Kind::Toggle,
+        get: |config| Value::Bool(config.notifications.enabled),
+        set: |config, value| {
+            if let Value::Bool(on) = value {
+                config.notifications.enabled = on;
+            }
+        },
+    },
+];
+
+/// Writes the given preferences into a config file, keeping everything
+/// else in it as written, comments and all. A file that does not parse is
+/// left alone.
+pub fn save(path: &Path, config: &Config, preferences: &[&Preference]) -> io::Result<()> {
+    let mut document: DocumentMut = match fs::read_to_string(path) {
+        Ok(contents) => contents.parse().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => DocumentMut::new(),
+        Err(e) => return Err(e),
+    };
+    for preference in preferences {
+        let section = document
+            .entry(preference.section)
+            .or_insert_with(toml_edit::table)
+            .as_table_like_mut()
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("[{}] is not a table", preference.section))
+            })?;
+        let mut value = preference.get(config).to_toml();
+        match section.get_mut(preference.key).and_then(Item::as_value_mut) {
+            // Replaced in place, so comments around the old value stay
+            Some(old) => {
+                *value.decor_mut() = old.decor().clone();
+                *old = value;
+            }
+            None => {
+                section.insert(preference.key, Item::Value(value));
+            }
+        }
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, document.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preference(key: &str) -> &'static Preference {
+        PREFERENCES.iter().find(|p| format!("{}.{}", p.section, p.key) == key).unwrap()
+    }
+
+    #[test]
+    fn test_step_each_kind() {
+        let mut config = Config::default();
+        let volume = preference("playback.volume");
+        volume.step(&mut config, true);
+        assert_eq!(config.playback.volume, 100);
+        volume.step(&mut config, false);
+        assert_eq!(volume.display(&config, "on", "off"), "95%");
+
+        let visualizer = preference("visualizer.enabled");
+        let was = config.visualizer.enabled;
+        visualizer.step(&mut config, true);
+        assert_eq!(config.visualizer.enabled, !was);
+
+        let theme = preference("theme.preset");
+        config.theme.preset = "default".to_string();
+        theme.step(&mut config, false);
+        assert_eq!(config.theme.preset, themes::PRESETS.last().unwrap().0);
+        theme.step(&mut config, true);
+        assert_eq!(config.theme.preset, "default");
+    }
+
+    #[test]
+    fn test_save_keeps_other_keys() {
+        let path = std::env::temp_dir().join(format!("juke_prefs_{}.toml", std::process::id()));
+        fs::write(&path, "[playback]\nvolume = 40\ndevice = \"USB\"\n\n[ui]\nno_color = true\n").unwrap();
+
+        let mut config = Config::default();
+        config.playback.volume = 70;
+        config.notifications.enabled = true;
+        save(&path, &config, &[preference("playback.volume"), preference("notifications.enabled")]).unwrap();
+
+        let table: toml::Table = toml::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(table["playback"]["volume"].as_integer(), Some(70));
+        assert_eq!(table["playback"]["device"].as_str(), Some("USB"));
+        assert_eq!(table["ui"]["no_color"].as_bool(), Some(true));
+        assert_eq!(table["notifications"]["enabled"].as_bool(), Some(true));
+        assert!(table["playback"].get("seek_step").is_none());
+    }
+
+    #[test]
+    fn test_save_keeps_comments() {
+        let path = std::env::temp_dir().join(format!("juke_prefs_comments_{}.toml", std::process::id()));
+        let contents = "# My juke setup\n\n[playback]\n# Quiet at night\nvolume = 40 # was 60\ndevice = \"USB\"\n";
+        fs::write(&path, contents).unwrap();
+
+        let mut config = Config::default();
+        config.playback.volume = 70;
+        save(&path, &config, &[preference("playback.volume")]).unwrap();
+
+        let saved = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(saved, contents.replace("volume = 40", "volume = 70"));
+    }
+}
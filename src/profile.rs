@@ -0,0 +1,110 @@
+//! Timing of startup, reported in the log and by `--profile-startup`.
+//!
+//! Each phase is timed with one pair of `Instant` reads, so the profile is
+//! always kept. The summary is a single line of `name=milliseconds` pairs in
+//! a fixed order, ending with the total and whatever no phase covered, so
+//! reports from different versions can be compared with diff.
+
+use std::time::{Duration, Instant};
+
+/// Phases of startup, timed one after another.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    started: Instant,
+    phases: Vec<(&'static str, Duration)>,
+    /// Time from start to the end of the last phase, once finished.
+    total: Option<Duration>,
+}
+
+impl Profile {
+    /// Starts timing.
+    pub fn start() -> Self {
+        Self { started: Instant::now(), phases: Vec::new(), total: None }
+    }
+
+    /// Runs `f`, recording how long it took as a phase.
+    pub fn time<T>(&mut self, name: &'static str, f: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let value = f();
+        self.add(name, started.elapsed());
+        value
+    }
+
+    /// Records a phase timed elsewhere. Adding to a phase already recorded
+    /// adds up.
+    pub fn add(&mut self, name: &'static str, took: Duration) {
+        match self.phases.iter_mut().find(|(phase, _)| *phase == name) {
+            Some((_, total)) => *total += took,
+            None => self.phases.push((name, took)),
+        }
+    }
+
+    /// Stops timing; startup is over.
+    pub fn finish(&mut self) {
+        self.total.get_or_insert_with(|| self.started.elapsed());
+    }
+
+    /// Returns the time from start to finish, or so far.
+    pub fn total(&self) -> Duration {
+        self.total.unwrap_or_else(|| self.started.elapsed())
+    }
+
+    /// Returns the time spent in a phase.
+    #[cfg(test)]
+    pub fn phase(&self, name: &str) -> Option<Duration> {
+        self.phases.iter().find(|(phase, _)| *phase == name).map(|(_, took)| *took)
+    }
+
+    /// Formats the profile as one line, such as
+    /// `startup total=182.4ms config=1.2ms ... other=0.3ms`.
+    pub fn summary(&self) -> String {
+        let total = self.total();
+        let covered: Duration = self.phases.iter().map(|(_, took)| *took).sum();
+        let mut line = format!("startup total={}", millis(total));
+        for (name, took) in &self.phases {
+            line.push_str(&format!(" {}={}", name, millis(*took)));
+        }
+        line.push_str(&format!(" other={}", millis(total.saturating_sub(covered))));
+        line
+    }
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:.1}ms", duration.as_secs_f64() * 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phases_add_up_to_total() {
+        let mut profile = Profile::start();
+        profile.time("first", || std::thread::sleep(Duration::from_millis(20)));
+        profile.time("second", || std::thread::sleep(Duration::from_millis(10)));
+        profile.add("second", Duration::from_millis(5));
+        profile.add("none", Duration::ZERO);
+        std::thread::sleep(Duration::from_millis(5));
+        profile.finish();
+
+        let sum: Duration = ["first", "second", "none"].iter().filter_map(|name| profile.phase(name)).sum();
+        let total = profile.total();
+        // The phases cover all but the last sleep, and the added 5ms never ran
+        assert!(sum >= Duration::from_millis(35));
+        assert!(total >= Duration::from_millis(35), "total {:?}", total);
+        assert!(total.abs_diff(sum) < Duration::from_millis(20), "sum {:?}, total {:?}", sum, total);
+
+        // Finished is finished
+        std::thread::sleep(Duration::from_millis(2));
+        assert_eq!(profile.total(), total);
+    }
+
+    #[test]
+    fn test_summary_format() {
+        let mut profile = Profile::start();
+        profile.add("config", Duration::from_micros(1200));
+        profile.add("walk", Duration::ZERO);
+        profile.total = Some(Duration::from_millis(3));
+        assert_eq!(profile.summary(), "startup total=3.0ms config=1.2ms walk=0.0ms other=1.8ms");
+    }
+}
@@ -0,0 +1,158 @@
+//! What was typed into each kind of prompt before, so a path given once
+//! need not be typed again.
+//!
+//! Entries are kept per kind in `prompts.toml` in the state directory, the
+//! newest last and at most [`LIMIT`] of each. Kinds that may hold something
+//! private are kept only until juke exits.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Entries kept of each kind.
+pub const LIMIT: usize = 50;
+
+/// What a prompt asks for; each kind has a history of its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// Where to save a playlist.
+    SavePath,
+    /// A new title for a track.
+    Rename,
+}
+
+impl Kind {
+    fn key(self) -> &'static str {
+        match self {
+            Kind::SavePath => "save_path",
+            Kind::Rename => "rename",
+        }
+    }
+
+    /// Whether entries are saved for the next session. Titles are as often
+    /// notes to oneself as names, so they stay out of the state directory.
+    fn persists(self) -> bool {
+        match self {
+            Kind::SavePath => true,
+            Kind::Rename => false,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct HistoryFile {
+    /// Entries of each kind, oldest first.
+    #[serde(default)]
+    prompts: BTreeMap<String, Vec<String>>,
+}
+
+/// The entries of every kind of prompt.
+#[derive(Debug, Default)]
+pub struct PromptHistory {
+    file: HistoryFile,
+    /// File the history was loaded from and is saved to.
+    path: Option<PathBuf>,
+}
+
+impl PromptHistory {
+    /// Loads the history from a state directory. Missing or unreadable
+    /// files give none; without a directory it lasts only until juke exits.
+    pub fn load(state_dir: Option<&Path>) -> Self {
+        let path = state_dir.map(|dir| dir.join("prompts.toml"));
+        let file = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default();
+        Self { file, path }
+    }
+
+    /// Saves the kinds that persist to the file the history was loaded from.
+    pub fn save(&self) -> std::io::Result<()> {
+        let path = self.path.as_ref().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "no state directory")
+        })?;
+        let saved = HistoryFile {
+            prompts: [Kind::SavePath, Kind::Rename]
+                .into_iter()
+                .filter(|kind| kind.persists())
+                .filter_map(|kind| Some((kind.key().to_string(), self.file.prompts.get(kind.key())?.clone())))
+                .collect(),
+        };
+        let toml_string = toml::to_string_pretty(&saved).map_err(std::io::Error::other)?;
+        fs::write(path, toml_string)
+    }
+
+    /// The entries of a kind, oldest first.
+    pub fn entries(&self, kind: Kind) -> &[String] {
+        self.file.prompts.get(kind.key()).map_or(&[], Vec::as_slice)
+    }
+
+    /// Adds an entry, unless it is blank or the same as the one before it,
+    /// dropping the oldest beyond [`LIMIT`].
+    pub fn add(&mut self, kind: Kind, entry: &str) {
+        let entry = entry.trim();
+        if entry.is_empty() {
+            return;
+        }
+        let entries = self.file.prompts.entry(kind.key().to_string()).or_default();
+        if entries.last().is_some_and(|last| last == entry) {
+            return;
+        }
+        entries.push(entry.to_string());
+        if entries.len() > LIMIT {
+            entries.drain(..entries.len() - LIMIT);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_skips_repeats_and_blanks() {
+        let mut history = PromptHistory::default();
+        history.add(Kind::SavePath, "~/a.m3u");
+        history.add(Kind::SavePath, " ~/a.m3u ");
+        history.add(Kind::SavePath, "  ");
+        history.add(Kind::SavePath, "~/b.m3u");
+        history.add(Kind::SavePath, "~/a.m3u");
+        assert_eq!(history.entries(Kind::SavePath), ["~/a.m3u", "~/b.m3u", "~/a.m3u"]);
+        assert!(history.entries(Kind::Rename).is_empty());
+    }
+
+    #[test]
+    fn test_add_keeps_the_newest() {
+        let mut history = PromptHistory::default();
+        for i in 0..LIMIT + 5 {
+            history.add(Kind::Rename, &i.to_string());
+        }
+        let entries = history.entries(Kind::Rename);
+        assert_eq!(entries.len(), LIMIT);
+        assert_eq!(entries[0], "5");
+        assert_eq!(entries[LIMIT - 1], (LIMIT + 4).to_string());
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = std::env::temp_dir().join(format!("juke_prompts_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut history = PromptHistory::load(Some(&dir));
+        history.add(Kind::SavePath, "~/playlists/live.m3u");
+        history.add(Kind::SavePath, "~/playlists/chill.m3u");
+        history.add(Kind::Rename, "Secret demo");
+        history.save().unwrap();
+
+        // Titles are not written down
+        let loaded = PromptHistory::load(Some(&dir));
+        assert_eq!(loaded.entries(Kind::SavePath), ["~/playlists/live.m3u", "~/playlists/chill.m3u"]);
+        assert!(loaded.entries(Kind::Rename).is_empty());
+        assert!(!fs::read_to_string(dir.join("prompts.toml")).unwrap().contains("Secret"));
+
+        assert!(PromptHistory::load(None).save().is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
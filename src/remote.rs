@@ -0,0 +1,811 @@
+//! Remote control of a running instance.
+//!
+//! Clients send one JSON request per line, such as `{"command":"seek","position":30}`,
+//! and get one JSON response per line: `{"ok":true}` or
+//! `{"ok":false,"error":"..."}`. The transport is a Unix socket, or a named
+//! pipe on Windows, behind the [`RemoteControl`] trait.
+
+use serde_json::{json, Value};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Longest request line accepted.
+const MAX_REQUEST_LEN: usize = 64 * 1024;
+
+/// Longest response line accepted; playlists can be large.
+const MAX_RESPONSE_LEN: usize = 64 * 1024 * 1024;
+
+/// How long a client waits for the player to answer.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A command sent to a running instance.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    PlayPause,
+    Play,
+    Pause,
+    Next,
+    Previous,
+    /// Play the track at a playlist index.
+    PlayAt(usize),
+    /// Jump to a position in seconds.
+    Seek(f64),
+    /// Set the volume in percent.
+    Volume(u8),
+    Quit,
+    /// Report what is playing.
+    Status,
+    /// List the tracks in the playlist.
+    Playlist,
+}
+
+impl Command {
+    /// Names accepted by `--send`, with the argument each takes.
+    pub const USAGE: &'static str =
+        "play_pause, play, pause, next, previous, play_at <index>, seek <seconds>, volume <0-100>, quit, status, playlist";
+
+    /// Parses a command name and its optional argument.
+    pub fn parse(name: &str, arg: Option<&str>) -> Result<Self, String> {
+        let number = |what: &str| -> Result<f64, String> {
+            arg.and_then(|a| a.trim().parse::<f64>().ok())
+                .filter(|n| n.is_finite())
+                .ok_or_else(|| format!("'{}' needs {}", name, what))
+        };
+        match name {
+            "play_pause" => Ok(Command::PlayPause),
+            "play" => Ok(Command::Play),
+            "pause" => Ok(Command::Pause),
+            "next" => Ok(Command::Next),
+            "previous" => Ok(Command::Previous),
+            "quit" => Ok(Command::Quit),
+            "status" => Ok(Command::Status),
+            "playlist" => Ok(Command::Playlist),
+            "play_at" => {
+                let index = number("a playlist index")?;
+                if index < 0.0 || index.fract() != 0.0 {
+                    return Err("'play_at' needs a playlist index of 0 or more".to_string());
+                }
+                Ok(Command::PlayAt(index as usize))
+            }
+            "seek" => {
+                let position = number("a position in seconds")?;
+                if position < 0.0 {
+                    return Err("'seek' needs a position of 0 or more".to_string());
+                }
+                Ok(Command::Seek(position))
+            }
+            "volume" => {
+                let level = number("a level from 0 to 100")?;
+                if !(0.0..=100.0).contains(&level) {
+                    return Err("'volume' needs a level from 0 to 100".to_string());
+                }
+                Ok(Command::Volume(level.round() as u8))
+            }
+            _ => Err(format!("Unknown command '{}' (valid commands: {})", name, Self::USAGE)),
+        }
+    }
+
+    /// Parses a request object.
+    pub fn from_json(request: &Value) -> Result<Self, String> {
+        let name = request
+            .get("command")
+            .and_then(Value::as_str)
+            .ok_or("Request needs a \"command\" string")?;
+        let arg = match name {
+            "play_at" => request.get("index"),
+            "seek" => request.get("position"),
+            "volume" => request.get("level"),
+            _ => None,
+        };
+        let arg = arg.and_then(Value::as_f64).map(|n| n.to_string());
+        Self::parse(name, arg.as_deref())
+    }
+
+    /// Builds the request object for this command.
+    pub fn to_json(&self) -> Value {
+        let (name, arg) = match self {
+            Command::PlayPause => ("play_pause", None),
+            Command::Play => ("play", None),
+            Command::Pause => ("pause", None),
+            Command::Next => ("next", None),
+            Command::Previous => ("previous", None),
+            Command::PlayAt(index) => ("play_at", Some(("index", json!(index)))),
+            Command::Seek(position) => ("seek", Some(("position", json!(position)))),
+            Command::Volume(level) => ("volume", Some(("level", json!(level)))),
+            Command::Quit => ("quit", None),
+            Command::Status => ("status", None),
+            Command::Playlist => ("playlist", None),
+        };
+        let mut request = json!({ "command": name });
+        if let Some((key, value)) = arg {
+            request[key] = value;
+        }
+        request
+    }
+}
+
+/// The answer to a request.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Response {
+    /// Success, with optional fields to add to the reply.
+    Ok(Vec<(String, Value)>),
+    Error(String),
+}
+
+impl Response {
+    pub fn ok() -> Self {
+        Response::Ok(Vec::new())
+    }
+
+    pub fn to_json(&self) -> Value {
+        match self {
+            Response::Ok(fields) => {
+                let mut all = json!({ "ok": true });
+                for (key, value) in fields {
+                    all[key.as_str()] = value.clone();
+                }
+                all
+            }
+            Response::Error(message) => json!({
+                "ok": false,
+                "error": message.as_str(),
+            }),
+        }
+    }
+}
+
+/// A snapshot of the player, as returned by the `status` command.
+///
+/// The JSON shape is part of the command-line interface; see [`STATUS_SCHEMA`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Status {
+    pub track: Option<StatusTrack>,
+    pub position_secs: f64,
+    pub duration_secs: f64,
+    /// "playing", "paused", or "stopped".
+    pub state: &'static str,
+    /// Volume in percent.
+    pub volume: u8,
+    pub shuffle: bool,
+    /// "off", "all", or "single".
+    pub repeat: &'static str,
+    /// Zero-based position of the current track in the playlist.
+    pub index: Option<usize>,
+    pub length: usize,
+    /// Changes whenever tracks are added, removed, reordered, or retagged.
+    pub playlist_version: u64,
+}
+
+/// The current track in a [`Status`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatusTrack {
+    pub path: String,
+    pub title: String,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+}
+
+/// Description of the `--status` output, shown in `--help`.
+pub const STATUS_SCHEMA: &str = r#"{
+  "track": {                  (null when nothing is loaded)
+    "path": string,
+    "title": string,          (file name when untagged)
+    "artist": string | null,
+    "album": string | null
+  },
+  "position": number,         (seconds)
+  "duration": number,         (seconds, 0 when unknown)
+  "state": "playing" | "paused" | "stopped",
+  "volume": number,           (0-100)
+  "shuffle": boolean,
+  "repeat": "off" | "all" | "single",
+  "index": number | null,     (zero-based playlist position)
+  "length": number,           (tracks in the playlist)
+  "playlist_version": number  (changes when the playlist does)
+}"#;
+
+impl Status {
+    pub fn to_json(&self) -> Value {
+        let track = self.track.as_ref().map_or(Value::Null, |track| {
+            json!({
+                "path": track.path.as_str(),
+                "title": track.title.as_str(),
+                "artist": track.artist.clone(),
+                "album": track.album.clone(),
+            })
+        });
+        json!({
+            "track": track,
+            "position": self.position_secs,
+            "duration": self.duration_secs,
+            "state": self.state,
+            "volume": self.volume,
+            "shuffle": self.shuffle,
+            "repeat": self.repeat,
+            "index": self.index,
+            "length": self.length,
+            "playlist_version": self.playlist_version,
+        })
+    }
+}
+
+/// A playlist entry, as returned by the `playlist` command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaylistEntry {
+    pub path: String,
+    pub title: String,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    /// Length in seconds, if known.
+    pub duration_secs: Option<f64>,
+}
+
+impl PlaylistEntry {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "path": self.path.as_str(),
+            "title": self.title.as_str(),
+            "artist": self.artist.clone(),
+            "album": self.album.clone(),
+            "duration": self.duration_secs,
+        })
+    }
+}
+
+/// A bidirectional byte stream to one client.
+pub trait Connection: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Connection for T {}
+
+/// A listening endpoint that hands out client connections one at a time.
+pub trait RemoteControl: Send {
+    /// Waits for the next client.
+    fn accept(&mut self) -> io::Result<Box<dyn Connection>>;
+}
+
+/// Serves requests on one connection until the client disconnects.
+///
+/// Requests may arrive split across reads or several in one read.
+pub fn serve_connection<S: Read + Write>(
+    mut stream: S,
+    mut dispatch: impl FnMut(Command) -> Response,
+) -> io::Result<()> {
+    let mut pending = Vec::new();
+    let mut buffer = [0u8; 4096];
+
+    loop {
+        let read = match stream.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        pending.extend_from_slice(&buffer[..read]);
+
+        while let Some(end) = pending.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = pending.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            if line.trim().is_empty() {
+                continue;
+            }
+            let response = match serde_json::from_str::<Value>(line.trim()) {
+                Ok(request) => match Command::from_json(&request) {
+                    Ok(command) => dispatch(command),
+                    Err(e) => Response::Error(e),
+                },
+                Err(e) => Response::Error(format!("Invalid JSON: {}", e)),
+            };
+            writeln!(stream, "{}", response.to_json())?;
+            stream.flush()?;
+        }
+
+        if pending.len() > MAX_REQUEST_LEN {
+            writeln!(stream, "{}", Response::Error("Request too long".to_string()).to_json())?;
+            return Ok(());
+        }
+    }
+}
+
+/// Sends one request and reads the response line.
+pub fn request(connection: &mut dyn Connection, request: &Value) -> io::Result<Value> {
+    writeln!(connection, "{}", request)?;
+    connection.flush()?;
+
+    // One byte past the limit tells a response that is too long
+    let mut response = Vec::new();
+    BufReader::new(connection.take(MAX_RESPONSE_LEN as u64 + 1)).read_until(b'\n', &mut response)?;
+    if response.last() == Some(&b'\n') {
+        response.pop();
+    }
+    if response.len() > MAX_RESPONSE_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "response too long"));
+    }
+
+    serde_json::from_str::<Value>(&String::from_utf8_lossy(&response))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// A command waiting for the player, with the channel for its answer.
+pub type Pending = (Command, mpsc::Sender<Response>);
+
+/// Hands a command to the player and waits for its answer.
+pub fn forward(tx: &mpsc::Sender<Pending>, command: Command) -> Response {
+    let (reply_tx, reply_rx) = mpsc::channel();
+    if tx.send((command, reply_tx)).is_err() {
+        return Response::Error("juke is shutting down".to_string());
+    }
+    reply_rx
+        .recv_timeout(REPLY_TIMEOUT)
+        .unwrap_or_else(|_| Response::Error("juke did not respond".to_string()))
+}
+
+/// Accepts clients on a background thread and forwards their commands.
+pub struct RemoteServer {
+    rx: mpsc::Receiver<Pending>,
+    #[cfg(unix)]
+    _socket: unix::SocketGuard,
+}
+
+impl RemoteServer {
+    /// Starts listening on the platform endpoint.
+    ///
+    /// Fails with `AddrInUse` if another instance is already listening.
+    pub fn start() -> io::Result<Self> {
+        #[cfg(unix)]
+        {
+            let (listener, guard) = unix::UnixRemote::bind(&unix::socket_path())?;
+            Ok(Self::spawn(Box::new(listener), guard))
+        }
+        #[cfg(windows)]
+        {
+            Ok(Self::spawn(Box::new(windows::PipeRemote::create(&windows::pipe_name())?)))
+        }
+        #[cfg(not(any(unix, windows)))]
+        {
+            Err(io::Error::new(io::ErrorKind::Unsupported, "remote control is not supported on this platform"))
+        }
+    }
+
+    fn spawn(
+        mut listener: Box<dyn RemoteControl>,
+        #[cfg(unix)] socket: unix::SocketGuard,
+    ) -> Self {
+        let (tx, rx) = mpsc::channel::<Pending>();
+        std::thread::spawn(move || {
+            while let Ok(connection) = listener.accept() {
+                let tx = tx.clone();
+                std::thread::spawn(move || {
+                    let _ = serve_connection(connection, |command| forward(&tx, command));
+                });
+            }
+        });
+        Self {
+            rx,
+            #[cfg(unix)]
+            _socket: socket,
+        }
+    }
+
+    /// Returns the next command waiting to be handled, if any.
+    pub fn try_next(&self) -> Option<Pending> {
+        self.rx.try_recv().ok()
+    }
+}
+
+/// Connects to a running instance.
+///
+/// Fails with `NotFound` or `ConnectionRefused` when none is running.
+pub fn connect() -> io::Result<Box<dyn Connection>> {
+    #[cfg(unix)]
+    {
+        Ok(Box::new(std::os::unix::net::UnixStream::connect(unix::socket_path())?))
+    }
+    #[cfg(windows)]
+    {
+        Ok(Box::new(windows::connect(&windows::pipe_name())?))
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "remote control is not supported on this platform"))
+    }
+}
+
+/// Returns true if a connection error means no instance is running.
+pub fn is_not_running(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused
+    )
+}
+
+/// Returns the current user's name for per-user endpoints, keeping only safe characters.
+fn user_name() -> String {
+    let name = std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_default();
+    let name: String = name
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        .collect();
+    if name.is_empty() { "user".to_string() } else { name }
+}
+
+#[cfg(unix)]
+mod unix {
+    use super::{Connection, RemoteControl};
+    use std::fs;
+    use std::io;
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::{Path, PathBuf};
+
+    /// Returns the socket path: in `$XDG_RUNTIME_DIR` if set, else the temp directory.
+    pub fn socket_path() -> PathBuf {
+        let dir = std::env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(std::env::temp_dir);
+        dir.join(format!("juke-{}.sock", super::user_name()))
+    }
+
+    pub struct UnixRemote {
+        listener: UnixListener,
+    }
+
+    /// Removes the socket file when the server goes away.
+    pub struct SocketGuard {
+        path: PathBuf,
+    }
+
+    impl Drop for SocketGuard {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+
+    impl UnixRemote {
+        /// Binds the socket, replacing a stale one left by a crashed instance.
+        pub fn bind(path: &Path) -> io::Result<(Self, SocketGuard)> {
+            if path.exists() {
+                if UnixStream::connect(path).is_ok() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AddrInUse,
+                        format!(
+                            "another juke is listening on {}; control it with juke --send",
+                            path.display()
+                        ),
+                    ));
+                }
+                fs::remove_file(path)?;
+            }
+
+            let listener = UnixListener::bind(path)?;
+            // Only the owner may control playback
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+            let guard = SocketGuard {
+                path: path.to_path_buf(),
+            };
+            Ok((Self { listener }, guard))
+        }
+    }
+
+    impl RemoteControl for UnixRemote {
+        fn accept(&mut self) -> io::Result<Box<dyn Connection>> {
+            let (stream, _) = self.listener.accept()?;
+            Ok(Box::new(stream))
+        }
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use super::{Connection, RemoteControl};
+    use std::ffi::c_void;
+    use std::fs::File;
+    use std::io;
+    use std::os::windows::io::{AsRawHandle, FromRawHandle, RawHandle};
+
+    type Handle = *mut c_void;
+
+    const PIPE_ACCESS_DUPLEX: u32 = 0x0000_0003;
+    const FILE_FLAG_FIRST_PIPE_INSTANCE: u32 = 0x0008_0000;
+    const PIPE_TYPE_BYTE: u32 = 0x0000_0000;
+    const PIPE_REJECT_REMOTE_CLIENTS: u32 = 0x0000_0008;
+    const PIPE_UNLIMITED_INSTANCES: u32 = 255;
+    const ERROR_ACCESS_DENIED: i32 = 5;
+    const ERROR_PIPE_BUSY: i32 = 231;
+    const ERROR_PIPE_CONNECTED: i32 = 535;
+    const BUFFER_SIZE: u32 = 4096;
+    /// Longest wait for a free pipe instance, in milliseconds.
+    const BUSY_TIMEOUT_MS: u32 = 2000;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn CreateNamedPipeW(
+            name: *const u16,
+            open_mode: u32,
+            pipe_mode: u32,
+            max_instances: u32,
+            out_buffer_size: u32,
+            in_buffer_size: u32,
+            default_timeout: u32,
+            security_attributes: *mut c_void,
+        ) -> Handle;
+        fn ConnectNamedPipe(pipe: Handle, overlapped: *mut c_void) -> i32;
+        fn WaitNamedPipeW(name: *const u16, timeout: u32) -> i32;
+    }
+
+    /// Returns the pipe name, `\\.\pipe\juke-<user>`.
+    pub fn pipe_name() -> String {
+        format!(r"\\.\pipe\juke-{}", super::user_name())
+    }
+
+    /// Opens the pipe as a client, waiting while every instance is busy
+    /// serving other clients.
+    pub fn connect(name: &str) -> io::Result<File> {
+        let wide: Vec<u16> = name.encode_utf16().chain(Some(0)).collect();
+        loop {
+            match std::fs::OpenOptions::new().read(true).write(true).open(name) {
+                Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY) => {
+                    // SAFETY: `wide` is NUL-terminated and outlives the call
+                    if unsafe { WaitNamedPipeW(wide.as_ptr(), BUSY_TIMEOUT_MS) } == 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// A named pipe server. Each client gets its own pipe instance.
+    pub struct PipeRemote {
+        name: Vec<u16>,
+        /// The instance waiting for the next client.
+        next: Option<File>,
+    }
+
+    impl PipeRemote {
+        /// Creates the first pipe instance.
+        ///
+        /// Fails with `AddrInUse` if another instance owns the pipe.
+        pub fn create(name: &str) -> io::Result<Self> {
+            let name: Vec<u16> = name.encode_utf16().chain(Some(0)).collect();
+            let first = create_instance(&name, true).map_err(|e| {
+                if e.raw_os_error() == Some(ERROR_ACCESS_DENIED) {
+                    io::Error::new(io::ErrorKind::AddrInUse, "another juke owns the pipe; control it with juke --send")
+                } else {
+                    e
+                }
+            })?;
+            Ok(Self {
+                name,
+                next: Some(first),
+            })
+        }
+    }
+
+    /// Creates a pipe instance; only the first may claim the name.
+    fn create_instance(name: &[u16], first: bool) -> io::Result<File> {
+        let open_mode = PIPE_ACCESS_DUPLEX | if first { FILE_FLAG_FIRST_PIPE_INSTANCE } else { 0 };
+        // SAFETY: `name` is NUL-terminated and outlives the call
+        let handle = unsafe {
+            CreateNamedPipeW(
+                name.as_ptr(),
+                open_mode,
+                PIPE_TYPE_BYTE | PIPE_REJECT_REMOTE_CLIENTS,
+                PIPE_UNLIMITED_INSTANCES,
+                BUFFER_SIZE,
+                BUFFER_SIZE,
+                0,
+                std::ptr::null_mut(),
+            )
+        };
+        if handle.is_null() || handle as isize == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: the handle is valid and owned by nothing else
+        Ok(unsafe { File::from_raw_handle(handle as RawHandle) })
+    }
+
+    impl RemoteControl for PipeRemote {
+        fn accept(&mut self) -> io::Result<Box<dyn Connection>> {
+            let pipe = match self.next.take() {
+                Some(pipe) => pipe,
+                None => create_instance(&self.name, false)?,
+            };
+            // SAFETY: the pipe handle is valid for the duration of the call
+            let connected = unsafe { ConnectNamedPipe(pipe.as_raw_handle() as Handle, std::ptr::null_mut()) };
+            if connected == 0 {
+                let error = io::Error::last_os_error();
+                // A client that connected before the call is fine
+                if error.raw_os_error() != Some(ERROR_PIPE_CONNECTED) {
+                    return Err(error);
+                }
+            }
+            // Another client finds an instance waiting while this one is
+            // served; if it cannot be made now, the next call tries again
+            self.next = create_instance(&self.name, false).ok();
+            Ok(Box::new(pipe))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// An in-memory duplex stream that returns at most `chunk` bytes per read.
+    struct Duplex {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+        chunk: usize,
+    }
+
+    impl Duplex {
+        fn new(input: &str, chunk: usize) -> Self {
+            Self {
+                input: Cursor::new(input.as_bytes().to_vec()),
+                output: Vec::new(),
+                chunk,
+            }
+        }
+
+        fn responses(&self) -> Vec<String> {
+            String::from_utf8_lossy(&self.output).lines().map(String::from).collect()
+        }
+    }
+
+    impl Read for Duplex {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(self.chunk);
+            self.input.read(&mut buf[..len])
+        }
+    }
+
+    impl Write for Duplex {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn serve(input: &str, chunk: usize) -> (Vec<String>, Vec<Command>) {
+        let mut stream = Duplex::new(input, chunk);
+        let mut received = Vec::new();
+        serve_connection(&mut stream, |command| {
+            received.push(command);
+            Response::ok()
+        })
+        .unwrap();
+        (stream.responses(), received)
+    }
+
+    #[test]
+    fn test_requests_split_across_reads() {
+        let input = "{\"command\":\"next\"}\n{\"command\": \"seek\", \"position\": 30}\n\n{\"command\":\"volume\",\"level\":55}\n";
+        for chunk in [1, 3, 4096] {
+            let (responses, received) = serve(input, chunk);
+            assert_eq!(responses, [r#"{"ok":true}"#; 3], "chunk size {}", chunk);
+            assert_eq!(received, [Command::Next, Command::Seek(30.0), Command::Volume(55)]);
+        }
+    }
+
+    #[test]
+    fn test_bad_requests_get_errors() {
+        let input = "not json\n{\"command\":\"dance\"}\n{\"command\":\"volume\",\"level\":150}\n{\"command\":\"pause\"}\n";
+        let (responses, received) = serve(input, 7);
+        assert_eq!(responses.len(), 4);
+        for response in &responses[..3] {
+            let json = serde_json::from_str::<Value>(response).unwrap();
+            assert_eq!(json.get("ok"), Some(&Value::Bool(false)));
+            assert!(json.get("error").and_then(Value::as_str).is_some());
+        }
+        assert_eq!(received, [Command::Pause]);
+    }
+
+    #[test]
+    fn test_unterminated_request_is_ignored() {
+        let (responses, received) = serve("{\"command\":\"quit\"}", 5);
+        assert!(responses.is_empty());
+        assert!(received.is_empty());
+    }
+
+    #[test]
+    fn test_command_json_round_trip() {
+        let commands = [
+            Command::PlayPause,
+            Command::Play,
+            Command::Pause,
+            Command::Next,
+            Command::Previous,
+            Command::PlayAt(3),
+            Command::Seek(12.5),
+            Command::Volume(40),
+            Command::Quit,
+            Command::Status,
+            Command::Playlist,
+        ];
+        for command in commands {
+            assert_eq!(Command::from_json(&command.to_json()), Ok(command.clone()));
+        }
+        assert!(Command::parse("seek", None).is_err());
+        assert!(Command::parse("seek", Some("-1")).is_err());
+        assert_eq!(Command::parse("volume", Some("33")), Ok(Command::Volume(33)));
+        assert_eq!(Command::parse("play_at", Some("0")), Ok(Command::PlayAt(0)));
+        assert!(Command::parse("play_at", Some("1.5")).is_err());
+    }
+
+    #[test]
+    fn test_status_serialization() {
+        let mut status = Status {
+            track: Some(StatusTrack {
+                path: "/music/a \"b\".mp3".to_string(),
+                title: "A".to_string(),
+                artist: Some("Band".to_string()),
+                album: None,
+            }),
+            position_secs: 12.5,
+            duration_secs: 180.0,
+            state: "playing",
+            volume: 80,
+            shuffle: false,
+            repeat: "all",
+            index: Some(0),
+            length: 3,
+            playlist_version: 7,
+        };
+        assert_eq!(
+            status.to_json().to_string(),
+            concat!(
+                r#"{"track":{"path":"/music/a \"b\".mp3","title":"A","artist":"Band","album":null},"#,
+                r#""position":12.5,"duration":180.0,"state":"playing","volume":80,"shuffle":false,"#,
+                r#""repeat":"all","index":0,"length":3,"playlist_version":7}"#
+            )
+        );
+
+        status.track = None;
+        status.index = None;
+        let json = status.to_json();
+        assert_eq!(json.get("track"), Some(&Value::Null));
+        assert_eq!(json.get("index"), Some(&Value::Null));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_socket_sequential_clients() {
+        let path = std::env::temp_dir().join(format!("juke_remote_{}.sock", std::process::id()));
+        let (listener, guard) = unix::UnixRemote::bind(&path).unwrap();
+        // A second server on the same socket is refused
+        let second = unix::UnixRemote::bind(&path).map(|_| ());
+        assert_eq!(second.unwrap_err().kind(), io::ErrorKind::AddrInUse);
+
+        let server = RemoteServer::spawn(Box::new(listener), guard);
+        let player = std::thread::spawn(move || {
+            for _ in 0..2 {
+                let (command, reply) = loop {
+                    if let Some(pending) = server.try_next() {
+                        break pending;
+                    }
+                    std::thread::sleep(Duration::from_millis(5));
+                };
+                let _ = reply.send(match command {
+                    Command::Next => Response::ok(),
+                    _ => Response::Error("nope".to_string()),
+                });
+            }
+        });
+
+        for (command, ok) in [(Command::Next, true), (Command::Quit, false)] {
+            let mut connection = std::os::unix::net::UnixStream::connect(&path).unwrap();
+            let response = request(&mut connection, &command.to_json()).unwrap();
+            assert_eq!(response.get("ok"), Some(&Value::Bool(ok)));
+        }
+        player.join().unwrap();
+        // The server was dropped with the player thread, removing the socket
+        assert!(!path.exists());
+    }
+}
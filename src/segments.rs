@@ -0,0 +1,241 @@
+//! Sidecar files that split one audio file into named segments, such as a
+//! live set without a cue sheet or a rip with hidden pregap content.
+//!
+//! `set.flac` is split by `set.segments.toml` next to it:
+//!
+//! ```toml
+//! [[segment]]
+//! title = "Intro"
+//! start = "0:00"
+//!
+//! [[segment]]
+//! title = "Opening Song"
+//! start = "2:31"
+//! end = "7:02.5"
+//! ```
+//!
+//! Each segment becomes a virtual track playing from its `start` to its
+//! `end`, or to the next segment's start, or to the end of the file.
+
+use crate::playlist::Track;
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Suffix replacing the audio file's extension.
+const SIDECAR_SUFFIX: &str = "segments.toml";
+
+/// A named part of an audio file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub title: String,
+    pub artist: Option<String>,
+    pub start: Duration,
+    pub end: Option<Duration>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SidecarFile {
+    #[serde(default)]
+    segment: Vec<RawSegment>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawSegment {
+    title: String,
+    artist: Option<String>,
+    start: Time,
+    end: Option<Time>,
+}
+
+/// A time given as seconds or as `[h:]m:ss[.fff]`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Time {
+    Seconds(f64),
+    Text(String),
+}
+
+impl Time {
+    fn to_duration(&self) -> Result<Duration, SegmentError> {
+        let seconds = match self {
+            Time::Seconds(seconds) => Some(*seconds),
+            Time::Text(text) => parse_time(text),
+        };
+        seconds
+            .filter(|s| s.is_finite() && *s >= 0.0)
+            .map(Duration::from_secs_f64)
+            .ok_or_else(|| SegmentError::BadTime(self.describe()))
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Time::Seconds(seconds) => seconds.to_string(),
+            Time::Text(text) => text.clone(),
+        }
+    }
+}
+
+/// Parses `[h:]m:ss[.fff]` or plain seconds.
+fn parse_time(text: &str) -> Option<f64> {
+    let mut seconds = 0.0;
+    let parts: Vec<&str> = text.trim().split(':').collect();
+    if parts.len() > 3 {
+        return None;
+    }
+    for (i, part) in parts.iter().enumerate() {
+        let last = i + 1 == parts.len();
+        // Only the seconds may have a fraction, and only the first part may exceed 59
+        let value: f64 = if last { part.parse().ok()? } else { f64::from(part.parse::<u32>().ok()?) };
+        if i > 0 && value >= 60.0 {
+            return None;
+        }
+        seconds = seconds * 60.0 + value;
+    }
+    Some(seconds)
+}
+
+/// Errors reading a sidecar file.
+#[derive(Debug, PartialEq)]
+pub enum SegmentError {
+    Io(String),
+    Parse(String),
+    BadTime(String),
+    /// A segment ends before it starts or starts before the previous one.
+    Order(String),
+}
+
+impl std::fmt::Display for SegmentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SegmentError::Io(msg) => write!(f, "Cannot read segments: {}", msg),
+            SegmentError::Parse(msg) => write!(f, "Invalid segments file: {}", msg),
+            SegmentError::BadTime(time) => write!(f, "Invalid segment time {:?}", time),
+            SegmentError::Order(title) => write!(f, "Segment {:?} is out of order", title),
+        }
+    }
+}
+
+/// Returns the sidecar path for an audio file.
+pub fn sidecar_path(audio: &Path) -> PathBuf {
+    audio.with_extension(SIDECAR_SUFFIX)
+}
+
+/// Reads the segments defined for an audio file, or `None` without a sidecar.
+pub fn load(audio: &Path) -> Result<Option<Vec<Segment>>, SegmentError> {
+    let path = sidecar_path(audio);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let contents = fs::read_to_string(&path).map_err(|e| SegmentError::Io(e.to_string()))?;
+    parse(&contents).map(Some)
+}
+
+/// Parses the contents of a sidecar file.
+fn parse(contents: &str) -> Result<Vec<Segment>, SegmentError> {
+    let file: SidecarFile = toml::from_str(contents).map_err(|e| SegmentError::Parse(e.message().to_string()))?;
+    let mut segments: Vec<Segment> = Vec::with_capacity(file.segment.len());
+    for raw in file.segment {
+        let start = raw.start.to_duration()?;
+        let end = raw.end.as_ref().map(Time::to_duration).transpose()?;
+        let after_previous = segments.last().is_none_or(|previous| previous.start < start);
+        if !after_previous || end.is_some_and(|end| end <= start) {
+            return Err(SegmentError::Order(raw.title));
+        }
+        segments.push(Segment { title: raw.title, artist: raw.artist, start, end });
+    }
+    Ok(segments)
+}
+
+/// Turns a track into one virtual track per segment.
+///
+/// Segments keep the file's metadata apart from the title, the artist if
+/// given, and the track number, which counts segments. A segment without an
+/// end plays to the next one's start, or to the end of the file.
+pub fn expand(track: Track, segments: &[Segment]) -> Vec<Track> {
+    segments
+        .iter()
+        .enumerate()
+        .map(|(i, segment)| {
+            let end = segment.end.or_else(|| segments.get(i + 1).map(|next| next.start));
+            let duration = end.or(track.duration).map(|end| end.saturating_sub(segment.start));
+            let mut virtual_track = track.clone();
+            virtual_track.title = Some(segment.title.clone());
+            virtual_track.inferred.title = false;
+            if let Some(artist) = &segment.artist {
+                virtual_track.artist = Some(artist.clone());
+                virtual_track.inferred.artist = false;
+            }
+            virtual_track.track_number = Some(i as u32 + 1);
+            virtual_track.start_offset = Some(segment.start);
+            virtual_track.end_offset = end;
+            virtual_track.duration = duration;
+            virtual_track
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(parse_time("0:00"), Some(0.0));
+        assert_eq!(parse_time("2:31"), Some(151.0));
+        assert_eq!(parse_time("1:02:03.5"), Some(3723.5));
+        assert_eq!(parse_time("75:00"), Some(4500.0));
+        assert_eq!(parse_time("90"), Some(90.0));
+        assert_eq!(parse_time("1:60"), None);
+        assert_eq!(parse_time("1.5:00"), None);
+        assert_eq!(parse_time("a:00"), None);
+        assert_eq!(parse_time("1:2:3:4"), None);
+    }
+
+    #[test]
+    fn test_expand_segments() {
+        let segments = parse(
+            "[[segment]]\ntitle = \"Pregap\"\nstart = 0\n\n\
+             [[segment]]\ntitle = \"Song\"\nartist = \"Guest\"\nstart = \"1:30\"\nend = \"4:00\"\n\n\
+             [[segment]]\ntitle = \"Encore\"\nstart = \"5:00\"\n",
+        )
+        .unwrap();
+        let mut track = Track::new(PathBuf::from("/music/live.flac"));
+        track.artist = Some("Band".to_string());
+        track.duration = Some(Duration::from_secs(600));
+
+        let tracks = expand(track, &segments);
+        let secs = |d: Option<Duration>| d.map(|d| d.as_secs());
+        let summary: Vec<_> = tracks
+            .iter()
+            .map(|t| (t.display_name(), t.artist.clone().unwrap(), secs(t.start_offset), secs(t.end_offset), secs(t.duration)))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("Pregap".to_string(), "Band".to_string(), Some(0), Some(90), Some(90)),
+                ("Song".to_string(), "Guest".to_string(), Some(90), Some(240), Some(150)),
+                ("Encore".to_string(), "Band".to_string(), Some(300), None, Some(300)),
+            ]
+        );
+        assert_eq!(tracks[2].track_number, Some(3));
+    }
+
+    #[test]
+    fn test_invalid_sidecar() {
+        let out_of_order = "[[segment]]\ntitle = \"B\"\nstart = 60\n\n[[segment]]\ntitle = \"A\"\nstart = 30\n";
+        assert_eq!(parse(out_of_order), Err(SegmentError::Order("A".to_string())));
+        let backwards = "[[segment]]\ntitle = \"A\"\nstart = 60\nend = 30\n";
+        assert_eq!(parse(backwards), Err(SegmentError::Order("A".to_string())));
+        assert_eq!(
+            parse("[[segment]]\ntitle = \"A\"\nstart = \"1:xx\"\n"),
+            Err(SegmentError::BadTime("1:xx".to_string()))
+        );
+        assert!(matches!(parse("[[segment]]\nstart = 0\n"), Err(SegmentError::Parse(_))));
+
+        assert_eq!(sidecar_path(Path::new("/music/live.flac")), PathBuf::from("/music/live.segments.toml"));
+    }
+}
@@ -0,0 +1,160 @@
+//! Saving and restoring the playback session between launches.
+
+use crate::playlist::RepeatMode;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Version written to session files; files with another version are ignored.
+pub const SESSION_VERSION: u32 = 1;
+
+/// Playback state saved at exit. Every field is optional so partial files
+/// restore whatever they contain.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    pub version: u32,
+    /// Directory or playlist file the tracks were loaded from.
+    pub source: Option<PathBuf>,
+    /// Track that was playing.
+    pub track: Option<PathBuf>,
+    /// Position in the track in seconds.
+    pub position_secs: Option<u64>,
+    /// Volume in percent.
+    pub volume: Option<u8>,
+    pub shuffle: Option<bool>,
+    /// "off", "all", or "single".
+    pub repeat: Option<String>,
+    /// Tracks waiting in the play queue.
+    pub queue: Vec<PathBuf>,
+}
+
+impl Session {
+    /// Returns the session file path inside a state directory.
+    pub fn default_path(state_dir: &Path) -> PathBuf {
+        state_dir.join("session.toml")
+    }
+
+    /// Loads a session file.
+    ///
+    /// Returns `None` if the file is missing, unreadable, or from another
+    /// version, warning about the latter two.
+    pub fn load(path: &Path) -> Option<Self> {
+        let contents = fs::read_to_string(path).ok()?;
+        match Self::parse(&contents) {
+            Ok(session) => Some(session),
+            Err(e) => {
+                eprintln!("Warning: Ignoring session file {:?}: {}", path, e);
+                None
+            }
+        }
+    }
+
+    /// Parses session file contents.
+    fn parse(contents: &str) -> Result<Self, String> {
+        let session: Session = toml::from_str(contents).map_err(|e| e.to_string())?;
+        if session.version != SESSION_VERSION {
+            return Err(format!(
+                "version {} is not supported (expected {})",
+                session.version, SESSION_VERSION
+            ));
+        }
+        Ok(session)
+    }
+
+    /// Saves the session, creating parent directories as needed.
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let toml_string = toml::to_string_pretty(self).map_err(std::io::Error::other)?;
+
+        fs::write(path, toml_string)
+    }
+
+    /// Returns the saved repeat mode, if valid.
+    pub fn repeat_mode(&self) -> Option<RepeatMode> {
+        match self.repeat.as_deref()? {
+            "off" => Some(RepeatMode::Off),
+            "all" => Some(RepeatMode::All),
+            "single" => Some(RepeatMode::Single),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the name a repeat mode is saved as.
+pub fn repeat_name(mode: RepeatMode) -> &'static str {
+    match mode {
+        RepeatMode::Off => "off",
+        RepeatMode::All => "all",
+        RepeatMode::Single => "single",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let session = Session {
+            version: SESSION_VERSION,
+            source: Some(PathBuf::from("/music")),
+            track: Some(PathBuf::from("/music/a.mp3")),
+            position_secs: Some(42),
+            volume: Some(70),
+            shuffle: Some(true),
+            repeat: Some(repeat_name(RepeatMode::Single).to_string()),
+            queue: vec![PathBuf::from("/music/b.mp3")],
+        };
+        let toml_str = toml::to_string_pretty(&session).unwrap();
+        let parsed = Session::parse(&toml_str).unwrap();
+        assert_eq!(parsed, session);
+        assert_eq!(parsed.repeat_mode(), Some(RepeatMode::Single));
+    }
+
+    #[test]
+    fn test_partial_file() {
+        let parsed = Session::parse("version = 1\ntrack = \"/music/a.mp3\"\n").unwrap();
+        assert_eq!(parsed.track, Some(PathBuf::from("/music/a.mp3")));
+        assert_eq!(parsed.source, None);
+        assert_eq!(parsed.position_secs, None);
+        assert_eq!(parsed.volume, None);
+        assert_eq!(parsed.repeat_mode(), None);
+        assert!(parsed.queue.is_empty());
+    }
+
+    #[test]
+    fn test_version_mismatch() {
+        assert!(Session::parse("version = 2\nvolume = 50\n").is_err());
+        // Files without a version predate versioning
+        assert!(Session::parse("volume = 50\n").is_err());
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        assert!(Session::parse("version = 1\nvolume = \"loud\"\n").is_err());
+
+        let parsed = Session::parse("version = 1\nrepeat = \"sometimes\"\n").unwrap();
+        assert_eq!(parsed.repeat_mode(), None);
+    }
+
+    #[test]
+    fn test_load_and_save() {
+        let dir = std::env::temp_dir().join(format!("juke_session_{}", std::process::id()));
+        let path = Session::default_path(&dir);
+        assert_eq!(Session::load(&path), None);
+
+        let session = Session {
+            version: SESSION_VERSION,
+            volume: Some(30),
+            ..Default::default()
+        };
+        session.save(&path).unwrap();
+        assert_eq!(Session::load(&path), Some(session));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
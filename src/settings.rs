@@ -0,0 +1,486 @@
+//! Resolution of settings from command-line flags, environment variables, and
+//! the config file.
+//!
+//! Precedence is flag > environment variable > config file > default.
+
+use crate::config::{Config, InterfaceMode};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where a resolved setting came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    ConfigFile,
+    Env(&'static str),
+    Flag(&'static str),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::ConfigFile => write!(f, "config file"),
+            Source::Env(name) => write!(f, "${}", name),
+            Source::Flag(flag) => write!(f, "{}", flag),
+        }
+    }
+}
+
+/// Overrides given on the command line.
+#[derive(Debug, Clone, Default)]
+pub struct CliOverrides {
+    pub config: Option<PathBuf>,
+    pub volume: Option<u8>,
+    pub no_color: bool,
+    pub state_dir: Option<PathBuf>,
+    pub theme: Option<String>,
+    /// Skip restoring the saved session for this launch.
+    pub fresh: bool,
+    /// Print plain lines instead of drawing the full-screen interface.
+    pub plain: bool,
+    /// Play only files first seen in this many days, newest first.
+    pub recent: Option<u64>,
+    /// Start with shuffle on.
+    pub shuffle: bool,
+    /// Print how long startup took and exit.
+    pub profile_startup: bool,
+    /// Never ask the first-run setup questions.
+    pub no_setup: bool,
+    /// Draw the visualizer from fixed levels, for screenshots. Not listed
+    /// in the help.
+    pub viz_demo: bool,
+    pub max_depth: Option<usize>,
+    pub follow_symlinks: Option<bool>,
+    pub include_hidden: Option<bool>,
+}
+
+impl CliOverrides {
+    /// Removes recognized flags from `args`, leaving the rest for the caller.
+    pub fn extract(args: &mut Vec<String>) -> Result<Self, String> {
+        let mut overrides = Self::default();
+        let mut rest = Vec::with_capacity(args.len());
+        let mut iter = args.drain(..);
+
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--config" => {
+                    overrides.config = Some(iter.next().ok_or("--config needs a path")?.into());
+                }
+                "--state-dir" => {
+                    overrides.state_dir = Some(iter.next().ok_or("--state-dir needs a path")?.into());
+                }
+                "--volume" => {
+                    let value = iter.next().ok_or("--volume needs a value")?;
+                    overrides.volume = Some(
+                        parse_volume(&value)
+                            .ok_or_else(|| format!("Invalid --volume '{}': expected 0-100", value))?,
+                    );
+                }
+                "--theme" => {
+                    overrides.theme = Some(iter.next().ok_or("--theme needs a name")?);
+                }
+                "--max-depth" => {
+                    let value = iter.next().ok_or("--max-depth needs a value")?;
+                    overrides.max_depth = Some(value.parse().map_err(|_| {
+                        format!("Invalid --max-depth '{}': expected a number, 0 for unlimited", value)
+                    })?);
+                }
+                "--follow-symlinks" => overrides.follow_symlinks = Some(true),
+                "--no-follow-symlinks" => overrides.follow_symlinks = Some(false),
+                "--hidden" => overrides.include_hidden = Some(true),
+                "--no-hidden" => overrides.include_hidden = Some(false),
+                "--no-color" => overrides.no_color = true,
+                "--fresh" => overrides.fresh = true,
+                "--plain" => overrides.plain = true,
+                "--shuffle" => overrides.shuffle = true,
+                "--profile-startup" => overrides.profile_startup = true,
+                "--no-setup" => overrides.no_setup = true,
+                "--viz-demo" => overrides.viz_demo = true,
+                "--recent" => {
+                    let value = iter.next().ok_or("--recent needs a number of days")?;
+                    overrides.recent = Some(value.parse().map_err(|_| {
+                        format!("Invalid --recent '{}': expected a number of days", value)
+                    })?);
+                }
+                _ => rest.push(arg),
+            }
+        }
+
+        drop(iter);
+        *args = rest;
+        Ok(overrides)
+    }
+
+    /// Returns the config file that resolving these overrides will load.
+    pub fn config_path(&self) -> Option<PathBuf> {
+        choose_config_path(self.config.clone(), &|name| std::env::var(name).ok()).0
+    }
+}
+
+/// The resolved configuration, with the origin of each overridable setting.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub config: Config,
+    pub config_path: Option<PathBuf>,
+    pub sources: Vec<(&'static str, Source)>,
+}
+
+impl Settings {
+    /// Resolves settings from the real environment and config file.
+    pub fn resolve(cli: CliOverrides) -> Self {
+        Self::resolve_with(
+            cli,
+            |name| std::env::var(name).ok(),
+            |path| {
+                // A config file that cannot be created is reported once, with
+                // the rest of what cannot be written to
+                let (config, warnings) = if path.exists() || crate::state::writable(path).is_ok() {
+                    Config::load_from(path)
+                } else {
+                    (Config::default(), Vec::new())
+                };
+                for warning in warnings {
+                    eprintln!("Warning: {}", warning);
+                }
+                (config, read_table(path))
+            },
+        )
+    }
+
+    /// Resolves settings using the given environment lookup and config loader.
+    fn resolve_with(
+        cli: CliOverrides,
+        env: impl Fn(&str) -> Option<String>,
+        load: impl Fn(&Path) -> (Config, toml::Table),
+    ) -> Self {
+        let mut sources = Vec::new();
+
+        let (config_path, source) = choose_config_path(cli.config, &env);
+        sources.push(("config", source));
+
+        let (mut config, table) = match &config_path {
+            Some(path) => load(path),
+            None => {
+                eprintln!("Warning: Could not determine config directory, using defaults");
+                (Config::default(), toml::Table::new())
+            }
+        };
+        let in_file = |section: &str, key: &str| {
+            table
+                .get(section)
+                .and_then(|s| s.as_table())
+                .is_some_and(|s| s.contains_key(key))
+        };
+
+        // Volume
+        let source = if let Some(volume) = cli.volume {
+            config.playback.volume = volume;
+            Source::Flag("--volume")
+        } else if let Some(volume) = env_value(&env, "JUKE_VOLUME", parse_volume) {
+            config.playback.volume = volume;
+            Source::Env("JUKE_VOLUME")
+        } else if in_file("playback", "volume") {
+            Source::ConfigFile
+        } else {
+            Source::Default
+        };
+        sources.push(("playback.volume", source));
+
+        // Colors
+        let source = if cli.no_color {
+            config.ui.no_color = true;
+            Source::Flag("--no-color")
+        } else if let Some(no_color) = env_value(&env, "JUKE_NO_COLOR", parse_bool) {
+            config.ui.no_color = no_color;
+            Source::Env("JUKE_NO_COLOR")
+        } else if in_file("ui", "no_color") {
+            Source::ConfigFile
+        } else {
+            Source::Default
+        };
+        sources.push(("ui.no_color", source));
+
+        // Interface mode
+        let source = if cli.plain {
+            config.ui.mode = InterfaceMode::Plain;
+            Source::Flag("--plain")
+        } else if in_file("ui", "mode") {
+            Source::ConfigFile
+        } else {
+            Source::Default
+        };
+        sources.push(("ui.mode", source));
+
+        // State directory
+        let source = if let Some(dir) = cli.state_dir {
+            config.paths.state_dir = Some(dir);
+            Source::Flag("--state-dir")
+        } else if let Some(dir) = env_value(&env, "JUKE_STATE_DIR", |v| Some(PathBuf::from(v))) {
+            config.paths.state_dir = Some(dir);
+            Source::Env("JUKE_STATE_DIR")
+        } else if config.paths.state_dir.is_some() {
+            Source::ConfigFile
+        } else {
+            config.paths.state_dir = default_state_dir();
+            Source::Default
+        };
+        sources.push(("paths.state_dir", source));
+
+        // Scan options
+        let source = if let Some(depth) = cli.max_depth {
+            config.playlist.max_depth = depth;
+            Source::Flag("--max-depth")
+        } else if in_file("playlist", "max_depth") {
+            Source::ConfigFile
+        } else {
+            Source::Default
+        };
+        sources.push(("playlist.max_depth", source));
+
+        let source = if let Some(follow) = cli.follow_symlinks {
+            config.playlist.follow_symlinks = follow;
+            Source::Flag(if follow { "--follow-symlinks" } else { "--no-follow-symlinks" })
+        } else if in_file("playlist", "follow_symlinks") {
+            Source::ConfigFile
+        } else {
+            Source::Default
+        };
+        sources.push(("playlist.follow_symlinks", source));
+
+        let source = if let Some(hidden) = cli.include_hidden {
+            config.playlist.include_hidden = hidden;
+            Source::Flag(if hidden { "--hidden" } else { "--no-hidden" })
+        } else if in_file("playlist", "include_hidden") {
+            Source::ConfigFile
+        } else {
+            Source::Default
+        };
+        sources.push(("playlist.include_hidden", source));
+
+        // Theme preset
+        let source = if let Some(theme) = cli.theme {
+            config.theme.preset = theme;
+            Source::Flag("--theme")
+        } else if in_file("theme", "preset") {
+            Source::ConfigFile
+        } else {
+            Source::Default
+        };
+        sources.push(("theme.preset", source));
+
+        Self {
+            config,
+            config_path,
+            sources,
+        }
+    }
+
+    /// Returns where a setting came from.
+    pub fn source(&self, name: &str) -> Option<Source> {
+        self.sources.iter().find(|(n, _)| *n == name).map(|(_, source)| *source)
+    }
+
+    /// Describes the resolved configuration for `--print-config`.
+    pub fn describe(&self) -> String {
+        let mut out = String::new();
+        for (name, source) in &self.sources {
+            if *name == "config" {
+                let path = self
+                    .config_path
+                    .as_ref()
+                    .map_or_else(|| "none".to_string(), |p| p.display().to_string());
+                out.push_str(&format!("# Config file: {} (from {})\n", path, source));
+            } else {
+                out.push_str(&format!("# {} from {}\n", name, source));
+            }
+        }
+        out.push('\n');
+        out.push_str(&toml::to_string_pretty(&self.config).unwrap_or_default());
+        out
+    }
+}
+
+/// Returns the config file to use, and where that choice came from.
+fn choose_config_path(flag: Option<PathBuf>, env: &impl Fn(&str) -> Option<String>) -> (Option<PathBuf>, Source) {
+    if let Some(path) = flag {
+        (Some(path), Source::Flag("--config"))
+    } else if let Some(path) = env_value(env, "JUKE_CONFIG", |v| Some(PathBuf::from(v))) {
+        (Some(path), Source::Env("JUKE_CONFIG"))
+    } else {
+        (Config::config_path(), Source::Default)
+    }
+}
+
+/// Returns the default state directory.
+///
+/// - Linux: `~/.local/share/juke`
+/// - macOS: `~/Library/Application Support/juke`
+/// - Windows: `%APPDATA%\juke`
+fn default_state_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|path| path.join("juke"))
+}
+
+/// Reads a config file as a raw table, used to tell which keys it sets.
+fn read_table(path: &Path) -> toml::Table {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|contents| contents.parse().ok())
+        .unwrap_or_default()
+}
+
+/// Reads and parses an environment variable, warning about invalid values.
+fn env_value<T>(
+    env: &impl Fn(&str) -> Option<String>,
+    name: &str,
+    parse: impl Fn(&str) -> Option<T>,
+) -> Option<T> {
+    let raw = env(name)?;
+    let parsed = if raw.trim().is_empty() { None } else { parse(raw.trim()) };
+    if parsed.is_none() {
+        eprintln!("Warning: Ignoring invalid value '{}' for ${}", raw, name);
+    }
+    parsed
+}
+
+/// Parses a volume percentage.
+fn parse_volume(value: &str) -> Option<u8> {
+    value.parse::<u8>().ok().filter(|v| *v <= 100)
+}
+
+/// Parses a boolean environment value.
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn resolve(cli: CliOverrides, env: &[(&str, &str)], file: &str) -> Settings {
+        let env: HashMap<String, String> =
+            env.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        let table: toml::Table = file.parse().unwrap();
+        Settings::resolve_with(
+            cli,
+            |name| env.get(name).cloned(),
+            |_| {
+                let mut config = Config::default();
+                if let Some(volume) = table.get("playback").and_then(|p| p.get("volume")) {
+                    config.playback.volume = volume.as_integer().unwrap() as u8;
+                }
+                (config, table.clone())
+            },
+        )
+    }
+
+    fn source(settings: &Settings, name: &str) -> Source {
+        settings.source(name).unwrap()
+    }
+
+    #[test]
+    fn test_precedence() {
+        let file = "[playback]\nvolume = 40\n";
+
+        let settings = resolve(CliOverrides::default(), &[], file);
+        assert_eq!(settings.config.playback.volume, 40);
+        assert_eq!(source(&settings, "playback.volume"), Source::ConfigFile);
+
+        let settings = resolve(CliOverrides::default(), &[("JUKE_VOLUME", "60")], file);
+        assert_eq!(settings.config.playback.volume, 60);
+        assert_eq!(source(&settings, "playback.volume"), Source::Env("JUKE_VOLUME"));
+
+        let cli = CliOverrides {
+            volume: Some(80),
+            ..Default::default()
+        };
+        let settings = resolve(cli, &[("JUKE_VOLUME", "60")], file);
+        assert_eq!(settings.config.playback.volume, 80);
+        assert_eq!(source(&settings, "playback.volume"), Source::Flag("--volume"));
+
+        let settings = resolve(CliOverrides::default(), &[], "");
+        assert_eq!(settings.config.playback.volume, 100);
+        assert_eq!(source(&settings, "playback.volume"), Source::Default);
+    }
+
+    #[test]
+    fn test_invalid_env_values_are_ignored() {
+        let env = [("JUKE_VOLUME", "loud"), ("JUKE_NO_COLOR", "maybe"), ("JUKE_STATE_DIR", " ")];
+        let settings = resolve(CliOverrides::default(), &env, "[ui]\nno_color = true\n");
+        assert_eq!(settings.config.playback.volume, 100);
+        assert_eq!(source(&settings, "playback.volume"), Source::Default);
+        assert_eq!(source(&settings, "ui.no_color"), Source::ConfigFile);
+        assert_eq!(source(&settings, "paths.state_dir"), Source::Default);
+
+        let settings = resolve(CliOverrides::default(), &[("JUKE_VOLUME", "101")], "");
+        assert_eq!(settings.config.playback.volume, 100);
+    }
+
+    #[test]
+    fn test_env_paths() {
+        let env = [("JUKE_CONFIG", "/tmp/juke.toml"), ("JUKE_STATE_DIR", "/tmp/state"), ("JUKE_NO_COLOR", "1")];
+        let settings = resolve(CliOverrides::default(), &env, "");
+        assert_eq!(settings.config_path, Some(PathBuf::from("/tmp/juke.toml")));
+        assert_eq!(settings.config.paths.state_dir, Some(PathBuf::from("/tmp/state")));
+        assert!(settings.config.ui.no_color);
+        assert_eq!(source(&settings, "config"), Source::Env("JUKE_CONFIG"));
+        assert!(settings.describe().contains("# paths.state_dir from $JUKE_STATE_DIR\n"));
+    }
+
+    #[test]
+    fn test_extract_flags() {
+        let mut args: Vec<String> = ["juke", "--volume", "30", "music", "--no-color", "--state-dir", "/s"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let cli = CliOverrides::extract(&mut args).unwrap();
+        assert_eq!(args, ["juke", "music"]);
+        assert_eq!(cli.volume, Some(30));
+        assert!(cli.no_color);
+        assert_eq!(cli.state_dir, Some(PathBuf::from("/s")));
+
+        let mut args = vec!["juke".to_string(), "--volume".to_string(), "200".to_string()];
+        assert!(CliOverrides::extract(&mut args).is_err());
+
+        let mut args: Vec<String> = ["juke", "--recent", "30", "--shuffle"].iter().map(|s| s.to_string()).collect();
+        let cli = CliOverrides::extract(&mut args).unwrap();
+        assert_eq!((cli.recent, cli.shuffle), (Some(30), true));
+        let mut args = vec!["juke".to_string(), "--recent".to_string(), "soon".to_string()];
+        assert!(CliOverrides::extract(&mut args).is_err());
+    }
+
+    #[test]
+    fn test_scan_flags_beat_config() {
+        let mut args: Vec<String> = ["juke", "--max-depth", "2", "--no-hidden"].iter().map(|s| s.to_string()).collect();
+        let cli = CliOverrides::extract(&mut args).unwrap();
+        let settings = resolve(cli, &[], "[playlist]\nmax_depth = 5\ninclude_hidden = true\n");
+        assert_eq!(settings.config.playlist.max_depth, 2);
+        assert!(!settings.config.playlist.include_hidden);
+        assert!(settings.config.playlist.follow_symlinks);
+        assert_eq!(source(&settings, "playlist.max_depth"), Source::Flag("--max-depth"));
+        assert_eq!(source(&settings, "playlist.include_hidden"), Source::Flag("--no-hidden"));
+        assert_eq!(source(&settings, "playlist.follow_symlinks"), Source::Default);
+
+        let mut args = vec!["juke".to_string(), "--max-depth".to_string(), "-1".to_string()];
+        assert!(CliOverrides::extract(&mut args).is_err());
+    }
+
+    #[test]
+    fn test_interface_mode() {
+        let ui: crate::config::UiConfig = toml::from_str("mode = \"plain\"").unwrap();
+        assert_eq!(ui.mode, InterfaceMode::Plain);
+        let settings = resolve(CliOverrides::default(), &[], "[ui]\nmode = \"plain\"\n");
+        assert_eq!(source(&settings, "ui.mode"), Source::ConfigFile);
+
+        let cli = CliOverrides { plain: true, ..Default::default() };
+        let settings = resolve(cli, &[], "");
+        assert_eq!(settings.config.ui.mode, InterfaceMode::Plain);
+        assert_eq!(source(&settings, "ui.mode"), Source::Flag("--plain"));
+    }
+}
@@ -0,0 +1,237 @@
+//! First-run setup: a few questions asked in the plain terminal before the
+//! interface starts, when there is no config file yet.
+//!
+//! The answers are written into a new config file, with a comment above
+//! each, and the file goes through the same parsing and validation as any
+//! other. With `--no-setup`, or when input or output is not a terminal, the
+//! questions are skipped and the defaults are written as before.
+
+use crate::config::Config;
+use crate::themes;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// What the setup questions were answered with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Answers {
+    /// Music played when juke is started without a path.
+    pub music_dir: Option<PathBuf>,
+    /// Theme preset name.
+    pub theme: String,
+    /// Pick up where the last session left off.
+    pub resume: bool,
+    /// Desktop notifications.
+    pub notifications: bool,
+}
+
+impl Answers {
+    /// Sets the answered settings in a config.
+    fn apply(&self, config: &mut Config) {
+        config.paths.music_dir = self.music_dir.clone();
+        config.theme.preset = self.theme.clone();
+        config.resume.enabled = self.resume;
+        config.notifications.enabled = self.notifications;
+    }
+}
+
+/// Comments written above the answered settings, by section and key.
+const NOTES: &[(&str, &str, &str)] = &[
+    ("paths", "music_dir", "Played when juke is started without a path and there is no session to resume"),
+    ("theme", "preset", "Color theme; juke --list-themes shows them all"),
+    ("resume", "enabled", "Pick up where the last session left off"),
+    ("notifications", "enabled", "Desktop notifications when the track changes"),
+];
+
+/// Returns the platform's music folder, if it exists.
+pub fn suggested_music_dir() -> Option<PathBuf> {
+    dirs::audio_dir().filter(|dir| dir.is_dir())
+}
+
+/// Asks the setup questions on the terminal and writes the config file.
+pub fn run(path: &Path) -> io::Result<()> {
+    let mut output = io::stdout();
+    let answers = ask(&mut io::stdin().lock(), &mut output, suggested_music_dir().as_deref())?;
+    write(path, &answers)?;
+    writeln!(output, "Saved to {}; edit it any time, or press o in juke for common settings.\n", path.display())
+}
+
+/// Asks the setup questions. Enter, or the end of input, keeps the
+/// suggested answer; an answer that does not fit is asked again.
+pub fn ask(input: &mut impl BufRead, output: &mut impl Write, suggested_dir: Option<&Path>) -> io::Result<Answers> {
+    writeln!(output, "Welcome to juke! A few questions to get started (Enter keeps the suggestion).\n")?;
+
+    let music_dir = loop {
+        let suggestion = suggested_dir.map_or_else(|| "current folder".to_string(), |dir| dir.display().to_string());
+        let Some(answer) = prompt(input, output, &format!("Music folder [{}]: ", suggestion))? else {
+            break suggested_dir.map(Path::to_path_buf);
+        };
+        if answer.is_empty() {
+            break suggested_dir.map(Path::to_path_buf);
+        }
+        let dir = expand_home(&answer);
+        if dir.is_dir() {
+            break Some(dir);
+        }
+        writeln!(output, "  {} is not a folder", dir.display())?;
+    };
+
+    let names: Vec<&str> = themes::PRESETS.iter().map(|(name, _)| *name).collect();
+    writeln!(output, "Themes: {}", names.join(", "))?;
+    let theme = loop {
+        let Some(answer) = prompt(input, output, &format!("Theme [{}]: ", names[0]))? else {
+            break names[0].to_string();
+        };
+        if answer.is_empty() {
+            break names[0].to_string();
+        }
+        if let Some(name) = names.iter().find(|name| name.eq_ignore_ascii_case(&answer)) {
+            break name.to_string();
+        }
+        writeln!(output, "  No theme called {}", answer)?;
+    };
+
+    let resume = yes_no(input, output, "Resume where you left off next time?", true)?;
+    let notifications = yes_no(input, output, "Desktop notifications when the track changes?", false)?;
+    writeln!(output)?;
+
+    Ok(Answers { music_dir, theme, resume, notifications })
+}
+
+/// Asks a yes or no question.
+fn yes_no(input: &mut impl BufRead, output: &mut impl Write, question: &str, suggested: bool) -> io::Result<bool> {
+    let choices = if suggested { "[Y/n]" } else { "[y/N]" };
+    loop {
+        let Some(answer) = prompt(input, output, &format!("{} {} ", question, choices))? else {
+            return Ok(suggested);
+        };
+        match answer.to_ascii_lowercase().as_str() {
+            "" => return Ok(suggested),
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => writeln!(output, "  Please answer y or n")?,
+        }
+    }
+}
+
+/// Shows a prompt and reads one line, trimmed. Returns `None` at the end
+/// of input.
+fn prompt(input: &mut impl BufRead, output: &mut impl Write, text: &str) -> io::Result<Option<String>> {
+    write!(output, "{}", text)?;
+    output.flush()?;
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        writeln!(output)?;
+        return Ok(None);
+    }
+    Ok(Some(line.trim().to_string()))
+}
+
+/// Expands a leading `~` to the home directory.
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix('~'), dirs::home_dir()) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with(['/', '\\']) => {
+            home.join(rest.trim_start_matches(['/', '\\']))
+        }
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Returns the config file for a set of answers: the defaults with the
+/// answers applied, a comment above each answered setting.
+pub fn render(answers: &Answers) -> io::Result<String> {
+    let mut config = Config::default();
+    answers.apply(&mut config);
+    let toml_string = toml::to_string_pretty(&config).map_err(io::Error::other)?;
+    Ok(format!("# juke configuration, written by the first-run setup\n\n{}", annotate(&toml_string, NOTES)))
+}
+
+/// Writes the config file for a set of answers, checking it loads like any
+/// other config file first.
+pub fn write(path: &Path, answers: &Answers) -> io::Result<()> {
+    let contents = render(answers)?;
+    Config::parse(&contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, contents)
+}
+
+/// Puts a comment line above each of the given keys in a TOML document.
+fn annotate(toml: &str, notes: &[(&str, &str, &str)]) -> String {
+    let mut out = String::new();
+    let mut section = "";
+    for line in toml.lines() {
+        let trimmed = line.trim();
+        if let Some(name) = trimmed.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+            section = name;
+        } else if let Some((key, _)) = trimmed.split_once('=')
+            && let Some((_, _, note)) = notes.iter().find(|(s, k, _)| *s == section && *k == key.trim())
+        {
+            out.push_str(&format!("# {}\n", note));
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn answer(script: &str, suggested: Option<&Path>) -> (Answers, String) {
+        let mut output = Vec::new();
+        let answers = ask(&mut Cursor::new(script), &mut output, suggested).unwrap();
+        (answers, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_scripted_answers() {
+        let dir = std::env::temp_dir();
+        let script = format!("/no/such/folder\n{}\nneon\nNord\nmaybe\nn\ny\n", dir.display());
+        let (answers, output) = answer(&script, None);
+        assert_eq!(
+            answers,
+            Answers { music_dir: Some(dir), theme: "nord".to_string(), resume: false, notifications: true }
+        );
+        // Each answer that did not fit was asked again
+        assert!(output.contains("/no/such/folder is not a folder"));
+        assert!(output.contains("No theme called neon"));
+        assert!(output.contains("Please answer y or n"));
+    }
+
+    #[test]
+    fn test_enter_and_end_of_input_keep_suggestions() {
+        let suggested = std::env::temp_dir();
+        let (answers, _) = answer("\n\n", Some(&suggested));
+        assert_eq!(
+            answers,
+            Answers { music_dir: Some(suggested), theme: "default".to_string(), resume: true, notifications: false }
+        );
+        let (answers, _) = answer("", None);
+        assert_eq!(answers.music_dir, None);
+        assert!(answers.resume);
+    }
+
+    #[test]
+    fn test_written_config_loads_with_comments() {
+        let answers = Answers {
+            music_dir: Some(PathBuf::from("/music")),
+            theme: "mono".to_string(),
+            resume: true,
+            notifications: true,
+        };
+        let contents = render(&answers).unwrap();
+        assert!(contents.contains("# Color theme; juke --list-themes shows them all\npreset = \"mono\""));
+        assert!(contents.contains("# Pick up where the last session left off\nenabled = true"));
+
+        let (config, warnings) = Config::parse(&contents).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(config.paths.music_dir, Some(PathBuf::from("/music")));
+        assert_eq!(config.theme.preset, "mono");
+        assert!(config.resume.enabled && config.notifications.enabled);
+        assert_eq!(config.playback.seek_step, Config::default().playback.seek_step);
+    }
+}
@@ -0,0 +1,146 @@
+//! Frequency bands of the samples just played, for the visualizer's bars.
+//!
+//! The last [`WINDOW`] samples, mixed down to mono, go through a Hann window
+//! and a radix-2 FFT. The bins from [`LOWEST_HZ`] up to [`HIGHEST_HZ`] are
+//! grouped into bands of equal width on a log scale, as pitch is heard, and
+//! each band shows its loudest bin on a decibel scale.
+
+use std::f32::consts::PI;
+
+/// Samples transformed at once: 23 ms at 44.1 kHz, with bins 43 Hz apart.
+pub const WINDOW: usize = 1024;
+
+/// Where the lowest band starts.
+const LOWEST_HZ: f32 = 40.0;
+
+/// Where the highest band ends, unless the sample rate stops short of it.
+const HIGHEST_HZ: f32 = 16_000.0;
+
+/// Level shown as nothing, in dB below a full scale sine.
+const FLOOR_DB: f32 = -60.0;
+
+/// Returns `n` band levels from 0.0 to 1.0, bass first, of the last
+/// [`WINDOW`] of `samples`. Fewer samples than that give silence.
+pub fn bands(samples: &[f32], sample_rate: u32, n: usize) -> Vec<f32> {
+    if samples.len() < WINDOW || sample_rate == 0 {
+        return vec![0.0; n];
+    }
+    let samples = &samples[samples.len() - WINDOW..];
+    let mut re: Vec<f32> = samples
+        .iter()
+        .enumerate()
+        .map(|(i, sample)| sample * (0.5 - 0.5 * (2.0 * PI * i as f32 / WINDOW as f32).cos()))
+        .collect();
+    let mut im = vec![0.0; WINDOW];
+    fft(&mut re, &mut im);
+    // Scaled so that a full scale sine peaks at 1.0, allowing for the
+    // window halving the energy
+    let magnitudes: Vec<f32> =
+        (0..WINDOW / 2).map(|bin| (re[bin] * re[bin] + im[bin] * im[bin]).sqrt() * 4.0 / WINDOW as f32).collect();
+
+    let bin_hz = sample_rate as f32 / WINDOW as f32;
+    let highest = HIGHEST_HZ.min(sample_rate as f32 / 2.0);
+    let edge = |i: usize| LOWEST_HZ * (highest / LOWEST_HZ).powf(i as f32 / n as f32);
+    (0..n)
+        .map(|i| {
+            let first = ((edge(i) / bin_hz) as usize).clamp(1, magnitudes.len() - 1);
+            let last = ((edge(i + 1) / bin_hz).ceil() as usize).clamp(first + 1, magnitudes.len());
+            let peak = magnitudes[first..last].iter().copied().fold(0.0, f32::max);
+            db_level(peak)
+        })
+        .collect()
+}
+
+/// Maps a magnitude onto 0.0 to 1.0, from [`FLOOR_DB`] up to full scale.
+fn db_level(magnitude: f32) -> f32 {
+    if magnitude <= 0.0 {
+        return 0.0;
+    }
+    (1.0 - 20.0 * magnitude.log10() / FLOOR_DB).clamp(0.0, 1.0)
+}
+
+/// Transforms `re` and `im`, whose length is a power of two, in place.
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    debug_assert!(n.is_power_of_two() && im.len() == n);
+    // Put the samples in bit-reversed order
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    // Then combine ever longer runs of them
+    let mut len = 2;
+    while len <= n {
+        let step = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (step * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let tr = re[b] * cos - im[b] * sin;
+                let ti = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - tr;
+                im[b] = im[a] - ti;
+                re[a] += tr;
+                im[a] += ti;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(hz: f32, sample_rate: u32) -> Vec<f32> {
+        (0..WINDOW).map(|i| (2.0 * PI * hz * i as f32 / sample_rate as f32).sin()).collect()
+    }
+
+    #[test]
+    fn test_fft() {
+        // An impulse holds every frequency equally
+        let mut re = vec![0.0; 8];
+        re[0] = 1.0;
+        let mut im = vec![0.0; 8];
+        fft(&mut re, &mut im);
+        assert!(re.iter().all(|&x| (x - 1.0).abs() < 1e-6) && im.iter().all(|&x| x.abs() < 1e-6));
+
+        // A sine of 4 cycles in 64 samples lands in bin 4
+        let mut re: Vec<f32> = (0..64).map(|i| (2.0 * PI * 4.0 * i as f32 / 64.0).sin()).collect();
+        let mut im = vec![0.0; 64];
+        fft(&mut re, &mut im);
+        let magnitude = |bin: usize| (re[bin] * re[bin] + im[bin] * im[bin]).sqrt();
+        assert!((magnitude(4) - 32.0).abs() < 1e-3);
+        assert!(magnitude(3) < 1e-3 && magnitude(5) < 1e-3);
+    }
+
+    #[test]
+    fn test_a_tone_lights_its_band() {
+        let levels = bands(&sine(1000.0, 44100), 44100, 12);
+        assert_eq!(levels.len(), 12);
+        let loudest = (0..12).max_by(|&a, &b| levels[a].total_cmp(&levels[b])).unwrap();
+        // 1 kHz is in the band from 40 · 400^(6/12) = 800 Hz up
+        assert_eq!(loudest, 6);
+        assert!(levels[loudest] > 0.95);
+        assert!(levels[0] < 0.2 && levels[11] < 0.2);
+
+        let levels = bands(&sine(100.0, 48000), 48000, 12);
+        assert!(levels[1] > 0.9 && levels[8] < 0.2);
+    }
+
+    #[test]
+    fn test_silence_and_short_windows() {
+        assert_eq!(bands(&[0.0; WINDOW], 44100, 8), vec![0.0; 8]);
+        assert_eq!(bands(&sine(1000.0, 44100)[1..], 44100, 8), vec![0.0; 8]);
+        assert!(bands(&sine(1000.0, 44100), 44100, 0).is_empty());
+    }
+}
@@ -0,0 +1,127 @@
+//! Where juke writes between sessions: the state directory and the config
+//! file.
+//!
+//! Both are checked once at startup. One that cannot be written to, as on
+//! NixOS or in a container with a read-only home, is left alone for the rest
+//! of the session with one message in the log, rather than every feature
+//! that saves failing on its own. Set `JUKE_STATE_DIR` or `--state-dir` to
+//! somewhere writable to keep state anyway.
+
+use crate::lock::{LockError, StateLock};
+use crate::statecheck;
+use crate::strings::Strings;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The places juke may write to this session.
+#[derive(Debug, Default)]
+pub struct StateStore {
+    /// The state directory, while this instance may write to it.
+    dir: Option<PathBuf>,
+    /// The config file, when it can be written to.
+    config_path: Option<PathBuf>,
+    /// Held for as long as the state directory is in use.
+    _lock: Option<StateLock>,
+    /// Another instance holds the state directory; its process ID, if known.
+    locked_by: Option<Option<u32>>,
+    /// Places that cannot be written to, and why.
+    unwritable: Vec<(PathBuf, String)>,
+}
+
+impl StateStore {
+    /// Checks the state directory and config file, creating the directory
+    /// if it is missing, and locks the state directory for this instance.
+    pub fn open(state_dir: Option<PathBuf>, config_path: Option<PathBuf>) -> Self {
+        let mut store = Self::default();
+        if let Some(path) = config_path {
+            match writable(&path) {
+                Ok(()) => store.config_path = Some(path),
+                Err(e) => store.unwritable.push((path, e.to_string())),
+            }
+        }
+        let Some(dir) = state_dir else {
+            return store;
+        };
+        if let Err(e) = statecheck::prepare(&dir) {
+            store.unwritable.push((dir, e.to_string()));
+            return store;
+        }
+        // A second instance would undo the first one's saves, so it keeps its state to itself
+        match StateLock::acquire(&dir) {
+            Ok(lock) => store._lock = Some(lock),
+            Err(LockError::Held(pid)) => {
+                store.locked_by = Some(pid);
+                return store;
+            }
+            Err(e) => eprintln!("Warning: {}", e),
+        }
+        store.dir = Some(dir);
+        store
+    }
+
+    /// The state directory, if this instance may write to it.
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+
+    /// The config file, if it can be written to.
+    pub fn config_path(&self) -> Option<&Path> {
+        self.config_path.as_deref()
+    }
+
+    /// The process ID of the instance using the state directory, when it
+    /// is another one. `Some(None)` when its ID is unknown.
+    pub fn locked_by(&self) -> Option<Option<u32>> {
+        self.locked_by
+    }
+
+    /// Says what cannot be written to this session, if anything, in one
+    /// line.
+    pub fn unwritable_message(&self, strings: &Strings) -> Option<String> {
+        let (_, error) = self.unwritable.first()?;
+        let paths: Vec<String> = self.unwritable.iter().map(|(path, _)| path.display().to_string()).collect();
+        Some(strings.text_with("state.read_only", &[("paths", &paths.join(", ")), ("error", error)]))
+    }
+}
+
+/// Checks that a file can be written to without changing it, creating its
+/// directory if it is missing.
+pub fn writable(file: &Path) -> io::Result<()> {
+    if file.exists() {
+        return OpenOptions::new().append(true).open(file).map(drop);
+    }
+    let dir = file.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    fs::create_dir_all(dir)?;
+    let probe = dir.join(".juke-write-check");
+    fs::write(&probe, b"")?;
+    fs::remove_file(&probe)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unwritable_places_are_left_alone() {
+        let root = std::env::temp_dir().join(format!("juke_state_{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        // Nothing can be created under a plain file, even by root
+        let blocker = root.join("file");
+        fs::write(&blocker, b"").unwrap();
+
+        let store = StateStore::open(Some(blocker.join("state")), Some(blocker.join("config.toml")));
+        assert_eq!(store.dir(), None);
+        assert_eq!(store.config_path(), None);
+        let message = store.unwritable_message(&Strings::default()).unwrap();
+        assert!(message.contains("file/config.toml, "), "{}", message);
+
+        let store = StateStore::open(Some(root.join("state")), Some(root.join("juke").join("config.toml")));
+        assert_eq!(store.dir(), Some(root.join("state").as_path()));
+        assert_eq!(store.config_path(), Some(root.join("juke").join("config.toml").as_path()));
+        assert_eq!(store.unwritable_message(&Strings::default()), None);
+        assert!(!root.join("juke").join("config.toml").exists());
+        drop(store);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
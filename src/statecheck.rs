@@ -0,0 +1,267 @@
+//! The state directory check, run at startup and by `juke --doctor`.
+//!
+//! Every file juke keeps in the state directory is TOML, but for the play
+//! history, which skips the lines it cannot read, so each is parsed
+//! once up front. One that does not parse is moved aside to `*.corrupt`,
+//! where it no longer breaks whatever reads it later, and the user is told
+//! once instead of in scattered errors mid-session.
+
+use crate::strings::Strings;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Files juke keeps in the state directory that must parse as TOML.
+pub const STATE_FILES: &[&str] =
+    &["session.toml", "library.toml", "loudness.toml", "lookup.toml", "marks.toml", "prompts.toml", "tags.toml"];
+
+/// Free space below which files written all session long, like the play
+/// history, are not written.
+pub const MIN_FREE_BYTES: u64 = 16 * 1000 * 1000;
+
+/// Suffix added to files moved aside because they do not parse.
+const CORRUPT_SUFFIX: &str = "corrupt";
+
+/// A state file the check found broken.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// The file did not parse and was moved to `moved`.
+    Quarantined { file: PathBuf, moved: PathBuf, error: String },
+    /// The file could not be read, or did not parse and was left in place.
+    Unreadable { file: PathBuf, error: String },
+}
+
+/// What the check found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub dir: PathBuf,
+    /// The directory did not exist and was created.
+    pub created: bool,
+    /// The directory cannot be created or written to.
+    pub error: Option<String>,
+    /// State files found and checked.
+    pub checked: usize,
+    pub problems: Vec<Problem>,
+    /// Space left on the directory's file system, when known.
+    pub free_bytes: Option<u64>,
+}
+
+impl Report {
+    /// Whether there is too little space left for files written all
+    /// session long.
+    pub fn low_space(&self) -> bool {
+        self.free_bytes.is_some_and(|free| free < MIN_FREE_BYTES)
+    }
+
+    /// Whether nothing needs the user's attention.
+    pub fn is_healthy(&self) -> bool {
+        self.error.is_none() && self.problems.is_empty() && !self.low_space()
+    }
+
+    /// Describes the findings, one line each, ending with a summary.
+    /// `show` writes paths.
+    pub fn lines(&self, strings: &Strings, show: impl Fn(&Path) -> String) -> Vec<String> {
+        let dir = show(&self.dir);
+        if let Some(error) = &self.error {
+            return vec![strings.text_with("state.unusable", &[("path", &dir), ("error", error)])];
+        }
+        let mut lines = Vec::new();
+        if self.created {
+            lines.push(strings.text_with("state.created", &[("path", &dir)]));
+        }
+        for problem in &self.problems {
+            lines.push(match problem {
+                Problem::Quarantined { file, moved, error } => strings.text_with(
+                    "state.quarantined",
+                    &[("file", &show(file)), ("moved", &show(moved)), ("error", error)],
+                ),
+                Problem::Unreadable { file, error } => {
+                    strings.text_with("state.unreadable", &[("file", &show(file)), ("error", error)])
+                }
+            });
+        }
+        let free = self.free_bytes.map(megabytes).unwrap_or_else(|| strings.text("state.free_unknown").to_string());
+        if self.low_space() {
+            lines.push(strings.text_with("state.low_space", &[("free", &free)]));
+        }
+        lines.push(strings.text_with("state.checked", &[("path", &dir), ("files", &self.checked), ("free", &free)]));
+        lines
+    }
+}
+
+/// Creates the state directory if it is missing, readable only by the
+/// user, and checks it can be written to. Returns whether it was created.
+pub fn prepare(dir: &Path) -> io::Result<bool> {
+    let created = !dir.is_dir();
+    if created {
+        let mut builder = fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder.create(dir)?;
+    }
+    let probe = dir.join(".juke-write-check");
+    fs::write(&probe, b"")?;
+    fs::remove_file(&probe)?;
+    Ok(created)
+}
+
+/// Checks the state directory, creating it if it is missing. Files that do
+/// not parse are moved aside when `quarantine` is set, and only reported
+/// otherwise, as when another juke is using them.
+pub fn check(dir: &Path, quarantine: bool) -> Report {
+    let mut report = Report {
+        dir: dir.to_path_buf(),
+        created: false,
+        error: None,
+        checked: 0,
+        problems: Vec::new(),
+        free_bytes: None,
+    };
+    match prepare(dir) {
+        Ok(created) => report.created = created,
+        Err(e) => {
+            report.error = Some(e.to_string());
+            return report;
+        }
+    }
+
+    for name in STATE_FILES {
+        let file = dir.join(name);
+        let text = match fs::read_to_string(&file) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => {
+                report.checked += 1;
+                report.problems.push(Problem::Unreadable { file, error: e.to_string() });
+                continue;
+            }
+        };
+        report.checked += 1;
+        let Err(e) = text.parse::<toml::Table>() else {
+            continue;
+        };
+        let error = e.message().to_string();
+        let moved = corrupt_path(&file);
+        report.problems.push(match quarantine.then(|| fs::rename(&file, &moved)) {
+            Some(Ok(())) => Problem::Quarantined { file, moved, error },
+            Some(Err(e)) => Problem::Unreadable { file, error: format!("{}; cannot move it aside: {}", error, e) },
+            None => Problem::Unreadable { file, error },
+        });
+    }
+
+    report.free_bytes = free_space(dir);
+    report
+}
+
+/// Where a broken state file is moved to, next to it.
+fn corrupt_path(file: &Path) -> PathBuf {
+    let mut name = file.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(CORRUPT_SUFFIX);
+    file.with_file_name(name)
+}
+
+/// Formats a byte count in megabytes.
+fn megabytes(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / 1_000_000.0)
+}
+
+/// Returns the space left to unprivileged users on the file system of `dir`.
+#[cfg(unix)]
+fn free_space(dir: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = std::ffi::CString::new(dir.as_os_str().as_bytes()).ok()?;
+    // SAFETY: statvfs is plain data, for which all zeroes is valid
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: path is NUL-terminated and stat is valid for writes
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    #[allow(clippy::unnecessary_cast)]
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn free_space(_dir: &Path) -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("juke_statecheck_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_missing_directory_is_created() {
+        let dir = temp_dir("missing").join("state");
+        let report = check(&dir, true);
+        assert!(report.created && report.error.is_none());
+        assert!(dir.is_dir());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&dir).unwrap().permissions().mode() & 0o777, 0o700);
+        }
+        assert!(!check(&dir, true).created);
+        fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_unparsable_file_is_quarantined() {
+        let dir = temp_dir("quarantine");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("session.toml"), "volume = 0.5\n").unwrap();
+        fs::write(dir.join("library.toml"), "[files\nbroken").unwrap();
+
+        let report = check(&dir, true);
+        assert_eq!(report.checked, 2);
+        let [Problem::Quarantined { file, moved, .. }] = report.problems.as_slice() else {
+            panic!("expected one quarantined file: {:?}", report.problems);
+        };
+        assert_eq!(file, &dir.join("library.toml"));
+        assert_eq!(moved, &dir.join("library.toml.corrupt"));
+        assert!(!file.exists());
+        assert_eq!(fs::read_to_string(moved).unwrap(), "[files\nbroken");
+        assert!(dir.join("session.toml").exists());
+        assert!(!report.is_healthy());
+
+        let lines = report.lines(&Strings::default(), |path| path.display().to_string());
+        assert!(lines[0].contains("library.toml.corrupt"), "{:?}", lines);
+        assert!(lines.last().unwrap().contains("2 files checked"), "{:?}", lines);
+
+        // Once moved aside, the directory is clean again
+        assert!(check(&dir, true).problems.is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_file_in_use_is_left_in_place() {
+        let dir = temp_dir("in_use");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("marks.toml"), "= nothing").unwrap();
+
+        let report = check(&dir, false);
+        assert!(matches!(report.problems.as_slice(), [Problem::Unreadable { .. }]));
+        assert!(dir.join("marks.toml").exists());
+        assert!(!dir.join("marks.toml.corrupt").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_low_space() {
+        let mut report = check(&temp_dir("space"), true);
+        report.free_bytes = Some(MIN_FREE_BYTES - 1);
+        assert!(report.low_space() && !report.is_healthy());
+        let lines = report.lines(&Strings::default(), |path| path.display().to_string());
+        assert!(lines.iter().any(|line| line.contains("16.0 MB")), "{:?}", lines);
+        report.free_bytes = None;
+        assert!(!report.low_space());
+        fs::remove_dir_all(&report.dir).unwrap();
+    }
+}
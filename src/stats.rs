@@ -0,0 +1,172 @@
+//! Listening statistics for the stats screen, summarized from the play and
+//! skip counts and listening time kept in the library.
+
+use crate::library::{Library, PlayStats};
+use crate::playlist::{self, Track};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+/// Entries in each ranking.
+pub const TOP_COUNT: usize = 10;
+
+/// What the stats screen shows.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Summary {
+    /// Total time listened.
+    pub listened: Duration,
+    /// Time listened on each day of the week, Monday first.
+    pub by_weekday: [Duration; 7],
+    /// Artists by plays of their tracks.
+    pub top_artists: Vec<(String, u32)>,
+    /// Tracks by plays.
+    pub top_tracks: Vec<(String, u32)>,
+    /// Tracks by skips.
+    pub most_skipped: Vec<(String, u32)>,
+}
+
+impl Summary {
+    /// Returns true if nothing has been listened to yet.
+    pub fn is_empty(&self) -> bool {
+        self.listened.is_zero() && self.top_tracks.is_empty() && self.most_skipped.is_empty()
+    }
+}
+
+/// Summarizes the library's counts.
+///
+/// Tracks are named after their entry in `tracks` when they are in the
+/// playlist, else after their file; artists are only known for tracks in the
+/// playlist.
+pub fn summarize(library: &Library, tracks: &[Track]) -> Summary {
+    let mut known: HashMap<String, &Track> = HashMap::new();
+    for track in tracks {
+        // Segments of one file share its counts; the first one names it
+        let key = playlist::canonical_key(&track.path).to_string_lossy().into_owned();
+        known.entry(key).or_insert(track);
+    }
+    let counts: Vec<(String, Option<String>, PlayStats)> = library
+        .play_counts()
+        .map(|(key, stats)| match known.get(key) {
+            Some(track) => (track.display_name(), track.artist.clone(), stats),
+            None => (file_name(key), None, stats),
+        })
+        .collect();
+
+    let by_weekday = library.listened_by_weekday();
+    Summary {
+        listened: by_weekday.iter().sum(),
+        by_weekday,
+        top_artists: top(counts.iter().filter_map(|(_, artist, stats)| Some((artist.clone()?, stats.plays)))),
+        top_tracks: top(counts.iter().map(|(name, _, stats)| (name.clone(), stats.plays))),
+        most_skipped: top(counts.iter().map(|(name, _, stats)| (name.clone(), stats.skips))),
+    }
+}
+
+/// Adds up the counts per name and returns the [`TOP_COUNT`] highest, ties
+/// in name order. Zero counts are left out.
+fn top(counts: impl Iterator<Item = (String, u32)>) -> Vec<(String, u32)> {
+    let mut totals: HashMap<String, u32> = HashMap::new();
+    for (name, count) in counts {
+        let total = totals.entry(name).or_default();
+        *total = total.saturating_add(count);
+    }
+    let mut ranked: Vec<(String, u32)> = totals.into_iter().filter(|(_, count)| *count > 0).collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    ranked.truncate(TOP_COUNT);
+    ranked
+}
+
+/// Returns the file name of a library key without its extension.
+fn file_name(key: &str) -> String {
+    let path = Path::new(key);
+    path.file_stem().unwrap_or(path.as_os_str()).to_string_lossy().into_owned()
+}
+
+/// Returns the height of each histogram bar in eighths of a line, scaled so
+/// the largest value fills `lines` lines. Any nonzero value gets at least an
+/// eighth, so it stays visible.
+pub fn bar_heights(values: &[Duration], lines: u16) -> Vec<u32> {
+    let max = values.iter().max().copied().unwrap_or_default();
+    let full = u32::from(lines) * 8;
+    values
+        .iter()
+        .map(|value| {
+            if value.is_zero() {
+                return 0;
+            }
+            let eighths = (value.as_secs_f64() / max.as_secs_f64() * f64::from(full)).round() as u32;
+            eighths.clamp(1, full)
+        })
+        .collect()
+}
+
+/// Formats a listening time as hours and minutes ("3h 05m", "42m").
+pub fn format_listened(listened: Duration) -> String {
+    let minutes = listened.as_secs() / 60;
+    if minutes >= 60 {
+        format!("{}h {:02}m", minutes / 60, minutes % 60)
+    } else {
+        format!("{}m", minutes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_summarize() {
+        let mut library = Library::default();
+        let mut tracks = Vec::new();
+        for (name, artist, plays, skips) in [
+            ("a.mp3", Some("Band"), 3, 0),
+            ("b.mp3", Some("Band"), 2, 1),
+            ("c.mp3", Some("Solo"), 4, 0),
+            ("d.mp3", None, 0, 5),
+        ] {
+            let mut track = Track::new(PathBuf::from("/music").join(name));
+            track.title = Some(name.trim_end_matches(".mp3").to_uppercase());
+            track.artist = artist.map(String::from);
+            for _ in 0..plays {
+                library.record_play(&track.path);
+            }
+            for _ in 0..skips {
+                library.record_skip(&track.path);
+            }
+            tracks.push(track);
+        }
+        // Played before but no longer in the playlist
+        library.record_play(Path::new("/music/gone.flac"));
+        library.record_listened(Duration::from_secs(600), 0);
+
+        let summary = summarize(&library, &tracks[..3]);
+        let pairs = |v: &[(&str, u32)]| v.iter().map(|(n, c)| (n.to_string(), *c)).collect::<Vec<_>>();
+        assert_eq!(summary.top_artists, pairs(&[("Band", 5), ("Solo", 4)]));
+        assert_eq!(summary.top_tracks, pairs(&[("C", 4), ("A", 3), ("B", 2), ("gone", 1)]));
+        assert_eq!(summary.most_skipped, pairs(&[("d", 5), ("B", 1)]));
+        assert_eq!(summary.listened, Duration::from_secs(600));
+        assert_eq!(summary.by_weekday[3], Duration::from_secs(600));
+        assert!(!summary.is_empty());
+
+        assert!(summarize(&Library::default(), &tracks).is_empty());
+    }
+
+    #[test]
+    fn test_top_keeps_ten() {
+        let ranked = top((0..15).map(|i| (format!("{:02}", i), i % 4)));
+        assert_eq!(ranked.len(), TOP_COUNT);
+        assert_eq!(ranked[0], ("03".to_string(), 3));
+        assert_eq!(ranked[3], ("02".to_string(), 2));
+        assert!(ranked.iter().all(|(_, count)| *count > 0));
+    }
+
+    #[test]
+    fn test_bar_heights() {
+        let secs = |values: &[u64]| values.iter().map(|&s| Duration::from_secs(s)).collect::<Vec<_>>();
+        assert_eq!(bar_heights(&secs(&[100, 50, 0, 1, 25, 75, 100]), 2), [16, 8, 0, 1, 4, 12, 16]);
+        assert_eq!(bar_heights(&secs(&[0; 7]), 4), [0; 7]);
+        assert_eq!(format_listened(Duration::from_secs(42 * 60 + 59)), "42m");
+        assert_eq!(format_listened(Duration::from_secs(3 * 3600 + 5 * 60)), "3h 05m");
+    }
+}
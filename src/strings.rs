@@ -0,0 +1,476 @@
+//! User-visible text, with English built in and translations loaded from TOML.
+//!
+//! A translation lives in `languages/<language>.toml` next to the config file
+//! and may use dotted keys or tables (`[help]` then `quit = "..."`). Keys it
+//! leaves out fall back to English. `{name}` placeholders are filled in by
+//! [`Strings::text_with`].
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Name of the directory next to the config file that holds translations.
+pub const LANGUAGES_DIR: &str = "languages";
+
+/// Built-in English text for every key.
+const ENGLISH: &[(&str, &str)] = &[
+    ("goodbye", "Thanks for using juke!"),
+    ("error.no_audio_files", "No audio files found"),
+    ("error.usage", "Usage: {program} [directory, playlist.m3u, or - for paths on stdin]..."),
+    ("error.save_session", "Warning: Could not save session: {error}"),
+    ("error.edits_lost", "Warning: Unsaved playlist changes were lost"),
+    ("error.draw", "Fatal: Failed to draw terminal: {error}"),
+    ("error.playback", "Playback error: {error}"),
+    ("error.tag_write", "Could not write tags to {path}: {error}"),
+    ("error.listen_log", "Could not add to the listen log {path}: {error}"),
+    ("error.unplayable", "Warning: Skipping unplayable track: {track} ({error})"),
+    ("error.no_playable_tracks", "No playable tracks remaining"),
+    ("notice.see_log", "{message} — press e"),
+    ("notice.state_locked", "Another juke (pid {pid}) is using the state directory, so nothing is saved"),
+    ("notice.state_locked_unknown", "Another juke is using the state directory, so nothing is saved"),
+    ("notice.state_problems", "Problems found in the state directory"),
+    ("state.created", "Created the state directory {path}"),
+    ("state.read_only", "Cannot write to {paths} ({error}), so nothing is saved this session"),
+    ("state.unusable", "Cannot use the state directory {path}: {error}, so nothing is saved"),
+    ("state.quarantined", "{file} could not be read ({error}) and was moved to {moved}"),
+    ("state.unreadable", "{file} could not be read: {error}"),
+    ("state.low_space", "Only {free} free in the state directory, so the play history is not recorded"),
+    ("state.free_unknown", "unknown space"),
+    ("state.checked", "State directory {path}: {files} files checked, {free} free"),
+    ("notice.scan_errors", "Scanned {scanned} files, {errors} errors — press e"),
+    ("log.unknown_key", "Unknown key \"{key}\" in [keys]; the action keeps its other keys, or its own"),
+    ("log.remote_disabled", "Remote control disabled: {error}"),
+    ("log.mpd_disabled", "MPD listener on port {port} disabled: {error}"),
+    ("log.announce_unavailable", "Track announcements are on, but there is nowhere to write them: set ui.announce_path or redirect standard error"),
+    ("log.lookup_unavailable", "Tag lookup is enabled, but juke was built without MusicBrainz support"),
+    ("log.looked_up", "Looked up {path}: {track} ({score}%)"),
+    ("log.tags_written", "Wrote tags to {path}"),
+    ("log.duration_mismatch", "{track} ended at {played}, not the {claimed} it claimed; measuring its length"),
+    ("log.duration_measured", "Measured {track} frame by frame: {length}, not {claimed}"),
+    ("log.empty", "Nothing to report"),
+    ("log.title", "Log"),
+    ("log.footer", "Esc: Back | Up/Down: Scroll"),
+    ("session.past_end", "Session: saved position is past the end of the track, skipped"),
+    ("session.missing_track", "Session: saved track is no longer in the playlist, skipped"),
+    ("session.other_track", "Session: position belongs to another track, skipped"),
+    ("session.invalid_volume", "Session: invalid volume {volume}, skipped"),
+    ("session.invalid_repeat", "Session: invalid repeat mode, skipped"),
+    ("session.queue_restored", "Restored {count} queued tracks"),
+    ("refresh.nothing", "Nothing to refresh"),
+    ("refresh.stdin", "Tracks read from standard input cannot be refreshed"),
+    ("refresh.done", "+{added} / −{removed} tracks"),
+    ("refresh.failed", "Refresh failed: {error}"),
+    ("remote.nothing_playing", "Nothing is playing"),
+    ("remote.past_end", "Position is past the end of the track"),
+    ("remote.no_such_track", "No track at that position"),
+    ("preamp.changed", "Pre-amp: {db} dB"),
+    ("preamp.may_clip", "Pre-amp: {db} dB (may clip)"),
+    ("delete.deleted", "Deleted {track}"),
+    ("delete.trashed", "Moved to trash: {track}"),
+    ("delete.failed", "Could not delete {track}: {error}"),
+    ("delete.title_permanent", "Delete file?"),
+    ("delete.action_permanent", "permanently delete"),
+    ("delete.title_trash", "Move to trash?"),
+    ("delete.action_trash", "move to trash"),
+    ("delete.cancel", "cancel"),
+    ("state.playing", "▶ Playing"),
+    ("state.paused", "⏸ Paused"),
+    ("state.stopped", "⏹ Stopped"),
+    ("shuffle.off", "Shuffle: Off"),
+    ("shuffle.on", "⤮ Shuffle"),
+    ("repeat.off", "Repeat: Off"),
+    ("repeat.all", "↻ All"),
+    ("repeat.single", "↻ Single"),
+    ("player.help_hint", "? Help"),
+    ("album.mode", "Album mode: {album}"),
+    ("album.left", "Back to the playlist order"),
+    ("album.none", "No other tracks from this album"),
+    ("selection.mode", "Playing filtered selection ({count} tracks)"),
+    ("selection.over", "End of the selection"),
+    ("prefs.title", "Preferences"),
+    ("prefs.footer", "←/→: Change | Esc: Save and close"),
+    ("prefs.on", "On"),
+    ("prefs.off", "Off"),
+    ("prefs.volume", "Volume at start"),
+    ("prefs.seek_step", "Seek step"),
+    ("prefs.replay_secs", "Replay jump"),
+    ("prefs.limiter", "Limiter"),
+    ("prefs.stop_after_latched", "Keep pausing after each track"),
+    ("prefs.preload", "Decode the next track ahead"),
+    ("preload.status", "⇉ ready"),
+    ("log.decoded_cache", "Decoded cache: {entries} tracks, {used} of {budget} MB"),
+    ("prefs.theme", "Theme"),
+    ("prefs.visualizer", "Visualizer"),
+    ("prefs.visualizer_fps", "Visualizer frame rate"),
+    ("visualizer.source", "Visualizer: {source}"),
+    ("visualizer.real", "frequency bands measured from the audio"),
+    ("visualizer.loudness", "loudness measured from the audio"),
+    ("visualizer.simulated", "simulated levels, as visualizer.source asks"),
+    ("visualizer.test", "fixed demo levels"),
+    ("prefs.notifications", "Desktop notifications"),
+    ("prefs.saved", "Preferences saved to {path}"),
+    ("prefs.save_failed", "Could not save preferences: {error}"),
+    ("prefs.not_saved", "No config file, so preferences last until juke exits"),
+    ("browser.no_directory", "The folder browser needs a directory, not a playlist file"),
+    ("browser.failed", "Cannot open folder: {error}"),
+    ("browser.no_tracks", "No tracks in {folder}"),
+    ("browser.empty", "No folders here — p plays this one"),
+    ("browser.footer", "Enter: Open | Backspace: Up | p: Play | Esc: Back"),
+    ("replay.back", "-{seconds}s"),
+    ("seek.target", "→ {time}"),
+    ("preview.playing", "Previewing: {track}"),
+    ("stop_after.status", "■ after this track"),
+    ("limiter.status", "LIM"),
+    ("player.volume", "Vol {volume}%"),
+    ("player.muted", "Muted"),
+    ("stop_after.on", "Pausing when this track ends"),
+    ("stop_after.off", "Playing on when this track ends"),
+    ("player.no_track", "No track loaded"),
+    ("empty.title", "The playlist is empty"),
+    ("empty.hint", "F5: scan {source} again | o: preferences | q: quit"),
+    ("empty.hint_no_source", "q: quit"),
+    ("empty.nothing_to_play", "Nothing to play: the playlist is empty"),
+    ("player.resumed", "Resumed after sleep"),
+    ("tracks.title", "Tracks"),
+    ("tracks.prompt", "Track List - Start typing to search..."),
+    ("tracks.search", "Search: {query}_"),
+    ("tracks.disc", "Disc {disc}"),
+    ("tracks.no_matches", "No tracks match your search"),
+    ("tracks.no_source", "Chosen tracks"),
+    ("chips.marked", "Marked"),
+    ("chips.queued", "Queued"),
+    ("chips.unplayable", "Unplayable"),
+    ("chips.album", "Album"),
+    ("chips.none", "No tracks match the {chip} filter"),
+    ("tracks.filtered", "filter: '{query}' — {matches}/{total}"),
+    ("tracks.footer", "Esc: Back | Enter: Play selected | Shift+Enter: Play these | Shift+P: Preview | Shift+Q: Queue | m / ': Mark / Jump | o: Sort | Shift+J: A-Z | Shift+D: Delete | e: Rename | 1-4: Filters | C-l: Clear filters | Type to search"),
+    ("jump.footer", "Letter: Jump to it, again for the next | #: Not A-Z | Enter: Play selected | Esc / Shift+J: Back to search"),
+    ("jump.none", "Nothing under {letter}"),
+    ("marks.title", "Marks"),
+    ("marks.footer", "Letter / Enter: Jump | Esc: Close"),
+    ("marks.set", "Marked {track} as {letter}"),
+    ("marks.unset", "No mark {letter}"),
+    ("marks.missing", "The track marked {letter} is no longer in the playlist"),
+    ("marks.invalid", "Marks are letters, not {key}"),
+    ("marks.none", "No marks yet: press m and a letter to mark a track"),
+    ("marks.gone", "{path} (not in the playlist)"),
+    ("queue.title", "Up Next"),
+    ("queue.empty", "Nothing left to play"),
+    ("queue.added", "Queued {track}"),
+    ("queue.removed", "Removed {track} from the queue"),
+    ("sort.menu_title", "Sort by ({direction})"),
+    ("sort.footer", "Enter: Apply | R: Reverse | Esc: Cancel"),
+    ("sort.title", "Title"),
+    ("sort.artist", "Artist"),
+    ("sort.album", "Album"),
+    ("sort.track_number", "Track #"),
+    ("sort.duration", "Duration"),
+    ("sort.path", "Path"),
+    ("sort.random", "Random"),
+    ("sort.ascending", "ascending"),
+    ("sort.descending", "descending"),
+    ("sort.a_to_z", "A→Z"),
+    ("sort.z_to_a", "Z→A"),
+    ("sort.low_to_high", "low→high"),
+    ("sort.high_to_low", "high→low"),
+    ("sort.sorted", "Sorted by {key} ({direction})"),
+    ("sort.shuffled", "Track list shuffled"),
+    ("stats.title", "Statistics"),
+    ("stats.listened", "Listening time: {time}"),
+    ("stats.weekdays", "Mon Tue Wed Thu Fri Sat Sun"),
+    ("stats.top_artists", "Top artists"),
+    ("stats.top_tracks", "Top tracks"),
+    ("stats.most_skipped", "Most skipped"),
+    ("stats.none", "None yet"),
+    ("stats.empty", "Nothing played yet. Play counts and listening time show up here as you listen."),
+    ("help.title", "Help"),
+    ("help.heading", "juke - Keybindings"),
+    ("help.play_pause", "Play / Pause"),
+    ("help.next", "Next track"),
+    ("help.previous", "Previous track"),
+    ("help.seek_forward", "Seek forward {seconds}s"),
+    ("help.seek_backward", "Seek backward {seconds}s"),
+    ("help.volume_up", "Volume up"),
+    ("help.volume_down", "Volume down"),
+    ("help.mute", "Mute or unmute"),
+    ("help.preamp_up", "Pre-amp up"),
+    ("help.preamp_down", "Pre-amp down"),
+    ("help.shuffle", "Toggle shuffle"),
+    ("help.repeat", "Cycle repeat mode"),
+    ("help.replay", "Replay the last seconds"),
+    ("help.album", "Play this track's album in order / back to the playlist"),
+    ("help.stop_after", "Pause when this track ends"),
+    ("help.track_list", "Toggle track list"),
+    ("help.stats", "Show listening statistics"),
+    ("help.browser", "Browse folders and play one"),
+    ("help.preferences", "Preferences"),
+    ("help.queue_panel", "Toggle upcoming tracks panel"),
+    ("help.focus_next", "Focus the next pane"),
+    ("help.focus_previous", "Focus the previous pane"),
+    ("focus.now_playing", "Now Playing"),
+    ("help.refresh", "Refresh playlist from source"),
+    ("help.log", "Show log"),
+    ("help.help", "Toggle help (this screen)"),
+    ("help.quit", "Quit"),
+    ("help.palette", "Search commands"),
+    ("help.in_track_list", "In the track list"),
+    ("help.sort_menu", "Sort the track list"),
+    ("help.marks_menu", "List marks"),
+    ("help.set_mark", "Mark the selected track with a letter"),
+    ("help.jump_to_mark", "Jump to a marked track"),
+    ("help.jump_mode", "Jump by initial letter"),
+    ("help.chip_marked", "Show only marked tracks"),
+    ("help.chip_queued", "Show only queued tracks"),
+    ("help.unplayable", "Show only tracks that failed to open"),
+    ("help.chip_album", "Show only the playing track's album"),
+    ("help.clear_chips", "Turn the filters off"),
+    ("help.queue", "Queue or unqueue the selected track"),
+    ("help.preview", "Preview the selected track"),
+    ("help.delete", "Delete the selected track"),
+    ("help.rename", "Rename the selected track in the playlist"),
+    ("help.save_playlist", "Save the playlist as M3U"),
+    ("help.read_tags", "Read the tags a fast scan skipped"),
+    ("help.write_tags", "Write the selected track's new title and looked-up tags into its file"),
+    ("help.play_and_save", "Play the tracks listed and save them as a playlist"),
+    ("prompt.save_results", " Save these tracks as "),
+    ("selection.saved", "Playing {count} tracks · saved to {path}"),
+    ("tags.read", "Read the tags of {count} files"),
+    ("task.refresh", "Refreshing"),
+    ("task.read_tags", "Reading tags"),
+    ("task.measure", "Measuring length"),
+    ("task.waiting", "{name} (waiting)"),
+    ("task.percent", "{name} {percent}%"),
+    ("task.count", "{name} ({done} files)"),
+    ("task.status", "{tasks} · Esc to cancel"),
+    ("task.cancelled", "Cancelled: {tasks}"),
+    ("tags.all_read", "Every track's tags are read"),
+    ("tags.nothing_to_write", "Nothing to write: the track was neither renamed nor looked up"),
+    ("tags.writing", "Writing tags to {track}"),
+    ("tags.write_held", "Writing tags to {track} once it stops playing"),
+    ("log.fast_scan", "Scanned without reading tags: {cached} tracks filled in from the tag cache, {unread} left to read as they play or with Shift+I"),
+    ("prompt.footer", "Enter: OK | Esc: Cancel | Up/Down: History | Ctrl+R: Search it"),
+    ("prompt.search", "History search: "),
+    ("prompt.rename", " Rename track "),
+    ("prompt.save_playlist", " Save playlist as "),
+    ("tracks.renamed", "Renamed to {title}; C-s saves the playlist"),
+    ("playlist.saved", "Saved {count} tracks to {path}"),
+    ("playlist.settings_applied", "Playlist preferences applied"),
+    ("playlist.save_failed", "Could not save the playlist: {error}"),
+    ("quit.title", " Unsaved playlist changes "),
+    ("quit.save", "Save and quit"),
+    ("quit.discard", "Quit without saving"),
+    ("quit.cancel", "Keep playing"),
+    ("quit.footer", "Enter: Choose | s: Save | d: Discard | Esc: Cancel"),
+    ("palette.title", "Command: {query}_"),
+    ("palette.footer", "Enter: Run | Up/Down: Choose | Esc: Close | Type to search"),
+    ("palette.no_matches", "No commands match"),
+    ("help.close", "Press any key to close"),
+    (
+        "size.warning",
+        "Terminal too small!\n\nMinimum: {min_width}x{min_height}\nCurrent: {width}x{height}\n\nPlease resize terminal to continue.",
+    ),
+    ("plain.playing", "Playing: {track} [{position}/{length}]"),
+    ("plain.paused", "Paused at {time}"),
+    ("plain.resumed", "Playing from {time}"),
+    ("plain.stopped", "Stopped"),
+    ("plain.volume", "Volume {volume}%"),
+    ("plain.shuffle_on", "Shuffle on"),
+    ("plain.shuffle_off", "Shuffle off"),
+    ("plain.repeat_off", "Repeat off"),
+    ("plain.repeat_all", "Repeat all"),
+    ("plain.repeat_single", "Repeat one track"),
+    (
+        "plain.track_list",
+        "Track list, {count} tracks. Type to search, Up and Down to choose, Enter to play, Esc to go back.",
+    ),
+    ("plain.search", "Search {query}: {matches} matches"),
+    ("plain.selected", "{position}. {track}"),
+    ("plain.confirm_delete", "{question} {path}. Press y to {action} or n to cancel."),
+    ("notify.paused", "Paused: {summary}"),
+    ("notify.playlist_end", "End of playlist"),
+];
+
+/// The text shown to the user, in the configured language.
+#[derive(Debug, Clone, Default)]
+pub struct Strings {
+    translations: HashMap<String, String>,
+}
+
+impl Strings {
+    /// Loads the translation for `ui.language` from the config directory.
+    ///
+    /// `None` and `"en"` without a translation file give the built-in English.
+    /// Unknown keys in the file are ignored with a warning.
+    pub fn load(language: Option<&str>, config_dir: Option<&Path>) -> Result<Self, StringsError> {
+        let Some(language) = language else {
+            return Ok(Self::default());
+        };
+        if language.is_empty() || language.contains(['/', '\\', '.']) {
+            return Err(StringsError::UnknownLanguage(language.to_string(), None));
+        }
+        let path = config_dir.map(|dir| dir.join(LANGUAGES_DIR).join(format!("{}.toml", language)));
+
+        let contents = match path.as_deref().map(std::fs::read_to_string) {
+            Some(Ok(contents)) => contents,
+            _ if language.eq_ignore_ascii_case("en") => return Ok(Self::default()),
+            Some(Err(e)) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(StringsError::Read(path.clone().unwrap_or_default(), e.to_string()));
+            }
+            _ => return Err(StringsError::UnknownLanguage(language.to_string(), path)),
+        };
+        let table: toml::Table = contents
+            .parse()
+            .map_err(|e: toml::de::Error| StringsError::Read(path.clone().unwrap_or_default(), e.message().to_string()))?;
+
+        let mut translations = HashMap::new();
+        flatten(&table, "", &mut translations);
+        translations.retain(|key, _| {
+            let known = ENGLISH.iter().any(|(k, _)| k == key);
+            if !known {
+                eprintln!("Warning: Ignoring unknown key '{}' in the '{}' translation", key, language);
+            }
+            known
+        });
+
+        Ok(Self { translations })
+    }
+
+    /// Returns the text for a key, or the key itself if it is unknown.
+    pub fn text<'a>(&'a self, key: &'a str) -> &'a str {
+        if let Some(text) = self.translations.get(key) {
+            return text;
+        }
+        ENGLISH
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map_or(key, |(_, text)| text)
+    }
+
+    /// Returns the text for a key with its `{name}` placeholders filled in.
+    ///
+    /// Placeholders without a value are left as they are.
+    pub fn text_with(&self, key: &str, values: &[(&str, &dyn fmt::Display)]) -> String {
+        let mut text = self.text(key).to_string();
+        for (name, value) in values {
+            text = text.replace(&format!("{{{}}}", name), &value.to_string());
+        }
+        text
+    }
+}
+
+/// Collects string values from nested tables under dotted keys.
+fn flatten(table: &toml::Table, prefix: &str, out: &mut HashMap<String, String>) {
+    for (key, value) in table {
+        let key = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+        match value {
+            toml::Value::String(text) => {
+                out.insert(key, text.clone());
+            }
+            toml::Value::Table(table) => flatten(table, &key, out),
+            _ => eprintln!("Warning: Ignoring translation key '{}', values must be text", key),
+        }
+    }
+}
+
+/// Errors that can occur while loading a translation.
+#[derive(Debug, PartialEq, Eq)]
+pub enum StringsError {
+    /// No translation file exists for the language.
+    UnknownLanguage(String, Option<PathBuf>),
+    /// The translation file could not be read or parsed.
+    Read(PathBuf, String),
+}
+
+impl fmt::Display for StringsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StringsError::UnknownLanguage(language, Some(path)) => {
+                write!(f, "No translation for language '{}' (expected {})", language, path.display())
+            }
+            StringsError::UnknownLanguage(language, None) => {
+                write!(f, "No translation for language '{}'", language)
+            }
+            StringsError::Read(path, e) => write!(f, "Could not read translation {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl std::error::Error for StringsError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns every key passed to `text` or `text_with` in the sources.
+    fn referenced_keys() -> Vec<(String, String)> {
+        let src = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+        let mut keys = Vec::new();
+        for entry in std::fs::read_dir(src).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().and_then(|e| e.to_str()) != Some("rs") {
+                continue;
+            }
+            let source = std::fs::read_to_string(&path).unwrap();
+            for call in [".text(\"", ".text_with(\""] {
+                for (at, _) in source.match_indices(call) {
+                    let rest = &source[at + call.len()..];
+                    let key = &rest[..rest.find('"').unwrap()];
+                    keys.push((path.display().to_string(), key.to_string()));
+                }
+            }
+        }
+        keys
+    }
+
+    #[test]
+    fn test_referenced_keys_exist() {
+        let keys = referenced_keys();
+        assert!(keys.len() > 40, "found only {} keys, has the call syntax changed?", keys.len());
+        for (file, key) in keys {
+            assert!(ENGLISH.iter().any(|(k, _)| *k == key), "{} uses unknown key '{}'", file, key);
+        }
+    }
+
+    #[test]
+    fn test_catalog_keys_are_unique() {
+        let mut seen = std::collections::HashSet::new();
+        for (key, _) in ENGLISH {
+            assert!(seen.insert(key), "duplicate key '{}'", key);
+        }
+    }
+
+    #[test]
+    fn test_load_translation() {
+        let dir = std::env::temp_dir().join(format!("juke_strings_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join(LANGUAGES_DIR)).unwrap();
+        std::fs::write(
+            dir.join(LANGUAGES_DIR).join("fr.toml"),
+            "goodbye = \"Merci d'avoir utilisé juke !\"\nunknown = \"x\"\n\n[help]\nquit = \"Quitter\"\n",
+        )
+        .unwrap();
+
+        let strings = Strings::load(Some("fr"), Some(&dir)).unwrap();
+        let missing = Strings::load(Some("de"), Some(&dir));
+        let english = Strings::load(Some("en"), Some(&dir)).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(strings.text("goodbye"), "Merci d'avoir utilisé juke !");
+        assert_eq!(strings.text("help.quit"), "Quitter");
+        // Untranslated keys fall back to English
+        assert_eq!(strings.text("help.next"), "Next track");
+        assert!(!strings.translations.contains_key("unknown"));
+        assert!(matches!(missing, Err(StringsError::UnknownLanguage(..))));
+        assert_eq!(english.text("goodbye"), "Thanks for using juke!");
+        assert!(Strings::load(Some("../fr"), Some(Path::new("/"))).is_err());
+    }
+
+    #[test]
+    fn test_placeholders() {
+        let strings = Strings::default();
+        assert_eq!(
+            strings.text_with("delete.failed", &[("track", &"a.mp3"), ("error", &"denied")]),
+            "Could not delete a.mp3: denied"
+        );
+        assert_eq!(strings.text_with("help.seek_forward", &[]), "Seek forward {seconds}s");
+    }
+}
@@ -0,0 +1,88 @@
+//! Noticing that the system was suspended.
+//!
+//! `Instant` stops while the system sleeps, and a clock that keeps counting
+//! through sleep, `CLOCK_BOOTTIME` on Linux and the wall clock elsewhere,
+//! runs ahead of it by the time asleep. A stalled or stopped process moves
+//! both alike, so it is never taken for sleep.
+
+use std::time::{Duration, Instant};
+
+/// Time asleep from which playback is recovered.
+pub const SLEEP_GAP: Duration = Duration::from_secs(30);
+
+/// Both clocks, read together.
+#[derive(Debug, Clone, Copy)]
+pub struct Clocks {
+    /// Stops while the system sleeps.
+    awake: Instant,
+    /// Keeps counting while the system sleeps.
+    total: Duration,
+}
+
+impl Clocks {
+    pub fn now() -> Self {
+        Self { awake: Instant::now(), total: total_clock() }
+    }
+}
+
+/// Returns how long the system slept between two readings.
+pub fn slept(before: Clocks, after: Clocks) -> Duration {
+    let awake = after.awake.duration_since(before.awake);
+    // A wall clock set back counts as no sleep at all
+    after.total.saturating_sub(before.total).saturating_sub(awake)
+}
+
+/// Returns whether the system was suspended between two readings.
+pub fn was_suspended(before: Clocks, after: Clocks) -> bool {
+    slept(before, after) >= SLEEP_GAP
+}
+
+#[cfg(target_os = "linux")]
+fn total_clock() -> Duration {
+    // SAFETY: timespec is plain data, for which all zeroes is valid
+    let mut time: libc::timespec = unsafe { std::mem::zeroed() };
+    // SAFETY: time is valid for writes
+    if unsafe { libc::clock_gettime(libc::CLOCK_BOOTTIME, &mut time) } != 0 {
+        return Duration::ZERO;
+    }
+    Duration::new(time.tv_sec as u64, time.tv_nsec as u32)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn total_clock() -> Duration {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clocks(start: Instant, awake: u64, total: u64) -> Clocks {
+        Clocks { awake: start + Duration::from_secs(awake), total: Duration::from_secs(1_000 + total) }
+    }
+
+    #[test]
+    fn test_only_sleep_counts() {
+        let start = Instant::now();
+        let before = clocks(start, 0, 0);
+
+        // Suspended for 45 seconds, one second after the last update
+        assert_eq!(slept(before, clocks(start, 1, 46)), Duration::from_secs(45));
+        assert!(was_suspended(before, clocks(start, 1, 46)));
+
+        // The main loop stalled, or the process was stopped, for a minute
+        assert!(!was_suspended(before, clocks(start, 60, 60)));
+
+        // A short nap, and the wall clock set back an hour
+        assert!(!was_suspended(before, clocks(start, 1, 10)));
+        let set_back = Clocks { awake: start + Duration::from_secs(1), total: Duration::ZERO };
+        assert_eq!(slept(before, set_back), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_clocks_agree_while_awake() {
+        let before = Clocks::now();
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(slept(before, Clocks::now()) < Duration::from_secs(1));
+    }
+}
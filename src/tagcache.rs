@@ -0,0 +1,214 @@
+//! Tags and durations read after a fast scan, kept in `tags.toml` in the
+//! state directory so that the next launch shows them without reading the
+//! files again.
+//!
+//! Entries are keyed by path as scanned and not checked against the files,
+//! since even that costs a round trip per file on a network mount. Playing a
+//! track or reading tags again brings its entry up to date.
+//!
+//! Lengths measured by counting an MP3's frames are kept here too, and
+//! replace what the file claims however it was scanned.
+
+use crate::playlist::{self, Playlist, Track};
+use crate::task::Reporter;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// What was read from one file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct Entry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    artist: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    album: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    year: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    track: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    disc: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    duration_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    replay_gain: Option<f32>,
+}
+
+/// Persistent store of tags read from files after a fast scan.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TagCache {
+    #[serde(default)]
+    files: BTreeMap<String, Entry>,
+    /// Lengths in milliseconds measured frame by frame, by path.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    measured: BTreeMap<String, u64>,
+    /// File the cache was loaded from and is saved to.
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    /// Entries were added since the cache was loaded or saved.
+    #[serde(skip)]
+    changed: bool,
+}
+
+impl TagCache {
+    /// Loads the cache from a state directory, returning an empty one if it is
+    /// missing or unreadable. Without a state directory nothing is persisted.
+    pub fn load(state_dir: Option<&Path>) -> Self {
+        let path = state_dir.map(|dir| dir.join("tags.toml"));
+        let cache: Self = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default();
+        Self { path, ..cache }
+    }
+
+    /// Saves the cache to the file it was loaded from, if anything was added.
+    pub fn save(&mut self) -> std::io::Result<()> {
+        let Some(path) = self.path.as_ref().filter(|_| self.changed) else {
+            return Ok(());
+        };
+        let toml_string = toml::to_string_pretty(self).map_err(std::io::Error::other)?;
+        fs::write(path, toml_string)?;
+        self.changed = false;
+        Ok(())
+    }
+
+    /// Stores what was read from a file.
+    pub fn insert(&mut self, probed: &Track) {
+        let entry = Entry {
+            title: probed.title.clone(),
+            artist: probed.artist.clone(),
+            album: probed.album.clone(),
+            year: probed.year,
+            track: probed.track_number,
+            disc: probed.disc_number,
+            duration_ms: probed.duration.map(|d| d.as_millis() as u64),
+            replay_gain: probed.replay_gain,
+        };
+        let key = probed.path.to_string_lossy().to_string();
+        if self.files.get(&key) != Some(&entry) {
+            self.files.insert(key, entry);
+            self.changed = true;
+        }
+    }
+
+    /// Stores the length of a file measured frame by frame.
+    pub fn set_measured(&mut self, path: &Path, length: Duration) {
+        let millis = length.as_millis() as u64;
+        if self.measured.insert(path.to_string_lossy().to_string(), millis) != Some(millis) {
+            self.changed = true;
+        }
+    }
+
+    /// Returns the length measured for a file, if it was.
+    pub fn measured(&self, path: &Path) -> Option<Duration> {
+        self.measured.get(path.to_string_lossy().as_ref()).map(|&millis| Duration::from_millis(millis))
+    }
+
+    /// Returns what was last read from a file, as a track.
+    pub fn get(&self, path: &Path) -> Option<Track> {
+        let entry = self.files.get(path.to_string_lossy().as_ref())?;
+        let mut track = Track::new(path.to_path_buf());
+        track.title = entry.title.clone();
+        track.artist = entry.artist.clone();
+        track.album = entry.album.clone();
+        track.year = entry.year;
+        track.track_number = entry.track;
+        track.disc_number = entry.disc;
+        track.duration = self.measured(path).or(entry.duration_ms.map(Duration::from_millis));
+        track.replay_gain = entry.replay_gain;
+        track.probed = true;
+        Some(track)
+    }
+
+    /// Fills in the tracks a fast scan left unread from what is cached, and
+    /// the lengths measured of the others. Returns how many were filled in.
+    pub fn apply(&self, playlist: &mut Playlist) -> usize {
+        let mut applied = 0;
+        for index in 0..playlist.len() {
+            let track = &playlist.tracks()[index];
+            if !track.probed {
+                if let Some(cached) = self.get(&track.path) {
+                    playlist.update_track(index, |track| track.enrich(&cached));
+                    applied += 1;
+                }
+            } else if let Some(length) = self.measured(&track.path)
+                && !track.is_segment()
+                && track.duration != Some(length)
+            {
+                playlist.update_track(index, |track| track.duration = Some(length));
+            }
+        }
+        applied
+    }
+}
+
+/// Reads the tags of files as the work of a task, sending each file's as
+/// it is read, until the task is cancelled.
+pub fn read(paths: Vec<PathBuf>, reporter: &Reporter<Track>) {
+    let total = paths.len();
+    for (read, path) in paths.iter().enumerate() {
+        if !reporter.send(playlist::extract_metadata(path)) || !reporter.progress(read + 1, Some(total)) {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_fills_unread_tracks() {
+        let dir = std::env::temp_dir().join(format!("juke_tagcache_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut probed = Track::new(PathBuf::from("/smb/music/01.flac"));
+        probed.title = Some("Opening".to_string());
+        probed.duration = Some(Duration::from_millis(201_500));
+
+        let mut cache = TagCache::load(Some(&dir));
+        cache.insert(&probed);
+        cache.save().unwrap();
+        assert!(!cache.changed);
+        let cache = TagCache::load(Some(&dir));
+        fs::remove_dir_all(&dir).unwrap();
+
+        let mut playlist = Playlist::new();
+        playlist.add_track(Track::new(PathBuf::from("/smb/music/01.flac")));
+        playlist.add_track(Track::new(PathBuf::from("/smb/music/02.flac")));
+        assert_eq!(cache.apply(&mut playlist), 1);
+        let tracks = playlist.tracks();
+        assert_eq!(tracks[0].display_name(), "Opening");
+        assert_eq!(tracks[0].duration, Some(Duration::from_millis(201_500)));
+        assert!(tracks[0].probed && !tracks[1].probed);
+        assert!(playlist.matches(0, "opening"));
+    }
+
+    #[test]
+    fn test_measured_lengths_replace_claimed_ones() {
+        let mut cache = TagCache::default();
+        let mut probed = Track::new(PathBuf::from("/music/vbr.mp3"));
+        probed.duration = Some(Duration::from_secs(180));
+        cache.insert(&probed);
+        cache.set_measured(&probed.path, Duration::from_millis(214_300));
+        assert_eq!(cache.get(&probed.path).unwrap().duration, Some(Duration::from_millis(214_300)));
+
+        let mut read = probed.clone();
+        read.probed = true;
+        let mut segment = read.clone();
+        segment.end_offset = Some(Duration::from_secs(60));
+        segment.duration = Some(Duration::from_secs(60));
+        let mut playlist = Playlist::new();
+        playlist.add_track(read);
+        playlist.add_track(segment);
+        assert_eq!(cache.apply(&mut playlist), 0);
+        let tracks = playlist.tracks();
+        assert_eq!(tracks[0].duration, Some(Duration::from_millis(214_300)));
+        assert_eq!(tracks[1].duration, Some(Duration::from_secs(60)));
+    }
+}
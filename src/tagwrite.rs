@@ -0,0 +1,361 @@
+//! Writing tags into music files, one at a time on a background thread.
+//!
+//! Everything that changes tags, such as ReplayGain from a loudness scan or
+//! tags found on MusicBrainz, goes through a [`TagWriteQueue`]. Writes are
+//! spaced out, retried when the failure looks temporary, and checked by
+//! reading the file back. The file being played is never written while it
+//! plays, since some platforms lock open files; its writes wait until the
+//! track changes.
+
+use lofty::config::WriteOptions;
+use lofty::error::{ErrorKind, LoftyError};
+use lofty::file::{AudioFile, TaggedFileExt};
+use lofty::tag::{Accessor, ItemKey, Tag};
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, mpsc};
+use std::time::{Duration, Instant};
+
+/// Least time between two writes, so a batch does not hog the disk that
+/// is being played from.
+const WRITE_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Attempts made at a write failing for a reason that may pass.
+const ATTEMPTS: u32 = 3;
+
+/// Wait before the first retry, doubled for each one after.
+const RETRY_DELAY: Duration = Duration::from_millis(200);
+
+/// A change to a file's tags.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TagEdit {
+    /// REPLAYGAIN_TRACK_GAIN and REPLAYGAIN_TRACK_PEAK.
+    ReplayGain { gain_db: f64, peak: f64 },
+    /// Title, artist, and album, each written only where the file has none.
+    FillMissing { title: String, artist: Option<String>, album: Option<String> },
+    /// A title given in juke, replacing the file's.
+    Title(String),
+}
+
+/// Errors from writing tags.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TagWriteError {
+    /// The file is marked read-only, so juke leaves it alone.
+    ReadOnly,
+    /// The file could not be read or written.
+    Io(String),
+    /// The file could not be reached for a reason that may pass.
+    Busy(String),
+    /// The file's tags could not be read or written.
+    Tag(String),
+    /// The file was written but does not read back.
+    Unreadable(String),
+    /// The queue stopped before the write was made.
+    Closed,
+}
+
+impl fmt::Display for TagWriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TagWriteError::ReadOnly => write!(f, "File is read-only"),
+            TagWriteError::Io(e) => write!(f, "IO error: {}", e),
+            TagWriteError::Busy(e) => write!(f, "File busy: {}", e),
+            TagWriteError::Tag(e) => write!(f, "Tag error: {}", e),
+            TagWriteError::Unreadable(e) => write!(f, "File does not read back after writing: {}", e),
+            TagWriteError::Closed => write!(f, "Tag writing stopped"),
+        }
+    }
+}
+
+impl std::error::Error for TagWriteError {}
+
+impl TagWriteError {
+    /// Returns whether trying again later might work.
+    fn is_transient(&self) -> bool {
+        matches!(self, TagWriteError::Busy(_))
+    }
+}
+
+impl From<LoftyError> for TagWriteError {
+    fn from(e: LoftyError) -> Self {
+        match e.kind() {
+            // A file that is busy or briefly unavailable may be writable in a moment
+            ErrorKind::Io(io) if is_transient(io.kind()) => TagWriteError::Busy(e.to_string()),
+            ErrorKind::Io(_) => TagWriteError::Io(e.to_string()),
+            _ => TagWriteError::Tag(e.to_string()),
+        }
+    }
+}
+
+fn is_transient(kind: io::ErrorKind) -> bool {
+    matches!(
+        kind,
+        io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::ResourceBusy
+    )
+}
+
+/// Writes an edit into a file, then reads the file back to check it.
+pub fn write(path: &Path, edit: &TagEdit) -> Result<(), TagWriteError> {
+    let metadata = fs::metadata(path).map_err(|e| {
+        if is_transient(e.kind()) { TagWriteError::Busy(e.to_string()) } else { TagWriteError::Io(e.to_string()) }
+    })?;
+    if metadata.permissions().readonly() {
+        return Err(TagWriteError::ReadOnly);
+    }
+
+    let mut tagged_file = lofty::read_from_path(path)?;
+    if tagged_file.primary_tag().is_none() {
+        let tag_type = tagged_file.primary_tag_type();
+        tagged_file.insert_tag(Tag::new(tag_type));
+    }
+    if let Some(tag) = tagged_file.primary_tag_mut() {
+        match edit {
+            TagEdit::ReplayGain { gain_db, peak } => {
+                tag.insert_text(ItemKey::ReplayGainTrackGain, format!("{:.2} dB", gain_db));
+                tag.insert_text(ItemKey::ReplayGainTrackPeak, format!("{:.6}", peak));
+            }
+            TagEdit::FillMissing { title, artist, album } => {
+                if tag.title().is_none() {
+                    tag.set_title(title.clone());
+                }
+                if tag.artist().is_none()
+                    && let Some(artist) = artist
+                {
+                    tag.set_artist(artist.clone());
+                }
+                if tag.album().is_none()
+                    && let Some(album) = album
+                {
+                    tag.set_album(album.clone());
+                }
+            }
+            TagEdit::Title(title) => tag.set_title(title.clone()),
+        }
+    }
+    tagged_file.save_to_path(path, WriteOptions::default())?;
+
+    lofty::read_from_path(path).map_err(|e| TagWriteError::Unreadable(e.to_string()))?;
+    Ok(())
+}
+
+/// How a write went, for the log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    pub path: PathBuf,
+    pub result: Result<(), TagWriteError>,
+}
+
+/// The result of one queued write, once it is made.
+#[derive(Debug)]
+pub struct Receipt {
+    rx: mpsc::Receiver<Result<(), TagWriteError>>,
+}
+
+impl Receipt {
+    /// Waits for the write to be made.
+    pub fn wait(self) -> Result<(), TagWriteError> {
+        self.rx.recv().unwrap_or(Err(TagWriteError::Closed))
+    }
+}
+
+/// A write waiting its turn.
+struct Request {
+    path: PathBuf,
+    edit: TagEdit,
+    reply: mpsc::Sender<Result<(), TagWriteError>>,
+}
+
+enum Command {
+    Write(Request),
+    /// The file now playing, whose writes wait.
+    Playing(Option<PathBuf>),
+}
+
+/// Writes tags one file at a time on a background thread.
+pub struct TagWriteQueue {
+    tx: mpsc::Sender<Command>,
+    outcomes: Mutex<mpsc::Receiver<Outcome>>,
+}
+
+impl TagWriteQueue {
+    /// Starts the thread writing tags.
+    pub fn spawn() -> Self {
+        Self::with_writer(write)
+    }
+
+    /// Starts the thread with a function doing the writing instead of
+    /// [`write`].
+    pub fn with_writer(writer: fn(&Path, &TagEdit) -> Result<(), TagWriteError>) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let (outcome_tx, outcomes) = mpsc::channel();
+        std::thread::spawn(move || run(rx, outcome_tx, writer, RETRY_DELAY));
+        Self { tx, outcomes: Mutex::new(outcomes) }
+    }
+
+    /// Queues an edit to a file.
+    pub fn enqueue(&self, path: &Path, edit: TagEdit) -> Receipt {
+        let (reply, rx) = mpsc::channel();
+        // If the thread is gone the reply is dropped, which the receipt reports
+        let _ = self.tx.send(Command::Write(Request { path: path.to_path_buf(), edit, reply }));
+        Receipt { rx }
+    }
+
+    /// Tells the queue which file is playing, so its writes wait until it
+    /// is not.
+    pub fn set_playing(&self, path: Option<&Path>) {
+        let _ = self.tx.send(Command::Playing(path.map(Path::to_path_buf)));
+    }
+
+    /// Returns the writes made since the last call.
+    pub fn finished(&self) -> Vec<Outcome> {
+        self.outcomes.lock().unwrap().try_iter().collect()
+    }
+}
+
+/// Makes queued writes until every sender is gone. Writes to the playing
+/// file are held back, and made once it changes or the queue is dropped.
+fn run(
+    rx: mpsc::Receiver<Command>,
+    outcomes: mpsc::Sender<Outcome>,
+    writer: fn(&Path, &TagEdit) -> Result<(), TagWriteError>,
+    retry_delay: Duration,
+) {
+    let mut pending: VecDeque<Request> = VecDeque::new();
+    let mut playing: Option<PathBuf> = None;
+    let mut open = true;
+    let mut last_write: Option<Instant> = None;
+
+    loop {
+        // Take in everything sent, waiting only if there is nothing to write
+        loop {
+            let writable = pending.iter().any(|r| Some(&r.path) != playing.as_ref());
+            let command = if writable || !open {
+                rx.try_recv().map_err(|e| e == mpsc::TryRecvError::Disconnected)
+            } else {
+                rx.recv().map_err(|_| true)
+            };
+            match command {
+                Ok(Command::Write(request)) => pending.push_back(request),
+                Ok(Command::Playing(path)) => playing = path,
+                Err(disconnected) => {
+                    if disconnected {
+                        // Nothing plays without the queue's owner
+                        open = false;
+                        playing = None;
+                    }
+                    break;
+                }
+            }
+        }
+
+        let Some(at) = pending.iter().position(|r| Some(&r.path) != playing.as_ref()) else {
+            if open {
+                continue;
+            }
+            return;
+        };
+        let request = pending.remove(at).unwrap();
+
+        if let Some(wait) = last_write.map(|at| WRITE_INTERVAL.saturating_sub(at.elapsed())) {
+            std::thread::sleep(wait);
+        }
+        let mut result = writer(&request.path, &request.edit);
+        let mut delay = retry_delay;
+        for _ in 1..ATTEMPTS {
+            if !result.as_ref().is_err_and(TagWriteError::is_transient) {
+                break;
+            }
+            std::thread::sleep(delay);
+            delay *= 2;
+            result = writer(&request.path, &request.edit);
+        }
+        last_write = Some(Instant::now());
+
+        // The outcome goes first, so it is there once the receipt has its result
+        let _ = outcomes.send(Outcome { path: request.path, result: result.clone() });
+        let _ = request.reply.send(result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Paths written by [`record`], in order.
+    static WRITTEN: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+    fn record(path: &Path, _edit: &TagEdit) -> Result<(), TagWriteError> {
+        WRITTEN.lock().unwrap().push(path.to_path_buf());
+        Ok(())
+    }
+
+    fn gain() -> TagEdit {
+        TagEdit::ReplayGain { gain_db: -6.5, peak: 0.9 }
+    }
+
+    #[test]
+    fn test_playing_file_waits() {
+        let queue = TagWriteQueue::with_writer(record);
+        let (playing, other) = (Path::new("/music/playing.flac"), Path::new("/music/other.flac"));
+        queue.set_playing(Some(playing));
+        let held = queue.enqueue(playing, gain());
+        queue.enqueue(other, gain()).wait().unwrap();
+
+        // The other file went ahead; the playing one waits for the track to change
+        std::thread::sleep(WRITE_INTERVAL * 2);
+        assert_eq!(*WRITTEN.lock().unwrap(), [other]);
+
+        queue.set_playing(Some(other));
+        held.wait().unwrap();
+        assert_eq!(*WRITTEN.lock().unwrap(), [other, playing]);
+        let outcomes = queue.finished();
+        assert_eq!(outcomes.len(), 2);
+        assert!(outcomes.iter().all(|outcome| outcome.result.is_ok()));
+    }
+
+    static ATTEMPTED: AtomicUsize = AtomicUsize::new(0);
+
+    fn busy_twice(_path: &Path, _edit: &TagEdit) -> Result<(), TagWriteError> {
+        match ATTEMPTED.fetch_add(1, Ordering::SeqCst) {
+            0 | 1 => Err(TagWriteError::Busy("locked".to_string())),
+            _ => Ok(()),
+        }
+    }
+
+    #[test]
+    fn test_transient_failure_is_retried() {
+        let (tx, rx) = mpsc::channel();
+        let (outcome_tx, _outcomes) = mpsc::channel();
+        let thread = std::thread::spawn(move || run(rx, outcome_tx, busy_twice, Duration::from_millis(1)));
+        let (reply, result) = mpsc::channel();
+        tx.send(Command::Write(Request { path: PathBuf::from("a.mp3"), edit: gain(), reply })).unwrap();
+        drop(tx);
+        assert_eq!(result.recv().unwrap(), Ok(()));
+        assert_eq!(ATTEMPTED.load(Ordering::SeqCst), 3);
+        thread.join().unwrap();
+    }
+
+    #[test]
+    fn test_read_only_file_is_refused() {
+        let path = std::env::temp_dir().join(format!("juke_tagwrite_{}.mp3", std::process::id()));
+        fs::write(&path, b"not really audio").unwrap();
+        let mut permissions = fs::metadata(&path).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&path, permissions.clone()).unwrap();
+
+        let queue = TagWriteQueue::spawn();
+        let result = queue.enqueue(&path, gain()).wait();
+        assert_eq!(result, Err(TagWriteError::ReadOnly));
+        assert_eq!(fs::read(&path).unwrap(), b"not really audio");
+        assert_eq!(queue.finished()[0].result, Err(TagWriteError::ReadOnly));
+
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        fs::set_permissions(&path, permissions).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,283 @@
+//! Long operations on worker threads: directory scans, reading tags, and
+//! anything else that would hold up the interface.
+//!
+//! A [`Pool`] runs each [`Task`] on a thread of its own, a few at a time;
+//! the rest wait their turn. The work reports how far it has got and sends
+//! its results through a [`Reporter`], which the interface polls. Cancelling
+//! a task, or dropping it, tells the work to stop at its next report, and
+//! nothing it sends afterwards is delivered, so a cancelled task never
+//! changes anything.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// How often a waiting task looks to see whether it was cancelled.
+const WAIT_CHECK: Duration = Duration::from_millis(50);
+
+/// How far a task has got.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    pub done: usize,
+    /// How much there is to do, when known in advance.
+    pub total: Option<usize>,
+}
+
+impl Progress {
+    /// Returns how far the task has got in percent, when its total is
+    /// known.
+    pub fn percent(&self) -> Option<u8> {
+        let total = self.total.filter(|&total| total > 0)?;
+        Some((self.done.min(total) * 100 / total) as u8)
+    }
+}
+
+/// What a task's work sends to the interface.
+enum Message<T> {
+    Started,
+    Progress(Progress),
+    Result(T),
+}
+
+/// Handed to a task's work to report progress and results through.
+pub struct Reporter<T> {
+    sender: mpsc::Sender<Message<T>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl<T> Reporter<T> {
+    /// Whether the task was cancelled, when the work should stop.
+    pub fn cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Reports how far the work has got. Returns false once the task is
+    /// cancelled.
+    pub fn progress(&self, done: usize, total: Option<usize>) -> bool {
+        !self.cancelled() && self.sender.send(Message::Progress(Progress { done, total })).is_ok()
+    }
+
+    /// Sends a result. Returns false once the task is cancelled, when the
+    /// result is dropped.
+    pub fn send(&self, result: T) -> bool {
+        !self.cancelled() && self.sender.send(Message::Result(result)).is_ok()
+    }
+}
+
+/// A long operation running, or waiting to run, on a worker thread.
+pub struct Task<T> {
+    name: String,
+    receiver: mpsc::Receiver<Message<T>>,
+    cancelled: Arc<AtomicBool>,
+    started: bool,
+    progress: Progress,
+    finished: bool,
+}
+
+impl<T> Task<T> {
+    /// The name shown while it runs.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether it has started, rather than waiting for another task to
+    /// finish.
+    pub fn started(&self) -> bool {
+        self.started
+    }
+
+    /// How far it has got, as of the last poll.
+    pub fn progress(&self) -> Progress {
+        self.progress
+    }
+
+    /// Whether its work has ended and every result was polled.
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Tells the work to stop. Results not yet polled are dropped.
+    pub fn cancel(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+        self.finished = true;
+    }
+
+    /// Returns the results sent since the last poll, updating the
+    /// progress.
+    pub fn poll(&mut self) -> Vec<T> {
+        let mut results = Vec::new();
+        while !self.finished {
+            match self.receiver.try_recv() {
+                Ok(Message::Started) => self.started = true,
+                Ok(Message::Progress(progress)) => self.progress = progress,
+                Ok(Message::Result(result)) => results.push(result),
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => self.finished = true,
+            }
+        }
+        results
+    }
+}
+
+impl<T> Drop for Task<T> {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// Runs tasks, at most a few at once.
+#[derive(Debug, Clone)]
+pub struct Pool {
+    /// Tasks running, against the limit.
+    running: Arc<(Mutex<usize>, Condvar)>,
+    limit: usize,
+}
+
+/// Frees a task's place in the pool when its work ends, even by panicking.
+struct Slot(Arc<(Mutex<usize>, Condvar)>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        let (running, freed) = &*self.0;
+        *running.lock().unwrap_or_else(|e| e.into_inner()) -= 1;
+        freed.notify_one();
+    }
+}
+
+impl Pool {
+    /// Creates a pool running at most `limit` tasks at once, and at least
+    /// one.
+    pub fn new(limit: usize) -> Self {
+        Self { running: Arc::new((Mutex::new(0), Condvar::new())), limit: limit.max(1) }
+    }
+
+    /// Starts `work` on a thread of its own once the pool has room for it.
+    pub fn spawn<T, F>(&self, name: impl Into<String>, work: F) -> Task<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Reporter<T>) + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let reporter = Reporter { sender, cancelled: Arc::clone(&cancelled) };
+        let running = Arc::clone(&self.running);
+        let limit = self.limit;
+        std::thread::spawn(move || {
+            let Some(_slot) = wait_for_slot(running, limit, &reporter) else {
+                return;
+            };
+            if reporter.sender.send(Message::Started).is_ok() {
+                work(&reporter);
+            }
+        });
+        Task { name: name.into(), receiver, cancelled, started: false, progress: Progress::default(), finished: false }
+    }
+}
+
+/// Waits for a place in the pool, giving up if the task is cancelled first.
+fn wait_for_slot<T>(slots: Arc<(Mutex<usize>, Condvar)>, limit: usize, reporter: &Reporter<T>) -> Option<Slot> {
+    let (running, freed) = &*slots;
+    let mut count = running.lock().unwrap_or_else(|e| e.into_inner());
+    while *count >= limit {
+        if reporter.cancelled() {
+            return None;
+        }
+        count = freed.wait_timeout(count, WAIT_CHECK).unwrap_or_else(|e| e.into_inner()).0;
+    }
+    *count += 1;
+    drop(count);
+    Some(Slot(slots))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    /// Polls a task until `done` holds, collecting its results.
+    fn poll_until<T>(task: &mut Task<T>, done: impl Fn(&Task<T>) -> bool) -> Vec<T> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut results = Vec::new();
+        while !done(task) {
+            assert!(Instant::now() < deadline, "timed out polling {}", task.name());
+            results.extend(task.poll());
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        results
+    }
+
+    /// Counts to `steps`, waiting for the go-ahead before each step.
+    fn slow_count(pool: &Pool, steps: usize) -> (Task<usize>, mpsc::Sender<()>) {
+        let (go, wait) = mpsc::channel::<()>();
+        let task = pool.spawn("Counting", move |reporter: &Reporter<usize>| {
+            for step in 1..=steps {
+                if wait.recv().is_err() || !reporter.send(step) || !reporter.progress(step, Some(steps)) {
+                    return;
+                }
+            }
+        });
+        (task, go)
+    }
+
+    #[test]
+    fn test_task_reports_progress_and_results() {
+        let pool = Pool::new(2);
+        let (mut task, go) = slow_count(&pool, 4);
+        assert!(poll_until(&mut task, Task::started).is_empty());
+        assert_eq!(task.progress().percent(), None);
+
+        go.send(()).unwrap();
+        let results = poll_until(&mut task, |task| task.progress().done == 1);
+        assert_eq!(results, [1]);
+        assert_eq!(task.progress().percent(), Some(25));
+        assert!(!task.finished());
+
+        for _ in 0..3 {
+            go.send(()).unwrap();
+        }
+        assert_eq!(poll_until(&mut task, Task::finished), [2, 3, 4]);
+        assert_eq!(task.progress(), Progress { done: 4, total: Some(4) });
+    }
+
+    #[test]
+    fn test_cancelled_task_stops_and_delivers_nothing() {
+        let pool = Pool::new(1);
+        let (mut task, go) = slow_count(&pool, 100);
+        go.send(()).unwrap();
+        poll_until(&mut task, |task| task.progress().done == 1);
+
+        // Sent before the cancel but not yet polled
+        go.send(()).unwrap();
+        task.cancel();
+        go.send(()).unwrap();
+        assert!(task.finished());
+        assert!(task.poll().is_empty());
+
+        // Its place in the pool is freed for the next task
+        let (mut next, go) = slow_count(&pool, 1);
+        go.send(()).unwrap();
+        assert_eq!(poll_until(&mut next, Task::finished), [1]);
+    }
+
+    #[test]
+    fn test_pool_runs_at_most_its_limit() {
+        let pool = Pool::new(1);
+        let (mut first, go_first) = slow_count(&pool, 1);
+        let (mut second, go_second) = slow_count(&pool, 1);
+        poll_until(&mut first, Task::started);
+        std::thread::sleep(Duration::from_millis(20));
+        second.poll();
+        assert!(!second.started());
+
+        // A waiting task that is dropped never runs
+        let (waiting, _) = slow_count(&pool, 1);
+        drop(waiting);
+
+        go_first.send(()).unwrap();
+        poll_until(&mut first, Task::finished);
+        poll_until(&mut second, Task::started);
+        go_second.send(()).unwrap();
+        assert_eq!(poll_until(&mut second, Task::finished), [1]);
+    }
+}
@@ -0,0 +1,254 @@
+//! Text templates such as `%{artist: – }%title` for notifications and status output.
+//!
+//! `%artist`, `%title`, `%album`, `%duration`, and `%position` expand to track
+//! values, and `%index` and `%count` to its place in the playlist.
+//! `%{field:text}` expands to the value followed by `text`, or to nothing
+//! when the value is missing. `%%` is a literal percent sign. Anything
+//! unrecognized is kept as written.
+
+use crate::playlist::Track;
+use std::time::Duration;
+
+/// A value that can be placed in a template.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Artist,
+    Title,
+    Album,
+    Duration,
+    Position,
+    Index,
+    Count,
+}
+
+impl Field {
+    const ALL: [(&'static str, Field); 7] = [
+        ("artist", Field::Artist),
+        ("title", Field::Title),
+        ("album", Field::Album),
+        ("duration", Field::Duration),
+        ("position", Field::Position),
+        ("index", Field::Index),
+        ("count", Field::Count),
+    ];
+
+    /// Parses a field name.
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().find(|(n, _)| *n == name).map(|(_, f)| *f)
+    }
+}
+
+/// A piece of a parsed template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Field(Field),
+    /// A field followed by text, both omitted when the field is empty.
+    Conditional(Field, String),
+}
+
+/// A parsed template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    /// Parses a template. Parsing never fails; unknown syntax stays literal.
+    pub fn parse(template: &str) -> Self {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut rest = template;
+
+        while let Some(i) = rest.find('%') {
+            literal.push_str(&rest[..i]);
+            rest = &rest[i + 1..];
+
+            if let Some(after) = rest.strip_prefix('%') {
+                literal.push('%');
+                rest = after;
+                continue;
+            }
+
+            if let Some(inner) = rest.strip_prefix('{')
+                && let Some(end) = inner.find('}')
+                && let Some((name, text)) = inner[..end].split_once(':')
+                && let Some(field) = Field::from_name(name.trim())
+            {
+                flush(&mut parts, &mut literal);
+                parts.push(Part::Conditional(field, text.to_string()));
+                rest = &inner[end + 1..];
+                continue;
+            }
+
+            let name_len = rest.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(rest.len());
+            match Field::from_name(&rest[..name_len]) {
+                Some(field) => {
+                    flush(&mut parts, &mut literal);
+                    parts.push(Part::Field(field));
+                    rest = &rest[name_len..];
+                }
+                None => literal.push('%'),
+            }
+        }
+        literal.push_str(rest);
+        flush(&mut parts, &mut literal);
+
+        Self { parts }
+    }
+
+    /// Expands the template, trimming separators left dangling at either end
+    /// by missing values.
+    pub fn expand(&self, values: &Values) -> String {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => out.push_str(text),
+                Part::Field(field) => out.push_str(values.get(*field).unwrap_or("")),
+                Part::Conditional(field, text) => {
+                    if let Some(value) = values.get(*field) {
+                        out.push_str(value);
+                        out.push_str(text);
+                    }
+                }
+            }
+        }
+        trim_separators(&out).to_string()
+    }
+}
+
+/// Moves pending literal text into the parts list.
+fn flush(parts: &mut Vec<Part>, literal: &mut String) {
+    if !literal.is_empty() {
+        parts.push(Part::Literal(std::mem::take(literal)));
+    }
+}
+
+/// Trims whitespace and separator punctuation from both ends.
+fn trim_separators(text: &str) -> &str {
+    text.trim_matches(|c: char| c.is_whitespace() || matches!(c, '-' | '–' | '—' | '·' | '|' | '/' | ',' | ':'))
+}
+
+/// Values available to a template. Empty strings count as missing.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Values {
+    pub artist: Option<String>,
+    pub title: Option<String>,
+    pub album: Option<String>,
+    pub duration: Option<String>,
+    pub position: Option<String>,
+    /// Place of the track in the playlist, counting from 1.
+    pub index: Option<String>,
+    /// Tracks in the playlist.
+    pub count: Option<String>,
+}
+
+impl Values {
+    /// Builds the values for a track at a playback position.
+    pub fn for_track(track: &Track, position: Duration, duration: Duration) -> Self {
+        Self {
+            artist: track.artist.clone(),
+            title: Some(track.display_name()),
+            album: track.album.clone(),
+            duration: (!duration.is_zero()).then(|| format_time(duration)),
+            position: Some(format_time(position)),
+            index: None,
+            count: None,
+        }
+    }
+
+    /// Adds the track's place in a playlist of `count` tracks, `index`
+    /// counting from 0.
+    pub fn with_place(self, index: usize, count: usize) -> Self {
+        Self {
+            index: Some((index + 1).to_string()),
+            count: Some(count.to_string()),
+            ..self
+        }
+    }
+
+    /// Returns a value, treating empty strings as missing.
+    fn get(&self, field: Field) -> Option<&str> {
+        let value = match field {
+            Field::Artist => &self.artist,
+            Field::Title => &self.title,
+            Field::Album => &self.album,
+            Field::Duration => &self.duration,
+            Field::Position => &self.position,
+            Field::Index => &self.index,
+            Field::Count => &self.count,
+        };
+        value.as_deref().map(str::trim).filter(|v| !v.is_empty())
+    }
+}
+
+/// Formats a duration as `m:ss`, or `h:mm:ss` from one hour.
+pub fn format_time(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs % 3600 / 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(artist: Option<&str>, album: Option<&str>) -> Values {
+        Values {
+            artist: artist.map(String::from),
+            title: Some("Song".to_string()),
+            album: album.map(String::from),
+            duration: Some("3:05".to_string()),
+            position: Some("0:42".to_string()),
+            index: None,
+            count: None,
+        }
+    }
+
+    #[test]
+    fn test_placeholders() {
+        let template = Template::parse("%artist - %title [%position/%duration] 100%%");
+        assert_eq!(
+            template.expand(&values(Some("Band"), None)),
+            "Band - Song [0:42/3:05] 100%"
+        );
+    }
+
+    #[test]
+    fn test_place_in_playlist() {
+        let template = Template::parse("%title, %index of %count");
+        assert_eq!(template.expand(&values(None, None).with_place(2, 34)), "Song, 3 of 34");
+    }
+
+    #[test]
+    fn test_conditional_collapses_missing_fields() {
+        let template = Template::parse("%{artist: – }%title");
+        assert_eq!(template.expand(&values(Some("Band"), None)), "Band – Song");
+        assert_eq!(template.expand(&values(None, None)), "Song");
+        assert_eq!(template.expand(&values(Some("  "), None)), "Song");
+    }
+
+    #[test]
+    fn test_dangling_separators_are_trimmed() {
+        let template = Template::parse("%artist – %album");
+        assert_eq!(template.expand(&values(Some("Band"), Some("LP"))), "Band – LP");
+        assert_eq!(template.expand(&values(Some("Band"), None)), "Band");
+        assert_eq!(template.expand(&values(None, Some("LP"))), "LP");
+        assert_eq!(template.expand(&values(None, None)), "");
+    }
+
+    #[test]
+    fn test_unknown_syntax_is_literal() {
+        let template = Template::parse("%genre %{year: x} 50% %{artist");
+        assert_eq!(template.expand(&values(None, None)), "%genre %{year: x} 50% %{artist");
+    }
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!(format_time(Duration::from_secs(65)), "1:05");
+        assert_eq!(format_time(Duration::from_secs(3725)), "1:02:05");
+    }
+}
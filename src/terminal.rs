@@ -0,0 +1,155 @@
+//! Switching the terminal into juke's interface mode and back.
+//!
+//! [`TerminalGuard`] undoes everything [`TerminalGuard::enter`] did when it
+//! is dropped, whether juke quits, fails, or is stopped by a signal; the
+//! panic hook calls [`restore`] for the same. Restoring happens once, however
+//! many of those run. `juke --reset-terminal` runs [`reset`] to recover a
+//! terminal some other program, or an older juke, left wedged.
+//! [`query_background`] asks the terminal what color its background is.
+
+use crossterm::{
+    cursor::Show,
+    event::{DisableBracketedPaste, DisableFocusChange, DisableMouseCapture, EnableFocusChange},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use crate::config::Background;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// How long to wait for the terminal to say what its background is.
+const BACKGROUND_QUERY_TIMEOUT: Duration = Duration::from_millis(150);
+
+/// Whether the terminal is in interface mode and needs restoring.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Whether interface mode includes the alternate screen.
+static ALTERNATE_SCREEN: AtomicBool = AtomicBool::new(false);
+
+/// Keeps the terminal in interface mode until dropped.
+#[derive(Debug)]
+pub struct TerminalGuard {
+    _private: (),
+}
+
+impl TerminalGuard {
+    /// Enables raw mode and focus reporting, and with `alternate_screen`
+    /// switches to the alternate screen. If a step fails, the ones before it
+    /// are undone.
+    pub fn enter(alternate_screen: bool) -> io::Result<Self> {
+        enable_raw_mode()?;
+        ALTERNATE_SCREEN.store(alternate_screen, Ordering::SeqCst);
+        ACTIVE.store(true, Ordering::SeqCst);
+        // From here on, dropping the guard on an error restores the terminal
+        let guard = Self { _private: () };
+
+        // Focus changes slow down redraws and can hold back notifications
+        execute!(io::stdout(), EnableFocusChange)?;
+        // Plain output scrolls in the normal screen; raw mode is still needed for keys
+        if alternate_screen {
+            execute!(io::stdout(), EnterAlternateScreen)?;
+        }
+        Ok(guard)
+    }
+
+    /// Restores the terminal, reporting any failure.
+    pub fn leave(self) -> io::Result<()> {
+        restore()
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = restore();
+    }
+}
+
+/// Restores the terminal if it is still in interface mode.
+///
+/// The alternate screen is left before raw mode is disabled, so anything
+/// printed afterwards lands on the normal screen with normal line endings.
+/// Every step is tried even if an earlier one fails; the first error is
+/// returned.
+pub fn restore() -> io::Result<()> {
+    if !ACTIVE.swap(false, Ordering::SeqCst) {
+        return Ok(());
+    }
+    restore_steps(ALTERNATE_SCREEN.load(Ordering::SeqCst))
+}
+
+/// Restores the terminal whatever state it is in, for `--reset-terminal`.
+pub fn reset() -> io::Result<()> {
+    ACTIVE.store(false, Ordering::SeqCst);
+    restore_steps(true)
+}
+
+/// Leaves the alternate screen, stops mouse, paste, and focus reporting,
+/// shows the cursor, and disables raw mode.
+fn restore_steps(alternate_screen: bool) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    let steps = [
+        // Leaving a screen that was never entered would move the cursor
+        if alternate_screen { execute!(stdout, LeaveAlternateScreen) } else { Ok(()) },
+        execute!(stdout, DisableMouseCapture, DisableBracketedPaste, DisableFocusChange, Show),
+        disable_raw_mode(),
+    ];
+    steps.into_iter().collect()
+}
+
+/// Asks the terminal for its background color with an OSC 11 query.
+///
+/// Call it in raw mode, before anything reads input: the answer arrives as
+/// input, and crossterm would hand it out as key presses. A device attributes
+/// request follows the query; every terminal answers that one, so a terminal
+/// that ignores OSC 11 costs a round trip rather than the whole timeout.
+/// Keys pressed meanwhile are dropped. Returns `None` if no color arrives.
+#[cfg(unix)]
+pub fn query_background() -> Option<Background> {
+    use std::io::{IsTerminal, Write};
+    use std::os::fd::AsRawFd;
+    use std::time::Instant;
+
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+    if !stdin.is_terminal() || !stdout.is_terminal() {
+        return None;
+    }
+    stdout.write_all(b"\x1b]11;?\x1b\\\x1b[c").ok()?;
+    stdout.flush().ok()?;
+
+    // Read the descriptor directly, so nothing is left in Stdin's buffer
+    let fd = stdin.as_raw_fd();
+    let deadline = Instant::now() + BACKGROUND_QUERY_TIMEOUT;
+    let mut reply = Vec::new();
+    while !device_attributes_answered(&reply) {
+        let left = deadline.saturating_duration_since(Instant::now());
+        let mut poll_fd = libc::pollfd { fd, events: libc::POLLIN, revents: 0 };
+        // SAFETY: poll_fd is a valid pollfd for the duration of the call
+        let ready = unsafe { libc::poll(&mut poll_fd, 1, left.as_millis().try_into().unwrap_or(0)) };
+        if ready <= 0 {
+            break;
+        }
+        let mut buffer = [0u8; 64];
+        // SAFETY: the buffer is valid for writes of its whole length
+        let read = unsafe { libc::read(fd, buffer.as_mut_ptr().cast(), buffer.len()) };
+        let Ok(read @ 1..) = usize::try_from(read) else { break };
+        reply.extend_from_slice(&buffer[..read]);
+    }
+    crate::themes::background_from_osc11(&String::from_utf8_lossy(&reply))
+}
+
+#[cfg(not(unix))]
+pub fn query_background() -> Option<Background> {
+    None
+}
+
+/// Whether the reply so far ends with a device attributes answer,
+/// `ESC [ ? … c`, which the terminal sends after any OSC 11 answer.
+#[cfg(unix)]
+fn device_attributes_answered(reply: &[u8]) -> bool {
+    reply
+        .windows(3)
+        .position(|window| window == b"\x1b[?")
+        .is_some_and(|start| reply[start..].contains(&b'c'))
+}
@@ -0,0 +1,409 @@
+//! Color themes: built-in presets and `[theme]` overrides.
+
+use crate::config::{Background, Config, UiConfig};
+use ratatui::style::Color;
+use ratatui::text::Span;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+/// Background found at startup, for `theme.background = "auto"`.
+static DETECTED_BACKGROUND: OnceLock<Background> = OnceLock::new();
+
+/// Colors used by the interface, by role.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theme {
+    /// Visualizer, headers, and titles.
+    pub accent: Color,
+    /// Playback state and the played part of the progress bar.
+    pub status: Color,
+    /// Key names, the playing track, the shuffle indicator, and the progress bar while paused.
+    pub highlight: Color,
+    /// Repeat indicator.
+    pub secondary: Color,
+    /// Durations, footers, and hints.
+    pub dim: Color,
+    /// Warnings, destructive dialogs, and the progress bar after a failed seek.
+    pub error: Color,
+    /// Text of the selected track list row.
+    pub selection_fg: Color,
+    /// Background of the selected track list row.
+    pub selection_bg: Color,
+    /// Single-column characters for bars and markers.
+    pub glyphs: Glyphs,
+}
+
+/// Characters used for the progress bar and track list markers.
+///
+/// Every glyph occupies exactly one terminal column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Glyphs {
+    pub progress_filled: char,
+    pub progress_empty: char,
+    pub playing_marker: char,
+    pub selected_marker: char,
+    /// Marks tracks waiting in the play queue.
+    pub queue_badge: char,
+    /// Marks where a seek in progress will land on the progress bar.
+    pub seek_marker: char,
+    /// Marks tracks that failed to open when last tried.
+    pub unplayable_marker: char,
+    /// Follows tracks renamed in the track list.
+    pub renamed_marker: char,
+}
+
+impl Glyphs {
+    pub const UNICODE: Glyphs = Glyphs {
+        progress_filled: '⣿',
+        progress_empty: '⣀',
+        playing_marker: '▶',
+        selected_marker: ' ',
+        queue_badge: '•',
+        seek_marker: '○',
+        unplayable_marker: '✗',
+        renamed_marker: '✎',
+    };
+
+    pub const ASCII: Glyphs = Glyphs {
+        progress_filled: '#',
+        progress_empty: '-',
+        playing_marker: '>',
+        selected_marker: ' ',
+        queue_badge: '+',
+        seek_marker: 'o',
+        unplayable_marker: 'x',
+        renamed_marker: '*',
+    };
+
+    /// Builds the glyph set from `[ui]`: the ASCII or Unicode base set, then any
+    /// `[ui.glyphs]` entries on top. Entries that are not one column wide are
+    /// ignored with a warning.
+    pub fn from_config(config: &UiConfig) -> Self {
+        let mut glyphs = if config.ascii_only { Self::ASCII } else { Self::UNICODE };
+
+        let overrides = [
+            ("progress_filled", &config.glyphs.progress_filled, &mut glyphs.progress_filled),
+            ("progress_empty", &config.glyphs.progress_empty, &mut glyphs.progress_empty),
+            ("playing_marker", &config.glyphs.playing_marker, &mut glyphs.playing_marker),
+            ("selected_marker", &config.glyphs.selected_marker, &mut glyphs.selected_marker),
+            ("queue_badge", &config.glyphs.queue_badge, &mut glyphs.queue_badge),
+            ("seek_marker", &config.glyphs.seek_marker, &mut glyphs.seek_marker),
+            ("unplayable_marker", &config.glyphs.unplayable_marker, &mut glyphs.unplayable_marker),
+            ("renamed_marker", &config.glyphs.renamed_marker, &mut glyphs.renamed_marker),
+        ];
+        for (name, value, slot) in overrides {
+            let Some(value) = value else { continue };
+            match parse_glyph(value) {
+                Some(glyph) => *slot = glyph,
+                None => eprintln!(
+                    "Warning: Ignoring ui.glyphs.{} = '{}', glyphs must be one column wide",
+                    name, value
+                ),
+            }
+        }
+
+        glyphs
+    }
+}
+
+/// Parses a glyph: a single character that occupies exactly one column.
+fn parse_glyph(value: &str) -> Option<char> {
+    let mut chars = value.chars();
+    let glyph = chars.next()?;
+    let single = chars.next().is_none() && !glyph.is_control();
+    (single && Span::raw(value).width() == 1).then_some(glyph)
+}
+
+pub const DEFAULT: Theme = Theme {
+    accent: Color::Cyan,
+    status: Color::Green,
+    highlight: Color::Yellow,
+    secondary: Color::Magenta,
+    dim: Color::DarkGray,
+    error: Color::Red,
+    selection_fg: Color::Black,
+    selection_bg: Color::Cyan,
+    glyphs: Glyphs::UNICODE,
+};
+
+/// The default colors for light backgrounds, where dark gray hints and cyan
+/// accents are hard to read.
+pub const DEFAULT_LIGHT: Theme = Theme {
+    accent: Color::Blue,
+    status: Color::Green,
+    highlight: Color::Magenta,
+    secondary: Color::Indexed(130),
+    dim: Color::Indexed(242),
+    error: Color::Red,
+    selection_fg: Color::White,
+    selection_bg: Color::Blue,
+    glyphs: Glyphs::UNICODE,
+};
+
+pub const NORD: Theme = Theme {
+    accent: Color::Rgb(0x88, 0xC0, 0xD0),
+    status: Color::Rgb(0xA3, 0xBE, 0x8C),
+    highlight: Color::Rgb(0xEB, 0xCB, 0x8B),
+    secondary: Color::Rgb(0xB4, 0x8E, 0xAD),
+    dim: Color::Rgb(0x4C, 0x56, 0x6A),
+    error: Color::Rgb(0xBF, 0x61, 0x6A),
+    selection_fg: Color::Rgb(0x2E, 0x34, 0x40),
+    selection_bg: Color::Rgb(0x88, 0xC0, 0xD0),
+    glyphs: Glyphs::UNICODE,
+};
+
+pub const GRUVBOX_DARK: Theme = Theme {
+    accent: Color::Rgb(0x83, 0xA5, 0x98),
+    status: Color::Rgb(0xB8, 0xBB, 0x26),
+    highlight: Color::Rgb(0xFA, 0xBD, 0x2F),
+    secondary: Color::Rgb(0xD3, 0x86, 0x9B),
+    dim: Color::Rgb(0x92, 0x83, 0x74),
+    error: Color::Rgb(0xFB, 0x49, 0x34),
+    selection_fg: Color::Rgb(0x28, 0x28, 0x28),
+    selection_bg: Color::Rgb(0x83, 0xA5, 0x98),
+    glyphs: Glyphs::UNICODE,
+};
+
+pub const SOLARIZED_LIGHT: Theme = Theme {
+    accent: Color::Rgb(0x26, 0x8B, 0xD2),
+    status: Color::Rgb(0x85, 0x99, 0x00),
+    highlight: Color::Rgb(0xB5, 0x89, 0x00),
+    secondary: Color::Rgb(0xD3, 0x36, 0x82),
+    dim: Color::Rgb(0x93, 0xA1, 0xA1),
+    error: Color::Rgb(0xDC, 0x32, 0x2F),
+    selection_fg: Color::Rgb(0xFD, 0xF6, 0xE3),
+    selection_bg: Color::Rgb(0x26, 0x8B, 0xD2),
+    glyphs: Glyphs::UNICODE,
+};
+
+pub const MONO: Theme = Theme {
+    accent: Color::White,
+    status: Color::White,
+    highlight: Color::White,
+    secondary: Color::White,
+    dim: Color::DarkGray,
+    error: Color::White,
+    selection_fg: Color::Black,
+    selection_bg: Color::White,
+    glyphs: Glyphs::UNICODE,
+};
+
+/// Built-in presets by name.
+pub const PRESETS: &[(&str, Theme)] = &[
+    ("default", DEFAULT),
+    ("default-light", DEFAULT_LIGHT),
+    ("nord", NORD),
+    ("gruvbox-dark", GRUVBOX_DARK),
+    ("solarized-light", SOLARIZED_LIGHT),
+    ("mono", MONO),
+];
+
+/// Looks up a preset by name.
+pub fn preset(name: &str) -> Result<Theme, ThemeError> {
+    PRESETS
+        .iter()
+        .find(|(preset, _)| preset.eq_ignore_ascii_case(name.trim()))
+        .map(|(_, theme)| *theme)
+        .ok_or_else(|| ThemeError::UnknownPreset(name.to_string()))
+}
+
+/// Records the terminal background found at startup. Only the first call
+/// counts.
+pub fn set_detected_background(background: Background) {
+    let _ = DETECTED_BACKGROUND.set(background);
+}
+
+/// Reads the background from `COLORFGBG` ("15;0", or "0;default;15" from
+/// rxvt), whose last field is the background's palette index.
+pub fn background_from_colorfgbg(value: &str) -> Option<Background> {
+    let index: u8 = value.rsplit(';').next()?.trim().parse().ok()?;
+    match index {
+        7 | 9..=15 => Some(Background::Light),
+        0..=6 | 8 => Some(Background::Dark),
+        _ => None,
+    }
+}
+
+/// Reads the background from a terminal's answer to the OSC 11 query, such
+/// as `ESC ] 11 ; rgb:ffff/ffff/dddd BEL`. Each channel has one to four hex
+/// digits.
+pub fn background_from_osc11(reply: &str) -> Option<Background> {
+    let (_, rgb) = reply.split_once("]11;rgb:")?;
+    let rgb = rgb.split(['\x07', '\x1b']).next()?;
+    let mut channels = rgb.split('/').map(|channel| {
+        let digits = channel.len();
+        let value = u32::from_str_radix(channel, 16).ok().filter(|_| (1..=4).contains(&digits))?;
+        Some(value as f32 / ((1u32 << (4 * digits)) - 1) as f32)
+    });
+    let (r, g, b) = (channels.next()??, channels.next()??, channels.next()??);
+    if channels.next().is_some() {
+        return None;
+    }
+    let luminance = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    Some(if luminance > 0.5 { Background::Light } else { Background::Dark })
+}
+
+impl Theme {
+    /// Builds a theme from the `[theme]` section: the preset, then any
+    /// explicitly set colors on top. Invalid colors are ignored with a warning.
+    ///
+    /// The "default" preset follows `theme.background`, using the background
+    /// found at startup when it is "auto", and the dark colors if none was.
+    /// Glyphs come from the `[ui]` section.
+    pub fn from_config(config: &Config) -> Result<Self, ThemeError> {
+        let background = match config.theme.background {
+            Background::Auto => DETECTED_BACKGROUND.get().copied().unwrap_or(Background::Dark),
+            background => background,
+        };
+        let mut theme = preset(&config.theme.preset)?;
+        if config.theme.preset.trim().eq_ignore_ascii_case("default") && background == Background::Light {
+            theme = DEFAULT_LIGHT;
+        }
+        theme.glyphs = Glyphs::from_config(&config.ui);
+        let config = &config.theme;
+
+        let overrides = [
+            ("accent", &config.accent, &mut theme.accent),
+            ("status", &config.status, &mut theme.status),
+            ("highlight", &config.highlight, &mut theme.highlight),
+            ("secondary", &config.secondary, &mut theme.secondary),
+            ("dim", &config.dim, &mut theme.dim),
+            ("error", &config.error, &mut theme.error),
+            ("selection_fg", &config.selection_fg, &mut theme.selection_fg),
+            ("selection_bg", &config.selection_bg, &mut theme.selection_bg),
+        ];
+        for (name, value, slot) in overrides {
+            let Some(value) = value else { continue };
+            match Color::from_str(value) {
+                Ok(color) => *slot = color,
+                Err(_) => eprintln!("Warning: Ignoring invalid color '{}' for theme.{}", value, name),
+            }
+        }
+
+        Ok(theme)
+    }
+
+    /// Returns the colors shown in a preview swatch, in field order.
+    pub fn swatch(&self) -> [Color; 6] {
+        [self.accent, self.status, self.highlight, self.secondary, self.dim, self.error]
+    }
+}
+
+/// Errors that can occur while selecting a theme.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ThemeError {
+    UnknownPreset(String),
+}
+
+impl std::fmt::Display for ThemeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ThemeError::UnknownPreset(name) => {
+                let names: Vec<_> = PRESETS.iter().map(|(name, _)| *name).collect();
+                write!(f, "Unknown theme '{}' (valid themes: {})", name, names.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for ThemeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ThemeConfig;
+
+    #[test]
+    fn test_preset_lookup() {
+        assert_eq!(preset("nord"), Ok(NORD));
+        assert_eq!(preset("Gruvbox-Dark"), Ok(GRUVBOX_DARK));
+
+        let error = preset("dracula").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unknown theme 'dracula' (valid themes: default, default-light, nord, gruvbox-dark, solarized-light, mono)"
+        );
+    }
+
+    #[test]
+    fn test_overrides_apply_on_top_of_preset() {
+        let config = Config {
+            theme: ThemeConfig {
+                preset: "mono".to_string(),
+                accent: Some("#FF8800".to_string()),
+                dim: Some("not a color".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let theme = Theme::from_config(&config).unwrap();
+        assert_eq!(theme.accent, Color::Rgb(0xFF, 0x88, 0x00));
+        assert_eq!(theme.dim, MONO.dim);
+        assert_eq!(theme.selection_bg, MONO.selection_bg);
+    }
+
+    #[test]
+    fn test_default_preset_follows_background() {
+        let mut config = Config::default();
+        config.theme.background = Background::Light;
+        assert_eq!(Theme::from_config(&config).unwrap().accent, DEFAULT_LIGHT.accent);
+        config.theme.background = Background::Dark;
+        assert_eq!(Theme::from_config(&config).unwrap().accent, DEFAULT.accent);
+
+        // Other presets keep their own colors
+        config.theme.background = Background::Light;
+        config.theme.preset = "nord".to_string();
+        assert_eq!(Theme::from_config(&config).unwrap().accent, NORD.accent);
+    }
+
+    #[test]
+    fn test_background_detection_replies() {
+        let osc = [
+            ("\x1b]11;rgb:ffff/ffff/ffff\x1b\\", Some(Background::Light)),
+            ("\x1b]11;rgb:fdfd/f6f6/e3e3\x07", Some(Background::Light)),
+            ("\x1b]11;rgb:00/2b/36\x07", Some(Background::Dark)),
+            ("\x1b]11;rgb:1/1/1\x07", Some(Background::Dark)),
+            ("\x1b]11;rgb:ffff/ffff\x07", None),
+            ("\x1b[?62;22c", None),
+        ];
+        for (reply, expected) in osc {
+            assert_eq!(background_from_osc11(reply), expected, "reading {:?}", reply);
+        }
+
+        assert_eq!(background_from_colorfgbg("15;0"), Some(Background::Dark));
+        assert_eq!(background_from_colorfgbg("0;default;15"), Some(Background::Light));
+        assert_eq!(background_from_colorfgbg("0;7"), Some(Background::Light));
+        assert_eq!(background_from_colorfgbg("15;default"), None);
+    }
+
+    #[test]
+    fn test_glyph_width_validation() {
+        let cases = [
+            ("#", Some('#')),
+            ("█", Some('█')),
+            ("·", Some('·')),
+            ("", None),
+            ("ab", None),
+            ("🎵", None),
+            ("中", None),
+            ("\t", None),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_glyph(input), expected, "parsing {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_ascii_only_selects_ascii_set() {
+        let mut config = UiConfig {
+            ascii_only: true,
+            ..Default::default()
+        };
+        assert_eq!(Glyphs::from_config(&config), Glyphs::ASCII);
+
+        config.glyphs.playing_marker = Some("*".to_string());
+        config.glyphs.progress_filled = Some("🎵".to_string());
+        let glyphs = Glyphs::from_config(&config);
+        assert_eq!(glyphs.playing_marker, '*');
+        assert_eq!(glyphs.progress_filled, '#');
+    }
+}
@@ -0,0 +1,14 @@
+//! Moving files to the operating system's trash.
+
+use std::io;
+use std::path::Path;
+
+/// Moves a file to the user's trash.
+///
+/// Uses the freedesktop.org trash on Linux and the BSDs, trashing files on
+/// another volume into that volume's `.Trash-$uid`, the Finder on macOS,
+/// and the Recycle Bin on Windows.
+pub fn move_to_trash(path: &Path) -> io::Result<()> {
+    trash::delete(path).map_err(|e| io::Error::other(e.to_string()))
+}
+
@@ -0,0 +1,121 @@
+//! Skipping the same intro and outro on every file in a directory, such as
+//! the sponsor jingles around each episode of a podcast.
+//!
+//! Rules come from `[[playlist.trim]]` in the config. A rule's glob is
+//! matched against the directory a file is in, with the syntax of the
+//! exclude globs: `*` and `?` stay within one component, `**` spans several,
+//! and a glob without a `/` matches the directory's name alone. Every rule
+//! that matches applies in order, so a later rule overrides the lengths it
+//! sets and leaves the rest to earlier ones.
+
+use crate::filter;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+
+/// Skips the start and end of the files in matching directories.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrimRule {
+    /// Glob matched against the directory a file is in.
+    pub dir: String,
+    /// Seconds skipped at the start.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skip_intro_secs: Option<u64>,
+    /// Seconds left unplayed at the end, where playback moves on.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skip_outro_secs: Option<u64>,
+}
+
+/// How much of a track's start and end is skipped.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Trim {
+    pub intro: Duration,
+    pub outro: Duration,
+}
+
+impl Trim {
+    /// Whether nothing is skipped.
+    pub fn is_empty(&self) -> bool {
+        self.intro.is_zero() && self.outro.is_zero()
+    }
+
+    /// Fits the trim to a track `length` long. When the intro and outro
+    /// would leave nothing to play, the track is played whole.
+    pub fn within(self, length: Duration) -> Self {
+        if length.is_zero() {
+            // The end is unknown, but the start can still be skipped
+            return Self { intro: self.intro, outro: Duration::ZERO };
+        }
+        match self.intro + self.outro < length {
+            true => self,
+            false => Self::default(),
+        }
+    }
+
+    /// Where the outro starts in a track `length` long.
+    pub fn outro_start(&self, length: Duration) -> Duration {
+        length.saturating_sub(self.outro)
+    }
+}
+
+/// Returns what the rules skip of the file at `path`.
+pub fn trim_for(rules: &[TrimRule], path: &Path) -> Trim {
+    let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) else {
+        return Trim::default();
+    };
+    let mut trim = Trim::default();
+    for rule in rules.iter().filter(|rule| filter::matches(&rule.dir, dir, true)) {
+        if let Some(secs) = rule.skip_intro_secs {
+            trim.intro = Duration::from_secs(secs);
+        }
+        if let Some(secs) = rule.skip_outro_secs {
+            trim.outro = Duration::from_secs(secs);
+        }
+    }
+    trim
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(dir: &str, intro: Option<u64>, outro: Option<u64>) -> TrimRule {
+        TrimRule { dir: dir.to_string(), skip_intro_secs: intro, skip_outro_secs: outro }
+    }
+
+    fn secs(intro: u64, outro: u64) -> Trim {
+        Trim { intro: Duration::from_secs(intro), outro: Duration::from_secs(outro) }
+    }
+
+    #[test]
+    fn test_overlapping_rules_apply_in_order() {
+        let rules = [
+            rule("/pods/**", Some(30), Some(30)),
+            rule("/pods/Daily", Some(12), None),
+            rule("Daily", None, Some(0)),
+            rule("/pods/*/extras", Some(0), Some(0)),
+        ];
+        let trim = |path: &str| trim_for(&rules, Path::new(path));
+        assert_eq!(trim("/pods/Weekly/ep1.mp3"), secs(30, 30));
+        assert_eq!(trim("/pods/ep1.mp3"), secs(30, 30));
+        // The later rules override only what they set
+        assert_eq!(trim("/pods/Daily/ep1.mp3"), secs(12, 0));
+        assert_eq!(trim("/pods/Weekly/extras/bonus.mp3"), secs(0, 0));
+        // Deeper directories only match globs that reach them
+        assert_eq!(trim("/pods/Daily/old/ep1.mp3"), secs(30, 30));
+        assert_eq!(trim("/music/Daily/song.mp3"), secs(0, 0));
+        assert_eq!(trim("/music/song.mp3"), Trim::default());
+        assert_eq!(trim("song.mp3"), Trim::default());
+    }
+
+    #[test]
+    fn test_trim_fits_the_track() {
+        let length = Duration::from_secs(100);
+        assert_eq!(secs(30, 30).within(length), secs(30, 30));
+        assert_eq!(secs(60, 40).within(length), Trim::default());
+        assert_eq!(secs(30, 30).within(Duration::ZERO), secs(30, 0));
+        assert_eq!(secs(30, 30).outro_start(length), Duration::from_secs(70));
+        assert!(Trim::default().is_empty());
+    }
+}
@@ -1 +1,758 @@
 //! Terminal user interface components.
+
+use crate::actions;
+use crate::player::PlaybackState;
+use crate::themes::Theme;
+use crate::trim::Trim;
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::style::{Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Clear, Paragraph};
+use ratatui::Frame;
+use std::time::Duration;
+
+/// What the progress bar's filled part shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressStyle {
+    Playing,
+    Paused,
+    /// A seek just failed.
+    SeekFailed,
+}
+
+impl ProgressStyle {
+    /// Returns the style for a playback state, unless a seek just failed.
+    pub fn new(state: PlaybackState, seek_failed: bool) -> Self {
+        match state {
+            _ if seek_failed => ProgressStyle::SeekFailed,
+            PlaybackState::Playing => ProgressStyle::Playing,
+            PlaybackState::Paused | PlaybackState::Stopped => ProgressStyle::Paused,
+        }
+    }
+}
+
+/// Returns the column of a `width` column progress bar that `target` falls
+/// in, or `None` with an unknown duration or no columns.
+pub fn marker_column(target: Duration, dur: Duration, width: usize) -> Option<usize> {
+    if dur.is_zero() || width == 0 {
+        return None;
+    }
+    let fraction = (target.as_secs_f64() / dur.as_secs_f64()).clamp(0.0, 1.0);
+    Some(((fraction * width as f64) as usize).min(width - 1))
+}
+
+/// Renders the progress bar as styled spans `width` columns wide: the played
+/// part in the style's color, then the buffered part, if any, dimmed, then the rest.
+/// The intro and outro skipped by `trim` are dimmed at either end, and a
+/// pending seek `target` is marked on top, in the style's color.
+///
+/// With an unknown duration the whole bar is unplayed.
+#[allow(clippy::too_many_arguments)]
+pub fn render_progress_bar(
+    pos: Duration,
+    dur: Duration,
+    trim: Trim,
+    buffered: Option<Duration>,
+    target: Option<Duration>,
+    width: usize,
+    style: ProgressStyle,
+    theme: &Theme,
+) -> Vec<Span<'static>> {
+    let columns = |until: Duration| {
+        if dur.is_zero() {
+            return 0;
+        }
+        let fraction = (until.as_secs_f64() / dur.as_secs_f64()).min(1.0);
+        (fraction * width as f64).round() as usize
+    };
+    let filled = columns(pos);
+    let buffered = buffered.map_or(filled, columns).max(filled);
+
+    let color = match style {
+        ProgressStyle::Playing => theme.status,
+        ProgressStyle::Paused => theme.highlight,
+        ProgressStyle::SeekFailed => theme.error,
+    };
+    let parts = [
+        (theme.glyphs.progress_filled, filled, Style::default().fg(color)),
+        (theme.glyphs.progress_empty, buffered - filled, Style::default().fg(theme.dim)),
+        (theme.glyphs.progress_empty, width - buffered, Style::default()),
+    ];
+    let mut cells: Vec<(char, Style)> =
+        parts.into_iter().flat_map(|(glyph, len, style)| std::iter::repeat_n((glyph, style), len)).collect();
+    let (intro_end, outro_start) = (columns(trim.intro), columns(trim.outro_start(dur)));
+    for (column, (_, style)) in cells.iter_mut().enumerate() {
+        if column < intro_end || (column >= outro_start && !trim.outro.is_zero()) {
+            *style = Style::default().fg(theme.dim);
+        }
+    }
+    if let Some(column) = target.and_then(|target| marker_column(target, dur, width)) {
+        cells[column] = (theme.glyphs.seek_marker, Style::default().fg(color).add_modifier(Modifier::BOLD));
+    }
+
+    let mut spans: Vec<(String, Style)> = Vec::new();
+    for (glyph, style) in cells {
+        match spans.last_mut() {
+            Some((text, last)) if *last == style && text.ends_with(glyph) => text.push(glyph),
+            _ => spans.push((glyph.to_string(), style)),
+        }
+    }
+    spans.into_iter().map(|(text, style)| Span::styled(text, style)).collect()
+}
+
+/// Returns a pane's border title, picked out while the pane has the focus.
+pub fn pane_title(title: &str, focused: bool, theme: &Theme) -> Span<'static> {
+    let style = match focused {
+        true => Style::default().fg(theme.highlight).add_modifier(Modifier::BOLD | Modifier::REVERSED),
+        false => Style::default(),
+    };
+    Span::styled(title.to_string(), style)
+}
+
+/// A small centered list of choices drawn over the current view, such as
+/// the sort menu. The caller maps the selected index back to its choices.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Menu {
+    pub title: String,
+    pub items: Vec<String>,
+    /// Highlighted item.
+    pub selected: usize,
+    /// Item marked as the current setting.
+    pub marked: Option<usize>,
+    /// Key hints shown below the items.
+    pub footer: String,
+}
+
+impl Menu {
+    /// Creates a menu with the marked item, if any, selected.
+    pub fn new(title: String, items: Vec<String>, marked: Option<usize>, footer: String) -> Self {
+        let selected = marked.unwrap_or(0).min(items.len().saturating_sub(1));
+        Self { title, items, selected, marked, footer }
+    }
+
+    /// Moves the selection up, wrapping to the bottom.
+    pub fn up(&mut self) {
+        if !self.items.is_empty() {
+            self.selected = self.selected.checked_sub(1).unwrap_or(self.items.len() - 1);
+        }
+    }
+
+    /// Moves the selection down, wrapping to the top.
+    pub fn down(&mut self) {
+        if !self.items.is_empty() {
+            self.selected = (self.selected + 1) % self.items.len();
+        }
+    }
+
+    /// Returns the area the menu takes up, centered in `size`.
+    fn area(&self, size: Rect) -> Rect {
+        let widest = self
+            .items
+            .iter()
+            .map(|item| item.chars().count() + 4)
+            .chain([self.title.chars().count(), self.footer.chars().count()])
+            .max()
+            .unwrap_or(0);
+        // Borders plus one column of padding on each side
+        let width = (widest as u16).saturating_add(4).min(size.width);
+        let height = (self.items.len() as u16).saturating_add(4).min(size.height);
+        Rect {
+            x: size.x + (size.width - width) / 2,
+            y: size.y + (size.height - height) / 2,
+            width,
+            height,
+        }
+    }
+
+    /// Draws the menu over whatever is on screen.
+    pub fn render(&self, f: &mut Frame, size: Rect, theme: &Theme) {
+        let mut lines: Vec<Line> = self
+            .items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| {
+                let marker = if self.marked == Some(i) { theme.glyphs.playing_marker } else { ' ' };
+                let style = if i == self.selected {
+                    Style::default().bg(theme.selection_bg).fg(theme.selection_fg).add_modifier(Modifier::BOLD)
+                } else {
+                    Style::default()
+                };
+                Line::from(Span::styled(format!(" {} {} ", marker, item), style))
+            })
+            .collect();
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled(self.footer.clone(), Style::default().fg(theme.dim))));
+
+        let area = self.area(size);
+        let menu = Paragraph::new(lines).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(self.title.clone())
+                .border_style(Style::default().fg(theme.accent)),
+        );
+        f.render_widget(Clear, area);
+        f.render_widget(menu, area);
+    }
+}
+
+/// A line of text to type, drawn over the screen, shared by everything that
+/// asks for one.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Prompt {
+    pub title: String,
+    text: String,
+    /// Cursor position, in characters.
+    cursor: usize,
+    /// Key hints shown below the text.
+    pub footer: String,
+    /// Earlier entries of this kind of prompt, oldest first.
+    history: Vec<String>,
+    /// Which of them is shown, while stepping through them.
+    recalled: Option<usize>,
+    /// What was typed before stepping into the history.
+    draft: String,
+    /// What the history is searched for, and which of the matches is
+    /// shown, while searching.
+    search: Option<(String, usize)>,
+    /// Shown before the search in place of the footer.
+    search_label: String,
+}
+
+impl Prompt {
+    /// Creates a prompt holding `text`, with the cursor at its end.
+    pub fn new(title: String, text: &str, footer: String) -> Self {
+        Self { title, text: text.to_string(), cursor: text.chars().count(), footer, ..Self::default() }
+    }
+
+    /// Offers earlier entries, oldest first, to step through with
+    /// [`Prompt::older`] and [`Prompt::newer`] and to search.
+    pub fn with_history(mut self, history: Vec<String>, search_label: String) -> Self {
+        self.history = history;
+        self.search_label = search_label;
+        self
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Replaces the text, with the cursor at its end.
+    fn set_text(&mut self, text: String) {
+        self.cursor = text.chars().count();
+        self.text = text;
+    }
+
+    /// Shows an entry of the history, remembering what was typed first.
+    fn recall(&mut self, index: usize) {
+        if self.recalled.is_none() {
+            self.draft = self.text.clone();
+        }
+        self.recalled = Some(index);
+        self.set_text(self.history[index].clone());
+    }
+
+    /// Shows the entry before the one shown, starting from the newest.
+    pub fn older(&mut self) {
+        self.search = None;
+        let index = match self.recalled {
+            Some(index) => index.saturating_sub(1),
+            None if self.history.is_empty() => return,
+            None => self.history.len() - 1,
+        };
+        self.recall(index);
+    }
+
+    /// Shows the entry after the one shown, or after the newest, what was
+    /// typed before.
+    pub fn newer(&mut self) {
+        self.search = None;
+        let Some(index) = self.recalled else {
+            return;
+        };
+        if index + 1 < self.history.len() {
+            self.recall(index + 1);
+        } else {
+            self.recalled = None;
+            let draft = std::mem::take(&mut self.draft);
+            self.set_text(draft);
+        }
+    }
+
+    /// Starts searching the history, or while searching, shows the next
+    /// match.
+    pub fn search(&mut self) {
+        match &mut self.search {
+            Some((_, shown)) => *shown += 1,
+            None => self.search = Some((String::new(), 0)),
+        }
+        self.show_match();
+    }
+
+    /// Stops searching, putting back what was typed before. Returns false
+    /// when there was no search.
+    pub fn cancel_search(&mut self) -> bool {
+        if self.search.take().is_none() {
+            return false;
+        }
+        if self.recalled.take().is_some() {
+            let draft = std::mem::take(&mut self.draft);
+            self.set_text(draft);
+        }
+        true
+    }
+
+    /// Edits the search instead of the text, while searching, and returns
+    /// true if it did.
+    fn edit_search(&mut self, edit: impl FnOnce(&mut String)) -> bool {
+        let Some((query, shown)) = &mut self.search else {
+            return false;
+        };
+        edit(query);
+        *shown = 0;
+        self.show_match();
+        true
+    }
+
+    /// Lists the entries matching the search, the best and then the newest
+    /// first.
+    fn matches(&self) -> Vec<usize> {
+        let query = self.search.as_ref().map_or("", |(query, _)| query.as_str());
+        let mut matches: Vec<(u32, usize)> = (0..self.history.len())
+            .rev()
+            .filter_map(|index| Some((actions::fuzzy_score(query, &self.history[index])?, index)))
+            .collect();
+        matches.sort_by_key(|&(score, _)| score);
+        matches.into_iter().map(|(_, index)| index).collect()
+    }
+
+    /// Shows the match the search is on, wrapping around past the last.
+    /// With none, the text stays as it was.
+    fn show_match(&mut self) {
+        let matches = self.matches();
+        let Some((_, shown)) = &mut self.search else {
+            return;
+        };
+        if matches.is_empty() {
+            return;
+        }
+        *shown %= matches.len();
+        let index = matches[*shown];
+        self.recall(index);
+    }
+
+    /// Byte offset of the character at `index`.
+    fn offset(&self, index: usize) -> usize {
+        self.text.char_indices().nth(index).map_or(self.text.len(), |(offset, _)| offset)
+    }
+
+    /// Types a character at the cursor.
+    pub fn insert(&mut self, c: char) {
+        if self.edit_search(|query| query.push(c)) {
+            return;
+        }
+        let offset = self.offset(self.cursor);
+        self.text.insert(offset, c);
+        self.cursor += 1;
+    }
+
+    /// Deletes the character before the cursor.
+    pub fn backspace(&mut self) {
+        if self.edit_search(|query| _ = query.pop()) {
+            return;
+        }
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            let offset = self.offset(self.cursor);
+            self.text.remove(offset);
+        }
+    }
+
+    /// Deletes the character under the cursor.
+    pub fn delete(&mut self) {
+        self.search = None;
+        if self.cursor < self.text.chars().count() {
+            let offset = self.offset(self.cursor);
+            self.text.remove(offset);
+        }
+    }
+
+    pub fn left(&mut self) {
+        self.search = None;
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+
+    pub fn right(&mut self) {
+        self.search = None;
+        self.cursor = (self.cursor + 1).min(self.text.chars().count());
+    }
+
+    pub fn home(&mut self) {
+        self.search = None;
+        self.cursor = 0;
+    }
+
+    pub fn end(&mut self) {
+        self.search = None;
+        self.cursor = self.text.chars().count();
+    }
+
+    /// Deletes everything.
+    pub fn clear(&mut self) {
+        self.search = None;
+        self.text.clear();
+        self.cursor = 0;
+    }
+
+    /// Returns the characters that fit in `width` columns, scrolled so the
+    /// cursor shows, and where the cursor is among them.
+    fn visible(&self, width: usize) -> (Vec<char>, usize) {
+        let chars: Vec<char> = self.text.chars().chain([' ']).collect();
+        let start = (self.cursor + 1).saturating_sub(width.max(1));
+        let shown = chars.into_iter().skip(start).take(width.max(1)).collect();
+        (shown, self.cursor - start)
+    }
+
+    /// Draws the prompt over whatever is on screen.
+    pub fn render(&self, f: &mut Frame, size: Rect, theme: &Theme) {
+        let width = 64.min(size.width);
+        let height = 5.min(size.height);
+        let area = Rect {
+            x: size.x + (size.width - width) / 2,
+            y: size.y + (size.height - height) / 2,
+            width,
+            height,
+        };
+        // Borders plus one column of padding on each side
+        let (shown, cursor) = self.visible(usize::from(width.saturating_sub(4)));
+        let spans: Vec<Span> = shown
+            .into_iter()
+            .enumerate()
+            .map(|(i, c)| {
+                let style = if i == cursor { Style::default().add_modifier(Modifier::REVERSED) } else { Style::default() };
+                Span::styled(c.to_string(), style)
+            })
+            .collect();
+        let lines = vec![
+            Line::from(spans),
+            Line::from(""),
+            match &self.search {
+                Some((query, _)) => Line::from(vec![
+                    Span::styled(self.search_label.clone(), Style::default().fg(theme.dim)),
+                    Span::raw(query.clone()),
+                ]),
+                None => Line::from(Span::styled(self.footer.clone(), Style::default().fg(theme.dim))),
+            },
+        ];
+        let prompt = Paragraph::new(lines).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(self.title.clone())
+                .border_style(Style::default().fg(theme.accent))
+                .padding(ratatui::widgets::Padding::horizontal(1)),
+        );
+        f.render_widget(Clear, area);
+        f.render_widget(prompt, area);
+    }
+}
+
+/// Draws the track list's filter chips, each with its key and name, like
+/// "[1 Marked]". Chips that are on stand out and count the tracks they let
+/// through.
+pub fn chip_spans(chips: &[(char, String, Option<usize>)], theme: &Theme) -> Vec<Span<'static>> {
+    let mut spans = Vec::new();
+    for (key, name, count) in chips {
+        spans.push(Span::raw(" "));
+        spans.push(match count {
+            Some(count) => Span::styled(
+                format!("[{} {} {}]", key, name, count),
+                Style::default().fg(theme.selection_fg).bg(theme.selection_bg),
+            ),
+            None => Span::styled(format!("[{} {}]", key, name), Style::default().fg(theme.dim)),
+        });
+    }
+    spans
+}
+
+/// Flashes a status line for the visual bell.
+pub fn bell_line(line: Line) -> Line {
+    line.patch_style(Style::default().add_modifier(Modifier::REVERSED))
+}
+
+/// Flashes the last row of `area` for the visual bell, on screens whose
+/// status shows at the bottom.
+pub fn bell_row(buffer: &mut Buffer, area: Rect) {
+    let Some(y) = area.bottom().checked_sub(1).filter(|&y| y >= area.y) else {
+        return;
+    };
+    for x in area.left()..area.right() {
+        let cell = &mut buffer[(x, y)];
+        cell.modifier |= Modifier::REVERSED;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::themes;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    fn spans(pos: u64, dur: u64, buffered: Option<u64>, width: usize, style: ProgressStyle) -> Vec<(usize, Style)> {
+        let theme = themes::preset("default").unwrap();
+        render_progress_bar(
+            Duration::from_secs(pos),
+            Duration::from_secs(dur),
+            Trim::default(),
+            buffered.map(Duration::from_secs),
+            None,
+            width,
+            style,
+            &theme,
+        )
+        .iter()
+        .map(|span| (span.content.chars().count(), span.style))
+        .collect()
+    }
+
+    #[test]
+    fn test_progress_bar_spans() {
+        let theme = themes::preset("default").unwrap();
+        let fg = |color| Style::default().fg(color);
+
+        assert_eq!(
+            spans(60, 120, None, 20, ProgressStyle::Playing),
+            [(10, fg(theme.status)), (10, Style::default())]
+        );
+        assert_eq!(
+            spans(60, 120, None, 20, ProgressStyle::Paused),
+            [(10, fg(theme.highlight)), (10, Style::default())]
+        );
+        assert_eq!(
+            spans(60, 120, Some(90), 20, ProgressStyle::SeekFailed),
+            [(10, fg(theme.error)), (5, fg(theme.dim)), (5, Style::default())]
+        );
+        // Past the end and fully buffered
+        assert_eq!(spans(200, 120, Some(120), 20, ProgressStyle::Playing), [(20, fg(theme.status))]);
+
+        // Edge cases
+        assert_eq!(spans(60, 120, None, 0, ProgressStyle::Playing), []);
+        assert_eq!(spans(60, 0, None, 20, ProgressStyle::Playing), [(20, Style::default())]);
+        assert_eq!(
+            ProgressStyle::new(PlaybackState::Playing, true),
+            ProgressStyle::SeekFailed
+        );
+    }
+
+    #[test]
+    fn test_progress_bar_renders() {
+        let theme = themes::preset("default").unwrap();
+        let bar = render_progress_bar(
+            Duration::from_secs(30),
+            Duration::from_secs(60),
+            Trim::default(),
+            None,
+            None,
+            8,
+            ProgressStyle::Playing,
+            &theme,
+        );
+        let mut terminal = Terminal::new(TestBackend::new(10, 1)).unwrap();
+        terminal
+            .draw(|f| f.render_widget(Paragraph::new(Line::from(bar)), f.area()))
+            .unwrap();
+
+        let buffer = terminal.backend().buffer();
+        let symbols: String = (0..10).map(|x| buffer[(x, 0)].symbol()).collect();
+        assert_eq!(symbols, "⣿⣿⣿⣿⣀⣀⣀⣀  ");
+        assert_eq!(buffer[(3, 0)].fg, theme.status);
+        assert_eq!(buffer[(4, 0)].fg, ratatui::style::Color::Reset);
+    }
+
+    #[test]
+    fn test_seek_marker() {
+        let secs = Duration::from_secs;
+        // Each column covers an equal share of the track, the last one its end
+        assert_eq!(marker_column(secs(0), secs(100), 20), Some(0));
+        assert_eq!(marker_column(secs(4), secs(100), 20), Some(0));
+        assert_eq!(marker_column(secs(5), secs(100), 20), Some(1));
+        assert_eq!(marker_column(secs(50), secs(100), 20), Some(10));
+        assert_eq!(marker_column(secs(100), secs(100), 20), Some(19));
+        assert_eq!(marker_column(secs(165), secs(180), 77), Some(70));
+        assert_eq!(marker_column(secs(200), secs(100), 20), Some(19));
+        assert_eq!(marker_column(secs(10), secs(100), 1), Some(0));
+        assert_eq!(marker_column(secs(10), secs(100), 0), None);
+        assert_eq!(marker_column(secs(10), Duration::ZERO, 20), None);
+
+        let theme = themes::preset("default").unwrap();
+        let bar = render_progress_bar(secs(30), secs(60), Trim::default(), None, Some(secs(45)), 8, ProgressStyle::Paused, &theme);
+        let text: String = bar.iter().map(|span| span.content.as_ref()).collect();
+        assert_eq!(text, "⣿⣿⣿⣿⣀⣀○⣀");
+        assert_eq!(bar[2].style.fg, Some(theme.highlight));
+
+        // Behind the position, the marker still shows
+        let bar = render_progress_bar(secs(30), secs(60), Trim::default(), None, Some(secs(0)), 8, ProgressStyle::Playing, &theme);
+        let text: String = bar.iter().map(|span| span.content.as_ref()).collect();
+        assert_eq!(text, "○⣿⣿⣿⣀⣀⣀⣀");
+    }
+
+    #[test]
+    fn test_progress_bar_dims_trimmed_ends() {
+        let theme = themes::preset("default").unwrap();
+        let secs = Duration::from_secs;
+        let trim = Trim { intro: secs(15), outro: secs(15) };
+        let bar = render_progress_bar(secs(30), secs(60), trim, None, None, 8, ProgressStyle::Playing, &theme);
+        let parts: Vec<(&str, Style)> = bar.iter().map(|span| (span.content.as_ref(), span.style)).collect();
+        let fg = |color| Style::default().fg(color);
+        assert_eq!(
+            parts,
+            [("⣿⣿", fg(theme.dim)), ("⣿⣿", fg(theme.status)), ("⣀⣀", Style::default()), ("⣀⣀", fg(theme.dim))]
+        );
+    }
+
+    #[test]
+    fn test_menu() {
+        let items = ["Title", "Artist", "Album"].map(String::from).to_vec();
+        let mut menu = Menu::new("Sort by".to_string(), items, Some(1), "Enter: Apply".to_string());
+        assert_eq!(menu.selected, 1);
+        menu.down();
+        menu.down();
+        assert_eq!(menu.selected, 0);
+        menu.up();
+        assert_eq!(menu.selected, 2);
+
+        let theme = themes::preset("default").unwrap();
+        let mut terminal = Terminal::new(TestBackend::new(30, 12)).unwrap();
+        terminal.draw(|f| menu.render(f, f.area(), &theme)).unwrap();
+        let buffer = terminal.backend().buffer();
+        let row = |y: u16| (0..30).map(|x| buffer[(x, y)].symbol()).collect::<String>();
+        // Three items, a blank line, and the footer inside borders, centered
+        assert_eq!(row(2).trim(), "┌Sort by───────┐");
+        assert_eq!(row(4).trim(), "│ ▶ Artist     │");
+        assert_eq!(buffer[(10, 5)].bg, theme.selection_bg);
+        assert_eq!(row(7).trim(), "│Enter: Apply  │");
+    }
+
+    #[test]
+    fn test_prompt_editing() {
+        let mut prompt = Prompt::new(" Rename ".to_string(), "Café", String::new());
+        prompt.insert('!');
+        assert_eq!(prompt.text(), "Café!");
+        prompt.left();
+        prompt.left();
+        prompt.backspace();
+        assert_eq!(prompt.text(), "Caé!");
+        prompt.delete();
+        assert_eq!(prompt.text(), "Ca!");
+        prompt.home();
+        prompt.insert('>');
+        prompt.end();
+        prompt.right();
+        prompt.insert('?');
+        assert_eq!(prompt.text(), ">Ca!?");
+        prompt.clear();
+        prompt.backspace();
+        assert_eq!(prompt.text(), "");
+    }
+
+    #[test]
+    fn test_prompt_history() {
+        let history = vec!["~/a.m3u".to_string(), "~/b.m3u".to_string()];
+        let mut prompt = Prompt::new(String::new(), "~/new", String::new()).with_history(history, String::new());
+        prompt.newer();
+        assert_eq!(prompt.text(), "~/new");
+        prompt.older();
+        assert_eq!(prompt.text(), "~/b.m3u");
+        prompt.older();
+        prompt.older();
+        assert_eq!(prompt.text(), "~/a.m3u");
+        prompt.insert('!');
+        assert_eq!(prompt.text(), "~/a.m3u!");
+        prompt.newer();
+        assert_eq!(prompt.text(), "~/b.m3u");
+        prompt.newer();
+        assert_eq!(prompt.text(), "~/new");
+
+        // Without a history there is nothing to step through
+        let mut prompt = Prompt::new(String::new(), "x", String::new());
+        prompt.older();
+        assert_eq!(prompt.text(), "x");
+    }
+
+    #[test]
+    fn test_prompt_history_search() {
+        let history = ["~/music/live.m3u", "~/music/chill.m3u", "~/lists/live-1975.m3u"].map(String::from).to_vec();
+        let mut prompt = Prompt::new(String::new(), "~/", String::new()).with_history(history, String::new());
+        // Typing goes to the search, which shows the newest best match
+        prompt.search();
+        assert_eq!(prompt.text(), "~/lists/live-1975.m3u");
+        for c in "mlive".chars() {
+            prompt.insert(c);
+        }
+        assert_eq!(prompt.text(), "~/music/live.m3u");
+        prompt.backspace();
+        prompt.backspace();
+        prompt.backspace();
+        prompt.backspace();
+        prompt.insert('c');
+        assert_eq!(prompt.text(), "~/music/chill.m3u");
+        // Again for the next match, around to the first
+        prompt.search();
+        prompt.search();
+        assert_eq!(prompt.text(), "~/music/chill.m3u");
+        prompt.insert('z');
+        assert_eq!(prompt.text(), "~/music/chill.m3u");
+
+        // Cancelling puts back what was typed; moving keeps the match
+        assert!(prompt.cancel_search());
+        assert_eq!(prompt.text(), "~/");
+        assert!(!prompt.cancel_search());
+        prompt.search();
+        prompt.end();
+        prompt.insert('!');
+        assert_eq!(prompt.text(), "~/lists/live-1975.m3u!");
+    }
+
+    #[test]
+    fn test_prompt_scrolls_to_cursor() {
+        let mut prompt = Prompt::new(String::new(), "abcdefgh", String::new());
+        // The cursor after the end takes a column of its own
+        assert_eq!(prompt.visible(4), ("fgh ".chars().collect(), 3));
+        prompt.home();
+        assert_eq!(prompt.visible(4), ("abcd".chars().collect(), 0));
+        assert_eq!(prompt.visible(20).0.len(), 9);
+    }
+
+    #[test]
+    fn test_chip_spans() {
+        let theme = themes::preset("default").unwrap();
+        let chips = [('1', "Marked".to_string(), None), ('2', "Queued".to_string(), Some(3))];
+        let spans = chip_spans(&chips, &theme);
+        let text: String = spans.iter().map(|span| span.content.as_ref()).collect();
+        assert_eq!(text, " [1 Marked] [2 Queued 3]");
+        assert_eq!(spans[1].style.fg, Some(theme.dim));
+        assert_eq!(spans[3].style.bg, Some(theme.selection_bg));
+    }
+
+    #[test]
+    fn test_bell_flash() {
+        let mut terminal = Terminal::new(TestBackend::new(12, 3)).unwrap();
+        let line = Line::from(vec![Span::raw("["), Span::styled("Playing", Style::default().fg(ratatui::style::Color::Green))]);
+        terminal
+            .draw(|f| {
+                f.render_widget(Paragraph::new(bell_line(line)), f.area());
+                bell_row(f.buffer_mut(), Rect::new(0, 0, 10, 3));
+            })
+            .unwrap();
+        let buffer = terminal.backend().buffer();
+        let reversed = |x: u16, y: u16| buffer[(x, y)].modifier.contains(Modifier::REVERSED);
+
+        // The line's text keeps its colors, inverted
+        assert!((0..8).all(|x| reversed(x, 0)));
+        assert!(!reversed(8, 0));
+        assert_eq!(buffer[(1, 0)].fg, ratatui::style::Color::Green);
+        // The whole bottom row of the area, and nothing past it
+        assert!((0..10).all(|x| reversed(x, 2)));
+        assert!(!reversed(10, 2) && !reversed(0, 1));
+    }
+}
@@ -0,0 +1,299 @@
+//! Bar visualizer animation.
+//!
+//! Bar levels come from a [`VisualizerSource`]; drawing them does not care
+//! which one.
+
+use crate::config::BarSource;
+use crate::player::LevelMeter;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Block characters from lowest to highest, for the visualizer and charts.
+pub const BAR_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Frame rate at which `decay` is the fraction of excess height removed per frame.
+const DECAY_REFERENCE_FPS: f32 = 30.0;
+
+/// Decay bars fall by at least once playback pauses, so that bars set to
+/// hold their peak still come to rest.
+const PAUSED_DECAY: f32 = 0.2;
+
+/// Level under which a falling bar is at rest.
+const REST: f32 = 0.01;
+
+/// Returns the time between visualizer frames for a frame rate.
+pub fn frame_interval(fps: u32) -> Duration {
+    Duration::from_secs_f64(1.0 / f64::from(fps.max(1)))
+}
+
+/// Moves a bar toward its new level: rising immediately, falling at the decay rate.
+///
+/// `decay` is the fraction of the gap closed per frame at 30 fps, scaled by the
+/// time actually elapsed so bars fall at the same speed at any frame rate.
+/// 1.0 drops instantly, 0.0 holds the peak.
+pub fn decay_bar(previous: f32, target: f32, decay: f32, elapsed: Duration) -> f32 {
+    if target >= previous {
+        return target;
+    }
+    let frames = elapsed.as_secs_f32() * DECAY_REFERENCE_FPS;
+    let retain = (1.0 - decay.clamp(0.0, 1.0)).powf(frames);
+    target + (previous - target) * retain
+}
+
+/// Moves bars toward the levels of `source`.
+pub fn update_bars(source: &dyn VisualizerSource, bars: &mut [f32], decay: f32, elapsed: Duration) {
+    let targets = source.bands(bars.len());
+    for (bar, target) in bars.iter_mut().zip(targets) {
+        *bar = decay_bar(*bar, target.clamp(0.0, 1.0), decay, elapsed);
+    }
+}
+
+/// Lets bars fall to rest, as after playback pauses or stops.
+pub fn settle_bars(bars: &mut [f32], decay: f32, elapsed: Duration) {
+    for bar in bars {
+        let level = decay_bar(*bar, 0.0, decay.max(PAUSED_DECAY), elapsed);
+        *bar = if level < REST { 0.0 } else { level };
+    }
+}
+
+/// Renders bar levels as a string of block characters.
+pub fn render_bars(bars: &[f32]) -> String {
+    bars.iter()
+        .map(|&level| {
+            // Map the level (0.0-1.0) to a character index (0-7)
+            let index = (level * 7.0).round() as usize;
+            BAR_LEVELS[index.min(7)]
+        })
+        .collect()
+}
+
+/// Where the visualizer's bar levels come from.
+pub trait VisualizerSource {
+    /// Which source this is, for the log.
+    fn kind(&self) -> SourceKind;
+
+    /// Returns `n` bar levels from 0.0 to 1.0, left to right.
+    fn bands(&self, n: usize) -> Vec<f32>;
+}
+
+/// The kinds of [`VisualizerSource`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceKind {
+    Real,
+    Loudness,
+    Simulated,
+    Test,
+}
+
+impl SourceKind {
+    /// Text key describing the source.
+    pub fn text_key(self) -> &'static str {
+        match self {
+            SourceKind::Real => "visualizer.real",
+            SourceKind::Loudness => "visualizer.loudness",
+            SourceKind::Simulated => "visualizer.simulated",
+            SourceKind::Test => "visualizer.test",
+        }
+    }
+}
+
+/// Picks the source for `visualizer.source`, measuring what the player's
+/// meter hears.
+pub fn choose(config: BarSource, meter: Arc<LevelMeter>) -> Box<dyn VisualizerSource> {
+    match config {
+        BarSource::Auto => Box::new(RealSpectrum::new(meter)),
+        BarSource::Loudness => Box::new(Loudness::new(meter)),
+        BarSource::Simulated => Box::new(SimulatedSine::new()),
+    }
+}
+
+/// Frequency bands measured from the samples played.
+pub struct RealSpectrum {
+    meter: Arc<LevelMeter>,
+}
+
+impl RealSpectrum {
+    pub fn new(meter: Arc<LevelMeter>) -> Self {
+        Self { meter }
+    }
+}
+
+impl VisualizerSource for RealSpectrum {
+    fn kind(&self) -> SourceKind {
+        SourceKind::Real
+    }
+
+    fn bands(&self, n: usize) -> Vec<f32> {
+        self.meter.spectrum(n)
+    }
+}
+
+/// The loudness of the last moments played, scrolling from right to left.
+pub struct Loudness {
+    meter: Arc<LevelMeter>,
+}
+
+impl Loudness {
+    pub fn new(meter: Arc<LevelMeter>) -> Self {
+        Self { meter }
+    }
+}
+
+impl VisualizerSource for Loudness {
+    fn kind(&self) -> SourceKind {
+        SourceKind::Loudness
+    }
+
+    /// The newest level is the rightmost bar; before there are `n`, the
+    /// bars on the left stay empty.
+    fn bands(&self, n: usize) -> Vec<f32> {
+        let recent = self.meter.recent(n);
+        let mut levels = vec![0.0; n - recent.len()];
+        levels.extend(recent);
+        levels
+    }
+}
+
+/// Sine waves of rising frequency, bass to treble, that do not follow the
+/// audio.
+pub struct SimulatedSine {
+    started: Instant,
+}
+
+impl SimulatedSine {
+    pub fn new() -> Self {
+        Self { started: Instant::now() }
+    }
+
+    /// Returns the levels `time` seconds in.
+    fn levels_at(time: f32, n: usize) -> Vec<f32> {
+        (0..n)
+            .map(|i| {
+                // Each bar has a different base frequency, from 1 Hz up by 0.5 Hz
+                let freq = 1.0 + (i as f32 * 0.5);
+                let amplitude = (time * freq * std::f32::consts::PI).sin().abs();
+                // Add some variation to make it more interesting
+                let variation = (time * freq * 2.0).sin() * 0.3;
+                (amplitude * 0.7 + variation.abs() * 0.3).min(1.0)
+            })
+            .collect()
+    }
+}
+
+impl Default for SimulatedSine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VisualizerSource for SimulatedSine {
+    fn kind(&self) -> SourceKind {
+        SourceKind::Simulated
+    }
+
+    fn bands(&self, n: usize) -> Vec<f32> {
+        // Keep time in a reasonable range for the sine calculations
+        let time = (self.started.elapsed().as_millis() % 60_000) as f32 / 1000.0;
+        Self::levels_at(time, n)
+    }
+}
+
+/// The same arch of levels every frame, for tests and `--viz-demo`
+/// screenshots.
+pub struct TestBars;
+
+impl VisualizerSource for TestBars {
+    fn kind(&self) -> SourceKind {
+        SourceKind::Test
+    }
+
+    fn bands(&self, n: usize) -> Vec<f32> {
+        (0..n)
+            .map(|i| 0.15 + 0.85 * (std::f32::consts::PI * (i as f32 + 0.5) / n as f32).sin())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_micros(33_333);
+
+    #[test]
+    fn test_rising_is_immediate() {
+        for decay in [0.0, 0.5, 1.0] {
+            assert_eq!(decay_bar(0.2, 0.9, decay, FRAME), 0.9);
+        }
+    }
+
+    #[test]
+    fn test_decay_boundaries() {
+        // Full decay drops straight to the target
+        assert_eq!(decay_bar(1.0, 0.0, 1.0, FRAME), 0.0);
+        // No decay holds the peak
+        assert_eq!(decay_bar(1.0, 0.0, 0.0, FRAME), 1.0);
+        // No elapsed time changes nothing
+        assert_eq!(decay_bar(1.0, 0.0, 0.5, Duration::ZERO), 1.0);
+        // Out-of-range values are clamped
+        assert_eq!(decay_bar(1.0, 0.0, 2.0, FRAME), 0.0);
+        assert_eq!(decay_bar(1.0, 0.0, -1.0, FRAME), 1.0);
+    }
+
+    #[test]
+    fn test_decay_is_frame_rate_independent() {
+        // Two frames at 30 fps fall as far as one frame at 15 fps
+        let step = decay_bar(1.0, 0.0, 0.5, FRAME);
+        let twice = decay_bar(step, 0.0, 0.5, FRAME);
+        let once = decay_bar(1.0, 0.0, 0.5, FRAME * 2);
+        assert!((twice - 0.25).abs() < 1e-3);
+        assert!((once - twice).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_test_bars_render() {
+        let mut bars = [0.0; 12];
+        update_bars(&TestBars, &mut bars, 0.5, FRAME);
+        assert_eq!(render_bars(&bars), "▃▄▆▇████▇▆▄▃");
+        // Unchanged from frame to frame
+        update_bars(&TestBars, &mut bars, 0.5, FRAME);
+        assert_eq!(render_bars(&bars), "▃▄▆▇████▇▆▄▃");
+    }
+
+    #[test]
+    fn test_paused_bars_come_to_rest() {
+        let mut bars = [1.0, 0.5, 0.0];
+        settle_bars(&mut bars, 0.5, FRAME);
+        assert!((bars[0] - 0.5).abs() < 1e-3 && (bars[1] - 0.25).abs() < 1e-3 && bars[2] == 0.0);
+        // Even bars set to hold their peak fall, and end at zero
+        let mut bars = [1.0; 4];
+        for _ in 0..30 {
+            settle_bars(&mut bars, 0.0, FRAME);
+        }
+        assert_eq!(bars, [0.0; 4]);
+    }
+
+    #[test]
+    fn test_source_choice() {
+        let meter = Arc::new(LevelMeter::default());
+        assert_eq!(choose(BarSource::Auto, meter.clone()).kind(), SourceKind::Real);
+        assert_eq!(choose(BarSource::Loudness, meter.clone()).kind(), SourceKind::Loudness);
+        assert_eq!(choose(BarSource::Simulated, meter.clone()).kind(), SourceKind::Simulated);
+        // Nothing played yet
+        assert_eq!(choose(BarSource::Auto, meter.clone()).bands(4), vec![0.0; 4]);
+        assert_eq!(Loudness::new(meter).bands(4), vec![0.0; 4]);
+    }
+
+    #[test]
+    fn test_simulated_levels_stay_in_range() {
+        for time in [0.0, 0.3, 17.5, 59.9] {
+            assert!(SimulatedSine::levels_at(time, 12).iter().all(|level| (0.0..=1.0).contains(level)));
+        }
+    }
+
+    #[test]
+    fn test_frame_interval() {
+        assert_eq!(frame_interval(5), Duration::from_millis(200));
+        assert_eq!(frame_interval(0), Duration::from_secs(1));
+    }
+}